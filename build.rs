@@ -1,5 +1,7 @@
+use std::env;
 use std::fs;
 use std::path::Path;
+use std::path::PathBuf;
 use std::process::Command;
 
 fn main() {
@@ -8,6 +10,9 @@ fn main() {
 
     println!("cargo:rerun-if-changed={}", shader_dir);
 
+    // (file name, absolute path) of every compiled shader
+    let mut compiled_shaders = Vec::new();
+
     for entry in fs::read_dir(shader_dir)
         .expect("After git cloning, folder + permission should exist and be set correctly.")
     {
@@ -20,7 +25,7 @@ fn main() {
                     .to_str()
                     .expect("Extension should exist and be valid utf-8 since we set the name")
                 {
-                    "vert" | "frag" | "comp" => {
+                    "vert" | "frag" | "comp" | "rgen" | "rmiss" | "rchit" => {
                         let file_stem = path
                             .file_stem()
                             .expect("File should have a valid utf-8 stem since we name it")
@@ -30,14 +35,19 @@ fn main() {
                             .to_str()
                             .expect("Extension should be valid utf-8 since we set the name");
                         let output_file_name = format!("{}_{}.spv", file_stem, ext_text);
-                        let output_path = Path::new(&output_dir).join(output_file_name);
+                        let output_path = Path::new(&output_dir).join(&output_file_name);
 
                         println!("Compiling {:?}", path);
 
-                        let status = Command::new("glslc")
-                            .arg(&path)
-                            .arg("-o")
-                            .arg(&output_path)
+                        let mut command = Command::new("glslc");
+                        command.arg(&path).arg("-o").arg(&output_path);
+                        // ray tracing stages and ray queries need spir-v 1.4
+                        let uses_ray_query = fs::read_to_string(&path)
+                            .is_ok_and(|source| source.contains("GL_EXT_ray_query"));
+                        if matches!(ext_text, "rgen" | "rmiss" | "rchit") || uses_ray_query {
+                            command.arg("--target-env=vulkan1.3");
+                        }
+                        let status = command
                             .status()
                             .expect("glslc should not fail, since it should be installed + the shaders should be valid glsl");
 
@@ -48,10 +58,35 @@ fn main() {
                                     .expect("File should have a valid utf-8 name since we name it")
                             );
                         }
+                        compiled_shaders.push((output_file_name, output_path));
                     }
                     _ => (),
                 }
             }
         }
     }
+
+    write_embedded_shaders(&compiled_shaders);
+}
+
+// include_bytes! for every shader with the embed-shaders feature, an empty list otherwise
+// => shader.rs can always include the file
+fn write_embedded_shaders(compiled_shaders: &[(String, PathBuf)]) {
+    let manifest_dir = env::var("CARGO_MANIFEST_DIR").expect("Cargo always sets the manifest dir");
+    let out_dir = env::var("OUT_DIR").expect("Cargo always sets the out dir for build scripts");
+    let embed = env::var_os("CARGO_FEATURE_EMBED_SHADERS").is_some();
+
+    let mut code = String::from("&[\n");
+    if embed {
+        for (file_name, path) in compiled_shaders {
+            let absolute_path = Path::new(&manifest_dir).join(path);
+            code.push_str(&format!(
+                "    ({:?}, include_bytes!({:?})),\n",
+                file_name, absolute_path
+            ));
+        }
+    }
+    code.push_str("]\n");
+    fs::write(Path::new(&out_dir).join("embedded_shaders.rs"), code)
+        .expect("Out dir should be writable by the build script");
 }
@@ -0,0 +1,150 @@
+#version 450
+
+// from skybox.vert, clip space xy of the full screen triangle
+layout (location = 0) in vec2 inPosition;
+
+layout (location = 0) out vec4 outFragColor;
+
+// DescriptorSetSlot::Scene
+layout(set = 0, binding = 0) uniform SceneData {
+	mat4 view;
+	mat4 proj;
+	mat4 viewproj;
+	vec4 ambientColor;
+	vec4 sunlightDirection; //w for sun power
+	vec4 sunlightColor;
+	vec4 cameraPosition;
+	mat4 lightViewProj;
+	uvec4 lightCount; //x = number of lights in the light buffer
+} sceneData;
+
+layout(set = 0, binding = 1) uniform sampler2D shadowMap;
+
+#define LIGHT_DIRECTIONAL 0
+#define LIGHT_POINT 1
+#define LIGHT_SPOT 2
+
+struct Light {
+	vec4 position; //w = type
+	vec4 direction; //w = range
+	vec4 color; //w = intensity
+	vec4 cone; //x = cos inner angle, y = cos outer angle
+};
+
+layout(set = 0, binding = 2, std430) readonly buffer LightBuffer {
+	Light lights[];
+} lightBuffer;
+
+layout(set = 0, binding = 3) uniform samplerCube irradianceMap;
+layout(set = 0, binding = 4) uniform samplerCube specularMap;
+layout(set = 0, binding = 5) uniform sampler2D brdfLut;
+
+// written by gbuffer.frag, same pixel grid as the draw image => texel fetches
+layout(set = 1, binding = 0) uniform sampler2D albedoMetallic;
+layout(set = 1, binding = 1) uniform sampler2D normalRoughness;
+layout(set = 1, binding = 2) uniform sampler2D emissive;
+layout(set = 1, binding = 3) uniform sampler2D depthImage;
+// 1 = lit, written by ray_query_shadows.comp
+layout(set = 1, binding = 4) uniform sampler2D shadowMask;
+
+layout( push_constant ) uniform constants
+{
+	// inverse of sceneData.viewproj, clip space -> world space
+	mat4 inverseViewProj;
+	// x = 1 => sun shadows from the shadow mask instead of the shadow map
+	vec4 params;
+} PushConstants;
+
+// same as in mesh.frag
+float shadowFactor(vec3 position, vec3 normal, vec3 lightDir)
+{
+	vec4 lightClip = sceneData.lightViewProj * vec4(position, 1.0);
+	vec3 shadowCoord = lightClip.xyz / lightClip.w;
+	vec2 uv = shadowCoord.xy * 0.5 + 0.5;
+	if (any(lessThan(uv, vec2(0.0))) || any(greaterThan(uv, vec2(1.0))) || shadowCoord.z <= 0.0) {
+		return 1.0;
+	}
+	float bias = max(0.002 * (1.0 - dot(normal, lightDir)), 0.0005);
+	vec2 texelSize = 1.0 / vec2(textureSize(shadowMap, 0));
+	float lit = 0.0;
+	for (int x = -1; x <= 1; x++) {
+		for (int y = -1; y <= 1; y++) {
+			float closestDepth = texture(shadowMap, uv + vec2(x, y) * texelSize).r;
+			lit += shadowCoord.z + bias >= closestDepth ? 1.0 : 0.0;
+		}
+	}
+	return lit / 9.0;
+}
+
+vec3 shade(vec3 lightDir, vec3 radiance, vec3 normal, vec3 viewDir, vec3 baseColor, float metallic, float roughness)
+{
+	float lightValue = max(dot(normal, lightDir), 0.0);
+	vec3 halfDir = normalize(lightDir + viewDir);
+	float shininess = mix(256.0, 2.0, roughness);
+	float specular = pow(max(dot(normal, halfDir), 0.0), shininess) * (1.0 - roughness);
+	vec3 specularColor = mix(vec3(0.04), baseColor, metallic);
+	vec3 diffuse = baseColor * (1.0 - metallic) * lightValue;
+	return (diffuse + specularColor * specular) * radiance;
+}
+
+vec3 lightRadiance(Light light, vec3 position, out vec3 lightDir)
+{
+	int type = int(light.position.w);
+	vec3 radiance = light.color.rgb * light.color.w;
+	if (type == LIGHT_DIRECTIONAL) {
+		lightDir = -light.direction.xyz;
+		return radiance;
+	}
+	vec3 toLight = light.position.xyz - position;
+	float lightDistance = length(toLight);
+	lightDir = toLight / max(lightDistance, 0.0001);
+	float range = light.direction.w;
+	float window = clamp(1.0 - pow(lightDistance / max(range, 0.0001), 4.0), 0.0, 1.0);
+	radiance *= window * window / (lightDistance * lightDistance + 1.0);
+	if (type == LIGHT_SPOT) {
+		float cosAngle = dot(-lightDir, light.direction.xyz);
+		radiance *= smoothstep(light.cone.y, light.cone.x, cosAngle);
+	}
+	return radiance;
+}
+
+void main()
+{
+	ivec2 texel = ivec2(gl_FragCoord.xy);
+	// reversed depth => 0 is the far plane, the background/skybox stays
+	float depth = texelFetch(depthImage, texel, 0).r;
+	if (depth <= 0.0) {
+		discard;
+	}
+	vec4 world = PushConstants.inverseViewProj * vec4(inPosition, depth, 1.0);
+	vec3 position = world.xyz / world.w;
+
+	vec4 albedo = texelFetch(albedoMetallic, texel, 0);
+	vec4 normalRough = texelFetch(normalRoughness, texel, 0);
+	vec3 baseColor = albedo.rgb;
+	float metallic = albedo.a;
+	float roughness = normalRough.a;
+	vec3 normal = normalize(normalRough.xyz);
+
+	vec3 lightDir = normalize(-sceneData.sunlightDirection.xyz);
+	float shadow = PushConstants.params.x > 0.5
+		? texelFetch(shadowMask, texel, 0).r
+		: shadowFactor(position, normal, lightDir);
+	vec3 viewDir = normalize(sceneData.cameraPosition.xyz - position);
+
+	vec3 lit = shade(lightDir, sceneData.sunlightColor.rgb, normal, viewDir, baseColor, metallic, roughness) * shadow;
+	for (uint i = 0; i < sceneData.lightCount.x; i++) {
+		vec3 bufferLightDir;
+		vec3 radiance = lightRadiance(lightBuffer.lights[i], position, bufferLightDir);
+		lit += shade(bufferLightDir, radiance, normal, viewDir, baseColor, metallic, roughness);
+	}
+	float NdotV = max(dot(normal, viewDir), 0.0);
+	vec3 irradiance = texture(irradianceMap, normal).rgb;
+	float specularLod = roughness * float(textureQueryLevels(specularMap) - 1);
+	vec3 prefiltered = textureLod(specularMap, reflect(-viewDir, normal), specularLod).rgb;
+	vec2 brdf = texture(brdfLut, vec2(NdotV, roughness)).rg;
+	vec3 F0 = mix(vec3(0.04), baseColor, metallic);
+	vec3 ambient = (baseColor * (1.0 - metallic) * irradiance + prefiltered * (F0 * brdf.x + brdf.y)) * sceneData.ambientColor.rgb;
+
+	outFragColor = vec4(lit + ambient + texelFetch(emissive, texel, 0).rgb, 1.0);
+}
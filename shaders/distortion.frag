@@ -0,0 +1,78 @@
+#version 450
+
+layout (location = 0) in vec3 inNormal;
+layout (location = 1) in vec3 inColor;
+layout (location = 2) in vec2 inUV;
+layout (location = 3) in vec3 inPosition;
+
+// xy = offset in uv space, added up for overlapping meshes
+layout (location = 0) out vec4 outDistortion;
+
+// DescriptorSetSlot::Scene
+layout(set = 0, binding = 0) uniform SceneData {
+	mat4 view;
+	mat4 proj;
+	mat4 viewproj;
+	vec4 ambientColor;
+	vec4 sunlightDirection; //w for sun power
+	vec4 sunlightColor;
+	vec4 cameraPosition;
+	mat4 lightViewProj;
+	uvec4 lightCount; //x = number of lights in the light buffer
+} sceneData;
+
+//push constants block, starts like the one in mesh.vert
+// params: x = strength, y = noise scale, z = time * speed, w = mode (0 = heat haze, 1 = refraction)
+layout( push_constant ) uniform constants
+{
+	mat4 model_matrix;
+	uvec2 vertexBuffer;
+	uvec2 padding;
+	vec4 params;
+	// always 0 => mesh.vert uses the bound index buffer
+	uvec2 indexBuffer;
+	uvec2 padding2;
+	// always 0 => mesh.vert uses the push constants, not a draw buffer
+	uvec2 drawBuffer;
+} PushConstants;
+
+float hash(vec3 p)
+{
+	p = fract(p * 0.3183099 + 0.1);
+	p *= 17.0;
+	return fract(p.x * p.y * p.z * (p.x + p.y + p.z));
+}
+
+// value noise in [-1, 1]
+float noise(vec3 p)
+{
+	vec3 i = floor(p);
+	vec3 f = fract(p);
+	f = f * f * (3.0 - 2.0 * f);
+	float n = mix(mix(mix(hash(i + vec3(0, 0, 0)), hash(i + vec3(1, 0, 0)), f.x),
+	                  mix(hash(i + vec3(0, 1, 0)), hash(i + vec3(1, 1, 0)), f.x), f.y),
+	              mix(mix(hash(i + vec3(0, 0, 1)), hash(i + vec3(1, 0, 1)), f.x),
+	                  mix(hash(i + vec3(0, 1, 1)), hash(i + vec3(1, 1, 1)), f.x), f.y), f.z);
+	return n * 2.0 - 1.0;
+}
+
+void main()
+{
+	float strength = PushConstants.params.x;
+	vec3 normal = normalize(inNormal);
+	vec3 viewDir = normalize(sceneData.cameraPosition.xyz - inPosition);
+	// fade out towards the silhouette => no hard edges where the mesh ends
+	float facing = abs(dot(normal, viewDir));
+
+	vec2 offset;
+	if (PushConstants.params.w < 0.5) {
+		// noise in world space that rises over time
+		vec3 p = inPosition / max(PushConstants.params.y, 0.0001) - vec3(0.0, PushConstants.params.z, 0.0);
+		offset = vec2(noise(p), noise(p + vec3(17.0, 31.0, 5.0))) * facing;
+	} else {
+		// bend the background along the view space normal like a lens
+		offset = (sceneData.view * vec4(normal, 0.0)).xy * vec2(1.0, -1.0);
+	}
+	// the additive blend state multiplies with alpha
+	outDistortion = vec4(offset * strength, 0.0, 1.0);
+}
@@ -0,0 +1,40 @@
+#version 450
+#extension GL_EXT_buffer_reference : require
+#extension GL_EXT_nonuniform_qualifier : require
+
+layout (location = 0) in vec4 inColor;
+layout (location = 1) in vec2 inUV;
+
+layout (location = 0) out vec4 outFragColor;
+
+// same texture table as the materials, egui textures are registered like any other texture
+layout(set = 0, binding = 0) uniform sampler2D textures[];
+
+layout(buffer_reference, std430) readonly buffer VertexBuffer{
+	float data[];
+};
+
+layout( push_constant ) uniform constants
+{
+	vec2 screenSize;
+	uint textureIndex;
+	uint linearOutput; // 0 => the target is unorm, encode the color ourselves
+	VertexBuffer vertexBuffer;
+} PushConstants;
+
+vec3 linearToSrgb(vec3 linear)
+{
+	bvec3 cutoff = lessThan(linear, vec3(0.0031308));
+	vec3 lower = linear * 12.92;
+	vec3 higher = 1.055 * pow(linear, vec3(1.0 / 2.4)) - 0.055;
+	return mix(higher, lower, cutoff);
+}
+
+void main()
+{
+	vec4 color = inColor * texture(textures[PushConstants.textureIndex], inUV);
+	if (PushConstants.linearOutput == 0) {
+		color.rgb = linearToSrgb(color.rgb);
+	}
+	outFragColor = color;
+}
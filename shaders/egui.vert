@@ -0,0 +1,39 @@
+#version 450
+#extension GL_EXT_buffer_reference : require
+
+layout (location = 0) out vec4 outColor;
+layout (location = 1) out vec2 outUV;
+
+// egui vertices are 5 tightly packed values (pos.xy, uv.xy, rgba8 color)
+// => read as floats, a struct would be padded by std430
+layout(buffer_reference, std430) readonly buffer VertexBuffer{
+	float data[];
+};
+
+layout( push_constant ) uniform constants
+{
+	vec2 screenSize; // in points, same as the vertex positions
+	uint textureIndex;
+	uint linearOutput;
+	VertexBuffer vertexBuffer;
+} PushConstants;
+
+vec3 srgbToLinear(vec3 srgb)
+{
+	bvec3 cutoff = lessThan(srgb, vec3(0.04045));
+	vec3 lower = srgb / 12.92;
+	vec3 higher = pow((srgb + 0.055) / 1.055, vec3(2.4));
+	return mix(higher, lower, cutoff);
+}
+
+void main()
+{
+	uint base = gl_VertexIndex * 5;
+	vec2 position = vec2(PushConstants.vertexBuffer.data[base], PushConstants.vertexBuffer.data[base + 1]);
+	outUV = vec2(PushConstants.vertexBuffer.data[base + 2], PushConstants.vertexBuffer.data[base + 3]);
+	// premultiplied srgb => linear, textures are srgb too so the sampler already linearizes them
+	vec4 color = unpackUnorm4x8(floatBitsToUint(PushConstants.vertexBuffer.data[base + 4]));
+	outColor = vec4(srgbToLinear(color.rgb), color.a);
+	// egui has its origin in the top left like vulkan
+	gl_Position = vec4(position / PushConstants.screenSize * 2.0 - 1.0, 0.0, 1.0);
+}
@@ -0,0 +1,60 @@
+#version 450
+#extension GL_EXT_buffer_reference : require
+#extension GL_EXT_buffer_reference_uvec2 : require
+#extension GL_EXT_nonuniform_qualifier : require
+
+layout (location = 0) in vec3 inNormal;
+layout (location = 1) in vec3 inColor;
+layout (location = 2) in vec2 inUV;
+layout (location = 3) in vec3 inPosition;
+// textureIndices: x = color, y = metal rough, z = normal
+layout (location = 4) flat in uvec2 inMaterialData;
+layout (location = 5) flat in uvec4 inTextureIndices;
+
+// same order as the g-buffer images of Deferred
+layout (location = 0) out vec4 outAlbedo; //a = metallic
+layout (location = 1) out vec4 outNormal; //a = roughness
+layout (location = 2) out vec4 outEmissive;
+
+layout(buffer_reference, std430) readonly buffer MaterialData {
+	vec4 colorFactors;
+	vec4 metal_rough_factors;
+};
+
+// DescriptorSetSlot::Material
+layout(set = 1, binding = 0) uniform sampler2D textures[];
+
+// same as in mesh.frag
+vec3 perturbNormal(vec3 normal, vec3 position, vec2 uv)
+{
+	vec3 mapNormal = texture(textures[inTextureIndices.z], uv).xyz * 2.0 - 1.0;
+	vec3 dp1 = dFdx(position);
+	vec3 dp2 = dFdy(position);
+	vec2 duv1 = dFdx(uv);
+	vec2 duv2 = dFdy(uv);
+	vec3 dp2perp = cross(dp2, normal);
+	vec3 dp1perp = cross(normal, dp1);
+	vec3 tangent = dp2perp * duv1.x + dp1perp * duv2.x;
+	vec3 bitangent = dp2perp * duv1.y + dp1perp * duv2.y;
+	float invmax = inversesqrt(max(dot(tangent, tangent), dot(bitangent, bitangent)));
+	if (isinf(invmax) || isnan(invmax)) {
+		return normal;
+	}
+	mat3 tbn = mat3(tangent * invmax, bitangent * invmax, normal);
+	return normalize(tbn * mapNormal);
+}
+
+void main()
+{
+	MaterialData materialData = MaterialData(inMaterialData);
+	vec4 baseColor = texture(textures[inTextureIndices.x], inUV) * materialData.colorFactors;
+	vec4 metalRough = texture(textures[inTextureIndices.y], inUV);
+	float metallic = metalRough.b * materialData.metal_rough_factors.x;
+	float roughness = metalRough.g * materialData.metal_rough_factors.y;
+
+	outAlbedo = vec4(baseColor.rgb, metallic);
+	// world space, the lighting pass reconstructs the position from the depth
+	outNormal = vec4(perturbNormal(normalize(inNormal), inPosition, inUV), roughness);
+	// materials dont have an emissive factor yet
+	outEmissive = vec4(0.0);
+}
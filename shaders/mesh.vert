@@ -0,0 +1,87 @@
+#version 450
+#extension GL_EXT_buffer_reference : require
+#extension GL_EXT_buffer_reference_uvec2 : require
+
+layout (location = 0) out vec3 outNormal;
+layout (location = 1) out vec3 outColor;
+layout (location = 2) out vec2 outUV;
+layout (location = 3) out vec3 outPosition;
+layout (location = 4) flat out uvec2 outMaterialData;
+layout (location = 5) flat out uvec4 outTextureIndices;
+
+struct Vertex {
+	vec3 position;
+	float uv_x;
+	vec3 normal;
+	float uv_y;
+	vec4 color;
+}; 
+
+layout(buffer_reference, std430) readonly buffer VertexBuffer{ 
+	Vertex vertices[];
+};
+
+layout(buffer_reference, std430) readonly buffer IndexBuffer{
+	uint indices[];
+};
+
+// same layout as GPUDrawData
+// indexBuffer != 0 => non indexed draw, gl_VertexIndex is the position in the index buffer
+struct DrawData {
+	mat4 model_matrix;
+	VertexBuffer vertexBuffer;
+	uvec2 materialData;
+	uvec4 textureIndices;
+	uvec2 indexBuffer;
+};
+
+layout(buffer_reference, std430) readonly buffer DrawBuffer{
+	DrawData draws[];
+};
+
+// DescriptorSetSlot::Scene
+layout(set = 0, binding = 0) uniform SceneData {
+	mat4 view;
+	mat4 proj;
+	mat4 viewproj;
+	vec4 ambientColor;
+	vec4 sunlightDirection; //w for sun power
+	vec4 sunlightColor;
+	vec4 cameraPosition;
+	mat4 lightViewProj;
+	uvec4 lightCount; //x = number of lights in the light buffer
+} sceneData;
+
+//push constants block, same layout as GPUDrawPushConstants
+// drawBuffer != 0 => indirect draw, its draw data is at firstInstance (= gl_InstanceIndex)
+layout( push_constant ) uniform constants
+{	
+	DrawData draw;
+	uvec2 drawBuffer;
+} PushConstants;
+
+void main() 
+{	
+	DrawData draw = PushConstants.draw;
+	if (PushConstants.drawBuffer != uvec2(0)) {
+		draw = DrawBuffer(PushConstants.drawBuffer).draws[gl_InstanceIndex];
+	}
+	uint vertexIndex = gl_VertexIndex;
+	if (draw.indexBuffer != uvec2(0)) {
+		vertexIndex = IndexBuffer(draw.indexBuffer).indices[gl_VertexIndex];
+	}
+	//load vertex data from device adress
+	Vertex v = draw.vertexBuffer.vertices[vertexIndex];
+
+	//output data
+	vec4 worldPosition = draw.model_matrix * vec4(v.position, 1.0f);
+	gl_Position = sceneData.viewproj * worldPosition;
+	// only correct for uniform scaling, good enough for now
+	outNormal = normalize((draw.model_matrix * vec4(v.normal, 0.0f)).xyz);
+	outColor = v.color.xyz;
+	outUV.x = v.uv_x;
+	outUV.y = v.uv_y;
+	outPosition = worldPosition.xyz;
+	outMaterialData = draw.materialData;
+	outTextureIndices = draw.textureIndices;
+}
@@ -0,0 +1,185 @@
+#version 450
+#extension GL_EXT_buffer_reference : require
+#extension GL_EXT_buffer_reference_uvec2 : require
+#extension GL_EXT_nonuniform_qualifier : require
+
+layout (location = 0) in vec3 inNormal;
+layout (location = 1) in vec3 inColor;
+layout (location = 2) in vec2 inUV;
+layout (location = 3) in vec3 inPosition;
+// material of the draw, only the vertex shader can look it up for indirect draws
+// textureIndices: x = color, y = metal rough, z = normal
+layout (location = 4) flat in uvec2 inMaterialData;
+layout (location = 5) flat in uvec4 inTextureIndices;
+
+// summed up over all transparent fragments of a pixel, resolved by oit_composite.frag
+layout (location = 0) out vec4 outAccumulation; //rgb = weighted premultiplied color, a = weighted alpha
+// -log(1 - alpha) => the sum is -log of the product of (1 - alpha), which additive blending cant
+// compute directly
+layout (location = 1) out float outRevealage;
+
+// DescriptorSetSlot::Scene
+layout(set = 0, binding = 0) uniform SceneData {
+	mat4 view;
+	mat4 proj;
+	mat4 viewproj;
+	vec4 ambientColor;
+	vec4 sunlightDirection; //w for sun power
+	vec4 sunlightColor;
+	vec4 cameraPosition;
+	mat4 lightViewProj;
+	uvec4 lightCount; //x = number of lights in the light buffer
+} sceneData;
+
+// reversed depth, cleared to 0 => closer to the light is larger
+layout(set = 0, binding = 1) uniform sampler2D shadowMap;
+
+#define LIGHT_DIRECTIONAL 0
+#define LIGHT_POINT 1
+#define LIGHT_SPOT 2
+
+struct Light {
+	vec4 position; //w = type
+	vec4 direction; //w = range
+	vec4 color; //w = intensity
+	vec4 cone; //x = cos inner angle, y = cos outer angle
+};
+
+layout(set = 0, binding = 2, std430) readonly buffer LightBuffer {
+	Light lights[];
+} lightBuffer;
+
+// image based lighting, prefiltered when the environment is loaded
+layout(set = 0, binding = 3) uniform samplerCube irradianceMap;
+// roughness 0 at mip 0 up to roughness 1 at the last mip
+layout(set = 0, binding = 4) uniform samplerCube specularMap;
+// x = n dot v, y = roughness => r = scale, g = bias of F0
+layout(set = 0, binding = 5) uniform sampler2D brdfLut;
+
+layout(buffer_reference, std430) readonly buffer MaterialData {
+	vec4 colorFactors;
+	vec4 metal_rough_factors;
+};
+
+// DescriptorSetSlot::Material: bindless texture table, the material picks its textures through
+// inTextureIndices
+layout(set = 1, binding = 0) uniform sampler2D textures[];
+
+// vertices dont have tangents => build the tangent frame from screen space derivatives
+vec3 perturbNormal(vec3 normal, vec3 position, vec2 uv)
+{
+	vec3 mapNormal = texture(textures[inTextureIndices.z], uv).xyz * 2.0 - 1.0;
+	vec3 dp1 = dFdx(position);
+	vec3 dp2 = dFdy(position);
+	vec2 duv1 = dFdx(uv);
+	vec2 duv2 = dFdy(uv);
+	vec3 dp2perp = cross(dp2, normal);
+	vec3 dp1perp = cross(normal, dp1);
+	vec3 tangent = dp2perp * duv1.x + dp1perp * duv2.x;
+	vec3 bitangent = dp2perp * duv1.y + dp1perp * duv2.y;
+	float invmax = inversesqrt(max(dot(tangent, tangent), dot(bitangent, bitangent)));
+	// degenerate uvs => no usable tangent frame
+	if (isinf(invmax) || isnan(invmax)) {
+		return normal;
+	}
+	mat3 tbn = mat3(tangent * invmax, bitangent * invmax, normal);
+	return normalize(tbn * mapNormal);
+}
+
+// 3x3 pcf, 1 = lit, 0 = in shadow
+float shadowFactor(vec3 position, vec3 normal, vec3 lightDir)
+{
+	vec4 lightClip = sceneData.lightViewProj * vec4(position, 1.0);
+	vec3 shadowCoord = lightClip.xyz / lightClip.w;
+	vec2 uv = shadowCoord.xy * 0.5 + 0.5;
+	// outside of the shadow map => nothing can cast a shadow there
+	if (any(lessThan(uv, vec2(0.0))) || any(greaterThan(uv, vec2(1.0))) || shadowCoord.z <= 0.0) {
+		return 1.0;
+	}
+	// surfaces at grazing angles need more bias against acne
+	float bias = max(0.002 * (1.0 - dot(normal, lightDir)), 0.0005);
+	vec2 texelSize = 1.0 / vec2(textureSize(shadowMap, 0));
+	float lit = 0.0;
+	for (int x = -1; x <= 1; x++) {
+		for (int y = -1; y <= 1; y++) {
+			float closestDepth = texture(shadowMap, uv + vec2(x, y) * texelSize).r;
+			lit += shadowCoord.z + bias >= closestDepth ? 1.0 : 0.0;
+		}
+	}
+	return lit / 9.0;
+}
+
+// blinn phong, lightDir points from the surface to the light
+vec3 shade(vec3 lightDir, vec3 radiance, vec3 normal, vec3 viewDir, vec3 baseColor, float metallic, float roughness)
+{
+	float lightValue = max(dot(normal, lightDir), 0.0);
+	vec3 halfDir = normalize(lightDir + viewDir);
+	float shininess = mix(256.0, 2.0, roughness);
+	float specular = pow(max(dot(normal, halfDir), 0.0), shininess) * (1.0 - roughness);
+	vec3 specularColor = mix(vec3(0.04), baseColor, metallic);
+	vec3 diffuse = baseColor * (1.0 - metallic) * lightValue;
+	return (diffuse + specularColor * specular) * radiance;
+}
+
+// radiance of a light from the buffer at position, lightDir is set to the direction to the light
+vec3 lightRadiance(Light light, vec3 position, out vec3 lightDir)
+{
+	int type = int(light.position.w);
+	vec3 radiance = light.color.rgb * light.color.w;
+	if (type == LIGHT_DIRECTIONAL) {
+		lightDir = -light.direction.xyz;
+		return radiance;
+	}
+	vec3 toLight = light.position.xyz - position;
+	float lightDistance = length(toLight);
+	lightDir = toLight / max(lightDistance, 0.0001);
+	// inverse square falloff that reaches 0 at the range
+	float range = light.direction.w;
+	float window = clamp(1.0 - pow(lightDistance / max(range, 0.0001), 4.0), 0.0, 1.0);
+	radiance *= window * window / (lightDistance * lightDistance + 1.0);
+	if (type == LIGHT_SPOT) {
+		float cosAngle = dot(-lightDir, light.direction.xyz);
+		radiance *= smoothstep(light.cone.y, light.cone.x, cosAngle);
+	}
+	return radiance;
+}
+
+void main() 
+{
+	MaterialData materialData = MaterialData(inMaterialData);
+	vec4 baseColor = texture(textures[inTextureIndices.x], inUV) * materialData.colorFactors;
+	// gltf stores roughness in g and metallic in b
+	vec4 metalRough = texture(textures[inTextureIndices.y], inUV);
+	float metallic = metalRough.b * materialData.metal_rough_factors.x;
+	float roughness = metalRough.g * materialData.metal_rough_factors.y;
+
+	vec3 normal = perturbNormal(normalize(inNormal), inPosition, inUV);
+	vec3 lightDir = normalize(-sceneData.sunlightDirection.xyz);
+	float shadow = shadowFactor(inPosition, normal, lightDir);
+	vec3 viewDir = normalize(sceneData.cameraPosition.xyz - inPosition);
+
+	// only the sun casts shadows
+	vec3 lit = shade(lightDir, sceneData.sunlightColor.rgb, normal, viewDir, baseColor.rgb, metallic, roughness) * shadow;
+	for (uint i = 0; i < sceneData.lightCount.x; i++) {
+		vec3 bufferLightDir;
+		vec3 radiance = lightRadiance(lightBuffer.lights[i], inPosition, bufferLightDir);
+		lit += shade(bufferLightDir, radiance, normal, viewDir, baseColor.rgb, metallic, roughness);
+	}
+	// split sum approximation, the ambient color scales the whole environment
+	float NdotV = max(dot(normal, viewDir), 0.0);
+	vec3 irradiance = texture(irradianceMap, normal).rgb;
+	float specularLod = roughness * float(textureQueryLevels(specularMap) - 1);
+	vec3 prefiltered = textureLod(specularMap, reflect(-viewDir, normal), specularLod).rgb;
+	vec2 brdf = texture(brdfLut, vec2(NdotV, roughness)).rg;
+	vec3 F0 = mix(vec3(0.04), baseColor.rgb, metallic);
+	vec3 ambient = (baseColor.rgb * (1.0 - metallic) * irradiance + prefiltered * (F0 * brdf.x + brdf.y)) * sceneData.ambientColor.rgb;
+
+	// weighted blended order independent transparency (McGuire and Bavoil), closer fragments get
+	// a larger weight => they win where the order matters
+	float alpha = baseColor.a;
+	float viewDepth = abs((sceneData.view * vec4(inPosition, 1.0)).z);
+	float weight = alpha * clamp(10.0 / (1e-5 + pow(viewDepth / 5.0, 2.0) + pow(viewDepth / 200.0, 6.0)), 1e-2, 3e3);
+	outAccumulation = vec4((lit + ambient) * alpha, alpha) * weight;
+	// alpha 1 would be an infinite sum
+	outRevealage = -log(1.0 - min(alpha, 0.999));
+}
@@ -0,0 +1,25 @@
+#version 450
+
+// from skybox.vert
+layout (location = 0) in vec2 inPosition;
+
+// blended over the draw image with alpha = coverage of the transparent surfaces
+layout (location = 0) out vec4 outFragColor;
+
+// written by mesh_oit.frag, same pixel grid as the draw image => texel fetches
+layout(set = 0, binding = 0) uniform sampler2D accumulation;
+layout(set = 0, binding = 1) uniform sampler2D revealage;
+
+void main()
+{
+	ivec2 texel = ivec2(gl_FragCoord.xy);
+	// product of (1 - alpha) of all fragments, stored as -log
+	float revealed = exp(-texelFetch(revealage, texel, 0).r);
+	if (revealed >= 1.0) {
+		discard;
+	}
+	vec4 accumulated = texelFetch(accumulation, texel, 0);
+	// weighted average of the premultiplied colors
+	vec3 color = accumulated.rgb / max(accumulated.a, 1e-5);
+	outFragColor = vec4(color, 1.0 - revealed);
+}
@@ -0,0 +1,59 @@
+#version 450
+#extension GL_EXT_buffer_reference : require
+#extension GL_EXT_buffer_reference_uvec2 : require
+
+struct Vertex {
+	vec3 position;
+	float uv_x;
+	vec3 normal;
+	float uv_y;
+	vec4 color;
+};
+
+layout(buffer_reference, std430) readonly buffer VertexBuffer{
+	Vertex vertices[];
+};
+
+// same layout as GPUDrawData
+struct DrawData {
+	mat4 model_matrix;
+	VertexBuffer vertexBuffer;
+	uvec2 materialData;
+	uvec4 textureIndices;
+	uvec2 indexBuffer;
+};
+
+layout(buffer_reference, std430) readonly buffer DrawBuffer{
+	DrawData draws[];
+};
+
+// DescriptorSetSlot::Scene
+layout(set = 0, binding = 0) uniform SceneData {
+	mat4 view;
+	mat4 proj;
+	mat4 viewproj;
+	vec4 ambientColor;
+	vec4 sunlightDirection; //w for sun power
+	vec4 sunlightColor;
+	vec4 cameraPosition;
+	mat4 lightViewProj;
+	uvec4 lightCount; //x = number of lights in the light buffer
+} sceneData;
+
+//push constants block, same layout as in mesh.vert
+layout( push_constant ) uniform constants
+{
+	DrawData draw;
+	uvec2 drawBuffer;
+} PushConstants;
+
+// depth only => no outputs besides the position
+void main()
+{
+	DrawData draw = PushConstants.draw;
+	if (PushConstants.drawBuffer != uvec2(0)) {
+		draw = DrawBuffer(PushConstants.drawBuffer).draws[gl_InstanceIndex];
+	}
+	Vertex v = draw.vertexBuffer.vertices[gl_VertexIndex];
+	gl_Position = sceneData.lightViewProj * draw.model_matrix * vec4(v.position, 1.0f);
+}
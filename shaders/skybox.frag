@@ -0,0 +1,22 @@
+#version 450
+
+layout (location = 0) in vec2 inPosition;
+
+layout (location = 0) out vec4 outFragColor;
+
+layout(set = 0, binding = 0) uniform samplerCube skybox;
+
+layout( push_constant ) uniform constants
+{
+	mat4 inverseViewProj;
+} PushConstants;
+
+void main()
+{
+	// reversed depth => 1 is the near and 0 the far plane
+	// the difference of both points is the view ray, also for orthographic cameras
+	vec4 near = PushConstants.inverseViewProj * vec4(inPosition, 1.0, 1.0);
+	vec4 far = PushConstants.inverseViewProj * vec4(inPosition, 0.0, 1.0);
+	vec3 direction = normalize(far.xyz / far.w - near.xyz / near.w);
+	outFragColor = vec4(textureLod(skybox, direction, 0.0).rgb, 1.0);
+}
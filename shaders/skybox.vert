@@ -0,0 +1,12 @@
+#version 450
+
+layout (location = 0) out vec2 outPosition;
+
+// one triangle that covers the whole screen, no vertex buffer needed
+void main()
+{
+	vec2 uv = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2);
+	outPosition = uv * 2.0 - 1.0;
+	// reversed depth => 0 is the far plane
+	gl_Position = vec4(outPosition, 0.0, 1.0);
+}
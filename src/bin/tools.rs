@@ -0,0 +1,102 @@
+use game_engine::image_diff;
+use game_engine::image_diff::ImageDiffSettings;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+const USAGE: &str = "Usage: tools <COMMAND> [OPTIONS]
+
+Commands:
+  diff <EXPECTED> <ACTUAL>
+                        compare two screenshots, exits with 1 if they differ too much
+    --heatmap <PATH>    write an image that shows where the screenshots differ
+    --threshold <PERCENT>
+                        allowed share of visibly different pixels (default: 0.1)
+    --tolerance <VALUE> ignore channel differences up to VALUE (0-255, default: 2)
+  -h, --help            print this help";
+
+struct DiffArgs {
+    expected: PathBuf,
+    actual: PathBuf,
+    heatmap: Option<PathBuf>,
+    settings: ImageDiffSettings,
+}
+
+impl DiffArgs {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut paths = Vec::new();
+        let mut heatmap = None;
+        let mut settings = ImageDiffSettings::default();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--heatmap" => {
+                    let path = args.next().ok_or("--heatmap expects a path")?;
+                    heatmap = Some(PathBuf::from(path));
+                }
+                "--threshold" => {
+                    let percent = args
+                        .next()
+                        .ok_or("--threshold expects a percentage")?
+                        .parse::<f32>()
+                        .map_err(|e| format!("Invalid percentage for --threshold: {}", e))?;
+                    if !(0.0..=100.0).contains(&percent) {
+                        return Err("--threshold expects a percentage between 0 and 100".into());
+                    }
+                    settings.max_different_pixels = percent / 100.0;
+                }
+                "--tolerance" => {
+                    settings.channel_tolerance = args
+                        .next()
+                        .ok_or("--tolerance expects a value")?
+                        .parse::<u8>()
+                        .map_err(|e| format!("Invalid value for --tolerance: {}", e))?;
+                }
+                _ if arg.starts_with("--") => return Err(format!("Unknown argument: {}", arg)),
+                _ => paths.push(PathBuf::from(arg)),
+            }
+        }
+        let [expected, actual]: [PathBuf; 2] = paths
+            .try_into()
+            .map_err(|_| "diff expects exactly two images")?;
+        Ok(DiffArgs {
+            expected,
+            actual,
+            heatmap,
+            settings,
+        })
+    }
+}
+
+fn diff(args: DiffArgs) -> Result<bool, String> {
+    let diff = image_diff::diff_files(&args.expected, &args.actual, args.settings)?;
+    println!("{}", diff.summary());
+    if let Some(path) = args.heatmap {
+        diff.heatmap()
+            .save(&path)
+            .map_err(|e| format!("Could not write heatmap {:?}: {}", path, e))?;
+        println!("Wrote heatmap to {:?}", path);
+    }
+    Ok(diff.passed())
+}
+
+// exit codes: 0 => images match, 1 => images differ, 2 => invalid arguments or unreadable files
+fn main() -> ExitCode {
+    env_logger::init();
+    let mut args = std::env::args().skip(1);
+    let result = match args.next().as_deref() {
+        Some("diff") => DiffArgs::parse(args).and_then(diff),
+        Some("-h") | Some("--help") => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Some(command) => Err(format!("Unknown command: {}", command)),
+        None => Err("Missing command".to_string()),
+    };
+    match result {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::from(1),
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            ExitCode::from(2)
+        }
+    }
+}
@@ -0,0 +1,256 @@
+use crate::input::Action;
+use crate::input::ActionMap;
+use crate::input::InputState;
+use crate::vulkan_rs::Aabb;
+use nalgebra_glm as glm;
+
+// radians per pixel of mouse movement
+const MOUSE_SENSITIVITY: f32 = 0.003;
+// dont allow looking straight up/down => look_at would get a degenerate up vector
+const MAX_PITCH: f32 = 89.0 * std::f32::consts::PI / 180.0;
+const MIN_ORBIT_DISTANCE: f32 = 0.5;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Projection {
+    // fov_y in radians
+    Perspective { fov_y: f32, near: f32, far: f32 },
+    // height of the visible area in world units, width follows from the aspect ratio
+    Orthographic { height: f32, near: f32, far: f32 },
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Camera {
+    pub position: glm::Vec3,
+    // yaw = pitch = 0 => looking down -z
+    pub yaw: f32,
+    pub pitch: f32,
+    pub projection: Projection,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Camera {
+            position: glm::vec3(0.0, 0.0, 5.0),
+            yaw: 0.0,
+            pitch: 0.0,
+            projection: Projection::Perspective {
+                fov_y: 70.0 * std::f32::consts::PI / 180.0,
+                near: 0.1,
+                far: 100.0,
+            },
+        }
+    }
+}
+
+impl Camera {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn forward(&self) -> glm::Vec3 {
+        glm::vec3(
+            self.pitch.cos() * self.yaw.sin(),
+            self.pitch.sin(),
+            -self.pitch.cos() * self.yaw.cos(),
+        )
+    }
+
+    pub fn right(&self) -> glm::Vec3 {
+        glm::normalize(&glm::cross(&self.forward(), &glm::Vec3::y()))
+    }
+
+    pub fn view_matrix(&self) -> glm::Mat4 {
+        glm::look_at_rh(
+            &self.position,
+            &(self.position + self.forward()),
+            &glm::Vec3::y(),
+        )
+    }
+
+    // reversed depth (near = 1, far = 0) and y pointing down like vulkan clip space
+    pub fn projection_matrix(&self, aspect_ratio: f32) -> glm::Mat4 {
+        let mut projection = match self.projection {
+            Projection::Perspective { fov_y, near, far } => {
+                glm::reversed_perspective_rh_zo(aspect_ratio, fov_y, near, far)
+            }
+            Projection::Orthographic { height, near, far } => {
+                let half_height = height / 2.0;
+                let half_width = half_height * aspect_ratio;
+                // swapping near and far reverses the depth range
+                glm::ortho_rh_zo(
+                    -half_width,
+                    half_width,
+                    -half_height,
+                    half_height,
+                    far,
+                    near,
+                )
+            }
+        };
+        projection[(1, 1)] *= -1.0;
+        projection
+    }
+
+    // jitter is added in clip space => 2 / extent moves the image by one pixel (taa)
+    pub fn jittered_projection_matrix(&self, aspect_ratio: f32, jitter: glm::Vec2) -> glm::Mat4 {
+        glm::translation(&glm::vec3(jitter.x, jitter.y, 0.0)) * self.projection_matrix(aspect_ratio)
+    }
+
+    // alpha = 0 => self, alpha = 1 => other. The projection is not interpolated
+    pub fn interpolate(&self, other: &Camera, alpha: f32) -> Camera {
+        Camera {
+            position: glm::lerp(&self.position, &other.position, alpha),
+            yaw: self.yaw + (other.yaw - self.yaw) * alpha,
+            pitch: self.pitch + (other.pitch - self.pitch) * alpha,
+            projection: other.projection,
+        }
+    }
+
+    pub fn toggle_projection(&mut self) {
+        self.projection = match self.projection {
+            Projection::Perspective { near, far, .. } => Projection::Orthographic {
+                height: 5.0,
+                near,
+                far,
+            },
+            Projection::Orthographic { near, far, .. } => Projection::Perspective {
+                fov_y: 70.0 * std::f32::consts::PI / 180.0,
+                near,
+                far,
+            },
+        };
+    }
+}
+
+// the six planes of a view projection, xyz: normal pointing inwards, w: distance
+#[derive(Debug, Clone, Copy)]
+pub struct Frustum {
+    planes: [glm::Vec4; 6],
+}
+
+impl Frustum {
+    // reversed depth clip space like the camera: -w <= x <= w, -w <= y <= w, 0 <= z <= w
+    pub fn from_view_proj(view_proj: &glm::Mat4) -> Self {
+        let row = |idx: usize| view_proj.row(idx).transpose();
+        let planes = [
+            row(3) + row(0),
+            row(3) - row(0),
+            row(3) + row(1),
+            row(3) - row(1),
+            row(2),
+            row(3) - row(2),
+        ]
+        .map(|plane| plane / glm::length(&plane.xyz()));
+        Frustum { planes }
+    }
+
+    // conservative => boxes close to a corner can pass although they are outside
+    pub fn intersects(&self, aabb: &Aabb) -> bool {
+        let center = aabb.center();
+        let extent = aabb.extent();
+        self.planes.iter().all(|plane| {
+            let normal = plane.xyz();
+            let radius = glm::dot(&extent, &glm::abs(&normal));
+            glm::dot(&normal, &center) + plane.w + radius >= 0.0
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ControllerMode {
+    // move actions move the camera, the look action rotates it
+    FirstPerson,
+    // the look action rotates around the target, the wheel zooms and move actions move the target
+    Orbit,
+}
+
+#[derive(Debug)]
+pub struct CameraController {
+    mode: ControllerMode,
+    // units per second
+    speed: f32,
+    orbit_target: glm::Vec3,
+    orbit_distance: f32,
+}
+
+impl Default for CameraController {
+    fn default() -> Self {
+        CameraController {
+            mode: ControllerMode::FirstPerson,
+            speed: 3.0,
+            orbit_target: glm::Vec3::zeros(),
+            orbit_distance: 5.0,
+        }
+    }
+}
+
+impl CameraController {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn mode(&self) -> ControllerMode {
+        self.mode
+    }
+
+    // the orbit target is placed in front of the camera => switching doesnt move the camera
+    pub fn set_mode(&mut self, camera: &Camera, mode: ControllerMode) {
+        if mode == ControllerMode::Orbit {
+            self.orbit_target = camera.position + camera.forward() * self.orbit_distance;
+        }
+        self.mode = mode;
+    }
+
+    pub fn toggle_mode(&mut self, camera: &Camera) {
+        let mode = match self.mode {
+            ControllerMode::FirstPerson => ControllerMode::Orbit,
+            ControllerMode::Orbit => ControllerMode::FirstPerson,
+        };
+        self.set_mode(camera, mode);
+    }
+
+    pub fn update(
+        &mut self,
+        camera: &mut Camera,
+        input: &InputState,
+        actions: &ActionMap,
+        delta_time: f32,
+    ) {
+        if actions.is_held(input, Action::Look) {
+            let mouse_delta = input.mouse_delta();
+            camera.yaw += mouse_delta.x * MOUSE_SENSITIVITY;
+            camera.pitch =
+                (camera.pitch - mouse_delta.y * MOUSE_SENSITIVITY).clamp(-MAX_PITCH, MAX_PITCH);
+        }
+
+        let mut movement = camera.forward()
+            * actions.axis(input, Action::MoveForward, Action::MoveBackward)
+            + camera.right() * actions.axis(input, Action::MoveRight, Action::MoveLeft)
+            + glm::Vec3::y() * actions.axis(input, Action::MoveUp, Action::MoveDown);
+        if movement.norm_squared() > 0.0 {
+            // diagonal movement should not be faster
+            movement = glm::normalize(&movement);
+        }
+        let speed = if actions.is_held(input, Action::MoveFast) {
+            self.speed * 4.0
+        } else {
+            self.speed
+        };
+        let movement = movement * speed * delta_time;
+
+        let scroll_delta = input.scroll_delta();
+        match self.mode {
+            ControllerMode::FirstPerson => {
+                camera.position += movement;
+                // wheel changes the movement speed
+                self.speed = (self.speed * 1.1f32.powf(scroll_delta)).clamp(0.1, 100.0);
+            }
+            ControllerMode::Orbit => {
+                self.orbit_target += movement;
+                self.orbit_distance =
+                    (self.orbit_distance * 0.9f32.powf(scroll_delta)).max(MIN_ORBIT_DISTANCE);
+                camera.position = self.orbit_target - camera.forward() * self.orbit_distance;
+            }
+        }
+    }
+}
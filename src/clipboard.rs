@@ -0,0 +1,55 @@
+// thin wrapper around the system clipboard
+// not every platform/session has one (e.g. headless) => failures are logged and ignored
+pub struct Clipboard {
+    clipboard: Option<arboard::Clipboard>,
+}
+
+impl Clipboard {
+    pub fn new() -> Self {
+        let clipboard = match arboard::Clipboard::new() {
+            Ok(clipboard) => Some(clipboard),
+            Err(e) => {
+                log::warn!("Clipboard is not available: {}", e);
+                None
+            }
+        };
+        Clipboard { clipboard }
+    }
+
+    pub fn get_text(&mut self) -> Option<String> {
+        match self.clipboard.as_mut()?.get_text() {
+            Ok(text) => Some(text),
+            Err(e) => {
+                log::debug!("Could not read clipboard: {}", e);
+                None
+            }
+        }
+    }
+
+    pub fn set_text(&mut self, text: &str) -> bool {
+        let Some(clipboard) = self.clipboard.as_mut() else {
+            return false;
+        };
+        match clipboard.set_text(text) {
+            Ok(()) => true,
+            Err(e) => {
+                log::warn!("Could not write to clipboard: {}", e);
+                false
+            }
+        }
+    }
+}
+
+impl Default for Clipboard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl std::fmt::Debug for Clipboard {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Clipboard")
+            .field("available", &self.clipboard.is_some())
+            .finish()
+    }
+}
@@ -0,0 +1,89 @@
+use std::time::Duration;
+use std::time::Instant;
+use winit::dpi::PhysicalPosition;
+use winit::dpi::PhysicalSize;
+use winit::monitor::MonitorHandle;
+use winit::window::Fullscreen;
+use winit::window::Window;
+
+#[derive(Debug, Clone)]
+pub struct MonitorInfo {
+    pub name: Option<String>,
+    pub size: PhysicalSize<u32>,
+    pub position: PhysicalPosition<i32>,
+    pub refresh_rate_hz: Option<f64>,
+    pub scale_factor: f64,
+    pub is_current: bool,
+    handle: MonitorHandle,
+}
+
+impl MonitorInfo {
+    fn new(handle: MonitorHandle, current: Option<&MonitorHandle>) -> Self {
+        MonitorInfo {
+            name: handle.name(),
+            size: handle.size(),
+            position: handle.position(),
+            refresh_rate_hz: handle
+                .refresh_rate_millihertz()
+                .map(|millihertz| millihertz as f64 / 1000.0),
+            scale_factor: handle.scale_factor(),
+            is_current: current == Some(&handle),
+            handle,
+        }
+    }
+}
+
+pub fn enumerate_monitors(window: &Window) -> Vec<MonitorInfo> {
+    let current = window.current_monitor();
+    window
+        .available_monitors()
+        .map(|handle| MonitorInfo::new(handle, current.as_ref()))
+        .collect()
+}
+
+pub fn current_refresh_rate(window: &Window) -> Option<f64> {
+    window
+        .current_monitor()
+        .and_then(|monitor| monitor.refresh_rate_millihertz())
+        .map(|millihertz| millihertz as f64 / 1000.0)
+}
+
+pub fn set_target_monitor(window: &Window, monitor: &MonitorInfo) {
+    log::info!("Moving window to monitor {:?}", monitor.name);
+    if window.fullscreen().is_some() {
+        window.set_fullscreen(Some(Fullscreen::Borderless(Some(monitor.handle.clone()))));
+    } else {
+        window.set_outer_position(monitor.position);
+    }
+}
+
+pub struct FrameLimiter {
+    target_frame_time: Duration,
+    next_frame: Instant,
+}
+
+impl FrameLimiter {
+    pub fn new(refresh_rate_hz: f64) -> Self {
+        FrameLimiter {
+            target_frame_time: Duration::from_secs_f64(1.0 / refresh_rate_hz),
+            next_frame: Instant::now(),
+        }
+    }
+
+    pub fn set_refresh_rate(&mut self, refresh_rate_hz: f64) {
+        self.target_frame_time = Duration::from_secs_f64(1.0 / refresh_rate_hz);
+    }
+
+    pub fn target_frame_time(&self) -> Duration {
+        self.target_frame_time
+    }
+
+    pub fn next_frame_time(&self) -> Instant {
+        self.next_frame
+    }
+
+    pub fn frame_started(&mut self) {
+        // if we fell behind, dont try to catch up with a burst of frames
+        self.next_frame = Instant::max(self.next_frame + self.target_frame_time, Instant::now());
+    }
+}
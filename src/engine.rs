@@ -0,0 +1,543 @@
+use crate::display;
+use crate::display::FrameLimiter;
+use crate::histogram_overlay::HistogramOverlay;
+use crate::input::Action;
+use crate::input::ActionMap;
+use crate::input::InputState;
+use crate::profile_scope;
+use crate::profiler;
+use crate::stats_overlay::StatsOverlay;
+use crate::vulkan_renderer::RendererConfig;
+use crate::vulkan_renderer::VulkanRenderer;
+use crate::vulkan_rs::report_live_objects;
+use std::sync::Arc;
+use winit::application::ApplicationHandler;
+use winit::error::EventLoopError;
+use winit::event::DeviceEvent;
+use winit::event::DeviceId;
+use winit::event::ElementState;
+use winit::event::StartCause;
+use winit::event::WindowEvent;
+use winit::event_loop::ActiveEventLoop;
+use winit::event_loop::ControlFlow;
+use winit::event_loop::EventLoop;
+use winit::window::Fullscreen;
+use winit::window::Window;
+use winit::window::WindowId;
+
+// fixed updates beyond this are dropped => the game runs slower than real time instead
+const MAX_UPDATES_PER_FRAME: u32 = 8;
+
+// everything the game code can access during a callback
+// fields are public => renderer and input can be borrowed at the same time
+pub struct Context<'a> {
+    pub event_loop: &'a ActiveEventLoop,
+    pub window: &'a Window,
+    pub renderer: &'a mut VulkanRenderer,
+    pub input: &'a InputState,
+    pub actions: &'a ActionMap,
+    exit_requested: bool,
+}
+
+impl Context<'_> {
+    // the engine stops after the current callback
+    pub fn exit(&mut self) {
+        self.exit_requested = true;
+    }
+}
+
+// implemented by the game, the engine calls it from the event loop
+pub trait App {
+    // called once the window and the renderer exist
+    fn init(&mut self, _context: &mut Context) {}
+
+    // called with a fixed delta_time (in seconds) => zero or more times per frame
+    // pressed/released input is only reported to the first update that runs after it happened
+    fn update(&mut self, context: &mut Context, delta_time: f32);
+
+    // called once per frame right before the renderer draws it
+    // alpha (0..1) is how far the frame is between the last and the next update
+    // => interpolate between the last two update states for smooth motion
+    fn render(&mut self, _context: &mut Context, _alpha: f32) {}
+
+    // called once per frame after render, build the debug ui here (egui immediate mode)
+    fn ui(&mut self, _context: &mut Context, _ui: &egui::Context) {}
+
+    // return true to consume the event => it doesnt reach the input state
+    // events that egui uses (e.g. clicks on a window) are consumed before they get here
+    // resizing, moving and closing the window is always handled by the engine
+    fn window_event(&mut self, _context: &mut Context, _event: &WindowEvent) -> bool {
+        false
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FramePacing {
+    // render as fast as possible, only the present mode limits the frame rate
+    Unlimited,
+    // wait for the refresh rate of the monitor the window is on
+    RefreshRate,
+    // frames per second
+    MaxFps(f64),
+}
+
+// entry point of a game: Engine::builder().title("Game").run(app)
+pub struct Engine;
+
+impl Engine {
+    pub fn builder() -> EngineBuilder {
+        EngineBuilder::new()
+    }
+
+    // default window and renderer settings
+    pub fn run<A: App>(app: A) -> Result<(), EventLoopError> {
+        EngineBuilder::new().run(app)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct EngineBuilder {
+    title: String,
+    width: u32,
+    height: u32,
+    fullscreen: bool,
+    monitor: Option<usize>,
+    frame_pacing: FramePacing,
+    // fixed updates per second
+    update_rate: f32,
+    renderer_config: RendererConfig,
+    actions: ActionMap,
+    // visible at startup, toggled with Action::ToggleStats
+    stats_overlay: bool,
+}
+
+impl Default for EngineBuilder {
+    fn default() -> Self {
+        EngineBuilder {
+            title: "LexEngine".to_string(),
+            width: 1800,
+            height: 1000,
+            fullscreen: false,
+            monitor: None,
+            frame_pacing: FramePacing::Unlimited,
+            update_rate: 60.0,
+            renderer_config: RendererConfig::default(),
+            actions: ActionMap::default(),
+            stats_overlay: false,
+        }
+    }
+}
+
+impl EngineBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn title(mut self, title: &str) -> Self {
+        self.title = title.to_string();
+        self
+    }
+
+    // logical size => scaled by the scale factor of the monitor
+    pub fn size(mut self, width: u32, height: u32) -> Self {
+        self.width = width;
+        self.height = height;
+        self
+    }
+
+    pub fn fullscreen(mut self, fullscreen: bool) -> Self {
+        self.fullscreen = fullscreen;
+        self
+    }
+
+    pub fn monitor(mut self, monitor: Option<usize>) -> Self {
+        self.monitor = monitor;
+        self
+    }
+
+    pub fn frame_pacing(mut self, frame_pacing: FramePacing) -> Self {
+        self.frame_pacing = frame_pacing;
+        self
+    }
+
+    // how often App::update is called per second, independent of the frame rate
+    pub fn update_rate(mut self, update_rate: f32) -> Self {
+        self.update_rate = update_rate.max(1.0);
+        self
+    }
+
+    pub fn renderer_config(mut self, renderer_config: RendererConfig) -> Self {
+        self.renderer_config = renderer_config;
+        self
+    }
+
+    pub fn actions(mut self, actions: ActionMap) -> Self {
+        self.actions = actions;
+        self
+    }
+
+    pub fn stats_overlay(mut self, visible: bool) -> Self {
+        self.stats_overlay = visible;
+        self
+    }
+
+    // blocks until the app exits or the window is closed
+    pub fn run<A: App>(self, app: A) -> Result<(), EventLoopError> {
+        let event_loop = EventLoop::new()?;
+        event_loop.set_control_flow(ControlFlow::Poll);
+        let stats_overlay = StatsOverlay::new(self.stats_overlay);
+        let mut engine = EngineLoop {
+            settings: self,
+            app,
+            window: None,
+            renderer: None,
+            last_frame: std::time::Instant::now(),
+            accumulated_time: 0.0,
+            frame_limiter: None,
+            input: InputState::new(),
+            ui: None,
+            stats_overlay,
+            histogram_overlay: HistogramOverlay::new(),
+        };
+        let result = event_loop.run_app(&mut engine);
+        // the renderer owns every gpu object => everything still alive afterwards was leaked
+        drop(engine);
+        report_live_objects();
+        result
+    }
+}
+
+struct EngineLoop<A: App> {
+    settings: EngineBuilder,
+    app: A,
+    window: Option<Arc<Window>>,
+    renderer: Option<VulkanRenderer>,
+    last_frame: std::time::Instant,
+    // time that was not simulated by fixed updates yet
+    accumulated_time: f32,
+    frame_limiter: Option<FrameLimiter>,
+    input: InputState,
+    // created with the window
+    ui: Option<egui_winit::State>,
+    stats_overlay: StatsOverlay,
+    histogram_overlay: HistogramOverlay,
+}
+
+impl<A: App> EngineLoop<A> {
+    fn select_monitor(window: &Window, monitor_idx: usize) {
+        let monitors = display::enumerate_monitors(window);
+        match monitors.get(monitor_idx) {
+            Some(monitor) => display::set_target_monitor(window, monitor),
+            None => log::warn!(
+                "Monitor {} does not exist. There are {} monitors",
+                monitor_idx,
+                monitors.len()
+            ),
+        }
+    }
+
+    // only needed for FramePacing::RefreshRate, the other modes dont depend on the monitor
+    fn update_frame_limiter(&mut self) {
+        if self.settings.frame_pacing != FramePacing::RefreshRate {
+            return;
+        }
+        if let (Some(frame_limiter), Some(window)) = (self.frame_limiter.as_mut(), &self.window) {
+            // not every platform reports refresh rates => 60Hz is a sane guess
+            let refresh_rate = display::current_refresh_rate(window).unwrap_or(60.0);
+            frame_limiter.set_refresh_rate(refresh_rate);
+            log::debug!("Limiting frame rate to {:.2} Hz", refresh_rate);
+        }
+    }
+
+    // falls back to safe mode instead of exiting => the user can still change the settings
+    // the error is shown in the title and available through VulkanRenderer::startup_error
+    fn create_renderer(&self, window: &Arc<Window>) -> Option<VulkanRenderer> {
+        let config = &self.settings.renderer_config;
+        let error = match VulkanRenderer::new(window.clone(), config.clone()) {
+            Ok(renderer) => return Some(renderer),
+            Err(e) => e,
+        };
+        log::error!("Failed to create renderer: {}", error);
+        log::warn!("Retrying in safe mode");
+        match VulkanRenderer::new(window.clone(), config.safe_mode()) {
+            Ok(mut renderer) => {
+                window.set_title(&format!("{} (safe mode: {})", self.settings.title, error));
+                renderer.set_startup_error(error.to_string());
+                Some(renderer)
+            }
+            Err(e) => {
+                log::error!("Failed to create renderer in safe mode: {}", e);
+                None
+            }
+        }
+    }
+
+    fn init_window(&mut self, event_loop: &ActiveEventLoop) -> Arc<Window> {
+        let window = event_loop
+            .create_window(
+                Window::default_attributes()
+                    .with_title(self.settings.title.clone())
+                    .with_inner_size(winit::dpi::LogicalSize::new(
+                        self.settings.width,
+                        self.settings.height,
+                    ))
+                    .with_fullscreen(
+                        self.settings
+                            .fullscreen
+                            .then_some(Fullscreen::Borderless(None)),
+                    ),
+            )
+            .expect("Window creation failed");
+        let window = Arc::new(window);
+        log::info!("succesfully created window");
+        for (idx, monitor) in display::enumerate_monitors(&window).iter().enumerate() {
+            log::info!(
+                "Monitor {}: {:?} {}x{} @ {:?} Hz{}",
+                idx,
+                monitor.name,
+                monitor.size.width,
+                monitor.size.height,
+                monitor.refresh_rate_hz,
+                if monitor.is_current { " (current)" } else { "" }
+            );
+        }
+        if let Some(monitor_idx) = self.settings.monitor {
+            Self::select_monitor(&window, monitor_idx);
+        }
+        window
+    }
+}
+
+impl<A: App> ApplicationHandler for EngineLoop<A> {
+    fn resumed(&mut self, event_loop: &ActiveEventLoop) {
+        log::info!("Setting up window and renderer");
+        let window = self.init_window(event_loop);
+
+        let Some(mut renderer) = self.create_renderer(&window) else {
+            event_loop.exit();
+            return;
+        };
+        let mut context = Context {
+            event_loop,
+            window: &window,
+            renderer: &mut renderer,
+            input: &self.input,
+            actions: &self.settings.actions,
+            exit_requested: false,
+        };
+        self.app.init(&mut context);
+        if context.exit_requested {
+            event_loop.exit();
+        }
+        self.ui = Some(egui_winit::State::new(
+            egui::Context::default(),
+            egui::ViewportId::ROOT,
+            &window,
+            Some(window.scale_factor() as f32),
+            None,
+            None,
+        ));
+        self.renderer = Some(renderer);
+        self.window = Some(window);
+        match self.settings.frame_pacing {
+            FramePacing::Unlimited => (),
+            FramePacing::RefreshRate => {
+                self.frame_limiter = Some(FrameLimiter::new(60.0));
+                self.update_frame_limiter();
+            }
+            FramePacing::MaxFps(fps) => self.frame_limiter = Some(FrameLimiter::new(fps)),
+        }
+        // dont count renderer setup as frame time
+        self.last_frame = std::time::Instant::now();
+    }
+
+    fn window_event(&mut self, event_loop: &ActiveEventLoop, _id: WindowId, event: WindowEvent) {
+        let (Some(renderer), Some(window)) = (self.renderer.as_mut(), self.window.as_ref()) else {
+            return;
+        };
+        let mut exit = false;
+        let mut moved = false;
+
+        let mut context = Context {
+            event_loop,
+            window,
+            renderer,
+            input: &self.input,
+            actions: &self.settings.actions,
+            exit_requested: false,
+        };
+        let ui_consumed = self
+            .ui
+            .as_mut()
+            .is_some_and(|ui| ui.on_window_event(window, &event).consumed);
+        let consumed = ui_consumed || self.app.window_event(&mut context, &event);
+        exit |= context.exit_requested;
+        match &event {
+            _ if !consumed => self.input.handle_event(&event),
+            // releases and focus changes always reach the input state => held keys dont get
+            // stuck when the ui took the event, e.g. the cursor is over a panel
+            WindowEvent::KeyboardInput { event: key, .. }
+                if key.state == ElementState::Released =>
+            {
+                self.input.handle_event(&event)
+            }
+            WindowEvent::MouseInput {
+                state: ElementState::Released,
+                ..
+            }
+            | WindowEvent::Focused(_) => self.input.handle_event(&event),
+            // another system (text input) takes over the keyboard => stop held movement keys
+            WindowEvent::KeyboardInput { .. } => self.input.release_all(),
+            _ => (),
+        }
+
+        match event {
+            WindowEvent::CloseRequested => {
+                log::info!("The close button was pressed; stopping");
+                exit = true;
+            }
+            WindowEvent::RedrawRequested => {
+                let delta_time = self.last_frame.elapsed().as_secs_f32();
+                self.last_frame = std::time::Instant::now();
+                if let Some(frame_limiter) = self.frame_limiter.as_mut() {
+                    frame_limiter.frame_started();
+                }
+                let fixed_delta_time = 1.0 / self.settings.update_rate;
+                self.accumulated_time += delta_time;
+                let mut updates = 0;
+                while self.accumulated_time >= fixed_delta_time && !exit {
+                    // long hitch, e.g. while the window is dragged
+                    if updates == MAX_UPDATES_PER_FRAME {
+                        self.accumulated_time = 0.0;
+                        break;
+                    }
+                    let mut context = Context {
+                        event_loop,
+                        window,
+                        renderer,
+                        input: &self.input,
+                        actions: &self.settings.actions,
+                        exit_requested: false,
+                    };
+                    {
+                        profile_scope!("update");
+                        self.app.update(&mut context, fixed_delta_time);
+                    }
+                    exit |= context.exit_requested;
+                    if self
+                        .settings
+                        .actions
+                        .was_released(&self.input, Action::ToggleStats)
+                    {
+                        self.stats_overlay.toggle();
+                    }
+                    if self
+                        .settings
+                        .actions
+                        .was_released(&self.input, Action::ToggleHistogram)
+                    {
+                        self.histogram_overlay.toggle(renderer);
+                    }
+                    self.accumulated_time -= fixed_delta_time;
+                    updates += 1;
+                    // everything after this only sees input that happens afterwards
+                    self.input.end_frame();
+                }
+                let alpha = (self.accumulated_time / fixed_delta_time).clamp(0.0, 1.0);
+                let mut context = Context {
+                    event_loop,
+                    window,
+                    renderer,
+                    input: &self.input,
+                    actions: &self.settings.actions,
+                    exit_requested: false,
+                };
+                {
+                    profile_scope!("render");
+                    self.app.render(&mut context, alpha);
+                }
+                exit |= context.exit_requested;
+                self.stats_overlay.record_frame(delta_time, renderer);
+                if let Some(ui) = self.ui.as_mut() {
+                    profile_scope!("ui");
+                    let raw_input = ui.take_egui_input(window);
+                    let egui_context = ui.egui_ctx().clone();
+                    let mut context = Context {
+                        event_loop,
+                        window,
+                        renderer,
+                        input: &self.input,
+                        actions: &self.settings.actions,
+                        exit_requested: false,
+                    };
+                    let output = egui_context.run(raw_input, |egui_context| {
+                        self.app.ui(&mut context, egui_context);
+                        self.stats_overlay.show(egui_context, context.renderer);
+                        self.histogram_overlay.show(egui_context, context.renderer);
+                    });
+                    exit |= context.exit_requested;
+                    ui.handle_platform_output(window, output.platform_output);
+                    let primitives =
+                        egui_context.tessellate(output.shapes, output.pixels_per_point);
+                    renderer.set_ui(primitives, output.textures_delta, output.pixels_per_point);
+                }
+                renderer.advance_simulation(delta_time);
+                window.pre_present_notify();
+                match renderer.draw() {
+                    Ok(()) => {}
+                    Err(e) if e.is_recoverable() => log::error!("Skipped frame: {}", e),
+                    Err(e) => {
+                        log::error!("Failed to draw frame: {}", e);
+                        exit = true;
+                    }
+                }
+                profiler::end_frame();
+            }
+            WindowEvent::Moved(_) => moved = true,
+            WindowEvent::Resized(physical_size) => {
+                let logical_size = physical_size.to_logical(window.scale_factor());
+                renderer.resize_swapchain(logical_size);
+            }
+            _ => (),
+        }
+        if exit {
+            event_loop.exit();
+            if let Err(e) = renderer.wait_idle() {
+                log::error!("Failed to wait for device idle: {}", e);
+            }
+        }
+        if moved {
+            // the window might be on a monitor with a different refresh rate now
+            self.update_frame_limiter();
+        }
+    }
+
+    fn device_event(
+        &mut self,
+        _event_loop: &ActiveEventLoop,
+        _device_id: DeviceId,
+        event: DeviceEvent,
+    ) {
+        self.input.handle_device_event(&event);
+    }
+
+    fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
+        if let Some(frame_limiter) = &self.frame_limiter {
+            event_loop.set_control_flow(ControlFlow::WaitUntil(frame_limiter.next_frame_time()));
+        }
+    }
+
+    fn new_events(&mut self, _event_loop: &ActiveEventLoop, cause: StartCause) {
+        match cause {
+            StartCause::Poll | StartCause::ResumeTimeReached { .. } => {
+                if let Some(window) = &self.window {
+                    window.request_redraw();
+                }
+            }
+            // an input event woke the loop before the frame limiter deadline => wait for the deadline
+            StartCause::WaitCancelled { .. } | StartCause::Init => {
+                log::trace!("Ignoring cause: {:?}", cause)
+            }
+        }
+    }
+}
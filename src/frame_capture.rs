@@ -0,0 +1,292 @@
+use crate::vulkan_rs::AllocatedBuffer;
+use crate::vulkan_rs::AllocatedImage;
+use crate::vulkan_rs::Allocator;
+use crate::vulkan_rs::Device;
+use crate::vulkan_rs::VulkanError;
+use ash::vk;
+use image::codecs::gif::GifEncoder;
+use image::codecs::gif::Repeat;
+use image::Delay;
+use image::Frame;
+use image::RgbaImage;
+use std::collections::VecDeque;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+#[derive(Debug, Clone, Copy)]
+pub struct FrameCaptureSettings {
+    // how much history is kept in the ring buffer
+    pub duration: Duration,
+    // frames are downscaled to this width (aspect ratio is kept) to keep the ring buffer small
+    pub max_width: u32,
+}
+
+impl Default for FrameCaptureSettings {
+    fn default() -> Self {
+        FrameCaptureSettings {
+            duration: Duration::from_secs(3),
+            max_width: 480,
+        }
+    }
+}
+
+struct CapturedFrame {
+    pixels: Vec<u8>,
+    timestamp: Instant,
+}
+
+pub struct FrameCapture {
+    device: Arc<Device>,
+    allocator: Arc<Mutex<Allocator>>,
+    settings: FrameCaptureSettings,
+    capture_image: AllocatedImage,
+    capture_extent: vk::Extent2D,
+    // one readback buffer per frame in flight => we only read a buffer after its frame finished
+    readback_buffers: Vec<AllocatedBuffer>,
+    pending_readbacks: Vec<Option<Instant>>,
+    frames: VecDeque<CapturedFrame>,
+}
+
+impl FrameCapture {
+    pub fn new(
+        device: Arc<Device>,
+        allocator: Arc<Mutex<Allocator>>,
+        settings: FrameCaptureSettings,
+        source_extent: vk::Extent2D,
+        frames_in_flight: usize,
+    ) -> Result<Self, VulkanError> {
+        let capture_extent = Self::capture_extent(&settings, source_extent);
+        log::info!(
+            "Starting frame capture of the last {:?} at {}x{}",
+            settings.duration,
+            capture_extent.width,
+            capture_extent.height
+        );
+        let (capture_image, readback_buffers) =
+            Self::create_resources(&device, &allocator, capture_extent, frames_in_flight)?;
+
+        Ok(FrameCapture {
+            device,
+            allocator,
+            settings,
+            capture_image,
+            capture_extent,
+            readback_buffers,
+            pending_readbacks: vec![None; frames_in_flight],
+            frames: VecDeque::new(),
+        })
+    }
+
+    fn capture_extent(
+        settings: &FrameCaptureSettings,
+        source_extent: vk::Extent2D,
+    ) -> vk::Extent2D {
+        let width = u32::min(settings.max_width, source_extent.width).max(1);
+        let height = ((source_extent.height as u64 * width as u64)
+            / source_extent.width.max(1) as u64)
+            .max(1) as u32;
+        vk::Extent2D { width, height }
+    }
+
+    fn create_resources(
+        device: &Arc<Device>,
+        allocator: &Arc<Mutex<Allocator>>,
+        capture_extent: vk::Extent2D,
+        frames_in_flight: usize,
+    ) -> Result<(AllocatedImage, Vec<AllocatedBuffer>), VulkanError> {
+        // SRGB so the blit applies the same encoding as the swapchain does
+        let capture_image = AllocatedImage::new(
+            device.clone(),
+            allocator.clone(),
+            vk::Format::R8G8B8A8_SRGB,
+            vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::TRANSFER_SRC,
+            vk::Extent3D {
+                width: capture_extent.width,
+                height: capture_extent.height,
+                depth: 1,
+            },
+            vk::ImageAspectFlags::COLOR,
+            1,
+        )?;
+        let buffer_size = (capture_extent.width * capture_extent.height * 4) as vk::DeviceSize;
+        let readback_buffers = (0..frames_in_flight)
+            .map(|_| {
+                AllocatedBuffer::new(
+                    device.clone(),
+                    allocator.clone(),
+                    "Frame Capture Readback Buffer",
+                    vk::BufferUsageFlags::TRANSFER_DST,
+                    buffer_size,
+                    gpu_allocator::MemoryLocation::GpuToCpu,
+                )
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok((capture_image, readback_buffers))
+    }
+
+    // has to be called while no frame is in flight, e.g. after the swapchain was recreated
+    // frames of the old size are dropped => all frames of an export have the same size
+    pub fn resize(&mut self, source_extent: vk::Extent2D) -> Result<(), VulkanError> {
+        let capture_extent = Self::capture_extent(&self.settings, source_extent);
+        if capture_extent == self.capture_extent {
+            return Ok(());
+        }
+        log::info!(
+            "Window was resized, restarting frame capture at {}x{}",
+            capture_extent.width,
+            capture_extent.height
+        );
+        let (capture_image, readback_buffers) = Self::create_resources(
+            &self.device,
+            &self.allocator,
+            capture_extent,
+            self.readback_buffers.len(),
+        )?;
+        self.capture_image = capture_image;
+        self.readback_buffers = readback_buffers;
+        self.capture_extent = capture_extent;
+        self.pending_readbacks.fill(None);
+        self.frames.clear();
+        Ok(())
+    }
+
+    // has to be called after the frame slot was waited on
+    pub fn collect(&mut self, frame_slot: usize) {
+        if let Some(timestamp) = self.pending_readbacks[frame_slot].take() {
+            let pixels = self.readback_buffers[frame_slot].mapped_bytes().to_vec();
+            self.frames.push_back(CapturedFrame { pixels, timestamp });
+            while let Some(oldest) = self.frames.front() {
+                if timestamp.duration_since(oldest.timestamp) > self.settings.duration {
+                    self.frames.pop_front();
+                } else {
+                    break;
+                }
+            }
+        }
+    }
+
+    // expects source_image to be in TRANSFER_SRC_OPTIMAL layout
+    pub fn record(
+        &mut self,
+        command_buffer: vk::CommandBuffer,
+        frame_slot: usize,
+        source_image: vk::Image,
+        source_extent: vk::Extent2D,
+    ) {
+        let capture_image = self.capture_image.image();
+        self.device.transition_image_layout(
+            command_buffer,
+            capture_image,
+            vk::ImageLayout::UNDEFINED,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+        );
+        self.device.copy_image_to_image(
+            command_buffer,
+            source_image,
+            capture_image,
+            source_extent,
+            self.capture_extent,
+        );
+        self.device.transition_image_layout(
+            command_buffer,
+            capture_image,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+        );
+        let copy_region = vk::BufferImageCopy {
+            buffer_offset: 0,
+            buffer_row_length: 0,
+            buffer_image_height: 0,
+            image_subresource: vk::ImageSubresourceLayers {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                mip_level: 0,
+                base_array_layer: 0,
+                layer_count: 1,
+            },
+            image_offset: vk::Offset3D { x: 0, y: 0, z: 0 },
+            image_extent: vk::Extent3D {
+                width: self.capture_extent.width,
+                height: self.capture_extent.height,
+                depth: 1,
+            },
+        };
+        self.device.cmd_copy_image_to_buffer(
+            command_buffer,
+            capture_image,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            self.readback_buffers[frame_slot].buffer(),
+            &[copy_region],
+        );
+        // the cpu maps the buffer once the frame finished
+        let host_barrier = vk::BufferMemoryBarrier2 {
+            s_type: vk::StructureType::BUFFER_MEMORY_BARRIER_2,
+            src_stage_mask: vk::PipelineStageFlags2::TRANSFER,
+            src_access_mask: vk::AccessFlags2::TRANSFER_WRITE,
+            dst_stage_mask: vk::PipelineStageFlags2::HOST,
+            dst_access_mask: vk::AccessFlags2::HOST_READ,
+            src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            buffer: self.readback_buffers[frame_slot].buffer(),
+            offset: 0,
+            size: vk::WHOLE_SIZE,
+            ..Default::default()
+        };
+        self.device
+            .cmd_pipeline_barrier(command_buffer, &[], &[host_barrier]);
+        self.pending_readbacks[frame_slot] = Some(Instant::now());
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+
+    fn to_rgba_image(&self, frame: &CapturedFrame) -> RgbaImage {
+        RgbaImage::from_raw(
+            self.capture_extent.width,
+            self.capture_extent.height,
+            frame.pixels.clone(),
+        )
+        .expect("Readback buffer has exactly width * height * 4 bytes")
+    }
+
+    pub fn save_image_sequence(&self, directory: &Path) -> image::ImageResult<()> {
+        std::fs::create_dir_all(directory)?;
+        for (idx, frame) in self.frames.iter().enumerate() {
+            let path = directory.join(format!("frame_{:05}.png", idx));
+            self.to_rgba_image(frame).save(&path)?;
+        }
+        log::info!(
+            "Saved {} captured frames to {:?}",
+            self.frames.len(),
+            directory
+        );
+        Ok(())
+    }
+
+    pub fn save_gif(&self, path: &Path) -> image::ImageResult<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let file = std::fs::File::create(path)?;
+        let mut encoder = GifEncoder::new(file);
+        encoder.set_repeat(Repeat::Infinite)?;
+        for (idx, frame) in self.frames.iter().enumerate() {
+            // gif delays are per frame => use the time until the next frame was captured
+            let delay = match self.frames.get(idx + 1) {
+                Some(next) => next.timestamp.duration_since(frame.timestamp),
+                None => Duration::from_millis(16),
+            };
+            encoder.encode_frame(Frame::from_parts(
+                self.to_rgba_image(frame),
+                0,
+                0,
+                Delay::from_saturating_duration(delay),
+            ))?;
+        }
+        log::info!("Saved {} captured frames to {:?}", self.frames.len(), path);
+        Ok(())
+    }
+}
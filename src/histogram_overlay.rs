@@ -0,0 +1,102 @@
+use crate::vulkan_renderer::VulkanRenderer;
+use crate::vulkan_rs::ColorHistogram;
+use crate::vulkan_rs::HISTOGRAM_BINS;
+
+const GRAPH_WIDTH: f32 = 256.0;
+const GRAPH_HEIGHT: f32 = 100.0;
+
+// luminance and rgb histogram of the final image in the bottom left corner
+// shadows are on the left, clipped highlights end up in the rightmost bin
+pub struct HistogramOverlay {
+    visible: bool,
+}
+
+impl HistogramOverlay {
+    pub fn new() -> Self {
+        HistogramOverlay { visible: false }
+    }
+
+    // the histogram is only computed on the gpu while the overlay is visible
+    pub fn toggle(&mut self, renderer: &mut VulkanRenderer) {
+        self.visible = !self.visible;
+        renderer.set_color_histogram(self.visible);
+    }
+
+    pub fn show(&self, ui: &egui::Context, renderer: &VulkanRenderer) {
+        if !self.visible {
+            return;
+        }
+        let Some(histogram) = renderer.color_histogram() else {
+            return;
+        };
+        egui::Area::new(egui::Id::new("histogram overlay"))
+            .anchor(egui::Align2::LEFT_BOTTOM, [8.0, -8.0])
+            .interactable(false)
+            .show(ui, |ui| {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    Self::graph(ui, histogram);
+                    let percent =
+                        |count: u32| count as f32 / histogram.pixel_count.max(1) as f32 * 100.0;
+                    ui.label(
+                        egui::RichText::new(format!(
+                            "black {:.1}%  clipped {:.1}%",
+                            percent(histogram.luminance[0]),
+                            percent(histogram.luminance[HISTOGRAM_BINS - 1])
+                        ))
+                        .monospace(),
+                    );
+                });
+            });
+    }
+
+    // luminance as grey bars, the channels as lines on top
+    fn graph(ui: &mut egui::Ui, histogram: &ColorHistogram) {
+        let (rect, _) =
+            ui.allocate_exact_size(egui::vec2(GRAPH_WIDTH, GRAPH_HEIGHT), egui::Sense::hover());
+        let painter = ui.painter_at(rect);
+        painter.rect_filled(rect, 0.0, egui::Color32::from_black_alpha(120));
+        // a single huge bin (e.g. a black sky) would flatten everything else => sqrt scale
+        let max_count = [
+            &histogram.luminance,
+            &histogram.red,
+            &histogram.green,
+            &histogram.blue,
+        ]
+        .iter()
+        .flat_map(|bins| bins.iter())
+        .copied()
+        .max()
+        .unwrap_or(0)
+        .max(1);
+        let height = |count: u32| (count as f32 / max_count as f32).sqrt() * rect.height();
+        let bin_width = rect.width() / HISTOGRAM_BINS as f32;
+        for (idx, count) in histogram.luminance.iter().enumerate() {
+            let x = rect.left() + idx as f32 * bin_width;
+            painter.rect_filled(
+                egui::Rect::from_min_max(
+                    egui::pos2(x, rect.bottom() - height(*count)),
+                    egui::pos2(x + bin_width, rect.bottom()),
+                ),
+                0.0,
+                egui::Color32::from_gray(160),
+            );
+        }
+        for (bins, color) in [
+            (&histogram.red, egui::Color32::RED),
+            (&histogram.green, egui::Color32::GREEN),
+            (&histogram.blue, egui::Color32::from_rgb(80, 120, 255)),
+        ] {
+            let points = bins
+                .iter()
+                .enumerate()
+                .map(|(idx, count)| {
+                    egui::pos2(
+                        rect.left() + (idx as f32 + 0.5) * bin_width,
+                        rect.bottom() - height(*count),
+                    )
+                })
+                .collect();
+            painter.add(egui::Shape::line(points, egui::Stroke::new(1.0, color)));
+        }
+    }
+}
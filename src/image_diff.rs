@@ -0,0 +1,212 @@
+use image::Rgb;
+use image::RgbImage;
+use image::RgbaImage;
+use std::path::Path;
+
+// delta e (cie76) below this is not visible to most people
+const JUST_NOTICEABLE_DIFFERENCE: f32 = 2.3;
+// the heatmap is fully red from this delta e on
+const HEATMAP_MAX_DELTA_E: f32 = 20.0;
+
+#[derive(Debug, Clone, Copy)]
+pub struct ImageDiffSettings {
+    // channel differences up to this are ignored by the per pixel metric (dithering, driver
+    // rounding, ...)
+    pub channel_tolerance: u8,
+    // compare fails if more than this fraction (0..1) of the pixels is visibly different
+    pub max_different_pixels: f32,
+}
+
+impl Default for ImageDiffSettings {
+    fn default() -> Self {
+        ImageDiffSettings {
+            channel_tolerance: 2,
+            max_different_pixels: 0.001,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ImageDiff {
+    pub width: u32,
+    pub height: u32,
+    // pixels with any channel (rgb) differing more than the tolerance
+    pub changed_pixels: u64,
+    pub max_channel_difference: u8,
+    // over all channels, in 0..255
+    pub mean_channel_difference: f32,
+    // pixels whose delta e is above the just noticeable difference
+    pub visible_pixels: u64,
+    pub mean_delta_e: f32,
+    pub max_delta_e: f32,
+    settings: ImageDiffSettings,
+    delta_e: Vec<f32>,
+    // grey version of the expected image, the heatmap is drawn on top of it
+    background: Vec<u8>,
+}
+
+impl ImageDiff {
+    pub fn pixel_count(&self) -> u64 {
+        self.width as u64 * self.height as u64
+    }
+
+    pub fn visible_fraction(&self) -> f32 {
+        self.visible_pixels as f32 / self.pixel_count().max(1) as f32
+    }
+
+    pub fn passed(&self) -> bool {
+        self.visible_fraction() <= self.settings.max_different_pixels
+    }
+
+    // dimmed expected image, visibly different pixels go from blue (small) to red (large)
+    pub fn heatmap(&self) -> RgbImage {
+        let mut heatmap = RgbImage::new(self.width, self.height);
+        for (idx, pixel) in heatmap.pixels_mut().enumerate() {
+            let delta_e = self.delta_e[idx];
+            *pixel = if delta_e > JUST_NOTICEABLE_DIFFERENCE {
+                heat_color(delta_e / HEATMAP_MAX_DELTA_E)
+            } else {
+                let grey = self.background[idx];
+                Rgb([grey, grey, grey])
+            };
+        }
+        heatmap
+    }
+
+    pub fn summary(&self) -> String {
+        format!(
+            "{}x{}: {} changed pixels (max channel difference {}, mean {:.3}), {} visibly \
+             different pixels ({:.4}%, mean delta e {:.3}, max {:.2}) => {}",
+            self.width,
+            self.height,
+            self.changed_pixels,
+            self.max_channel_difference,
+            self.mean_channel_difference,
+            self.visible_pixels,
+            self.visible_fraction() * 100.0,
+            self.mean_delta_e,
+            self.max_delta_e,
+            if self.passed() { "passed" } else { "FAILED" }
+        )
+    }
+}
+
+// alpha is ignored, screenshots are always opaque
+pub fn diff_images(
+    expected: &RgbaImage,
+    actual: &RgbaImage,
+    settings: ImageDiffSettings,
+) -> Result<ImageDiff, String> {
+    if expected.dimensions() != actual.dimensions() {
+        return Err(format!(
+            "Image sizes differ: {}x{} vs {}x{}",
+            expected.width(),
+            expected.height(),
+            actual.width(),
+            actual.height()
+        ));
+    }
+    let (width, height) = expected.dimensions();
+    let pixel_count = width as usize * height as usize;
+    let mut changed_pixels = 0;
+    let mut max_channel_difference = 0;
+    let mut channel_difference_sum = 0u64;
+    let mut visible_pixels = 0;
+    let mut delta_e_sum = 0.0f64;
+    let mut max_delta_e = 0.0f32;
+    let mut delta_e = Vec::with_capacity(pixel_count);
+    let mut background = Vec::with_capacity(pixel_count);
+    for (expected, actual) in expected.pixels().zip(actual.pixels()) {
+        let mut pixel_max = 0;
+        for channel in 0..3 {
+            let difference = expected[channel].abs_diff(actual[channel]);
+            channel_difference_sum += difference as u64;
+            pixel_max = pixel_max.max(difference);
+        }
+        max_channel_difference = max_channel_difference.max(pixel_max);
+        if pixel_max > settings.channel_tolerance {
+            changed_pixels += 1;
+        }
+        let expected_lab = srgb_to_lab(expected.0);
+        let pixel_delta_e = lab_distance(expected_lab, srgb_to_lab(actual.0));
+        if pixel_delta_e > JUST_NOTICEABLE_DIFFERENCE {
+            visible_pixels += 1;
+        }
+        delta_e_sum += pixel_delta_e as f64;
+        max_delta_e = max_delta_e.max(pixel_delta_e);
+        delta_e.push(pixel_delta_e);
+        // lightness is 0..100
+        background.push((expected_lab[0] * 0.01 * 80.0) as u8);
+    }
+    Ok(ImageDiff {
+        width,
+        height,
+        changed_pixels,
+        max_channel_difference,
+        mean_channel_difference: channel_difference_sum as f32 / (pixel_count.max(1) * 3) as f32,
+        visible_pixels,
+        mean_delta_e: (delta_e_sum / pixel_count.max(1) as f64) as f32,
+        max_delta_e,
+        settings,
+        delta_e,
+        background,
+    })
+}
+
+pub fn diff_files(
+    expected: &Path,
+    actual: &Path,
+    settings: ImageDiffSettings,
+) -> Result<ImageDiff, String> {
+    let open = |path: &Path| {
+        image::open(path)
+            .map(|image| image.into_rgba8())
+            .map_err(|e| format!("Could not open {:?}: {}", path, e))
+    };
+    diff_images(&open(expected)?, &open(actual)?, settings)
+}
+
+fn lab_distance(a: [f32; 3], b: [f32; 3]) -> f32 {
+    ((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2)).sqrt()
+}
+
+// screenshots are srgb encoded => linearize, go to xyz (d65) and from there to cie lab
+fn srgb_to_lab(pixel: [u8; 4]) -> [f32; 3] {
+    let linear = |value: u8| {
+        let value = value as f32 / 255.0;
+        if value <= 0.04045 {
+            value / 12.92
+        } else {
+            ((value + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    let (r, g, b) = (linear(pixel[0]), linear(pixel[1]), linear(pixel[2]));
+    let x = (0.4124 * r + 0.3576 * g + 0.1805 * b) / 0.95047;
+    let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+    let z = (0.0193 * r + 0.1192 * g + 0.9505 * b) / 1.08883;
+    let f = |t: f32| {
+        if t > 0.008856 {
+            t.cbrt()
+        } else {
+            7.787 * t + 16.0 / 116.0
+        }
+    };
+    let (fx, fy, fz) = (f(x), f(y), f(z));
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
+
+// t in 0..1 => blue, green, yellow, red
+fn heat_color(t: f32) -> Rgb<u8> {
+    let t = t.clamp(0.0, 1.0);
+    let (r, g, b) = if t < 0.33 {
+        let s = t / 0.33;
+        (0.0, s, 1.0 - s)
+    } else if t < 0.66 {
+        let s = (t - 0.33) / 0.33;
+        (s, 1.0, 0.0)
+    } else {
+        let s = (t - 0.66) / 0.34;
+        (1.0, 1.0 - s, 0.0)
+    };
+    Rgb([(r * 255.0) as u8, (g * 255.0) as u8, (b * 255.0) as u8])
+}
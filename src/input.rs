@@ -0,0 +1,171 @@
+use crate::clipboard::Clipboard;
+use winit::event::ElementState;
+use winit::event::Ime;
+use winit::event::KeyEvent;
+use winit::event::WindowEvent;
+use winit::keyboard::Key;
+use winit::keyboard::ModifiersState;
+use winit::keyboard::NamedKey;
+use winit::window::Window;
+
+mod actions;
+mod state;
+
+pub use actions::Action;
+pub use actions::ActionMap;
+pub use actions::Binding;
+pub use state::InputState;
+
+// collects typed text while text entry mode is active (console, text fields, chat, ...)
+// composed text (dead keys, IME) is already resolved by winit => we only append what we get
+#[derive(Debug, Default)]
+pub struct TextInput {
+    enabled: bool,
+    // while an IME is active, text arrives as Ime::Commit instead of KeyEvent::text
+    ime_active: bool,
+    text: String,
+    preedit: String,
+    submitted: Vec<String>,
+    modifiers: ModifiersState,
+}
+
+impl TextInput {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn set_enabled(&mut self, window: &Window, enabled: bool) {
+        log::debug!("Text entry mode: {}", enabled);
+        self.enabled = enabled;
+        self.preedit.clear();
+        window.set_ime_allowed(enabled);
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    // text that is currently being composed by the IME and not yet committed
+    pub fn preedit(&self) -> &str {
+        &self.preedit
+    }
+
+    // lines that were submitted with enter since the last call
+    pub fn take_submitted(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.submitted)
+    }
+
+    pub fn clear(&mut self) {
+        self.text.clear();
+        self.preedit.clear();
+    }
+
+    // returns false if the event was not consumed and should be handled by the rest of the app
+    pub fn handle_event(
+        &mut self,
+        window: &Window,
+        clipboard: &mut Clipboard,
+        event: &WindowEvent,
+    ) -> bool {
+        match event {
+            WindowEvent::ModifiersChanged(modifiers) => {
+                self.modifiers = modifiers.state();
+                false
+            }
+            WindowEvent::KeyboardInput { event, .. } => {
+                if self.enabled {
+                    self.handle_key(window, clipboard, event);
+                    true
+                } else {
+                    // enter opens text entry, similar to most chat boxes
+                    if event.state == ElementState::Released
+                        && event.logical_key == Key::Named(NamedKey::Enter)
+                    {
+                        self.set_enabled(window, true);
+                        true
+                    } else {
+                        false
+                    }
+                }
+            }
+            WindowEvent::Ime(ime) => {
+                self.handle_ime(ime);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn handle_key(&mut self, window: &Window, clipboard: &mut Clipboard, event: &KeyEvent) {
+        // leave text entry on release, otherwise the release would reach the game bindings
+        if event.state == ElementState::Released {
+            if event.logical_key == Key::Named(NamedKey::Escape) {
+                self.set_enabled(window, false);
+            }
+            return;
+        }
+        if self.modifiers.control_key() {
+            self.handle_shortcut(clipboard, event);
+            return;
+        }
+        match &event.logical_key {
+            Key::Named(NamedKey::Enter) => {
+                self.submitted.push(std::mem::take(&mut self.text));
+                self.preedit.clear();
+            }
+            Key::Named(NamedKey::Backspace) => {
+                self.text.pop();
+            }
+            _ => {
+                if self.ime_active {
+                    return;
+                }
+                if let Some(text) = &event.text {
+                    // control characters (tab, escape, ...) are not part of the text
+                    self.text.extend(text.chars().filter(|c| !c.is_control()));
+                }
+            }
+        }
+    }
+
+    // there is no selection => copy/cut always operate on the whole text
+    fn handle_shortcut(&mut self, clipboard: &mut Clipboard, event: &KeyEvent) {
+        let Key::Character(c) = &event.logical_key else {
+            return;
+        };
+        match c.to_lowercase().as_str() {
+            "c" => {
+                clipboard.set_text(&self.text);
+            }
+            "x" if clipboard.set_text(&self.text) => self.text.clear(),
+            "v" => {
+                if let Some(text) = clipboard.get_text() {
+                    // single line input => newlines would submit in the middle of the paste
+                    self.text.extend(text.chars().filter(|c| !c.is_control()));
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn handle_ime(&mut self, ime: &Ime) {
+        match ime {
+            Ime::Enabled => self.ime_active = true,
+            Ime::Disabled => {
+                self.ime_active = false;
+                self.preedit.clear();
+            }
+            Ime::Preedit(text, _cursor) => self.preedit = text.clone(),
+            Ime::Commit(text) => {
+                if self.enabled {
+                    self.preedit.clear();
+                    self.text.push_str(text);
+                }
+            }
+        }
+    }
+}
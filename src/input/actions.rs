@@ -0,0 +1,148 @@
+use super::state::InputState;
+use std::collections::HashMap;
+use winit::event::MouseButton;
+use winit::keyboard::KeyCode;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    Exit,
+    MoveForward,
+    MoveBackward,
+    MoveLeft,
+    MoveRight,
+    MoveUp,
+    MoveDown,
+    MoveFast,
+    // rotate the camera with the mouse while held
+    Look,
+    ToggleCameraMode,
+    ToggleProjection,
+    ToggleUpscaling,
+    ToggleDithering,
+    ToggleVsync,
+    CopyLastError,
+    NextCursor,
+    NextMonitor,
+    ToggleFrameCapture,
+    SaveFrameCapture,
+    ToggleMinimap,
+    ToggleFlashlight,
+    // handled by the engine, not the game
+    ToggleStats,
+    ToggleHistogram,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Binding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+}
+
+impl Binding {
+    fn is_held(&self, input: &InputState) -> bool {
+        match self {
+            Binding::Key(key) => input.is_key_held(*key),
+            Binding::Mouse(button) => input.is_button_held(*button),
+        }
+    }
+
+    fn was_pressed(&self, input: &InputState) -> bool {
+        match self {
+            Binding::Key(key) => input.was_key_pressed(*key),
+            Binding::Mouse(button) => input.was_button_pressed(*button),
+        }
+    }
+
+    fn was_released(&self, input: &InputState) -> bool {
+        match self {
+            Binding::Key(key) => input.was_key_released(*key),
+            Binding::Mouse(button) => input.was_button_released(*button),
+        }
+    }
+}
+
+// maps actions to any number of keys/buttons => game code doesnt care about the physical input
+#[derive(Debug, Clone)]
+pub struct ActionMap {
+    bindings: HashMap<Action, Vec<Binding>>,
+}
+
+impl Default for ActionMap {
+    fn default() -> Self {
+        let mut actions = ActionMap {
+            bindings: HashMap::new(),
+        };
+        actions.bind(Action::Exit, Binding::Key(KeyCode::Escape));
+        actions.bind(Action::MoveForward, Binding::Key(KeyCode::KeyW));
+        actions.bind(Action::MoveBackward, Binding::Key(KeyCode::KeyS));
+        actions.bind(Action::MoveLeft, Binding::Key(KeyCode::KeyA));
+        actions.bind(Action::MoveRight, Binding::Key(KeyCode::KeyD));
+        actions.bind(Action::MoveUp, Binding::Key(KeyCode::KeyE));
+        actions.bind(Action::MoveDown, Binding::Key(KeyCode::KeyQ));
+        actions.bind(Action::MoveFast, Binding::Key(KeyCode::ShiftLeft));
+        actions.bind(Action::Look, Binding::Mouse(MouseButton::Right));
+        actions.bind(Action::ToggleCameraMode, Binding::Key(KeyCode::KeyC));
+        actions.bind(Action::ToggleProjection, Binding::Key(KeyCode::KeyP));
+        actions.bind(Action::ToggleUpscaling, Binding::Key(KeyCode::F3));
+        actions.bind(Action::ToggleDithering, Binding::Key(KeyCode::F4));
+        actions.bind(Action::ToggleVsync, Binding::Key(KeyCode::F5));
+        actions.bind(Action::CopyLastError, Binding::Key(KeyCode::F6));
+        actions.bind(Action::NextCursor, Binding::Key(KeyCode::F7));
+        actions.bind(Action::NextMonitor, Binding::Key(KeyCode::F8));
+        actions.bind(Action::ToggleFrameCapture, Binding::Key(KeyCode::F9));
+        actions.bind(Action::SaveFrameCapture, Binding::Key(KeyCode::F10));
+        actions.bind(Action::ToggleMinimap, Binding::Key(KeyCode::KeyM));
+        actions.bind(Action::ToggleFlashlight, Binding::Key(KeyCode::KeyF));
+        actions.bind(Action::ToggleStats, Binding::Key(KeyCode::F1));
+        actions.bind(Action::ToggleHistogram, Binding::Key(KeyCode::F2));
+        actions
+    }
+}
+
+impl ActionMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn bind(&mut self, action: Action, binding: Binding) {
+        let bindings = self.bindings.entry(action).or_default();
+        if !bindings.contains(&binding) {
+            bindings.push(binding);
+        }
+    }
+
+    pub fn unbind(&mut self, action: Action) {
+        self.bindings.remove(&action);
+    }
+
+    pub fn bindings(&self, action: Action) -> &[Binding] {
+        self.bindings
+            .get(&action)
+            .map(|bindings| bindings.as_slice())
+            .unwrap_or_default()
+    }
+
+    pub fn is_held(&self, input: &InputState, action: Action) -> bool {
+        self.bindings(action)
+            .iter()
+            .any(|binding| binding.is_held(input))
+    }
+
+    pub fn was_pressed(&self, input: &InputState, action: Action) -> bool {
+        self.bindings(action)
+            .iter()
+            .any(|binding| binding.was_pressed(input))
+    }
+
+    // toggles trigger on release => text input can consume the press without triggering them
+    pub fn was_released(&self, input: &InputState, action: Action) -> bool {
+        self.bindings(action)
+            .iter()
+            .any(|binding| binding.was_released(input))
+    }
+
+    // -1, 0 or 1 depending on which of the two actions is held
+    pub fn axis(&self, input: &InputState, positive: Action, negative: Action) -> f32 {
+        self.is_held(input, positive) as i32 as f32 - self.is_held(input, negative) as i32 as f32
+    }
+}
@@ -0,0 +1,147 @@
+use nalgebra_glm as glm;
+use std::collections::HashSet;
+use winit::event::DeviceEvent;
+use winit::event::ElementState;
+use winit::event::MouseButton;
+use winit::event::MouseScrollDelta;
+use winit::event::WindowEvent;
+use winit::keyboard::KeyCode;
+use winit::keyboard::PhysicalKey;
+
+// keys and buttons that are held plus everything that happened since the last end_frame
+// events are collected from the event loop, game code queries the state once per frame
+#[derive(Debug, Default)]
+pub struct InputState {
+    keys_held: HashSet<KeyCode>,
+    keys_pressed: HashSet<KeyCode>,
+    keys_released: HashSet<KeyCode>,
+    buttons_held: HashSet<MouseButton>,
+    buttons_pressed: HashSet<MouseButton>,
+    buttons_released: HashSet<MouseButton>,
+    cursor_position: Option<glm::Vec2>,
+    // raw device motion => keeps working when the cursor hits the edge of the screen
+    mouse_delta: glm::Vec2,
+    // in lines, positive is scrolling up/away from the user
+    scroll_delta: f32,
+    focused: bool,
+}
+
+impl InputState {
+    pub fn new() -> Self {
+        InputState {
+            focused: true,
+            ..Default::default()
+        }
+    }
+
+    pub fn handle_event(&mut self, event: &WindowEvent) {
+        match event {
+            WindowEvent::KeyboardInput { event, .. } => {
+                let PhysicalKey::Code(key) = event.physical_key else {
+                    return;
+                };
+                match event.state {
+                    // key repeat would count as a new press otherwise
+                    ElementState::Pressed if !event.repeat => {
+                        self.keys_held.insert(key);
+                        self.keys_pressed.insert(key);
+                    }
+                    ElementState::Pressed => (),
+                    ElementState::Released => {
+                        self.keys_held.remove(&key);
+                        self.keys_released.insert(key);
+                    }
+                }
+            }
+            WindowEvent::MouseInput { state, button, .. } => match state {
+                ElementState::Pressed => {
+                    self.buttons_held.insert(*button);
+                    self.buttons_pressed.insert(*button);
+                }
+                ElementState::Released => {
+                    self.buttons_held.remove(button);
+                    self.buttons_released.insert(*button);
+                }
+            },
+            WindowEvent::CursorMoved { position, .. } => {
+                self.cursor_position = Some(glm::vec2(position.x as f32, position.y as f32));
+            }
+            WindowEvent::CursorLeft { .. } => self.cursor_position = None,
+            WindowEvent::MouseWheel { delta, .. } => {
+                self.scroll_delta += match delta {
+                    MouseScrollDelta::LineDelta(_, y) => *y,
+                    // roughly one line per 20 pixels
+                    MouseScrollDelta::PixelDelta(position) => position.y as f32 / 20.0,
+                };
+            }
+            WindowEvent::Focused(focused) => {
+                self.focused = *focused;
+                // releases that happen while the window is unfocused are never reported
+                if !focused {
+                    self.release_all();
+                }
+            }
+            _ => (),
+        }
+    }
+
+    pub fn handle_device_event(&mut self, event: &DeviceEvent) {
+        // device events are reported even if another window has the focus
+        if let (true, DeviceEvent::MouseMotion { delta }) = (self.focused, event) {
+            self.mouse_delta += glm::vec2(delta.0 as f32, delta.1 as f32);
+        }
+    }
+
+    // forget all held keys/buttons without reporting them as released, e.g. when another
+    // system (text input) takes over the keyboard
+    pub fn release_all(&mut self) {
+        self.keys_held.clear();
+        self.buttons_held.clear();
+    }
+
+    // has to be called after the game code queried the input of the current frame
+    pub fn end_frame(&mut self) {
+        self.keys_pressed.clear();
+        self.keys_released.clear();
+        self.buttons_pressed.clear();
+        self.buttons_released.clear();
+        self.mouse_delta = glm::Vec2::zeros();
+        self.scroll_delta = 0.0;
+    }
+
+    pub fn is_key_held(&self, key: KeyCode) -> bool {
+        self.keys_held.contains(&key)
+    }
+
+    pub fn was_key_pressed(&self, key: KeyCode) -> bool {
+        self.keys_pressed.contains(&key)
+    }
+
+    pub fn was_key_released(&self, key: KeyCode) -> bool {
+        self.keys_released.contains(&key)
+    }
+
+    pub fn is_button_held(&self, button: MouseButton) -> bool {
+        self.buttons_held.contains(&button)
+    }
+
+    pub fn was_button_pressed(&self, button: MouseButton) -> bool {
+        self.buttons_pressed.contains(&button)
+    }
+
+    pub fn was_button_released(&self, button: MouseButton) -> bool {
+        self.buttons_released.contains(&button)
+    }
+
+    pub fn cursor_position(&self) -> Option<glm::Vec2> {
+        self.cursor_position
+    }
+
+    pub fn mouse_delta(&self) -> glm::Vec2 {
+        self.mouse_delta
+    }
+
+    pub fn scroll_delta(&self) -> f32 {
+        self.scroll_delta
+    }
+}
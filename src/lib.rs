@@ -1,4 +1,75 @@
+pub mod camera;
+pub mod clipboard;
+pub mod display;
+mod engine;
+mod frame_capture;
+mod histogram_overlay;
+pub mod image_diff;
+pub mod input;
+mod minimap;
+pub mod paths;
+pub mod prelude;
+pub mod profiler;
+mod stats_overlay;
+mod stress_scene;
+pub mod telemetry;
+pub mod tuning;
 mod vulkan_renderer;
+// raw vulkan wrappers, their api follows ash and changes with the renderer
+#[cfg(feature = "low_level")]
+pub mod vulkan_rs;
+#[cfg(not(feature = "low_level"))]
 mod vulkan_rs;
+pub mod window_icons;
 
+#[cfg(feature = "low_level")]
+pub use ash;
+// the ui callback gets an egui::Context => games use the same egui version as the engine
+pub use egui;
+pub use engine::App;
+pub use engine::Context;
+pub use engine::Engine;
+pub use engine::EngineBuilder;
+pub use engine::FramePacing;
+pub use frame_capture::FrameCapture;
+pub use frame_capture::FrameCaptureSettings;
+pub use minimap::Minimap;
+pub use minimap::MinimapMarker;
+pub use minimap::MinimapSettings;
+pub use stress_scene::StressScene;
+pub use stress_scene::StressSceneSettings;
+pub use vulkan_renderer::enumerate_adapters;
+pub use vulkan_renderer::MaterialHandle;
+pub use vulkan_renderer::MeshHandle;
+pub use vulkan_renderer::RendererConfig;
+pub use vulkan_renderer::Transform;
 pub use vulkan_renderer::VulkanRenderer;
+pub use vulkan_rs::Aabb;
+pub use vulkan_rs::AdapterInfo;
+pub use vulkan_rs::AdapterType;
+pub use vulkan_rs::Antialiasing;
+pub use vulkan_rs::AssetError;
+pub use vulkan_rs::Cloth;
+pub use vulkan_rs::ClothCollider;
+pub use vulkan_rs::ClothSettings;
+pub use vulkan_rs::ColorHistogram;
+pub use vulkan_rs::DistortionMode;
+pub use vulkan_rs::DistortionSettings;
+pub use vulkan_rs::GpuPreference;
+pub use vulkan_rs::GraphEstimate;
+pub use vulkan_rs::Handle;
+pub use vulkan_rs::Light;
+pub use vulkan_rs::LoadState;
+pub use vulkan_rs::Model;
+pub use vulkan_rs::OutputColorSpace;
+pub use vulkan_rs::PassEstimate;
+pub use vulkan_rs::PassTiming;
+pub use vulkan_rs::PostProcessSettings;
+pub use vulkan_rs::PresentModePreference;
+pub use vulkan_rs::Primitive;
+pub use vulkan_rs::RayTracedAoSettings;
+pub use vulkan_rs::RenderPath;
+pub use vulkan_rs::Texture;
+pub use vulkan_rs::Tonemapper;
+pub use vulkan_rs::Transparency;
+pub use vulkan_rs::VulkanError;
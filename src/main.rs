@@ -1,139 +1,960 @@
+use game_engine::camera::Camera;
+use game_engine::camera::CameraController;
+use game_engine::clipboard::Clipboard;
+use game_engine::display;
+use game_engine::egui;
+use game_engine::input::Action;
+use game_engine::input::TextInput;
+use game_engine::paths;
+use game_engine::profiler;
+use game_engine::profiler::StageTiming;
+use game_engine::telemetry;
+use game_engine::telemetry::SessionStats;
+use game_engine::tuning::Tunables;
+use game_engine::tuning::TuningServer;
+use game_engine::window_icons;
+use game_engine::window_icons::CursorSet;
+use game_engine::Antialiasing;
+use game_engine::App;
+use game_engine::ClothCollider;
+use game_engine::ClothSettings;
+use game_engine::Context;
+use game_engine::EngineBuilder;
+use game_engine::FrameCapture;
+use game_engine::FrameCaptureSettings;
+use game_engine::FramePacing;
+use game_engine::GpuPreference;
+use game_engine::Handle;
+use game_engine::Light;
+use game_engine::LoadState;
+use game_engine::MinimapSettings;
+use game_engine::Model;
+use game_engine::PassTiming;
+use game_engine::PostProcessSettings;
+use game_engine::PresentModePreference;
+use game_engine::RenderPath;
+use game_engine::RendererConfig;
+use game_engine::StressScene;
+use game_engine::StressSceneSettings;
+use game_engine::Texture;
+use game_engine::Tonemapper;
+use game_engine::Transparency;
 use game_engine::VulkanRenderer;
+use nalgebra_glm as glm;
+use std::path::Path;
+use std::path::PathBuf;
 use std::sync::Arc;
-use winit::application::ApplicationHandler;
-use winit::event::ElementState;
-use winit::event::{KeyEvent, WindowEvent};
-use winit::event_loop::{ActiveEventLoop, ControlFlow, EventLoop};
-use winit::keyboard::KeyCode;
-use winit::keyboard::PhysicalKey;
-use winit::window::{Window, WindowId};
-
-struct WindowSettings {
-    title: String,
-    width: u32,
-    height: u32,
-}
-
-impl WindowSettings {
-    fn new(title: &str, width: u32, height: u32) -> Self {
-        WindowSettings {
-            title: title.to_string(),
-            width,
-            height,
-        }
-    }
-}
-
-struct GameEngine {
-    window: Option<Arc<Window>>,
-    window_settings: WindowSettings,
-    last_frame: std::time::Instant,
-    renderer: Option<VulkanRenderer>,
-}
-
-impl GameEngine {
-    fn new(window_settings: WindowSettings) -> GameEngine {
-        GameEngine {
-            window: None,
-            window_settings,
-            last_frame: std::time::Instant::now(),
-            renderer: None,
-        }
-    }
-
-    fn init_window(&mut self, event_loop: &ActiveEventLoop) -> Arc<Window> {
-        let window = event_loop
-            .create_window(
-                Window::default_attributes()
-                    .with_title(self.window_settings.title.clone())
-                    .with_inner_size(winit::dpi::LogicalSize::new(
-                        self.window_settings.width,
-                        self.window_settings.height,
-                    )),
-            )
-            .expect("Window creation failed");
-        let window = Arc::new(window);
-        log::info!("succesfully created window");
-        window
-    }
-}
-
-impl ApplicationHandler for GameEngine {
-    fn resumed(&mut self, event_loop: &ActiveEventLoop) {
-        log::info!("Setting up window and renderer");
-        let window = self.init_window(event_loop);
-
-        self.renderer = Some(VulkanRenderer::new(window.clone()));
-        self.window = Some(window);
-    }
-
-    fn window_event(&mut self, event_loop: &ActiveEventLoop, _id: WindowId, event: WindowEvent) {
-        if let (Some(renderer), Some(window)) = (self.renderer.as_mut(), self.window.as_ref()) {
-            let mut exit = false;
-            match event {
-                WindowEvent::CloseRequested => {
-                    log::info!("The close button was pressed; stopping");
-                    exit = true;
-                }
-                WindowEvent::RedrawRequested => {
-                    self.last_frame = std::time::Instant::now();
-                    window.pre_present_notify();
-                    renderer.draw();
-                }
-                WindowEvent::Resized(physical_size) => {
-                    let logical_size = physical_size.to_logical(window.scale_factor());
-                    renderer.resize_swapchain(logical_size);
-                }
-                WindowEvent::KeyboardInput {
-                    event:
-                        KeyEvent {
-                            physical_key: key,
-                            state: ElementState::Released,
-                            ..
-                        },
-                    ..
-                } => match key {
-                    PhysicalKey::Code(KeyCode::Escape) => {
-                        log::info!("Escape was pressed; Closing window");
-                        exit = true;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+use winit::event::WindowEvent;
+use winit::window::CursorIcon;
+
+const USAGE: &str = "Usage: game_engine [OPTIONS]
+
+Options:
+  --scene <PATH>        glTF file to load (default: assets/basicmesh.glb in the asset root)
+  --gpu <NAME|ID>       prefer the GPU whose name contains NAME (case-insensitive)
+                        or with the PCI ID 0xVENDOR[:0xDEVICE], e.g. 0x10de for NVIDIA
+  --prefer-display-gpu  prefer the GPU that can present to the window without a copy
+  --list-gpus           print the GPUs with Vulkan support and exit
+  --fullscreen          start in borderless fullscreen
+  --exclusive-fullscreen
+                        start in exclusive fullscreen if supported (Windows), borderless otherwise
+  --windowed            start windowed (default)
+  --monitor <INDEX>     move the window to the monitor with the given index
+  --frame-limit         limit the frame rate to the refresh rate of the current monitor
+  --max-fps <FPS>       limit the frame rate to FPS frames per second
+  --update-rate <HZ>    fixed game updates per second (default: 60)
+  --validation          enable the Vulkan validation layers (default in debug builds)
+  --no-validation       disable the Vulkan validation layers
+  --present-mode <MODE> immediate, fifo, mailbox or fifo-relaxed (default: mailbox)
+  --no-dither           disable dithering of the final image
+  --no-upscaling        use a bilinear blit instead of the sharp upscaler
+  --fxaa                smooth the edges of the final image with FXAA
+  --taa                 temporal anti-aliasing, accumulates jittered frames
+  --deferred            render opaque surfaces through a G-buffer instead of forward shading
+  --oit                 weighted blended order independent transparency instead of sorting
+  --ray-tracing         ray traced ambient occlusion and, with --deferred, ray traced sun shadows
+                        if the GPU supports them
+  --frames-in-flight <N>
+                        frames the cpu can record ahead of the gpu, 2 or 3 (default: 2)
+  --hot-reload          reload changed assets and recompile changed shaders while running
+                        (shaders need glslc)
+  --shader-dir <PATH>   load .spv files from PATH instead of the embedded shaders
+  --color-validation    warn about textures and swapchains in the wrong color space (sRGB/linear)
+  --hdr                 use an scRGB or HDR10 swapchain if the display supports it
+  --gpu-timeout <SECONDS>
+                        report a hang if the GPU takes longer for a frame (default: 2)
+  --safe-mode           start with the settings that are used when the renderer fails to start
+  --tuning-server <ADDR>
+                        accept live tuning commands on ADDR, e.g. 127.0.0.1:7878
+  --benchmark <FRAMES>  render FRAMES frames, print frame time statistics and exit
+  --stress <COUNT>      add a generated scene with COUNT cubes and spheres, random materials and lights
+  --stress-static       dont animate the generated scene
+  --stats-file <PATH>   append a summary of the session to PATH
+                        (default: stats/sessions.log in the data directory)
+  --print-stats         print the session summary on exit
+  --show-stats          show the fps/memory overlay at startup (toggle with F1)
+  -h, --help            print this help
+
+Environment:
+  LEX_ENGINE_ASSET_ROOT directory with assets/ and shaders/ (default: found next to the executable)
+  LEX_ENGINE_DATA_DIR   where stats and captures are written (default: per user app data)
+  LEX_ENGINE_CACHE_DIR  where regenerated data is cached (default: per user cache)
+  LEX_ENGINE_GPU        like --gpu, overrides it";
+
+struct CommandLineArgs {
+    renderer_config: RendererConfig,
+    fullscreen: bool,
+    monitor: Option<usize>,
+    frame_pacing: FramePacing,
+    update_rate: f32,
+    benchmark_frames: Option<usize>,
+    // None => no stress scene
+    stress_scene: Option<StressSceneSettings>,
+    tuning_address: Option<String>,
+    stats_file: PathBuf,
+    print_stats: bool,
+    show_stats: bool,
+}
+
+impl CommandLineArgs {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut parsed = CommandLineArgs {
+            renderer_config: RendererConfig::default(),
+            fullscreen: false,
+            monitor: None,
+            frame_pacing: FramePacing::Unlimited,
+            update_rate: 60.0,
+            benchmark_frames: None,
+            stress_scene: None,
+            tuning_address: None,
+            stats_file: paths::data_dir().join("stats/sessions.log"),
+            print_stats: false,
+            show_stats: false,
+        };
+        let mut safe_mode = false;
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--scene" => {
+                    let path = args.next().ok_or("--scene expects a path")?;
+                    parsed.renderer_config.scene_path = PathBuf::from(path);
+                }
+                "--gpu" => {
+                    let gpu = args.next().ok_or("--gpu expects a device name or id")?;
+                    parsed.renderer_config.gpu = GpuPreference {
+                        prefer_display_adapter: parsed.renderer_config.gpu.prefer_display_adapter,
+                        ..GpuPreference::parse(&gpu)
+                    };
+                }
+                "--prefer-display-gpu" => {
+                    parsed.renderer_config.gpu.prefer_display_adapter = true;
+                }
+                "--list-gpus" => {
+                    print_adapters();
+                    std::process::exit(0);
+                }
+                "--fullscreen" => parsed.fullscreen = true,
+                "--exclusive-fullscreen" => {
+                    parsed.fullscreen = true;
+                    parsed.renderer_config.exclusive_fullscreen = true;
+                }
+                "--windowed" => parsed.fullscreen = false,
+                "--monitor" => {
+                    let monitor = args
+                        .next()
+                        .ok_or("--monitor expects a monitor index")?
+                        .parse::<usize>()
+                        .map_err(|e| format!("Invalid monitor index for --monitor: {}", e))?;
+                    parsed.monitor = Some(monitor);
+                }
+                "--frame-limit" => parsed.frame_pacing = FramePacing::RefreshRate,
+                "--max-fps" => {
+                    let fps = args
+                        .next()
+                        .ok_or("--max-fps expects a frame rate")?
+                        .parse::<f64>()
+                        .map_err(|e| format!("Invalid frame rate for --max-fps: {}", e))?;
+                    if fps <= 0.0 {
+                        return Err("--max-fps expects a positive frame rate".to_string());
+                    }
+                    parsed.frame_pacing = FramePacing::MaxFps(fps);
+                }
+                "--update-rate" => {
+                    let rate = args
+                        .next()
+                        .ok_or("--update-rate expects a rate")?
+                        .parse::<f32>()
+                        .map_err(|e| format!("Invalid rate for --update-rate: {}", e))?;
+                    if rate < 1.0 {
+                        return Err(
+                            "--update-rate expects at least one update per second".to_string()
+                        );
+                    }
+                    parsed.update_rate = rate;
+                }
+                "--validation" => parsed.renderer_config.enable_validation = true,
+                "--no-validation" => parsed.renderer_config.enable_validation = false,
+                "--present-mode" => {
+                    parsed.renderer_config.present_mode = args
+                        .next()
+                        .ok_or("--present-mode expects a mode")?
+                        .parse::<PresentModePreference>()?;
+                }
+                "--no-dither" => parsed.renderer_config.dithering = false,
+                "--no-upscaling" => parsed.renderer_config.upscaling = false,
+                "--frames-in-flight" => {
+                    let frames = args
+                        .next()
+                        .ok_or("--frames-in-flight expects a frame count")?
+                        .parse::<usize>()
+                        .map_err(|e| {
+                            format!("Invalid frame count for --frames-in-flight: {}", e)
+                        })?;
+                    if !(2..=3).contains(&frames) {
+                        return Err("--frames-in-flight expects 2 or 3".to_string());
+                    }
+                    parsed.renderer_config.frames_in_flight = frames;
+                }
+                "--hot-reload" => {
+                    parsed.renderer_config.shader_hot_reload = true;
+                    parsed.renderer_config.asset_hot_reload = true;
+                }
+                "--color-validation" => parsed.renderer_config.color_validation = true,
+                "--hdr" => parsed.renderer_config.hdr_output = true,
+                "--fxaa" => parsed.renderer_config.antialiasing = Antialiasing::Fxaa,
+                "--taa" => parsed.renderer_config.antialiasing = Antialiasing::Taa,
+                "--deferred" => parsed.renderer_config.render_path = RenderPath::Deferred,
+                "--oit" => parsed.renderer_config.transparency = Transparency::WeightedBlended,
+                "--ray-tracing" => parsed.renderer_config.ray_tracing = true,
+                "--gpu-timeout" => {
+                    let seconds = args
+                        .next()
+                        .ok_or("--gpu-timeout expects a duration in seconds")?
+                        .parse::<f32>()
+                        .map_err(|e| format!("Invalid duration for --gpu-timeout: {}", e))?;
+                    if seconds <= 0.0 {
+                        return Err("--gpu-timeout expects a positive duration".to_string());
                     }
-                    PhysicalKey::Code(KeyCode::KeyW) => {
-                        log::info!("Pressing W")
+                    parsed.renderer_config.gpu_timeout = Duration::from_secs_f32(seconds);
+                }
+                "--shader-dir" => {
+                    let path = args.next().ok_or("--shader-dir expects a path")?;
+                    parsed.renderer_config.shader_dir = Some(PathBuf::from(path));
+                }
+                "--safe-mode" => safe_mode = true,
+                "--tuning-server" => {
+                    let address = args.next().ok_or("--tuning-server expects an address")?;
+                    parsed.tuning_address = Some(address);
+                }
+                "--benchmark" => {
+                    let frames = args
+                        .next()
+                        .ok_or("--benchmark expects a frame count")?
+                        .parse::<usize>()
+                        .map_err(|e| format!("Invalid frame count for --benchmark: {}", e))?;
+                    if frames == 0 {
+                        return Err("--benchmark expects at least one frame".to_string());
                     }
-                    _ => log::debug!("Something else was pressed"),
-                },
-                _ => (),
+                    parsed.benchmark_frames = Some(frames);
+                }
+                "--stress" => {
+                    let count = args
+                        .next()
+                        .ok_or("--stress expects an instance count")?
+                        .parse::<usize>()
+                        .map_err(|e| format!("Invalid instance count for --stress: {}", e))?;
+                    let settings = parsed.stress_scene.get_or_insert_with(Default::default);
+                    settings.instance_count = count;
+                }
+                "--stress-static" => {
+                    parsed
+                        .stress_scene
+                        .get_or_insert_with(Default::default)
+                        .animated = false;
+                }
+                "--stats-file" => {
+                    let path = args.next().ok_or("--stats-file expects a path")?;
+                    parsed.stats_file = PathBuf::from(path);
+                }
+                "--print-stats" => parsed.print_stats = true,
+                "--show-stats" => parsed.show_stats = true,
+                "-h" | "--help" => {
+                    println!("{}", USAGE);
+                    std::process::exit(0);
+                }
+                _ => return Err(format!("Unknown argument: {}", arg)),
             }
-            if exit {
-                event_loop.exit();
-                renderer.wait_idle();
+        }
+        // overrides the other renderer options no matter where it is
+        if safe_mode {
+            parsed.renderer_config = parsed.renderer_config.safe_mode();
+        }
+        Ok(parsed)
+    }
+}
+
+struct Benchmark {
+    frame_count: usize,
+    frame_times: Vec<Duration>,
+    // summed time and number of samples per profiled pass/stage
+    gpu_pass_times: Vec<(&'static str, Duration, u32)>,
+    cpu_stage_times: Vec<(&'static str, Duration, u32)>,
+}
+
+// the values --gpu accepts for each of them
+fn print_adapters() {
+    match game_engine::enumerate_adapters() {
+        Ok(adapters) => {
+            for adapter in adapters {
+                println!(
+                    "{} ({:?}, {} MiB) --gpu {:#06x}:{:#06x}",
+                    adapter.name,
+                    adapter.adapter_type,
+                    adapter.vram / (1024 * 1024),
+                    adapter.vendor_id,
+                    adapter.device_id
+                );
             }
         }
+        Err(e) => eprintln!("Could not list the GPUs: {}", e),
     }
+}
+
+fn add_sample(times: &mut Vec<(&'static str, Duration, u32)>, name: &'static str, time: Duration) {
+    match times.iter_mut().find(|(other, _, _)| *other == name) {
+        Some((_, total, samples)) => {
+            *total += time;
+            *samples += 1;
+        }
+        None => times.push((name, time, 1)),
+    }
+}
 
-    fn new_events(&mut self, _event_loop: &ActiveEventLoop, cause: winit::event::StartCause) {
-        match cause {
-            winit::event::StartCause::Poll => {
-                if let Some(window) = &self.window {
-                    window.request_redraw();
+fn print_averages(title: &str, times: &[(&'static str, Duration, u32)]) {
+    if times.is_empty() {
+        return;
+    }
+    println!("{}", title);
+    for (name, total, samples) in times.iter() {
+        println!(
+            "  {:<16} {:.3} ms",
+            name,
+            (*total / *samples).as_secs_f64() * 1000.0
+        );
+    }
+}
+
+impl Benchmark {
+    fn new(frame_count: usize) -> Self {
+        Benchmark {
+            frame_count,
+            frame_times: Vec::with_capacity(frame_count),
+            gpu_pass_times: Vec::new(),
+            cpu_stage_times: Vec::new(),
+        }
+    }
+
+    fn record_timings(&mut self, gpu_timings: &[PassTiming], cpu_timings: &[StageTiming]) {
+        for timing in gpu_timings {
+            add_sample(&mut self.gpu_pass_times, timing.name, timing.duration);
+        }
+        for timing in cpu_timings {
+            add_sample(&mut self.cpu_stage_times, timing.name, timing.duration);
+        }
+    }
+
+    // returns true once all frames were recorded
+    fn record_frame(&mut self, frame_time: Duration) -> bool {
+        self.frame_times.push(frame_time);
+        self.frame_times.len() >= self.frame_count
+    }
+
+    fn report(&self) {
+        let mut sorted_times = self.frame_times.clone();
+        sorted_times.sort();
+        let total: Duration = sorted_times.iter().sum();
+        let average = total / sorted_times.len() as u32;
+        let percentile_99 = sorted_times[(sorted_times.len() - 1) * 99 / 100];
+        println!("Benchmark results over {} frames:", sorted_times.len());
+        println!(
+            "  average: {:.3} ms ({:.1} fps)",
+            average.as_secs_f64() * 1000.0,
+            1.0 / average.as_secs_f64()
+        );
+        println!(
+            "  min:     {:.3} ms",
+            sorted_times[0].as_secs_f64() * 1000.0
+        );
+        println!(
+            "  max:     {:.3} ms",
+            sorted_times[sorted_times.len() - 1].as_secs_f64() * 1000.0
+        );
+        println!("  99th:    {:.3} ms", percentile_99.as_secs_f64() * 1000.0);
+        print_averages("Average cpu time per stage:", &self.cpu_stage_times);
+        print_averages("Average gpu time per pass:", &self.gpu_pass_times);
+    }
+}
+
+// the demo scene with debug controls, everything engine related lives in the library
+struct Demo {
+    benchmark: Option<Benchmark>,
+    // created in init, the renderer doesnt exist before
+    stress_settings: Option<StressSceneSettings>,
+    stress_scene: Option<StressScene>,
+    // frame time for the benchmark, updates always get the fixed time step
+    last_frame: Instant,
+    cursors: CursorSet,
+    text_input: TextInput,
+    clipboard: Clipboard,
+    last_error: Option<String>,
+    tuning_server: Option<TuningServer>,
+    tunables: Tunables,
+    camera_controller: CameraController,
+    // updated with the fixed time step, the renderer gets the interpolation of both
+    camera: Camera,
+    previous_camera: Camera,
+    // spot light attached to the camera
+    flashlight: bool,
+    // shared with the panic hook => crashes still end up in the stats file
+    stats: Arc<Mutex<SessionStats>>,
+    // the allocator report is too expensive to build every frame
+    last_memory_sample: Instant,
+    // dropped files that are still loading in the background
+    dropped_assets: Vec<DroppedAsset>,
+}
+
+enum DroppedHandle {
+    Model(Handle<Model>),
+    Texture(Handle<Texture>),
+}
+
+struct DroppedAsset {
+    path: PathBuf,
+    start: Instant,
+    handle: DroppedHandle,
+}
+
+impl DroppedAsset {
+    fn state(&self, renderer: &VulkanRenderer) -> LoadState {
+        match self.handle {
+            DroppedHandle::Model(handle) => renderer.load_state(handle),
+            DroppedHandle::Texture(handle) => renderer.load_state(handle),
+        }
+    }
+
+    fn error(&self, renderer: &VulkanRenderer) -> Option<String> {
+        match self.handle {
+            DroppedHandle::Model(handle) => renderer.load_error(handle),
+            DroppedHandle::Texture(handle) => renderer.load_error(handle),
+        }
+    }
+}
+
+impl Demo {
+    fn new(
+        benchmark: Option<Benchmark>,
+        stress_settings: Option<StressSceneSettings>,
+        tuning_server: Option<TuningServer>,
+        stats: Arc<Mutex<SessionStats>>,
+    ) -> Demo {
+        Demo {
+            benchmark,
+            stress_settings,
+            stress_scene: None,
+            last_frame: Instant::now(),
+            cursors: CursorSet::new(),
+            text_input: TextInput::new(),
+            clipboard: Clipboard::new(),
+            last_error: None,
+            tuning_server,
+            tunables: Self::init_tunables(),
+            camera_controller: CameraController::new(),
+            camera: Camera::new(),
+            previous_camera: Camera::new(),
+            flashlight: false,
+            stats,
+            last_memory_sample: Instant::now(),
+            dropped_assets: Vec::new(),
+        }
+    }
+
+    fn record_dropped_file(&mut self, renderer: &VulkanRenderer, path: &Path, start: Instant) {
+        let mut stats = self.stats.lock().unwrap();
+        stats.record_asset_load(&path.display().to_string(), start.elapsed());
+        stats.record_gpu_memory(renderer.gpu_memory_usage());
+    }
+
+    fn record_drop_error(&mut self, path: &Path, e: String) {
+        let message = format!("Failed to load dropped file {:?}: {}", path, e);
+        log::error!("{}", message);
+        self.last_error = Some(message);
+    }
+
+    // the load times include the background loading, not just the request
+    fn poll_dropped_assets(&mut self, renderer: &VulkanRenderer) {
+        let dropped_assets = std::mem::take(&mut self.dropped_assets);
+        for asset in dropped_assets {
+            match asset.state(renderer) {
+                LoadState::Loading => self.dropped_assets.push(asset),
+                LoadState::Ready => self.record_dropped_file(renderer, &asset.path, asset.start),
+                LoadState::Failed => {
+                    let e = asset.error(renderer).unwrap_or_default();
+                    self.record_drop_error(&asset.path, e);
                 }
             }
-            _ => log::warn!("Ignoring cause: {:?}", cause),
+        }
+    }
+
+    // defaults match the renderer defaults
+    fn init_tunables() -> Tunables {
+        let mut tunables = Tunables::new();
+        tunables.register("render_scale", 1.0, 0.1, 1.0);
+        tunables.register("upscale_sharpness", 0.2, 0.0, 2.0);
+        tunables.register("exposure", 1.0, 0.0, 8.0);
+        tunables.register("bloom_threshold", 1.0, 0.0, 4.0);
+        tunables.register("bloom_intensity", 0.05, 0.0, 1.0);
+        tunables.register("vignette", 0.25, 0.0, 1.0);
+        tunables.register("paper_white", 200.0, 80.0, 500.0);
+        tunables.register("peak_brightness", 1000.0, 200.0, 4000.0);
+        tunables.register("ambient_r", 0.2, 0.0, 1.0);
+        tunables.register("ambient_g", 0.2, 0.0, 1.0);
+        tunables.register("ambient_b", 0.2, 0.0, 1.0);
+        tunables.register("sun_dir_x", 0.0, -1.0, 1.0);
+        tunables.register("sun_dir_y", 0.0, -1.0, 1.0);
+        tunables.register("sun_dir_z", -1.0, -1.0, 1.0);
+        tunables.register("sun_power", 10.0, 0.0, 100.0);
+        tunables.register("sun_r", 1.0, 0.0, 1.0);
+        tunables.register("sun_g", 1.0, 0.0, 1.0);
+        tunables.register("sun_b", 1.0, 0.0, 1.0);
+        tunables
+    }
+
+    fn apply_tunables(renderer: &mut VulkanRenderer, tunables: &Tunables) {
+        let value = |name| tunables.get(name).unwrap_or_default();
+        renderer.set_render_scale(value("render_scale"));
+        renderer.set_upscale_sharpness(value("upscale_sharpness"));
+        renderer.set_post_process_settings(PostProcessSettings {
+            exposure: value("exposure"),
+            bloom_threshold: value("bloom_threshold"),
+            bloom_intensity: value("bloom_intensity"),
+            vignette_intensity: value("vignette"),
+            paper_white: value("paper_white"),
+            peak_brightness: value("peak_brightness"),
+            ..*renderer.post_process_settings()
+        });
+        renderer.set_ambient_color(glm::vec3(
+            value("ambient_r"),
+            value("ambient_g"),
+            value("ambient_b"),
+        ));
+        renderer.set_sunlight(
+            glm::vec3(value("sun_dir_x"), value("sun_dir_y"), value("sun_dir_z")),
+            value("sun_power"),
+            glm::vec3(value("sun_r"), value("sun_g"), value("sun_b")),
+        );
+    }
+}
+
+impl App for Demo {
+    fn init(&mut self, context: &mut Context) {
+        if let Err(e) =
+            window_icons::set_window_icon(context.window, &paths::asset_path("assets/icon.png"))
+        {
+            log::warn!("Could not set window icon: {}", e);
+        }
+        self.cursors.add(CursorIcon::Crosshair);
+        match window_icons::load_custom_cursor(
+            context.event_loop,
+            &paths::asset_path("assets/cursor.png"),
+            15,
+            15,
+        ) {
+            Ok(cursor) => {
+                self.cursors.add(cursor);
+            }
+            Err(e) => log::warn!("Could not load custom cursor: {}", e),
+        }
+        // cloth falls onto a sphere around the center of the default scene
+        match context.renderer.add_cloth(ClothSettings::default()) {
+            Ok(idx) => {
+                if let Some(cloth) = context.renderer.cloth_mut(idx) {
+                    cloth.set_colliders(&[ClothCollider::Sphere {
+                        center: glm::vec3(0.0, 0.0, 0.0),
+                        radius: 1.0,
+                    }]);
+                    cloth.set_wind(glm::vec3(0.5, 0.0, 0.2));
+                }
+            }
+            Err(e) => log::error!("Could not create cloth: {}", e),
+        }
+        if let Some(settings) = self.stress_settings {
+            match StressScene::new(context.renderer, settings) {
+                Ok(stress_scene) => self.stress_scene = Some(stress_scene),
+                Err(e) => log::error!("Could not create stress scene: {}", e),
+            }
+        }
+        let mut stats = self.stats.lock().unwrap();
+        stats.record_startup();
+        stats.record_gpu_memory(context.renderer.gpu_memory_usage());
+        self.last_frame = Instant::now();
+    }
+
+    fn window_event(&mut self, context: &mut Context, event: &WindowEvent) -> bool {
+        if self
+            .text_input
+            .handle_event(context.window, &mut self.clipboard, event)
+        {
+            // there is no console yet => just log what was typed
+            for line in self.text_input.take_submitted() {
+                log::info!("Text input: {}", line);
+            }
+            return true;
+        }
+        if let WindowEvent::DroppedFile(path) = event {
+            let start = Instant::now();
+            match load_dropped_file(context.renderer, path) {
+                Ok(Some(handle)) => self.dropped_assets.push(DroppedAsset {
+                    path: path.clone(),
+                    start,
+                    handle,
+                }),
+                Ok(None) => self.record_dropped_file(context.renderer, path, start),
+                Err(e) => self.record_drop_error(path, e),
+            }
+        }
+        false
+    }
+
+    fn update(&mut self, context: &mut Context, delta_time: f32) {
+        self.poll_dropped_assets(context.renderer);
+        let Context {
+            window,
+            renderer,
+            input,
+            actions,
+            ..
+        } = context;
+        if let Some(tuning_server) = self.tuning_server.as_mut() {
+            if tuning_server.poll(&mut self.tunables) {
+                Self::apply_tunables(renderer, &self.tunables);
+            }
+        }
+        self.previous_camera = self.camera;
+        let mut exit = false;
+        if actions.was_released(input, Action::Exit) {
+            log::info!("Exit action triggered; Closing window");
+            exit = true;
+        }
+        if actions.was_released(input, Action::ToggleCameraMode) {
+            self.camera_controller.toggle_mode(&self.camera);
+            log::info!("Camera mode: {:?}", self.camera_controller.mode());
+        }
+        if actions.was_released(input, Action::ToggleProjection) {
+            self.camera.toggle_projection();
+            log::info!("Camera projection: {:?}", self.camera.projection);
+        }
+        if actions.was_released(input, Action::ToggleUpscaling) {
+            let upscaling = !renderer.upscaling();
+            log::info!("Upscaling: {}", upscaling);
+            renderer.set_upscaling(upscaling);
+        }
+        if actions.was_released(input, Action::ToggleDithering) {
+            let dithering = !renderer.dithering();
+            log::info!("Dithering: {}", dithering);
+            renderer.set_dithering(dithering);
+        }
+        if actions.was_released(input, Action::ToggleVsync) {
+            let vsync = !renderer.vsync();
+            log::info!("VSync: {}", vsync);
+            renderer.set_vsync(vsync);
+        }
+        if actions.was_released(input, Action::CopyLastError) {
+            match &self.last_error {
+                Some(message) => {
+                    if self.clipboard.set_text(message) {
+                        log::info!("Copied last error to the clipboard");
+                    }
+                }
+                None => log::info!("No error to copy"),
+            }
+        }
+        if actions.was_released(input, Action::NextCursor) {
+            self.cursors.select_next(window);
+        }
+        if actions.was_released(input, Action::NextMonitor) {
+            let monitors = display::enumerate_monitors(window);
+            let current = monitors.iter().position(|monitor| monitor.is_current);
+            if !monitors.is_empty() {
+                let next = current.map_or(0, |idx| (idx + 1) % monitors.len());
+                display::set_target_monitor(window, &monitors[next]);
+            }
+        }
+        if actions.was_released(input, Action::ToggleFrameCapture) {
+            let result = if renderer.frame_capture().is_some() {
+                renderer
+                    .stop_frame_capture()
+                    .map(|_| log::info!("Stopped frame capture"))
+            } else {
+                renderer.start_frame_capture(FrameCaptureSettings::default())
+            };
+            if let Err(e) = result {
+                log::error!("Failed to toggle frame capture: {}", e);
+            }
+        }
+        if actions.was_released(input, Action::SaveFrameCapture) {
+            match renderer.frame_capture() {
+                Some(frame_capture) => save_frame_capture(frame_capture),
+                None => log::warn!("No frame capture running. Press F9 to start one"),
+            }
+        }
+        if actions.was_released(input, Action::ToggleFlashlight) {
+            self.flashlight = !self.flashlight;
+        }
+        if actions.was_released(input, Action::ToggleMinimap) {
+            let result = if renderer.minimap().is_some() {
+                renderer.stop_minimap()
+            } else {
+                renderer.start_minimap(MinimapSettings::default())
+            };
+            if let Err(e) = result {
+                log::error!("Failed to toggle minimap: {}", e);
+            }
+        }
+        self.camera_controller
+            .update(&mut self.camera, input, actions, delta_time);
+        if let Some(stress_scene) = self.stress_scene.as_mut() {
+            stress_scene.advance(delta_time);
+        }
+        if exit {
+            context.exit();
+        }
+    }
+
+    fn render(&mut self, context: &mut Context, alpha: f32) {
+        let camera = self.previous_camera.interpolate(&self.camera, alpha);
+        if self.flashlight {
+            context.renderer.submit_light(Light::Spot {
+                position: camera.position,
+                direction: camera.forward(),
+                color: glm::vec3(1.0, 0.95, 0.85),
+                intensity: 40.0,
+                range: 25.0,
+                inner_angle: 15.0_f32.to_radians(),
+                outer_angle: 25.0_f32.to_radians(),
+            });
+        }
+        if let Some(stress_scene) = self.stress_scene.as_ref() {
+            stress_scene.submit(context.renderer);
+        }
+        *context.renderer.camera_mut() = camera;
+        let frame_time = self.last_frame.elapsed();
+        self.last_frame = Instant::now();
+        {
+            let mut stats = self.stats.lock().unwrap();
+            stats.record_frame(frame_time);
+            if self.last_memory_sample.elapsed() >= Duration::from_secs(1) {
+                stats.record_gpu_memory(context.renderer.gpu_memory_usage());
+                self.last_memory_sample = Instant::now();
+            }
+        }
+        if let Some(benchmark) = self.benchmark.as_mut() {
+            benchmark.record_timings(context.renderer.frame_timings(), &profiler::last_frame());
+            if benchmark.record_frame(frame_time) {
+                benchmark.report();
+                context.exit();
+            }
+        }
+    }
+
+    // collapsed by default => doesnt cover the scene until it is opened
+    fn ui(&mut self, context: &mut Context, ui: &egui::Context) {
+        let renderer = &mut *context.renderer;
+        let mut tunables_changed = false;
+        egui::Window::new("Debug")
+            .default_open(false)
+            .show(ui, |ui| {
+                let mut dithering = renderer.dithering();
+                if ui.checkbox(&mut dithering, "Dithering").changed() {
+                    renderer.set_dithering(dithering);
+                }
+                let mut upscaling = renderer.upscaling();
+                if ui.checkbox(&mut upscaling, "Upscaling").changed() {
+                    renderer.set_upscaling(upscaling);
+                }
+                let mut antialiasing = renderer.antialiasing();
+                ui.horizontal(|ui| {
+                    ui.label("Anti-aliasing");
+                    ui.radio_value(&mut antialiasing, Antialiasing::None, "None");
+                    ui.radio_value(&mut antialiasing, Antialiasing::Fxaa, "FXAA");
+                    ui.radio_value(&mut antialiasing, Antialiasing::Taa, "TAA");
+                });
+                if antialiasing != renderer.antialiasing() {
+                    renderer.set_antialiasing(antialiasing);
+                }
+                let mut transparency = renderer.transparency();
+                ui.horizontal(|ui| {
+                    ui.label("Transparency");
+                    ui.radio_value(&mut transparency, Transparency::Sorted, "Sorted");
+                    ui.radio_value(&mut transparency, Transparency::WeightedBlended, "OIT");
+                });
+                if transparency != renderer.transparency() {
+                    renderer.set_transparency(transparency);
+                }
+                if renderer.supports_ray_query_shadows() {
+                    let mut ray_query_shadows = renderer.ray_query_shadows();
+                    if ui
+                        .checkbox(&mut ray_query_shadows, "Ray traced shadows")
+                        .changed()
+                    {
+                        renderer.set_ray_query_shadows(ray_query_shadows);
+                    }
+                }
+                if let Some(&ao) = renderer.ray_traced_ao_settings() {
+                    let mut settings = ao;
+                    ui.add(
+                        egui::Slider::new(&mut settings.intensity, 0.0..=1.0).text("Ray traced AO"),
+                    );
+                    ui.add(egui::Slider::new(&mut settings.radius, 0.1..=5.0).text("AO radius"));
+                    ui.add(egui::Slider::new(&mut settings.ray_count, 1..=16).text("AO rays"));
+                    if settings != ao {
+                        renderer.set_ray_traced_ao_settings(settings);
+                    }
+                }
+                let mut vsync = renderer.vsync();
+                if ui.checkbox(&mut vsync, "VSync").changed() {
+                    renderer.set_vsync(vsync);
+                }
+                let mut tonemapper = renderer.post_process_settings().tonemapper;
+                ui.horizontal(|ui| {
+                    ui.label("Tonemapper");
+                    ui.radio_value(&mut tonemapper, Tonemapper::None, "None");
+                    ui.radio_value(&mut tonemapper, Tonemapper::Aces, "ACES");
+                    ui.radio_value(&mut tonemapper, Tonemapper::Filmic, "Filmic");
+                });
+                if tonemapper != renderer.post_process_settings().tonemapper {
+                    renderer.set_post_process_settings(PostProcessSettings {
+                        tonemapper,
+                        ..*renderer.post_process_settings()
+                    });
+                }
+                ui.label(format!("Output: {:?}", renderer.output_color_space()));
+                ui.label(format!("Render path: {:?}", renderer.render_path()));
+                ui.checkbox(&mut self.flashlight, "Flashlight");
+                ui.separator();
+                // same values as the tuning server => changes show up in both
+                let entries: Vec<(String, f32, f32, f32)> = self
+                    .tunables
+                    .entries()
+                    .map(|(name, value, min, max)| (name.to_string(), value, min, max))
+                    .collect();
+                for (name, mut value, min, max) in entries {
+                    let slider = egui::Slider::new(&mut value, min..=max).text(&name);
+                    if ui.add(slider).changed() && self.tunables.set(&name, value).is_ok() {
+                        tunables_changed = true;
+                    }
+                }
+            });
+        if tunables_changed {
+            Self::apply_tunables(renderer, &self.tunables);
         }
     }
 }
 
+// None => loaded right away, otherwise the file is still loading in the background
+fn load_dropped_file(
+    renderer: &mut VulkanRenderer,
+    path: &Path,
+) -> Result<Option<DroppedHandle>, String> {
+    log::info!("Loading dropped file {:?}", path);
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_lowercase());
+    match extension.as_deref() {
+        Some("gltf") | Some("glb") => Ok(Some(DroppedHandle::Model(renderer.load_mesh_file(path)))),
+        Some("png") | Some("jpg") | Some("jpeg") | Some("tga") | Some("ktx2") => Ok(Some(
+            DroppedHandle::Texture(renderer.load_texture_file(path)),
+        )),
+        // hdr files are environment maps, as textures they can still be loaded from code
+        Some("hdr") => renderer
+            .load_environment_file(path)
+            .map(|_| None)
+            .map_err(|e| e.to_string()),
+        _ => Err(
+            "Unsupported file type. Drop a .gltf, .glb, .ktx2, .hdr or an image (.png, .jpg, .tga)"
+                .to_string(),
+        ),
+    }
+}
+
+fn save_frame_capture(frame_capture: &FrameCapture) {
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default();
+    let directory = paths::data_dir()
+        .join("captures")
+        .join(timestamp.to_string());
+    let result = frame_capture
+        .save_image_sequence(&directory)
+        .and_then(|_| frame_capture.save_gif(&directory.join("capture.gif")));
+    if let Err(e) = result {
+        log::error!("Failed to save frame capture: {}", e);
+    }
+}
+
 fn main() {
     env_logger::init();
-    let event_loop = EventLoop::new().unwrap();
-
-    event_loop.set_control_flow(ControlFlow::Poll);
+    let args = match CommandLineArgs::parse(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            std::process::exit(2);
+        }
+    };
 
-    let window_settings = WindowSettings::new("LexEngine", 1800, 1000);
-    let mut game_engine = GameEngine::new(window_settings);
+    // the engine still works without the tuning server => dont exit if binding fails
+    let tuning_server =
+        args.tuning_address
+            .and_then(|address| match TuningServer::bind(address.as_str()) {
+                Ok(server) => Some(server),
+                Err(e) => {
+                    log::error!("Could not start tuning server on {}: {}", address, e);
+                    None
+                }
+            });
+    let stats = Arc::new(Mutex::new(SessionStats::new()));
+    telemetry::write_stats_on_panic(stats.clone(), args.stats_file.clone());
+    let demo = Demo::new(
+        args.benchmark_frames.map(Benchmark::new),
+        args.stress_scene,
+        tuning_server,
+        stats.clone(),
+    );
 
-    event_loop
-        .run_app(&mut game_engine)
+    EngineBuilder::new()
+        .title("LexEngine")
+        .size(1800, 1000)
+        .fullscreen(args.fullscreen)
+        .monitor(args.monitor)
+        .frame_pacing(args.frame_pacing)
+        .update_rate(args.update_rate)
+        .renderer_config(args.renderer_config)
+        .stats_overlay(args.show_stats)
+        .run(demo)
         .expect("Runtime Error in the eventloop");
+    let stats = stats.lock().unwrap();
+    if let Err(e) = stats.append_to_file(&args.stats_file, false) {
+        log::error!(
+            "Could not write session stats to {:?}: {}",
+            args.stats_file,
+            e
+        );
+    }
+    if args.print_stats {
+        stats.print();
+    }
     log::info!("Exiting Program");
 }
@@ -0,0 +1,217 @@
+use crate::vulkan_rs::AllocatedImage;
+use crate::vulkan_rs::Allocator;
+use crate::vulkan_rs::Device;
+use crate::vulkan_rs::MaterialCache;
+use crate::vulkan_rs::MaterialTexture;
+use crate::vulkan_rs::SamplerSettings;
+use crate::vulkan_rs::TextureHandle;
+use crate::vulkan_rs::VulkanError;
+use ash::vk;
+use nalgebra_glm as glm;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+#[derive(Debug, Clone, Copy)]
+pub struct MinimapSettings {
+    // width and height of the offscreen target in pixels
+    pub size: u32,
+    // half the size of the area around the player that is visible, in world units
+    pub radius: f32,
+    // geometry more than this above/below the player is cut off
+    pub height: f32,
+    // draw the map into the top right corner of the screen, otherwise it is only a texture
+    pub overlay: bool,
+}
+
+impl Default for MinimapSettings {
+    fn default() -> Self {
+        MinimapSettings {
+            size: 256,
+            radius: 30.0,
+            height: 50.0,
+            overlay: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct MinimapMarker {
+    pub position: glm::Vec3,
+    pub color: [f32; 4],
+}
+
+const PLAYER_MARKER_COLOR: [f32; 4] = [1.0, 0.85, 0.1, 1.0];
+const BACKGROUND_COLOR: [f32; 4] = [0.05, 0.05, 0.08, 1.0];
+
+// top down orthographic view of the scene around the player, north (-z) is up
+pub struct Minimap {
+    settings: MinimapSettings,
+    texture: MaterialTexture,
+    depth_image: AllocatedImage,
+    // index into the bindless texture table => ui shaders can sample the map
+    texture_handle: TextureHandle,
+    markers: Vec<MinimapMarker>,
+}
+
+impl Minimap {
+    pub fn new(
+        device: Arc<Device>,
+        allocator: Arc<Mutex<Allocator>>,
+        material_cache: &mut MaterialCache,
+        settings: MinimapSettings,
+        color_format: vk::Format,
+    ) -> Result<Self, VulkanError> {
+        log::info!(
+            "Creating {}x{} minimap with a radius of {}",
+            settings.size,
+            settings.size,
+            settings.radius
+        );
+        let extent = vk::Extent3D {
+            width: settings.size,
+            height: settings.size,
+            depth: 1,
+        };
+        // same format as the draw image => the material pipelines can render into it
+        let color_image = AllocatedImage::new(
+            device.clone(),
+            allocator.clone(),
+            color_format,
+            vk::ImageUsageFlags::COLOR_ATTACHMENT
+                | vk::ImageUsageFlags::SAMPLED
+                | vk::ImageUsageFlags::TRANSFER_SRC,
+            extent,
+            vk::ImageAspectFlags::COLOR,
+            1,
+        )?;
+        let depth_image = AllocatedImage::new_depth_image(device, allocator, extent)?;
+        let texture = MaterialTexture {
+            image: Arc::new(color_image),
+            sampler: material_cache.sampler(
+                SamplerSettings::new(vk::Filter::LINEAR, vk::Filter::LINEAR).mipmap_mode(None),
+            )?,
+        };
+        let texture_handle = material_cache.register_texture(&texture)?;
+        Ok(Minimap {
+            settings,
+            texture,
+            depth_image,
+            texture_handle,
+            markers: Vec::new(),
+        })
+    }
+
+    pub fn settings(&self) -> &MinimapSettings {
+        &self.settings
+    }
+
+    pub fn extent(&self) -> vk::Extent2D {
+        vk::Extent2D {
+            width: self.settings.size,
+            height: self.settings.size,
+        }
+    }
+
+    // in SHADER_READ_ONLY_OPTIMAL after the frame that rendered it
+    pub fn image(&self) -> &AllocatedImage {
+        &self.texture.image
+    }
+
+    pub fn depth_image(&self) -> &AllocatedImage {
+        &self.depth_image
+    }
+
+    pub fn texture_index(&self) -> u32 {
+        self.texture_handle.index()
+    }
+
+    // drawn on top of the map in addition to the player
+    pub fn set_markers(&mut self, markers: Vec<MinimapMarker>) {
+        self.markers = markers;
+    }
+
+    pub fn background_color(&self) -> vk::ClearColorValue {
+        vk::ClearColorValue {
+            float32: BACKGROUND_COLOR,
+        }
+    }
+
+    pub fn eye_position(&self, center: glm::Vec3) -> glm::Vec3 {
+        center + glm::Vec3::y() * self.settings.height
+    }
+
+    pub fn view_matrix(&self, center: glm::Vec3) -> glm::Mat4 {
+        let eye = self.eye_position(center);
+        // looking straight down => -z as up vector keeps north at the top of the map
+        glm::look_at_rh(&eye, &(eye - glm::Vec3::y()), &-glm::Vec3::z())
+    }
+
+    // reversed depth and y pointing down like the camera
+    pub fn projection_matrix(&self) -> glm::Mat4 {
+        let radius = self.settings.radius;
+        let mut projection = glm::ortho_rh_zo(
+            -radius,
+            radius,
+            -radius,
+            radius,
+            2.0 * self.settings.height,
+            0.0,
+        );
+        projection[(1, 1)] *= -1.0;
+        projection
+    }
+
+    // pixel rects of the markers around center, the player gets an extra dot in the view direction
+    pub fn marker_rects(
+        &self,
+        center: glm::Vec3,
+        forward: glm::Vec3,
+    ) -> Vec<(vk::ClearColorValue, vk::Rect2D)> {
+        let marker_size = (self.settings.size / 48).max(2);
+        let heading = glm::vec2(forward.x, forward.z);
+        let heading = if heading.norm_squared() > 0.0 {
+            glm::normalize(&heading) * self.settings.radius * 0.08
+        } else {
+            glm::Vec2::zeros()
+        };
+        let player_markers = [
+            (center, PLAYER_MARKER_COLOR, marker_size * 2),
+            (
+                center + glm::vec3(heading.x, 0.0, heading.y),
+                PLAYER_MARKER_COLOR,
+                marker_size,
+            ),
+        ];
+        self.markers
+            .iter()
+            .map(|marker| (marker.position, marker.color, marker_size * 2))
+            .chain(player_markers)
+            .filter_map(|(position, color, size)| {
+                let rect = self.marker_rect(position - center, size)?;
+                Some((vk::ClearColorValue { float32: color }, rect))
+            })
+            .collect()
+    }
+
+    // markers outside of the map are not drawn
+    fn marker_rect(&self, offset: glm::Vec3, size: u32) -> Option<vk::Rect2D> {
+        let radius = self.settings.radius;
+        if offset.x.abs() > radius || offset.z.abs() > radius {
+            return None;
+        }
+        let map_size = self.settings.size as f32;
+        // x => right, -z => up
+        let x = (offset.x / radius + 1.0) * 0.5 * map_size;
+        let y = (offset.z / radius + 1.0) * 0.5 * map_size;
+        let half = size as f32 / 2.0;
+        let min_x = (x - half).min(map_size - size as f32).max(0.0) as i32;
+        let min_y = (y - half).min(map_size - size as f32).max(0.0) as i32;
+        Some(vk::Rect2D {
+            offset: vk::Offset2D { x: min_x, y: min_y },
+            extent: vk::Extent2D {
+                width: size.min(self.settings.size),
+                height: size.min(self.settings.size),
+            },
+        })
+    }
+}
@@ -0,0 +1,105 @@
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+// directory that contains assets/ and shaders/
+const ASSET_ROOT_ENV: &str = "LEX_ENGINE_ASSET_ROOT";
+// where saves, session stats and captures are written
+const DATA_DIR_ENV: &str = "LEX_ENGINE_DATA_DIR";
+const CACHE_DIR_ENV: &str = "LEX_ENGINE_CACHE_DIR";
+const APP_DIR_NAME: &str = "LexEngine";
+
+static ASSET_ROOT: OnceLock<PathBuf> = OnceLock::new();
+
+// resolved once, the working directory does not matter unless nothing else has an assets dir
+pub fn asset_root() -> &'static Path {
+    ASSET_ROOT.get_or_init(find_asset_root)
+}
+
+// paths relative to the asset root, e.g. "assets/icon.png" or "shaders/mesh_vert.spv"
+pub fn asset_path(relative: impl AsRef<Path>) -> PathBuf {
+    asset_root().join(relative)
+}
+
+fn find_asset_root() -> PathBuf {
+    if let Some(root) = std::env::var_os(ASSET_ROOT_ENV) {
+        return PathBuf::from(root);
+    }
+    // next to the executable for shipped builds, a few levels up for target/<profile>(/deps)
+    let executable_dir = std::env::current_exe()
+        .ok()
+        .and_then(|executable| executable.parent().map(Path::to_path_buf));
+    let candidates = executable_dir
+        .iter()
+        .flat_map(|dir| dir.ancestors().take(4))
+        .map(Path::to_path_buf)
+        .chain(std::env::current_dir().ok());
+    for candidate in candidates {
+        if candidate.join("assets").is_dir() {
+            log::info!("Using asset root {:?}", candidate);
+            return candidate;
+        }
+    }
+    log::warn!(
+        "Could not find the assets directory, set {} to its parent directory",
+        ASSET_ROOT_ENV
+    );
+    PathBuf::from(".")
+}
+
+// per user directory for saves/stats/captures, not created by this function
+pub fn data_dir() -> PathBuf {
+    if let Some(dir) = std::env::var_os(DATA_DIR_ENV) {
+        return PathBuf::from(dir);
+    }
+    platform_data_dir()
+        .map(|dir| dir.join(APP_DIR_NAME))
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+// like data_dir, but the content can be regenerated
+pub fn cache_dir() -> PathBuf {
+    if let Some(dir) = std::env::var_os(CACHE_DIR_ENV) {
+        return PathBuf::from(dir);
+    }
+    platform_cache_dir()
+        .map(|dir| dir.join(APP_DIR_NAME))
+        .unwrap_or_else(|| std::env::temp_dir().join(APP_DIR_NAME))
+}
+
+fn env_dir(name: &str) -> Option<PathBuf> {
+    std::env::var_os(name)
+        .filter(|value| !value.is_empty())
+        .map(PathBuf::from)
+}
+
+#[cfg(target_os = "windows")]
+fn platform_data_dir() -> Option<PathBuf> {
+    env_dir("APPDATA")
+}
+
+#[cfg(target_os = "windows")]
+fn platform_cache_dir() -> Option<PathBuf> {
+    env_dir("LOCALAPPDATA")
+}
+
+#[cfg(target_os = "macos")]
+fn platform_data_dir() -> Option<PathBuf> {
+    env_dir("HOME").map(|home| home.join("Library/Application Support"))
+}
+
+#[cfg(target_os = "macos")]
+fn platform_cache_dir() -> Option<PathBuf> {
+    env_dir("HOME").map(|home| home.join("Library/Caches"))
+}
+
+// xdg base directories
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+fn platform_data_dir() -> Option<PathBuf> {
+    env_dir("XDG_DATA_HOME").or_else(|| env_dir("HOME").map(|home| home.join(".local/share")))
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+fn platform_cache_dir() -> Option<PathBuf> {
+    env_dir("XDG_CACHE_HOME").or_else(|| env_dir("HOME").map(|home| home.join(".cache")))
+}
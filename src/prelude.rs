@@ -0,0 +1,39 @@
+// use game_engine::prelude::* => everything a game needs without touching vulkan types
+pub use crate::camera::Camera;
+pub use crate::camera::CameraController;
+pub use crate::camera::ControllerMode;
+pub use crate::camera::Projection;
+pub use crate::egui;
+pub use crate::input::Action;
+pub use crate::input::ActionMap;
+pub use crate::input::Binding;
+pub use crate::input::InputState;
+pub use crate::input::TextInput;
+pub use crate::Antialiasing;
+pub use crate::App;
+pub use crate::AssetError;
+pub use crate::Context;
+pub use crate::Engine;
+pub use crate::EngineBuilder;
+pub use crate::FramePacing;
+pub use crate::Handle;
+pub use crate::Light;
+pub use crate::LoadState;
+pub use crate::MaterialHandle;
+pub use crate::MeshHandle;
+pub use crate::Model;
+pub use crate::PostProcessSettings;
+pub use crate::Primitive;
+pub use crate::RayTracedAoSettings;
+pub use crate::RenderPath;
+pub use crate::RendererConfig;
+pub use crate::Texture;
+pub use crate::Tonemapper;
+pub use crate::Transform;
+pub use crate::Transparency;
+pub use crate::VulkanError;
+pub use crate::VulkanRenderer as Renderer;
+// transforms, lights and the camera use the glm types
+pub use nalgebra_glm as glm;
+// delta times are f32 seconds, the durations of the config are std ones
+pub use std::time::Duration;
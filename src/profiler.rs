@@ -0,0 +1,69 @@
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+#[derive(Debug, Clone, Copy)]
+pub struct StageTiming {
+    pub name: &'static str,
+    pub duration: Duration,
+}
+
+// scopes that ran more than once in a frame (fixed updates) are summed up
+static CURRENT_FRAME: Mutex<Vec<StageTiming>> = Mutex::new(Vec::new());
+static LAST_FRAME: Mutex<Vec<StageTiming>> = Mutex::new(Vec::new());
+
+// measures until it is dropped, use profile_scope! instead of creating it directly
+pub struct ProfileScope {
+    name: &'static str,
+    start: Instant,
+    #[cfg(feature = "tracy")]
+    _span: Option<tracy_client::Span>,
+}
+
+impl Drop for ProfileScope {
+    fn drop(&mut self) {
+        let duration = self.start.elapsed();
+        let mut frame = CURRENT_FRAME.lock().unwrap();
+        match frame.iter_mut().find(|stage| stage.name == self.name) {
+            Some(stage) => stage.duration += duration,
+            None => frame.push(StageTiming {
+                name: self.name,
+                duration,
+            }),
+        }
+    }
+}
+
+#[allow(unused_variables)]
+pub fn scope(name: &'static str, file: &'static str, line: u32) -> ProfileScope {
+    ProfileScope {
+        name,
+        start: Instant::now(),
+        #[cfg(feature = "tracy")]
+        _span: tracy_client::Client::running()
+            .map(|client| client.span_alloc(Some(name), name, file, line, 0)),
+    }
+}
+
+// times the rest of the enclosing block, also a tracy zone with the tracy feature
+#[macro_export]
+macro_rules! profile_scope {
+    ($name:expr) => {
+        let _profile_scope = $crate::profiler::scope($name, file!(), line!());
+    };
+}
+
+// call once per frame after everything of the frame was recorded
+pub fn end_frame() {
+    let stages = std::mem::take(&mut *CURRENT_FRAME.lock().unwrap());
+    *LAST_FRAME.lock().unwrap() = stages;
+    #[cfg(feature = "tracy")]
+    if let Some(client) = tracy_client::Client::running() {
+        client.frame_mark();
+    }
+}
+
+// cpu time of the scopes of the previous frame, in the order they finished
+pub fn last_frame() -> Vec<StageTiming> {
+    LAST_FRAME.lock().unwrap().clone()
+}
@@ -0,0 +1,153 @@
+use crate::vulkan_renderer::VulkanRenderer;
+use std::collections::VecDeque;
+use std::time::Duration;
+use std::time::Instant;
+
+// a few seconds at typical frame rates, one graph column per frame
+const FRAME_HISTORY: usize = 240;
+const GRAPH_HEIGHT: f32 = 60.0;
+// the allocator report walks every memory block => not every frame
+const MEMORY_SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+
+// fps, frame time graph, draw calls and gpu memory in the top left corner
+// drawn with egui => independent of the mesh pipelines and of the render scale
+pub struct StatsOverlay {
+    visible: bool,
+    // seconds, oldest first
+    frame_times: VecDeque<f32>,
+    // (allocated, reserved) bytes
+    memory: (u64, u64),
+    last_memory_sample: Option<Instant>,
+}
+
+impl StatsOverlay {
+    pub fn new(visible: bool) -> Self {
+        StatsOverlay {
+            visible,
+            frame_times: VecDeque::with_capacity(FRAME_HISTORY),
+            memory: (0, 0),
+            last_memory_sample: None,
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+    }
+
+    // frame times are recorded while hidden too => the graph is filled when it is opened
+    pub fn record_frame(&mut self, frame_time: f32, renderer: &VulkanRenderer) {
+        if self.frame_times.len() == FRAME_HISTORY {
+            self.frame_times.pop_front();
+        }
+        self.frame_times.push_back(frame_time);
+        if self.visible
+            && self
+                .last_memory_sample
+                .is_none_or(|sample| sample.elapsed() >= MEMORY_SAMPLE_INTERVAL)
+        {
+            self.memory = renderer.gpu_memory_report();
+            self.last_memory_sample = Some(Instant::now());
+        }
+    }
+
+    pub fn show(&self, ui: &egui::Context, renderer: &VulkanRenderer) {
+        if !self.visible || self.frame_times.is_empty() {
+            return;
+        }
+        let average = self.frame_times.iter().sum::<f32>() / self.frame_times.len() as f32;
+        let worst = self.frame_times.iter().copied().fold(0.0, f32::max);
+        let (allocated, reserved) = self.memory;
+        egui::Area::new(egui::Id::new("stats overlay"))
+            .anchor(egui::Align2::LEFT_TOP, [8.0, 8.0])
+            .interactable(false)
+            .show(ui, |ui| {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    let lines = [
+                        format!(
+                            "{:.0} fps  {:.2} ms (worst {:.2} ms)",
+                            1.0 / average.max(f32::EPSILON),
+                            average * 1000.0,
+                            worst * 1000.0
+                        ),
+                        format!("{} draw calls", renderer.draw_call_count()),
+                        format!(
+                            "gpu memory {:.1} / {:.1} MiB",
+                            allocated as f64 / (1024.0 * 1024.0),
+                            reserved as f64 / (1024.0 * 1024.0)
+                        ),
+                    ];
+                    for line in lines {
+                        ui.label(egui::RichText::new(line).monospace());
+                    }
+                    for timing in renderer.frame_timings() {
+                        ui.label(
+                            egui::RichText::new(format!(
+                                "  {:<14} {:.2} ms",
+                                timing.name,
+                                timing.duration.as_secs_f64() * 1000.0
+                            ))
+                            .monospace(),
+                        );
+                    }
+                    Self::bandwidth(ui, renderer, average);
+                    self.frame_time_graph(ui, worst);
+                });
+            });
+    }
+
+    // estimated traffic per pass, passes with the same name (e.g. sort steps) are summed up
+    fn bandwidth(ui: &mut egui::Ui, renderer: &VulkanRenderer, average: f32) {
+        let estimate = renderer.graph_estimate();
+        let mut passes: Vec<(&str, u64)> = Vec::new();
+        for pass in estimate.passes.iter().filter(|pass| pass.bytes > 0) {
+            match passes.iter_mut().find(|(name, _)| *name == pass.name) {
+                Some((_, bytes)) => *bytes += pass.bytes,
+                None => passes.push((&pass.name, pass.bytes)),
+            }
+        }
+        let total: u64 = passes.iter().map(|(_, bytes)| bytes).sum();
+        let mib = |bytes: u64| bytes as f64 / (1024.0 * 1024.0);
+        ui.label(
+            egui::RichText::new(format!(
+                "~{:.1} GiB/s, targets {:.1} MiB",
+                mib(total) / 1024.0 / average.max(f32::EPSILON) as f64,
+                mib(estimate.image_memory)
+            ))
+            .monospace(),
+        );
+        for (name, bytes) in passes {
+            ui.label(
+                egui::RichText::new(format!("  {:<14} {:.1} MiB", name, mib(bytes))).monospace(),
+            );
+        }
+    }
+
+    // one line per frame, the grey line is 60 fps
+    fn frame_time_graph(&self, ui: &mut egui::Ui, worst: f32) {
+        let (rect, _) = ui.allocate_exact_size(
+            egui::vec2(FRAME_HISTORY as f32, GRAPH_HEIGHT),
+            egui::Sense::hover(),
+        );
+        let painter = ui.painter_at(rect);
+        painter.rect_filled(rect, 0.0, egui::Color32::from_black_alpha(120));
+        // at least 30 fps high => a smooth 60 fps doesnt fill the whole graph with noise
+        let max_time = worst.max(1.0 / 30.0);
+        let y = |frame_time: f32| rect.bottom() - frame_time / max_time * rect.height();
+        painter.hline(
+            rect.x_range(),
+            y(1.0 / 60.0),
+            egui::Stroke::new(1.0, egui::Color32::GRAY),
+        );
+        let first_x = rect.right() - self.frame_times.len() as f32;
+        let points = self
+            .frame_times
+            .iter()
+            .enumerate()
+            .map(|(idx, frame_time)| egui::pos2(first_x + idx as f32, y(*frame_time)))
+            .collect();
+        painter.add(egui::Shape::line(
+            points,
+            egui::Stroke::new(1.0, egui::Color32::LIGHT_GREEN),
+        ));
+    }
+}
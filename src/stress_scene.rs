@@ -0,0 +1,216 @@
+use crate::vulkan_renderer::MaterialHandle;
+use crate::vulkan_renderer::MeshHandle;
+use crate::vulkan_renderer::VulkanRenderer;
+use crate::vulkan_rs::Light;
+use crate::vulkan_rs::Primitive;
+use crate::vulkan_rs::VulkanError;
+use nalgebra_glm as glm;
+
+// distance between the centers of neighbouring instances
+const SPACING: f32 = 3.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StressSceneSettings {
+    pub instance_count: usize,
+    // every instance gets one of these at random => descriptor/material switches
+    pub material_count: usize,
+    // point lights moving through the grid, limited by the renderer
+    pub light_count: usize,
+    // rotating instances and moving lights
+    pub animated: bool,
+    // same seed => same scene, for comparing benchmarks
+    pub seed: u64,
+}
+
+impl Default for StressSceneSettings {
+    fn default() -> Self {
+        StressSceneSettings {
+            instance_count: 10_000,
+            material_count: 64,
+            light_count: 32,
+            animated: true,
+            seed: 1,
+        }
+    }
+}
+
+// splitmix64 => reproducible on every platform without a rand dependency
+struct Random(u64);
+
+impl Random {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    // [0, 1)
+    fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    fn range(&mut self, min: f32, max: f32) -> f32 {
+        min + (max - min) * self.next_f32()
+    }
+
+    fn index(&mut self, len: usize) -> usize {
+        (self.next_u64() % len as u64) as usize
+    }
+
+    fn unit_vector(&mut self) -> glm::Vec3 {
+        let z = self.range(-1.0, 1.0);
+        let phi = self.range(0.0, std::f32::consts::TAU);
+        let r = (1.0 - z * z).sqrt();
+        glm::vec3(r * phi.cos(), r * phi.sin(), z)
+    }
+}
+
+struct Instance {
+    // index into meshes
+    mesh: usize,
+    material: usize,
+    position: glm::Vec3,
+    scale: f32,
+    axis: glm::Vec3,
+    // radians per second
+    angular_speed: f32,
+}
+
+struct StressLight {
+    center: glm::Vec3,
+    color: glm::Vec3,
+    // lights circle around their center
+    radius: f32,
+    phase: f32,
+}
+
+// grid of cubes and spheres with random materials and lights, submitted every frame
+// => stresses draw submission, culling and material switches independent of any asset files
+pub struct StressScene {
+    settings: StressSceneSettings,
+    meshes: Vec<MeshHandle>,
+    materials: Vec<MaterialHandle>,
+    instances: Vec<Instance>,
+    lights: Vec<StressLight>,
+    time: f32,
+}
+
+impl StressScene {
+    pub fn new(
+        renderer: &mut VulkanRenderer,
+        settings: StressSceneSettings,
+    ) -> Result<Self, VulkanError> {
+        let mut random = Random(settings.seed);
+        let materials = (0..settings.material_count.max(1))
+            .map(|_| {
+                let color = glm::vec4(
+                    random.range(0.1, 1.0),
+                    random.range(0.1, 1.0),
+                    random.range(0.1, 1.0),
+                    1.0,
+                );
+                let metallic = if random.next_f32() < 0.3 { 1.0 } else { 0.0 };
+                renderer.create_material(color, metallic, random.range(0.1, 1.0))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let meshes = [
+            Primitive::Cube,
+            Primitive::Sphere {
+                segments: 24,
+                rings: 12,
+            },
+        ]
+        .into_iter()
+        .map(|primitive| renderer.create_primitive_mesh(primitive, &materials[0]))
+        .collect::<Result<Vec<_>, _>>()?;
+
+        // cube shaped grid around the origin, slightly jittered => no perfect rows
+        let side = (settings.instance_count as f32).cbrt().ceil().max(1.0) as usize;
+        let offset = (side - 1) as f32 * SPACING * 0.5;
+        let instances = (0..settings.instance_count)
+            .map(|idx| {
+                let cell = glm::vec3(
+                    (idx % side) as f32,
+                    (idx / side % side) as f32,
+                    (idx / (side * side)) as f32,
+                );
+                let jitter = random.unit_vector() * SPACING * 0.2;
+                Instance {
+                    mesh: random.index(meshes.len()),
+                    material: random.index(materials.len()),
+                    position: cell * SPACING - glm::vec3(offset, offset, offset) + jitter,
+                    scale: random.range(0.3, 0.9),
+                    axis: random.unit_vector(),
+                    angular_speed: random.range(-2.0, 2.0),
+                }
+            })
+            .collect();
+        let lights = (0..settings.light_count)
+            .map(|_| StressLight {
+                center: glm::vec3(
+                    random.range(-offset, offset),
+                    random.range(-offset, offset),
+                    random.range(-offset, offset),
+                ),
+                color: glm::vec3(
+                    random.range(0.2, 1.0),
+                    random.range(0.2, 1.0),
+                    random.range(0.2, 1.0),
+                ),
+                radius: random.range(1.0, SPACING * 3.0),
+                phase: random.range(0.0, std::f32::consts::TAU),
+            })
+            .collect();
+        log::info!(
+            "Stress scene with {} instances, {} materials and {} lights",
+            settings.instance_count,
+            materials.len(),
+            settings.light_count
+        );
+        Ok(StressScene {
+            settings,
+            meshes,
+            materials,
+            instances,
+            lights,
+            time: 0.0,
+        })
+    }
+
+    pub fn settings(&self) -> &StressSceneSettings {
+        &self.settings
+    }
+
+    // fixed time step => the animation is the same in every benchmark run
+    pub fn advance(&mut self, delta_time: f32) {
+        if self.settings.animated {
+            self.time += delta_time;
+        }
+    }
+
+    // has to be called every frame, like VulkanRenderer::submit
+    pub fn submit(&self, renderer: &mut VulkanRenderer) {
+        for instance in &self.instances {
+            let rotation = glm::rotation(self.time * instance.angular_speed, &instance.axis);
+            let transform = glm::translation(&instance.position)
+                * rotation
+                * glm::scaling(&glm::vec3(instance.scale, instance.scale, instance.scale));
+            renderer.submit(
+                &self.meshes[instance.mesh],
+                transform,
+                Some(&self.materials[instance.material]),
+            );
+        }
+        for light in &self.lights {
+            let angle = self.time + light.phase;
+            renderer.submit_light(Light::Point {
+                position: light.center + glm::vec3(angle.cos(), 0.0, angle.sin()) * light.radius,
+                color: light.color,
+                intensity: 20.0,
+                range: SPACING * 4.0,
+            });
+        }
+    }
+}
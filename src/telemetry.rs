@@ -0,0 +1,192 @@
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+use std::time::SystemTime;
+
+// frame times are counted in 0.1 ms buckets => memory does not grow with the session length
+const BUCKET_WIDTH_US: u64 = 100;
+// everything above 1s ends up in the last bucket
+const BUCKET_COUNT: usize = 10_000;
+
+#[derive(Debug, Clone)]
+struct AssetLoad {
+    name: String,
+    duration: Duration,
+}
+
+// collects performance numbers over a whole run, one summary line is appended to a stats file
+// on exit => the file keeps the history over development
+#[derive(Debug, Clone)]
+pub struct SessionStats {
+    start: Instant,
+    startup_time: Option<Duration>,
+    frame_count: u64,
+    total_frame_time: Duration,
+    max_frame_time: Duration,
+    frame_time_buckets: Vec<u32>,
+    peak_gpu_memory: u64,
+    asset_loads: Vec<AssetLoad>,
+}
+
+impl SessionStats {
+    pub fn new() -> Self {
+        SessionStats {
+            start: Instant::now(),
+            startup_time: None,
+            frame_count: 0,
+            total_frame_time: Duration::ZERO,
+            max_frame_time: Duration::ZERO,
+            frame_time_buckets: vec![0; BUCKET_COUNT],
+            peak_gpu_memory: 0,
+            asset_loads: Vec::new(),
+        }
+    }
+
+    // time from creating the stats until the first frame can be rendered
+    pub fn record_startup(&mut self) {
+        self.startup_time.get_or_insert(self.start.elapsed());
+    }
+
+    pub fn record_frame(&mut self, frame_time: Duration) {
+        self.frame_count += 1;
+        self.total_frame_time += frame_time;
+        self.max_frame_time = self.max_frame_time.max(frame_time);
+        let bucket = (frame_time.as_micros() as u64 / BUCKET_WIDTH_US) as usize;
+        self.frame_time_buckets[bucket.min(BUCKET_COUNT - 1)] += 1;
+    }
+
+    pub fn record_gpu_memory(&mut self, bytes: u64) {
+        self.peak_gpu_memory = self.peak_gpu_memory.max(bytes);
+    }
+
+    pub fn record_asset_load(&mut self, name: &str, duration: Duration) {
+        self.asset_loads.push(AssetLoad {
+            name: name.to_string(),
+            duration,
+        });
+    }
+
+    pub fn average_frame_time(&self) -> Duration {
+        if self.frame_count == 0 {
+            return Duration::ZERO;
+        }
+        self.total_frame_time.div_f64(self.frame_count as f64)
+    }
+
+    // upper end of the bucket => slightly pessimistic, but never off by more than 0.1 ms
+    pub fn percentile_frame_time(&self, percentile: f64) -> Duration {
+        if self.frame_count == 0 {
+            return Duration::ZERO;
+        }
+        let target = ((self.frame_count as f64 * percentile / 100.0).ceil() as u64).max(1);
+        let mut counted = 0;
+        for (bucket, count) in self.frame_time_buckets.iter().enumerate() {
+            counted += *count as u64;
+            if counted >= target {
+                let upper_end = Duration::from_micros((bucket as u64 + 1) * BUCKET_WIDTH_US);
+                return upper_end.min(self.max_frame_time);
+            }
+        }
+        self.max_frame_time
+    }
+
+    // one line of key=value pairs, asset names are quoted since paths can contain spaces
+    pub fn summary(&self, crashed: bool) -> String {
+        let timestamp = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or_default();
+        let milliseconds = |duration: Duration| duration.as_secs_f64() * 1000.0;
+        let total_load_time: Duration = self.asset_loads.iter().map(|load| load.duration).sum();
+        let slowest_load = self
+            .asset_loads
+            .iter()
+            .max_by_key(|load| load.duration)
+            .map(|load| {
+                format!(
+                    " slowest_load={:?} slowest_load_ms={:.1}",
+                    load.name,
+                    milliseconds(load.duration)
+                )
+            })
+            .unwrap_or_default();
+        format!(
+            "timestamp={} session_s={:.1} startup_ms={:.1} frames={} avg_frame_ms={:.3} p99_frame_ms={:.3} max_frame_ms={:.3} peak_gpu_memory_mb={:.1} asset_loads={} asset_load_ms={:.1}{} crashed={}",
+            timestamp,
+            self.start.elapsed().as_secs_f64(),
+            milliseconds(self.startup_time.unwrap_or_default()),
+            self.frame_count,
+            milliseconds(self.average_frame_time()),
+            milliseconds(self.percentile_frame_time(99.0)),
+            milliseconds(self.max_frame_time),
+            self.peak_gpu_memory as f64 / (1024.0 * 1024.0),
+            self.asset_loads.len(),
+            milliseconds(total_load_time),
+            slowest_load,
+            crashed
+        )
+    }
+
+    pub fn append_to_file(&self, path: &Path, crashed: bool) -> std::io::Result<()> {
+        if let Some(parent) = path.parent() {
+            if !parent.as_os_str().is_empty() {
+                std::fs::create_dir_all(parent)?;
+            }
+        }
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        writeln!(file, "{}", self.summary(crashed))
+    }
+
+    pub fn print(&self) {
+        println!("Session stats:");
+        println!(
+            "  frames:      {} in {:.1} s",
+            self.frame_count,
+            self.start.elapsed().as_secs_f64()
+        );
+        println!(
+            "  average:     {:.3} ms",
+            self.average_frame_time().as_secs_f64() * 1000.0
+        );
+        println!(
+            "  99th:        {:.3} ms",
+            self.percentile_frame_time(99.0).as_secs_f64() * 1000.0
+        );
+        println!(
+            "  peak memory: {:.1} MiB",
+            self.peak_gpu_memory as f64 / (1024.0 * 1024.0)
+        );
+        for load in &self.asset_loads {
+            println!(
+                "  loaded {} in {:.1} ms",
+                load.name,
+                load.duration.as_secs_f64() * 1000.0
+            );
+        }
+    }
+}
+
+impl Default for SessionStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// a panic skips the normal exit => write the stats from the panic hook, flagged as crashed
+pub fn write_stats_on_panic(stats: Arc<Mutex<SessionStats>>, path: PathBuf) {
+    let previous_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        // the panicking thread might hold the lock => dont block
+        if let Ok(stats) = stats.try_lock() {
+            if let Err(e) = stats.append_to_file(&path, true) {
+                log::error!("Could not write session stats to {:?}: {}", path, e);
+            }
+        }
+        previous_hook(info);
+    }));
+}
@@ -0,0 +1,226 @@
+use std::collections::BTreeMap;
+use std::io::ErrorKind;
+use std::io::Read;
+use std::io::Write;
+use std::net::TcpListener;
+use std::net::TcpStream;
+use std::net::ToSocketAddrs;
+
+// clients that send more than this without a newline are disconnected
+const MAX_LINE_LENGTH: usize = 1024;
+// clients that dont read their responses are disconnected once this much is queued
+const MAX_PENDING_OUTPUT: usize = 64 * 1024;
+
+#[derive(Debug, Clone, Copy)]
+struct Tunable {
+    value: f32,
+    min: f32,
+    max: f32,
+}
+
+// named values that can be changed while the engine is running
+// the engine reads them back every frame => no callbacks needed
+#[derive(Debug, Default)]
+pub struct Tunables {
+    values: BTreeMap<String, Tunable>,
+}
+
+impl Tunables {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register(&mut self, name: &str, value: f32, min: f32, max: f32) {
+        self.values.insert(
+            name.to_string(),
+            Tunable {
+                value: value.clamp(min, max),
+                min,
+                max,
+            },
+        );
+    }
+
+    pub fn get(&self, name: &str) -> Option<f32> {
+        self.values.get(name).map(|tunable| tunable.value)
+    }
+
+    // (name, value, min, max) sorted by name
+    pub fn entries(&self) -> impl Iterator<Item = (&str, f32, f32, f32)> {
+        self.values
+            .iter()
+            .map(|(name, tunable)| (name.as_str(), tunable.value, tunable.min, tunable.max))
+    }
+
+    // returns the clamped value that was actually set
+    pub fn set(&mut self, name: &str, value: f32) -> Result<f32, String> {
+        let tunable = self
+            .values
+            .get_mut(name)
+            .ok_or_else(|| format!("unknown tunable {}", name))?;
+        if !value.is_finite() {
+            return Err(format!("invalid value {}", value));
+        }
+        tunable.value = value.clamp(tunable.min, tunable.max);
+        Ok(tunable.value)
+    }
+}
+
+struct Client {
+    stream: TcpStream,
+    incoming: Vec<u8>,
+    // responses the socket didnt accept yet, written whenever the server is polled
+    outgoing: Vec<u8>,
+}
+
+// line based text protocol => can be used with netcat/telnet or a small script
+//   list              -> "<name> <value> <min> <max>" per tunable, followed by "end"
+//   get <name>        -> "ok <name> <value>"
+//   set <name> <val>  -> "ok <name> <value>" (value is clamped to the registered range)
+// errors are answered with "err <message>"
+// NOTE: there is no authentication => only bind to addresses of trusted networks
+pub struct TuningServer {
+    listener: TcpListener,
+    clients: Vec<Client>,
+}
+
+impl TuningServer {
+    pub fn bind(address: impl ToSocketAddrs) -> std::io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        // polled from the event loop => must never block
+        listener.set_nonblocking(true)?;
+        log::info!("Tuning server listening on {}", listener.local_addr()?);
+        Ok(TuningServer {
+            listener,
+            clients: Vec::new(),
+        })
+    }
+
+    // accepts new clients and handles pending commands. Returns true if a tunable was changed
+    pub fn poll(&mut self, tunables: &mut Tunables) -> bool {
+        self.accept_clients();
+        let mut changed = false;
+        self.clients.retain_mut(|client| {
+            match Self::handle_client(client, tunables, &mut changed) {
+                Ok(connected) => connected,
+                Err(e) => {
+                    log::warn!("Tuning client error: {}", e);
+                    false
+                }
+            }
+        });
+        changed
+    }
+
+    fn accept_clients(&mut self) {
+        loop {
+            match self.listener.accept() {
+                Ok((stream, address)) => {
+                    if let Err(e) = stream.set_nonblocking(true) {
+                        log::warn!("Could not set up tuning client {}: {}", address, e);
+                        continue;
+                    }
+                    log::info!("Tuning client connected: {}", address);
+                    self.clients.push(Client {
+                        stream,
+                        incoming: Vec::new(),
+                        outgoing: Vec::new(),
+                    });
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return,
+                Err(e) => {
+                    log::warn!("Could not accept tuning client: {}", e);
+                    return;
+                }
+            }
+        }
+    }
+
+    // returns false if the client disconnected
+    fn handle_client(
+        client: &mut Client,
+        tunables: &mut Tunables,
+        changed: &mut bool,
+    ) -> std::io::Result<bool> {
+        let mut chunk = [0; 256];
+        loop {
+            match client.stream.read(&mut chunk) {
+                Ok(0) => return Ok(false),
+                Ok(n) => client.incoming.extend_from_slice(&chunk[..n]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+
+        while let Some(end) = client.incoming.iter().position(|byte| *byte == b'\n') {
+            let line: Vec<u8> = client.incoming.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line);
+            let response = Self::handle_command(line.trim(), tunables, changed);
+            client.outgoing.extend_from_slice(response.as_bytes());
+        }
+        if client.incoming.len() > MAX_LINE_LENGTH {
+            log::warn!("Tuning client sent a too long line, disconnecting");
+            return Ok(false);
+        }
+        Self::flush_client(client)?;
+        if client.outgoing.len() > MAX_PENDING_OUTPUT {
+            log::warn!("Tuning client does not read its responses, disconnecting");
+            return Ok(false);
+        }
+        Ok(true)
+    }
+
+    // writes as much as the socket accepts without blocking, the rest is sent on the next poll
+    fn flush_client(client: &mut Client) -> std::io::Result<()> {
+        let mut written = 0;
+        while written < client.outgoing.len() {
+            match client.stream.write(&client.outgoing[written..]) {
+                Ok(0) => return Err(ErrorKind::WriteZero.into()),
+                Ok(n) => written += n,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        client.outgoing.drain(..written);
+        Ok(())
+    }
+
+    fn handle_command(line: &str, tunables: &mut Tunables, changed: &mut bool) -> String {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        match parts.as_slice() {
+            [] => String::new(),
+            ["list"] => {
+                let mut response = String::new();
+                for (name, tunable) in tunables.values.iter() {
+                    response.push_str(&format!(
+                        "{} {} {} {}\n",
+                        name, tunable.value, tunable.min, tunable.max
+                    ));
+                }
+                response.push_str("end\n");
+                response
+            }
+            ["get", name] => match tunables.get(name) {
+                Some(value) => format!("ok {} {}\n", name, value),
+                None => format!("err unknown tunable {}\n", name),
+            },
+            ["set", name, value] => {
+                let value = match value.parse::<f32>() {
+                    Ok(value) => value,
+                    Err(e) => return format!("err invalid value {}: {}\n", value, e),
+                };
+                match tunables.set(name, value) {
+                    Ok(value) => {
+                        log::debug!("Tunable {} set to {}", name, value);
+                        *changed = true;
+                        format!("ok {} {}\n", name, value)
+                    }
+                    Err(e) => format!("err {}\n", e),
+                }
+            }
+            _ => format!("err unknown command: {}\n", line),
+        }
+    }
+}
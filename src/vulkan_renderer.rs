@@ -1,56 +1,204 @@
+use crate::camera::Camera;
+use crate::camera::Frustum;
+use crate::frame_capture::FrameCapture;
+use crate::frame_capture::FrameCaptureSettings;
+use crate::minimap::Minimap;
+use crate::minimap::MinimapSettings;
+use crate::paths;
+use crate::profile_scope;
+use crate::profiler;
+use crate::vulkan_rs::check_color_space;
 use crate::vulkan_rs::debug;
+use crate::vulkan_rs::semaphore_submit_info;
+use crate::vulkan_rs::set_shader_override_dir;
 use crate::vulkan_rs::window;
+use crate::vulkan_rs::AdapterInfo;
 use crate::vulkan_rs::AllocatedBuffer;
 use crate::vulkan_rs::AllocatedImage;
 use crate::vulkan_rs::Allocator;
+use crate::vulkan_rs::Antialiasing;
 use crate::vulkan_rs::AppInfo;
+use crate::vulkan_rs::Asset;
+use crate::vulkan_rs::AssetError;
+use crate::vulkan_rs::AssetManager;
+use crate::vulkan_rs::AsyncUploader;
+use crate::vulkan_rs::BufferUsage;
+use crate::vulkan_rs::Cloth;
+use crate::vulkan_rs::ClothSettings;
+use crate::vulkan_rs::ClothSolver;
+use crate::vulkan_rs::ColorHistogram;
+use crate::vulkan_rs::ColorSpace;
 use crate::vulkan_rs::ComputePipeline;
+use crate::vulkan_rs::Deferred;
+use crate::vulkan_rs::DeletionQueue;
 use crate::vulkan_rs::DescriptorAllocator;
 use crate::vulkan_rs::DescriptorAllocatorGrowable;
 use crate::vulkan_rs::DescriptorLayoutBuilder;
 use crate::vulkan_rs::DescriptorSetLayout;
+use crate::vulkan_rs::DescriptorSetSlot;
 use crate::vulkan_rs::DescriptorWriter;
 use crate::vulkan_rs::Device;
+use crate::vulkan_rs::DeviceRequirements;
+use crate::vulkan_rs::Distortion;
+use crate::vulkan_rs::DistortionSettings;
+use crate::vulkan_rs::DrawBatches;
+use crate::vulkan_rs::EguiRenderer;
 use crate::vulkan_rs::EngineInfo;
-use crate::vulkan_rs::GPUDrawPushConstants;
-use crate::vulkan_rs::GraphicsPipeline;
-use crate::vulkan_rs::GraphicsPipelineBuilder;
+use crate::vulkan_rs::Environment;
+use crate::vulkan_rs::Fxaa;
+use crate::vulkan_rs::GPULight;
+use crate::vulkan_rs::GpuPreference;
+use crate::vulkan_rs::GpuProfiler;
+use crate::vulkan_rs::GraphEstimate;
+use crate::vulkan_rs::GraphPass;
+use crate::vulkan_rs::GraphSubmission;
+use crate::vulkan_rs::Handle;
+use crate::vulkan_rs::Histogram;
+use crate::vulkan_rs::ImageLayers;
+use crate::vulkan_rs::ImageUsage;
 use crate::vulkan_rs::ImmediateCommandData;
 use crate::vulkan_rs::Instance;
+use crate::vulkan_rs::Light;
+use crate::vulkan_rs::LoadState;
+use crate::vulkan_rs::Material;
+use crate::vulkan_rs::MaterialCache;
+use crate::vulkan_rs::MaterialConstants;
+use crate::vulkan_rs::MaterialDescription;
+use crate::vulkan_rs::MaterialPass;
+use crate::vulkan_rs::MaterialTexture;
 use crate::vulkan_rs::MeshAsset;
+use crate::vulkan_rs::Model;
+use crate::vulkan_rs::Oit;
+use crate::vulkan_rs::OptionalFeatures;
+use crate::vulkan_rs::OutputColorSpace;
+use crate::vulkan_rs::PassQueue;
+use crate::vulkan_rs::PassTiming;
 use crate::vulkan_rs::PhysicalDeviceSelector;
 use crate::vulkan_rs::PoolSizeRatio;
-use crate::vulkan_rs::Sampler;
+use crate::vulkan_rs::PostProcess;
+use crate::vulkan_rs::PostProcessSettings;
+use crate::vulkan_rs::PresentModePreference;
+use crate::vulkan_rs::Primitive;
+use crate::vulkan_rs::PushConstants;
+use crate::vulkan_rs::RayQueryShadows;
+use crate::vulkan_rs::RayTracedAo;
+use crate::vulkan_rs::RayTracedAoSettings;
+use crate::vulkan_rs::RayTracingScene;
+use crate::vulkan_rs::RenderGraph;
+use crate::vulkan_rs::RenderPath;
+use crate::vulkan_rs::SamplerSettings;
+use crate::vulkan_rs::Scene;
+use crate::vulkan_rs::ShaderCompiler;
 use crate::vulkan_rs::ShaderModule;
+use crate::vulkan_rs::Skinning;
+use crate::vulkan_rs::Skybox;
 use crate::vulkan_rs::Surface;
 use crate::vulkan_rs::Swapchain;
+use crate::vulkan_rs::Taa;
+use crate::vulkan_rs::Texture;
+use crate::vulkan_rs::Transparency;
+use crate::vulkan_rs::Upscaler;
 use crate::vulkan_rs::Version;
+use crate::vulkan_rs::VulkanError;
 use ash::vk;
 use nalgebra_glm as glm;
 use raw_window_handle::HasDisplayHandle;
 use std::path::Path;
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::sync::Mutex;
+use std::time::Duration;
 use winit::window::Window;
 
+// object space -> world space of a submitted mesh
+pub type Transform = glm::Mat4;
+
+// a mesh of a loaded scene or file, can be submitted any number of times per frame
+// meshes of models are looked up on submit => handles keep working across hot reloads
+#[derive(Clone)]
+pub struct MeshHandle {
+    name: String,
+    source: MeshSource,
+}
+
+#[derive(Clone)]
+enum MeshSource {
+    // created by the renderer, never reloaded
+    Mesh(Arc<MeshAsset>),
+    // index into the meshes of the current version of the model
+    Model(Handle<Model>, usize),
+}
+
+impl MeshHandle {
+    fn new(mesh: Arc<MeshAsset>) -> Self {
+        MeshHandle {
+            name: mesh.name().to_string(),
+            source: MeshSource::Mesh(mesh),
+        }
+    }
+
+    fn of_model(model: Handle<Model>, idx: usize, mesh: &MeshAsset) -> Self {
+        MeshHandle {
+            name: mesh.name().to_string(),
+            source: MeshSource::Model(model, idx),
+        }
+    }
+
+    // the name when the handle was created, a reload can rename the mesh
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+#[derive(Clone)]
+pub struct MaterialHandle(Arc<Material>);
+
+struct RenderObject {
+    mesh: Arc<MeshAsset>,
+    transform: glm::Mat4,
+    // None => the materials of the surfaces
+    material: Option<Arc<Material>>,
+}
+
 pub struct FrameData {
     device: Arc<Device>,
     command_pool: vk::CommandPool,
     command_buffer: vk::CommandBuffer,
+    // on the async compute family, the graphics family if there is none
+    compute_command_pool: vk::CommandPool,
+    // the graph submissions after the first one, indexed like PassQueue::index
+    // reset and reused every frame
+    submission_command_buffers: [Vec<vk::CommandBuffer>; 2],
     image_available_semaphore: vk::Semaphore,
     result_presentable_semaphore: vk::Semaphore,
-    in_flight_fence: vk::Fence,
     frame_descriptors: DescriptorAllocatorGrowable,
     gpu_scene_data_buffer: AllocatedBuffer,
+    // same as the scene data, but seen from the minimap camera
+    minimap_scene_data_buffer: AllocatedBuffer,
+    light_buffer: AllocatedBuffer,
+    // flushed once the frame timeline says that this slot finished
+    deletion_queue: DeletionQueue,
+    // submissions of the frame that used this slot => kept alive until it finished
+    render_objects: Vec<RenderObject>,
+    // graph passes of the frame that used this slot, logged if the gpu hangs
+    pass_names: Vec<String>,
 }
 
 impl FrameData {
-    fn new(device: Arc<Device>, allocator: Arc<Mutex<Allocator>>) -> FrameData {
-        let command_pool = device.create_command_pool();
-        let command_buffer = device.create_command_buffer(command_pool);
-        let image_available_semaphore = device.create_semaphore();
-        let result_presentable_semaphore = device.create_semaphore();
-        let in_flight_fence = device.create_fence(vk::FenceCreateFlags::SIGNALED);
+    fn new(
+        device: Arc<Device>,
+        allocator: Arc<Mutex<Allocator>>,
+        slot: usize,
+    ) -> Result<FrameData, VulkanError> {
+        // TODO: handles created before a failing call are leaked. Only matters if we want to
+        // recover from errors during setup
+        let command_pool = device.create_command_pool()?;
+        let command_buffer = device.create_command_buffer(command_pool)?;
+        device.set_object_name(command_buffer, &format!("frame {} command buffer", slot));
+        let compute_command_pool =
+            device.create_command_pool_for_family(device.get_compute_queue_idx())?;
+        let image_available_semaphore = device.create_semaphore()?;
+        let result_presentable_semaphore = device.create_semaphore()?;
         let frame_sizes = vec![
             PoolSizeRatio {
                 descriptor_type: vk::DescriptorType::STORAGE_IMAGE,
@@ -70,28 +218,56 @@ impl FrameData {
             },
         ];
 
-        let mut frame_descriptors =
-            DescriptorAllocatorGrowable::new(device.clone(), frame_sizes, 1000);
-        frame_descriptors.init_pool();
+        let mut frame_descriptors = DescriptorAllocatorGrowable::new(
+            device.clone(),
+            &format!("frame {} descriptors", slot),
+            frame_sizes,
+            1000,
+        );
+        // the pools are cleared every frame => the usage of one frame is what we have to fit
+        frame_descriptors.set_auto_tune(true);
+        frame_descriptors.init_pool()?;
 
         let gpu_scene_data_buffer = AllocatedBuffer::new(
             device.clone(),
-            allocator,
+            allocator.clone(),
             "GPU Scene Data Buffer",
             vk::BufferUsageFlags::UNIFORM_BUFFER,
             std::mem::size_of::<GPUSceneData>() as u64,
             gpu_allocator::MemoryLocation::CpuToGpu,
-        );
-        FrameData {
+        )?;
+        let minimap_scene_data_buffer = AllocatedBuffer::new(
+            device.clone(),
+            allocator.clone(),
+            "Minimap Scene Data Buffer",
+            vk::BufferUsageFlags::UNIFORM_BUFFER,
+            std::mem::size_of::<GPUSceneData>() as u64,
+            gpu_allocator::MemoryLocation::CpuToGpu,
+        )?;
+        let light_buffer = AllocatedBuffer::new(
+            device.clone(),
+            allocator,
+            "Light Buffer",
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            (MAX_LIGHTS * std::mem::size_of::<GPULight>()) as u64,
+            gpu_allocator::MemoryLocation::CpuToGpu,
+        )?;
+        Ok(FrameData {
             device,
             command_pool,
             command_buffer,
+            compute_command_pool,
+            submission_command_buffers: [Vec::new(), Vec::new()],
             image_available_semaphore,
             result_presentable_semaphore,
-            in_flight_fence,
             frame_descriptors,
             gpu_scene_data_buffer,
-        }
+            minimap_scene_data_buffer,
+            light_buffer,
+            deletion_queue: DeletionQueue::new(),
+            render_objects: Vec::new(),
+            pass_names: Vec::new(),
+        })
     }
 }
 
@@ -99,11 +275,11 @@ impl Drop for FrameData {
     fn drop(&mut self) {
         log::debug!("Dropping FrameData");
         self.device.destroy_command_pool(self.command_pool);
+        self.device.destroy_command_pool(self.compute_command_pool);
         self.device
             .destroy_semaphore(self.image_available_semaphore);
         self.device
             .destroy_semaphore(self.result_presentable_semaphore);
-        self.device.destroy_fence(self.in_flight_fence);
     }
 }
 
@@ -116,6 +292,12 @@ pub struct GPUSceneData {
     ambient_color: glm::Vec4,
     sunlight_dir: glm::Vec4,
     sunlight_color: glm::Vec4,
+    // w is unused
+    camera_position: glm::Vec4,
+    // world space => clip space of the shadow map
+    light_view_proj: glm::Mat4,
+    // x = number of lights in the light buffer
+    light_count: glm::UVec4,
 }
 
 impl Default for GPUSceneData {
@@ -127,14 +309,170 @@ impl Default for GPUSceneData {
             ambient_color: glm::vec4(0.2, 0.2, 0.2, 1.0),
             sunlight_dir: glm::vec4(0.0, 0.0, -1.0, 10.0),
             sunlight_color: glm::vec4(1.0, 1.0, 1.0, 1.0),
+            camera_position: glm::vec4(0.0, 0.0, 0.0, 1.0),
+            light_view_proj: glm::identity(),
+            light_count: glm::UVec4::zeros(),
+        }
+    }
+}
+
+// lights submitted after this are ignored for the frame
+const MAX_LIGHTS: usize = 256;
+const SHADOW_MAP_SIZE: u32 = 2048;
+const MIN_VULKAN_VERSION: Version = Version {
+    major: 1,
+    minor: 3,
+    patch: 0,
+};
+// gpu name or "0x<vendor id>[:0x<device id>]", see GpuPreference::parse
+const GPU_OVERRIDE_ENV: &str = "LEX_ENGINE_GPU";
+// half the size of the area around the camera that receives shadows, in world units
+const SHADOW_RADIUS: f32 = 20.0;
+// casters this far in front of/behind the camera along the light direction are still rendered
+const SHADOW_DEPTH_RANGE: f32 = 50.0;
+
+#[derive(Debug, Clone)]
+pub struct RendererConfig {
+    pub enable_validation: bool,
+    // which gpu to use if several are suitable, GPU_OVERRIDE_ENV replaces its filters
+    pub gpu: GpuPreference,
+    pub scene_path: PathBuf,
+    // falls back to FIFO if the preferred mode is not supported
+    pub present_mode: PresentModePreference,
+    // dither the draw image before it is copied to the 8 bit swapchain => no banding in gradients
+    pub dithering: bool,
+    // sharp upscaling when rendering at render_scale < 1, bilinear blit otherwise
+    pub upscaling: bool,
+    // fxaa smooths edges of the final image, taa accumulates jittered frames of the scene
+    pub antialiasing: Antialiasing,
+    // deferred => opaque surfaces go through a g-buffer, can only be chosen at startup
+    pub render_path: RenderPath,
+    // sorted alpha blending or weighted blended order independent transparency
+    pub transparency: Transparency,
+    // hardware ray traced ambient occlusion (VK_KHR_ray_tracing_pipeline) and sun shadows
+    // traced with ray queries in the deferred path (VK_KHR_ray_query), prefers gpus that support
+    // them and runs without them if the selected one doesnt
+    pub ray_tracing: bool,
+    // take exclusive control of the monitor while the window is fullscreen (windows only)
+    // => lower latency, falls back to borderless if not available
+    pub exclusive_fullscreen: bool,
+    // 2 or 3, more frames => more latency but the cpu can run further ahead of the gpu
+    // limited to the number of swapchain images at runtime
+    pub frames_in_flight: usize,
+    // recompile changed files in ./shaders with glslc and rebuild their pipelines between frames
+    pub shader_hot_reload: bool,
+    // .spv files in this directory replace the embedded shaders (embed-shaders feature)
+    // hot reloading compiles the sources in this directory, ./shaders if it is not set
+    pub shader_dir: Option<PathBuf>,
+    // reload changed gltf files and textures while running, also turns on shader_hot_reload
+    pub asset_hot_reload: bool,
+    // log textures and swapchain formats whose color space doesnt match how they are used
+    pub color_validation: bool,
+    // a frame that takes longer on the gpu is reported as a hang
+    pub gpu_timeout: Duration,
+    // scRGB or HDR10 swapchain if the display supports it, srgb otherwise
+    pub hdr_output: bool,
+}
+
+impl Default for RendererConfig {
+    fn default() -> Self {
+        RendererConfig {
+            enable_validation: cfg!(debug_assertions),
+            gpu: GpuPreference::default(),
+            scene_path: paths::asset_path("assets/basicmesh.glb"),
+            present_mode: PresentModePreference::Mailbox,
+            dithering: true,
+            upscaling: true,
+            antialiasing: Antialiasing::None,
+            render_path: RenderPath::Forward,
+            transparency: Transparency::Sorted,
+            ray_tracing: false,
+            exclusive_fullscreen: false,
+            frames_in_flight: 2,
+            shader_hot_reload: false,
+            shader_dir: None,
+            asset_hot_reload: false,
+            color_validation: false,
+            gpu_timeout: Duration::from_secs(2),
+            hdr_output: false,
+        }
+    }
+}
+
+impl RendererConfig {
+    // fallback if the renderer can not be created with this config
+    // only what every vulkan 1.3 gpu supports and the shaders that were shipped
+    pub fn safe_mode(&self) -> Self {
+        RendererConfig {
+            enable_validation: false,
+            // the preferred gpu might be the one that is missing features
+            gpu: GpuPreference::default(),
+            scene_path: self.scene_path.clone(),
+            present_mode: PresentModePreference::Fifo,
+            dithering: false,
+            upscaling: false,
+            antialiasing: Antialiasing::None,
+            render_path: RenderPath::Forward,
+            transparency: Transparency::Sorted,
+            ray_tracing: false,
+            exclusive_fullscreen: false,
+            frames_in_flight: 2,
+            shader_hot_reload: false,
+            shader_dir: None,
+            asset_hot_reload: false,
+            color_validation: false,
+            // a slow gpu is not a missing feature
+            gpu_timeout: self.gpu_timeout,
+            hdr_output: false,
+        }
+    }
+
+    // GPU_OVERRIDE_ENV wins over the config, e.g. to pick a gpu without touching the game
+    fn gpu_preference(&self) -> GpuPreference {
+        match std::env::var(GPU_OVERRIDE_ENV) {
+            Ok(value) if !value.is_empty() => {
+                log::info!("Using {}={:?} to select the GPU", GPU_OVERRIDE_ENV, value);
+                GpuPreference {
+                    prefer_display_adapter: self.gpu.prefer_display_adapter,
+                    ..GpuPreference::parse(&value)
+                }
+            }
+            _ => self.gpu.clone(),
         }
     }
 }
 
-pub const MAX_FRAMES_IN_FLIGHT: usize = 2;
+fn instance_info() -> (AppInfo, EngineInfo) {
+    let app_info = AppInfo {
+        name: "Vulkan Renderer".to_string(),
+        version: Version {
+            major: 1,
+            minor: 0,
+            patch: 0,
+        },
+    };
+    let engine_info = EngineInfo {
+        name: "Vulkan Engine".to_string(),
+        version: Version {
+            major: 1,
+            minor: 0,
+            patch: 0,
+        },
+        vulkan_version: MIN_VULKAN_VERSION,
+    };
+    (app_info, engine_info)
+}
+
+// all gpus with vulkan support, without a window => a launcher can list them before the
+// renderer exists and pass the chosen one as RendererConfig::gpu with GpuPreference::adapter
+// gpus below vulkan 1.3 or without the required features are listed too, the selector skips them
+pub fn enumerate_adapters() -> Result<Vec<AdapterInfo>, VulkanError> {
+    let (app_info, engine_info) = instance_info();
+    let instance = Instance::new(app_info, engine_info, &[], &[], &[], None)?;
+    crate::vulkan_rs::enumerate_adapters(&instance)
+}
 
 pub struct VulkanRenderer {
-    #[allow(dead_code)]
     allocator: Arc<Mutex<Allocator>>,
     #[allow(dead_code)]
     instance: Arc<Instance>,
@@ -147,38 +485,108 @@ pub struct VulkanRenderer {
     device: Arc<Device>,
     swapchain: Swapchain,
     frame_data: Vec<FrameData>,
+    // frames that are actually used, at most frame_data.len()
+    frames_in_flight: usize,
+    // number of submitted frames
     frame_index: usize,
+    // frame n signals n + 1 when it is done => replaces a fence per frame slot
+    frame_timeline: vk::Semaphore,
+    // indexed like PassQueue::index, every graph submission signals the next value of its queue
+    queue_timelines: [vk::Semaphore; 2],
+    queue_timeline_values: [u64; 2],
+    gpu_timeout: Duration,
+    gpu_profiler: GpuProfiler,
+    // of the last recorded frame
+    draw_calls: u32,
     draw_image: AllocatedImage,
     depth_image: AllocatedImage,
+    // nearest sampler => the fragment shader does the filtering (pcf)
+    shadow_map: MaterialTexture,
     descriptor_allocator: DescriptorAllocator,
     draw_image_descriptor: vk::DescriptorSet,
     draw_image_descriptor_layout: DescriptorSetLayout,
     gradient_pipeline: ComputePipeline,
+    dither_pipeline: ComputePipeline,
+    dithering: bool,
+    upscaler: Upscaler,
+    post_process: PostProcess,
+    histogram: Histogram,
+    draw_batches: DrawBatches,
+    graph_estimate: GraphEstimate,
+    upscaling: bool,
+    fxaa: Fxaa,
+    taa: Taa,
+    antialiasing: Antialiasing,
+    // None => forward rendering
+    deferred: Option<Deferred>,
+    oit: Oit,
+    transparency: Transparency,
+    // None => ray tracing was not requested or the device doesnt support it
+    ray_tracing_scene: Option<RayTracingScene>,
+    ray_traced_ao: Option<RayTracedAo>,
+    // None => forward path or no ray query support
+    ray_query_shadows: Option<RayQueryShadows>,
+    distortion: Distortion,
+    skybox: Skybox,
+    // drawn onto the swapchain image => not affected by render scale, dithering or captures
+    egui_renderer: EguiRenderer,
+    // 0 is the strongest, every +1 halves the sharpening
+    upscale_sharpness: f32,
     immediate_command_data: ImmediateCommandData,
-    mesh_pipeline: GraphicsPipeline,
-    test_meshes: Vec<MeshAsset>,
+    // shared with the loading threads of the asset manager
+    uploader: Arc<AsyncUploader>,
+    assets: AssetManager,
+    material_cache: MaterialCache,
+    // image based lighting, uniform white until an .hdr file is loaded
+    environment: Environment,
+    scene: Scene,
+    // the model the scene was built from and its version, a new version (finished loading or
+    // hot reloaded) rebuilds the scene
+    scene_model: Option<(Handle<Model>, u32)>,
+    cloth_solver: ClothSolver,
+    cloths: Vec<Cloth>,
+    skinning: Skinning,
+    // dropped textures replace the materials of all surfaces
+    material_override: Option<Arc<Material>>,
+    // texture of the material override and its version, same as scene_model
+    override_texture: Option<(Handle<Texture>, u32)>,
+    // cubemap of set_skybox and its version, None => the environment or the gradient is shown
+    skybox_texture: Option<(Handle<Texture>, u32)>,
     resize_swapchain: Option<winit::dpi::LogicalSize<u32>>,
+    // set when acquire/present report that the swapchain does not match the surface anymore
+    // or when the present mode was changed
+    swapchain_out_of_date: bool,
+    window: Arc<Window>,
     render_scale: f32,
     scene_data: GPUSceneData,
+    camera: Camera,
     scene_data_descriptor_layout: DescriptorSetLayout,
     white_texture: AllocatedImage,
     black_texture: AllocatedImage,
     grey_texture: AllocatedImage,
-    error_checkerboard_texture: AllocatedImage,
-    default_sampler_linear: Sampler,
-    default_sampler_nearest: Sampler,
-    single_image_descriptor_layout: DescriptorSetLayout,
+    frame_capture: Option<FrameCapture>,
+    minimap: Option<Minimap>,
+    // submitted for the next frame only
+    lights: Vec<Light>,
+    draw_list: Vec<RenderObject>,
+    shader_compiler: Option<ShaderCompiler>,
+    // why the renderer runs in safe mode, None if the requested config worked
+    startup_error: Option<String>,
 }
 
 impl VulkanRenderer {
-    pub fn new(window: Arc<Window>) -> VulkanRenderer {
-        let raw_display_handle = window
-            .display_handle()
-            .expect("I hope window has a display handle")
-            .as_raw();
-        let mut required_extensions = window::get_required_instance_extensions(raw_display_handle);
-        let (required_layers, debug_messenger_create_info) = if cfg!(debug_assertions) {
-            log::info!("Debug mode enabled, enabling validation layers");
+    pub fn new(window: Arc<Window>, config: RendererConfig) -> Result<VulkanRenderer, VulkanError> {
+        let shader_hot_reload = config.shader_hot_reload || config.asset_hot_reload;
+        // the compiler writes next to the sources => those have to win over the embedded ones
+        let shader_dir = config
+            .shader_dir
+            .clone()
+            .or_else(|| shader_hot_reload.then(|| paths::asset_path("shaders")));
+        set_shader_override_dir(shader_dir.clone());
+        let raw_display_handle = window.display_handle()?.as_raw();
+        let mut required_extensions = window::get_required_instance_extensions(raw_display_handle)?;
+        let (required_layers, debug_messenger_create_info) = if config.enable_validation {
+            log::info!("Enabling validation layers");
             let required_debug_extensions = debug::get_required_extensions();
             required_extensions.extend(required_debug_extensions);
             (
@@ -186,64 +594,87 @@ impl VulkanRenderer {
                 Some(debug::DebugMessenger::fill_create_info()),
             )
         } else {
-            log::info!("Debug mode disabled, not enabling validation layers");
+            log::info!("Not enabling validation layers");
             (vec![], None)
         };
         log::debug!("Required extensions: {:?}", required_extensions);
         log::debug!("Required layers: {:?}", required_layers);
-        let min_vulkan_version = Version {
-            major: 1,
-            minor: 3,
-            patch: 0,
-        };
-        let app_info = AppInfo {
-            name: "Vulkan Renderer".to_string(),
-            version: Version {
-                major: 1,
-                minor: 0,
-                patch: 0,
-            },
-        };
-        let engine_info = EngineInfo {
-            name: "Vulkan Engine".to_string(),
-            version: Version {
-                major: 1,
-                minor: 0,
-                patch: 0,
-            },
-            vulkan_version: min_vulkan_version,
-        };
+        let (app_info, engine_info) = instance_info();
         let instance = Instance::new(
             app_info,
             engine_info,
             &required_layers,
             &required_extensions,
+            &window::get_optional_instance_extensions(raw_display_handle),
             debug_messenger_create_info,
-        );
-        let debug_messenger = if cfg!(debug_assertions) {
+        )?;
+        let debug_messenger = if config.enable_validation {
             log::info!("Creating debug messenger");
-            Some(debug::DebugMessenger::new(instance.clone()))
+            Some(debug::DebugMessenger::new(instance.clone())?)
         } else {
             None
         };
-        let surface = window::Surface::new(instance.clone(), window.clone());
-
-        let physical_device_selector = PhysicalDeviceSelector::new(min_vulkan_version);
-        let physical_device = physical_device_selector.select(instance.clone(), &surface);
+        let surface = window::Surface::new(instance.clone(), window.clone())?;
+
+        let mut device_requirements = DeviceRequirements::default();
+        // needs VK_KHR_get_surface_capabilities2 on the instance
+        if config.exclusive_fullscreen
+            && instance.is_extension_enabled(ash::khr::get_surface_capabilities2::NAME)
+        {
+            device_requirements
+                .optional_extensions
+                .push(ash::ext::full_screen_exclusive::NAME);
+        }
+        device_requirements.optional_features = OptionalFeatures {
+            ray_tracing_pipeline: config.ray_tracing,
+            ray_query: config.ray_tracing,
+        };
 
-        let device = Device::new(instance.clone(), &physical_device, &surface);
+        let physical_device_selector =
+            PhysicalDeviceSelector::new(MIN_VULKAN_VERSION).prefer(config.gpu_preference());
+        let selected_device =
+            physical_device_selector.select(instance.clone(), &surface, &device_requirements)?;
+        let physical_device = selected_device.physical_device;
+        log::info!(
+            "Optional device features granted: {:?}",
+            selected_device.granted_features
+        );
+        let device = Device::new(
+            instance.clone(),
+            &physical_device,
+            &surface,
+            &device_requirements,
+        )?;
 
         let swapchain = surface.create_swapchain(
             &physical_device,
             device.clone(),
             window.inner_size().to_logical(window.scale_factor()),
-        );
-
-        let allocator = Allocator::new(device.clone());
-        let mut frame_data = Vec::with_capacity(MAX_FRAMES_IN_FLIGHT);
-        for _ in 0..MAX_FRAMES_IN_FLIGHT {
-            frame_data.push(FrameData::new(device.clone(), allocator.clone()));
+            config.present_mode,
+            config.exclusive_fullscreen,
+            config.hdr_output,
+        )?;
+
+        let allocator = Allocator::new(device.clone())?;
+        let frame_count = config.frames_in_flight.clamp(2, 3);
+        if frame_count != config.frames_in_flight {
+            log::warn!(
+                "{} frames in flight are not supported, using {}",
+                config.frames_in_flight,
+                frame_count
+            );
         }
+        let mut frame_data = Vec::with_capacity(frame_count);
+        for slot in 0..frame_count {
+            frame_data.push(FrameData::new(device.clone(), allocator.clone(), slot)?);
+        }
+        let frames_in_flight = Self::usable_frames_in_flight(frame_count, &swapchain);
+        let frame_timeline = device.create_timeline_semaphore(0)?;
+        let queue_timelines = [
+            device.create_timeline_semaphore(0)?,
+            device.create_timeline_semaphore(0)?,
+        ];
+        let gpu_profiler = GpuProfiler::new(device.clone(), frame_data.len())?;
 
         let draw_extent = vk::Extent3D {
             width: window.inner_size().width,
@@ -251,80 +682,181 @@ impl VulkanRenderer {
             depth: 1,
         };
         let draw_image =
-            AllocatedImage::new_draw_color_image(device.clone(), allocator.clone(), draw_extent);
+            AllocatedImage::new_draw_color_image(device.clone(), allocator.clone(), draw_extent)?;
         let (
             draw_image_descriptor,
             draw_image_descriptor_layout,
             descriptor_allocator,
             scene_data_descriptor_layout,
-            single_image_descriptor_layout,
-        ) = VulkanRenderer::init_descriptors(device.clone(), &draw_image);
+        ) = VulkanRenderer::init_descriptors(device.clone(), &draw_image)?;
 
         let depth_image =
-            AllocatedImage::new_depth_image(device.clone(), allocator.clone(), draw_extent);
+            AllocatedImage::new_depth_image(device.clone(), allocator.clone(), draw_extent)?;
 
-        let gradient_shader = ShaderModule::new(device.clone(), "shaders/gradient_color_comp.spv");
+        let gradient_shader = ShaderModule::new(device.clone(), "shaders/gradient_color_comp.spv")?;
         let gradient_pipeline = ComputePipeline::new(
             device.clone(),
             &[draw_image_descriptor_layout.layout()],
             gradient_shader,
-        );
+        )?;
+
+        let dither_shader = ShaderModule::new(device.clone(), "shaders/dither_comp.spv")?;
+        let dither_pipeline = ComputePipeline::new(
+            device.clone(),
+            &[draw_image_descriptor_layout.layout()],
+            dither_shader,
+        )?;
+
+        let upscaler = Upscaler::new(
+            device.clone(),
+            allocator.clone(),
+            &descriptor_allocator,
+            &draw_image,
+        )?;
+        let post_process = PostProcess::new(
+            device.clone(),
+            allocator.clone(),
+            &descriptor_allocator,
+            &draw_image,
+        )?;
+        let fxaa = Fxaa::new(
+            device.clone(),
+            allocator.clone(),
+            &descriptor_allocator,
+            &draw_image,
+        )?;
+        let taa = Taa::new(
+            device.clone(),
+            allocator.clone(),
+            &descriptor_allocator,
+            &draw_image,
+            &depth_image,
+        )?;
+        let histogram = Histogram::new(
+            device.clone(),
+            allocator.clone(),
+            &draw_image,
+            frame_data.len(),
+        )?;
+        let draw_batches = DrawBatches::new(device.clone(), allocator.clone(), frame_data.len())?;
+
+        let distortion = Distortion::new(
+            device.clone(),
+            allocator.clone(),
+            &descriptor_allocator,
+            &draw_image,
+            depth_image.format(),
+            &scene_data_descriptor_layout,
+        )?;
+
+        let skybox = Skybox::new(device.clone(), draw_image.format(), depth_image.format())?;
+
+        let immediate_command_data = ImmediateCommandData::new(device.clone())?;
+        let uploader = Arc::new(AsyncUploader::new(device.clone())?);
+        let mut assets = AssetManager::new(device.clone(), allocator.clone(), uploader.clone());
+        assets.set_hot_reload(config.asset_hot_reload);
+
+        let (white_texture, black_texture, grey_texture, error_checkerboard_texture) =
+            VulkanRenderer::init_default_textures(device.clone(), allocator.clone(), &uploader)?;
 
-        let mesh_frag_shader = ShaderModule::new(device.clone(), "shaders/tex_image_frag.spv");
-        let mesh_vert_shader = ShaderModule::new(device.clone(), "shaders/triangle_mesh_vert.spv");
-        let push_constants = vk::PushConstantRange {
-            stage_flags: vk::ShaderStageFlags::VERTEX,
-            offset: 0,
-            size: std::mem::size_of::<GPUDrawPushConstants>() as u32,
+        let mut material_cache = MaterialCache::new(
+            device.clone(),
+            allocator.clone(),
+            &uploader,
+            &scene_data_descriptor_layout,
+            Arc::new(error_checkerboard_texture),
+            draw_image.format(),
+            depth_image.format(),
+        )?;
+        material_cache.set_color_validation(config.color_validation);
+        // hdr swapchains are linear or pq encoded on purpose
+        if config.color_validation && !swapchain.color_space().is_hdr() {
+            check_color_space("swapchain", swapchain.format(), ColorSpace::Srgb);
+        }
+
+        let deferred = match config.render_path {
+            RenderPath::Forward => None,
+            RenderPath::Deferred => Some(Deferred::new(
+                device.clone(),
+                allocator.clone(),
+                &descriptor_allocator,
+                &draw_image,
+                &depth_image,
+                &scene_data_descriptor_layout,
+                material_cache.texture_table_layout(),
+            )?),
         };
-        let mesh_pipeline_layout_info = vk::PipelineLayoutCreateInfo {
-            s_type: vk::StructureType::PIPELINE_LAYOUT_CREATE_INFO,
-            p_next: std::ptr::null(),
-            flags: vk::PipelineLayoutCreateFlags::empty(),
-            set_layout_count: 1,
-            p_set_layouts: &single_image_descriptor_layout.layout(),
-            push_constant_range_count: 1,
-            p_push_constant_ranges: &push_constants,
-            ..Default::default()
+
+        let oit = Oit::new(
+            device.clone(),
+            allocator.clone(),
+            &descriptor_allocator,
+            &draw_image,
+            depth_image.format(),
+            &scene_data_descriptor_layout,
+            material_cache.texture_table_layout(),
+        )?;
+
+        let egui_renderer = EguiRenderer::new(
+            device.clone(),
+            allocator.clone(),
+            &material_cache,
+            swapchain.format(),
+            frame_data.len(),
+        )?;
+
+        let shadow_map = MaterialTexture {
+            image: Arc::new(AllocatedImage::new_shadow_map(
+                device.clone(),
+                allocator.clone(),
+                SHADOW_MAP_SIZE,
+            )?),
+            sampler: material_cache.sampler(
+                SamplerSettings::new(vk::Filter::NEAREST, vk::Filter::NEAREST).mipmap_mode(None),
+            )?,
         };
-        let mesh_pipeline_layout = device.create_pipeline_layout(&mesh_pipeline_layout_info);
-        let mesh_pipeline = GraphicsPipelineBuilder::new()
-            .set_layout(mesh_pipeline_layout)
-            .set_shaders(&mesh_frag_shader, &mesh_vert_shader)
-            .set_input_topology(vk::PrimitiveTopology::TRIANGLE_LIST)
-            .set_polygon_mode(vk::PolygonMode::FILL)
-            .set_cull_mode(vk::CullModeFlags::NONE, vk::FrontFace::CLOCKWISE)
-            .disable_multisampling()
-            .disable_blending()
-            .enable_depth_test(vk::TRUE, vk::CompareOp::GREATER_OR_EQUAL)
-            .set_color_attachment_format(draw_image.format())
-            .set_depth_format(depth_image.format())
-            .build_pipeline(device.clone());
-
-        let immediate_command_data = ImmediateCommandData::new(device.clone());
-
-        let test_meshes = MeshAsset::load_gltf(
+
+        let environment = Environment::new_uniform(
             device.clone(),
             allocator.clone(),
             &immediate_command_data,
-            Path::new("./assets/basicmesh.glb"),
-            true,
-        )
-        .unwrap();
+            &uploader,
+            &mut material_cache,
+        )?;
 
-        let (white_texture, black_texture, grey_texture, error_checkerboard_texture) =
-            VulkanRenderer::init_default_textures(
+        // loaded in the background, a broken scene file is not fatal => just render without meshes
+        let scene_model = Some((assets.load_model(&config.scene_path), 0));
+
+        let cloth_solver = ClothSolver::new(device.clone(), allocator.clone())?;
+        let skinning = Skinning::new(device.clone(), allocator.clone(), frame_count)?;
+        let ray_traced_ao = if device.supports_ray_tracing() {
+            Some(RayTracedAo::new(
                 device.clone(),
                 allocator.clone(),
-                &immediate_command_data,
-            );
-
-        let default_sampler_linear =
-            Sampler::new(device.clone(), vk::Filter::LINEAR, vk::Filter::LINEAR);
-        let default_sampler_nearest =
-            Sampler::new(device.clone(), vk::Filter::NEAREST, vk::Filter::NEAREST);
+                frame_count,
+            )?)
+        } else {
+            if config.ray_tracing {
+                log::warn!("Ray tracing is not supported by the device, ray traced ao is disabled");
+            }
+            None
+        };
+        // the shadow mask is only read by the lighting pass of the deferred path
+        let ray_query_shadows = if device.supports_ray_query() && deferred.is_some() {
+            Some(RayQueryShadows::new(device.clone(), frame_count)?)
+        } else {
+            if config.ray_tracing {
+                log::warn!(
+                    "Ray query shadows need ray query support and the deferred render path, \
+                     using the shadow map"
+                );
+            }
+            None
+        };
+        let ray_tracing_scene = (ray_traced_ao.is_some() || ray_query_shadows.is_some())
+            .then(|| RayTracingScene::new(device.clone(), allocator.clone(), frame_count));
 
-        VulkanRenderer {
+        Ok(VulkanRenderer {
             surface,
             allocator,
             instance,
@@ -333,28 +865,82 @@ impl VulkanRenderer {
             device,
             swapchain,
             frame_data,
+            frames_in_flight,
             frame_index: 0,
+            frame_timeline,
+            queue_timelines,
+            queue_timeline_values: [0, 0],
+            gpu_timeout: config.gpu_timeout,
+            gpu_profiler,
+            draw_calls: 0,
             draw_image,
             depth_image,
+            shadow_map,
             descriptor_allocator,
             draw_image_descriptor_layout,
             draw_image_descriptor,
             gradient_pipeline,
+            dither_pipeline,
+            dithering: config.dithering,
+            upscaler,
+            post_process,
+            histogram,
+            draw_batches,
+            graph_estimate: GraphEstimate::default(),
+            upscaling: config.upscaling,
+            fxaa,
+            taa,
+            antialiasing: config.antialiasing,
+            deferred,
+            oit,
+            transparency: config.transparency,
+            ray_tracing_scene,
+            ray_traced_ao,
+            ray_query_shadows,
+            distortion,
+            skybox,
+            egui_renderer,
+            upscale_sharpness: 0.2,
             immediate_command_data,
-            mesh_pipeline,
-            test_meshes,
+            uploader,
+            assets,
+            material_cache,
+            environment,
+            scene: Scene::default(),
+            scene_model,
+            cloth_solver,
+            cloths: Vec::new(),
+            skinning,
+            material_override: None,
+            override_texture: None,
+            skybox_texture: None,
             resize_swapchain: None,
+            swapchain_out_of_date: false,
+            window,
             render_scale: 1.0,
             scene_data_descriptor_layout,
             scene_data: GPUSceneData::default(),
+            camera: Camera::new(),
             white_texture,
             black_texture,
             grey_texture,
-            error_checkerboard_texture,
-            default_sampler_linear,
-            default_sampler_nearest,
-            single_image_descriptor_layout,
-        }
+            frame_capture: None,
+            minimap: None,
+            lights: Vec::new(),
+            draw_list: Vec::new(),
+            shader_compiler: shader_dir
+                .filter(|_| shader_hot_reload)
+                .map(ShaderCompiler::new),
+            startup_error: None,
+        })
+    }
+
+    pub fn startup_error(&self) -> Option<&str> {
+        self.startup_error.as_deref()
+    }
+
+    pub fn set_startup_error(&mut self, error: String) {
+        self.startup_error = Some(error);
     }
 
     #[allow(clippy::identity_op)]
@@ -370,13 +956,16 @@ impl VulkanRenderer {
     fn init_default_textures(
         device: Arc<Device>,
         allocator: Arc<Mutex<Allocator>>,
-        immediate_command: &ImmediateCommandData,
-    ) -> (
-        AllocatedImage,
-        AllocatedImage,
-        AllocatedImage,
-        AllocatedImage,
-    ) {
+        uploader: &AsyncUploader,
+    ) -> Result<
+        (
+            AllocatedImage,
+            AllocatedImage,
+            AllocatedImage,
+            AllocatedImage,
+        ),
+        VulkanError,
+    > {
         let white = Self::pack_unorm4x8([1.0, 1.0, 1.0, 1.0]);
         let white_texture = AllocatedImage::new_texture(
             &[white],
@@ -390,8 +979,8 @@ impl VulkanRenderer {
                 depth: 1,
             },
             false,
-            immediate_command,
-        );
+            uploader,
+        )?;
 
         let black = Self::pack_unorm4x8([0.0, 0.0, 0.0, 1.0]);
         let black_texture = AllocatedImage::new_texture(
@@ -406,8 +995,8 @@ impl VulkanRenderer {
                 depth: 1,
             },
             false,
-            immediate_command,
-        );
+            uploader,
+        )?;
 
         let grey = Self::pack_unorm4x8([0.67, 0.67, 0.67, 1.0]);
         let grey_texture = AllocatedImage::new_texture(
@@ -422,8 +1011,8 @@ impl VulkanRenderer {
                 depth: 1,
             },
             false,
-            immediate_command,
-        );
+            uploader,
+        )?;
 
         const SIZE: usize = 16;
         let magenta = Self::pack_unorm4x8([1.0, 0.0, 1.0, 1.0]);
@@ -445,33 +1034,45 @@ impl VulkanRenderer {
                 depth: 1,
             },
             false,
-            immediate_command,
-        );
-        (
+            uploader,
+        )?;
+        Ok((
             white_texture,
             black_texture,
             grey_texture,
             error_checkerboard_texture,
-        )
+        ))
     }
 
     fn init_descriptors(
         device: Arc<Device>,
         draw_image: &AllocatedImage,
-    ) -> (
-        vk::DescriptorSet,
-        DescriptorSetLayout,
-        DescriptorAllocator,
-        DescriptorSetLayout,
-        DescriptorSetLayout,
-    ) {
-        let ratio_sizes = vec![PoolSizeRatio {
-            descriptor_type: vk::DescriptorType::STORAGE_IMAGE,
-            ratio: 1.0,
-        }];
+    ) -> Result<
+        (
+            vk::DescriptorSet,
+            DescriptorSetLayout,
+            DescriptorAllocator,
+            DescriptorSetLayout,
+        ),
+        VulkanError,
+    > {
+        // the compute passes with fixed inputs (upscaler, distortion, post processing, taa) and
+        // the g-buffer of the deferred path allocate from this pool too, most of them use an
+        // input and an output image
+        let ratio_sizes = vec![
+            PoolSizeRatio {
+                descriptor_type: vk::DescriptorType::STORAGE_IMAGE,
+                ratio: 3.0,
+            },
+            // g-buffer + shadow mask, oit, taa
+            PoolSizeRatio {
+                descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                ratio: 1.0,
+            },
+        ];
 
         let mut descriptor_allocator = DescriptorAllocator::new(device.clone());
-        descriptor_allocator.init_pool(10, &ratio_sizes);
+        descriptor_allocator.init_pool(32, &ratio_sizes)?;
 
         let mut builder = DescriptorLayoutBuilder::new();
         builder.add_binding(
@@ -480,10 +1081,10 @@ impl VulkanRenderer {
             vk::ShaderStageFlags::COMPUTE,
         );
         let draw_image_descriptor_layout =
-            builder.build(device.clone(), vk::DescriptorSetLayoutCreateFlags::empty());
+            builder.build(device.clone(), vk::DescriptorSetLayoutCreateFlags::empty())?;
 
         let draw_image_descriptor =
-            descriptor_allocator.allocate(draw_image_descriptor_layout.layout());
+            descriptor_allocator.allocate(draw_image_descriptor_layout.layout())?;
 
         let mut writer = DescriptorWriter::new();
         writer.add_storage_image(0, draw_image.image_view());
@@ -495,193 +1096,1271 @@ impl VulkanRenderer {
             vk::DescriptorType::UNIFORM_BUFFER,
             vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
         );
-        let scene_data_descriptor_layout =
-            builder.build(device.clone(), vk::DescriptorSetLayoutCreateFlags::empty());
-
-        let mut builder = DescriptorLayoutBuilder::new();
+        // shadow map
         builder.add_binding(
-            0,
+            1,
             vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
             vk::ShaderStageFlags::FRAGMENT,
         );
-        let single_image_descriptor_layout =
-            builder.build(device.clone(), vk::DescriptorSetLayoutCreateFlags::empty());
+        // point/spot lights
+        builder.add_binding(
+            2,
+            vk::DescriptorType::STORAGE_BUFFER,
+            vk::ShaderStageFlags::FRAGMENT,
+        );
+        // irradiance map, specular map, brdf lut
+        for binding in 3..6 {
+            builder.add_binding(
+                binding,
+                vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                vk::ShaderStageFlags::FRAGMENT,
+            );
+        }
+        let scene_data_descriptor_layout =
+            builder.build(device.clone(), vk::DescriptorSetLayoutCreateFlags::empty())?;
 
-        (
+        Ok((
             draw_image_descriptor,
             draw_image_descriptor_layout,
             descriptor_allocator,
             scene_data_descriptor_layout,
-            single_image_descriptor_layout,
-        )
+        ))
     }
 
-    fn get_current_frame(&self) -> &FrameData {
-        &self.frame_data[self.frame_index % MAX_FRAMES_IN_FLIGHT]
+    // more frames than swapchain images cant be in flight => acquire would block anyway
+    fn usable_frames_in_flight(frame_count: usize, swapchain: &Swapchain) -> usize {
+        let usable = frame_count.min(swapchain.image_count()).max(1);
+        if usable < frame_count {
+            log::warn!(
+                "Only {} swapchain images => using {} instead of {} frames in flight",
+                swapchain.image_count(),
+                usable,
+                frame_count
+            );
+        }
+        usable
     }
 
-    fn get_current_frame_mut(&mut self) -> &mut FrameData {
-        &mut self.frame_data[self.frame_index % MAX_FRAMES_IN_FLIGHT]
+    // takes over finished background loads and rebuilds what uses a new version of an asset
+    // assets that cant be used are logged and skipped => a broken file never stops the renderer
+    fn update_assets(&mut self) {
+        for retired in self.assets.update(&mut self.material_cache) {
+            self.defer_destruction(retired);
+        }
+        if let Some((handle, version)) = self.scene_model {
+            let current_version = self.assets.version(handle);
+            // failed loads keep the current scene, the manager logged the error
+            if let Some(model) = self
+                .assets
+                .get(handle)
+                .filter(|_| current_version != version)
+            {
+                let model = model.clone();
+                self.scene_model = Some((handle, current_version));
+                if model.meshes().is_empty() {
+                    log::warn!("{:?} does not contain any meshes", model.path());
+                } else {
+                    match Scene::from_model(self.device.clone(), self.allocator.clone(), &model) {
+                        Ok(scene) => {
+                            // old meshes might still be used by frames in flight
+                            let old_scene = std::mem::replace(&mut self.scene, scene);
+                            self.defer_destruction(old_scene);
+                        }
+                        Err(e) => log::error!("Could not build scene of {:?}: {}", model.path(), e),
+                    }
+                }
+            }
+        }
+        if let Some((handle, version)) = self.override_texture {
+            let current_version = self.assets.version(handle);
+            if let Some(texture) = self
+                .assets
+                .get(handle)
+                .filter(|_| current_version != version)
+            {
+                let texture = texture.clone();
+                self.override_texture = Some((handle, current_version));
+                if let Err(e) = self.set_override_texture(&texture) {
+                    log::error!(
+                        "Could not create material for {:?}: {}",
+                        self.assets.path(handle),
+                        e
+                    );
+                }
+            }
+        }
+        if let Some((handle, version)) = self.skybox_texture {
+            let current_version = self.assets.version(handle);
+            if let Some(texture) = self
+                .assets
+                .get(handle)
+                .filter(|_| current_version != version)
+            {
+                let image = texture.image().clone();
+                self.skybox_texture = Some((handle, current_version));
+                let result = self
+                    .material_cache
+                    .sampler(SamplerSettings::new(vk::Filter::LINEAR, vk::Filter::LINEAR))
+                    .and_then(|sampler| {
+                        self.set_skybox_cubemap(Some(MaterialTexture { image, sampler }))
+                    });
+                if let Err(e) = result {
+                    log::error!(
+                        "Could not show {:?} as skybox: {}",
+                        self.assets.path(handle),
+                        e
+                    );
+                }
+            }
+        }
     }
 
-    pub fn draw(&mut self) {
-        if let Some(logical_size) = self.resize_swapchain.take() {
-            self.device.wait_idle();
-            self.swapchain.recreate(&self.physical_device, logical_size);
+    fn set_override_texture(&mut self, texture: &Texture) -> Result<(), VulkanError> {
+        // the bindless table only holds 2d views
+        if texture.image().layers() != ImageLayers::Single {
+            log::error!("Only 2d textures can replace the materials, cubemaps are for the skybox");
+            return Ok(());
         }
-        // MAX_IN_FLIGHT_FRAMES is 2 => we wait for the frame before the previous one to finish.
-        self.device
-            .wait_for_fence(&self.get_current_frame().in_flight_fence, 1_000_000_000); //1E9 ns -> 1s
-        self.device
-            .reset_fence(&self.get_current_frame().in_flight_fence);
-        self.get_current_frame_mut().frame_descriptors.clear_pools();
-
-        let current_frame = self.get_current_frame();
-
-        let (presentation_image_index, presentation_image) = self
-            .swapchain
-            .acquire_next_image(current_frame.image_available_semaphore, 1_000_000_000);
-        let presentation_extent = self.swapchain.extent();
-
-        let command_buffer = current_frame.command_buffer;
-        // commands are finished -> can reset command buffer
-        self.device.reset_command_buffer(command_buffer);
+        let sampler = self
+            .material_cache
+            .sampler(SamplerSettings::new(vk::Filter::LINEAR, vk::Filter::LINEAR))?;
+        let material = self.material_cache.create_material(MaterialDescription {
+            base_color: Some(MaterialTexture {
+                image: texture.image().clone(),
+                sampler,
+            }),
+            ..Default::default()
+        })?;
+        if let Some(old_material) = self.material_override.replace(material) {
+            self.defer_destruction(old_material);
+        }
+        Ok(())
+    }
 
-        // draw into image with higher precision before presenting results -> more accurate colors
-        let draw_image = self.draw_image.image();
-        let draw_extent = self.draw_image.extent();
-        let draw_extent = vk::Extent2D {
-            width: (std::cmp::min(draw_extent.width, self.swapchain.extent().width) as f32
-                * self.render_scale) as u32,
-            height: (std::cmp::min(draw_extent.height, self.swapchain.extent().height) as f32
-                * self.render_scale) as u32,
+    fn reload_changed_shaders(&mut self) {
+        let Some(shader_compiler) = self.shader_compiler.as_mut() else {
+            return;
         };
-        let draw_image_view = self.draw_image.image_view();
+        for file_name in shader_compiler.poll_changes() {
+            // the old pipeline stays in use if the new one cant be built
+            if let Err(e) = self.reload_shader(&file_name) {
+                log::error!("Could not reload {}: {}", file_name, e);
+            }
+        }
+    }
 
-        // start recording commands
-        self.device
-            .begin_command_buffer(command_buffer, vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
-        self.device.transition_image_layout(
-            command_buffer,
-            draw_image,
-            vk::ImageLayout::UNDEFINED,
-            vk::ImageLayout::GENERAL,
+    fn reload_shader(&mut self, file_name: &str) -> Result<(), VulkanError> {
+        match file_name {
+            "gradient_color_comp.spv" | "dither_comp.spv" => {
+                let shader =
+                    ShaderModule::new(self.device.clone(), &format!("shaders/{}", file_name))?;
+                let pipeline = ComputePipeline::new(
+                    self.device.clone(),
+                    &[self.draw_image_descriptor_layout.layout()],
+                    shader,
+                )?;
+                let old_pipeline = if file_name == "dither_comp.spv" {
+                    std::mem::replace(&mut self.dither_pipeline, pipeline)
+                } else {
+                    std::mem::replace(&mut self.gradient_pipeline, pipeline)
+                };
+                self.defer_destruction(old_pipeline);
+            }
+            "mesh_vert.spv" | "mesh_frag.spv" | "shadow_vert.spv" => {
+                let old_pipelines = self.material_cache.rebuild_pipelines(
+                    &self.scene_data_descriptor_layout,
+                    self.draw_image.format(),
+                    self.depth_image.format(),
+                )?;
+                self.defer_destruction(old_pipelines);
+            }
+            _ => {
+                log::warn!("{} has no hot reload yet, restart to use it", file_name);
+                return Ok(());
+            }
+        }
+        log::info!("Reloaded pipelines using {}", file_name);
+        Ok(())
+    }
+
+    // waits until the frame timeline reached value. A frame that takes longer than gpu_timeout
+    // is logged with the passes in flight and gets one more timeout to finish the queue
+    // => VulkanError::GpuHang if it still didnt, the caller can skip the frame and try again
+    fn wait_for_frame(&self, value: u64) -> Result<(), VulkanError> {
+        let timeout = self.gpu_timeout.as_nanos() as u64;
+        match self
+            .device
+            .wait_semaphore(self.frame_timeline, value, timeout)
+        {
+            Err(VulkanError::Vk(vk::Result::TIMEOUT)) => {}
+            result => return result,
+        }
+        let finished = self
+            .device
+            .get_semaphore_counter_value(self.frame_timeline)?;
+        let passes = self.in_flight_passes(finished);
+        log::warn!(
+            "GPU did not finish frame {} within {:?}, in flight: {}",
+            value.saturating_sub(1),
+            self.gpu_timeout,
+            passes.join(", ")
         );
+        // the last submitted frame => the whole queue has to drain
+        match self
+            .device
+            .wait_semaphore(self.frame_timeline, self.frame_index as u64, timeout)
+        {
+            Ok(()) => {
+                log::info!("GPU caught up after the timeout");
+                Ok(())
+            }
+            Err(VulkanError::Vk(vk::Result::TIMEOUT)) => Err(VulkanError::GpuHang {
+                frame: finished,
+                passes,
+            }),
+            Err(e) => Err(e),
+        }
+    }
 
-        self.draw_background(command_buffer, draw_extent);
+    // "frame: pass" for every frame after the last finished one
+    fn in_flight_passes(&self, finished: u64) -> Vec<String> {
+        let mut passes = Vec::new();
+        for frame in finished as usize..self.frame_index {
+            let mut names = self.frame_data[frame % self.frames_in_flight]
+                .pass_names
+                .clone();
+            // sort steps and per mesh passes share a name
+            names.dedup();
+            passes.extend(names.iter().map(|name| format!("{}: {}", frame, name)));
+        }
+        passes
+    }
 
-        self.device.transition_image_layout(
-            command_buffer,
-            draw_image,
-            vk::ImageLayout::GENERAL,
-            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
-        );
+    fn frame_slot(&self) -> usize {
+        self.frame_index % self.frames_in_flight
+    }
 
-        self.device.transition_image_layout(
-            command_buffer,
-            self.depth_image.image(),
-            vk::ImageLayout::UNDEFINED,
-            vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL,
-        );
+    // frames up to the last submitted one might still use the resource
+    // => the slot of the last submitted frame is the last one that is waited for
+    fn defer_destruction<T: 'static>(&mut self, resource: T) {
+        let last_slot = (self.frame_index + self.frames_in_flight - 1) % self.frames_in_flight;
+        self.frame_data[last_slot]
+            .deletion_queue
+            .push_resource(resource);
+    }
 
-        self.mesh_pipeline.begin_drawing(
-            command_buffer,
-            draw_image_view,
-            self.depth_image.image_view(),
-            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
-            vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL,
-            draw_extent,
-            None,
-        );
+    fn get_current_frame(&self) -> &FrameData {
+        &self.frame_data[self.frame_slot()]
+    }
 
-        let scene_data = GPUSceneData::default();
-        self.get_current_frame_mut()
-            .gpu_scene_data_buffer
-            .copy_from_slice(&[scene_data], 0);
-        let descriptor_set = self.frame_data[self.frame_index % MAX_FRAMES_IN_FLIGHT]
+    fn get_current_frame_mut(&mut self) -> &mut FrameData {
+        let frame_slot = self.frame_slot();
+        &mut self.frame_data[frame_slot]
+    }
+
+    fn allocate_scene_descriptor_set(
+        &mut self,
+        scene_data_buffer: vk::Buffer,
+    ) -> Result<vk::DescriptorSet, VulkanError> {
+        let frame_slot = self.frame_slot();
+        let scene_descriptor_set = self.frame_data[frame_slot]
             .frame_descriptors
-            .allocate(self.scene_data_descriptor_layout.layout());
+            .allocate(&self.scene_data_descriptor_layout)?;
         let mut writer = DescriptorWriter::new();
         writer.add_uniform_buffer(
             0,
-            self.get_current_frame_mut().gpu_scene_data_buffer.buffer(),
+            scene_data_buffer,
             std::mem::size_of::<GPUSceneData>() as u64,
             0,
         );
-        writer.update_descriptor_set(&self.device, descriptor_set);
-
-        let image_set = self.frame_data[self.frame_index % MAX_FRAMES_IN_FLIGHT]
-            .frame_descriptors
-            .allocate(self.single_image_descriptor_layout.layout());
-        let mut writer = DescriptorWriter::new();
         writer.add_image(
-            0,
-            self.error_checkerboard_texture.image_view(),
-            self.default_sampler_nearest.sampler(),
+            1,
+            self.shadow_map.image.image_view(),
+            self.shadow_map.sampler.sampler(),
             vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
             vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
         );
-        writer.update_descriptor_set(&self.device, image_set);
-
-        self.device.cmd_bind_descriptor_sets(
-            command_buffer,
-            self.mesh_pipeline.layout(),
-            vk::PipelineBindPoint::GRAPHICS,
-            &[image_set],
-        );
-        self.mesh_pipeline
-            .draw(command_buffer, draw_extent, &self.test_meshes[2]);
-
-        self.mesh_pipeline.end_drawing(command_buffer);
-
-        self.device.transition_image_layout(
-            command_buffer,
-            draw_image,
-            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
-            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+        writer.add_buffer(
+            2,
+            self.frame_data[frame_slot].light_buffer.buffer(),
+            (MAX_LIGHTS * std::mem::size_of::<GPULight>()) as u64,
+            0,
+            vk::DescriptorType::STORAGE_BUFFER,
         );
+        let environment_maps = [
+            self.environment.irradiance_map(),
+            self.environment.specular_map(),
+            self.environment.brdf_lut(),
+        ];
+        for (binding, (image_view, sampler)) in (3..).zip(environment_maps) {
+            writer.add_image(
+                binding,
+                image_view,
+                sampler,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            );
+        }
+        writer.update_descriptor_set(&self.device, scene_descriptor_set);
+        Ok(scene_descriptor_set)
+    }
 
-        self.device.transition_image_layout(
-            command_buffer,
-            presentation_image,
-            vk::ImageLayout::UNDEFINED,
-            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
-        );
+    // every surface of the scene, the cloths and the submitted objects => batched indirect draws
+    // has to be called after the view projection of the frame was set
+    fn batch_draws(&mut self, frame_slot: usize) -> Result<(), VulkanError> {
+        let material_override = self.material_override.as_ref();
+        let frustum = Frustum::from_view_proj(&self.scene_data.view_proj);
+        let scene_instances = self
+            .scene
+            .mesh_instances()
+            .map(|(mesh, world_matrix)| (mesh, world_matrix, None));
+        let cloth_instances = self
+            .cloths
+            .iter()
+            .map(|cloth| (cloth.mesh(), cloth.world_transform(), None));
+        let submitted = self.frame_data[frame_slot]
+            .render_objects
+            .iter()
+            .map(|object| {
+                (
+                    object.mesh.as_ref(),
+                    &object.transform,
+                    object.material.as_ref(),
+                )
+            });
+        for (mesh, world_matrix, instance_material) in
+            scene_instances.chain(cloth_instances).chain(submitted)
+        {
+            for surface in mesh.surfaces() {
+                let material = material_override
+                    .or(instance_material)
+                    .unwrap_or(surface.material());
+                let in_view = frustum.intersects(&surface.bounds().transformed(world_matrix));
+                self.draw_batches
+                    .add(mesh, surface, material, world_matrix, in_view);
+            }
+        }
+        self.draw_batches.prepare(frame_slot, self.camera.position)
+    }
 
-        self.device.copy_image_to_image(
-            command_buffer,
+    pub fn draw(&mut self) -> Result<(), VulkanError> {
+        // taken right away => a skipped frame drops its submissions instead of piling them up
+        let draw_list = std::mem::take(&mut self.draw_list);
+        self.reload_changed_shaders();
+        // minimized window => a swapchain with a zero extent is not allowed, skip the frame
+        let window_size = self.window.inner_size();
+        if window_size.width == 0 || window_size.height == 0 {
+            return Ok(());
+        }
+        if self.swapchain_out_of_date && self.resize_swapchain.is_none() {
+            self.resize_swapchain = Some(window_size.to_logical(self.window.scale_factor()));
+        }
+        if let Some(logical_size) = self.resize_swapchain.take() {
+            self.device.wait_idle()?;
+            match self.swapchain.recreate(&self.physical_device, logical_size) {
+                Ok(true) => {}
+                // minimized or not mapped yet => keep trying on the next frames
+                Ok(false) => {
+                    self.swapchain_out_of_date = true;
+                    return Ok(());
+                }
+                // the surface can be briefly unusable while the compositor reconfigures it
+                Err(VulkanError::Vk(
+                    e @ (vk::Result::ERROR_OUT_OF_DATE_KHR
+                    | vk::Result::ERROR_NATIVE_WINDOW_IN_USE_KHR),
+                )) => {
+                    log::warn!("Could not recreate swapchain ({}), retrying next frame", e);
+                    self.swapchain_out_of_date = true;
+                    return Ok(());
+                }
+                Err(e) => return Err(e),
+            }
+            self.swapchain_out_of_date = false;
+            // the gpu is idle => changing the number of frames is safe, slots that are not used
+            // anymore would never flush their queue otherwise
+            for frame in self.frame_data.iter_mut() {
+                frame.deletion_queue.flush();
+            }
+            self.frames_in_flight =
+                Self::usable_frames_in_flight(self.frame_data.len(), &self.swapchain);
+            if let Some(frame_capture) = self.frame_capture.as_mut() {
+                frame_capture.resize(self.swapchain.extent())?;
+            }
+        }
+        // with 2 frames in flight we wait for the frame before the previous one to finish
+        // (the frame that used this slot last)
+        let slot_finished = (self.frame_index + 1).saturating_sub(self.frames_in_flight);
+        {
+            profile_scope!("wait for gpu");
+            self.wait_for_frame(slot_finished as u64)?;
+        }
+        self.get_current_frame_mut().deletion_queue.flush();
+        self.uploader.collect()?;
+        self.update_assets();
+
+        let Some(acquired_image) = self.swapchain.acquire_next_image(
+            self.get_current_frame().image_available_semaphore,
+            1_000_000_000,
+        )?
+        else {
+            // nothing was submitted => we can just try again next frame with a new swapchain
+            log::debug!("Swapchain out of date while acquiring image");
+            self.swapchain_out_of_date = true;
+            return Ok(());
+        };
+        // suboptimal images can still be presented => recreate after this frame
+        if acquired_image.suboptimal {
+            self.swapchain_out_of_date = true;
+        }
+        let presentation_image_index = acquired_image.index;
+        let presentation_image = acquired_image.image;
+
+        self.get_current_frame_mut().frame_descriptors.clear_pools();
+        let frame_slot = self.frame_slot();
+        // the previous submissions of this slot are done => replacing them resets the list
+        self.frame_data[frame_slot].render_objects = draw_list;
+        if let Some(frame_capture) = self.frame_capture.as_mut() {
+            frame_capture.collect(frame_slot);
+        }
+        self.histogram.collect(frame_slot);
+        self.egui_renderer.prepare(
+            frame_slot,
+            self.swapchain.format(),
+            &mut self.material_cache,
+            &self.uploader,
+            &mut self.frame_data[frame_slot].deletion_queue,
+        )?;
+
+        let current_frame = self.get_current_frame();
+        let presentation_extent = self.swapchain.extent();
+
+        let command_buffer = current_frame.command_buffer;
+        let image_available_semaphore = current_frame.image_available_semaphore;
+        // commands are finished -> can reset command buffer
+        self.device.reset_command_buffer(command_buffer)?;
+
+        // draw into image with higher precision before presenting results -> more accurate colors
+        let draw_image = self.draw_image.image();
+        let draw_extent = self.draw_image.extent();
+        // part of the draw image that ends up on the screen
+        let output_extent = vk::Extent2D {
+            width: std::cmp::min(draw_extent.width, self.swapchain.extent().width),
+            height: std::cmp::min(draw_extent.height, self.swapchain.extent().height),
+        };
+        let draw_extent = vk::Extent2D {
+            width: (output_extent.width as f32 * self.render_scale) as u32,
+            height: (output_extent.height as f32 * self.render_scale) as u32,
+        };
+        let upscale = self.upscaling && draw_extent != output_extent;
+        // without the upscaler the present copy does the scaling
+        let final_extent = if upscale { output_extent } else { draw_extent };
+        let draw_image_view = self.draw_image.image_view();
+
+        let view = self.camera.view_matrix();
+        let aspect_ratio = draw_extent.width as f32 / draw_extent.height as f32;
+        // taa => everything is rasterized with a different sub pixel offset every frame
+        let proj = if self.antialiasing == Antialiasing::Taa {
+            let jitter = self.taa.jitter(draw_extent);
+            let unjittered_view_proj = self.camera.projection_matrix(aspect_ratio) * view;
+            self.taa.prepare(&unjittered_view_proj, jitter, draw_extent);
+            self.camera.jittered_projection_matrix(aspect_ratio, jitter)
+        } else {
+            self.taa.reset();
+            self.camera.projection_matrix(aspect_ratio)
+        };
+        let camera_position = self.camera.position;
+        self.scene_data.camera_position =
+            glm::vec4(camera_position.x, camera_position.y, camera_position.z, 1.0);
+        self.scene_data.view = view;
+        self.scene_data.proj = proj;
+        self.scene_data.view_proj = proj * view;
+        self.scene_data.light_view_proj = self.light_view_proj();
+        if self.lights.len() > MAX_LIGHTS {
+            log::warn!(
+                "{} lights submitted, only the first {} are rendered",
+                self.lights.len(),
+                MAX_LIGHTS
+            );
+            self.lights.truncate(MAX_LIGHTS);
+        }
+        let gpu_lights: Vec<GPULight> =
+            self.lights.drain(..).map(|light| (&light).into()).collect();
+        self.frame_data[frame_slot]
+            .light_buffer
+            .copy_from_slice(&gpu_lights, 0);
+        self.scene_data.light_count = glm::vec4(gpu_lights.len() as u32, 0, 0, 0);
+        self.batch_draws(frame_slot)?;
+        let scene_data = self.scene_data;
+        self.get_current_frame_mut()
+            .gpu_scene_data_buffer
+            .copy_from_slice(&[scene_data], 0);
+        let scene_descriptor_set = self.allocate_scene_descriptor_set(
+            self.frame_data[frame_slot].gpu_scene_data_buffer.buffer(),
+        )?;
+        let minimap_descriptor_set = match self.minimap.as_ref() {
+            Some(minimap) => {
+                // same lighting as the main view, only the camera is different
+                let mut minimap_scene_data = scene_data;
+                let eye = minimap.eye_position(camera_position);
+                minimap_scene_data.view = minimap.view_matrix(camera_position);
+                minimap_scene_data.proj = minimap.projection_matrix();
+                minimap_scene_data.view_proj = minimap_scene_data.proj * minimap_scene_data.view;
+                minimap_scene_data.camera_position = glm::vec4(eye.x, eye.y, eye.z, 1.0);
+                self.frame_data[frame_slot]
+                    .minimap_scene_data_buffer
+                    .copy_from_slice(&[minimap_scene_data], 0);
+                Some(
+                    self.allocate_scene_descriptor_set(
+                        self.frame_data[frame_slot]
+                            .minimap_scene_data_buffer
+                            .buffer(),
+                    )?,
+                )
+            }
+            None => None,
+        };
+
+        // everything until the command buffer is ended
+        let record_scope = profiler::scope("record", file!(), line!());
+        let cloth_steps = self.cloth_solver.take_steps();
+        for cloth in self.cloths.iter_mut() {
+            self.cloth_solver.prepare(cloth, frame_slot, cloth_steps);
+        }
+        self.skinning.prepare(frame_slot, &self.scene)?;
+        if let Some(ray_tracing_scene) = self.ray_tracing_scene.as_mut() {
+            // skinned meshes would need a blas rebuild every frame => they dont occlude
+            let frame = &mut self.frame_data[frame_slot];
+            let instances = self.scene.static_mesh_instances().chain(
+                frame
+                    .render_objects
+                    .iter()
+                    .map(|object| (object.mesh.as_ref(), &object.transform)),
+            );
+            ray_tracing_scene.prepare(frame_slot, instances, &mut frame.deletion_queue)?;
+        }
+        if let Some(ao) = self.ray_traced_ao.as_mut() {
+            ao.advance_frame();
+        }
+
+        // start recording commands
+        self.device
+            .begin_command_buffer(command_buffer, vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT)?;
+        self.gpu_profiler.begin_frame(command_buffer, frame_slot);
+        let profiler = &self.gpu_profiler;
+
+        // contents of the draw/depth image from the last frame are not needed => UNDEFINED
+        let mut graph = RenderGraph::new();
+        let draw = graph.import_image(
+            "draw image",
             draw_image,
+            vk::ImageAspectFlags::COLOR,
+            vk::ImageLayout::UNDEFINED,
+        );
+        let depth = graph.import_image(
+            "depth image",
+            self.depth_image.image(),
+            vk::ImageAspectFlags::DEPTH,
+            vk::ImageLayout::UNDEFINED,
+        );
+        let shadow = graph.import_image(
+            "shadow map",
+            self.shadow_map.image.image(),
+            vk::ImageAspectFlags::DEPTH,
+            vk::ImageLayout::UNDEFINED,
+        );
+        let presentation = graph.import_image(
+            "swapchain image",
             presentation_image,
-            draw_extent,
-            presentation_extent,
+            vk::ImageAspectFlags::COLOR,
+            vk::ImageLayout::UNDEFINED,
+        );
+        graph.export_image(presentation, vk::ImageLayout::PRESENT_SRC_KHR);
+        graph.wait_before_use(
+            presentation,
+            semaphore_submit_info(
+                image_available_semaphore,
+                0,
+                vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+            ),
+        );
+        let draw_format = self.draw_image.format();
+        graph.set_image_size(draw, draw_extent, draw_format);
+        graph.set_image_size(depth, draw_extent, self.depth_image.format());
+        graph.set_image_size(
+            shadow,
+            vk::Extent2D {
+                width: SHADOW_MAP_SIZE,
+                height: SHADOW_MAP_SIZE,
+            },
+            self.shadow_map.image.format(),
+        );
+        graph.set_image_size(presentation, presentation_extent, self.swapchain.format());
+
+        let gradient_pipeline = &self.gradient_pipeline;
+        let draw_image_descriptor = self.draw_image_descriptor;
+        // the timestamps are on the graphics queue, the compute queue might not support them
+        let profile_background = !self.device.has_async_compute();
+        graph.add_pass(
+            GraphPass::new("background")
+                .image(draw, ImageUsage::StorageWrite)
+                .async_compute()
+                .record(move |command_buffer| {
+                    let _scope =
+                        profile_background.then(|| profiler.scope(command_buffer, "background"));
+                    gradient_pipeline.execute_compute(
+                        command_buffer,
+                        &[draw_image_descriptor],
+                        draw_extent,
+                    )
+                }),
         );
 
-        self.device.transition_image_layout(
-            command_buffer,
-            presentation_image,
-            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
-            vk::ImageLayout::PRESENT_SRC_KHR,
+        let cloth_solver = &self.cloth_solver;
+        let cloths = &self.cloths;
+        let mut deformed_vertices: Vec<_> = cloths
+            .iter()
+            .map(|cloth| cloth_solver.add_passes(&mut graph, cloth, frame_slot, cloth_steps))
+            .collect();
+        deformed_vertices.extend(self.skinning.add_passes(&mut graph, &self.scene));
+
+        let indirect_buffers =
+            self.draw_batches
+                .add_passes(&mut graph, camera_position, self.scene_data.view_proj);
+
+        let device = &self.device;
+        let material_cache = &self.material_cache;
+        let draw_batches = &self.draw_batches;
+        let scene = &self.scene;
+        let depth_image_view = self.depth_image.image_view();
+        let skybox = &self.skybox;
+        let view_proj = self.scene_data.view_proj;
+        let shadow_map_view = self.shadow_map.image.image_view();
+        // cloth and deformed vertices are written by compute shaders and read through the
+        // device address
+        let shadow_pass = deformed_vertices
+            .iter()
+            .fold(GraphPass::new("shadow"), |pass, vertices| {
+                pass.buffer(*vertices, BufferUsage::StorageRead)
+            });
+        graph.add_pass(
+            shadow_pass
+                .image(shadow, ImageUsage::DepthAttachment)
+                .record(move |command_buffer| {
+                    let pipeline = material_cache.shadow_pipeline();
+                    pipeline.begin_depth_only(
+                        command_buffer,
+                        shadow_map_view,
+                        vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL,
+                        vk::Extent2D {
+                            width: SHADOW_MAP_SIZE,
+                            height: SHADOW_MAP_SIZE,
+                        },
+                    );
+                    device.cmd_bind_descriptor_set(
+                        command_buffer,
+                        pipeline.layout(),
+                        vk::PipelineBindPoint::GRAPHICS,
+                        DescriptorSetSlot::Scene,
+                        scene_descriptor_set,
+                    );
+                    // transparent surfaces dont cast shadows
+                    draw_batches.record(command_buffer, pipeline, MaterialPass::Opaque);
+                    pipeline.end_drawing(command_buffer);
+                }),
         );
 
-        self.device.end_command_buffer(command_buffer);
+        let minimap = self.minimap.as_ref().zip(minimap_descriptor_set);
+        let minimap_target = minimap.map(|(minimap, minimap_descriptor_set)| {
+            let target = graph.import_image(
+                "minimap",
+                minimap.image().image(),
+                vk::ImageAspectFlags::COLOR,
+                vk::ImageLayout::UNDEFINED,
+            );
+            let minimap_depth = graph.import_image(
+                "minimap depth",
+                minimap.depth_image().image(),
+                vk::ImageAspectFlags::DEPTH,
+                vk::ImageLayout::UNDEFINED,
+            );
+            // sampled as ui texture after the frame
+            graph.export_image(target, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+            graph.set_image_size(target, minimap.extent(), minimap.image().format());
+            graph.set_image_size(
+                minimap_depth,
+                minimap.extent(),
+                minimap.depth_image().format(),
+            );
+            let minimap_pass = deformed_vertices
+                .iter()
+                .fold(GraphPass::new("minimap"), |pass, vertices| {
+                    pass.buffer(*vertices, BufferUsage::StorageRead)
+                });
+            let minimap_pass = indirect_buffers
+                .iter()
+                .fold(minimap_pass, |pass, commands| {
+                    pass.buffer(*commands, BufferUsage::Indirect)
+                });
+            let marker_rects = minimap.marker_rects(camera_position, self.camera.forward());
+            graph.add_pass(
+                minimap_pass
+                    .image(target, ImageUsage::ColorAttachment)
+                    .image(minimap_depth, ImageUsage::DepthAttachment)
+                    .image(shadow, ImageUsage::Sampled)
+                    .record(move |command_buffer| {
+                        let opaque_pipeline = material_cache.pipeline(MaterialPass::Opaque);
+                        opaque_pipeline.begin_drawing(
+                            command_buffer,
+                            minimap.image().image_view(),
+                            minimap.depth_image().image_view(),
+                            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                            vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL,
+                            minimap.extent(),
+                            Some(minimap.background_color()),
+                        );
+                        for pass in [MaterialPass::Opaque, MaterialPass::Transparent] {
+                            let pipeline = material_cache.pipeline(pass);
+                            pipeline.bind(command_buffer);
+                            device.cmd_bind_descriptor_set(
+                                command_buffer,
+                                pipeline.layout(),
+                                vk::PipelineBindPoint::GRAPHICS,
+                                DescriptorSetSlot::Scene,
+                                minimap_descriptor_set,
+                            );
+                            device.cmd_bind_descriptor_set(
+                                command_buffer,
+                                pipeline.layout(),
+                                vk::PipelineBindPoint::GRAPHICS,
+                                DescriptorSetSlot::Material,
+                                material_cache.texture_descriptor_set(),
+                            );
+                            draw_batches.record(command_buffer, pipeline, pass);
+                        }
+                        // markers are plain squares on top of the map
+                        for (color, rect) in marker_rects {
+                            device.cmd_clear_color_rects(command_buffer, color, &[rect]);
+                        }
+                        opaque_pipeline.end_drawing(command_buffer);
+                    }),
+            );
+            (target, minimap)
+        });
+
+        // shared by every pass that traces rays this frame
+        let tlas = self
+            .ray_tracing_scene
+            .as_ref()
+            .and_then(|ray_tracing_scene| {
+                let structure = ray_tracing_scene.tlas(frame_slot)?;
+                let tlas = ray_tracing_scene.add_build_pass(&mut graph, frame_slot)?;
+                Some((tlas, structure))
+            });
+
+        // deferred => the opaque surfaces are already in the draw and depth image
+        // when the geometry pass starts, it only adds the sky and the sorted transparent surfaces
+        if let Some(deferred) = &self.deferred {
+            let gbuffer = deferred.import_gbuffer(&mut graph, draw_extent);
+            let shadow_mask = deferred.import_shadow_mask(&mut graph, draw_extent);
+            let gbuffer_pass = deformed_vertices
+                .iter()
+                .fold(GraphPass::new("gbuffer"), |pass, vertices| {
+                    pass.buffer(*vertices, BufferUsage::StorageRead)
+                });
+            let gbuffer_pass = indirect_buffers
+                .iter()
+                .fold(gbuffer_pass, |pass, commands| {
+                    pass.buffer(*commands, BufferUsage::Indirect)
+                });
+            let gbuffer_pass = gbuffer.iter().fold(gbuffer_pass, |pass, image| {
+                pass.image(*image, ImageUsage::ColorAttachment)
+            });
+            graph.add_pass(
+                gbuffer_pass
+                    .image(depth, ImageUsage::DepthAttachment)
+                    .record(move |command_buffer| {
+                        let _scope = profiler.scope(command_buffer, "gbuffer");
+                        deferred.record_gbuffer(
+                            command_buffer,
+                            depth_image_view,
+                            draw_extent,
+                            scene_descriptor_set,
+                            material_cache.texture_descriptor_set(),
+                            draw_batches,
+                        );
+                    }),
+            );
+            let ray_query_shadows = match (&self.ray_query_shadows, tlas) {
+                (Some(ray_query_shadows), Some(tlas)) => ray_query_shadows.add_pass(
+                    &mut graph,
+                    tlas,
+                    frame_slot,
+                    (depth, &self.depth_image),
+                    (gbuffer[1], deferred.normal_image()),
+                    (shadow_mask, deferred.shadow_mask()),
+                    draw_extent,
+                    &self.scene_data.view_proj,
+                    self.scene_data.sunlight_dir.xyz(),
+                ),
+                _ => false,
+            };
+            let lighting_pass = gbuffer
+                .iter()
+                .fold(GraphPass::new("deferred lighting"), |pass, image| {
+                    pass.image(*image, ImageUsage::Sampled)
+                });
+            // the mask is sampled even if it wasnt written => it needs the read only layout
+            graph.add_pass(
+                lighting_pass
+                    .image(depth, ImageUsage::Sampled)
+                    .image(shadow, ImageUsage::Sampled)
+                    .image(shadow_mask, ImageUsage::Sampled)
+                    .image(draw, ImageUsage::ColorAttachment)
+                    .record(move |command_buffer| {
+                        let _scope = profiler.scope(command_buffer, "deferred lighting");
+                        deferred.record_lighting(
+                            command_buffer,
+                            draw_image_view,
+                            draw_extent,
+                            scene_descriptor_set,
+                            &view_proj,
+                            ray_query_shadows,
+                        );
+                    }),
+            );
+        }
+        let opaque_forward = self.deferred.is_none();
+        // weighted blended => the transparent surfaces are accumulated after the geometry pass
+        let sorted_transparency = self.transparency == Transparency::Sorted;
+        let geometry_pass = GraphPass::new("geometry")
+            .image(draw, ImageUsage::ColorAttachment)
+            .image(depth, ImageUsage::DepthAttachment)
+            .image(shadow, ImageUsage::Sampled);
+        let geometry_pass = deformed_vertices
+            .iter()
+            .fold(geometry_pass, |pass, vertices| {
+                pass.buffer(*vertices, BufferUsage::StorageRead)
+            });
+        let geometry_pass = indirect_buffers
+            .iter()
+            .fold(geometry_pass, |pass, commands| {
+                pass.buffer(*commands, BufferUsage::Indirect)
+            });
+        graph.add_pass(geometry_pass.record(move |command_buffer| {
+            let _scope = profiler.scope(command_buffer, "geometry");
+            let opaque_pipeline = material_cache.pipeline(MaterialPass::Opaque);
+            if !opaque_forward {
+                opaque_pipeline.begin_drawing_load_depth(
+                    command_buffer,
+                    draw_image_view,
+                    depth_image_view,
+                    vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                    vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL,
+                    draw_extent,
+                    None,
+                );
+            } else {
+                opaque_pipeline.begin_drawing(
+                    command_buffer,
+                    draw_image_view,
+                    depth_image_view,
+                    vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                    vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL,
+                    draw_extent,
+                    None,
+                );
+            }
+            let record_pass = |pass| {
+                let pipeline = material_cache.pipeline(pass);
+                pipeline.bind(command_buffer);
+                // textures are bindless => the sets are the same for every surface
+                device.cmd_bind_descriptor_set(
+                    command_buffer,
+                    pipeline.layout(),
+                    vk::PipelineBindPoint::GRAPHICS,
+                    DescriptorSetSlot::Scene,
+                    scene_descriptor_set,
+                );
+                device.cmd_bind_descriptor_set(
+                    command_buffer,
+                    pipeline.layout(),
+                    vk::PipelineBindPoint::GRAPHICS,
+                    DescriptorSetSlot::Material,
+                    material_cache.texture_descriptor_set(),
+                );
+                draw_batches.record_culled(command_buffer, pipeline, pass);
+            };
+            if opaque_forward {
+                record_pass(MaterialPass::Opaque);
+            }
+            // the sky fills what the opaque surfaces left at the far plane
+            // => transparent surfaces blend over it
+            skybox.record(command_buffer, &view_proj);
+            // transparent surfaces blend with what is behind them => draw them last
+            if sorted_transparency {
+                record_pass(MaterialPass::Transparent);
+            }
+            opaque_pipeline.end_drawing(command_buffer);
+        }));
+
+        if !sorted_transparency {
+            let oit = &self.oit;
+            let oit_images = oit.import_images(&mut graph, draw_extent);
+            let accumulate_pass = deformed_vertices
+                .iter()
+                .fold(GraphPass::new("oit accumulate"), |pass, vertices| {
+                    pass.buffer(*vertices, BufferUsage::StorageRead)
+                });
+            let accumulate_pass = indirect_buffers
+                .iter()
+                .fold(accumulate_pass, |pass, commands| {
+                    pass.buffer(*commands, BufferUsage::Indirect)
+                });
+            let accumulate_pass = oit_images.iter().fold(accumulate_pass, |pass, image| {
+                pass.image(*image, ImageUsage::ColorAttachment)
+            });
+            graph.add_pass(
+                accumulate_pass
+                    .image(depth, ImageUsage::DepthAttachment)
+                    .image(shadow, ImageUsage::Sampled)
+                    .record(move |command_buffer| {
+                        let _scope = profiler.scope(command_buffer, "oit accumulate");
+                        oit.record_accumulate(
+                            command_buffer,
+                            depth_image_view,
+                            draw_extent,
+                            scene_descriptor_set,
+                            material_cache.texture_descriptor_set(),
+                            draw_batches,
+                        );
+                    }),
+            );
+            let composite_pass = oit_images
+                .iter()
+                .fold(GraphPass::new("oit composite"), |pass, image| {
+                    pass.image(*image, ImageUsage::Sampled)
+                });
+            graph.add_pass(
+                composite_pass
+                    .image(draw, ImageUsage::ColorAttachment)
+                    .record(move |command_buffer| {
+                        oit.record_composite(command_buffer, draw_image_view, draw_extent);
+                    }),
+            );
+        }
 
-        let current_frame = self.get_current_frame();
-        self.submit_to_queue(current_frame, current_frame.in_flight_fence);
-        self.swapchain.present_image(
-            current_frame.result_presentable_semaphore,
-            presentation_image_index,
+        if let (Some(ao), Some(tlas)) = (&self.ray_traced_ao, tlas) {
+            ao.add_passes(
+                &mut graph,
+                tlas,
+                frame_slot,
+                (draw, &self.draw_image),
+                (depth, &self.depth_image),
+                draw_extent,
+                &self.scene_data.view_proj,
+                camera_position,
+            );
+        }
+
+        if self.distortion.is_active() {
+            let distortion = &self.distortion;
+            let distortion_image = graph.import_image(
+                "distortion image",
+                distortion.distortion_image(),
+                vk::ImageAspectFlags::COLOR,
+                vk::ImageLayout::UNDEFINED,
+            );
+            let scene_copy = graph.import_image(
+                "distortion scene copy",
+                distortion.scene_copy_image(),
+                vk::ImageAspectFlags::COLOR,
+                vk::ImageLayout::UNDEFINED,
+            );
+            graph.set_image_size(
+                distortion_image,
+                draw_extent,
+                distortion.distortion_format(),
+            );
+            graph.set_image_size(scene_copy, draw_extent, draw_format);
+            let distortion_pass = deformed_vertices
+                .iter()
+                .fold(GraphPass::new("distortion"), |pass, vertices| {
+                    pass.buffer(*vertices, BufferUsage::StorageRead)
+                });
+            graph.add_pass(
+                distortion_pass
+                    .image(distortion_image, ImageUsage::ColorAttachment)
+                    .image(depth, ImageUsage::DepthAttachment)
+                    .record(move |command_buffer| {
+                        distortion.record_draw(
+                            command_buffer,
+                            depth_image_view,
+                            draw_extent,
+                            scene_descriptor_set,
+                            scene.mesh_instances(),
+                        );
+                    }),
+            );
+            graph.add_pass(
+                GraphPass::new("distortion copy")
+                    .image(draw, ImageUsage::TransferSrc)
+                    .image(scene_copy, ImageUsage::TransferDst)
+                    .record(move |command_buffer| {
+                        device.copy_image_to_image(
+                            command_buffer,
+                            draw_image,
+                            distortion.scene_copy_image(),
+                            draw_extent,
+                            draw_extent,
+                        );
+                    }),
+            );
+            graph.add_pass(
+                GraphPass::new("distortion apply")
+                    .image(scene_copy, ImageUsage::StorageRead)
+                    .image(distortion_image, ImageUsage::StorageRead)
+                    .image(draw, ImageUsage::StorageWrite)
+                    .record(move |command_buffer| {
+                        distortion.record_apply(command_buffer, draw_extent);
+                    }),
+            );
+        }
+
+        // on the hdr colors of the scene, at render resolution => the upscaler gets a stable image
+        if self.antialiasing == Antialiasing::Taa {
+            self.taa.add_passes(&mut graph, draw, draw_image, depth);
+        }
+
+        // tonemapped before upscaling => the upscaler works on ldr colors like it expects
+        self.post_process
+            .add_passes(&mut graph, draw, draw_extent, self.swapchain.color_space());
+
+        if upscale {
+            let intermediate = graph.import_image(
+                "upscale image",
+                self.upscaler.intermediate_image(),
+                vk::ImageAspectFlags::COLOR,
+                vk::ImageLayout::UNDEFINED,
+            );
+            graph.set_image_size(intermediate, output_extent, draw_format);
+            let upscaler = &self.upscaler;
+            let sharpness = self.upscale_sharpness;
+            graph.add_pass(
+                GraphPass::new("upscale")
+                    .image(draw, ImageUsage::StorageRead)
+                    .image(intermediate, ImageUsage::StorageWrite)
+                    .record(move |command_buffer| {
+                        upscaler.record_easu(command_buffer, draw_extent, output_extent)
+                    }),
+            );
+            graph.add_pass(
+                GraphPass::new("sharpen")
+                    .image(intermediate, ImageUsage::StorageRead)
+                    .image(draw, ImageUsage::StorageWrite)
+                    .record(move |command_buffer| {
+                        upscaler.record_rcas(command_buffer, output_extent, sharpness)
+                    }),
+            );
+        }
+
+        // before the overlays => only the image of the scene is counted
+        if self.histogram.is_enabled() {
+            self.histogram
+                .add_passes(&mut graph, draw, frame_slot, final_extent);
+        }
+
+        if let Some((target, minimap)) =
+            minimap_target.filter(|(_, minimap)| minimap.settings().overlay)
+        {
+            // top right corner, never more than half of the screen
+            let map_extent = minimap.extent();
+            let overlay_size = map_extent
+                .width
+                .min(final_extent.width / 2)
+                .min(final_extent.height / 2);
+            let margin = overlay_size / 16;
+            let overlay_offset = vk::Offset2D {
+                x: (final_extent.width - overlay_size - margin) as i32,
+                y: margin as i32,
+            };
+            let minimap_image = minimap.image().image();
+            graph.add_pass(
+                GraphPass::new("minimap overlay")
+                    .image(target, ImageUsage::TransferSrc)
+                    .image(draw, ImageUsage::TransferDst)
+                    .record(move |command_buffer| {
+                        device.blit_image_region(
+                            command_buffer,
+                            minimap_image,
+                            draw_image,
+                            map_extent,
+                            overlay_offset,
+                            vk::Extent2D {
+                                width: overlay_size,
+                                height: overlay_size,
+                            },
+                        );
+                    }),
+            );
+        }
+
+        // after the overlays => right before the present copy, only dithering has to come later
+        if self.antialiasing == Antialiasing::Fxaa {
+            self.fxaa
+                .add_passes(&mut graph, draw, draw_image, final_extent);
+        }
+
+        // the hdr formats have at least 10 bits => no visible banding
+        if self.dithering && !self.swapchain.color_space().is_hdr() {
+            let dither_pipeline = &self.dither_pipeline;
+            // frame index only offsets the noise pattern => precision loss of the cast doesnt matter
+            let push_constants = PushConstants::new(
+                glm::vec4(
+                    (self.frame_index % 64) as f32,
+                    1.0,
+                    final_extent.width as f32,
+                    final_extent.height as f32,
+                ),
+                glm::Vec4::zeros(),
+                glm::Vec4::zeros(),
+                glm::Vec4::zeros(),
+            );
+            graph.add_pass(
+                GraphPass::new("dither")
+                    .image(draw, ImageUsage::StorageWrite)
+                    .record(move |command_buffer| {
+                        dither_pipeline.execute_compute_with_constants(
+                            command_buffer,
+                            &[draw_image_descriptor],
+                            final_extent,
+                            &push_constants,
+                        )
+                    }),
+            );
+        }
+
+        graph.add_pass(
+            GraphPass::new("present copy")
+                .image(draw, ImageUsage::TransferSrc)
+                .image(presentation, ImageUsage::TransferDst)
+                .record(move |command_buffer| {
+                    let _scope = profiler.scope(command_buffer, "present blit");
+                    device.copy_image_to_image(
+                        command_buffer,
+                        draw_image,
+                        presentation_image,
+                        final_extent,
+                        presentation_extent,
+                    );
+                }),
         );
+
+        if !self.egui_renderer.is_empty() {
+            let egui_renderer = &self.egui_renderer;
+            let presentation_view = self.swapchain.image_view(presentation_image_index);
+            let texture_descriptor_set = self.material_cache.texture_descriptor_set();
+            graph.add_pass(
+                GraphPass::new("ui")
+                    .image(presentation, ImageUsage::ColorAttachment)
+                    .record(move |command_buffer| {
+                        let _scope = profiler.scope(command_buffer, "ui");
+                        egui_renderer.record(
+                            command_buffer,
+                            presentation_view,
+                            presentation_extent,
+                            texture_descriptor_set,
+                        );
+                    }),
+            );
+        }
+
+        if let Some(frame_capture) = self.frame_capture.as_mut() {
+            graph.add_pass(
+                GraphPass::new("frame capture")
+                    .image(draw, ImageUsage::TransferSrc)
+                    .side_effects()
+                    .record(move |command_buffer| {
+                        frame_capture.record(command_buffer, frame_slot, draw_image, final_extent);
+                    }),
+            );
+        }
+
+        // the previous submissions of this slot are done => their command buffers can be reused
+        let frame = &self.frame_data[frame_slot];
+        let mut submission_command_buffers = frame.submission_command_buffers.clone();
+        let command_pools = [frame.command_pool, frame.compute_command_pool];
+        let mut used_command_buffers = [0; 2];
+        let next_command_buffer = |queue: PassQueue| {
+            let idx = queue.index();
+            let command_buffer =
+                match submission_command_buffers[idx].get(used_command_buffers[idx]) {
+                    Some(command_buffer) => {
+                        device.reset_command_buffer(*command_buffer)?;
+                        *command_buffer
+                    }
+                    None => {
+                        let command_buffer = device.create_command_buffer(command_pools[idx])?;
+                        submission_command_buffers[idx].push(command_buffer);
+                        command_buffer
+                    }
+                };
+            used_command_buffers[idx] += 1;
+            device.begin_command_buffer(
+                command_buffer,
+                vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT,
+            )?;
+            Ok(command_buffer)
+        };
+        let (graph_estimate, submissions) =
+            graph.execute(device, command_buffer, next_command_buffer)?;
+        self.graph_estimate = graph_estimate;
+        self.frame_data[frame_slot].submission_command_buffers = submission_command_buffers;
+        self.frame_data[frame_slot].pass_names = self
+            .graph_estimate
+            .passes
+            .iter()
+            .map(|pass| pass.name.clone())
+            .collect();
+
+        for submission in submissions.iter() {
+            self.device.end_command_buffer(submission.command_buffer)?;
+        }
+        drop(record_scope);
+        self.draw_calls = self.device.take_draw_call_count();
+
+        {
+            profile_scope!("submit");
+            self.submit_to_queues(frame_slot, &submissions)?;
+        }
+        let current_frame = self.get_current_frame();
+        let needs_recreation = {
+            profile_scope!("present");
+            self.swapchain.present_image(
+                current_frame.result_presentable_semaphore,
+                presentation_image_index,
+            )?
+        };
+        if needs_recreation {
+            log::debug!("Swapchain out of date after present");
+            self.swapchain_out_of_date = true;
+        }
         self.frame_index += 1;
+        Ok(())
     }
 
-    pub fn draw_background(&self, command_buffer: vk::CommandBuffer, draw_extent: vk::Extent2D) {
-        self.gradient_pipeline.execute_compute(
-            command_buffer,
-            &[self.draw_image_descriptor],
-            draw_extent,
-        )
+    // tessellated egui output, drawn on top of the next frame
+    pub fn set_ui(
+        &mut self,
+        primitives: Vec<egui::ClippedPrimitive>,
+        textures_delta: egui::TexturesDelta,
+        pixels_per_point: f32,
+    ) {
+        self.egui_renderer
+            .set_frame(primitives, textures_delta, pixels_per_point);
     }
 
+    // takes raw vulkan handles => only part of the api with the low level wrappers
+    #[cfg(feature = "low_level")]
     pub fn cmd_clear_image(&self, command_buffer: vk::CommandBuffer, image: vk::Image) {
         let flash_color = (self.frame_index as f32 / 100.0).sin().abs();
         let clear_value = vk::ClearColorValue {
@@ -695,63 +2374,647 @@ impl VulkanRenderer {
         );
     }
 
-    fn submit_to_queue(&self, current_frame: &FrameData, fence: vk::Fence) {
-        // command_buffer: is the clear cmd buffer
-        // when submitting -> we say that this cmd buffer should be executed
-        // when the image_available_semaphore was signaled (i.e. the image is available)
-        // and after the cmd buffer is executed, the result_presentable_semaphore will be signaled
-        // so that we can present the image to the surface
-        let cmd_buffer_submit_info = vk::CommandBufferSubmitInfo {
-            s_type: vk::StructureType::COMMAND_BUFFER_SUBMIT_INFO,
-            command_buffer: current_frame.command_buffer,
-            p_next: std::ptr::null(),
-            ..Default::default()
+    // the submissions of the graph in order, each one signals the timeline of its queue
+    // => the other queue can wait for it without a semaphore per submission
+    fn submit_to_queues(
+        &mut self,
+        frame_slot: usize,
+        submissions: &[GraphSubmission],
+    ) -> Result<(), VulkanError> {
+        let current_frame = &self.frame_data[frame_slot];
+        // the last submission of the previous frame, it waited for all compute work of that frame
+        let previous_frame_value = self.queue_timeline_values[PassQueue::Graphics.index()];
+        let first_compute = submissions
+            .iter()
+            .position(|submission| submission.queue == PassQueue::Compute);
+        let mut signaled_values = Vec::with_capacity(submissions.len());
+        for (idx, submission) in submissions.iter().enumerate() {
+            let queue = submission.queue.index();
+            self.queue_timeline_values[queue] += 1;
+            signaled_values.push(self.queue_timeline_values[queue]);
+            let cmd_buffer_submit_info = vk::CommandBufferSubmitInfo {
+                s_type: vk::StructureType::COMMAND_BUFFER_SUBMIT_INFO,
+                command_buffer: submission.command_buffer,
+                p_next: std::ptr::null(),
+                ..Default::default()
+            };
+            let mut wait_semaphore_submit_infos = submission.wait_semaphores.clone();
+            if let Some(wait) = submission.wait {
+                wait_semaphore_submit_infos.push(semaphore_submit_info(
+                    self.queue_timelines[submissions[wait].queue.index()],
+                    signaled_values[wait],
+                    vk::PipelineStageFlags2::ALL_COMMANDS,
+                ));
+            }
+            // imported images (draw image, ...) dont know what the previous frame did with
+            // them => the compute queue could overwrite them while the graphics queue still
+            // reads them. Barriers only cover earlier work of the same queue
+            if Some(idx) == first_compute && submission.wait.is_none() {
+                wait_semaphore_submit_infos.push(semaphore_submit_info(
+                    self.queue_timelines[PassQueue::Graphics.index()],
+                    previous_frame_value,
+                    vk::PipelineStageFlags2::ALL_COMMANDS,
+                ));
+            }
+            // the frame might use meshes/textures that are still being uploaded
+            if idx == 0 {
+                wait_semaphore_submit_infos.push(self.uploader.wait_semaphore_info());
+            }
+            let mut signal_semaphore_submit_infos = vec![semaphore_submit_info(
+                self.queue_timelines[queue],
+                self.queue_timeline_values[queue],
+                vk::PipelineStageFlags2::ALL_COMMANDS,
+            )];
+            // the last one is on the graphics queue and after everything
+            // => the frame slot can be reused after the frame timeline is signaled
+            if idx == submissions.len() - 1 {
+                signal_semaphore_submit_infos.push(semaphore_submit_info(
+                    current_frame.result_presentable_semaphore,
+                    0,
+                    vk::PipelineStageFlags2::ALL_GRAPHICS,
+                ));
+                signal_semaphore_submit_infos.push(semaphore_submit_info(
+                    self.frame_timeline,
+                    self.frame_index as u64 + 1,
+                    vk::PipelineStageFlags2::ALL_COMMANDS,
+                ));
+            }
+            let submit_info = vk::SubmitInfo2 {
+                s_type: vk::StructureType::SUBMIT_INFO_2,
+                p_next: std::ptr::null(),
+                wait_semaphore_info_count: wait_semaphore_submit_infos.len() as u32,
+                p_wait_semaphore_infos: wait_semaphore_submit_infos.as_ptr(),
+                signal_semaphore_info_count: signal_semaphore_submit_infos.len() as u32,
+                p_signal_semaphore_infos: signal_semaphore_submit_infos.as_ptr(),
+                command_buffer_info_count: 1,
+                p_command_buffer_infos: &cmd_buffer_submit_info,
+                ..Default::default()
+            };
+            match submission.queue {
+                PassQueue::Graphics => {
+                    if idx == 0 {
+                        self.device
+                            .graphics_queue_insert_label(&format!("frame {}", self.frame_index));
+                    }
+                    self.device
+                        .submit_to_graphics_queue(submit_info, vk::Fence::null())?
+                }
+                PassQueue::Compute => self
+                    .device
+                    .submit_to_compute_queue(submit_info, vk::Fence::null())?,
+            }
+        }
+        Ok(())
+    }
+
+    pub fn wait_idle(&self) -> Result<(), VulkanError> {
+        self.device.wait_idle()
+    }
+
+    pub fn resize_swapchain(&mut self, logical_size: winit::dpi::LogicalSize<u32>) {
+        self.resize_swapchain = Some(logical_size);
+    }
+
+    pub fn render_scale(&self) -> f32 {
+        self.render_scale
+    }
+
+    // draw image has the size of the window => we can only render at a lower resolution
+    pub fn set_render_scale(&mut self, render_scale: f32) {
+        self.render_scale = render_scale.clamp(0.1, 1.0);
+    }
+
+    pub fn camera(&self) -> &Camera {
+        &self.camera
+    }
+
+    // matrices are uploaded at the start of the next draw
+    pub fn camera_mut(&mut self) -> &mut Camera {
+        &mut self.camera
+    }
+
+    pub fn upscaling(&self) -> bool {
+        self.upscaling
+    }
+
+    pub fn set_upscaling(&mut self, enabled: bool) {
+        self.upscaling = enabled;
+    }
+
+    pub fn antialiasing(&self) -> Antialiasing {
+        self.antialiasing
+    }
+
+    pub fn set_antialiasing(&mut self, antialiasing: Antialiasing) {
+        self.antialiasing = antialiasing;
+    }
+
+    pub fn transparency(&self) -> Transparency {
+        self.transparency
+    }
+
+    pub fn set_transparency(&mut self, transparency: Transparency) {
+        self.transparency = transparency;
+    }
+
+    // RendererConfig::ray_tracing was set and the device supports it
+    pub fn supports_ray_tracing(&self) -> bool {
+        self.ray_traced_ao.is_some()
+    }
+
+    // deferred path and RendererConfig::ray_tracing on a device with ray queries
+    pub fn supports_ray_query_shadows(&self) -> bool {
+        self.ray_query_shadows.is_some()
+    }
+
+    // false => the sun shadows come from the shadow map
+    pub fn ray_query_shadows(&self) -> bool {
+        self.ray_query_shadows
+            .as_ref()
+            .is_some_and(|shadows| shadows.enabled())
+    }
+
+    pub fn set_ray_query_shadows(&mut self, enabled: bool) {
+        if let Some(shadows) = &mut self.ray_query_shadows {
+            shadows.set_enabled(enabled);
+        }
+    }
+
+    // None => no ray tracing, intensity 0 turns the ao off
+    pub fn ray_traced_ao_settings(&self) -> Option<&RayTracedAoSettings> {
+        self.ray_traced_ao.as_ref().map(|ao| ao.settings())
+    }
+
+    pub fn set_ray_traced_ao_settings(&mut self, settings: RayTracedAoSettings) {
+        if let Some(ao) = &mut self.ray_traced_ao {
+            ao.set_settings(settings);
+        }
+    }
+
+    // fixed by RendererConfig::render_path
+    pub fn render_path(&self) -> RenderPath {
+        match self.deferred {
+            Some(_) => RenderPath::Deferred,
+            None => RenderPath::Forward,
+        }
+    }
+
+    pub fn set_upscale_sharpness(&mut self, sharpness: f32) {
+        self.upscale_sharpness = sharpness.max(0.0);
+    }
+
+    // can change when the swapchain is recreated
+    pub fn output_color_space(&self) -> OutputColorSpace {
+        self.swapchain.color_space()
+    }
+
+    pub fn post_process_settings(&self) -> &PostProcessSettings {
+        self.post_process.settings()
+    }
+
+    pub fn set_post_process_settings(&mut self, settings: PostProcessSettings) {
+        self.post_process.set_settings(settings);
+    }
+
+    pub fn set_ambient_color(&mut self, color: glm::Vec3) {
+        self.scene_data.ambient_color = glm::vec4(color.x, color.y, color.z, 1.0);
+    }
+
+    // power is stored in the w component of the direction
+    pub fn set_sunlight(&mut self, direction: glm::Vec3, power: f32, color: glm::Vec3) {
+        self.scene_data.sunlight_dir = glm::vec4(direction.x, direction.y, direction.z, power);
+        self.scene_data.sunlight_color = glm::vec4(color.x, color.y, color.z, 1.0);
+    }
+
+    // all instances of meshes with this name distort what is behind them, None => remove
+    pub fn set_distortion(&mut self, mesh_name: &str, settings: Option<DistortionSettings>) {
+        self.distortion.set(mesh_name, settings);
+    }
+
+    // only lights the next frame => has to be called every frame, like drawing
+    pub fn submit_light(&mut self, light: Light) {
+        self.lights.push(light);
+    }
+
+    // drawn in the next frame only, on top of the scene => has to be called every frame
+    // material None => the materials of the mesh surfaces
+    pub fn submit(
+        &mut self,
+        mesh: &MeshHandle,
+        transform: Transform,
+        material: Option<&MaterialHandle>,
+    ) {
+        let mesh = match &mesh.source {
+            MeshSource::Mesh(mesh) => mesh.clone(),
+            // the reload removed the mesh => nothing to draw
+            MeshSource::Model(model, idx) => match self
+                .assets
+                .get(*model)
+                .and_then(|model| model.meshes().get(*idx))
+            {
+                Some(mesh) => mesh.clone(),
+                None => return,
+            },
         };
-        let wait_semaphore_submit_info = vk::SemaphoreSubmitInfo {
-            s_type: vk::StructureType::SEMAPHORE_SUBMIT_INFO,
-            semaphore: current_frame.image_available_semaphore,
-            stage_mask: vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
-            p_next: std::ptr::null(),
-            device_index: 0,
-            value: 1,
+        self.draw_list.push(RenderObject {
+            mesh,
+            transform,
+            material: material.map(|material| material.0.clone()),
+        });
+    }
+
+    // mesh of the current scene by its gltf name, None until the scene finished loading
+    pub fn mesh(&self, name: &str) -> Option<MeshHandle> {
+        let (idx, mesh) = self
+            .scene
+            .meshes()
+            .iter()
+            .enumerate()
+            .find(|(_, mesh)| mesh.name() == name)?;
+        // the scene shares the meshes of its model => the handle can follow reloads of the model
+        let model = self.scene_model.map(|(model, _)| model).filter(|model| {
+            self.assets
+                .get(*model)
+                .and_then(|model| model.meshes().get(idx))
+                .is_some_and(|model_mesh| Arc::ptr_eq(model_mesh, mesh))
+        });
+        Some(match model {
+            Some(model) => MeshHandle::of_model(model, idx, mesh),
+            None => MeshHandle::new(mesh.clone()),
+        })
+    }
+
+    // untextured material, e.g. for procedural meshes
+    pub fn create_material(
+        &mut self,
+        color: glm::Vec4,
+        metallic: f32,
+        roughness: f32,
+    ) -> Result<MaterialHandle, VulkanError> {
+        let material = self.material_cache.create_material(MaterialDescription {
+            constants: MaterialConstants {
+                color_factors: color,
+                metal_rough_factors: glm::vec4(metallic, roughness, 0.0, 0.0),
+            },
             ..Default::default()
+        })?;
+        Ok(MaterialHandle(material))
+    }
+
+    pub fn create_primitive_mesh(
+        &mut self,
+        primitive: Primitive,
+        material: &MaterialHandle,
+    ) -> Result<MeshHandle, VulkanError> {
+        let mesh = primitive.create_mesh(
+            self.device.clone(),
+            self.allocator.clone(),
+            &self.uploader,
+            material.0.clone(),
+        )?;
+        Ok(MeshHandle::new(Arc::new(mesh)))
+    }
+
+    // gltf file without replacing the scene, e.g. props that are only submitted
+    // loaded in the background, files that were loaded before are reused
+    pub fn load_model(&mut self, path: &Path) -> Handle<Model> {
+        self.assets.load_model(path)
+    }
+
+    // None until the model is ready. The handles follow hot reloads of the model, meshes that
+    // a reload removed are not drawn anymore
+    pub fn model_meshes(&self, model: Handle<Model>) -> Option<Vec<MeshHandle>> {
+        let meshes = self.assets.get(model)?.meshes();
+        Some(
+            meshes
+                .iter()
+                .enumerate()
+                .map(|(idx, mesh)| MeshHandle::of_model(model, idx, mesh))
+                .collect(),
+        )
+    }
+
+    // materials of the model in the order of the gltf file, None until the model is ready
+    // a hot reload creates new materials => get them again when asset_version changes
+    pub fn model_materials(&self, model: Handle<Model>) -> Option<Vec<MaterialHandle>> {
+        let materials = self.assets.get(model)?.materials();
+        Some(materials.iter().cloned().map(MaterialHandle).collect())
+    }
+
+    pub fn load_state<T: Asset>(&self, handle: Handle<T>) -> LoadState {
+        self.assets.state(handle)
+    }
+
+    // changes when a hot reload replaced the asset behind the handle
+    pub fn asset_version<T: Asset>(&self, handle: Handle<T>) -> u32 {
+        self.assets.version(handle)
+    }
+
+    // the reason a load failed, it is also logged
+    pub fn load_error<T: Asset>(&self, handle: Handle<T>) -> Option<String> {
+        self.assets.error(handle).map(|e| e.to_string())
+    }
+
+    // orthographic projection of the sun around the camera
+    // snapped to whole shadow map texels => the shadows dont shimmer when the camera moves
+    fn light_view_proj(&self) -> glm::Mat4 {
+        let sun_direction = self.scene_data.sunlight_dir.xyz();
+        let direction = if sun_direction.norm_squared() > 0.0 {
+            glm::normalize(&sun_direction)
+        } else {
+            -glm::Vec3::y()
         };
-        let signal_semaphore_submit_info = vk::SemaphoreSubmitInfo {
-            s_type: vk::StructureType::SEMAPHORE_SUBMIT_INFO,
-            semaphore: current_frame.result_presentable_semaphore,
-            stage_mask: vk::PipelineStageFlags2::ALL_GRAPHICS,
-            p_next: std::ptr::null(),
-            device_index: 0,
-            value: 1,
-            ..Default::default()
+        // look_at needs an up vector that is not parallel to the view direction
+        let up = if direction.y.abs() > 0.99 {
+            glm::Vec3::z()
+        } else {
+            glm::Vec3::y()
         };
-        let submit_info = vk::SubmitInfo2 {
-            s_type: vk::StructureType::SUBMIT_INFO_2,
-            p_next: std::ptr::null(),
-            wait_semaphore_info_count: 1,
-            p_wait_semaphore_infos: &wait_semaphore_submit_info,
-            signal_semaphore_info_count: 1,
-            p_signal_semaphore_infos: &signal_semaphore_submit_info,
-            command_buffer_info_count: 1,
-            p_command_buffer_infos: &cmd_buffer_submit_info,
+        let light_view = glm::look_at_rh(&glm::Vec3::zeros(), &direction, &up);
+        let center = light_view
+            * glm::vec4(
+                self.camera.position.x,
+                self.camera.position.y,
+                self.camera.position.z,
+                1.0,
+            );
+        let texel_size = 2.0 * SHADOW_RADIUS / SHADOW_MAP_SIZE as f32;
+        let center_x = (center.x / texel_size).floor() * texel_size;
+        let center_y = (center.y / texel_size).floor() * texel_size;
+        // view space looks down -z => near/far are distances along -z
+        let near = -center.z - SHADOW_DEPTH_RANGE;
+        let far = -center.z + SHADOW_DEPTH_RANGE;
+        // swapping near and far reverses the depth range like the camera does
+        let light_proj = glm::ortho_rh_zo(
+            center_x - SHADOW_RADIUS,
+            center_x + SHADOW_RADIUS,
+            center_y - SHADOW_RADIUS,
+            center_y + SHADOW_RADIUS,
+            far,
+            near,
+        );
+        light_proj * light_view
+    }
+
+    pub fn present_mode(&self) -> PresentModePreference {
+        self.swapchain.present_mode_preference()
+    }
+
+    // swapchain is recreated at the start of the next frame
+    pub fn set_present_mode(&mut self, present_mode: PresentModePreference) {
+        if self.swapchain.present_mode_preference() == present_mode {
+            return;
+        }
+        self.swapchain.set_present_mode_preference(present_mode);
+        self.swapchain_out_of_date = true;
+    }
+
+    pub fn vsync(&self) -> bool {
+        self.present_mode().is_vsync()
+    }
+
+    pub fn set_vsync(&mut self, enabled: bool) {
+        if enabled {
+            self.set_present_mode(PresentModePreference::Fifo);
+        } else {
+            self.set_present_mode(PresentModePreference::Mailbox);
+        }
+    }
+
+    pub fn dithering(&self) -> bool {
+        self.dithering
+    }
+
+    pub fn set_dithering(&mut self, enabled: bool) {
+        self.dithering = enabled;
+    }
+
+    // replaces the scene once the file is loaded
+    pub fn load_mesh_file(&mut self, path: &Path) -> Handle<Model> {
+        let handle = self.assets.load_model(path);
+        self.scene_model = Some((handle, 0));
+        handle
+    }
+
+    // returns the index of the new cloth
+    pub fn add_cloth(&mut self, settings: ClothSettings) -> Result<usize, VulkanError> {
+        let material = self.material_cache.create_material(MaterialDescription {
+            constants: MaterialConstants {
+                color_factors: settings.color,
+                // fabric is rough and not metallic at all
+                metal_rough_factors: glm::vec4(0.0, 0.9, 0.0, 0.0),
+            },
             ..Default::default()
-        };
-        self.device.submit_to_graphics_queue(submit_info, fence);
+        })?;
+        let cloth = self.cloth_solver.create_cloth(
+            &self.immediate_command_data,
+            &self.uploader,
+            material,
+            settings,
+            self.frame_data.len(),
+        )?;
+        self.cloths.push(cloth);
+        Ok(self.cloths.len() - 1)
     }
 
-    pub fn wait_idle(&self) {
-        self.device.wait_idle();
+    pub fn cloths(&self) -> &[Cloth] {
+        &self.cloths
     }
 
-    pub fn resize_swapchain(&mut self, logical_size: winit::dpi::LogicalSize<u32>) {
-        self.resize_swapchain = Some(logical_size);
+    pub fn cloth_mut(&mut self, idx: usize) -> Option<&mut Cloth> {
+        self.cloths.get_mut(idx)
+    }
+
+    pub fn remove_cloths(&mut self) -> Result<(), VulkanError> {
+        // buffers might still be used by frames in flight
+        let cloths = std::mem::take(&mut self.cloths);
+        self.defer_destruction(cloths);
+        Ok(())
+    }
+
+    // simulations run in fixed steps during the next draw, animations are sampled right away
+    pub fn advance_simulation(&mut self, delta_time: f32) {
+        self.cloth_solver.advance(delta_time);
+        self.scene.advance_animation(delta_time);
+    }
+
+    // names of the animations of the loaded scene
+    pub fn animations(&self) -> Vec<&str> {
+        self.scene
+            .animations()
+            .iter()
+            .map(|animation| animation.name())
+            .collect()
+    }
+
+    // the first animation of a scene is played in a loop after loading
+    // None or an unknown name => the current pose is kept
+    pub fn play_animation(&mut self, name: Option<&str>, looping: bool) {
+        let animation = name.and_then(|name| {
+            self.scene
+                .animations()
+                .iter()
+                .position(|animation| animation.name() == name)
+        });
+        self.scene.play_animation(animation, looping);
+    }
+
+    // 1 => normal speed, applies to the animation that is currently playing
+    pub fn set_animation_speed(&mut self, speed: f32) {
+        self.scene.set_animation_speed(speed);
+    }
+
+    // replaces the materials of all surfaces once the file is loaded
+    pub fn load_texture_file(&mut self, path: &Path) -> Handle<Texture> {
+        // shown as base color => srgb
+        let handle = self.assets.load_texture(path, ColorSpace::Srgb);
+        self.override_texture = Some((handle, 0));
+        handle
+    }
+
+    // equirectangular .hdr file, prefiltered once on load
+    pub fn load_environment_file(&mut self, path: &Path) -> Result<(), AssetError> {
+        let environment = Environment::load_hdr(
+            path,
+            self.device.clone(),
+            self.allocator.clone(),
+            &self.immediate_command_data,
+            &self.uploader,
+            &mut self.material_cache,
+        )?;
+        // frames in flight might still sample the old maps
+        let old_environment = std::mem::replace(&mut self.environment, environment);
+        self.defer_destruction(old_environment);
+        self.skybox_texture = None;
+        self.set_skybox_cubemap(Some(self.environment.environment_map()))?;
+        Ok(())
+    }
+
+    // KTX2 cubemap for set_skybox, the format of the file decides the color space
+    pub fn load_cubemap_file(&mut self, path: &Path) -> Handle<Texture> {
+        self.assets.load_texture(path, ColorSpace::Srgb)
+    }
+
+    // cubemap texture behind the scene, shown once it is loaded and again after hot reloads
+    // None => the gradient background, loading an environment also shows it as skybox
+    pub fn set_skybox(&mut self, cubemap: Option<Handle<Texture>>) -> Result<(), VulkanError> {
+        self.skybox_texture = cubemap.map(|handle| (handle, 0));
+        if cubemap.is_none() {
+            self.set_skybox_cubemap(None)?;
+        }
+        Ok(())
+    }
+
+    fn set_skybox_cubemap(&mut self, cubemap: Option<MaterialTexture>) -> Result<(), VulkanError> {
+        // the skybox descriptor set is rewritten => no frame in flight may use it
+        self.device.wait_idle()?;
+        self.skybox.set_cubemap(cubemap);
+        Ok(())
+    }
+
+    pub fn start_frame_capture(
+        &mut self,
+        settings: FrameCaptureSettings,
+    ) -> Result<(), VulkanError> {
+        // readback buffers of a previous capture might still be in use
+        self.device.wait_idle()?;
+        self.frame_capture = Some(FrameCapture::new(
+            self.device.clone(),
+            self.allocator.clone(),
+            settings,
+            self.swapchain.extent(),
+            self.frame_data.len(),
+        )?);
+        Ok(())
+    }
+
+    pub fn stop_frame_capture(&mut self) -> Result<Option<FrameCapture>, VulkanError> {
+        self.device.wait_idle()?;
+        Ok(self.frame_capture.take())
+    }
+
+    // builds a full allocator report => dont call this every frame
+    pub fn gpu_memory_usage(&self) -> u64 {
+        self.allocator.lock().unwrap().reserved_bytes()
+    }
+
+    // (allocated, reserved) bytes, builds a full allocator report => not every frame
+    pub fn gpu_memory_report(&self) -> (u64, u64) {
+        self.allocator.lock().unwrap().usage()
+    }
+
+    // every draw of the last frame: shadow, minimap, geometry and ui
+    pub fn draw_call_count(&self) -> u32 {
+        self.draw_calls
+    }
+
+    // gpu time of the profiled passes, a few frames old
+    pub fn frame_timings(&self) -> &[PassTiming] {
+        self.gpu_profiler.frame_timings()
+    }
+
+    // memory traffic per pass of the last recorded frame, see GraphEstimate
+    pub fn graph_estimate(&self) -> &GraphEstimate {
+        &self.graph_estimate
+    }
+
+    // computed on the gpu for every frame while enabled, costs a compute pass and a small copy
+    pub fn set_color_histogram(&mut self, enabled: bool) {
+        self.histogram.set_enabled(enabled);
+    }
+
+    // a few frames old, None while disabled
+    pub fn color_histogram(&self) -> Option<&ColorHistogram> {
+        self.histogram.latest()
+    }
+
+    pub fn frame_capture(&self) -> Option<&FrameCapture> {
+        self.frame_capture.as_ref()
+    }
+
+    pub fn start_minimap(&mut self, settings: MinimapSettings) -> Result<(), VulkanError> {
+        let minimap = Minimap::new(
+            self.device.clone(),
+            self.allocator.clone(),
+            &mut self.material_cache,
+            settings,
+            self.draw_image.format(),
+        )?;
+        // the old target might still be rendered to
+        if let Some(old_minimap) = self.minimap.replace(minimap) {
+            self.defer_destruction(old_minimap);
+        }
+        Ok(())
+    }
+
+    pub fn stop_minimap(&mut self) -> Result<(), VulkanError> {
+        if let Some(old_minimap) = self.minimap.take() {
+            self.defer_destruction(old_minimap);
+        }
+        Ok(())
+    }
+
+    pub fn minimap(&self) -> Option<&Minimap> {
+        self.minimap.as_ref()
+    }
+
+    pub fn minimap_mut(&mut self) -> Option<&mut Minimap> {
+        self.minimap.as_mut()
     }
 }
 
 impl Drop for VulkanRenderer {
     fn drop(&mut self) {
+        // the loading threads might still submit uploads
+        self.assets.shutdown();
         log::debug!("Dropping VulkanRenderer. Waiting for device idle");
-        self.device.wait_idle();
+        if let Err(e) = self.device.wait_idle() {
+            log::error!("Failed to wait for device idle: {}", e);
+        }
         log::debug!("Device is idle. Dropping resources");
+        self.device.destroy_semaphore(self.frame_timeline);
+        for timeline in self.queue_timelines {
+            self.device.destroy_semaphore(timeline);
+        }
     }
 }
@@ -1,37 +1,148 @@
 mod allocation;
+mod animation;
+mod antialiasing;
+mod assets;
+mod async_upload;
+mod block_decode;
+mod cloth;
 pub mod debug;
+mod deferred;
+mod deletion_queue;
 mod descriptor;
 mod device;
+mod distortion;
+mod draw_batches;
+mod egui_renderer;
+mod environment;
+mod error;
+mod gpu_profiler;
+mod gpu_sort;
+mod histogram;
 mod immediate_submit;
 mod instance;
+mod ktx;
+mod leak_tracker;
+mod light;
+mod material;
 mod mesh;
+mod oit;
 mod pipelines;
+mod post_process;
+mod primitives;
+mod ray_query_shadows;
+mod ray_traced_ao;
+mod ray_tracing;
+mod render_graph;
+mod scene;
 mod shader;
+mod skinning;
+mod skybox;
+mod taa;
+mod texture;
+mod upscaler;
 mod utils;
 pub mod window;
 
 pub use allocation::AllocatedBuffer;
 pub use allocation::AllocatedImage;
 pub use allocation::Allocator;
+pub use antialiasing::Antialiasing;
+pub use antialiasing::Fxaa;
+pub use assets::Asset;
+pub use assets::AssetManager;
+pub use assets::Handle;
+pub use assets::LoadState;
+pub use assets::Texture;
+pub use async_upload::AsyncUploader;
+pub use cloth::Cloth;
+pub use cloth::ClothCollider;
+pub use cloth::ClothSettings;
+pub use cloth::ClothSolver;
+pub use deferred::Deferred;
+pub use deferred::RenderPath;
+pub use deletion_queue::DeletionQueue;
 pub use descriptor::DescriptorAllocator;
 pub use descriptor::DescriptorAllocatorGrowable;
 pub use descriptor::DescriptorLayoutBuilder;
 pub use descriptor::DescriptorSetLayout;
+pub use descriptor::DescriptorSetSlot;
 pub use descriptor::DescriptorWriter;
 pub use descriptor::PoolSizeRatio;
+pub use descriptor::TextureHandle;
+pub use device::enumerate_adapters;
+pub use device::AdapterInfo;
+pub use device::AdapterType;
 pub use device::Device;
+pub use device::DeviceRequirements;
+pub use device::GpuPreference;
+pub use device::ImageLayers;
+pub use device::OptionalFeatures;
 pub use device::PhysicalDeviceSelector;
+pub use distortion::Distortion;
+pub use distortion::DistortionMode;
+pub use distortion::DistortionSettings;
+pub use draw_batches::DrawBatches;
+pub use egui_renderer::EguiRenderer;
+pub use environment::Environment;
+pub use error::AssetError;
+pub use error::VulkanError;
+pub use gpu_profiler::GpuProfiler;
+pub use gpu_profiler::PassTiming;
+pub use histogram::ColorHistogram;
+pub use histogram::Histogram;
+pub use histogram::HISTOGRAM_BINS;
 pub use immediate_submit::ImmediateCommandData;
 pub use instance::AppInfo;
 pub use instance::EngineInfo;
 pub use instance::Instance;
 pub use instance::Version;
+pub use leak_tracker::report_live_objects;
+pub use light::GPULight;
+pub use light::Light;
+pub use material::Material;
+pub use material::MaterialCache;
+pub use material::MaterialConstants;
+pub use material::MaterialDescription;
+pub use material::MaterialPass;
+pub use material::MaterialTexture;
+pub use mesh::Aabb;
+pub use mesh::GPUDrawData;
 pub use mesh::GPUDrawPushConstants;
 pub use mesh::MeshAsset;
-pub use mesh::Sampler;
+pub use mesh::SamplerSettings;
+pub use oit::Oit;
+pub use oit::Transparency;
 pub use pipelines::ComputePipeline;
-pub use pipelines::GraphicsPipeline;
-pub use pipelines::GraphicsPipelineBuilder;
+pub use pipelines::PushConstants;
+pub use post_process::PostProcess;
+pub use post_process::PostProcessSettings;
+pub use post_process::Tonemapper;
+pub use primitives::Primitive;
+pub use ray_query_shadows::RayQueryShadows;
+pub use ray_traced_ao::RayTracedAo;
+pub use ray_traced_ao::RayTracedAoSettings;
+pub use ray_tracing::RayTracingScene;
+pub use render_graph::BufferUsage;
+pub use render_graph::GraphEstimate;
+pub use render_graph::GraphPass;
+pub use render_graph::GraphSubmission;
+pub use render_graph::ImageUsage;
+pub use render_graph::PassEstimate;
+pub use render_graph::PassQueue;
+pub use render_graph::RenderGraph;
+pub use scene::Model;
+pub use scene::Scene;
+pub use shader::set_shader_override_dir;
+pub use shader::ShaderCompiler;
 pub use shader::ShaderModule;
+pub use skinning::Skinning;
+pub use skybox::Skybox;
+pub use taa::Taa;
+pub use texture::check_color_space;
+pub use texture::ColorSpace;
+pub use upscaler::Upscaler;
+pub use utils::semaphore_submit_info;
+pub use window::OutputColorSpace;
+pub use window::PresentModePreference;
 pub use window::Surface;
 pub use window::Swapchain;
@@ -1,4 +1,7 @@
-use super::ImmediateCommandData;
+use super::async_upload::AsyncUploader;
+use super::device::ImageLayers;
+use super::error::VulkanError;
+use super::leak_tracker::TrackedObject;
 use crate::vulkan_rs::Device;
 use ash::vk;
 use gpu_allocator::vulkan::Allocation;
@@ -16,17 +19,17 @@ pub struct Allocator {
 }
 
 impl Allocator {
-    pub fn new(device: Arc<Device>) -> Arc<Mutex<Self>> {
-        let allocator = device.create_allocator();
+    pub fn new(device: Arc<Device>) -> Result<Arc<Mutex<Self>>, VulkanError> {
+        let allocator = device.create_allocator()?;
 
-        Arc::new(Mutex::new(Self { device, allocator }))
+        Ok(Arc::new(Mutex::new(Self { device, allocator })))
     }
 
     pub fn allocate_image(
         &mut self,
         image: vk::Image,
         image_memory_req: vk::MemoryRequirements,
-    ) -> Allocation {
+    ) -> Result<Allocation, VulkanError> {
         let allocation_create_desc = AllocationCreateDesc {
             name: "Image",
             location: gpu_allocator::MemoryLocation::GpuOnly,
@@ -34,13 +37,16 @@ impl Allocator {
             linear: false,
             allocation_scheme: AllocationScheme::GpuAllocatorManaged,
         };
-        let allocation = self
-            .allocator
-            .allocate(&allocation_create_desc)
-            .expect("I pray that this never fails");
-        self.device
-            .bind_image_memory(image, unsafe { allocation.memory() }, allocation.offset());
-        allocation
+        let allocation = self.allocator.allocate(&allocation_create_desc)?;
+        if let Err(e) = self.device.bind_image_memory(
+            image,
+            unsafe { allocation.memory() },
+            allocation.offset(),
+        ) {
+            self.free_allocation(allocation)?;
+            return Err(e);
+        }
+        Ok(allocation)
     }
 
     pub fn allocate_buffer(
@@ -49,7 +55,7 @@ impl Allocator {
         buffer: vk::Buffer,
         buffer_memory_req: vk::MemoryRequirements,
         location: gpu_allocator::MemoryLocation,
-    ) -> Allocation {
+    ) -> Result<Allocation, VulkanError> {
         let allocation_create_desc = AllocationCreateDesc {
             name: buffer_name,
             requirements: buffer_memory_req,
@@ -57,20 +63,33 @@ impl Allocator {
             linear: true,
             allocation_scheme: AllocationScheme::GpuAllocatorManaged,
         };
-        let allocation = self
-            .allocator
-            .allocate(&allocation_create_desc)
-            .expect("I pray that this never fails");
-        self.device
-            .bind_buffer_memory(buffer, unsafe { allocation.memory() }, allocation.offset());
-        allocation
+        let allocation = self.allocator.allocate(&allocation_create_desc)?;
+        if let Err(e) = self.device.bind_buffer_memory(
+            buffer,
+            unsafe { allocation.memory() },
+            allocation.offset(),
+        ) {
+            self.free_allocation(allocation)?;
+            return Err(e);
+        }
+        Ok(allocation)
     }
 
-    pub fn free_allocation(&mut self, allocation: Allocation) {
+    pub fn free_allocation(&mut self, allocation: Allocation) -> Result<(), VulkanError> {
         log::debug!("Freeing allocation");
-        self.allocator
-            .free(allocation)
-            .expect("I pray that this never fails");
+        self.allocator.free(allocation)?;
+        Ok(())
+    }
+
+    // memory of all blocks, including the unused parts => what the driver actually handed out
+    pub fn reserved_bytes(&self) -> u64 {
+        self.allocator.generate_report().total_reserved_bytes
+    }
+
+    // (allocated, reserved) from a single report, the difference is free space inside the blocks
+    pub fn usage(&self) -> (u64, u64) {
+        let report = self.allocator.generate_report();
+        (report.total_allocated_bytes, report.total_reserved_bytes)
     }
 }
 
@@ -88,6 +107,10 @@ pub struct AllocatedImage {
     allocation: Option<Allocation>,
     extent: vk::Extent3D,
     format: vk::Format,
+    aspect_flags: vk::ImageAspectFlags,
+    mip_levels: u32,
+    layers: ImageLayers,
+    _tracked: TrackedObject,
 }
 
 impl AllocatedImage {
@@ -99,49 +122,207 @@ impl AllocatedImage {
         extent: vk::Extent3D,
         aspect_flags: vk::ImageAspectFlags,
         mip_levels: u32,
-    ) -> Self {
-        let image = device.create_image(format, usage_flags, extent, mip_levels);
+    ) -> Result<Self, VulkanError> {
+        Self::new_with_layers(
+            device,
+            allocator,
+            format,
+            usage_flags,
+            extent,
+            aspect_flags,
+            mip_levels,
+            ImageLayers::Single,
+        )
+    }
+
+    // 6 layers with a CUBE view, the faces are size x size
+    pub fn new_cubemap(
+        device: Arc<Device>,
+        allocator: Arc<Mutex<Allocator>>,
+        format: vk::Format,
+        usage_flags: vk::ImageUsageFlags,
+        size: u32,
+        mip_levels: u32,
+    ) -> Result<Self, VulkanError> {
+        let extent = vk::Extent3D {
+            width: size,
+            height: size,
+            depth: 1,
+        };
+        Self::new_with_layers(
+            device,
+            allocator,
+            format,
+            usage_flags,
+            extent,
+            vk::ImageAspectFlags::COLOR,
+            mip_levels,
+            ImageLayers::Cube,
+        )
+    }
+
+    // the default view covers every layer, single layers need their own views (create_layer_view)
+    #[allow(clippy::too_many_arguments)]
+    pub fn new_with_layers(
+        device: Arc<Device>,
+        allocator: Arc<Mutex<Allocator>>,
+        format: vk::Format,
+        usage_flags: vk::ImageUsageFlags,
+        extent: vk::Extent3D,
+        aspect_flags: vk::ImageAspectFlags,
+        mip_levels: u32,
+        layers: ImageLayers,
+    ) -> Result<Self, VulkanError> {
+        Self::new_shared(
+            device,
+            allocator,
+            format,
+            usage_flags,
+            extent,
+            aspect_flags,
+            mip_levels,
+            layers,
+            &[],
+        )
+    }
+
+    // queue_families see Device::create_image, e.g. async_compute_families for images that
+    // are written on the compute queue and read on the graphics queue
+    #[allow(clippy::too_many_arguments)]
+    pub fn new_shared(
+        device: Arc<Device>,
+        allocator: Arc<Mutex<Allocator>>,
+        format: vk::Format,
+        usage_flags: vk::ImageUsageFlags,
+        extent: vk::Extent3D,
+        aspect_flags: vk::ImageAspectFlags,
+        mip_levels: u32,
+        layers: ImageLayers,
+        queue_families: &[u32],
+    ) -> Result<Self, VulkanError> {
+        let image = device.create_image(
+            format,
+            usage_flags,
+            extent,
+            mip_levels,
+            layers,
+            queue_families,
+        )?;
         let image_mem_req = device.get_image_memory_requirements(image);
 
         let allocation = allocator
             .lock()
             .expect("Mutex has been poisoned and i dont wanan handle it yet")
             .allocate_image(image, image_mem_req);
-        let image_view = device.create_image_view(image, format, aspect_flags, mip_levels);
-        Self {
+        let allocation = match allocation {
+            Ok(allocation) => allocation,
+            Err(e) => {
+                device.destroy_image(image);
+                return Err(e);
+            }
+        };
+        // from here on drop cleans up the image and allocation if creating the view fails
+        let mut allocated_image = Self {
             device,
             allocator,
             image,
-            image_view,
+            image_view: vk::ImageView::null(),
             allocation: Some(allocation),
             extent,
             format,
-        }
+            aspect_flags,
+            mip_levels,
+            layers,
+            _tracked: TrackedObject::new(
+                "AllocatedImage",
+                format!(
+                    "{}x{} {:?}{}",
+                    extent.width,
+                    extent.height,
+                    format,
+                    match layers {
+                        ImageLayers::Single => String::new(),
+                        ImageLayers::Array(count) => format!(" array of {}", count),
+                        ImageLayers::Cube => " cube".to_string(),
+                    }
+                ),
+            ),
+        };
+        allocated_image.image_view = allocated_image.device.create_image_view(
+            image,
+            format,
+            aspect_flags,
+            mip_levels,
+            layers,
+        )?;
+        Ok(allocated_image)
     }
 
     pub fn new_draw_color_image(
         device: Arc<Device>,
         allocator: Arc<Mutex<Allocator>>,
         extent: vk::Extent3D,
-    ) -> Self {
+    ) -> Result<Self, VulkanError> {
         let usage = vk::ImageUsageFlags::COLOR_ATTACHMENT
             | vk::ImageUsageFlags::STORAGE
             | vk::ImageUsageFlags::TRANSFER_SRC
             | vk::ImageUsageFlags::TRANSFER_DST;
         let format = vk::Format::R16G16B16A16_SFLOAT;
         let aspect = vk::ImageAspectFlags::COLOR;
-        Self::new(device, allocator, format, usage, extent, aspect, 1)
+        // the background and post processing can run on the async compute queue
+        let queue_families = device.async_compute_families();
+        let image = Self::new_shared(
+            device,
+            allocator,
+            format,
+            usage,
+            extent,
+            aspect,
+            1,
+            ImageLayers::Single,
+            &queue_families,
+        )?;
+        image.set_name("draw image");
+        Ok(image)
     }
 
     pub fn new_depth_image(
         device: Arc<Device>,
         allocator: Arc<Mutex<Allocator>>,
         extent: vk::Extent3D,
-    ) -> Self {
-        let usage = vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT;
+    ) -> Result<Self, VulkanError> {
+        // sampled => taa reconstructs the motion of the camera from it
+        let usage = vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | vk::ImageUsageFlags::SAMPLED;
         let format = vk::Format::D32_SFLOAT;
         let aspect_flags = vk::ImageAspectFlags::DEPTH;
-        Self::new(device, allocator, format, usage, extent, aspect_flags, 1)
+        let image = Self::new(device, allocator, format, usage, extent, aspect_flags, 1)?;
+        image.set_name("depth image");
+        Ok(image)
+    }
+
+    // depth only render target that is sampled afterwards
+    pub fn new_shadow_map(
+        device: Arc<Device>,
+        allocator: Arc<Mutex<Allocator>>,
+        size: u32,
+    ) -> Result<Self, VulkanError> {
+        let usage = vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | vk::ImageUsageFlags::SAMPLED;
+        let extent = vk::Extent3D {
+            width: size,
+            height: size,
+            depth: 1,
+        };
+        let image = Self::new(
+            device,
+            allocator,
+            vk::Format::D32_SFLOAT,
+            usage,
+            extent,
+            vk::ImageAspectFlags::DEPTH,
+            1,
+        )?;
+        image.set_name("shadow map");
+        Ok(image)
     }
 
     fn allocate_texture(
@@ -151,7 +332,7 @@ impl AllocatedImage {
         usage_flags: vk::ImageUsageFlags,
         extent: vk::Extent3D,
         mip_mapped: bool,
-    ) -> Self {
+    ) -> Result<Self, VulkanError> {
         let mip_levels = if mip_mapped {
             f32::floor(f32::log2(u32::max(extent.width, extent.height) as f32)) as u32 + 1
         } else {
@@ -182,9 +363,10 @@ impl AllocatedImage {
         usage_flags: vk::ImageUsageFlags,
         extent: vk::Extent3D,
         mip_mapped: bool,
-        immediate_command: &ImmediateCommandData,
-    ) -> Self {
-        let size = extent.width * extent.height * extent.depth * 4;
+        uploader: &AsyncUploader,
+    ) -> Result<Self, VulkanError> {
+        // not every format has 4 bytes per texel (e.g. float textures)
+        let size = std::mem::size_of_val(data);
         let mut staging_buffer = AllocatedBuffer::new(
             device.clone(),
             allocator.clone(),
@@ -192,7 +374,7 @@ impl AllocatedImage {
             vk::BufferUsageFlags::TRANSFER_SRC,
             size as u64,
             gpu_allocator::MemoryLocation::CpuToGpu,
-        );
+        )?;
         staging_buffer.copy_from_slice(data, 0);
 
         let image = Self::allocate_texture(
@@ -202,43 +384,104 @@ impl AllocatedImage {
             usage_flags | vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::TRANSFER_SRC,
             extent,
             mip_mapped,
-        );
-        immediate_command.immediate_submit(|device, cmd| {
-            let image = image.image();
-            device.transition_image_layout(
-                cmd,
-                image,
-                vk::ImageLayout::UNDEFINED,
-                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
-            );
-            let copy_region = vk::BufferImageCopy {
-                buffer_offset: 0,
+        )?;
+        let copy_region = vk::BufferImageCopy {
+            buffer_offset: 0,
+            buffer_row_length: 0,
+            buffer_image_height: 0,
+            image_subresource: vk::ImageSubresourceLayers {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                mip_level: 0,
+                base_array_layer: 0,
+                layer_count: 1,
+            },
+            image_offset: vk::Offset3D { x: 0, y: 0, z: 0 },
+            image_extent: extent,
+        };
+        // staging buffer is kept alive by the uploader until the copy is done
+        uploader.upload_image(
+            staging_buffer,
+            image.image(),
+            extent,
+            image.mip_levels(),
+            &[copy_region],
+            image.mip_levels() > 1,
+        )?;
+        Ok(image)
+    }
+
+    // levels are already in the final format (e.g. block compressed), level 0 first
+    // a level holds all layers of it one after another (e.g. the 6 faces of a cube)
+    #[allow(clippy::too_many_arguments)]
+    pub fn new_texture_with_levels(
+        levels: &[&[u8]],
+        device: Arc<Device>,
+        allocator: Arc<Mutex<Allocator>>,
+        format: vk::Format,
+        usage_flags: vk::ImageUsageFlags,
+        extent: vk::Extent3D,
+        layers: ImageLayers,
+        uploader: &AsyncUploader,
+    ) -> Result<Self, VulkanError> {
+        // buffer offsets have to be a multiple of the block size => 16 covers every bc/astc format
+        let mut offsets = Vec::with_capacity(levels.len());
+        let mut size = 0;
+        for level in levels {
+            offsets.push(size);
+            size = (size + level.len() as u64).next_multiple_of(16);
+        }
+        let mut staging_buffer = AllocatedBuffer::new(
+            device.clone(),
+            allocator.clone(),
+            "Texture Staging Buffer",
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            size.max(1),
+            gpu_allocator::MemoryLocation::CpuToGpu,
+        )?;
+        for (level, offset) in levels.iter().zip(&offsets) {
+            staging_buffer.copy_from_slice(level, *offset as usize);
+        }
+
+        let image = Self::new_with_layers(
+            device,
+            allocator,
+            format,
+            usage_flags | vk::ImageUsageFlags::TRANSFER_DST,
+            extent,
+            vk::ImageAspectFlags::COLOR,
+            levels.len() as u32,
+            layers,
+        )?;
+        let copy_regions: Vec<vk::BufferImageCopy> = offsets
+            .iter()
+            .enumerate()
+            .map(|(level, offset)| vk::BufferImageCopy {
+                buffer_offset: *offset,
                 buffer_row_length: 0,
                 buffer_image_height: 0,
                 image_subresource: vk::ImageSubresourceLayers {
                     aspect_mask: vk::ImageAspectFlags::COLOR,
-                    mip_level: 0,
+                    mip_level: level as u32,
                     base_array_layer: 0,
-                    layer_count: 1,
+                    layer_count: layers.count(),
                 },
                 image_offset: vk::Offset3D { x: 0, y: 0, z: 0 },
-                image_extent: extent,
-            };
-            device.cmd_copy_buffer_to_image(
-                cmd,
-                staging_buffer.buffer(),
-                image,
-                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
-                &[copy_region],
-            );
-            device.transition_image_layout(
-                cmd,
-                image,
-                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
-                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
-            );
-        });
-        image
+                image_extent: vk::Extent3D {
+                    width: (extent.width >> level).max(1),
+                    height: (extent.height >> level).max(1),
+                    depth: 1,
+                },
+            })
+            .collect();
+        uploader.upload_image(
+            staging_buffer,
+            image.image(),
+            extent,
+            levels.len() as u32,
+            &copy_regions,
+            false,
+        )?;
+        Ok(image)
     }
 
     pub fn image(&self) -> vk::Image {
@@ -255,13 +498,71 @@ impl AllocatedImage {
     pub fn format(&self) -> vk::Format {
         self.format
     }
+
+    pub fn mip_levels(&self) -> u32 {
+        self.mip_levels
+    }
+
+    pub fn layers(&self) -> ImageLayers {
+        self.layers
+    }
+
+    // TYPE_2D view of one layer (e.g. a cube face or an atlas slice) and one mip level
+    // to render into it, the caller has to destroy it
+    // nothing renders into layered images yet
+    #[allow(dead_code)]
+    pub fn create_layer_view(
+        &self,
+        layer: u32,
+        mip_level: u32,
+    ) -> Result<vk::ImageView, VulkanError> {
+        assert!(layer < self.layers.count() && mip_level < self.mip_levels);
+        self.device.create_image_view_range(
+            self.image,
+            vk::ImageViewType::TYPE_2D,
+            self.format,
+            vk::ImageSubresourceRange {
+                aspect_mask: self.aspect_flags,
+                base_mip_level: mip_level,
+                level_count: 1,
+                base_array_layer: layer,
+                layer_count: 1,
+            },
+        )
+    }
+
+    // TYPE_2D_ARRAY view of every layer at one mip level => storage writes into cubemaps
+    // the caller has to destroy it
+    pub fn create_mip_view(&self, mip_level: u32) -> Result<vk::ImageView, VulkanError> {
+        assert!(mip_level < self.mip_levels);
+        self.device.create_image_view_range(
+            self.image,
+            vk::ImageViewType::TYPE_2D_ARRAY,
+            self.format,
+            vk::ImageSubresourceRange {
+                aspect_mask: self.aspect_flags,
+                base_mip_level: mip_level,
+                level_count: 1,
+                base_array_layer: 0,
+                layer_count: self.layers.count(),
+            },
+        )
+    }
+
+    // shows up in validation messages and captures, the view gets the same name
+    pub fn set_name(&self, name: &str) {
+        self.device.set_object_name(self.image, name);
+        self.device
+            .set_object_name(self.image_view, &format!("{} view", name));
+    }
 }
 
 impl Drop for AllocatedImage {
     fn drop(&mut self) {
         log::debug!("Dropping allocated image");
         self.device.destroy_image_view(self.image_view);
-        self.allocator
+        let result = self
+            .allocator
             .lock()
             .expect("Mutex has been poisoned and i dont wanan handle it yet")
             .free_allocation(
@@ -269,6 +570,9 @@ impl Drop for AllocatedImage {
                     .take()
                     .expect("Allocation should exist until its dropped"),
             );
+        if let Err(e) = result {
+            log::error!("Failed to free image allocation: {}", e);
+        }
         self.device.destroy_image(self.image);
     }
 }
@@ -279,6 +583,7 @@ pub struct AllocatedBuffer {
     buffer: vk::Buffer,
     allocation: Option<Allocation>,
     cpu_accesible: bool,
+    _tracked: TrackedObject,
 }
 
 impl AllocatedBuffer {
@@ -289,21 +594,31 @@ impl AllocatedBuffer {
         usage: vk::BufferUsageFlags,
         size: vk::DeviceSize,
         location: gpu_allocator::MemoryLocation,
-    ) -> Self {
-        let buffer = device.create_buffer(usage, size);
+    ) -> Result<Self, VulkanError> {
+        let buffer = device.create_buffer(usage, size)?;
         let mem_requirements = device.get_buffer_memory_requirements(buffer);
         let allocation = allocator
             .lock()
             .expect("Mutex has been poisoned and i dont wanan handle it yet")
             .allocate_buffer(buffer_name, buffer, mem_requirements, location);
-        let cpu_accesible = location == gpu_allocator::MemoryLocation::CpuToGpu;
-        Self {
+        let allocation = match allocation {
+            Ok(allocation) => allocation,
+            Err(e) => {
+                device.destroy_buffer(buffer);
+                return Err(e);
+            }
+        };
+        let cpu_accesible = location == gpu_allocator::MemoryLocation::CpuToGpu
+            || location == gpu_allocator::MemoryLocation::GpuToCpu;
+        device.set_object_name(buffer, buffer_name);
+        Ok(Self {
             device,
             allocator,
             buffer,
             allocation: Some(allocation),
             cpu_accesible,
-        }
+            _tracked: TrackedObject::new("AllocatedBuffer", buffer_name),
+        })
     }
 
     pub fn get_device_address(&self) -> vk::DeviceAddress {
@@ -322,6 +637,16 @@ impl AllocatedBuffer {
         }
     }
 
+    pub fn mapped_bytes(&self) -> &[u8] {
+        if !self.cpu_accesible {
+            panic!("Cannot read from buffer that is not cpu accesible");
+        }
+        self.allocation
+            .as_ref()
+            .and_then(|allocation| allocation.mapped_slice())
+            .expect("Cpu accesible allocations should be persistently mapped by gpu_allocator")
+    }
+
     pub fn buffer(&self) -> vk::Buffer {
         self.buffer
     }
@@ -330,7 +655,8 @@ impl AllocatedBuffer {
 impl Drop for AllocatedBuffer {
     fn drop(&mut self) {
         log::debug!("Dropping allocated buffer");
-        self.allocator
+        let result = self
+            .allocator
             .lock()
             .expect("Mutex has been poisoned and i dont wanan handle it yet")
             .free_allocation(
@@ -338,6 +664,9 @@ impl Drop for AllocatedBuffer {
                     .take()
                     .expect("Allocation should exist until its dropped"),
             );
+        if let Err(e) = result {
+            log::error!("Failed to free buffer allocation: {}", e);
+        }
         self.device.destroy_buffer(self.buffer);
     }
 }
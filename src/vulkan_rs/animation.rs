@@ -0,0 +1,287 @@
+use nalgebra_glm as glm;
+
+// joints of a gltf skin, the i-th inverse bind matrix belongs to the i-th joint
+pub struct Skin {
+    name: String,
+    // node indices
+    joints: Vec<usize>,
+    // object space of the mesh -> local space of the joint in the bind pose
+    inverse_bind_matrices: Vec<glm::Mat4>,
+}
+
+impl Skin {
+    pub fn load_gltf(skin: gltf::Skin, buffers: &[gltf::buffer::Data]) -> Self {
+        let joints: Vec<usize> = skin.joints().map(|joint| joint.index()).collect();
+        let reader = skin.reader(|buffer| Some(&buffers[buffer.index()]));
+        // no matrices => the joints are already in bind pose
+        let inverse_bind_matrices = match reader.read_inverse_bind_matrices() {
+            Some(iter) => iter.map(glm::Mat4::from).collect(),
+            None => vec![glm::Mat4::identity(); joints.len()],
+        };
+        Skin {
+            name: skin.name().unwrap_or("Unnamed Skin").to_string(),
+            joints,
+            inverse_bind_matrices,
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    // world space joint matrices in the layout skinning.comp expects
+    // node_world_transform returns the current world transform of a node
+    pub fn joint_matrices<'a>(
+        &'a self,
+        node_world_transform: impl Fn(usize) -> &'a glm::Mat4 + 'a,
+    ) -> impl Iterator<Item = glm::Mat4> + 'a {
+        self.joints
+            .iter()
+            .zip(&self.inverse_bind_matrices)
+            .map(move |(joint, inverse_bind)| node_world_transform(*joint) * inverse_bind)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelTarget {
+    Translation,
+    // quaternion as xyzw
+    Rotation,
+    Scale,
+    // one weight per morph target of the mesh of the node
+    Weights,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interpolation {
+    Step,
+    Linear,
+    // every key has an in tangent, a value and an out tangent
+    CubicSpline,
+}
+
+// keyframes of one property of one node
+pub struct AnimationChannel {
+    node: usize,
+    target: ChannelTarget,
+    interpolation: Interpolation,
+    // seconds, ascending
+    times: Vec<f32>,
+    // width floats per value, 3 for translations and scales, 4 for rotations
+    values: Vec<f32>,
+    width: usize,
+}
+
+impl AnimationChannel {
+    pub fn node(&self) -> usize {
+        self.node
+    }
+
+    pub fn target(&self) -> ChannelTarget {
+        self.target
+    }
+
+    // cubic splines store (in tangent, value, out tangent) per key => element != key
+    fn element(&self, element: usize) -> &[f32] {
+        &self.values[element * self.width..(element + 1) * self.width]
+    }
+
+    fn value(&self, key: usize) -> &[f32] {
+        match self.interpolation {
+            Interpolation::CubicSpline => self.element(key * 3 + 1),
+            _ => self.element(key),
+        }
+    }
+
+    // clamped to the first and last key, rotations are normalized
+    pub fn sample(&self, time: f32, out: &mut Vec<f32>) {
+        out.clear();
+        let next = self.times.partition_point(|key_time| *key_time <= time);
+        if next == 0 {
+            out.extend_from_slice(self.value(0));
+            return;
+        }
+        if next == self.times.len() {
+            out.extend_from_slice(self.value(next - 1));
+            return;
+        }
+        let previous = next - 1;
+        let key_duration = self.times[next] - self.times[previous];
+        let t = (time - self.times[previous]) / key_duration;
+        match self.interpolation {
+            Interpolation::Step => {
+                out.extend_from_slice(self.value(previous));
+                return;
+            }
+            Interpolation::Linear => {
+                let start = self.value(previous);
+                let end = self.value(next);
+                // q and -q are the same rotation => take the shorter way
+                let dot: f32 = start.iter().zip(end).map(|(a, b)| a * b).sum();
+                let sign = if self.target == ChannelTarget::Rotation && dot < 0.0 {
+                    -1.0
+                } else {
+                    1.0
+                };
+                out.extend(
+                    start
+                        .iter()
+                        .zip(end)
+                        .map(|(start, end)| start + (sign * end - start) * t),
+                );
+            }
+            Interpolation::CubicSpline => {
+                // hermite spline, the tangents are scaled by the key duration
+                let start = self.element(previous * 3 + 1);
+                let start_tangent = self.element(previous * 3 + 2);
+                let end = self.element(next * 3 + 1);
+                let end_tangent = self.element(next * 3);
+                let (t2, t3) = (t * t, t * t * t);
+                out.extend((0..self.width).map(|idx| {
+                    start[idx] * (2.0 * t3 - 3.0 * t2 + 1.0)
+                        + start_tangent[idx] * key_duration * (t3 - 2.0 * t2 + t)
+                        + end[idx] * (-2.0 * t3 + 3.0 * t2)
+                        + end_tangent[idx] * key_duration * (t3 - t2)
+                }));
+            }
+        }
+        if self.target == ChannelTarget::Rotation {
+            let length = out.iter().map(|value| value * value).sum::<f32>().sqrt();
+            if length > 0.0 {
+                out.iter_mut().for_each(|value| *value /= length);
+            }
+        }
+    }
+}
+
+pub struct Animation {
+    name: String,
+    channels: Vec<AnimationChannel>,
+    // time of the last key of all channels
+    duration: f32,
+}
+
+impl Animation {
+    pub fn load_gltf(animation: gltf::Animation, buffers: &[gltf::buffer::Data]) -> Self {
+        use gltf::animation::util::ReadOutputs;
+        let name = animation.name().unwrap_or("Unnamed Animation").to_string();
+        let mut channels = Vec::new();
+        for channel in animation.channels() {
+            let reader = channel.reader(|buffer| Some(&buffers[buffer.index()]));
+            let (Some(times), Some(outputs)) = (reader.read_inputs(), reader.read_outputs()) else {
+                continue;
+            };
+            let (target, values): (_, Vec<_>) = match outputs {
+                ReadOutputs::Translations(iter) => {
+                    (ChannelTarget::Translation, iter.flatten().collect())
+                }
+                ReadOutputs::Rotations(iter) => {
+                    (ChannelTarget::Rotation, iter.into_f32().flatten().collect())
+                }
+                ReadOutputs::Scales(iter) => (ChannelTarget::Scale, iter.flatten().collect()),
+                ReadOutputs::MorphTargetWeights(iter) => {
+                    (ChannelTarget::Weights, iter.into_f32().collect())
+                }
+            };
+            let interpolation = match channel.sampler().interpolation() {
+                gltf::animation::Interpolation::Step => Interpolation::Step,
+                gltf::animation::Interpolation::Linear => Interpolation::Linear,
+                gltf::animation::Interpolation::CubicSpline => Interpolation::CubicSpline,
+            };
+            let times: Vec<f32> = times.collect();
+            let values_per_key = match interpolation {
+                Interpolation::CubicSpline => 3,
+                _ => 1,
+            };
+            // weights are stored as one flat list => the width follows from the key count
+            let width = match target {
+                ChannelTarget::Translation | ChannelTarget::Scale => 3,
+                ChannelTarget::Rotation => 4,
+                ChannelTarget::Weights => values.len() / (times.len() * values_per_key).max(1),
+            };
+            if times.is_empty()
+                || width == 0
+                || values.len() != times.len() * values_per_key * width
+            {
+                log::warn!("Skipping broken channel of animation {}", name);
+                continue;
+            }
+            channels.push(AnimationChannel {
+                node: channel.target().node().index(),
+                target,
+                interpolation,
+                times,
+                values,
+                width,
+            });
+        }
+        let duration = channels
+            .iter()
+            .filter_map(|channel| channel.times.last().copied())
+            .fold(0.0, f32::max);
+        Animation {
+            name,
+            channels,
+            duration,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn channels(&self) -> &[AnimationChannel] {
+        &self.channels
+    }
+
+    pub fn duration(&self) -> f32 {
+        self.duration
+    }
+}
+
+// playback state of one animation of a scene
+#[derive(Debug, Clone)]
+pub struct AnimationPlayer {
+    // index into the animations of the scene
+    animation: usize,
+    time: f32,
+    // negative => plays backwards
+    speed: f32,
+    // not looping => stops at the last key
+    looping: bool,
+}
+
+impl AnimationPlayer {
+    pub fn new(animation: usize, looping: bool) -> Self {
+        AnimationPlayer {
+            animation,
+            time: 0.0,
+            speed: 1.0,
+            looping,
+        }
+    }
+
+    pub fn animation(&self) -> usize {
+        self.animation
+    }
+
+    pub fn time(&self) -> f32 {
+        self.time
+    }
+
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed = speed;
+    }
+
+    pub fn advance(&mut self, delta_time: f32, duration: f32) {
+        self.time += delta_time * self.speed;
+        if duration <= 0.0 {
+            self.time = 0.0;
+        } else if self.looping {
+            self.time = self.time.rem_euclid(duration);
+        } else {
+            self.time = self.time.clamp(0.0, duration);
+        }
+    }
+}
@@ -0,0 +1,136 @@
+use super::allocation::AllocatedImage;
+use super::allocation::Allocator;
+use super::descriptor::DescriptorAllocator;
+use super::descriptor::DescriptorLayoutBuilder;
+use super::descriptor::DescriptorSetLayout;
+use super::descriptor::DescriptorWriter;
+use super::device::Device;
+use super::error::VulkanError;
+use super::pipelines::ComputePipeline;
+use super::pipelines::PushConstants;
+use super::render_graph::GraphPass;
+use super::render_graph::ImageHandle;
+use super::render_graph::ImageUsage;
+use super::render_graph::RenderGraph;
+use super::shader::ShaderModule;
+use ash::vk;
+use nalgebra_glm as glm;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Antialiasing {
+    None,
+    // post process on the final image => cheap, but slightly blurs textures
+    Fxaa,
+    // jittered frames accumulated over time => also smooths shading and thin geometry,
+    // but fast motion can leave short trails
+    Taa,
+}
+
+// FXAA over the final image in two passes:
+//   copy: draw image -> copy image, the filter reads neighbours that it also writes
+//   fxaa: copy image -> draw image with smoothed edges
+pub struct Fxaa {
+    device: Arc<Device>,
+    copy_image: AllocatedImage,
+    // binding 0 = copy image (input), binding 1 = draw image (output)
+    _descriptor_layout: DescriptorSetLayout,
+    descriptor: vk::DescriptorSet,
+    pipeline: ComputePipeline,
+}
+
+impl Fxaa {
+    pub fn new(
+        device: Arc<Device>,
+        allocator: Arc<Mutex<Allocator>>,
+        descriptor_allocator: &DescriptorAllocator,
+        draw_image: &AllocatedImage,
+    ) -> Result<Self, VulkanError> {
+        let copy_image =
+            AllocatedImage::new_draw_color_image(device.clone(), allocator, draw_image.extent())?;
+        copy_image.set_name("fxaa copy");
+
+        let mut builder = DescriptorLayoutBuilder::new();
+        builder.add_binding(
+            0,
+            vk::DescriptorType::STORAGE_IMAGE,
+            vk::ShaderStageFlags::COMPUTE,
+        );
+        builder.add_binding(
+            1,
+            vk::DescriptorType::STORAGE_IMAGE,
+            vk::ShaderStageFlags::COMPUTE,
+        );
+        let descriptor_layout =
+            builder.build(device.clone(), vk::DescriptorSetLayoutCreateFlags::empty())?;
+        let descriptor = descriptor_allocator.allocate(descriptor_layout.layout())?;
+        let mut writer = DescriptorWriter::new();
+        writer.add_storage_image(0, copy_image.image_view());
+        writer.add_storage_image(1, draw_image.image_view());
+        writer.update_descriptor_set(&device, descriptor);
+
+        let shader = ShaderModule::new(device.clone(), "shaders/fxaa_comp.spv")?;
+        let pipeline = ComputePipeline::new(device.clone(), &[descriptor_layout.layout()], shader)?;
+
+        Ok(Fxaa {
+            device,
+            copy_image,
+            _descriptor_layout: descriptor_layout,
+            descriptor,
+            pipeline,
+        })
+    }
+
+    // draw has to be the draw image, extent is the part of it that is presented
+    pub fn add_passes<'a>(
+        &'a self,
+        graph: &mut RenderGraph<'a>,
+        draw: ImageHandle,
+        draw_image: vk::Image,
+        extent: vk::Extent2D,
+    ) {
+        let copy = graph.import_image(
+            "fxaa copy",
+            self.copy_image.image(),
+            vk::ImageAspectFlags::COLOR,
+            vk::ImageLayout::UNDEFINED,
+        );
+        graph.set_image_size(copy, extent, self.copy_image.format());
+        let device = &self.device;
+        let copy_image = self.copy_image.image();
+        graph.add_pass(
+            GraphPass::new("fxaa copy")
+                .image(draw, ImageUsage::TransferSrc)
+                .image(copy, ImageUsage::TransferDst)
+                .record(move |command_buffer| {
+                    device.copy_image_to_image(
+                        command_buffer,
+                        draw_image,
+                        copy_image,
+                        extent,
+                        extent,
+                    );
+                }),
+        );
+        let push_constants = PushConstants::new(
+            glm::vec4(extent.width as f32, extent.height as f32, 0.0, 0.0),
+            glm::Vec4::zeros(),
+            glm::Vec4::zeros(),
+            glm::Vec4::zeros(),
+        );
+        graph.add_pass(
+            GraphPass::new("fxaa")
+                .image(copy, ImageUsage::StorageRead)
+                .image(draw, ImageUsage::StorageWrite)
+                .record(move |command_buffer| {
+                    self.pipeline.execute_compute_with_constants(
+                        command_buffer,
+                        &[self.descriptor],
+                        extent,
+                        &push_constants,
+                    );
+                }),
+        );
+    }
+}
@@ -0,0 +1,686 @@
+use super::allocation::AllocatedImage;
+use super::allocation::Allocator;
+use super::async_upload::AsyncUploader;
+use super::device::Device;
+use super::error::AssetError;
+use super::ktx::load_ktx2;
+use super::material::MaterialCache;
+use super::scene::Model;
+use super::scene::UploadedModel;
+use super::texture::load_texture;
+use super::texture::ColorSpace;
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::thread::JoinHandle;
+use std::time::Duration;
+use std::time::Instant;
+use std::time::SystemTime;
+
+// decoding and uploading a big texture takes a while => a few files at once
+const LOADING_THREADS: usize = 2;
+// hot reload checks the modification times of the asset files this often
+const WATCH_INTERVAL: Duration = Duration::from_millis(500);
+
+// index into the slots of one asset type, the type parameter keeps handles of different
+// asset types apart. Handles stay valid as long as the manager lives
+pub struct Handle<T> {
+    idx: usize,
+    _asset: PhantomData<fn() -> T>,
+}
+
+// derives would require T: Clone/Eq/... although only the index is compared
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Handle<T> {}
+
+impl<T> PartialEq for Handle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.idx == other.idx
+    }
+}
+
+impl<T> Eq for Handle<T> {}
+
+impl<T> std::hash::Hash for Handle<T> {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.idx.hash(state);
+    }
+}
+
+impl<T> std::fmt::Debug for Handle<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Handle({})", self.idx)
+    }
+}
+
+impl<T> Handle<T> {
+    fn new(idx: usize) -> Self {
+        Handle {
+            idx,
+            _asset: PhantomData,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoadState {
+    Loading,
+    Ready,
+    // the error is logged and can be queried with AssetManager::error
+    // a failed reload keeps the old version => the asset stays Ready
+    Failed,
+}
+
+// an image file or .ktx2 texture, uploaded by a loading thread
+pub struct Texture {
+    image: Arc<AllocatedImage>,
+    color_space: ColorSpace,
+}
+
+impl Texture {
+    pub fn image(&self) -> &Arc<AllocatedImage> {
+        &self.image
+    }
+
+    pub fn color_space(&self) -> ColorSpace {
+        self.color_space
+    }
+}
+
+enum SlotState<T> {
+    Loading,
+    Ready(Arc<T>),
+    Failed(AssetError),
+}
+
+pub struct AssetSlot<T> {
+    // sent again for retries and reloads
+    job: LoadJob,
+    path: PathBuf,
+    state: SlotState<T>,
+    // when the current load was requested => load times in the log
+    requested: Instant,
+    // successful loads so far => users notice when a reload replaced the asset
+    version: u32,
+    // the old version stays in use until the reload finished
+    reloading: bool,
+}
+
+impl<T> AssetSlot<T> {
+    fn new(job: LoadJob) -> Self {
+        AssetSlot {
+            path: job.path().to_path_buf(),
+            job,
+            state: SlotState::Loading,
+            requested: Instant::now(),
+            version: 0,
+            reloading: false,
+        }
+    }
+
+    // returns the replaced version if this was a reload
+    fn finish(&mut self, result: Result<T, AssetError>) -> Option<Arc<T>> {
+        self.reloading = false;
+        match result {
+            Ok(asset) => {
+                log::info!(
+                    "Loaded {:?} in {:.1} ms",
+                    self.path,
+                    self.requested.elapsed().as_secs_f64() * 1000.0
+                );
+                self.version += 1;
+                match std::mem::replace(&mut self.state, SlotState::Ready(Arc::new(asset))) {
+                    SlotState::Ready(old) => Some(old),
+                    _ => None,
+                }
+            }
+            // e.g. a half written file => the old version stays until the next save
+            Err(e) if matches!(self.state, SlotState::Ready(_)) => {
+                log::error!("Could not reload {:?}: {}", self.path, e);
+                None
+            }
+            Err(e) => {
+                log::error!("Could not load {:?}: {}", self.path, e);
+                self.state = SlotState::Failed(e);
+                None
+            }
+        }
+    }
+
+    // a retry might already be on its way when the watcher reports the change
+    fn can_reload(&self) -> bool {
+        !matches!(self.state, SlotState::Loading) && !self.reloading
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum WatchedAsset {
+    Texture(usize),
+    Model(usize),
+}
+
+enum WatchMessage {
+    // the files an asset was loaded from, sent after every load
+    Files(WatchedAsset, Vec<PathBuf>),
+    Enabled(bool),
+}
+
+// polls the modification times on its own thread => the render thread never waits for stat calls
+// a changed asset is reported once and watched again when its reload sent the new file list
+fn watch_files(messages: mpsc::Receiver<WatchMessage>, changed: mpsc::Sender<WatchedAsset>) {
+    let mut watched: HashMap<WatchedAsset, Vec<(PathBuf, Option<SystemTime>)>> = HashMap::new();
+    let mut enabled = false;
+    let mut last_poll = Instant::now();
+    loop {
+        match messages.recv_timeout(WATCH_INTERVAL.saturating_sub(last_poll.elapsed())) {
+            Ok(WatchMessage::Files(asset, files)) => {
+                let files = files
+                    .into_iter()
+                    .map(|path| {
+                        let modified = modified_time(&path);
+                        (path, modified)
+                    })
+                    .collect();
+                watched.insert(asset, files);
+            }
+            Ok(WatchMessage::Enabled(value)) => enabled = value,
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+            // the manager shut down
+            Err(mpsc::RecvTimeoutError::Disconnected) => return,
+        }
+        if last_poll.elapsed() < WATCH_INTERVAL {
+            continue;
+        }
+        last_poll = Instant::now();
+        if !enabled {
+            continue;
+        }
+        watched.retain(|asset, files| {
+            let modified = files
+                .iter()
+                .any(|(path, modified)| modified_time(path) != *modified);
+            // only fails during shutdown => the next recv returns
+            if modified {
+                let _ = changed.send(*asset);
+            }
+            !modified
+        });
+    }
+}
+
+// None if the file doesnt exist (anymore) => recreating it counts as a change
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+// external buffers and images of a gltf file => saving a texture of a model reloads the model
+fn gltf_dependencies(path: &Path, document: &gltf::Document) -> Vec<PathBuf> {
+    let directory = path.parent().unwrap_or(Path::new(""));
+    let buffer_uris = document
+        .buffers()
+        .filter_map(|buffer| match buffer.source() {
+            gltf::buffer::Source::Uri(uri) => Some(uri),
+            gltf::buffer::Source::Bin => None,
+        });
+    let image_uris = document.images().filter_map(|image| match image.source() {
+        gltf::image::Source::Uri { uri, .. } => Some(uri),
+        gltf::image::Source::View { .. } => None,
+    });
+    buffer_uris
+        .chain(image_uris)
+        .filter(|uri| !uri.starts_with("data:"))
+        .map(|uri| directory.join(uri))
+        .collect()
+}
+
+// old versions of hot reloaded assets, frames in flight might still use them
+pub enum RetiredAsset {
+    Texture(Arc<Texture>),
+    Model(Arc<Model>),
+}
+
+// the asset types the manager can load => the queries work with any handle
+pub trait Asset: Sized + 'static {
+    fn slots(assets: &AssetManager) -> &[AssetSlot<Self>];
+}
+
+impl Asset for Texture {
+    fn slots(assets: &AssetManager) -> &[AssetSlot<Self>] {
+        &assets.textures
+    }
+}
+
+impl Asset for Model {
+    fn slots(assets: &AssetManager) -> &[AssetSlot<Self>] {
+        &assets.models
+    }
+}
+
+#[derive(Clone)]
+enum LoadJob {
+    Texture {
+        idx: usize,
+        path: PathBuf,
+        color_space: ColorSpace,
+    },
+    Model {
+        idx: usize,
+        path: PathBuf,
+    },
+}
+
+impl LoadJob {
+    fn path(&self) -> &Path {
+        match self {
+            LoadJob::Texture { path, .. } | LoadJob::Model { path, .. } => path,
+        }
+    }
+}
+
+enum LoadResult {
+    Texture {
+        idx: usize,
+        color_space: ColorSpace,
+        result: Result<AllocatedImage, AssetError>,
+    },
+    // the meshes are uploaded by the loading thread, their materials are created by update
+    // because they need the material cache
+    Model {
+        idx: usize,
+        // the gltf file and the files it references
+        files: Vec<PathBuf>,
+        result: Result<Box<UploadedModel>, AssetError>,
+    },
+}
+
+// what the loading threads need to upload textures
+struct LoaderContext {
+    device: Arc<Device>,
+    allocator: Arc<Mutex<Allocator>>,
+    uploader: Arc<AsyncUploader>,
+}
+
+impl LoaderContext {
+    fn load(&self, job: LoadJob) -> LoadResult {
+        match job {
+            LoadJob::Texture {
+                idx,
+                path,
+                color_space,
+            } => LoadResult::Texture {
+                idx,
+                color_space,
+                result: self.load_texture(&path, color_space),
+            },
+            LoadJob::Model { idx, path } => {
+                log::info!("Loading GLTF from file: {:?}", path);
+                let mut files = vec![path.clone()];
+                let result = self.load_model(&path, &mut files);
+                LoadResult::Model { idx, files, result }
+            }
+        }
+    }
+
+    fn load_model(
+        &self,
+        path: &Path,
+        files: &mut Vec<PathBuf>,
+    ) -> Result<Box<UploadedModel>, AssetError> {
+        let import = gltf::import(path)?;
+        files.extend(gltf_dependencies(path, &import.0));
+        let model = UploadedModel::new(
+            self.device.clone(),
+            self.allocator.clone(),
+            &self.uploader,
+            path,
+            import,
+            true,
+        )?;
+        Ok(Box::new(model))
+    }
+
+    // .ktx2 files are uploaded with their own format and mip levels, everything else is decoded
+    fn load_texture(
+        &self,
+        path: &Path,
+        color_space: ColorSpace,
+    ) -> Result<AllocatedImage, AssetError> {
+        let is_ktx2 = path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("ktx2"));
+        if is_ktx2 {
+            load_ktx2(
+                path,
+                self.device.clone(),
+                self.allocator.clone(),
+                &self.uploader,
+            )
+        } else {
+            load_texture(
+                path,
+                color_space,
+                self.device.clone(),
+                self.allocator.clone(),
+                &self.uploader,
+            )
+        }
+    }
+}
+
+// textures and gltf models by path, every file is only loaded once
+//   the loading threads read, decode and upload the files
+//   update has to be called regularly on the render thread, it creates the materials of models
+// handles can be used right away, the asset is available once the state is Ready
+// with hot reload, changed files are loaded again and replace the asset behind the same handle
+pub struct AssetManager {
+    // None after shutdown => the threads stop once the jobs in the queue are done
+    jobs: Option<mpsc::Sender<LoadJob>>,
+    results: mpsc::Receiver<LoadResult>,
+    threads: Vec<JoinHandle<()>>,
+    // None after shutdown => the watcher thread returns
+    watch: Option<mpsc::Sender<WatchMessage>>,
+    changed: mpsc::Receiver<WatchedAsset>,
+    watcher: Option<JoinHandle<()>>,
+    textures: Vec<AssetSlot<Texture>>,
+    texture_paths: HashMap<(PathBuf, ColorSpace), usize>,
+    models: Vec<AssetSlot<Model>>,
+    model_paths: HashMap<PathBuf, usize>,
+}
+
+impl AssetManager {
+    pub fn new(
+        device: Arc<Device>,
+        allocator: Arc<Mutex<Allocator>>,
+        uploader: Arc<AsyncUploader>,
+    ) -> Self {
+        let (job_sender, job_receiver) = mpsc::channel::<LoadJob>();
+        let (result_sender, results) = mpsc::channel();
+        // the receiver is shared => whichever thread is idle takes the next job
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+        let threads = (0..LOADING_THREADS)
+            .map(|idx| {
+                let jobs = job_receiver.clone();
+                let results = result_sender.clone();
+                let context = LoaderContext {
+                    device: device.clone(),
+                    allocator: allocator.clone(),
+                    uploader: uploader.clone(),
+                };
+                std::thread::Builder::new()
+                    .name(format!("asset loader {}", idx))
+                    .spawn(move || loop {
+                        // the lock is released before loading => the other threads keep going
+                        let Ok(job) = jobs.lock().unwrap().recv() else {
+                            return;
+                        };
+                        if results.send(context.load(job)).is_err() {
+                            return;
+                        }
+                    })
+                    .expect("Could not spawn asset loading thread")
+            })
+            .collect();
+        let (watch, watch_messages) = mpsc::channel();
+        let (changed_sender, changed) = mpsc::channel();
+        let watcher = std::thread::Builder::new()
+            .name("asset watcher".to_string())
+            .spawn(move || watch_files(watch_messages, changed_sender))
+            .expect("Could not spawn asset watcher thread");
+        AssetManager {
+            jobs: Some(job_sender),
+            results,
+            threads,
+            watch: Some(watch),
+            changed,
+            watcher: Some(watcher),
+            textures: Vec::new(),
+            texture_paths: HashMap::new(),
+            models: Vec::new(),
+            model_paths: HashMap::new(),
+        }
+    }
+
+    // the same file through different relative paths => one asset
+    fn normalized_path(path: &Path) -> PathBuf {
+        std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+    }
+
+    fn send(&self, job: LoadJob) {
+        if let Some(jobs) = self.jobs.as_ref() {
+            // only fails if every thread is gone, the slot then stays Loading
+            if jobs.send(job).is_err() {
+                log::error!("Asset loading threads are not running");
+            }
+        }
+    }
+
+    // returns the slot of the key and the job if it has to be (re)loaded
+    fn request<K: Eq + std::hash::Hash, T>(
+        slots: &mut Vec<AssetSlot<T>>,
+        paths: &mut HashMap<K, usize>,
+        key: K,
+        new_job: impl FnOnce(usize) -> LoadJob,
+    ) -> (usize, Option<LoadJob>) {
+        match paths.get(&key) {
+            Some(idx) => {
+                let slot = &mut slots[*idx];
+                // failed loads are retried, e.g. after fixing the file
+                let retry = matches!(slot.state, SlotState::Failed(_)) && !slot.reloading;
+                if retry {
+                    slot.state = SlotState::Loading;
+                    slot.requested = Instant::now();
+                }
+                (*idx, retry.then(|| slot.job.clone()))
+            }
+            None => {
+                let idx = slots.len();
+                let job = new_job(idx);
+                slots.push(AssetSlot::new(job.clone()));
+                paths.insert(key, idx);
+                (idx, Some(job))
+            }
+        }
+    }
+
+    pub fn load_texture(&mut self, path: &Path, color_space: ColorSpace) -> Handle<Texture> {
+        let path = Self::normalized_path(path);
+        let (idx, job) = Self::request(
+            &mut self.textures,
+            &mut self.texture_paths,
+            (path.clone(), color_space),
+            |idx| LoadJob::Texture {
+                idx,
+                path,
+                color_space,
+            },
+        );
+        if let Some(job) = job {
+            self.send(job);
+        }
+        Handle::new(idx)
+    }
+
+    pub fn load_model(&mut self, path: &Path) -> Handle<Model> {
+        let path = Self::normalized_path(path);
+        let (idx, job) = Self::request(
+            &mut self.models,
+            &mut self.model_paths,
+            path.clone(),
+            |idx| LoadJob::Model { idx, path },
+        );
+        if let Some(job) = job {
+            self.send(job);
+        }
+        Handle::new(idx)
+    }
+
+    pub fn set_hot_reload(&mut self, enabled: bool) {
+        self.send_watch(WatchMessage::Enabled(enabled));
+    }
+
+    fn send_watch(&self, message: WatchMessage) {
+        if let Some(watch) = self.watch.as_ref() {
+            if watch.send(message).is_err() {
+                log::error!("Asset watcher thread is not running");
+            }
+        }
+    }
+
+    // takes over everything the loading threads finished since the last call, doesnt block
+    // returns the old versions of reloaded assets => they have to outlive the frames in flight
+    pub fn update(&mut self, material_cache: &mut MaterialCache) -> Vec<RetiredAsset> {
+        self.reload_changed();
+        let mut retired = Vec::new();
+        while let Ok(result) = self.results.try_recv() {
+            match result {
+                LoadResult::Texture {
+                    idx,
+                    color_space,
+                    result,
+                } => {
+                    let slot = &mut self.textures[idx];
+                    let files = vec![slot.path.clone()];
+                    let result = result.map(|image| Texture {
+                        image: Arc::new(image),
+                        color_space,
+                    });
+                    retired.extend(slot.finish(result).map(RetiredAsset::Texture));
+                    self.send_watch(WatchMessage::Files(WatchedAsset::Texture(idx), files));
+                }
+                LoadResult::Model { idx, files, result } => {
+                    let slot = &mut self.models[idx];
+                    // errors end up in the slot => a broken file never stops the renderer
+                    let result = result.and_then(|uploaded| {
+                        material_cache.remove_file_materials(&slot.path);
+                        Model::new(*uploaded, material_cache).map_err(AssetError::from)
+                    });
+                    retired.extend(slot.finish(result).map(RetiredAsset::Model));
+                    self.send_watch(WatchMessage::Files(WatchedAsset::Model(idx), files));
+                }
+            }
+        }
+        retired
+    }
+
+    // the watcher thread only reports changes while hot reload is enabled
+    fn reload_changed(&mut self) {
+        while let Ok(asset) = self.changed.try_recv() {
+            let job = match asset {
+                WatchedAsset::Texture(idx) => Self::reload_job(&mut self.textures[idx]),
+                WatchedAsset::Model(idx) => Self::reload_job(&mut self.models[idx]),
+            };
+            if let Some(job) = job {
+                self.send(job);
+            }
+        }
+    }
+
+    fn reload_job<T>(slot: &mut AssetSlot<T>) -> Option<LoadJob> {
+        if !slot.can_reload() {
+            return None;
+        }
+        log::info!("{:?} changed, reloading", slot.path);
+        slot.reloading = true;
+        slot.requested = Instant::now();
+        Some(slot.job.clone())
+    }
+
+    pub fn state<T: Asset>(&self, handle: Handle<T>) -> LoadState {
+        match T::slots(self)[handle.idx].state {
+            SlotState::Loading => LoadState::Loading,
+            SlotState::Ready(_) => LoadState::Ready,
+            SlotState::Failed(_) => LoadState::Failed,
+        }
+    }
+
+    // None until the asset is Ready
+    pub fn get<T: Asset>(&self, handle: Handle<T>) -> Option<&Arc<T>> {
+        match &T::slots(self)[handle.idx].state {
+            SlotState::Ready(asset) => Some(asset),
+            _ => None,
+        }
+    }
+
+    pub fn error<T: Asset>(&self, handle: Handle<T>) -> Option<&AssetError> {
+        match &T::slots(self)[handle.idx].state {
+            SlotState::Failed(e) => Some(e),
+            _ => None,
+        }
+    }
+
+    // increases with every successful load => a changed version means the asset was reloaded
+    pub fn version<T: Asset>(&self, handle: Handle<T>) -> u32 {
+        T::slots(self)[handle.idx].version
+    }
+
+    pub fn path<T: Asset>(&self, handle: Handle<T>) -> &Path {
+        &T::slots(self)[handle.idx].path
+    }
+
+    // waits for the loading threads => nothing uses the device/uploader afterwards
+    pub fn shutdown(&mut self) {
+        self.jobs = None;
+        self.watch = None;
+        if let Some(watcher) = self.watcher.take() {
+            if watcher.join().is_err() {
+                log::error!("Asset watcher thread panicked");
+            }
+        }
+        for thread in self.threads.drain(..) {
+            if thread.join().is_err() {
+                log::error!("Asset loading thread panicked");
+            }
+        }
+    }
+}
+
+impl Drop for AssetManager {
+    fn drop(&mut self) {
+        log::debug!("Dropping AssetManager");
+        self.shutdown();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn watcher_reports_changed_files_once() {
+        let path = std::env::temp_dir().join(format!("asset_watch_{}.txt", std::process::id()));
+        let file = std::fs::File::create(&path).unwrap();
+        let (watch, messages) = mpsc::channel();
+        let (changed_sender, changed) = mpsc::channel();
+        let watcher = std::thread::spawn(move || watch_files(messages, changed_sender));
+        watch.send(WatchMessage::Enabled(true)).unwrap();
+        watch
+            .send(WatchMessage::Files(
+                WatchedAsset::Model(3),
+                vec![path.clone()],
+            ))
+            .unwrap();
+        assert!(changed.recv_timeout(WATCH_INTERVAL * 2).is_err());
+
+        file.set_modified(SystemTime::now() + Duration::from_secs(10))
+            .unwrap();
+        let timeout = WATCH_INTERVAL * 4;
+        assert_eq!(changed.recv_timeout(timeout), Ok(WatchedAsset::Model(3)));
+        // not watched again until the reload sends the files
+        assert!(changed.recv_timeout(WATCH_INTERVAL * 2).is_err());
+
+        drop(watch);
+        watcher.join().unwrap();
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,398 @@
+use super::allocation::AllocatedBuffer;
+use super::device::Device;
+use super::error::VulkanError;
+use super::utils::semaphore_submit_info;
+use ash::vk;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+struct PendingUpload {
+    // finished once the timeline reached this value
+    value: u64,
+    command_buffers: Vec<(vk::CommandPool, vk::CommandBuffer)>,
+    _staging_buffer: AllocatedBuffer,
+}
+
+struct UploadState {
+    last_value: u64,
+    pending: Vec<PendingUpload>,
+}
+
+// copies staging buffers into gpu resources without blocking the cpu
+//   the copies run on the transfer queue (or the graphics queue if there is no dedicated one)
+//   a second submission on the graphics queue takes over the ownership and generates mipmaps
+//   every upload signals the timeline semaphore => frames wait for last_value before drawing
+pub struct AsyncUploader {
+    device: Arc<Device>,
+    transfer_pool: vk::CommandPool,
+    graphics_pool: vk::CommandPool,
+    timeline: vk::Semaphore,
+    state: Mutex<UploadState>,
+}
+
+impl AsyncUploader {
+    pub fn new(device: Arc<Device>) -> Result<Self, VulkanError> {
+        let transfer_pool =
+            device.create_command_pool_for_family(device.get_transfer_queue_idx())?;
+        let graphics_pool = match device.create_command_pool() {
+            Ok(pool) => pool,
+            Err(e) => {
+                device.destroy_command_pool(transfer_pool);
+                return Err(e);
+            }
+        };
+        let timeline = match device.create_timeline_semaphore(0) {
+            Ok(semaphore) => semaphore,
+            Err(e) => {
+                device.destroy_command_pool(transfer_pool);
+                device.destroy_command_pool(graphics_pool);
+                return Err(e);
+            }
+        };
+        Ok(AsyncUploader {
+            device,
+            transfer_pool,
+            graphics_pool,
+            timeline,
+            state: Mutex::new(UploadState {
+                last_value: 0,
+                pending: Vec::new(),
+            }),
+        })
+    }
+
+    fn dedicated_transfer_queue(&self) -> bool {
+        self.device.get_transfer_queue_idx() != self.device.get_graphics_queue_idx()
+    }
+
+    // staging => buffers, the buffers can be used by the graphics queue once the value is reached
+    pub fn upload_buffers(
+        &self,
+        staging_buffer: AllocatedBuffer,
+        copies: &[(vk::Buffer, vk::BufferCopy)],
+    ) -> Result<u64, VulkanError> {
+        let dedicated = self.dedicated_transfer_queue();
+        let (src_family, dst_family) = self.ownership_families();
+        let barrier = |buffer: vk::Buffer, release: bool| vk::BufferMemoryBarrier2 {
+            s_type: vk::StructureType::BUFFER_MEMORY_BARRIER_2,
+            p_next: std::ptr::null(),
+            src_stage_mask: if release || !dedicated {
+                vk::PipelineStageFlags2::ALL_TRANSFER
+            } else {
+                vk::PipelineStageFlags2::NONE
+            },
+            src_access_mask: if release || !dedicated {
+                vk::AccessFlags2::TRANSFER_WRITE
+            } else {
+                vk::AccessFlags2::NONE
+            },
+            dst_stage_mask: if release {
+                vk::PipelineStageFlags2::NONE
+            } else {
+                vk::PipelineStageFlags2::ALL_COMMANDS
+            },
+            dst_access_mask: if release {
+                vk::AccessFlags2::NONE
+            } else {
+                vk::AccessFlags2::MEMORY_READ | vk::AccessFlags2::MEMORY_WRITE
+            },
+            src_queue_family_index: src_family,
+            dst_queue_family_index: dst_family,
+            buffer,
+            offset: 0,
+            size: vk::WHOLE_SIZE,
+            ..Default::default()
+        };
+        self.submit(
+            staging_buffer,
+            |device, command_buffer, staging_buffer| {
+                for (buffer, copy) in copies {
+                    device.cmd_copy_buffer(command_buffer, staging_buffer, *buffer, &[*copy]);
+                }
+                if dedicated {
+                    let releases: Vec<_> = copies
+                        .iter()
+                        .map(|(buffer, _)| barrier(*buffer, true))
+                        .collect();
+                    device.cmd_pipeline_barrier(command_buffer, &[], &releases);
+                }
+            },
+            |device, command_buffer| {
+                let acquires: Vec<_> = copies
+                    .iter()
+                    .map(|(buffer, _)| barrier(*buffer, false))
+                    .collect();
+                device.cmd_pipeline_barrier(command_buffer, &[], &acquires);
+            },
+        )
+    }
+
+    // staging => all levels in regions, afterwards SHADER_READ_ONLY_OPTIMAL
+    // with generate_mipmaps the regions only fill level 0 and the rest is blitted on the graphics queue
+    pub fn upload_image(
+        &self,
+        staging_buffer: AllocatedBuffer,
+        image: vk::Image,
+        extent: vk::Extent3D,
+        mip_levels: u32,
+        regions: &[vk::BufferImageCopy],
+        generate_mipmaps: bool,
+    ) -> Result<u64, VulkanError> {
+        let dedicated = self.dedicated_transfer_queue();
+        let (src_family, dst_family) = self.ownership_families();
+        let final_layout = if generate_mipmaps {
+            // generate_mipmaps expects every level in TRANSFER_DST_OPTIMAL
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL
+        } else {
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
+        };
+        let barrier = |release: bool| vk::ImageMemoryBarrier2 {
+            s_type: vk::StructureType::IMAGE_MEMORY_BARRIER_2,
+            p_next: std::ptr::null(),
+            src_stage_mask: if release || !dedicated {
+                vk::PipelineStageFlags2::ALL_TRANSFER
+            } else {
+                vk::PipelineStageFlags2::NONE
+            },
+            src_access_mask: if release || !dedicated {
+                vk::AccessFlags2::TRANSFER_WRITE
+            } else {
+                vk::AccessFlags2::NONE
+            },
+            dst_stage_mask: if release {
+                vk::PipelineStageFlags2::NONE
+            } else {
+                vk::PipelineStageFlags2::ALL_COMMANDS
+            },
+            dst_access_mask: if release {
+                vk::AccessFlags2::NONE
+            } else {
+                vk::AccessFlags2::MEMORY_READ | vk::AccessFlags2::MEMORY_WRITE
+            },
+            old_layout: vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            new_layout: final_layout,
+            src_queue_family_index: src_family,
+            dst_queue_family_index: dst_family,
+            image,
+            subresource_range: vk::ImageSubresourceRange {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                base_mip_level: 0,
+                level_count: vk::REMAINING_MIP_LEVELS,
+                base_array_layer: 0,
+                layer_count: vk::REMAINING_ARRAY_LAYERS,
+            },
+            ..Default::default()
+        };
+        self.submit(
+            staging_buffer,
+            |device, command_buffer, staging_buffer| {
+                device.transition_image_layout(
+                    command_buffer,
+                    image,
+                    vk::ImageLayout::UNDEFINED,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                );
+                device.cmd_copy_buffer_to_image(
+                    command_buffer,
+                    staging_buffer,
+                    image,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    regions,
+                );
+                if dedicated {
+                    device.cmd_pipeline_barrier(command_buffer, &[barrier(true)], &[]);
+                }
+            },
+            |device, command_buffer| {
+                device.cmd_pipeline_barrier(command_buffer, &[barrier(false)], &[]);
+                if generate_mipmaps {
+                    let extent = vk::Extent2D {
+                        width: extent.width,
+                        height: extent.height,
+                    };
+                    device.generate_mipmaps(command_buffer, image, extent, mip_levels, 1);
+                }
+            },
+        )
+    }
+
+    // release/acquire only if the resource actually changes the queue family
+    fn ownership_families(&self) -> (u32, u32) {
+        if self.dedicated_transfer_queue() {
+            (
+                self.device.get_transfer_queue_idx(),
+                self.device.get_graphics_queue_idx(),
+            )
+        } else {
+            (vk::QUEUE_FAMILY_IGNORED, vk::QUEUE_FAMILY_IGNORED)
+        }
+    }
+
+    // without a dedicated transfer queue both parts end up in one graphics command buffer
+    fn submit<T, G>(
+        &self,
+        staging_buffer: AllocatedBuffer,
+        transfer_commands: T,
+        graphics_commands: G,
+    ) -> Result<u64, VulkanError>
+    where
+        T: FnOnce(&Device, vk::CommandBuffer, vk::Buffer),
+        G: FnOnce(&Device, vk::CommandBuffer),
+    {
+        self.collect()?;
+        let mut state = self.state.lock().unwrap();
+        let mut command_buffers = Vec::new();
+        let result = if self.dedicated_transfer_queue() {
+            self.record_and_submit(
+                self.transfer_pool,
+                &mut command_buffers,
+                &mut state.last_value,
+                false,
+                |device, cmd| transfer_commands(device, cmd, staging_buffer.buffer()),
+            )
+            .and_then(|_| {
+                self.record_and_submit(
+                    self.graphics_pool,
+                    &mut command_buffers,
+                    &mut state.last_value,
+                    true,
+                    graphics_commands,
+                )
+            })
+        } else {
+            self.record_and_submit(
+                self.graphics_pool,
+                &mut command_buffers,
+                &mut state.last_value,
+                false,
+                |device, cmd| {
+                    transfer_commands(device, cmd, staging_buffer.buffer());
+                    graphics_commands(device, cmd);
+                },
+            )
+        };
+        if let Err(e) = result {
+            // a submitted part still references the staging buffer + command buffers
+            if let Err(wait_error) = self.wait(state.last_value) {
+                log::error!("Could not wait for failed upload: {}", wait_error);
+            }
+            for (pool, command_buffer) in command_buffers {
+                self.device.free_command_buffer(pool, command_buffer);
+            }
+            return Err(e);
+        }
+        let value = state.last_value;
+        state.pending.push(PendingUpload {
+            value,
+            command_buffers,
+            _staging_buffer: staging_buffer,
+        });
+        Ok(value)
+    }
+
+    // signals last_value + 1, waits for last_value first if wait_previous is set
+    fn record_and_submit<F>(
+        &self,
+        command_pool: vk::CommandPool,
+        command_buffers: &mut Vec<(vk::CommandPool, vk::CommandBuffer)>,
+        last_value: &mut u64,
+        wait_previous: bool,
+        commands: F,
+    ) -> Result<(), VulkanError>
+    where
+        F: FnOnce(&Device, vk::CommandBuffer),
+    {
+        let command_buffer = self.device.create_command_buffer(command_pool)?;
+        command_buffers.push((command_pool, command_buffer));
+        self.device
+            .begin_command_buffer(command_buffer, vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT)?;
+        commands(&self.device, command_buffer);
+        self.device.end_command_buffer(command_buffer)?;
+
+        let wait_info = semaphore_submit_info(
+            self.timeline,
+            *last_value,
+            vk::PipelineStageFlags2::ALL_COMMANDS,
+        );
+        let signal_info = semaphore_submit_info(
+            self.timeline,
+            *last_value + 1,
+            vk::PipelineStageFlags2::ALL_COMMANDS,
+        );
+        let command_buffer_info = vk::CommandBufferSubmitInfo {
+            s_type: vk::StructureType::COMMAND_BUFFER_SUBMIT_INFO,
+            p_next: std::ptr::null(),
+            command_buffer,
+            ..Default::default()
+        };
+        let submit_info = vk::SubmitInfo2 {
+            s_type: vk::StructureType::SUBMIT_INFO_2,
+            p_next: std::ptr::null(),
+            wait_semaphore_info_count: wait_previous as u32,
+            p_wait_semaphore_infos: &wait_info,
+            command_buffer_info_count: 1,
+            p_command_buffer_infos: &command_buffer_info,
+            signal_semaphore_info_count: 1,
+            p_signal_semaphore_infos: &signal_info,
+            ..Default::default()
+        };
+        if command_pool == self.transfer_pool && self.dedicated_transfer_queue() {
+            self.device
+                .submit_to_transfer_queue(submit_info, vk::Fence::null())?;
+        } else {
+            self.device
+                .submit_to_graphics_queue(submit_info, vk::Fence::null())?;
+        }
+        *last_value += 1;
+        Ok(())
+    }
+
+    // frees staging buffers + command buffers of finished uploads
+    pub fn collect(&self) -> Result<(), VulkanError> {
+        let completed = self.device.get_semaphore_counter_value(self.timeline)?;
+        let mut state = self.state.lock().unwrap();
+        let (finished, pending): (Vec<_>, Vec<_>) = state
+            .pending
+            .drain(..)
+            .partition(|upload| upload.value <= completed);
+        state.pending = pending;
+        for upload in finished {
+            for (pool, command_buffer) in upload.command_buffers {
+                self.device.free_command_buffer(pool, command_buffer);
+            }
+        }
+        Ok(())
+    }
+
+    fn wait(&self, value: u64) -> Result<(), VulkanError> {
+        self.device.wait_semaphore(self.timeline, value, u64::MAX)
+    }
+
+    // blocks until every upload so far is finished, e.g. before using a resource on the cpu side
+    pub fn wait_idle(&self) -> Result<(), VulkanError> {
+        let last_value = self.state.lock().unwrap().last_value;
+        self.wait(last_value)?;
+        self.collect()
+    }
+
+    // for the frame submit => nothing gets drawn with half uploaded resources
+    pub fn wait_semaphore_info(&self) -> vk::SemaphoreSubmitInfo<'static> {
+        semaphore_submit_info(
+            self.timeline,
+            self.state.lock().unwrap().last_value,
+            vk::PipelineStageFlags2::ALL_COMMANDS,
+        )
+    }
+}
+
+impl Drop for AsyncUploader {
+    fn drop(&mut self) {
+        log::debug!("Dropping AsyncUploader");
+        if let Err(e) = self.wait_idle() {
+            log::error!("Could not wait for uploads: {}", e);
+        }
+        self.device.destroy_command_pool(self.transfer_pool);
+        self.device.destroy_command_pool(self.graphics_pool);
+        self.device.destroy_semaphore(self.timeline);
+    }
+}
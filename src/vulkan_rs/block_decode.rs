@@ -0,0 +1,447 @@
+use ash::vk;
+
+// cpu decoders for block compressed formats the gpu can't sample (e.g. bc on mobile, etc2 on
+// most desktop gpus) => the texture is uploaded as rgba8 instead, 4x the memory but it shows up
+// astc, bc6h and bc7 have no decoder here
+
+// rgba8 format the blocks of format are decoded to, None => no decoder for it
+// single and two channel formats keep their channels in r/g like the gpu would sample them
+pub fn decoded_format(format: vk::Format) -> Option<vk::Format> {
+    let decoded = match format {
+        vk::Format::BC1_RGB_SRGB_BLOCK
+        | vk::Format::BC1_RGBA_SRGB_BLOCK
+        | vk::Format::BC2_SRGB_BLOCK
+        | vk::Format::BC3_SRGB_BLOCK
+        | vk::Format::ETC2_R8G8B8_SRGB_BLOCK
+        | vk::Format::ETC2_R8G8B8A8_SRGB_BLOCK => vk::Format::R8G8B8A8_SRGB,
+        vk::Format::BC1_RGB_UNORM_BLOCK
+        | vk::Format::BC1_RGBA_UNORM_BLOCK
+        | vk::Format::BC2_UNORM_BLOCK
+        | vk::Format::BC3_UNORM_BLOCK
+        | vk::Format::BC4_UNORM_BLOCK
+        | vk::Format::BC5_UNORM_BLOCK
+        | vk::Format::ETC2_R8G8B8_UNORM_BLOCK
+        | vk::Format::ETC2_R8G8B8A8_UNORM_BLOCK => vk::Format::R8G8B8A8_UNORM,
+        _ => return None,
+    };
+    Some(decoded)
+}
+
+// one image of width x height pixels in 4x4 blocks, row by row => rgba8 rows
+// format has to have a decoded_format and data has to hold every block
+pub fn decode(format: vk::Format, data: &[u8], width: u32, height: u32) -> Vec<u8> {
+    let block_bytes = match format {
+        vk::Format::BC1_RGB_UNORM_BLOCK
+        | vk::Format::BC1_RGB_SRGB_BLOCK
+        | vk::Format::BC1_RGBA_UNORM_BLOCK
+        | vk::Format::BC1_RGBA_SRGB_BLOCK
+        | vk::Format::BC4_UNORM_BLOCK
+        | vk::Format::ETC2_R8G8B8_UNORM_BLOCK
+        | vk::Format::ETC2_R8G8B8_SRGB_BLOCK => 8,
+        _ => 16,
+    };
+    let width = width.max(1) as usize;
+    let height = height.max(1) as usize;
+    let blocks_x = width.div_ceil(4);
+    let mut pixels = vec![0u8; width * height * 4];
+    for (idx, block) in data.chunks_exact(block_bytes).enumerate() {
+        let block_x = (idx % blocks_x) * 4;
+        let block_y = (idx / blocks_x) * 4;
+        if block_y >= height {
+            break;
+        }
+        let texels = decode_block(format, block);
+        // blocks at the right and bottom edge can reach past the image
+        for y in 0..4.min(height - block_y) {
+            for x in 0..4.min(width - block_x) {
+                let offset = ((block_y + y) * width + block_x + x) * 4;
+                pixels[offset..offset + 4].copy_from_slice(&texels[y * 4 + x]);
+            }
+        }
+    }
+    pixels
+}
+
+// 16 texels row by row
+fn decode_block(format: vk::Format, block: &[u8]) -> [[u8; 4]; 16] {
+    match format {
+        vk::Format::BC1_RGB_UNORM_BLOCK | vk::Format::BC1_RGB_SRGB_BLOCK => {
+            decode_bc1(block, BC1Alpha::Opaque)
+        }
+        vk::Format::BC1_RGBA_UNORM_BLOCK | vk::Format::BC1_RGBA_SRGB_BLOCK => {
+            decode_bc1(block, BC1Alpha::Punchthrough)
+        }
+        vk::Format::BC2_UNORM_BLOCK | vk::Format::BC2_SRGB_BLOCK => {
+            let mut texels = decode_bc1(&block[8..], BC1Alpha::FourColors);
+            let alpha = u64::from_le_bytes(block[..8].try_into().unwrap());
+            for (idx, texel) in texels.iter_mut().enumerate() {
+                texel[3] = ((alpha >> (idx * 4)) & 0xF) as u8 * 17;
+            }
+            texels
+        }
+        vk::Format::BC3_UNORM_BLOCK | vk::Format::BC3_SRGB_BLOCK => {
+            let mut texels = decode_bc1(&block[8..], BC1Alpha::FourColors);
+            for (texel, alpha) in texels.iter_mut().zip(decode_bc4(&block[..8])) {
+                texel[3] = alpha;
+            }
+            texels
+        }
+        vk::Format::BC4_UNORM_BLOCK => decode_bc4(block).map(|red| [red, 0, 0, 255]),
+        vk::Format::BC5_UNORM_BLOCK => {
+            let red = decode_bc4(&block[..8]);
+            let green = decode_bc4(&block[8..]);
+            std::array::from_fn(|idx| [red[idx], green[idx], 0, 255])
+        }
+        vk::Format::ETC2_R8G8B8_UNORM_BLOCK | vk::Format::ETC2_R8G8B8_SRGB_BLOCK => {
+            decode_etc2(block)
+        }
+        vk::Format::ETC2_R8G8B8A8_UNORM_BLOCK | vk::Format::ETC2_R8G8B8A8_SRGB_BLOCK => {
+            let mut texels = decode_etc2(&block[8..]);
+            for (texel, alpha) in texels.iter_mut().zip(decode_eac(&block[..8])) {
+                texel[3] = alpha;
+            }
+            texels
+        }
+        _ => unreachable!("{:?} has no decoder", format),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BC1Alpha {
+    // color0 <= color1 => 3 colors + black
+    Opaque,
+    // color0 <= color1 => 3 colors + transparent black
+    Punchthrough,
+    // color block of bc2/bc3, always 4 colors
+    FourColors,
+}
+
+fn rgb565(color: u16) -> [u32; 3] {
+    let r = (color >> 11) as u32 & 0x1F;
+    let g = (color >> 5) as u32 & 0x3F;
+    let b = color as u32 & 0x1F;
+    [r << 3 | r >> 2, g << 2 | g >> 4, b << 3 | b >> 2]
+}
+
+fn decode_bc1(block: &[u8], alpha: BC1Alpha) -> [[u8; 4]; 16] {
+    let color0 = u16::from_le_bytes([block[0], block[1]]);
+    let color1 = u16::from_le_bytes([block[2], block[3]]);
+    let indices = u32::from_le_bytes([block[4], block[5], block[6], block[7]]);
+    let c0 = rgb565(color0);
+    let c1 = rgb565(color1);
+    let mix = |w0: u32, w1: u32| -> [u8; 4] {
+        let channel = |i: usize| ((c0[i] * w0 + c1[i] * w1) / (w0 + w1)) as u8;
+        [channel(0), channel(1), channel(2), 255]
+    };
+    let palette = if color0 > color1 || alpha == BC1Alpha::FourColors {
+        [mix(1, 0), mix(0, 1), mix(2, 1), mix(1, 2)]
+    } else {
+        let black = if alpha == BC1Alpha::Punchthrough {
+            [0, 0, 0, 0]
+        } else {
+            [0, 0, 0, 255]
+        };
+        [mix(1, 0), mix(0, 1), mix(1, 1), black]
+    };
+    std::array::from_fn(|idx| palette[(indices >> (idx * 2)) as usize & 3])
+}
+
+// bc4 block or the alpha block of bc3
+fn decode_bc4(block: &[u8]) -> [u8; 16] {
+    let a0 = block[0] as u32;
+    let a1 = block[1] as u32;
+    let mut bits = [0; 8];
+    bits[..6].copy_from_slice(&block[2..8]);
+    let indices = u64::from_le_bytes(bits);
+    let palette: [u8; 8] = std::array::from_fn(|idx| {
+        let idx = idx as u32;
+        match idx {
+            0 => a0 as u8,
+            1 => a1 as u8,
+            _ if a0 > a1 => (((8 - idx) * a0 + (idx - 1) * a1) / 7) as u8,
+            6 => 0,
+            7 => 255,
+            _ => (((6 - idx) * a0 + (idx - 1) * a1) / 5) as u8,
+        }
+    });
+    std::array::from_fn(|idx| palette[(indices >> (idx * 3)) as usize & 7])
+}
+
+const ETC_MODIFIERS: [[i32; 2]; 8] = [
+    [2, 8],
+    [5, 17],
+    [9, 29],
+    [13, 42],
+    [18, 60],
+    [24, 80],
+    [33, 106],
+    [47, 183],
+];
+
+const ETC_DISTANCES: [i32; 8] = [3, 6, 11, 16, 23, 32, 41, 64];
+
+fn extend_4(value: u64) -> i32 {
+    let value = (value & 0xF) as i32;
+    value << 4 | value
+}
+
+fn extend_5(value: u64) -> i32 {
+    let value = (value & 0x1F) as i32;
+    value << 3 | value >> 2
+}
+
+fn extend_6(value: u64) -> i32 {
+    let value = (value & 0x3F) as i32;
+    value << 2 | value >> 4
+}
+
+fn extend_7(value: u64) -> i32 {
+    let value = (value & 0x7F) as i32;
+    value << 1 | value >> 6
+}
+
+fn rgb_texel(color: [i32; 3]) -> [u8; 4] {
+    [
+        color[0].clamp(0, 255) as u8,
+        color[1].clamp(0, 255) as u8,
+        color[2].clamp(0, 255) as u8,
+        255,
+    ]
+}
+
+fn offset_rgb(color: [i32; 3], offset: i32) -> [i32; 3] {
+    color.map(|channel| channel + offset)
+}
+
+// etc2 rgb block, etc1 blocks are a subset of it
+fn decode_etc2(block: &[u8]) -> [[u8; 4]; 16] {
+    let bits = u64::from_be_bytes(block[..8].try_into().unwrap());
+    // 2 bit index per texel, column by column, msb in the upper half
+    let texel_index = |x: usize, y: usize| {
+        let idx = x * 4 + y;
+        ((bits >> (idx + 16)) & 1) << 1 | (bits >> idx) & 1
+    };
+    let differential = bits >> 33 & 1 == 1;
+    let flip = bits >> 32 & 1 == 1;
+
+    let (color0, color1) = if differential {
+        let r = (bits >> 59 & 0x1F) as i32;
+        let g = (bits >> 51 & 0x1F) as i32;
+        let b = (bits >> 43 & 0x1F) as i32;
+        // 3 bit two's complement
+        let delta = |shift: u64| ((bits >> shift & 7) as i32) << 29 >> 29;
+        let (r2, g2, b2) = (r + delta(56), g + delta(48), b + delta(40));
+        // overflows mark the etc2 modes
+        if !(0..32).contains(&r2) {
+            return decode_etc2_t(bits, texel_index);
+        }
+        if !(0..32).contains(&g2) {
+            return decode_etc2_h(bits, texel_index);
+        }
+        if !(0..32).contains(&b2) {
+            return decode_etc2_planar(bits);
+        }
+        let extend = |value: i32| extend_5(value as u64);
+        (
+            [extend(r), extend(g), extend(b)],
+            [extend(r2), extend(g2), extend(b2)],
+        )
+    } else {
+        (
+            [
+                extend_4(bits >> 60),
+                extend_4(bits >> 52),
+                extend_4(bits >> 44),
+            ],
+            [
+                extend_4(bits >> 56),
+                extend_4(bits >> 48),
+                extend_4(bits >> 40),
+            ],
+        )
+    };
+    let table0 = ETC_MODIFIERS[(bits >> 37 & 7) as usize];
+    let table1 = ETC_MODIFIERS[(bits >> 34 & 7) as usize];
+    std::array::from_fn(|idx| {
+        let (x, y) = (idx % 4, idx / 4);
+        // flip => two 4x2 halves on top of each other, otherwise two 2x4 halves side by side
+        let second = if flip { y >= 2 } else { x >= 2 };
+        let (base, table) = if second {
+            (color1, table1)
+        } else {
+            (color0, table0)
+        };
+        let modifier = match texel_index(x, y) {
+            0 => table[0],
+            1 => table[1],
+            2 => -table[0],
+            _ => -table[1],
+        };
+        rgb_texel(offset_rgb(base, modifier))
+    })
+}
+
+fn decode_etc2_t(bits: u64, texel_index: impl Fn(usize, usize) -> u64) -> [[u8; 4]; 16] {
+    let color0 = [
+        extend_4((bits >> 59 & 3) << 2 | bits >> 56 & 3),
+        extend_4(bits >> 52),
+        extend_4(bits >> 48),
+    ];
+    let color1 = [
+        extend_4(bits >> 44),
+        extend_4(bits >> 40),
+        extend_4(bits >> 36),
+    ];
+    let distance = ETC_DISTANCES[((bits >> 34 & 3) << 1 | bits >> 32 & 1) as usize];
+    let palette = [
+        rgb_texel(color0),
+        rgb_texel(offset_rgb(color1, distance)),
+        rgb_texel(color1),
+        rgb_texel(offset_rgb(color1, -distance)),
+    ];
+    std::array::from_fn(|idx| palette[texel_index(idx % 4, idx / 4) as usize])
+}
+
+fn decode_etc2_h(bits: u64, texel_index: impl Fn(usize, usize) -> u64) -> [[u8; 4]; 16] {
+    let r0 = bits >> 59 & 0xF;
+    let g0 = (bits >> 56 & 7) << 1 | bits >> 52 & 1;
+    let b0 = (bits >> 51 & 1) << 3 | bits >> 47 & 7;
+    let r1 = bits >> 43 & 0xF;
+    let g1 = bits >> 39 & 0xF;
+    let b1 = bits >> 35 & 0xF;
+    // the order of the colors is the lowest bit of the distance index
+    let order = (r0 << 8 | g0 << 4 | b0) >= (r1 << 8 | g1 << 4 | b1);
+    let distance_idx = (bits >> 34 & 1) << 2 | (bits >> 32 & 1) << 1 | order as u64;
+    let distance = ETC_DISTANCES[distance_idx as usize];
+    let color0 = [extend_4(r0), extend_4(g0), extend_4(b0)];
+    let color1 = [extend_4(r1), extend_4(g1), extend_4(b1)];
+    let palette = [
+        rgb_texel(offset_rgb(color0, distance)),
+        rgb_texel(offset_rgb(color0, -distance)),
+        rgb_texel(offset_rgb(color1, distance)),
+        rgb_texel(offset_rgb(color1, -distance)),
+    ];
+    std::array::from_fn(|idx| palette[texel_index(idx % 4, idx / 4) as usize])
+}
+
+// origin, horizontal and vertical color, the texels are interpolated between them
+fn decode_etc2_planar(bits: u64) -> [[u8; 4]; 16] {
+    let origin = [
+        extend_6(bits >> 57),
+        extend_7((bits >> 56 & 1) << 6 | bits >> 49 & 0x3F),
+        extend_6((bits >> 48 & 1) << 5 | (bits >> 43 & 3) << 3 | bits >> 39 & 7),
+    ];
+    let horizontal = [
+        extend_6((bits >> 34 & 0x1F) << 1 | bits >> 32 & 1),
+        extend_7(bits >> 25),
+        extend_6(bits >> 19),
+    ];
+    let vertical = [extend_6(bits >> 13), extend_7(bits >> 6), extend_6(bits)];
+    std::array::from_fn(|idx| {
+        let (x, y) = ((idx % 4) as i32, (idx / 4) as i32);
+        rgb_texel(std::array::from_fn(|channel| {
+            (x * (horizontal[channel] - origin[channel])
+                + y * (vertical[channel] - origin[channel])
+                + 4 * origin[channel]
+                + 2)
+                >> 2
+        }))
+    })
+}
+
+const EAC_MODIFIERS: [[i32; 8]; 16] = [
+    [-3, -6, -9, -15, 2, 5, 8, 14],
+    [-3, -7, -10, -13, 2, 6, 9, 12],
+    [-2, -5, -8, -13, 1, 4, 7, 12],
+    [-2, -4, -6, -13, 1, 3, 5, 12],
+    [-3, -6, -8, -12, 2, 5, 7, 11],
+    [-3, -7, -9, -11, 2, 6, 8, 10],
+    [-4, -7, -8, -11, 3, 6, 7, 10],
+    [-3, -5, -8, -11, 2, 4, 7, 10],
+    [-2, -6, -8, -10, 1, 5, 7, 9],
+    [-2, -5, -8, -10, 1, 4, 7, 9],
+    [-2, -4, -8, -10, 1, 3, 7, 9],
+    [-2, -5, -7, -10, 1, 4, 6, 9],
+    [-3, -4, -7, -10, 2, 3, 6, 9],
+    [-1, -2, -3, -10, 0, 1, 2, 9],
+    [-4, -6, -8, -9, 3, 5, 7, 8],
+    [-3, -5, -7, -9, 2, 4, 6, 8],
+];
+
+// alpha block of etc2 rgba8
+fn decode_eac(block: &[u8]) -> [u8; 16] {
+    let bits = u64::from_be_bytes(block[..8].try_into().unwrap());
+    let base = (bits >> 56) as i32;
+    let multiplier = (bits >> 52 & 0xF) as i32;
+    let table = EAC_MODIFIERS[(bits >> 48 & 0xF) as usize];
+    std::array::from_fn(|idx| {
+        // 3 bit index per texel, column by column
+        let (x, y) = (idx % 4, idx / 4);
+        let modifier = table[(bits >> (45 - 3 * (x * 4 + y)) & 7) as usize];
+        (base + modifier * multiplier).clamp(0, 255) as u8
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bc1_solid_block() {
+        // both colors pure red, every texel uses color0
+        let block = [0x00, 0xF8, 0x00, 0xF8, 0, 0, 0, 0];
+        let pixels = decode(vk::Format::BC1_RGB_UNORM_BLOCK, &block, 4, 4);
+        assert!(pixels.chunks(4).all(|texel| texel == [255, 0, 0, 255]));
+    }
+
+    #[test]
+    fn bc1_punchthrough_alpha() {
+        // color0 <= color1 and index 3 => transparent black
+        let block = [0, 0, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF];
+        let pixels = decode(vk::Format::BC1_RGBA_UNORM_BLOCK, &block, 4, 4);
+        assert!(pixels.chunks(4).all(|texel| texel == [0, 0, 0, 0]));
+        let pixels = decode(vk::Format::BC1_RGB_UNORM_BLOCK, &block, 4, 4);
+        assert!(pixels.chunks(4).all(|texel| texel == [0, 0, 0, 255]));
+    }
+
+    #[test]
+    fn bc4_interpolates_between_the_endpoints() {
+        // index 0 for the first texel, index 1 for the second, the rest index 2 = 6/7 a0 + 1/7 a1
+        let indices: u64 = (2..16).fold(1 << 3, |bits, idx| bits | 2 << (idx * 3));
+        let mut block = [255, 3, 0, 0, 0, 0, 0, 0];
+        block[2..].copy_from_slice(&indices.to_le_bytes()[..6]);
+        let pixels = decode(vk::Format::BC4_UNORM_BLOCK, &block, 4, 4);
+        assert_eq!(
+            &pixels[..12],
+            &[255, 0, 0, 255, 3, 0, 0, 255, 219, 0, 0, 255]
+        );
+    }
+
+    #[test]
+    fn partial_blocks_are_cropped() {
+        // 5x5 => 2x2 blocks, only the pixels inside the image are written
+        let block = [0x00, 0xF8, 0x00, 0xF8, 0, 0, 0, 0];
+        let data = block.repeat(4);
+        let pixels = decode(vk::Format::BC1_RGB_UNORM_BLOCK, &data, 5, 5);
+        assert_eq!(pixels.len(), 5 * 5 * 4);
+    }
+
+    #[test]
+    fn etc1_individual_block() {
+        // both halves 0x88 gray, table 0, all texels index 0 => +2
+        let block = [0x88, 0x88, 0x88, 0x00, 0, 0, 0, 0];
+        let pixels = decode(vk::Format::ETC2_R8G8B8_UNORM_BLOCK, &block, 4, 4);
+        assert!(pixels
+            .chunks(4)
+            .all(|texel| texel == [0x8A, 0x8A, 0x8A, 255]));
+    }
+
+    #[test]
+    fn eac_alpha_block() {
+        // base 128, multiplier 1, table 0, all indices 4 => +2
+        let mut block = [128, 0x10, 0, 0, 0, 0, 0, 0];
+        let indices: u64 = (0..16).fold(0, |bits, _| bits << 3 | 4);
+        block[2..].copy_from_slice(&indices.to_be_bytes()[2..]);
+        assert!(decode_eac(&block).iter().all(|alpha| *alpha == 130));
+    }
+}
@@ -0,0 +1,539 @@
+use super::allocation::AllocatedBuffer;
+use super::allocation::Allocator;
+use super::async_upload::AsyncUploader;
+use super::descriptor::DescriptorAllocatorGrowable;
+use super::descriptor::DescriptorLayoutBuilder;
+use super::descriptor::DescriptorSetLayout;
+use super::descriptor::DescriptorWriter;
+use super::descriptor::PoolSizeRatio;
+use super::device::Device;
+use super::error::VulkanError;
+use super::immediate_submit::ImmediateCommandData;
+use super::material::Material;
+use super::mesh::Aabb;
+use super::mesh::BoundingSphere;
+use super::mesh::GPUMeshBuffers;
+use super::mesh::GeometricSurface;
+use super::mesh::MeshAsset;
+use super::mesh::Vertex;
+use super::pipelines::ComputePipeline;
+use super::pipelines::PushConstants;
+use super::render_graph::BufferHandle;
+use super::render_graph::BufferUsage;
+use super::render_graph::GraphPass;
+use super::render_graph::RenderGraph;
+use super::shader::ShaderModule;
+use ash::vk;
+use nalgebra_glm as glm;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+// has to match the collider array in cloth.comp
+pub const MAX_CLOTH_COLLIDERS: usize = 8;
+// the solver runs with a fixed time step => results dont depend on the frame rate
+const TIME_STEP: f32 = 1.0 / 120.0;
+// after a hitch we rather slow down the simulation than stall the frame
+const MAX_STEPS_PER_FRAME: u32 = 4;
+
+// matches the MODE_ constants in cloth.comp
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SolverMode {
+    Integrate = 0,
+    Constraints = 1,
+    Normals = 2,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ClothCollider {
+    Sphere {
+        center: glm::Vec3,
+        radius: f32,
+    },
+    Capsule {
+        start: glm::Vec3,
+        end: glm::Vec3,
+        radius: f32,
+    },
+}
+
+// matches Collider in cloth.comp, spheres are capsules with start == end
+#[repr(C)]
+#[derive(Debug, bytemuck::NoUninit, Copy, Clone)]
+struct GPUCollider {
+    start_radius: glm::Vec4,
+    end: glm::Vec4,
+}
+
+impl From<&ClothCollider> for GPUCollider {
+    fn from(collider: &ClothCollider) -> Self {
+        let (start, end, radius) = match *collider {
+            ClothCollider::Sphere { center, radius } => (center, center, radius),
+            ClothCollider::Capsule { start, end, radius } => (start, end, radius),
+        };
+        GPUCollider {
+            start_radius: glm::vec4(start.x, start.y, start.z, radius),
+            end: glm::vec4(end.x, end.y, end.z, 0.0),
+        }
+    }
+}
+
+// rectangular grid of particles. Particle (column, row) starts at
+// origin + width * column / (columns - 1) + height * row / (rows - 1)
+#[derive(Debug, Clone)]
+pub struct ClothSettings {
+    pub columns: u32,
+    pub rows: u32,
+    pub origin: glm::Vec3,
+    pub width: glm::Vec3,
+    pub height: glm::Vec3,
+    // (column, row) of particles that dont move
+    pub pinned: Vec<(u32, u32)>,
+    // 0..1, how strongly the distance constraints are enforced per iteration
+    pub stiffness: f32,
+    // 0..1, fraction of the velocity that is lost per step
+    pub damping: f32,
+    // rounded up to an odd number => the result always ends up in the same buffer
+    pub iterations: u32,
+    pub gravity: glm::Vec3,
+    pub color: glm::Vec4,
+}
+
+impl Default for ClothSettings {
+    // horizontal sheet pinned at two corners => falls down and swings
+    fn default() -> Self {
+        ClothSettings {
+            columns: 32,
+            rows: 32,
+            origin: glm::vec3(-1.5, 2.5, -1.5),
+            width: glm::vec3(3.0, 0.0, 0.0),
+            height: glm::vec3(0.0, 0.0, 3.0),
+            pinned: vec![(0, 0), (31, 0)],
+            stiffness: 1.0,
+            damping: 0.01,
+            iterations: 15,
+            gravity: glm::vec3(0.0, -9.81, 0.0),
+            color: glm::vec4(0.8, 0.2, 0.2, 1.0),
+        }
+    }
+}
+
+pub struct Cloth {
+    settings: ClothSettings,
+    mesh: MeshAsset,
+    // particles are simulated in world space
+    world_transform: glm::Mat4,
+    // ping pong buffers, the current state is always in the first one
+    positions: [AllocatedBuffer; 2],
+    previous_positions: AllocatedBuffer,
+    // [0]: first -> second buffer, [1]: second -> first buffer
+    particle_descriptors: [vk::DescriptorSet; 2],
+    // written by the host => one per frame in flight
+    collider_buffers: Vec<AllocatedBuffer>,
+    collider_descriptors: Vec<vk::DescriptorSet>,
+    colliders: Vec<ClothCollider>,
+    wind: glm::Vec3,
+    time: f32,
+}
+
+impl Cloth {
+    pub fn settings(&self) -> &ClothSettings {
+        &self.settings
+    }
+
+    pub fn mesh(&self) -> &MeshAsset {
+        &self.mesh
+    }
+
+    pub fn world_transform(&self) -> &glm::Mat4 {
+        &self.world_transform
+    }
+
+    pub fn colliders(&self) -> &[ClothCollider] {
+        &self.colliders
+    }
+
+    // colliders after the first MAX_CLOTH_COLLIDERS are ignored
+    pub fn set_colliders(&mut self, colliders: &[ClothCollider]) {
+        if colliders.len() > MAX_CLOTH_COLLIDERS {
+            log::warn!(
+                "Cloth supports at most {} colliders, ignoring {}",
+                MAX_CLOTH_COLLIDERS,
+                colliders.len() - MAX_CLOTH_COLLIDERS
+            );
+        }
+        self.colliders = colliders
+            .iter()
+            .take(MAX_CLOTH_COLLIDERS)
+            .copied()
+            .collect();
+    }
+
+    pub fn wind(&self) -> glm::Vec3 {
+        self.wind
+    }
+
+    pub fn set_wind(&mut self, wind: glm::Vec3) {
+        self.wind = wind;
+    }
+
+    fn upload_colliders(&mut self, frame_slot: usize) {
+        let mut gpu_colliders = [GPUCollider {
+            start_radius: glm::Vec4::zeros(),
+            end: glm::Vec4::zeros(),
+        }; MAX_CLOTH_COLLIDERS];
+        for (gpu_collider, collider) in gpu_colliders.iter_mut().zip(self.colliders.iter()) {
+            *gpu_collider = collider.into();
+        }
+        self.collider_buffers[frame_slot].copy_from_slice(&gpu_colliders, 0);
+    }
+}
+
+// owns what all cloths share: the pipeline and the descriptor layouts/pool
+// time is accumulated here and simulated in fixed steps when the passes are recorded
+pub struct ClothSolver {
+    device: Arc<Device>,
+    allocator: Arc<Mutex<Allocator>>,
+    descriptor_allocator: DescriptorAllocatorGrowable,
+    particle_layout: DescriptorSetLayout,
+    collider_layout: DescriptorSetLayout,
+    pipeline: ComputePipeline,
+    accumulated_time: f32,
+}
+
+impl ClothSolver {
+    pub fn new(device: Arc<Device>, allocator: Arc<Mutex<Allocator>>) -> Result<Self, VulkanError> {
+        let mut builder = DescriptorLayoutBuilder::new();
+        for binding in 0..4 {
+            builder.add_binding(
+                binding,
+                vk::DescriptorType::STORAGE_BUFFER,
+                vk::ShaderStageFlags::COMPUTE,
+            );
+        }
+        let particle_layout =
+            builder.build(device.clone(), vk::DescriptorSetLayoutCreateFlags::empty())?;
+
+        let mut builder = DescriptorLayoutBuilder::new();
+        builder.add_binding(
+            0,
+            vk::DescriptorType::UNIFORM_BUFFER,
+            vk::ShaderStageFlags::COMPUTE,
+        );
+        let collider_layout =
+            builder.build(device.clone(), vk::DescriptorSetLayoutCreateFlags::empty())?;
+
+        let sizes = vec![
+            PoolSizeRatio {
+                descriptor_type: vk::DescriptorType::STORAGE_BUFFER,
+                ratio: 4.0,
+            },
+            PoolSizeRatio {
+                descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
+                ratio: 1.0,
+            },
+        ];
+        let mut descriptor_allocator =
+            DescriptorAllocatorGrowable::new(device.clone(), "cloth descriptors", sizes, 16);
+        descriptor_allocator.init_pool()?;
+
+        let shader = ShaderModule::new(device.clone(), "shaders/cloth_comp.spv")?;
+        let pipeline = ComputePipeline::new(
+            device.clone(),
+            &[particle_layout.layout(), collider_layout.layout()],
+            shader,
+        )?;
+
+        Ok(ClothSolver {
+            device,
+            allocator,
+            descriptor_allocator,
+            particle_layout,
+            collider_layout,
+            pipeline,
+            accumulated_time: 0.0,
+        })
+    }
+
+    pub fn create_cloth(
+        &mut self,
+        immediate_command: &ImmediateCommandData,
+        uploader: &AsyncUploader,
+        material: Arc<Material>,
+        settings: ClothSettings,
+        frames_in_flight: usize,
+    ) -> Result<Cloth, VulkanError> {
+        let mut settings = settings;
+        settings.columns = settings.columns.max(2);
+        settings.rows = settings.rows.max(2);
+        settings.iterations |= 1;
+        let (columns, rows) = (settings.columns, settings.rows);
+
+        let mut particles = Vec::with_capacity((columns * rows) as usize);
+        for row in 0..rows {
+            for column in 0..columns {
+                let position = settings.origin
+                    + settings.width * (column as f32 / (columns - 1) as f32)
+                    + settings.height * (row as f32 / (rows - 1) as f32);
+                let inverse_mass = if settings.pinned.contains(&(column, row)) {
+                    0.0
+                } else {
+                    1.0
+                };
+                particles.push(glm::vec4(position.x, position.y, position.z, inverse_mass));
+            }
+        }
+
+        // front and back side get their own vertices => normals can point away from each side
+        let normal = glm::normalize(&glm::cross(&settings.width, &settings.height));
+        let mut vertices = Vec::with_capacity(particles.len() * 2);
+        for side_normal in [normal, -normal] {
+            for (idx, particle) in particles.iter().enumerate() {
+                let (column, row) = (idx as u32 % columns, idx as u32 / columns);
+                let uv_x = column as f32 / (columns - 1) as f32;
+                let uv_y = row as f32 / (rows - 1) as f32;
+                vertices.push(Vertex::new(
+                    particle.xyz(),
+                    uv_x,
+                    side_normal,
+                    uv_y,
+                    settings.color,
+                ));
+            }
+        }
+        let back_offset = particles.len() as u32;
+        let mut indices = Vec::with_capacity(((columns - 1) * (rows - 1) * 12) as usize);
+        for row in 0..rows - 1 {
+            for column in 0..columns - 1 {
+                let idx = row * columns + column;
+                let quad = [
+                    idx,
+                    idx + 1,
+                    idx + columns,
+                    idx + 1,
+                    idx + columns + 1,
+                    idx + columns,
+                ];
+                indices.extend_from_slice(&quad);
+                // back side with reversed winding
+                indices.extend(quad.iter().rev().map(|idx| idx + back_offset));
+            }
+        }
+        let buffers = GPUMeshBuffers::upload_mesh(
+            self.device.clone(),
+            self.allocator.clone(),
+            &indices,
+            &vertices,
+            uploader,
+        )?;
+        // the simulation moves the vertices on the gpu => the cloth is never culled
+        let surface = GeometricSurface::new(0, indices.len() as u32, material, Aabb::infinite());
+        let mesh = MeshAsset::new("Cloth", vec![surface], buffers, BoundingSphere::infinite());
+
+        let positions = [
+            self.create_particle_buffer("Cloth Positions", &particles, immediate_command)?,
+            self.create_particle_buffer("Cloth Positions", &particles, immediate_command)?,
+        ];
+        let previous_positions =
+            self.create_particle_buffer("Cloth Previous Positions", &particles, immediate_command)?;
+
+        let buffer_size = std::mem::size_of_val(particles.as_slice()) as u64;
+        let vertex_buffer_size = std::mem::size_of_val(vertices.as_slice()) as u64;
+        let mut particle_descriptors = [vk::DescriptorSet::null(); 2];
+        for (idx, descriptor) in particle_descriptors.iter_mut().enumerate() {
+            *descriptor = self.descriptor_allocator.allocate(&self.particle_layout)?;
+            let mut writer = DescriptorWriter::new();
+            for (binding, buffer, size) in [
+                (0, positions[idx].buffer(), buffer_size),
+                (1, positions[1 - idx].buffer(), buffer_size),
+                (2, previous_positions.buffer(), buffer_size),
+                (3, mesh.buffers().vertex_buffer(), vertex_buffer_size),
+            ] {
+                writer.add_buffer(binding, buffer, size, 0, vk::DescriptorType::STORAGE_BUFFER);
+            }
+            writer.update_descriptor_set(&self.device, *descriptor);
+        }
+
+        let collider_size = (std::mem::size_of::<GPUCollider>() * MAX_CLOTH_COLLIDERS) as u64;
+        let mut collider_buffers = Vec::with_capacity(frames_in_flight);
+        let mut collider_descriptors = Vec::with_capacity(frames_in_flight);
+        for _ in 0..frames_in_flight {
+            let buffer = AllocatedBuffer::new(
+                self.device.clone(),
+                self.allocator.clone(),
+                "Cloth Colliders",
+                vk::BufferUsageFlags::UNIFORM_BUFFER,
+                collider_size,
+                gpu_allocator::MemoryLocation::CpuToGpu,
+            )?;
+            let descriptor = self.descriptor_allocator.allocate(&self.collider_layout)?;
+            let mut writer = DescriptorWriter::new();
+            writer.add_uniform_buffer(0, buffer.buffer(), collider_size, 0);
+            writer.update_descriptor_set(&self.device, descriptor);
+            collider_buffers.push(buffer);
+            collider_descriptors.push(descriptor);
+        }
+
+        Ok(Cloth {
+            settings,
+            mesh,
+            world_transform: glm::Mat4::identity(),
+            positions,
+            previous_positions,
+            particle_descriptors,
+            collider_buffers,
+            collider_descriptors,
+            colliders: Vec::new(),
+            wind: glm::Vec3::zeros(),
+            time: 0.0,
+        })
+    }
+
+    fn create_particle_buffer(
+        &self,
+        name: &str,
+        particles: &[glm::Vec4],
+        immediate_command: &ImmediateCommandData,
+    ) -> Result<AllocatedBuffer, VulkanError> {
+        let size = std::mem::size_of_val(particles) as vk::DeviceSize;
+        let buffer = AllocatedBuffer::new(
+            self.device.clone(),
+            self.allocator.clone(),
+            name,
+            vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::TRANSFER_DST,
+            size,
+            gpu_allocator::MemoryLocation::GpuOnly,
+        )?;
+        let mut staging_buffer = AllocatedBuffer::new(
+            self.device.clone(),
+            self.allocator.clone(),
+            "Staging Buffer",
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            size,
+            gpu_allocator::MemoryLocation::CpuToGpu,
+        )?;
+        staging_buffer.copy_from_slice(particles, 0);
+        immediate_command.immediate_submit(|device, command_buffer| {
+            let copy = vk::BufferCopy {
+                src_offset: 0,
+                dst_offset: 0,
+                size,
+            };
+            device.cmd_copy_buffer(
+                command_buffer,
+                staging_buffer.buffer(),
+                buffer.buffer(),
+                &[copy],
+            );
+        })?;
+        Ok(buffer)
+    }
+
+    pub fn advance(&mut self, delta_time: f32) {
+        self.accumulated_time =
+            (self.accumulated_time + delta_time).min(TIME_STEP * MAX_STEPS_PER_FRAME as f32);
+    }
+
+    // number of fixed steps that have to be simulated this frame, the rest stays accumulated
+    pub fn take_steps(&mut self) -> u32 {
+        let steps = (self.accumulated_time / TIME_STEP) as u32;
+        self.accumulated_time -= steps as f32 * TIME_STEP;
+        steps
+    }
+
+    // has to be called before add_passes, cloths cant be borrowed mutably while the graph is built
+    pub fn prepare(&self, cloth: &mut Cloth, frame_slot: usize, steps: u32) {
+        cloth.upload_colliders(frame_slot);
+        cloth.time += steps as f32 * TIME_STEP;
+    }
+
+    // adds the simulation passes of one cloth. The returned vertex buffer has to be declared
+    // as StorageRead by the pass that draws the cloth
+    pub fn add_passes<'a>(
+        &'a self,
+        graph: &mut RenderGraph<'a>,
+        cloth: &'a Cloth,
+        frame_slot: usize,
+        steps: u32,
+    ) -> BufferHandle {
+        let positions = [
+            graph.import_buffer("cloth positions", cloth.positions[0].buffer()),
+            graph.import_buffer("cloth positions", cloth.positions[1].buffer()),
+        ];
+        let previous = graph.import_buffer(
+            "cloth previous positions",
+            cloth.previous_positions.buffer(),
+        );
+        let vertices = graph.import_buffer("cloth vertices", cloth.mesh.buffers().vertex_buffer());
+
+        let settings = &cloth.settings;
+        let collider_descriptor = cloth.collider_descriptors[frame_slot];
+        let extent = vk::Extent2D {
+            width: settings.columns,
+            height: settings.rows,
+        };
+        let rest_distance = glm::vec2(
+            glm::length(&settings.width) / (settings.columns - 1) as f32,
+            glm::length(&settings.height) / (settings.rows - 1) as f32,
+        );
+        let push_constants = |mode: SolverMode, time: f32| {
+            PushConstants::new(
+                glm::vec4(
+                    TIME_STEP,
+                    settings.damping,
+                    settings.stiffness,
+                    mode as u32 as f32,
+                ),
+                glm::vec4(
+                    settings.gravity.x,
+                    settings.gravity.y,
+                    settings.gravity.z,
+                    cloth.colliders.len() as f32,
+                ),
+                glm::vec4(cloth.wind.x, cloth.wind.y, cloth.wind.z, time),
+                glm::vec4(
+                    settings.columns as f32,
+                    settings.rows as f32,
+                    rest_distance.x,
+                    rest_distance.y,
+                ),
+            )
+        };
+        let pipeline = &self.pipeline;
+        // every dispatch swaps source and destination
+        let add_dispatch = |graph: &mut RenderGraph<'a>, source: usize, mode, time| {
+            let constants = push_constants(mode, time);
+            let descriptors = [cloth.particle_descriptors[source], collider_descriptor];
+            let pass = match mode {
+                SolverMode::Integrate => GraphPass::new("cloth integrate")
+                    .buffer(positions[1 - source], BufferUsage::StorageWrite)
+                    .buffer(previous, BufferUsage::StorageWrite),
+                SolverMode::Constraints => GraphPass::new("cloth constraints")
+                    .buffer(positions[1 - source], BufferUsage::StorageWrite),
+                SolverMode::Normals => {
+                    GraphPass::new("cloth normals").buffer(vertices, BufferUsage::StorageWrite)
+                }
+            };
+            let pass = pass.buffer(positions[source], BufferUsage::StorageRead);
+            graph.add_pass(pass.record(move |command_buffer| {
+                pipeline.execute_compute_with_constants(
+                    command_buffer,
+                    &descriptors,
+                    extent,
+                    &constants,
+                )
+            }));
+        };
+        // cloth.time was already advanced by prepare
+        let start_time = cloth.time - steps as f32 * TIME_STEP;
+        for step in 0..steps {
+            let time = start_time + step as f32 * TIME_STEP;
+            add_dispatch(graph, 0, SolverMode::Integrate, time);
+            for iteration in 0..settings.iterations {
+                let source = if iteration % 2 == 0 { 1 } else { 0 };
+                add_dispatch(graph, source, SolverMode::Constraints, time);
+            }
+        }
+        add_dispatch(graph, 0, SolverMode::Normals, cloth.time);
+        vertices
+    }
+}
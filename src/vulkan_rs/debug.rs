@@ -1,3 +1,4 @@
+use super::error::VulkanError;
 use super::instance::Instance;
 use ash::ext::debug_utils;
 use ash::vk;
@@ -15,7 +16,7 @@ pub fn get_required_extensions() -> Vec<CString> {
     vec![CString::from(debug_utils::NAME)]
 }
 
-pub unsafe extern "system" fn vulkan_debug_callback(
+unsafe extern "system" fn vulkan_debug_callback(
     message_severity: vk::DebugUtilsMessageSeverityFlagsEXT,
     message_type: vk::DebugUtilsMessageTypeFlagsEXT,
     p_callback_data: *const vk::DebugUtilsMessengerCallbackDataEXT,
@@ -63,19 +64,16 @@ impl DebugMessenger {
             ..Default::default()
         }
     }
-    pub fn new(instance: Arc<Instance>) -> DebugMessenger {
+    pub fn new(instance: Arc<Instance>) -> Result<DebugMessenger, VulkanError> {
         let create_info = Self::fill_create_info();
         let debug_utils_instance = instance.create_debug_utils_instance();
-        let messenger = unsafe {
-            debug_utils_instance
-                .create_debug_utils_messenger(&create_info, None)
-                .expect("Device should not be out of memory this early!")
-        };
-        DebugMessenger {
+        let messenger =
+            unsafe { debug_utils_instance.create_debug_utils_messenger(&create_info, None)? };
+        Ok(DebugMessenger {
             _instance: instance,
             messenger,
             debug_utils_instance,
-        }
+        })
     }
 }
 
@@ -88,3 +86,68 @@ impl Drop for DebugMessenger {
         }
     }
 }
+
+// names and labels show up in validation messages and in captures (renderdoc, nsight, ...)
+// only exists if the debug utils extension is enabled => the device skips them otherwise
+pub struct DebugLabels {
+    debug_utils_device: debug_utils::Device,
+}
+
+impl DebugLabels {
+    pub fn new(debug_utils_device: debug_utils::Device) -> Self {
+        DebugLabels { debug_utils_device }
+    }
+
+    pub fn set_object_name<T: vk::Handle>(&self, handle: T, name: &str) {
+        let Ok(name) = CString::new(name) else {
+            return;
+        };
+        let name_info = vk::DebugUtilsObjectNameInfoEXT::default()
+            .object_handle(handle)
+            .object_name(&name);
+        // only a debugging aid => failing is not worth an error
+        if let Err(e) = unsafe {
+            self.debug_utils_device
+                .set_debug_utils_object_name(&name_info)
+        } {
+            log::warn!("Could not name {:?}: {}", name, e);
+        }
+    }
+
+    // every begin needs an end in the same command buffer
+    pub fn cmd_begin_label(&self, command_buffer: vk::CommandBuffer, name: &str, color: [f32; 4]) {
+        let name = CString::new(name).unwrap_or_default();
+        let label = vk::DebugUtilsLabelEXT::default()
+            .label_name(&name)
+            .color(color);
+        unsafe {
+            self.debug_utils_device
+                .cmd_begin_debug_utils_label(command_buffer, &label);
+        }
+    }
+
+    pub fn cmd_end_label(&self, command_buffer: vk::CommandBuffer) {
+        unsafe {
+            self.debug_utils_device
+                .cmd_end_debug_utils_label(command_buffer);
+        }
+    }
+
+    pub fn cmd_insert_label(&self, command_buffer: vk::CommandBuffer, name: &str) {
+        let name = CString::new(name).unwrap_or_default();
+        let label = vk::DebugUtilsLabelEXT::default().label_name(&name);
+        unsafe {
+            self.debug_utils_device
+                .cmd_insert_debug_utils_label(command_buffer, &label);
+        }
+    }
+
+    pub fn queue_insert_label(&self, queue: vk::Queue, name: &str) {
+        let name = CString::new(name).unwrap_or_default();
+        let label = vk::DebugUtilsLabelEXT::default().label_name(&name);
+        unsafe {
+            self.debug_utils_device
+                .queue_insert_debug_utils_label(queue, &label);
+        }
+    }
+}
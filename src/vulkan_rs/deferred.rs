@@ -0,0 +1,360 @@
+use super::allocation::AllocatedImage;
+use super::allocation::Allocator;
+use super::descriptor::DescriptorAllocator;
+use super::descriptor::DescriptorLayoutBuilder;
+use super::descriptor::DescriptorSetLayout;
+use super::descriptor::DescriptorSetSlot;
+use super::descriptor::DescriptorWriter;
+use super::descriptor::SetLayouts;
+use super::device::Device;
+use super::draw_batches::DrawBatches;
+use super::error::VulkanError;
+use super::material::MaterialPass;
+use super::mesh::GPUDrawPushConstants;
+use super::mesh::Sampler;
+use super::pipelines::GraphicsPipeline;
+use super::pipelines::GraphicsPipelineBuilder;
+use super::render_graph::ImageHandle;
+use super::render_graph::RenderGraph;
+use super::shader::ShaderModule;
+use ash::vk;
+use nalgebra_glm as glm;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+// same order as the outputs of gbuffer.frag
+const GBUFFER_FORMATS: [vk::Format; 3] = [
+    // rgb = base color, a = metallic
+    vk::Format::R8G8B8A8_SRGB,
+    // xyz = world space normal, w = roughness
+    vk::Format::R16G16B16A16_SFLOAT,
+    // rgb = emitted light, always black until materials have an emissive factor
+    vk::Format::R16G16B16A16_SFLOAT,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenderPath {
+    // every surface is lit in the fragment shader of its draw
+    Forward,
+    // opaque surfaces are written to a g-buffer and lit once per pixel
+    // => the cost of the lights doesnt depend on the overdraw
+    Deferred,
+}
+
+// same layout as the push constants in deferred_lighting.frag
+#[repr(C)]
+#[derive(bytemuck::NoUninit, Copy, Clone, Debug)]
+struct GPULightingPushConstants {
+    inverse_view_proj: glm::Mat4,
+    // x = 1 => sun shadows from the shadow mask instead of the shadow map
+    params: glm::Vec4,
+}
+
+// opaque surfaces in two passes instead of the forward pipelines:
+//   gbuffer: the material pipelines with gbuffer.frag, writes albedo, normal and emissive
+//            (multiple render targets) and the usual depth image
+//   lighting: full screen triangle, reads the g-buffer + depth and lights every covered pixel
+//             onto the draw image, the same lighting as mesh.frag
+// the skybox and transparent surfaces are still drawn forward on top
+pub struct Deferred {
+    device: Arc<Device>,
+    gbuffer_images: [AllocatedImage; 3],
+    // 1 = lit, written by the ray query shadows, unused without them
+    shadow_mask: AllocatedImage,
+    // only referenced by the descriptor
+    _sampler: Sampler,
+    // binding 0-2 = g-buffer images, 3 = depth, 4 = shadow mask
+    _descriptor_layout: DescriptorSetLayout,
+    descriptor: vk::DescriptorSet,
+    gbuffer_pipeline: GraphicsPipeline,
+    lighting_pipeline: GraphicsPipeline,
+}
+
+impl Deferred {
+    pub fn new(
+        device: Arc<Device>,
+        allocator: Arc<Mutex<Allocator>>,
+        descriptor_allocator: &DescriptorAllocator,
+        draw_image: &AllocatedImage,
+        depth_image: &AllocatedImage,
+        scene_data_layout: &DescriptorSetLayout,
+        texture_table_layout: vk::DescriptorSetLayout,
+    ) -> Result<Self, VulkanError> {
+        let new_gbuffer_image = |format: vk::Format, name: &str| {
+            let image = AllocatedImage::new(
+                device.clone(),
+                allocator.clone(),
+                format,
+                vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+                draw_image.extent(),
+                vk::ImageAspectFlags::COLOR,
+                1,
+            )?;
+            image.set_name(name);
+            Ok::<_, VulkanError>(image)
+        };
+        let gbuffer_images = [
+            new_gbuffer_image(GBUFFER_FORMATS[0], "gbuffer albedo")?,
+            new_gbuffer_image(GBUFFER_FORMATS[1], "gbuffer normal")?,
+            new_gbuffer_image(GBUFFER_FORMATS[2], "gbuffer emissive")?,
+        ];
+        let shadow_mask = AllocatedImage::new(
+            device.clone(),
+            allocator.clone(),
+            vk::Format::R8_UNORM,
+            vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::SAMPLED,
+            draw_image.extent(),
+            vk::ImageAspectFlags::COLOR,
+            1,
+        )?;
+        shadow_mask.set_name("shadow mask");
+        // texel fetches only, the filter doesnt matter
+        let sampler = Sampler::new(device.clone(), vk::Filter::NEAREST, vk::Filter::NEAREST)?;
+
+        let mut builder = DescriptorLayoutBuilder::new();
+        for binding in 0..5 {
+            builder.add_binding(
+                binding,
+                vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                vk::ShaderStageFlags::FRAGMENT,
+            );
+        }
+        let descriptor_layout =
+            builder.build(device.clone(), vk::DescriptorSetLayoutCreateFlags::empty())?;
+        let descriptor = descriptor_allocator.allocate(descriptor_layout.layout())?;
+        let mut writer = DescriptorWriter::new();
+        let views = gbuffer_images
+            .iter()
+            .map(|image| image.image_view())
+            .chain([depth_image.image_view(), shadow_mask.image_view()]);
+        for (binding, view) in views.enumerate() {
+            writer.add_image(
+                binding as i32,
+                view,
+                sampler.sampler(),
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            );
+        }
+        writer.update_descriptor_set(&device, descriptor);
+
+        let gbuffer_pipeline = Self::build_gbuffer_pipeline(
+            device.clone(),
+            scene_data_layout.layout(),
+            texture_table_layout,
+            depth_image.format(),
+        )?;
+        let lighting_pipeline = Self::build_lighting_pipeline(
+            device.clone(),
+            scene_data_layout.layout(),
+            descriptor_layout.layout(),
+            draw_image.format(),
+        )?;
+
+        Ok(Deferred {
+            device,
+            gbuffer_images,
+            shadow_mask,
+            _sampler: sampler,
+            _descriptor_layout: descriptor_layout,
+            descriptor,
+            gbuffer_pipeline,
+            lighting_pipeline,
+        })
+    }
+
+    // same layout as the opaque material pipeline => draw_batches can record into it
+    fn build_gbuffer_pipeline(
+        device: Arc<Device>,
+        scene_data_layout: vk::DescriptorSetLayout,
+        texture_table_layout: vk::DescriptorSetLayout,
+        depth_format: vk::Format,
+    ) -> Result<GraphicsPipeline, VulkanError> {
+        let push_constants = vk::PushConstantRange {
+            stage_flags: vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+            offset: 0,
+            size: std::mem::size_of::<GPUDrawPushConstants>() as u32,
+        };
+        let set_layouts = SetLayouts::new()
+            .with(DescriptorSetSlot::Scene, scene_data_layout)
+            .with(DescriptorSetSlot::Material, texture_table_layout);
+        let layout_info = vk::PipelineLayoutCreateInfo {
+            s_type: vk::StructureType::PIPELINE_LAYOUT_CREATE_INFO,
+            p_next: std::ptr::null(),
+            flags: vk::PipelineLayoutCreateFlags::empty(),
+            set_layout_count: set_layouts.layouts().len() as u32,
+            p_set_layouts: set_layouts.layouts().as_ptr(),
+            push_constant_range_count: 1,
+            p_push_constant_ranges: &push_constants,
+            ..Default::default()
+        };
+        let pipeline_layout = device.create_pipeline_layout(&layout_info)?;
+        let frag_shader = ShaderModule::new(device.clone(), "shaders/gbuffer_frag.spv")?;
+        let vert_shader = ShaderModule::new(device.clone(), "shaders/mesh_vert.spv")?;
+        GraphicsPipelineBuilder::new()
+            .set_layout(pipeline_layout)
+            .set_shaders(&frag_shader, &vert_shader)
+            .set_input_topology(vk::PrimitiveTopology::TRIANGLE_LIST)
+            .set_polygon_mode(vk::PolygonMode::FILL)
+            .set_cull_mode(vk::CullModeFlags::NONE, vk::FrontFace::CLOCKWISE)
+            .disable_multisampling()
+            .set_color_attachment_formats(&GBUFFER_FORMATS)
+            .set_depth_format(depth_format)
+            .disable_blending()
+            .enable_depth_test(vk::TRUE, vk::CompareOp::GREATER_OR_EQUAL)
+            .build_pipeline(device)
+    }
+
+    // set 0 = scene data, set 1 = g-buffer
+    fn build_lighting_pipeline(
+        device: Arc<Device>,
+        scene_data_layout: vk::DescriptorSetLayout,
+        gbuffer_layout: vk::DescriptorSetLayout,
+        color_format: vk::Format,
+    ) -> Result<GraphicsPipeline, VulkanError> {
+        let push_constants = vk::PushConstantRange {
+            stage_flags: vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+            offset: 0,
+            size: std::mem::size_of::<GPULightingPushConstants>() as u32,
+        };
+        let set_layouts = [scene_data_layout, gbuffer_layout];
+        let layout_info = vk::PipelineLayoutCreateInfo {
+            s_type: vk::StructureType::PIPELINE_LAYOUT_CREATE_INFO,
+            p_next: std::ptr::null(),
+            flags: vk::PipelineLayoutCreateFlags::empty(),
+            set_layout_count: set_layouts.len() as u32,
+            p_set_layouts: set_layouts.as_ptr(),
+            push_constant_range_count: 1,
+            p_push_constant_ranges: &push_constants,
+            ..Default::default()
+        };
+        let pipeline_layout = device.create_pipeline_layout(&layout_info)?;
+        let frag_shader = ShaderModule::new(device.clone(), "shaders/deferred_lighting_frag.spv")?;
+        // the full screen triangle of the skybox
+        let vert_shader = ShaderModule::new(device.clone(), "shaders/skybox_vert.spv")?;
+        GraphicsPipelineBuilder::new()
+            .set_layout(pipeline_layout)
+            .set_shaders(&frag_shader, &vert_shader)
+            .set_input_topology(vk::PrimitiveTopology::TRIANGLE_LIST)
+            .set_polygon_mode(vk::PolygonMode::FILL)
+            .set_cull_mode(vk::CullModeFlags::NONE, vk::FrontFace::CLOCKWISE)
+            .disable_multisampling()
+            .set_color_attachment_format(color_format)
+            .disable_blending()
+            .disable_depth_test()
+            .build_pipeline(device)
+    }
+
+    // the g-buffer is overwritten every frame => imported without its old content
+    pub fn import_gbuffer(
+        &self,
+        graph: &mut RenderGraph,
+        extent: vk::Extent2D,
+    ) -> [ImageHandle; 3] {
+        self.gbuffer_images.each_ref().map(|image| {
+            let handle = graph.import_image(
+                "gbuffer",
+                image.image(),
+                vk::ImageAspectFlags::COLOR,
+                vk::ImageLayout::UNDEFINED,
+            );
+            graph.set_image_size(handle, extent, image.format());
+            handle
+        })
+    }
+
+    // only written when the ray query shadows are used
+    pub fn import_shadow_mask(&self, graph: &mut RenderGraph, extent: vk::Extent2D) -> ImageHandle {
+        let handle = graph.import_image(
+            "shadow mask",
+            self.shadow_mask.image(),
+            vk::ImageAspectFlags::COLOR,
+            vk::ImageLayout::UNDEFINED,
+        );
+        graph.set_image_size(handle, extent, self.shadow_mask.format());
+        handle
+    }
+
+    pub fn shadow_mask(&self) -> &AllocatedImage {
+        &self.shadow_mask
+    }
+
+    // world space normal + roughness, the second image of import_gbuffer
+    pub fn normal_image(&self) -> &AllocatedImage {
+        &self.gbuffer_images[1]
+    }
+
+    // clears the g-buffer and the depth image and draws the opaque surfaces that passed culling
+    pub fn record_gbuffer(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        depth_image: vk::ImageView,
+        extent: vk::Extent2D,
+        scene_descriptor_set: vk::DescriptorSet,
+        texture_descriptor_set: vk::DescriptorSet,
+        draw_batches: &DrawBatches,
+    ) {
+        let pipeline = &self.gbuffer_pipeline;
+        let color_images = self
+            .gbuffer_images
+            .each_ref()
+            .map(|image| image.image_view());
+        pipeline.begin_drawing_multiple(
+            command_buffer,
+            &color_images,
+            depth_image,
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL,
+            extent,
+        );
+        self.device.cmd_bind_descriptor_set(
+            command_buffer,
+            pipeline.layout(),
+            vk::PipelineBindPoint::GRAPHICS,
+            DescriptorSetSlot::Scene,
+            scene_descriptor_set,
+        );
+        self.device.cmd_bind_descriptor_set(
+            command_buffer,
+            pipeline.layout(),
+            vk::PipelineBindPoint::GRAPHICS,
+            DescriptorSetSlot::Material,
+            texture_descriptor_set,
+        );
+        draw_batches.record_culled(command_buffer, pipeline, MaterialPass::Opaque);
+        pipeline.end_drawing(command_buffer);
+    }
+
+    // pixels without a surface keep the background of the draw image
+    // view_proj has to be the one of the scene data => the depth matches
+    // shadow_mask => the sun shadows of the ray query pass instead of the shadow map
+    pub fn record_lighting(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        draw_image: vk::ImageView,
+        extent: vk::Extent2D,
+        scene_descriptor_set: vk::DescriptorSet,
+        view_proj: &glm::Mat4,
+        shadow_mask: bool,
+    ) {
+        let pipeline = &self.lighting_pipeline;
+        pipeline.begin_color_only(
+            command_buffer,
+            draw_image,
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            extent,
+        );
+        self.device.cmd_bind_descriptor_sets(
+            command_buffer,
+            pipeline.layout(),
+            vk::PipelineBindPoint::GRAPHICS,
+            &[scene_descriptor_set, self.descriptor],
+        );
+        let push_constants = GPULightingPushConstants {
+            inverse_view_proj: glm::inverse(view_proj),
+            params: glm::vec4(shadow_mask as u32 as f32, 0.0, 0.0, 0.0),
+        };
+        pipeline.draw_generated(command_buffer, 3, bytemuck::bytes_of(&push_constants));
+        pipeline.end_drawing(command_buffer);
+    }
+}
@@ -0,0 +1,44 @@
+// destructors that have to wait until the gpu is done with a frame
+// resources are pushed while recording/between frames and flushed once the frame finished on the gpu
+#[derive(Default)]
+pub struct DeletionQueue {
+    deletors: Vec<Box<dyn FnOnce()>>,
+}
+
+impl DeletionQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push<F>(&mut self, deletor: F)
+    where
+        F: FnOnce() + 'static,
+    {
+        self.deletors.push(Box::new(deletor));
+    }
+
+    // the resource is dropped on flush => anything that cleans up in Drop works
+    pub fn push_resource<T: 'static>(&mut self, resource: T) {
+        self.push(move || drop(resource));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.deletors.is_empty()
+    }
+
+    // reverse order => resources are destroyed before the things they were created from
+    pub fn flush(&mut self) {
+        while let Some(deletor) = self.deletors.pop() {
+            deletor();
+        }
+    }
+}
+
+impl Drop for DeletionQueue {
+    fn drop(&mut self) {
+        if !self.is_empty() {
+            log::debug!("Flushing {} deferred deletions", self.deletors.len());
+        }
+        self.flush();
+    }
+}
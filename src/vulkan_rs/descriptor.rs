@@ -1,23 +1,54 @@
 use super::device::Device;
+use super::error::VulkanError;
+use super::leak_tracker::TrackedObject;
+use super::shader::ShaderModule;
 use ash::vk;
+use std::collections::HashMap;
 use std::sync::Arc;
+use std::sync::Mutex;
 
+// guaranteed minimum for update after bind samplers is 500000 if descriptor indexing is supported
+const TEXTURE_TABLE_SIZE: u32 = 4096;
+// frames (= clear_pools calls) until the observed descriptor usage is reported
+const DESCRIPTOR_USAGE_WARM_UP_FRAMES: u32 = 300;
+// tuned pools get this much more than the peak usage
+const DESCRIPTOR_USAGE_HEADROOM: f32 = 1.25;
+
+#[derive(Default)]
 pub struct DescriptorLayoutBuilder<'a> {
     bindings: Vec<vk::DescriptorSetLayoutBinding<'a>>,
+    // one entry per binding
+    binding_flags: Vec<vk::DescriptorBindingFlags>,
 }
 
 pub struct DescriptorSetLayout {
     device: Arc<Device>,
     layout: vk::DescriptorSetLayout,
+    // descriptors one set of this layout takes from a pool, summed per type
+    descriptor_counts: Vec<(vk::DescriptorType, u32)>,
+    _tracked: TrackedObject,
 }
 
 impl DescriptorSetLayout {
-    pub fn new(device: Arc<Device>, layout: vk::DescriptorSetLayout) -> Self {
-        Self { device, layout }
+    pub fn new(
+        device: Arc<Device>,
+        layout: vk::DescriptorSetLayout,
+        descriptor_counts: Vec<(vk::DescriptorType, u32)>,
+    ) -> Self {
+        Self {
+            device,
+            layout,
+            descriptor_counts,
+            _tracked: TrackedObject::new("DescriptorSetLayout", format!("{:?}", layout)),
+        }
     }
     pub fn layout(&self) -> vk::DescriptorSetLayout {
         self.layout
     }
+
+    pub fn descriptor_counts(&self) -> &[(vk::DescriptorType, u32)] {
+        &self.descriptor_counts
+    }
 }
 
 impl Drop for DescriptorSetLayout {
@@ -31,6 +62,7 @@ impl<'a> DescriptorLayoutBuilder<'a> {
     pub fn new() -> DescriptorLayoutBuilder<'a> {
         DescriptorLayoutBuilder {
             bindings: Vec::new(),
+            binding_flags: Vec::new(),
         }
     }
 
@@ -39,55 +71,279 @@ impl<'a> DescriptorLayoutBuilder<'a> {
         binding_idx: u32,
         descriptor_type: vk::DescriptorType,
         stage_flags: vk::ShaderStageFlags,
+    ) {
+        self.add_array_binding(
+            binding_idx,
+            descriptor_type,
+            1,
+            stage_flags,
+            vk::DescriptorBindingFlags::empty(),
+        );
+    }
+
+    pub fn add_array_binding(
+        &mut self,
+        binding_idx: u32,
+        descriptor_type: vk::DescriptorType,
+        descriptor_count: u32,
+        stage_flags: vk::ShaderStageFlags,
+        binding_flags: vk::DescriptorBindingFlags,
     ) {
         let binding = vk::DescriptorSetLayoutBinding {
             binding: binding_idx,
             descriptor_type,
-            descriptor_count: 1,
+            descriptor_count,
             stage_flags,
             ..Default::default()
         };
         self.bindings.push(binding);
+        self.binding_flags.push(binding_flags);
+    }
+
+    // adds every binding the shader declares in the given set
+    // bindings shared between shaders are merged => only the stage flags are combined
+    pub fn add_shader_bindings(
+        &mut self,
+        shader: &ShaderModule,
+        set: u32,
+        stage_flags: vk::ShaderStageFlags,
+    ) {
+        for reflected in shader
+            .bindings()
+            .iter()
+            .filter(|binding| binding.set == set)
+        {
+            if let Some(existing) = self
+                .bindings
+                .iter_mut()
+                .find(|binding| binding.binding == reflected.binding)
+            {
+                if existing.descriptor_type != reflected.descriptor_type {
+                    log::warn!(
+                        "Binding {} is used as {:?} and {:?}",
+                        reflected.binding,
+                        existing.descriptor_type,
+                        reflected.descriptor_type
+                    );
+                }
+                existing.stage_flags |= stage_flags;
+                continue;
+            }
+            // runtime arrays have no size in the shader => same size as the texture table
+            let (descriptor_count, binding_flags) = match reflected.descriptor_count {
+                0 => (
+                    TEXTURE_TABLE_SIZE,
+                    vk::DescriptorBindingFlags::PARTIALLY_BOUND,
+                ),
+                count => (count, vk::DescriptorBindingFlags::empty()),
+            };
+            self.add_array_binding(
+                reflected.binding,
+                reflected.descriptor_type,
+                descriptor_count,
+                stage_flags,
+                binding_flags,
+            );
+        }
     }
 
     #[allow(dead_code)]
     pub fn clear(&mut self) {
         self.bindings.clear();
+        self.binding_flags.clear();
     }
 
     pub fn build(
         &self,
         device: Arc<Device>,
         flags: vk::DescriptorSetLayoutCreateFlags,
-    ) -> DescriptorSetLayout {
+    ) -> Result<DescriptorSetLayout, VulkanError> {
+        let binding_flags_info = vk::DescriptorSetLayoutBindingFlagsCreateInfo {
+            s_type: vk::StructureType::DESCRIPTOR_SET_LAYOUT_BINDING_FLAGS_CREATE_INFO,
+            p_next: std::ptr::null(),
+            binding_count: self.binding_flags.len() as u32,
+            p_binding_flags: self.binding_flags.as_ptr(),
+            ..Default::default()
+        };
+        // only chain the flags if they are used => layouts without them stay as they were
+        let p_next = if self.binding_flags.iter().any(|flags| !flags.is_empty()) {
+            &binding_flags_info as *const _ as *const std::ffi::c_void
+        } else {
+            std::ptr::null()
+        };
         let layout_info = vk::DescriptorSetLayoutCreateInfo {
             s_type: vk::StructureType::DESCRIPTOR_SET_LAYOUT_CREATE_INFO,
-            p_next: std::ptr::null(),
+            p_next,
             p_bindings: self.bindings.as_ptr(),
             binding_count: self.bindings.len() as u32,
             flags,
             ..Default::default()
         };
-        let set_layout = device.create_descriptor_set_layout(&layout_info);
-        DescriptorSetLayout::new(device, set_layout)
+        let set_layout = device.create_descriptor_set_layout(&layout_info)?;
+        let mut descriptor_counts = Vec::new();
+        for binding in self.bindings.iter() {
+            add_descriptor_count(
+                &mut descriptor_counts,
+                binding.descriptor_type,
+                binding.descriptor_count,
+            );
+        }
+        Ok(DescriptorSetLayout::new(
+            device,
+            set_layout,
+            descriptor_counts,
+        ))
+    }
+}
+
+fn add_descriptor_count(
+    counts: &mut Vec<(vk::DescriptorType, u32)>,
+    descriptor_type: vk::DescriptorType,
+    count: u32,
+) {
+    match counts.iter_mut().find(|(ty, _)| *ty == descriptor_type) {
+        Some((_, total)) => *total += count,
+        None => counts.push((descriptor_type, count)),
+    }
+}
+
+// descriptor sets of the mesh pipelines (and everything that draws meshes with mesh.vert)
+// the index is the `set = N` of the shaders, per object data is pushed => there is no object set
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DescriptorSetSlot {
+    // scene data, shadow map, lights and environment => changes once per frame (or per view)
+    Scene,
+    // bindless texture table => the same set for every material
+    Material,
+}
+
+impl DescriptorSetSlot {
+    pub fn index(self) -> u32 {
+        match self {
+            DescriptorSetSlot::Scene => 0,
+            DescriptorSetSlot::Material => 1,
+        }
+    }
+}
+
+// set layouts of a pipeline layout placed by slot => the order of the calls doesnt matter
+#[derive(Default)]
+pub struct SetLayouts {
+    layouts: Vec<vk::DescriptorSetLayout>,
+}
+
+impl SetLayouts {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with(mut self, slot: DescriptorSetSlot, layout: vk::DescriptorSetLayout) -> Self {
+        let idx = slot.index() as usize;
+        if self.layouts.len() <= idx {
+            self.layouts
+                .resize(idx + 1, vk::DescriptorSetLayout::null());
+        }
+        self.layouts[idx] = layout;
+        self
+    }
+
+    // every slot before the last one has to be set, vulkan does not allow holes
+    pub fn layouts(&self) -> &[vk::DescriptorSetLayout] {
+        debug_assert!(
+            self.layouts
+                .iter()
+                .all(|layout| *layout != vk::DescriptorSetLayout::null()),
+            "Pipeline layout has an unset descriptor set slot"
+        );
+        &self.layouts
     }
 }
 
+#[derive(Debug, Clone, Copy)]
 pub struct PoolSizeRatio {
     pub descriptor_type: vk::DescriptorType,
     pub ratio: f32,
 }
+
+// what a growable allocator handed out between two clear_pools calls
+#[derive(Debug, Default)]
+struct DescriptorUsage {
+    sets: u32,
+    descriptors: Vec<(vk::DescriptorType, u32)>,
+    // maximum of each value over all frames, not necessarily from the same frame
+    peak_sets: u32,
+    peak_descriptors: Vec<(vk::DescriptorType, u32)>,
+    frames: u32,
+}
+
+impl DescriptorUsage {
+    fn add_set(&mut self, layout: &DescriptorSetLayout) {
+        self.sets += 1;
+        for (descriptor_type, count) in layout.descriptor_counts() {
+            add_descriptor_count(&mut self.descriptors, *descriptor_type, *count);
+        }
+    }
+
+    fn finish_frame(&mut self) {
+        self.peak_sets = self.peak_sets.max(self.sets);
+        for (descriptor_type, count) in self.descriptors.drain(..) {
+            match self
+                .peak_descriptors
+                .iter_mut()
+                .find(|(ty, _)| *ty == descriptor_type)
+            {
+                Some((_, peak)) => *peak = (*peak).max(count),
+                None => self.peak_descriptors.push((descriptor_type, count)),
+            }
+        }
+        self.sets = 0;
+        self.frames += 1;
+    }
+
+    // types that were never used keep their ratio => allocating them later still works
+    fn suggested_ratios(&self, current: &[PoolSizeRatio]) -> Vec<PoolSizeRatio> {
+        let sets = self.peak_sets.max(1) as f32;
+        let mut ratios: Vec<PoolSizeRatio> = current
+            .iter()
+            .filter(|ratio| {
+                !self
+                    .peak_descriptors
+                    .iter()
+                    .any(|(ty, _)| *ty == ratio.descriptor_type)
+            })
+            .copied()
+            .collect();
+        ratios.extend(
+            self.peak_descriptors
+                .iter()
+                .map(|(descriptor_type, count)| PoolSizeRatio {
+                    descriptor_type: *descriptor_type,
+                    ratio: *count as f32 / sets * DESCRIPTOR_USAGE_HEADROOM,
+                }),
+        );
+        ratios
+    }
+}
 pub struct DescriptorAllocator {
     device: Arc<Device>,
     pool: Option<vk::DescriptorPool>,
+    _tracked: TrackedObject,
 }
 
 impl DescriptorAllocator {
     pub fn new(device: Arc<Device>) -> DescriptorAllocator {
-        Self { device, pool: None }
+        Self {
+            device,
+            pool: None,
+            _tracked: TrackedObject::new("DescriptorAllocator", "single pool"),
+        }
     }
 
-    pub fn init_pool(&mut self, max_sets: u32, pool_ratios: &[PoolSizeRatio]) {
+    pub fn init_pool(
+        &mut self,
+        max_sets: u32,
+        pool_ratios: &[PoolSizeRatio],
+    ) -> Result<(), VulkanError> {
         let mut pool_sizes = Vec::with_capacity(pool_ratios.len());
         for pool_ratio in pool_ratios {
             pool_sizes.push(vk::DescriptorPoolSize {
@@ -103,7 +359,8 @@ impl DescriptorAllocator {
             p_next: std::ptr::null(),
             ..Default::default()
         };
-        self.pool = Some(self.device.create_descriptor_pool(&pool_info));
+        self.pool = Some(self.device.create_descriptor_pool(&pool_info)?);
+        Ok(())
     }
 
     #[allow(dead_code)]
@@ -119,13 +376,17 @@ impl DescriptorAllocator {
         if let Some(pool) = self.pool.take() {
             self.device.destroy_descriptor_pool(pool);
         } else {
-            panic!("Tried to destroy non-initialized descriptor pool");
+            // happens if init_pool failed
+            log::warn!("Tried to destroy non-initialized descriptor pool");
         }
     }
 
     //TODO: think of a solution to handle the dependency of the descriptor set to the pool (aka
     //make sure that descriptor set is invalidated when pool is reset/destroyed)
-    pub fn allocate(&self, layout: vk::DescriptorSetLayout) -> vk::DescriptorSet {
+    pub fn allocate(
+        &self,
+        layout: vk::DescriptorSetLayout,
+    ) -> Result<vk::DescriptorSet, VulkanError> {
         if let Some(pool) = self.pool {
             let alloc_info = vk::DescriptorSetAllocateInfo {
                 s_type: vk::StructureType::DESCRIPTOR_SET_ALLOCATE_INFO,
@@ -136,9 +397,7 @@ impl DescriptorAllocator {
                 ..Default::default()
             };
             // we should only have one element in array since we only allocate one descriptor set
-            self.device
-                .allocate_descriptor_sets(&alloc_info)
-                .expect("I pray that i never run out of memory")[0]
+            Ok(self.device.allocate_descriptor_sets(&alloc_info)?[0])
         } else {
             panic!("Tried to allocate from non-initialized descriptor pool");
         }
@@ -154,36 +413,105 @@ impl Drop for DescriptorAllocator {
 
 pub struct DescriptorAllocatorGrowable {
     device: Arc<Device>,
+    name: String,
     ratios: Vec<PoolSizeRatio>,
     full_pools: Vec<vk::DescriptorPool>,
     ready_pools: Vec<vk::DescriptorPool>,
     sets_per_pool: u32,
+    usage: DescriptorUsage,
+    // replace the ratios with the observed usage after the warm up
+    auto_tune: bool,
+    _tracked: TrackedObject,
 }
 
 impl DescriptorAllocatorGrowable {
-    pub fn new(device: Arc<Device>, ratios: Vec<PoolSizeRatio>, max_sets: u32) -> Self {
+    pub fn new(device: Arc<Device>, name: &str, ratios: Vec<PoolSizeRatio>, max_sets: u32) -> Self {
         Self {
             device,
+            name: name.to_string(),
             ratios,
             full_pools: Vec::new(),
             ready_pools: Vec::new(),
             sets_per_pool: max_sets,
+            usage: DescriptorUsage::default(),
+            auto_tune: false,
+            _tracked: TrackedObject::new(
+                "DescriptorAllocatorGrowable",
+                format!("{} ({} sets in the first pool)", name, max_sets),
+            ),
         }
     }
 
-    pub fn init_pool(&mut self) {
-        let pool = self.create_new_pool(self.sets_per_pool, &self.ratios);
+    // only useful for allocators that are cleared every frame
+    pub fn set_auto_tune(&mut self, auto_tune: bool) {
+        self.auto_tune = auto_tune;
+    }
+
+    pub fn init_pool(&mut self) -> Result<(), VulkanError> {
+        let pool = self.create_new_pool(self.sets_per_pool, &self.ratios)?;
         self.ready_pools.push(pool);
         self.sets_per_pool = (self.sets_per_pool as f32 * 1.5) as u32;
+        Ok(())
     }
 
+    // none of the sets may be in use anymore
     pub fn clear_pools(&mut self) {
+        self.usage.finish_frame();
+        if self.usage.frames == DESCRIPTOR_USAGE_WARM_UP_FRAMES {
+            self.report_usage();
+        }
         self.ready_pools.append(&mut self.full_pools);
         for pool in self.ready_pools.iter() {
             self.device.reset_descriptor_pool(*pool);
         }
     }
 
+    fn report_usage(&mut self) {
+        let suggested_ratios = self.suggested_ratios();
+        let suggested_sets = self.suggested_sets_per_pool();
+        log::info!(
+            "Descriptor usage of {} after {} frames: at most {} sets and {:?} per frame",
+            self.name,
+            self.usage.frames,
+            self.usage.peak_sets,
+            self.usage.peak_descriptors
+        );
+        if !self.auto_tune {
+            log::info!(
+                "Suggested pool for {}: {} sets with the ratios {:?}",
+                self.name,
+                suggested_sets,
+                suggested_ratios
+            );
+            return;
+        }
+        log::info!(
+            "Tuning pools of {} to {} sets with the ratios {:?}",
+            self.name,
+            suggested_sets,
+            suggested_ratios
+        );
+        let new_pool = match self.create_new_pool(suggested_sets, &suggested_ratios) {
+            Ok(pool) => pool,
+            Err(e) => {
+                log::warn!("Could not create tuned descriptor pool: {}", e);
+                return;
+            }
+        };
+        self.destroy_pools();
+        self.ready_pools.push(new_pool);
+        self.ratios = suggested_ratios;
+        self.sets_per_pool = (suggested_sets as f32 * 1.5) as u32;
+    }
+
+    pub fn suggested_ratios(&self) -> Vec<PoolSizeRatio> {
+        self.usage.suggested_ratios(&self.ratios)
+    }
+
+    pub fn suggested_sets_per_pool(&self) -> u32 {
+        ((self.usage.peak_sets as f32 * DESCRIPTOR_USAGE_HEADROOM).ceil() as u32).max(1)
+    }
+
     pub fn destroy_pools(&mut self) {
         for pool in self.ready_pools.iter() {
             self.device.destroy_descriptor_pool(*pool);
@@ -195,20 +523,25 @@ impl DescriptorAllocatorGrowable {
         self.full_pools.clear();
     }
 
-    fn get_pool(&mut self) -> vk::DescriptorPool {
+    fn get_pool(&mut self) -> Result<vk::DescriptorPool, VulkanError> {
         if self.ready_pools.is_empty() {
-            let new_pool = self.create_new_pool(self.sets_per_pool, &self.ratios);
+            let new_pool = self.create_new_pool(self.sets_per_pool, &self.ratios)?;
             self.sets_per_pool = (self.sets_per_pool as f32 * 1.5) as u32;
             self.sets_per_pool = u32::min(self.sets_per_pool, 4092);
-            new_pool
+            Ok(new_pool)
         } else {
-            self.ready_pools
+            Ok(self
+                .ready_pools
                 .pop()
-                .expect("Vector should not be empty since we just checked for it")
+                .expect("Vector should not be empty since we just checked for it"))
         }
     }
 
-    fn create_new_pool(&self, set_count: u32, pool_ratios: &[PoolSizeRatio]) -> vk::DescriptorPool {
+    fn create_new_pool(
+        &self,
+        set_count: u32,
+        pool_ratios: &[PoolSizeRatio],
+    ) -> Result<vk::DescriptorPool, VulkanError> {
         let pool_sizes: Vec<vk::DescriptorPoolSize> = pool_ratios
             .iter()
             .map(|ratio| vk::DescriptorPoolSize {
@@ -228,36 +561,44 @@ impl DescriptorAllocatorGrowable {
         self.device.create_descriptor_pool(&pool_create_info)
     }
 
-    pub fn allocate(&mut self, layout: vk::DescriptorSetLayout) -> vk::DescriptorSet {
-        let pool_to_use = self.get_pool();
+    // takes the layout wrapper => the usage of the allocator can be tracked
+    pub fn allocate(
+        &mut self,
+        layout: &DescriptorSetLayout,
+    ) -> Result<vk::DescriptorSet, VulkanError> {
+        let pool_to_use = self.get_pool()?;
 
+        let set_layout = layout.layout();
         let mut alloc_info = vk::DescriptorSetAllocateInfo {
             s_type: vk::StructureType::DESCRIPTOR_SET_ALLOCATE_INFO,
             p_next: std::ptr::null(),
             descriptor_pool: pool_to_use,
             descriptor_set_count: 1,
-            p_set_layouts: &layout,
+            p_set_layouts: &set_layout,
             ..Default::default()
         };
         let result = self.device.allocate_descriptor_sets(&alloc_info);
         match result {
             Ok(sets) => {
                 self.ready_pools.push(pool_to_use);
-                sets[0]
+                self.usage.add_set(layout);
+                Ok(sets[0])
             }
             Err(vk::Result::ERROR_OUT_OF_POOL_MEMORY) | Err(vk::Result::ERROR_FRAGMENTED_POOL) => {
                 self.full_pools.push(pool_to_use);
-                let pool_to_use = self.get_pool();
+                let pool_to_use = self.get_pool()?;
                 alloc_info.descriptor_pool = pool_to_use;
-                // just crash if it doesnt work the second time
-                let sets = self
-                    .device
-                    .allocate_descriptor_sets(&alloc_info)
-                    .expect("I pray that i never run out of memory")[0];
+                // a fresh pool that is still too small is a real error
+                let result = self.device.allocate_descriptor_sets(&alloc_info);
                 self.ready_pools.push(pool_to_use);
-                sets
+                let set = result?[0];
+                self.usage.add_set(layout);
+                Ok(set)
+            }
+            Err(e) => {
+                self.ready_pools.push(pool_to_use);
+                Err(e.into())
             }
-            _ => panic!("I pray that i never run out of memory"),
         }
     }
 }
@@ -269,6 +610,7 @@ impl Drop for DescriptorAllocatorGrowable {
     }
 }
 
+#[derive(Default)]
 pub struct DescriptorWriter<'a> {
     //NOTE: box is used here to allow vector resizing without invalidating references
     // stored in self.writes
@@ -276,6 +618,11 @@ pub struct DescriptorWriter<'a> {
     buffer_infos: Vec<Box<vk::DescriptorBufferInfo>>,
     #[allow(clippy::vec_box)]
     image_infos: Vec<Box<vk::DescriptorImageInfo>>,
+    // acceleration structures are written through the p_next of the write
+    #[allow(clippy::vec_box)]
+    acceleration_structures: Vec<Box<vk::AccelerationStructureKHR>>,
+    #[allow(clippy::vec_box)]
+    acceleration_structure_infos: Vec<Box<vk::WriteDescriptorSetAccelerationStructureKHR<'a>>>,
     writes: Vec<vk::WriteDescriptorSet<'a>>,
 }
 
@@ -284,6 +631,8 @@ impl<'a> DescriptorWriter<'a> {
         DescriptorWriter {
             buffer_infos: Vec::new(),
             image_infos: Vec::new(),
+            acceleration_structures: Vec::new(),
+            acceleration_structure_infos: Vec::new(),
             writes: Vec::new(),
         }
     }
@@ -337,6 +686,25 @@ impl<'a> DescriptorWriter<'a> {
         sampler: vk::Sampler,
         image_layout: vk::ImageLayout,
         descriptor_type: vk::DescriptorType,
+    ) {
+        self.add_image_array_element(
+            binding,
+            0,
+            image_view,
+            sampler,
+            image_layout,
+            descriptor_type,
+        );
+    }
+
+    pub fn add_image_array_element(
+        &mut self,
+        binding: i32,
+        array_element: u32,
+        image_view: vk::ImageView,
+        sampler: vk::Sampler,
+        image_layout: vk::ImageLayout,
+        descriptor_type: vk::DescriptorType,
     ) {
         let image_info = vk::DescriptorImageInfo {
             sampler,
@@ -350,7 +718,7 @@ impl<'a> DescriptorWriter<'a> {
             p_next: std::ptr::null(),
             dst_set: vk::DescriptorSet::null(),
             dst_binding: binding as u32,
-            dst_array_element: 0,
+            dst_array_element: array_element,
             descriptor_count: 1,
             descriptor_type,
             p_image_info: &**self
@@ -372,10 +740,50 @@ impl<'a> DescriptorWriter<'a> {
         );
     }
 
+    // only with the ray tracing extensions enabled
+    pub fn add_acceleration_structure(
+        &mut self,
+        binding: i32,
+        acceleration_structure: vk::AccelerationStructureKHR,
+    ) {
+        self.acceleration_structures
+            .push(Box::new(acceleration_structure));
+        let acceleration_structure_info = vk::WriteDescriptorSetAccelerationStructureKHR {
+            s_type: vk::StructureType::WRITE_DESCRIPTOR_SET_ACCELERATION_STRUCTURE_KHR,
+            p_next: std::ptr::null(),
+            acceleration_structure_count: 1,
+            p_acceleration_structures: &**self
+                .acceleration_structures
+                .last()
+                .expect("Vector should have at least one element since we just added one"),
+            ..Default::default()
+        };
+        self.acceleration_structure_infos
+            .push(Box::new(acceleration_structure_info));
+
+        let descriptor_write = vk::WriteDescriptorSet {
+            s_type: vk::StructureType::WRITE_DESCRIPTOR_SET,
+            p_next: &**self
+                .acceleration_structure_infos
+                .last()
+                .expect("Vector should have at least one element since we just added one")
+                as *const _ as *const std::ffi::c_void,
+            dst_set: vk::DescriptorSet::null(),
+            dst_binding: binding as u32,
+            dst_array_element: 0,
+            descriptor_count: 1,
+            descriptor_type: vk::DescriptorType::ACCELERATION_STRUCTURE_KHR,
+            ..Default::default()
+        };
+        self.writes.push(descriptor_write);
+    }
+
     #[allow(dead_code)]
     pub fn clear(&mut self) {
         self.buffer_infos.clear();
         self.image_infos.clear();
+        self.acceleration_structures.clear();
+        self.acceleration_structure_infos.clear();
         self.writes.clear();
     }
 
@@ -386,3 +794,178 @@ impl<'a> DescriptorWriter<'a> {
         device.update_descriptor_sets(&self.writes);
     }
 }
+
+// index of a texture in the TextureTable, frees its slot when the last handle is dropped
+pub struct TextureHandle {
+    index: u32,
+    key: (vk::ImageView, vk::Sampler),
+    table: Arc<TextureTable>,
+}
+
+impl TextureHandle {
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+}
+
+impl Drop for TextureHandle {
+    fn drop(&mut self) {
+        self.table.release(self.key);
+    }
+}
+
+#[derive(Default)]
+struct TextureSlots {
+    // first index that was never used
+    next: u32,
+    free: Vec<u32>,
+    // index + number of handles => every image/sampler pair only occupies one slot
+    registered: HashMap<(vk::ImageView, vk::Sampler), (u32, u32)>,
+}
+
+// one big array of combined image samplers that every shader indexes into (bindless)
+// the set is allocated once and updated in place => no descriptor set per material
+pub struct TextureTable {
+    device: Arc<Device>,
+    layout: DescriptorSetLayout,
+    pool: vk::DescriptorPool,
+    set: vk::DescriptorSet,
+    slots: Mutex<TextureSlots>,
+}
+
+impl TextureTable {
+    pub fn new(device: Arc<Device>) -> Result<Arc<Self>, VulkanError> {
+        let mut builder = DescriptorLayoutBuilder::new();
+        // update after bind => registering textures doesnt invalidate recorded command buffers
+        // partially bound => unused slots dont need a valid descriptor
+        builder.add_array_binding(
+            0,
+            vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            TEXTURE_TABLE_SIZE,
+            vk::ShaderStageFlags::FRAGMENT,
+            vk::DescriptorBindingFlags::PARTIALLY_BOUND
+                | vk::DescriptorBindingFlags::UPDATE_AFTER_BIND
+                | vk::DescriptorBindingFlags::UPDATE_UNUSED_WHILE_PENDING,
+        );
+        let layout = builder.build(
+            device.clone(),
+            vk::DescriptorSetLayoutCreateFlags::UPDATE_AFTER_BIND_POOL,
+        )?;
+
+        let pool_size = vk::DescriptorPoolSize {
+            ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            descriptor_count: TEXTURE_TABLE_SIZE,
+        };
+        let pool_info = vk::DescriptorPoolCreateInfo {
+            s_type: vk::StructureType::DESCRIPTOR_POOL_CREATE_INFO,
+            p_next: std::ptr::null(),
+            flags: vk::DescriptorPoolCreateFlags::UPDATE_AFTER_BIND,
+            max_sets: 1,
+            pool_size_count: 1,
+            p_pool_sizes: &pool_size,
+            ..Default::default()
+        };
+        let pool = device.create_descriptor_pool(&pool_info)?;
+        let set_layout = layout.layout();
+        let alloc_info = vk::DescriptorSetAllocateInfo {
+            s_type: vk::StructureType::DESCRIPTOR_SET_ALLOCATE_INFO,
+            p_next: std::ptr::null(),
+            descriptor_pool: pool,
+            descriptor_set_count: 1,
+            p_set_layouts: &set_layout,
+            ..Default::default()
+        };
+        let set = match device.allocate_descriptor_sets(&alloc_info) {
+            Ok(sets) => sets[0],
+            Err(e) => {
+                device.destroy_descriptor_pool(pool);
+                return Err(e.into());
+            }
+        };
+        Ok(Arc::new(Self {
+            device,
+            layout,
+            pool,
+            set,
+            slots: Mutex::new(TextureSlots::default()),
+        }))
+    }
+
+    pub fn layout(&self) -> vk::DescriptorSetLayout {
+        self.layout.layout()
+    }
+
+    pub fn descriptor_set(&self) -> vk::DescriptorSet {
+        self.set
+    }
+
+    // the image has to be in SHADER_READ_ONLY_OPTIMAL whenever it is sampled
+    pub fn register(
+        self: &Arc<Self>,
+        image_view: vk::ImageView,
+        sampler: vk::Sampler,
+    ) -> Result<TextureHandle, VulkanError> {
+        let key = (image_view, sampler);
+        let mut slots = self
+            .slots
+            .lock()
+            .expect("Mutex has been poisoned and i dont wanan handle it yet");
+        if let Some((index, count)) = slots.registered.get_mut(&key) {
+            *count += 1;
+            return Ok(TextureHandle {
+                index: *index,
+                key,
+                table: self.clone(),
+            });
+        }
+        let index = match slots.free.pop() {
+            Some(index) => index,
+            None if slots.next < TEXTURE_TABLE_SIZE => {
+                slots.next += 1;
+                slots.next - 1
+            }
+            None => return Err(VulkanError::TextureTableFull),
+        };
+        slots.registered.insert(key, (index, 1));
+
+        let mut writer = DescriptorWriter::new();
+        writer.add_image_array_element(
+            0,
+            index,
+            image_view,
+            sampler,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+        );
+        writer.update_descriptor_set(&self.device, self.set);
+        Ok(TextureHandle {
+            index,
+            key,
+            table: self.clone(),
+        })
+    }
+
+    // the descriptor stays in place => a freed slot is only overwritten by the next register
+    fn release(&self, key: (vk::ImageView, vk::Sampler)) {
+        let mut slots = self
+            .slots
+            .lock()
+            .expect("Mutex has been poisoned and i dont wanan handle it yet");
+        let Some((index, count)) = slots.registered.get_mut(&key) else {
+            return;
+        };
+        *count -= 1;
+        if *count == 0 {
+            let index = *index;
+            slots.registered.remove(&key);
+            slots.free.push(index);
+        }
+    }
+}
+
+impl Drop for TextureTable {
+    fn drop(&mut self) {
+        log::debug!("Destroying TextureTable");
+        self.device.destroy_descriptor_pool(self.pool);
+    }
+}
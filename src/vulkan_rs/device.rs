@@ -1,7 +1,12 @@
+use super::debug::DebugLabels;
+use super::descriptor::DescriptorSetSlot;
+use super::error::VulkanError;
 use super::instance::Instance;
 use super::instance::Version;
-use super::pipelines::PushConstants;
+use super::material::Material;
+use super::mesh::GeometricSurface;
 use super::window::Surface;
+use super::GPUDrawData;
 use super::GPUDrawPushConstants;
 use super::MeshAsset;
 use ash::vk;
@@ -10,123 +15,511 @@ use nalgebra_glm as glm;
 use std::cmp::Reverse;
 use std::collections::HashSet;
 use std::ffi::c_char;
+use std::ffi::CStr;
+use std::sync::atomic::AtomicU32;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::MutexGuard;
+
+// array layers of an image and how the default view sees them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageLayers {
+    Single,
+    // e.g. shadow atlases, viewed as TYPE_2D_ARRAY
+    Array(u32),
+    // 6 faces in the order +x, -x, +y, -y, +z, -z
+    Cube,
+}
+
+impl ImageLayers {
+    pub fn count(self) -> u32 {
+        match self {
+            ImageLayers::Single => 1,
+            ImageLayers::Array(count) => count,
+            ImageLayers::Cube => 6,
+        }
+    }
+
+    pub fn view_type(self) -> vk::ImageViewType {
+        match self {
+            ImageLayers::Single => vk::ImageViewType::TYPE_2D,
+            ImageLayers::Array(_) => vk::ImageViewType::TYPE_2D_ARRAY,
+            ImageLayers::Cube => vk::ImageViewType::CUBE,
+        }
+    }
+
+    fn create_flags(self) -> vk::ImageCreateFlags {
+        match self {
+            ImageLayers::Cube => vk::ImageCreateFlags::CUBE_COMPATIBLE,
+            _ => vk::ImageCreateFlags::empty(),
+        }
+    }
+}
+
+// hardware ray tracing, only enabled if all of them and their features are supported
+pub const RAY_TRACING_EXTENSIONS: [&CStr; 3] = [
+    ash::khr::acceleration_structure::NAME,
+    ash::khr::ray_tracing_pipeline::NAME,
+    // required by acceleration_structure
+    ash::khr::deferred_host_operations::NAME,
+];
+
+// inline ray tracing from any shader stage, without a ray tracing pipeline
+pub const RAY_QUERY_EXTENSIONS: [&CStr; 3] = [
+    ash::khr::acceleration_structure::NAME,
+    ash::khr::ray_query::NAME,
+    ash::khr::deferred_host_operations::NAME,
+];
+
+// what a device needs to run the renderer, checked by PhysicalDeviceSelector and enabled by
+// Device::new => both see the same list
+// only the booleans of the feature structs are used, Device::new builds the p_next chain itself
+#[derive(Debug, Clone)]
+pub struct DeviceRequirements {
+    pub extensions: Vec<&'static CStr>,
+    pub base_features: vk::PhysicalDeviceFeatures,
+    pub vulkan11_features: vk::PhysicalDeviceVulkan11Features<'static>,
+    pub vulkan12_features: vk::PhysicalDeviceVulkan12Features<'static>,
+    pub vulkan13_features: vk::PhysicalDeviceVulkan13Features<'static>,
+    // enabled if the device supports them, a device without them is still suitable
+    pub optional_extensions: Vec<&'static CStr>,
+    pub optional_features: OptionalFeatures,
+}
+
+impl Default for DeviceRequirements {
+    // what the renderer needs
+    fn default() -> Self {
+        DeviceRequirements {
+            extensions: vec![ash::khr::swapchain::NAME],
+            base_features: vk::PhysicalDeviceFeatures {
+                // one indirect draw per batch, the first instance selects the draw data
+                multi_draw_indirect: vk::TRUE,
+                draw_indirect_first_instance: vk::TRUE,
+                ..Default::default()
+            },
+            vulkan11_features: vk::PhysicalDeviceVulkan11Features::default(),
+            vulkan12_features: vk::PhysicalDeviceVulkan12Features {
+                buffer_device_address: vk::TRUE,
+                descriptor_indexing: vk::TRUE,
+                // bindless textures
+                runtime_descriptor_array: vk::TRUE,
+                descriptor_binding_partially_bound: vk::TRUE,
+                descriptor_binding_sampled_image_update_after_bind: vk::TRUE,
+                descriptor_binding_update_unused_while_pending: vk::TRUE,
+                // async uploads
+                timeline_semaphore: vk::TRUE,
+                // the draw count of a batch is written by the culling shader
+                draw_indirect_count: vk::TRUE,
+                ..Default::default()
+            },
+            vulkan13_features: vk::PhysicalDeviceVulkan13Features {
+                dynamic_rendering: vk::TRUE,
+                synchronization2: vk::TRUE,
+                ..Default::default()
+            },
+            optional_extensions: Vec::new(),
+            optional_features: OptionalFeatures::default(),
+        }
+    }
+}
+
+impl DeviceRequirements {
+    // every feature that is true here is supported by the device
+    fn features_supported(&self, supported: &DeviceFeatures) -> bool {
+        // SAFETY: the ranges cover the Bool32 members of the structs, between the first and
+        // the last of them there is nothing else
+        unsafe {
+            bools_supported(
+                &self.base_features,
+                &supported.base_features,
+                std::mem::offset_of!(vk::PhysicalDeviceFeatures, robust_buffer_access),
+                std::mem::offset_of!(vk::PhysicalDeviceFeatures, inherited_queries),
+            ) && bools_supported(
+                &self.vulkan11_features,
+                &supported.vulkan11_features,
+                std::mem::offset_of!(
+                    vk::PhysicalDeviceVulkan11Features,
+                    storage_buffer16_bit_access
+                ),
+                std::mem::offset_of!(vk::PhysicalDeviceVulkan11Features, shader_draw_parameters),
+            ) && bools_supported(
+                &self.vulkan12_features,
+                &supported.vulkan12_features,
+                std::mem::offset_of!(
+                    vk::PhysicalDeviceVulkan12Features,
+                    sampler_mirror_clamp_to_edge
+                ),
+                std::mem::offset_of!(
+                    vk::PhysicalDeviceVulkan12Features,
+                    subgroup_broadcast_dynamic_id
+                ),
+            ) && bools_supported(
+                &self.vulkan13_features,
+                &supported.vulkan13_features,
+                std::mem::offset_of!(vk::PhysicalDeviceVulkan13Features, robust_image_access),
+                std::mem::offset_of!(vk::PhysicalDeviceVulkan13Features, maintenance4),
+            )
+        }
+    }
+
+    // the optional features the device supports, only the requested ones can be granted
+    fn granted_features(
+        &self,
+        instance: &Arc<Instance>,
+        device: vk::PhysicalDevice,
+    ) -> Result<OptionalFeatures, VulkanError> {
+        let requested = self.optional_features;
+        if !requested.ray_tracing_pipeline && !requested.ray_query {
+            return Ok(OptionalFeatures::default());
+        }
+        // checks the extensions too
+        let ray_tracing = instance.get_ray_tracing_features(device)?;
+        Ok(OptionalFeatures {
+            ray_tracing_pipeline: requested.ray_tracing_pipeline
+                && ray_tracing.ray_tracing_pipeline,
+            ray_query: requested.ray_query && ray_tracing.ray_query,
+        })
+    }
+}
+
+// members first..=last of both structs are Bool32s
+unsafe fn bools_supported<T>(required: &T, supported: &T, first: usize, last: usize) -> bool {
+    let count = (last - first) / std::mem::size_of::<vk::Bool32>() + 1;
+    let bools = |features: &T| {
+        std::slice::from_raw_parts(
+            (features as *const T as *const u8).add(first) as *const vk::Bool32,
+            count,
+        )
+    };
+    bools(required)
+        .iter()
+        .zip(bools(supported))
+        .all(|(required, supported)| *required == vk::FALSE || *supported == vk::TRUE)
+}
+
+// enabled if the device supports them, the selector reports which ones a device grants
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OptionalFeatures {
+    // RAY_TRACING_EXTENSIONS and their features
+    pub ray_tracing_pipeline: bool,
+    // RAY_QUERY_EXTENSIONS and their features
+    pub ray_query: bool,
+}
+
+struct Candidate {
+    device: vk::PhysicalDevice,
+    adapter: AdapterInfo,
+    granted: OptionalFeatures,
+    score: u64,
+    presents_from_graphics: bool,
+}
+
+// the device the selector chose and what it grants of the optional features
+#[derive(Debug, Clone, Copy)]
+pub struct SelectedDevice {
+    pub physical_device: vk::PhysicalDevice,
+    pub granted_features: OptionalFeatures,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AdapterType {
+    Discrete,
+    Integrated,
+    Virtual,
+    Cpu,
+    Other,
+}
+
+impl From<vk::PhysicalDeviceType> for AdapterType {
+    fn from(device_type: vk::PhysicalDeviceType) -> Self {
+        match device_type {
+            vk::PhysicalDeviceType::DISCRETE_GPU => AdapterType::Discrete,
+            vk::PhysicalDeviceType::INTEGRATED_GPU => AdapterType::Integrated,
+            vk::PhysicalDeviceType::VIRTUAL_GPU => AdapterType::Virtual,
+            vk::PhysicalDeviceType::CPU => AdapterType::Cpu,
+            _ => AdapterType::Other,
+        }
+    }
+}
+
+// what a launcher needs to show a gpu picker, see enumerate_adapters
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AdapterInfo {
+    pub name: String,
+    pub adapter_type: AdapterType,
+    // pci ids, e.g. vendor 0x10de = nvidia, 0x1002 = amd, 0x8086 = intel
+    pub vendor_id: u32,
+    pub device_id: u32,
+    // bytes in the device local heaps, shared system memory for most integrated gpus
+    pub vram: u64,
+}
+
+impl AdapterInfo {
+    pub fn new(instance: &Instance, device: vk::PhysicalDevice) -> Self {
+        let properties = instance.get_physical_device_properties(device);
+        let memory_properties = instance.get_physical_device_memory_properties(device);
+        let vram = memory_properties
+            .memory_heaps_as_slice()
+            .iter()
+            .filter(|heap| heap.flags.contains(vk::MemoryHeapFlags::DEVICE_LOCAL))
+            .map(|heap| heap.size)
+            .sum();
+        AdapterInfo {
+            name: properties
+                .device_name_as_c_str()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            adapter_type: properties.device_type.into(),
+            vendor_id: properties.vendor_id,
+            device_id: properties.device_id,
+            vram,
+        }
+    }
+}
+
+// every gpu with vulkan support, including the ones the renderer cant use
+pub fn enumerate_adapters(instance: &Instance) -> Result<Vec<AdapterInfo>, VulkanError> {
+    Ok(instance
+        .enumerate_physical_devices()?
+        .into_iter()
+        .map(|device| AdapterInfo::new(instance, device))
+        .collect())
+}
+
+// which suitable gpu the selector picks, the score decides between the matching ones
+// a preference that matches no suitable gpu is ignored
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GpuPreference {
+    // case-insensitive substring of the device name, e.g. "nvidia" or "rtx 4070"
+    pub name: Option<String>,
+    pub vendor_id: Option<u32>,
+    pub device_id: Option<u32>,
+    // prefer gpus whose graphics queue can present to the window
+    // vulkan cant tell which gpu drives a monitor, but the others have to copy every frame to
+    // it => on hybrid laptops and multi gpu desktops they usually cant present from graphics
+    pub prefer_display_adapter: bool,
+}
+
+impl GpuPreference {
+    // exactly the adapter a launcher picked from enumerate_adapters
+    pub fn adapter(adapter: &AdapterInfo) -> Self {
+        GpuPreference {
+            name: Some(adapter.name.clone()),
+            vendor_id: Some(adapter.vendor_id),
+            device_id: Some(adapter.device_id),
+            prefer_display_adapter: false,
+        }
+    }
+
+    // "0x10de" => vendor id, "0x10de:0x2684" => vendor and device id, anything else => name
+    pub fn parse(value: &str) -> Self {
+        let parse_id = |id: &str| {
+            id.trim()
+                .strip_prefix("0x")
+                .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+        };
+        let mut ids = value.split(':');
+        let vendor_id = ids.next().and_then(parse_id);
+        let device_id = ids.next().and_then(parse_id);
+        if vendor_id.is_some() && (device_id.is_some() || !value.contains(':')) {
+            return GpuPreference {
+                vendor_id,
+                device_id,
+                ..Default::default()
+            };
+        }
+        GpuPreference {
+            name: Some(value.to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn has_filter(&self) -> bool {
+        self.name.is_some() || self.vendor_id.is_some() || self.device_id.is_some()
+    }
+
+    fn matches(&self, adapter: &AdapterInfo) -> bool {
+        let name_matches = self
+            .name
+            .as_ref()
+            .is_none_or(|name| adapter.name.to_lowercase().contains(&name.to_lowercase()));
+        name_matches
+            && self.vendor_id.is_none_or(|id| id == adapter.vendor_id)
+            && self.device_id.is_none_or(|id| id == adapter.device_id)
+    }
+}
 
 pub struct PhysicalDeviceSelector {
     minimum_vulkan_version: Version,
+    preference: GpuPreference,
 }
 
 impl PhysicalDeviceSelector {
     pub fn new(minimum_vulkan_version: Version) -> Self {
         PhysicalDeviceSelector {
             minimum_vulkan_version,
+            preference: GpuPreference::default(),
         }
     }
 
-    pub fn select(&self, instance: Arc<Instance>, surface: &Surface) -> vk::PhysicalDevice {
-        let physical_devices = instance.enumerate_physical_devices();
+    pub fn prefer(mut self, preference: GpuPreference) -> Self {
+        self.preference = preference;
+        self
+    }
+
+    pub fn select(
+        &self,
+        instance: Arc<Instance>,
+        surface: &Surface,
+        requirements: &DeviceRequirements,
+    ) -> Result<SelectedDevice, VulkanError> {
+        let physical_devices = instance.enumerate_physical_devices()?;
 
         log::info!(
             "Found {} devices with Vulkan support",
             physical_devices.len()
         );
 
-        let mut suitable_devices: Vec<vk::PhysicalDevice> = physical_devices
-            .into_iter()
-            .filter(|device| {
-                Self::is_device_suitable(&instance, device, surface, self.minimum_vulkan_version)
-            })
-            .collect();
+        let mut suitable_devices: Vec<Candidate> = Vec::new();
+        for device in physical_devices {
+            let adapter = AdapterInfo::new(&instance, device);
+            log::info!(
+                "  {} ({:?}, vendor {:#06x}, device {:#06x}, {} MiB)",
+                adapter.name,
+                adapter.adapter_type,
+                adapter.vendor_id,
+                adapter.device_id,
+                adapter.vram / (1024 * 1024)
+            );
+            if let Some(granted) = Self::check_device_suitability(
+                &instance,
+                &device,
+                surface,
+                self.minimum_vulkan_version,
+                requirements,
+            )? {
+                let queue_families = instance.find_queue_families(&device, surface)?;
+                suitable_devices.push(Candidate {
+                    device,
+                    granted,
+                    score: Self::get_device_suitability_score(&instance, device, &granted),
+                    presents_from_graphics: queue_families.graphics_family
+                        == queue_families.presentation_family,
+                    adapter,
+                });
+            }
+        }
         log::info!("Found {} suitable devices", suitable_devices.len());
 
-        suitable_devices
-            .sort_by_key(|device| Reverse(self.get_device_suitability_score(&instance, *device)));
-
         if suitable_devices.is_empty() {
-            panic!("No suitable devices found!")
+            return Err(VulkanError::NoSuitableDevice);
         }
 
-        let chosen_device = suitable_devices[0];
+        let preference = &self.preference;
+        if preference.has_filter() {
+            let any_match = suitable_devices
+                .iter()
+                .any(|candidate| preference.matches(&candidate.adapter));
+            if any_match {
+                suitable_devices.retain(|candidate| preference.matches(&candidate.adapter));
+            } else {
+                log::warn!(
+                    "No suitable device matching {:?} found. Falling back to default selection",
+                    preference
+                );
+            }
+        }
+        // the display adapter wins over a better type => the user asked for it
+        suitable_devices.sort_by_key(|candidate| {
+            Reverse((
+                preference.prefer_display_adapter && candidate.presents_from_graphics,
+                candidate.score,
+            ))
+        });
 
-        let device_properties = instance.get_physical_device_properties(chosen_device);
-        let device_name = device_properties.device_name_as_c_str().expect(
-            "Should be able to convert device name to c_str since its a string coming from a C API",
+        let chosen_device = &suitable_devices[0];
+        let physical_device = chosen_device.device;
+        let granted_features = chosen_device.granted;
+        log::info!(
+            "Choosing device {:?}, optional features: {:?}",
+            chosen_device.adapter.name,
+            granted_features
         );
 
-        log::info!("Choosing device {:?}", device_name);
-
-        chosen_device
+        Ok(SelectedDevice {
+            physical_device,
+            granted_features,
+        })
     }
 
-    fn is_device_suitable(
+    // None => not suitable, the granted optional features otherwise
+    fn check_device_suitability(
         instance: &Arc<Instance>,
         device: &vk::PhysicalDevice,
         surface: &Surface,
         minimum_vulkan_version: Version,
-    ) -> bool {
+        requirements: &DeviceRequirements,
+    ) -> Result<Option<OptionalFeatures>, VulkanError> {
         let device_properties = instance.get_physical_device_properties(*device);
         let min_version_vk = minimum_vulkan_version.to_api_version();
 
         if min_version_vk > device_properties.api_version {
-            return false;
+            return Ok(None);
         }
 
-        let queue_families_supported = instance.find_queue_families(device, surface).is_complete();
+        let queue_families_supported = instance.find_queue_families(device, surface)?.is_complete();
 
-        //TODO: handle extensions/features/swap_chain_support better, s.t. you dont have to specify
-        //stuff twice
-        let required_device_extensions: [&str; 1] = ["VK_KHR_swapchain"];
         let extensions_supported =
-            Self::check_device_extension_support(instance, device, &required_device_extensions);
+            Self::check_device_extension_support(instance, device, &requirements.extensions)?;
 
         let mut swapchain_adequate = false;
         if extensions_supported {
-            let swap_chain_support = surface.query_support_details(device);
+            let swap_chain_support = surface.query_support_details(device)?;
             swapchain_adequate = !swap_chain_support.surface_formats.is_empty()
                 && !swap_chain_support.present_modes.is_empty();
         }
 
-        let features_supported = Self::check_feature_support(instance, device);
+        let feature_support = Self::check_feature_support(instance, device, requirements)?;
 
-        queue_families_supported && extensions_supported && swapchain_adequate && features_supported
+        let suitable = queue_families_supported
+            && extensions_supported
+            && swapchain_adequate
+            && feature_support.required;
+        Ok(suitable.then_some(feature_support.granted))
     }
 
     fn check_device_extension_support(
         instance: &Arc<Instance>,
         device: &vk::PhysicalDevice,
-        required_extensions: &[&str],
-    ) -> bool {
-        let supported_extensions = instance.enumerate_device_extension_properties(*device);
-        let cross_section = supported_extensions.iter().filter(|extension_prop| {
-            required_extensions.contains(
-                &extension_prop
-                    .extension_name_as_c_str()
-                    .expect("We only use basic ASCII strings here so shouldnt fail")
-                    .to_str()
-                    .expect("We only use basic ASCII strings here so shouldnt fail"),
-            )
-        });
-        cross_section.count() == required_extensions.len()
-    }
-
-    fn check_feature_support(instance: &Arc<Instance>, device: &vk::PhysicalDevice) -> bool {
-        //TODO: at some point: pass required features via param -> and check whether these
-        //arbitrary features are supported
+        required_extensions: &[&CStr],
+    ) -> Result<bool, VulkanError> {
+        let supported_extensions = instance.enumerate_device_extension_properties(*device)?;
+        Ok(required_extensions.iter().all(|extension| {
+            supported_extensions
+                .iter()
+                .any(|prop| prop.extension_name_as_c_str() == Ok(*extension))
+        }))
+    }
+
+    // required => the requirements are met, the optional features are granted if supported
+    pub fn check_feature_support(
+        instance: &Arc<Instance>,
+        device: &vk::PhysicalDevice,
+        requirements: &DeviceRequirements,
+    ) -> Result<FeatureSupport, VulkanError> {
         let supported_features = instance.get_supported_features(device);
-
-        let vulkan12_features = supported_features.vulkan12_features;
-        let vulkan13_features = supported_features.vulkan13_features;
-
-        vulkan12_features.buffer_device_address == vk::TRUE
-            && vulkan12_features.descriptor_indexing == vk::TRUE
-            && vulkan13_features.dynamic_rendering == vk::TRUE
-            && vulkan13_features.synchronization2 == vk::TRUE
+        Ok(FeatureSupport {
+            required: requirements.features_supported(&supported_features),
+            granted: requirements.granted_features(instance, *device)?,
+        })
     }
 
+    // only granted features count => they were requested
     fn get_device_suitability_score(
-        &self,
         instance: &Arc<Instance>,
         device: vk::PhysicalDevice,
+        granted: &OptionalFeatures,
     ) -> u64 {
         let device_properties = instance.get_physical_device_properties(device);
         let mut score = 0;
@@ -136,6 +529,13 @@ impl PhysicalDeviceSelector {
             vk::PhysicalDeviceType::CPU => 10,
             _ => 0,
         };
+        // breaks ties between devices of the same type, never picks a worse type for it
+        if granted.ray_tracing_pipeline {
+            score += 50;
+        }
+        if granted.ray_query {
+            score += 25;
+        }
         score
     }
 }
@@ -148,25 +548,88 @@ pub struct DeviceFeatures<'a> {
     pub base_features: vk::PhysicalDeviceFeatures,
 }
 
+pub struct FeatureSupport {
+    pub required: bool,
+    pub granted: OptionalFeatures,
+}
+
+// false for every feature whose extension the device doesnt have
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RayTracingFeatures {
+    // needed by both of the others
+    pub acceleration_structure: bool,
+    pub ray_tracing_pipeline: bool,
+    pub ray_query: bool,
+}
+
+// a vk::Queue with the lock vulkan requires around submits/presents to it
+// roles that share a family share the Queue (queue 0 of the family) => one lock for all of them
+pub struct Queue {
+    family_idx: u32,
+    handle: Mutex<vk::Queue>,
+}
+
+impl Queue {
+    fn new(device: &ash::Device, family_idx: u32) -> Arc<Self> {
+        let handle = unsafe { device.get_device_queue(family_idx, 0) };
+        Arc::new(Queue {
+            family_idx,
+            handle: Mutex::new(handle),
+        })
+    }
+
+    pub fn family_idx(&self) -> u32 {
+        self.family_idx
+    }
+
+    // hold the guard while the queue is used => submissions from several threads dont overlap
+    pub fn lock(&self) -> MutexGuard<'_, vk::Queue> {
+        self.handle.lock().unwrap()
+    }
+}
+
+struct RayTracingFunctions {
+    pipeline: ash::khr::ray_tracing_pipeline::Device,
+    properties: vk::PhysicalDeviceRayTracingPipelinePropertiesKHR<'static>,
+}
+
 pub struct Device {
     instance: Arc<Instance>,
     physical_device: vk::PhysicalDevice,
     handle: ash::Device,
-    graphics_queue: vk::Queue,
-    graphics_queue_family_idx: u32,
-    presentation_queue: vk::Queue,
-    presentation_queue_family_idx: u32,
+    graphics_queue: Arc<Queue>,
+    presentation_queue: Arc<Queue>,
+    // same as the graphics queue if there is no dedicated transfer family
+    transfer_queue: Arc<Queue>,
+    // same as the graphics queue if there is no family with compute but without graphics
+    compute_queue: Arc<Queue>,
+    // every distinct queue above, locked in this order by wait_idle
+    queues: Vec<Arc<Queue>>,
+    // only if VK_EXT_full_screen_exclusive was requested and is supported
+    full_screen_exclusive: Option<ash::ext::full_screen_exclusive::Device>,
+    // only if RAY_TRACING_EXTENSIONS or RAY_QUERY_EXTENSIONS were requested and are supported
+    acceleration_structure: Option<ash::khr::acceleration_structure::Device>,
+    // only if RAY_TRACING_EXTENSIONS were requested and are supported
+    ray_tracing: Option<RayTracingFunctions>,
+    // RAY_QUERY_EXTENSIONS were requested and are supported => shaders can use GL_EXT_ray_query
+    ray_query: bool,
+    // the optional features of the requirements that were enabled
+    granted_features: OptionalFeatures,
+    // only if the instance has debug utils (= validation enabled)
+    debug_labels: Option<DebugLabels>,
+    // draws recorded since the last take_draw_call_count, for the stats overlay
+    draw_calls: AtomicU32,
 }
 
 impl Device {
     pub fn new(
         instance: Arc<Instance>,
         physical_device: &vk::PhysicalDevice,
-        //required_device_features: &DeviceFeatures,
-        //required_extensions: &[&str],
         surface: &Surface,
-    ) -> Arc<Self> {
-        let queue_family_indices = instance.find_queue_families(physical_device, surface);
+        // the same the device was selected with
+        requirements: &DeviceRequirements,
+    ) -> Result<Arc<Self>, VulkanError> {
+        let queue_family_indices = instance.find_queue_families(physical_device, surface)?;
         let graphics_q_fam_idx = queue_family_indices
             .graphics_family
             .expect("Q should exist since we checked for device suitabiity");
@@ -174,9 +637,30 @@ impl Device {
             .presentation_family
             .expect("Q should exist since we checked for device suitabiity");
 
+        let transfer_q_fam_idx = queue_family_indices
+            .transfer_family
+            .unwrap_or(graphics_q_fam_idx);
+        let compute_q_fam_idx = queue_family_indices
+            .compute_family
+            .unwrap_or(graphics_q_fam_idx);
+
         let mut unique_queue_families = HashSet::new();
         unique_queue_families.insert(graphics_q_fam_idx);
         unique_queue_families.insert(present_q_fam_idx);
+        unique_queue_families.insert(transfer_q_fam_idx);
+        unique_queue_families.insert(compute_q_fam_idx);
+        log::info!(
+            "Queue families: graphics {}, present {}{}, compute {:?}, transfer {:?}",
+            graphics_q_fam_idx,
+            present_q_fam_idx,
+            if graphics_q_fam_idx == present_q_fam_idx {
+                " (shared)"
+            } else {
+                " (separate => concurrent swapchain images)"
+            },
+            queue_family_indices.compute_family,
+            queue_family_indices.transfer_family
+        );
         log::debug!("Using Queue Families: {:?}", unique_queue_families);
 
         let mut queue_create_infos: Vec<vk::DeviceQueueCreateInfo> = Vec::new();
@@ -193,36 +677,88 @@ impl Device {
             queue_create_infos.push(device_queue_create_info);
         }
 
-        //TODO: handle better
-        let required_extensions = ["VK_KHR_swapchain"];
-        let required_extensions_cstr = required_extensions
-            .iter()
-            .map(|ext| std::ffi::CString::new(*ext).unwrap())
-            .collect::<Vec<std::ffi::CString>>();
-        let required_extension_names_raw: Vec<*const c_char> = required_extensions_cstr
+        let granted_features = requirements.granted_features(&instance, *physical_device)?;
+        let mut enabled_extensions: Vec<&CStr> = requirements.extensions.clone();
+        let supported_extensions =
+            instance.enumerate_device_extension_properties(*physical_device)?;
+        for extension in requirements.optional_extensions.iter() {
+            let supported = supported_extensions
+                .iter()
+                .any(|prop| prop.extension_name_as_c_str() == Ok(*extension));
+            if supported {
+                enabled_extensions.push(extension);
+            } else {
+                log::info!("Optional device extension {:?} not supported", extension);
+            }
+        }
+        // granted => the device has all of their extensions
+        if granted_features.ray_tracing_pipeline {
+            enabled_extensions.extend(RAY_TRACING_EXTENSIONS);
+        }
+        if granted_features.ray_query {
+            enabled_extensions.extend(RAY_QUERY_EXTENSIONS);
+        }
+        // the ray tracing and ray query extensions share some of theirs
+        let mut seen = HashSet::new();
+        enabled_extensions.retain(|extension| seen.insert(*extension));
+        let enabled_extension_names_raw: Vec<*const c_char> = enabled_extensions
             .iter()
-            .map(|ext| ext.as_ptr() as *const c_char)
+            .map(|extension| extension.as_ptr())
             .collect();
+        let is_enabled = |name: &CStr| enabled_extensions.contains(&name);
+        let ray_tracing_enabled = granted_features.ray_tracing_pipeline;
+        let ray_query_enabled = granted_features.ray_query;
+        let acceleration_structure_enabled = ray_tracing_enabled || ray_query_enabled;
+
+        // copies of the requirements => their p_next is replaced by the chain below
+        let mut vulkan11_feats = vk::PhysicalDeviceVulkan11Features {
+            s_type: vk::StructureType::PHYSICAL_DEVICE_VULKAN_1_1_FEATURES,
+            p_next: std::ptr::null_mut(),
+            ..requirements.vulkan11_features
+        };
         let mut vulkan12_feats = vk::PhysicalDeviceVulkan12Features {
             s_type: vk::StructureType::PHYSICAL_DEVICE_VULKAN_1_2_FEATURES,
-            buffer_device_address: vk::TRUE,
-            descriptor_indexing: vk::TRUE,
-            ..Default::default()
+            p_next: &mut vulkan11_feats as *mut _ as *mut std::ffi::c_void,
+            ..requirements.vulkan12_features
         };
         let mut vulkan13_feats = vk::PhysicalDeviceVulkan13Features {
             s_type: vk::StructureType::PHYSICAL_DEVICE_VULKAN_1_3_FEATURES,
             p_next: &mut vulkan12_feats as *mut _ as *mut std::ffi::c_void,
-            dynamic_rendering: vk::TRUE,
-            synchronization2: vk::TRUE,
+            ..requirements.vulkan13_features
+        };
+        let mut ray_tracing_pipeline_feats = vk::PhysicalDeviceRayTracingPipelineFeaturesKHR {
+            s_type: vk::StructureType::PHYSICAL_DEVICE_RAY_TRACING_PIPELINE_FEATURES_KHR,
+            ray_tracing_pipeline: vk::TRUE,
             ..Default::default()
         };
-        let device_features = vk::PhysicalDeviceFeatures {
+        let mut ray_query_feats = vk::PhysicalDeviceRayQueryFeaturesKHR {
+            s_type: vk::StructureType::PHYSICAL_DEVICE_RAY_QUERY_FEATURES_KHR,
+            ray_query: vk::TRUE,
             ..Default::default()
         };
+        let mut acceleration_structure_feats = vk::PhysicalDeviceAccelerationStructureFeaturesKHR {
+            s_type: vk::StructureType::PHYSICAL_DEVICE_ACCELERATION_STRUCTURE_FEATURES_KHR,
+            acceleration_structure: vk::TRUE,
+            ..Default::default()
+        };
+        // the ray tracing features are only allowed in the chain if their extensions are enabled
+        let mut features_chain = &mut vulkan13_feats as *mut _ as *mut std::ffi::c_void;
+        if ray_tracing_enabled {
+            ray_tracing_pipeline_feats.p_next = features_chain;
+            features_chain = &mut ray_tracing_pipeline_feats as *mut _ as *mut std::ffi::c_void;
+        }
+        if ray_query_enabled {
+            ray_query_feats.p_next = features_chain;
+            features_chain = &mut ray_query_feats as *mut _ as *mut std::ffi::c_void;
+        }
+        if acceleration_structure_enabled {
+            acceleration_structure_feats.p_next = features_chain;
+            features_chain = &mut acceleration_structure_feats as *mut _ as *mut std::ffi::c_void;
+        }
         let required_features = vk::PhysicalDeviceFeatures2 {
             s_type: vk::StructureType::PHYSICAL_DEVICE_FEATURES_2,
-            p_next: &mut vulkan13_feats as *mut _ as *mut std::ffi::c_void,
-            features: device_features,
+            p_next: features_chain,
+            features: requirements.base_features,
             ..Default::default()
         };
 
@@ -232,43 +768,93 @@ impl Device {
             queue_create_info_count: queue_create_infos.len() as u32,
             p_next: &required_features as *const vk::PhysicalDeviceFeatures2
                 as *const std::ffi::c_void,
-            enabled_extension_count: required_extension_names_raw.len() as u32,
-            pp_enabled_extension_names: required_extension_names_raw.as_ptr(),
+            enabled_extension_count: enabled_extension_names_raw.len() as u32,
+            pp_enabled_extension_names: enabled_extension_names_raw.as_ptr(),
             flags: vk::DeviceCreateFlags::empty(),
             ..Default::default()
         };
-        let logical_device = instance.create_logical_device(physical_device, &device_create_info);
-        let graphics_queue = unsafe { logical_device.get_device_queue(graphics_q_fam_idx, 0) };
-        let presentation_queue = unsafe { logical_device.get_device_queue(present_q_fam_idx, 0) };
+        let logical_device =
+            instance.create_logical_device(physical_device, &device_create_info)?;
+        let mut queues: Vec<Arc<Queue>> = Vec::new();
+        let mut queue_of_family = |family_idx: u32| {
+            if let Some(queue) = queues.iter().find(|queue| queue.family_idx() == family_idx) {
+                return queue.clone();
+            }
+            let queue = Queue::new(&logical_device, family_idx);
+            queues.push(queue.clone());
+            queue
+        };
+        let graphics_queue = queue_of_family(graphics_q_fam_idx);
+        let presentation_queue = queue_of_family(present_q_fam_idx);
+        let transfer_queue = queue_of_family(transfer_q_fam_idx);
+        let compute_queue = queue_of_family(compute_q_fam_idx);
+        if compute_q_fam_idx != graphics_q_fam_idx {
+            log::info!("Async compute on queue family {}", compute_q_fam_idx);
+        }
+        let full_screen_exclusive = is_enabled(ash::ext::full_screen_exclusive::NAME)
+            .then(|| instance.create_full_screen_exclusive_loader(&logical_device));
+        let acceleration_structure = acceleration_structure_enabled
+            .then(|| instance.create_acceleration_structure_loader(&logical_device));
+        let ray_tracing = ray_tracing_enabled.then(|| RayTracingFunctions {
+            pipeline: instance.create_ray_tracing_pipeline_loader(&logical_device),
+            properties: instance.get_ray_tracing_pipeline_properties(*physical_device),
+        });
+        if ray_tracing_enabled {
+            log::info!("Hardware ray tracing enabled");
+        }
+        if ray_query_enabled {
+            log::info!("Ray queries enabled");
+        }
+        let debug_labels = instance
+            .is_extension_enabled(ash::ext::debug_utils::NAME)
+            .then(|| DebugLabels::new(instance.create_debug_utils_device(&logical_device)));
 
-        Arc::new(Device {
+        Ok(Arc::new(Device {
             instance,
             physical_device: *physical_device,
             handle: logical_device,
             graphics_queue,
-            graphics_queue_family_idx: graphics_q_fam_idx,
             presentation_queue,
-            presentation_queue_family_idx: present_q_fam_idx,
-        })
+            transfer_queue,
+            compute_queue,
+            queues,
+            full_screen_exclusive,
+            acceleration_structure,
+            ray_tracing,
+            ray_query: ray_query_enabled,
+            granted_features,
+            debug_labels,
+            draw_calls: AtomicU32::new(0),
+        }))
+    }
+
+    pub fn create_command_pool(&self) -> Result<vk::CommandPool, VulkanError> {
+        self.create_command_pool_for_family(self.graphics_queue.family_idx())
     }
 
-    pub fn create_command_pool(&self) -> vk::CommandPool {
+    // command buffers can only be submitted to queues of the pool's family
+    pub fn create_command_pool_for_family(
+        &self,
+        queue_family_index: u32,
+    ) -> Result<vk::CommandPool, VulkanError> {
         let command_pool_create_info = vk::CommandPoolCreateInfo {
             s_type: vk::StructureType::COMMAND_POOL_CREATE_INFO,
             flags: vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER,
-            queue_family_index: self.graphics_queue_family_idx,
+            queue_family_index,
             p_next: std::ptr::null(),
             ..Default::default()
         };
 
-        unsafe {
+        Ok(unsafe {
             self.handle
-                .create_command_pool(&command_pool_create_info, None)
-                .expect("I pray that I never run out of memory")
-        }
+                .create_command_pool(&command_pool_create_info, None)?
+        })
     }
 
-    pub fn create_command_buffer(&self, command_pool: vk::CommandPool) -> vk::CommandBuffer {
+    pub fn create_command_buffer(
+        &self,
+        command_pool: vk::CommandPool,
+    ) -> Result<vk::CommandBuffer, VulkanError> {
         let command_buffer_allocate_info = vk::CommandBufferAllocateInfo {
             s_type: vk::StructureType::COMMAND_BUFFER_ALLOCATE_INFO,
             command_pool,
@@ -277,13 +863,23 @@ impl Device {
             p_next: std::ptr::null(),
             ..Default::default()
         };
+        let command_buffers = unsafe {
+            self.handle
+                .allocate_command_buffers(&command_buffer_allocate_info)?
+        };
+        Ok(*command_buffers
+            .first()
+            .expect("We should get atleast 1 command_buffer since count is set to 1"))
+    }
+
+    pub fn free_command_buffer(
+        &self,
+        command_pool: vk::CommandPool,
+        command_buffer: vk::CommandBuffer,
+    ) {
         unsafe {
-            *self
-                .handle
-                .allocate_command_buffers(&command_buffer_allocate_info)
-                .expect("I pray that I never run out of memory")
-                .first()
-                .expect("We should get atleast 1 command_buffer since count is set to 1")
+            self.handle
+                .free_command_buffers(command_pool, &[command_buffer]);
         }
     }
 
@@ -294,43 +890,92 @@ impl Device {
     }
 
     pub fn get_graphics_queue_idx(&self) -> u32 {
-        self.graphics_queue_family_idx
+        self.graphics_queue.family_idx()
     }
 
     pub fn get_presentation_queue_idx(&self) -> u32 {
-        self.presentation_queue_family_idx
+        self.presentation_queue.family_idx()
+    }
+
+    pub fn get_transfer_queue_idx(&self) -> u32 {
+        self.transfer_queue.family_idx()
     }
 
-    pub fn get_presentation_queue(&self) -> vk::Queue {
-        self.presentation_queue
+    pub fn get_compute_queue_idx(&self) -> u32 {
+        self.compute_queue.family_idx()
     }
 
+    // compute work can run next to the graphics queue instead of between its commands
+    pub fn has_async_compute(&self) -> bool {
+        self.get_compute_queue_idx() != self.get_graphics_queue_idx()
+    }
+
+    // families that have to share images used by async compute and graphics
+    // empty => EXCLUSIVE is fine, there is no async compute queue
+    pub fn async_compute_families(&self) -> Vec<u32> {
+        if self.has_async_compute() {
+            vec![self.get_graphics_queue_idx(), self.get_compute_queue_idx()]
+        } else {
+            Vec::new()
+        }
+    }
+
+    pub fn get_presentation_queue(&self) -> Arc<Queue> {
+        self.presentation_queue.clone()
+    }
+
+    pub fn properties(&self) -> vk::PhysicalDeviceProperties {
+        self.instance
+            .get_physical_device_properties(self.physical_device)
+    }
+
+    // for optimal tiling, the only tiling we create images with
+    pub fn supports_format(&self, format: vk::Format, features: vk::FormatFeatureFlags) -> bool {
+        self.instance
+            .get_physical_device_format_properties(self.physical_device, format)
+            .optimal_tiling_features
+            .contains(features)
+    }
+
+    // cube => 6 layers that can be viewed as a cubemap
     pub fn create_image(
         &self,
         format: vk::Format,
         usage_flags: vk::ImageUsageFlags,
         extent: vk::Extent3D,
         mip_levels: u32,
-    ) -> vk::Image {
+        layers: ImageLayers,
+        // more than one => CONCURRENT, the image can be used by all of them without transfers
+        queue_families: &[u32],
+    ) -> Result<vk::Image, VulkanError> {
+        let sharing_mode = if queue_families.len() > 1 {
+            vk::SharingMode::CONCURRENT
+        } else {
+            vk::SharingMode::EXCLUSIVE
+        };
         let image_create_info = vk::ImageCreateInfo {
             s_type: vk::StructureType::IMAGE_CREATE_INFO,
             p_next: std::ptr::null(),
+            flags: layers.create_flags(),
             image_type: vk::ImageType::TYPE_2D,
             format,
             extent,
             mip_levels,
-            array_layers: 1,
+            array_layers: layers.count(),
             samples: vk::SampleCountFlags::TYPE_1,
             tiling: vk::ImageTiling::OPTIMAL,
             usage: usage_flags,
+            sharing_mode,
+            queue_family_index_count: if queue_families.len() > 1 {
+                queue_families.len() as u32
+            } else {
+                0
+            },
+            p_queue_family_indices: queue_families.as_ptr(),
             ..Default::default()
         };
 
-        unsafe {
-            self.handle
-                .create_image(&image_create_info, None)
-                .expect("Device hopefully not out of memory")
-        }
+        Ok(unsafe { self.handle.create_image(&image_create_info, None)? })
     }
 
     pub fn destroy_image(&self, image: vk::Image) {
@@ -343,40 +988,57 @@ impl Device {
         unsafe { self.handle.get_image_memory_requirements(image) }
     }
 
+    // view of every mip level and layer
     pub fn create_image_view(
         &self,
         image: vk::Image,
         format: vk::Format,
         aspect_flags: vk::ImageAspectFlags,
         mip_levels: u32,
-    ) -> vk::ImageView {
-        let image_view_create_info = vk::ImageViewCreateInfo {
-            s_type: vk::StructureType::IMAGE_VIEW_CREATE_INFO,
-            p_next: std::ptr::null(),
-            view_type: vk::ImageViewType::TYPE_2D,
+        layers: ImageLayers,
+    ) -> Result<vk::ImageView, VulkanError> {
+        self.create_image_view_range(
             image,
+            layers.view_type(),
             format,
-            subresource_range: vk::ImageSubresourceRange {
+            vk::ImageSubresourceRange {
                 aspect_mask: aspect_flags,
                 base_mip_level: 0,
                 level_count: mip_levels,
                 base_array_layer: 0,
-                layer_count: 1,
+                layer_count: layers.count(),
             },
+        )
+    }
+
+    // e.g. cube views or views of single mip levels for storage writes
+    pub fn create_image_view_range(
+        &self,
+        image: vk::Image,
+        view_type: vk::ImageViewType,
+        format: vk::Format,
+        subresource_range: vk::ImageSubresourceRange,
+    ) -> Result<vk::ImageView, VulkanError> {
+        let image_view_create_info = vk::ImageViewCreateInfo {
+            s_type: vk::StructureType::IMAGE_VIEW_CREATE_INFO,
+            p_next: std::ptr::null(),
+            view_type,
+            image,
+            format,
+            subresource_range,
             ..Default::default()
         };
-        unsafe {
+        Ok(unsafe {
             self.handle
-                .create_image_view(&image_view_create_info, None)
-                .expect("Device hopefully not out of memory")
-        }
+                .create_image_view(&image_view_create_info, None)?
+        })
     }
 
     pub fn create_image_views(
         &self,
         format: vk::Format,
         swapchain_images: &[vk::Image],
-    ) -> Vec<vk::ImageView> {
+    ) -> Result<Vec<vk::ImageView>, VulkanError> {
         let mut swapchain_views: Vec<vk::ImageView> = Vec::with_capacity(swapchain_images.len());
         for image in swapchain_images.iter() {
             let create_info = vk::ImageViewCreateInfo {
@@ -401,14 +1063,17 @@ impl Device {
                 flags: vk::ImageViewCreateFlags::empty(),
                 ..Default::default()
             };
-            let image_view = unsafe {
-                self.handle
-                    .create_image_view(&create_info, None)
-                    .expect("Device hopefully not out of memory")
-            };
-            swapchain_views.push(image_view);
+            match unsafe { self.handle.create_image_view(&create_info, None) } {
+                Ok(image_view) => swapchain_views.push(image_view),
+                Err(e) => {
+                    for image_view in swapchain_views {
+                        self.destroy_image_view(image_view);
+                    }
+                    return Err(e.into());
+                }
+            }
         }
-        swapchain_views
+        Ok(swapchain_views)
     }
 
     pub fn destroy_image_view(&self, image_view: vk::ImageView) {
@@ -422,15 +1087,18 @@ impl Device {
         image: vk::Image,
         memory: vk::DeviceMemory,
         offset: vk::DeviceSize,
-    ) {
+    ) -> Result<(), VulkanError> {
         unsafe {
-            self.handle
-                .bind_image_memory(image, memory, offset)
-                .expect("I pray that host is never out of memory")
+            self.handle.bind_image_memory(image, memory, offset)?;
         }
+        Ok(())
     }
 
-    pub fn create_buffer(&self, usage: vk::BufferUsageFlags, size: vk::DeviceSize) -> vk::Buffer {
+    pub fn create_buffer(
+        &self,
+        usage: vk::BufferUsageFlags,
+        size: vk::DeviceSize,
+    ) -> Result<vk::Buffer, VulkanError> {
         let buffer_create_info = vk::BufferCreateInfo {
             s_type: vk::StructureType::BUFFER_CREATE_INFO,
             p_next: std::ptr::null(),
@@ -438,11 +1106,7 @@ impl Device {
             size,
             ..Default::default()
         };
-        unsafe {
-            self.handle
-                .create_buffer(&buffer_create_info, None)
-                .expect("I pray that I never run out of memory")
-        }
+        Ok(unsafe { self.handle.create_buffer(&buffer_create_info, None)? })
     }
 
     pub fn destroy_buffer(&self, buffer: vk::Buffer) {
@@ -460,12 +1124,11 @@ impl Device {
         buffer: vk::Buffer,
         memory: vk::DeviceMemory,
         offset: vk::DeviceSize,
-    ) {
+    ) -> Result<(), VulkanError> {
         unsafe {
-            self.handle
-                .bind_buffer_memory(buffer, memory, offset)
-                .expect("I pray that host is never out of memory")
+            self.handle.bind_buffer_memory(buffer, memory, offset)?;
         }
+        Ok(())
     }
 
     pub fn get_buffer_device_address(&self, buffer: vk::Buffer) -> vk::DeviceAddress {
@@ -485,103 +1148,450 @@ impl Device {
         self.instance.create_swapchain_loader(&self.handle)
     }
 
-    pub fn create_semaphore(&self) -> vk::Semaphore {
-        let semaphore_create_info = vk::SemaphoreCreateInfo {
-            s_type: vk::StructureType::SEMAPHORE_CREATE_INFO,
-            p_next: std::ptr::null(),
-            flags: vk::SemaphoreCreateFlags::empty(),
-            ..Default::default()
-        };
-        unsafe {
-            self.handle
-                .create_semaphore(&semaphore_create_info, None)
-                .expect("I pray that I never run out of memory")
-        }
+    pub fn full_screen_exclusive(&self) -> Option<&ash::ext::full_screen_exclusive::Device> {
+        self.full_screen_exclusive.as_ref()
     }
 
-    pub fn destroy_semaphore(&self, semaphore: vk::Semaphore) {
-        unsafe {
-            self.handle.destroy_semaphore(semaphore, None);
-        }
+    pub fn granted_features(&self) -> OptionalFeatures {
+        self.granted_features
     }
 
-    pub fn create_fence(&self, flags: vk::FenceCreateFlags) -> vk::Fence {
-        let fence_create_info = vk::FenceCreateInfo {
-            s_type: vk::StructureType::FENCE_CREATE_INFO,
-            p_next: std::ptr::null(),
-            flags,
-            ..Default::default()
-        };
-        unsafe {
-            self.handle
-                .create_fence(&fence_create_info, None)
-                .expect("I pray that I never run out of memory")
-        }
+    // ray tracing pipelines or ray queries
+    pub fn supports_acceleration_structures(&self) -> bool {
+        self.acceleration_structure.is_some()
     }
 
-    pub fn destroy_fence(&self, fence: vk::Fence) {
-        unsafe {
-            self.handle.destroy_fence(fence, None);
-        }
+    pub fn supports_ray_tracing(&self) -> bool {
+        self.ray_tracing.is_some()
     }
 
-    pub fn wait_for_fence(&self, fence: &vk::Fence, timeout: u64) {
-        self.wait_for_fences(&[*fence], true, timeout)
+    pub fn supports_ray_query(&self) -> bool {
+        self.ray_query
     }
 
-    pub fn wait_for_fences(&self, fences: &[vk::Fence], wait_all: bool, timeout: u64) {
-        unsafe {
-            self.handle
-                .wait_for_fences(fences, wait_all, timeout)
-                .expect("I pray that I never run out of memory")
-        }
+    // the functions below panic if their extensions are not enabled
+    // => check supports_acceleration_structures/supports_ray_tracing first
+    fn acceleration_structure(&self) -> &ash::khr::acceleration_structure::Device {
+        self.acceleration_structure
+            .as_ref()
+            .expect("Acceleration structures are only used if the device supports them")
     }
 
-    pub fn reset_fence(&self, fence: &vk::Fence) {
-        self.reset_fences(&[*fence])
+    fn ray_tracing(&self) -> &RayTracingFunctions {
+        self.ray_tracing
+            .as_ref()
+            .expect("Ray tracing functions are only used if the device supports ray tracing")
+    }
+
+    pub fn ray_tracing_properties(&self) -> &vk::PhysicalDeviceRayTracingPipelinePropertiesKHR<'_> {
+        &self.ray_tracing().properties
+    }
+
+    pub fn create_acceleration_structure(
+        &self,
+        create_info: &vk::AccelerationStructureCreateInfoKHR,
+    ) -> Result<vk::AccelerationStructureKHR, VulkanError> {
+        Ok(unsafe {
+            self.acceleration_structure()
+                .create_acceleration_structure(create_info, None)?
+        })
     }
 
-    pub fn reset_fences(&self, fences: &[vk::Fence]) {
+    pub fn destroy_acceleration_structure(
+        &self,
+        acceleration_structure: vk::AccelerationStructureKHR,
+    ) {
         unsafe {
-            self.handle
-                .reset_fences(fences)
-                .expect("I pray that I never run out of memory")
+            self.acceleration_structure()
+                .destroy_acceleration_structure(acceleration_structure, None);
         }
     }
 
-    pub fn begin_command_buffer(
+    // one primitive count per geometry of build_info
+    pub fn get_acceleration_structure_build_sizes(
         &self,
-        command_buffer: vk::CommandBuffer,
-        flags: vk::CommandBufferUsageFlags,
-    ) {
-        let begin_command_buffer_info = vk::CommandBufferBeginInfo {
-            s_type: vk::StructureType::COMMAND_BUFFER_BEGIN_INFO,
-            flags,
-            p_inheritance_info: std::ptr::null(),
+        build_info: &vk::AccelerationStructureBuildGeometryInfoKHR,
+        max_primitive_counts: &[u32],
+    ) -> vk::AccelerationStructureBuildSizesInfoKHR<'static> {
+        let mut size_info = vk::AccelerationStructureBuildSizesInfoKHR {
+            s_type: vk::StructureType::ACCELERATION_STRUCTURE_BUILD_SIZES_INFO_KHR,
             ..Default::default()
         };
-
         unsafe {
-            self.handle
-                .begin_command_buffer(command_buffer, &begin_command_buffer_info)
-                .expect("I pray that I never run out of memory")
+            self.acceleration_structure()
+                .get_acceleration_structure_build_sizes(
+                    vk::AccelerationStructureBuildTypeKHR::DEVICE,
+                    build_info,
+                    max_primitive_counts,
+                    &mut size_info,
+                );
         }
+        size_info
     }
 
-    pub fn reset_command_buffer(&self, command_buffer: vk::CommandBuffer) {
+    pub fn get_acceleration_structure_device_address(
+        &self,
+        acceleration_structure: vk::AccelerationStructureKHR,
+    ) -> vk::DeviceAddress {
+        let address_info = vk::AccelerationStructureDeviceAddressInfoKHR {
+            s_type: vk::StructureType::ACCELERATION_STRUCTURE_DEVICE_ADDRESS_INFO_KHR,
+            acceleration_structure,
+            ..Default::default()
+        };
+        unsafe {
+            self.acceleration_structure()
+                .get_acceleration_structure_device_address(&address_info)
+        }
+    }
+
+    pub fn cmd_build_acceleration_structures(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        infos: &[vk::AccelerationStructureBuildGeometryInfoKHR],
+        build_range_infos: &[&[vk::AccelerationStructureBuildRangeInfoKHR]],
+    ) {
+        unsafe {
+            self.acceleration_structure()
+                .cmd_build_acceleration_structures(command_buffer, infos, build_range_infos);
+        }
+    }
+
+    pub fn create_ray_tracing_pipelines(
+        &self,
+        create_infos: &[vk::RayTracingPipelineCreateInfoKHR],
+    ) -> Result<Vec<vk::Pipeline>, VulkanError> {
+        unsafe {
+            self.ray_tracing()
+                .pipeline
+                .create_ray_tracing_pipelines(
+                    vk::DeferredOperationKHR::null(),
+                    vk::PipelineCache::null(),
+                    create_infos,
+                    None,
+                )
+                .map_err(|(_, e)| e.into())
+        }
+    }
+
+    // shader_group_handle_size bytes per group
+    pub fn get_ray_tracing_shader_group_handles(
+        &self,
+        pipeline: vk::Pipeline,
+        group_count: u32,
+    ) -> Result<Vec<u8>, VulkanError> {
+        let data_size =
+            (group_count * self.ray_tracing_properties().shader_group_handle_size) as usize;
+        Ok(unsafe {
+            self.ray_tracing()
+                .pipeline
+                .get_ray_tracing_shader_group_handles(pipeline, 0, group_count, data_size)?
+        })
+    }
+
+    // regions = raygen, miss, hit, callable of the shader binding table
+    #[allow(clippy::too_many_arguments)]
+    pub fn trace_rays(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        pipeline: vk::Pipeline,
+        layout: vk::PipelineLayout,
+        descriptor_sets: &[vk::DescriptorSet],
+        regions: &[vk::StridedDeviceAddressRegionKHR; 4],
+        extent: vk::Extent2D,
+        push_constants: &[u8],
+    ) {
+        unsafe {
+            self.handle.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::RAY_TRACING_KHR,
+                pipeline,
+            );
+            self.handle.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::RAY_TRACING_KHR,
+                layout,
+                0,
+                descriptor_sets,
+                &[],
+            );
+            if !push_constants.is_empty() {
+                self.handle.cmd_push_constants(
+                    command_buffer,
+                    layout,
+                    vk::ShaderStageFlags::RAYGEN_KHR,
+                    0,
+                    push_constants,
+                );
+            }
+            self.ray_tracing().pipeline.cmd_trace_rays(
+                command_buffer,
+                &regions[0],
+                &regions[1],
+                &regions[2],
+                &regions[3],
+                extent.width,
+                extent.height,
+                1,
+            );
+        }
+    }
+
+    // the debug helpers below do nothing without debug utils
+    pub fn set_object_name<T: vk::Handle>(&self, handle: T, name: &str) {
+        if let Some(debug_labels) = &self.debug_labels {
+            debug_labels.set_object_name(handle, name);
+        }
+    }
+
+    pub fn cmd_begin_label(&self, command_buffer: vk::CommandBuffer, name: &str, color: [f32; 4]) {
+        if let Some(debug_labels) = &self.debug_labels {
+            debug_labels.cmd_begin_label(command_buffer, name, color);
+        }
+    }
+
+    pub fn cmd_end_label(&self, command_buffer: vk::CommandBuffer) {
+        if let Some(debug_labels) = &self.debug_labels {
+            debug_labels.cmd_end_label(command_buffer);
+        }
+    }
+
+    pub fn cmd_insert_label(&self, command_buffer: vk::CommandBuffer, name: &str) {
+        if let Some(debug_labels) = &self.debug_labels {
+            debug_labels.cmd_insert_label(command_buffer, name);
+        }
+    }
+
+    pub fn graphics_queue_insert_label(&self, name: &str) {
+        if let Some(debug_labels) = &self.debug_labels {
+            debug_labels.queue_insert_label(*self.graphics_queue.lock(), name);
+        }
+    }
+
+    pub fn create_semaphore(&self) -> Result<vk::Semaphore, VulkanError> {
+        let semaphore_create_info = vk::SemaphoreCreateInfo {
+            s_type: vk::StructureType::SEMAPHORE_CREATE_INFO,
+            p_next: std::ptr::null(),
+            flags: vk::SemaphoreCreateFlags::empty(),
+            ..Default::default()
+        };
+        Ok(unsafe { self.handle.create_semaphore(&semaphore_create_info, None)? })
+    }
+
+    // counts up instead of being signaled/unsignaled => one semaphore can track many submissions
+    pub fn create_timeline_semaphore(
+        &self,
+        initial_value: u64,
+    ) -> Result<vk::Semaphore, VulkanError> {
+        let type_create_info = vk::SemaphoreTypeCreateInfo {
+            s_type: vk::StructureType::SEMAPHORE_TYPE_CREATE_INFO,
+            p_next: std::ptr::null(),
+            semaphore_type: vk::SemaphoreType::TIMELINE,
+            initial_value,
+            ..Default::default()
+        };
+        let semaphore_create_info = vk::SemaphoreCreateInfo {
+            s_type: vk::StructureType::SEMAPHORE_CREATE_INFO,
+            p_next: &type_create_info as *const vk::SemaphoreTypeCreateInfo
+                as *const std::ffi::c_void,
+            flags: vk::SemaphoreCreateFlags::empty(),
+            ..Default::default()
+        };
+        Ok(unsafe { self.handle.create_semaphore(&semaphore_create_info, None)? })
+    }
+
+    pub fn get_semaphore_counter_value(
+        &self,
+        semaphore: vk::Semaphore,
+    ) -> Result<u64, VulkanError> {
+        Ok(unsafe { self.handle.get_semaphore_counter_value(semaphore)? })
+    }
+
+    // blocks until the timeline semaphore reached value
+    pub fn wait_semaphore(
+        &self,
+        semaphore: vk::Semaphore,
+        value: u64,
+        timeout: u64,
+    ) -> Result<(), VulkanError> {
+        let wait_info = vk::SemaphoreWaitInfo {
+            s_type: vk::StructureType::SEMAPHORE_WAIT_INFO,
+            p_next: std::ptr::null(),
+            semaphore_count: 1,
+            p_semaphores: &semaphore,
+            p_values: &value,
+            ..Default::default()
+        };
+        unsafe {
+            self.handle.wait_semaphores(&wait_info, timeout)?;
+        }
+        Ok(())
+    }
+
+    pub fn destroy_semaphore(&self, semaphore: vk::Semaphore) {
+        unsafe {
+            self.handle.destroy_semaphore(semaphore, None);
+        }
+    }
+
+    // 0 => the graphics queue can not write timestamps
+    pub fn graphics_timestamp_valid_bits(&self) -> u32 {
+        self.instance
+            .get_physical_device_queue_family_properties(&self.physical_device)
+            [self.get_graphics_queue_idx() as usize]
+            .timestamp_valid_bits
+    }
+
+    pub fn create_query_pool(
+        &self,
+        query_type: vk::QueryType,
+        query_count: u32,
+    ) -> Result<vk::QueryPool, VulkanError> {
+        let query_pool_create_info = vk::QueryPoolCreateInfo {
+            s_type: vk::StructureType::QUERY_POOL_CREATE_INFO,
+            p_next: std::ptr::null(),
+            query_type,
+            query_count,
+            ..Default::default()
+        };
+        Ok(unsafe {
+            self.handle
+                .create_query_pool(&query_pool_create_info, None)?
+        })
+    }
+
+    pub fn destroy_query_pool(&self, query_pool: vk::QueryPool) {
+        unsafe {
+            self.handle.destroy_query_pool(query_pool, None);
+        }
+    }
+
+    pub fn cmd_reset_query_pool(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        query_pool: vk::QueryPool,
+        first_query: u32,
+        query_count: u32,
+    ) {
+        unsafe {
+            self.handle
+                .cmd_reset_query_pool(command_buffer, query_pool, first_query, query_count);
+        }
+    }
+
+    pub fn cmd_write_timestamp(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        stage: vk::PipelineStageFlags2,
+        query_pool: vk::QueryPool,
+        query: u32,
+    ) {
+        unsafe {
+            self.handle
+                .cmd_write_timestamp2(command_buffer, stage, query_pool, query);
+        }
+    }
+
+    // each result is [value, availability], queries that were not written yet stay at 0
+    // => does not wait for them
+    pub fn get_query_results_with_availability(
+        &self,
+        query_pool: vk::QueryPool,
+        first_query: u32,
+        results: &mut [[u64; 2]],
+    ) -> Result<(), VulkanError> {
+        let result = unsafe {
+            self.handle.get_query_pool_results(
+                query_pool,
+                first_query,
+                results,
+                vk::QueryResultFlags::TYPE_64 | vk::QueryResultFlags::WITH_AVAILABILITY,
+            )
+        };
+        match result {
+            // not ready => some queries are unavailable, the availability says which
+            Ok(()) | Err(vk::Result::NOT_READY) => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn create_fence(&self, flags: vk::FenceCreateFlags) -> Result<vk::Fence, VulkanError> {
+        let fence_create_info = vk::FenceCreateInfo {
+            s_type: vk::StructureType::FENCE_CREATE_INFO,
+            p_next: std::ptr::null(),
+            flags,
+            ..Default::default()
+        };
+        Ok(unsafe { self.handle.create_fence(&fence_create_info, None)? })
+    }
+
+    pub fn destroy_fence(&self, fence: vk::Fence) {
+        unsafe {
+            self.handle.destroy_fence(fence, None);
+        }
+    }
+
+    pub fn wait_for_fence(&self, fence: &vk::Fence, timeout: u64) -> Result<(), VulkanError> {
+        self.wait_for_fences(&[*fence], true, timeout)
+    }
+
+    pub fn wait_for_fences(
+        &self,
+        fences: &[vk::Fence],
+        wait_all: bool,
+        timeout: u64,
+    ) -> Result<(), VulkanError> {
+        unsafe {
+            self.handle.wait_for_fences(fences, wait_all, timeout)?;
+        }
+        Ok(())
+    }
+
+    pub fn reset_fence(&self, fence: &vk::Fence) -> Result<(), VulkanError> {
+        self.reset_fences(&[*fence])
+    }
+
+    pub fn reset_fences(&self, fences: &[vk::Fence]) -> Result<(), VulkanError> {
+        unsafe {
+            self.handle.reset_fences(fences)?;
+        }
+        Ok(())
+    }
+
+    pub fn begin_command_buffer(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        flags: vk::CommandBufferUsageFlags,
+    ) -> Result<(), VulkanError> {
+        let begin_command_buffer_info = vk::CommandBufferBeginInfo {
+            s_type: vk::StructureType::COMMAND_BUFFER_BEGIN_INFO,
+            flags,
+            p_inheritance_info: std::ptr::null(),
+            ..Default::default()
+        };
+
         unsafe {
             self.handle
-                .reset_command_buffer(command_buffer, vk::CommandBufferResetFlags::empty())
-                .expect("I pray that I never run out of memory");
+                .begin_command_buffer(command_buffer, &begin_command_buffer_info)?;
         }
+        Ok(())
     }
 
-    pub fn end_command_buffer(&self, command_buffer: vk::CommandBuffer) {
+    pub fn reset_command_buffer(
+        &self,
+        command_buffer: vk::CommandBuffer,
+    ) -> Result<(), VulkanError> {
         unsafe {
             self.handle
-                .end_command_buffer(command_buffer)
-                .expect("I pray that I never run out of memory");
+                .reset_command_buffer(command_buffer, vk::CommandBufferResetFlags::empty())?;
         }
+        Ok(())
+    }
+
+    pub fn end_command_buffer(&self, command_buffer: vk::CommandBuffer) -> Result<(), VulkanError> {
+        unsafe {
+            self.handle.end_command_buffer(command_buffer)?;
+        }
+        Ok(())
     }
 
     pub fn transition_image_layout(
@@ -631,6 +1641,27 @@ impl Device {
         }
     }
 
+    pub fn cmd_pipeline_barrier(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        image_barriers: &[vk::ImageMemoryBarrier2],
+        buffer_barriers: &[vk::BufferMemoryBarrier2],
+    ) {
+        let dependency_info = vk::DependencyInfo {
+            s_type: vk::StructureType::DEPENDENCY_INFO,
+            p_next: std::ptr::null(),
+            image_memory_barrier_count: image_barriers.len() as u32,
+            p_image_memory_barriers: image_barriers.as_ptr(),
+            buffer_memory_barrier_count: buffer_barriers.len() as u32,
+            p_buffer_memory_barriers: buffer_barriers.as_ptr(),
+            ..Default::default()
+        };
+        unsafe {
+            self.handle
+                .cmd_pipeline_barrier2(command_buffer, &dependency_info);
+        }
+    }
+
     pub fn cmd_clear_color_image(
         &self,
         command_buffer: vk::CommandBuffer,
@@ -656,6 +1687,35 @@ impl Device {
         }
     }
 
+    // only valid inside of a rendering scope, clears rects of the color attachment 0
+    pub fn cmd_clear_color_rects(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        clear_color: vk::ClearColorValue,
+        rects: &[vk::Rect2D],
+    ) {
+        if rects.is_empty() {
+            return;
+        }
+        let attachment = vk::ClearAttachment {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            color_attachment: 0,
+            clear_value: vk::ClearValue { color: clear_color },
+        };
+        let clear_rects: Vec<vk::ClearRect> = rects
+            .iter()
+            .map(|rect| vk::ClearRect {
+                rect: *rect,
+                base_array_layer: 0,
+                layer_count: 1,
+            })
+            .collect();
+        unsafe {
+            self.handle
+                .cmd_clear_attachments(command_buffer, &[attachment], &clear_rects);
+        }
+    }
+
     pub fn copy_image_to_image(
         &self,
         command_buffer: vk::CommandBuffer,
@@ -663,6 +1723,26 @@ impl Device {
         dst_image: vk::Image,
         src_size: vk::Extent2D,
         dst_size: vk::Extent2D,
+    ) {
+        self.blit_image_region(
+            command_buffer,
+            src_image,
+            dst_image,
+            src_size,
+            vk::Offset2D { x: 0, y: 0 },
+            dst_size,
+        );
+    }
+
+    // scales the top left src_size of src into the dst_size rect at dst_offset
+    pub fn blit_image_region(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        src_image: vk::Image,
+        dst_image: vk::Image,
+        src_size: vk::Extent2D,
+        dst_offset: vk::Offset2D,
+        dst_size: vk::Extent2D,
     ) {
         let blit_region = vk::ImageBlit2 {
             s_type: vk::StructureType::IMAGE_BLIT_2,
@@ -676,10 +1756,14 @@ impl Device {
                 },
             ],
             dst_offsets: [
-                vk::Offset3D { x: 0, y: 0, z: 0 },
                 vk::Offset3D {
-                    x: dst_size.width as i32,
-                    y: dst_size.height as i32,
+                    x: dst_offset.x,
+                    y: dst_offset.y,
+                    z: 0,
+                },
+                vk::Offset3D {
+                    x: dst_offset.x + dst_size.width as i32,
+                    y: dst_offset.y + dst_size.height as i32,
                     z: 1,
                 },
             ],
@@ -715,23 +1799,171 @@ impl Device {
         }
     }
 
-    pub fn submit_to_graphics_queue(&self, submit_info: vk::SubmitInfo2, fence: vk::Fence) {
+    // expects all levels in TRANSFER_DST_OPTIMAL with level 0 filled
+    // => every level is downsampled from the previous one, afterwards all are SHADER_READ_ONLY_OPTIMAL
+    pub fn generate_mipmaps(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        image: vk::Image,
+        extent: vk::Extent2D,
+        mip_levels: u32,
+        layer_count: u32,
+    ) {
+        let level_barrier =
+            |level, old_layout, new_layout, src_access, dst_access| vk::ImageMemoryBarrier2 {
+                s_type: vk::StructureType::IMAGE_MEMORY_BARRIER_2,
+                p_next: std::ptr::null(),
+                src_stage_mask: vk::PipelineStageFlags2::TRANSFER,
+                src_access_mask: src_access,
+                dst_stage_mask: vk::PipelineStageFlags2::TRANSFER
+                    | vk::PipelineStageFlags2::FRAGMENT_SHADER
+                    | vk::PipelineStageFlags2::COMPUTE_SHADER,
+                dst_access_mask: dst_access,
+                old_layout,
+                new_layout,
+                image,
+                subresource_range: vk::ImageSubresourceRange {
+                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    base_mip_level: level,
+                    level_count: 1,
+                    base_array_layer: 0,
+                    layer_count,
+                },
+                ..Default::default()
+            };
+        let mut level_size = extent;
+        for level in 0..mip_levels {
+            // previous level is complete => use it as the source of the blit
+            self.cmd_pipeline_barrier(
+                command_buffer,
+                &[level_barrier(
+                    level,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                    vk::AccessFlags2::TRANSFER_WRITE,
+                    vk::AccessFlags2::TRANSFER_READ,
+                )],
+                &[],
+            );
+            if level + 1 < mip_levels {
+                let half_size = vk::Extent2D {
+                    width: (level_size.width / 2).max(1),
+                    height: (level_size.height / 2).max(1),
+                };
+                let blit_region = vk::ImageBlit2 {
+                    s_type: vk::StructureType::IMAGE_BLIT_2,
+                    p_next: std::ptr::null(),
+                    src_offsets: [
+                        vk::Offset3D { x: 0, y: 0, z: 0 },
+                        vk::Offset3D {
+                            x: level_size.width as i32,
+                            y: level_size.height as i32,
+                            z: 1,
+                        },
+                    ],
+                    dst_offsets: [
+                        vk::Offset3D { x: 0, y: 0, z: 0 },
+                        vk::Offset3D {
+                            x: half_size.width as i32,
+                            y: half_size.height as i32,
+                            z: 1,
+                        },
+                    ],
+                    src_subresource: vk::ImageSubresourceLayers {
+                        aspect_mask: vk::ImageAspectFlags::COLOR,
+                        base_array_layer: 0,
+                        layer_count,
+                        mip_level: level,
+                    },
+                    dst_subresource: vk::ImageSubresourceLayers {
+                        aspect_mask: vk::ImageAspectFlags::COLOR,
+                        base_array_layer: 0,
+                        layer_count,
+                        mip_level: level + 1,
+                    },
+                    ..Default::default()
+                };
+                // linear blits of the 8 bit rgba formats we use are supported everywhere
+                let blit_info = vk::BlitImageInfo2 {
+                    s_type: vk::StructureType::BLIT_IMAGE_INFO_2,
+                    p_next: std::ptr::null(),
+                    src_image: image,
+                    src_image_layout: vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                    dst_image: image,
+                    dst_image_layout: vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    filter: vk::Filter::LINEAR,
+                    region_count: 1,
+                    p_regions: &blit_region,
+                    ..Default::default()
+                };
+                unsafe {
+                    self.handle.cmd_blit_image2(command_buffer, &blit_info);
+                }
+                level_size = half_size;
+            }
+            self.cmd_pipeline_barrier(
+                command_buffer,
+                &[level_barrier(
+                    level,
+                    vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                    vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                    vk::AccessFlags2::TRANSFER_READ,
+                    vk::AccessFlags2::SHADER_SAMPLED_READ,
+                )],
+                &[],
+            );
+        }
+    }
+
+    pub fn submit_to_graphics_queue(
+        &self,
+        submit_info: vk::SubmitInfo2,
+        fence: vk::Fence,
+    ) -> Result<(), VulkanError> {
+        self.submit(&self.graphics_queue, submit_info, fence)
+    }
+
+    pub fn submit_to_transfer_queue(
+        &self,
+        submit_info: vk::SubmitInfo2,
+        fence: vk::Fence,
+    ) -> Result<(), VulkanError> {
+        self.submit(&self.transfer_queue, submit_info, fence)
+    }
+
+    // the graphics queue if there is no async compute queue
+    pub fn submit_to_compute_queue(
+        &self,
+        submit_info: vk::SubmitInfo2,
+        fence: vk::Fence,
+    ) -> Result<(), VulkanError> {
+        self.submit(&self.compute_queue, submit_info, fence)
+    }
+
+    fn submit(
+        &self,
+        queue: &Queue,
+        submit_info: vk::SubmitInfo2,
+        fence: vk::Fence,
+    ) -> Result<(), VulkanError> {
+        let queue = queue.lock();
         unsafe {
-            self.handle
-                .queue_submit2(self.graphics_queue, &[submit_info], fence)
-                .expect("I pray that I never run out of memory");
+            self.handle.queue_submit2(*queue, &[submit_info], fence)?;
         }
+        Ok(())
     }
 
-    pub fn wait_idle(&self) {
+    // vkDeviceWaitIdle uses every queue => other threads (asset loaders) must not submit meanwhile
+    pub fn wait_idle(&self) -> Result<(), VulkanError> {
+        let _queues: Vec<MutexGuard<vk::Queue>> =
+            self.queues.iter().map(|queue| queue.lock()).collect();
         unsafe {
-            self.handle
-                .device_wait_idle()
-                .expect("I pray that I never run out of memory");
+            self.handle.device_wait_idle()?;
         }
+        Ok(())
     }
 
-    pub fn create_allocator(&self) -> Allocator {
+    pub fn create_allocator(&self) -> Result<Allocator, VulkanError> {
         self.instance
             .create_allocator(self.physical_device, self.handle.clone())
     }
@@ -739,12 +1971,11 @@ impl Device {
     pub fn create_descriptor_set_layout(
         &self,
         layout_info: &vk::DescriptorSetLayoutCreateInfo,
-    ) -> vk::DescriptorSetLayout {
-        unsafe {
+    ) -> Result<vk::DescriptorSetLayout, VulkanError> {
+        Ok(unsafe {
             self.handle
-                .create_descriptor_set_layout(layout_info, None)
-                .expect("I pray that I never run out of memory")
-        }
+                .create_descriptor_set_layout(layout_info, None)?
+        })
     }
 
     pub fn destroy_descriptor_set_layout(&self, layout: vk::DescriptorSetLayout) {
@@ -756,12 +1987,8 @@ impl Device {
     pub fn create_descriptor_pool(
         &self,
         pool_info: &vk::DescriptorPoolCreateInfo,
-    ) -> vk::DescriptorPool {
-        unsafe {
-            self.handle
-                .create_descriptor_pool(pool_info, None)
-                .expect("I pray that I never run out of memory")
-        }
+    ) -> Result<vk::DescriptorPool, VulkanError> {
+        Ok(unsafe { self.handle.create_descriptor_pool(pool_info, None)? })
     }
 
     #[allow(dead_code)]
@@ -795,12 +2022,8 @@ impl Device {
     pub fn create_shader_module(
         &self,
         create_info: &vk::ShaderModuleCreateInfo,
-    ) -> vk::ShaderModule {
-        unsafe {
-            self.handle
-                .create_shader_module(create_info, None)
-                .expect("I pray that I never run out of memory and that the  shader code is valid")
-        }
+    ) -> Result<vk::ShaderModule, VulkanError> {
+        Ok(unsafe { self.handle.create_shader_module(create_info, None)? })
     }
 
     pub fn destroy_shader_module(&self, module: vk::ShaderModule) {
@@ -812,12 +2035,8 @@ impl Device {
     pub fn create_pipeline_layout(
         &self,
         create_info: &vk::PipelineLayoutCreateInfo,
-    ) -> vk::PipelineLayout {
-        unsafe {
-            self.handle
-                .create_pipeline_layout(create_info, None)
-                .expect("I pray that I never run out of memory")
-        }
+    ) -> Result<vk::PipelineLayout, VulkanError> {
+        Ok(unsafe { self.handle.create_pipeline_layout(create_info, None)? })
     }
 
     pub fn destroy_pipeline_layout(&self, layout: vk::PipelineLayout) {
@@ -829,22 +2048,22 @@ impl Device {
     pub fn create_compute_pipelines(
         &self,
         create_infos: &[vk::ComputePipelineCreateInfo],
-    ) -> Vec<vk::Pipeline> {
+    ) -> Result<Vec<vk::Pipeline>, VulkanError> {
         unsafe {
             self.handle
                 .create_compute_pipelines(vk::PipelineCache::null(), create_infos, None)
-                .expect("I pray that I never run out of memory")
+                .map_err(|(_, e)| e.into())
         }
     }
 
     pub fn create_graphics_pipeline(
         &self,
         create_infos: &[vk::GraphicsPipelineCreateInfo],
-    ) -> Vec<vk::Pipeline> {
+    ) -> Result<Vec<vk::Pipeline>, VulkanError> {
         unsafe {
             self.handle
                 .create_graphics_pipelines(vk::PipelineCache::null(), create_infos, None)
-                .expect("I pray that I never run out of memory")
+                .map_err(|(_, e)| e.into())
         }
     }
 
@@ -861,25 +2080,28 @@ impl Device {
         layout: vk::PipelineLayout,
         descriptor_sets: &[vk::DescriptorSet],
         group_counts: [u32; 3],
-        push_constants: &PushConstants,
+        push_constants: &[u8],
     ) {
         unsafe {
             self.handle
                 .cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::COMPUTE, pipeline);
-            self.handle.cmd_bind_descriptor_sets(
-                command_buffer,
-                vk::PipelineBindPoint::COMPUTE,
-                layout,
-                0,
-                descriptor_sets,
-                &[],
-            );
+            // shaders that only use device addresses dont have sets
+            if !descriptor_sets.is_empty() {
+                self.handle.cmd_bind_descriptor_sets(
+                    command_buffer,
+                    vk::PipelineBindPoint::COMPUTE,
+                    layout,
+                    0,
+                    descriptor_sets,
+                    &[],
+                );
+            }
             self.handle.cmd_push_constants(
                 command_buffer,
                 layout,
                 vk::ShaderStageFlags::COMPUTE,
                 0,
-                push_constants.as_bytes(),
+                push_constants,
             );
             self.handle.cmd_dispatch(
                 command_buffer,
@@ -909,6 +2131,27 @@ impl Device {
         }
     }
 
+    // binds one set of a pipeline that was built with SetLayouts
+    pub fn cmd_bind_descriptor_set(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        layout: vk::PipelineLayout,
+        pipeline_bind_point: vk::PipelineBindPoint,
+        slot: DescriptorSetSlot,
+        descriptor_set: vk::DescriptorSet,
+    ) {
+        unsafe {
+            self.handle.cmd_bind_descriptor_sets(
+                command_buffer,
+                pipeline_bind_point,
+                layout,
+                slot.index(),
+                &[descriptor_set],
+                &[],
+            );
+        }
+    }
+
     pub fn begin_rendering(
         &self,
         command_buffer: vk::CommandBuffer,
@@ -931,6 +2174,20 @@ impl Device {
         }
     }
 
+    pub fn cmd_bind_graphics_pipeline(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        pipeline: vk::Pipeline,
+    ) {
+        unsafe {
+            self.handle.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                pipeline,
+            );
+        }
+    }
+
     pub fn end_rendering(&self, command_buffer: vk::CommandBuffer) {
         unsafe {
             self.handle.cmd_end_rendering(command_buffer);
@@ -941,32 +2198,49 @@ impl Device {
         &self,
         command_buffer: vk::CommandBuffer,
         layout: vk::PipelineLayout,
-        draw_extent: vk::Extent2D,
         asset: &MeshAsset,
+        surface: &GeometricSurface,
+        material: &Material,
+        world_matrix: &glm::Mat4,
+    ) {
+        // view and projection come from the scene data => only the model matrix is pushed
+        let push_constants = GPUDrawPushConstants {
+            draw: GPUDrawData {
+                world_matrix: *world_matrix,
+                device_address: asset.buffers().vertex_buffer_address(),
+                material_address: material.constants_address(),
+                texture_indices: material.texture_indices(),
+                index_address: 0,
+                _padding: 0,
+            },
+            draw_buffer: 0,
+        };
+        self.draw_surface(
+            command_buffer,
+            layout,
+            asset,
+            surface,
+            push_constants.as_bytes(),
+        );
+    }
+
+    // push constants are pushed to vertex + fragment stage starting at offset 0
+    pub fn draw_surface(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        layout: vk::PipelineLayout,
+        asset: &MeshAsset,
+        surface: &GeometricSurface,
+        push_constants: &[u8],
     ) {
         unsafe {
             let buffer = asset.buffers();
-            let surface = asset.surfaces()[0];
-            let view_mtx = glm::translate(&glm::Mat4::identity(), &glm::vec3(0., 0., -5.));
-            let mut projection_mtx = glm::reversed_perspective_rh_zo(
-                draw_extent.width as f32 / draw_extent.height as f32,
-                70.0 * std::f32::consts::PI / 180.0,
-                0.1,
-                100.0,
-            );
-            projection_mtx[(1, 1)] *= -1.0;
-            let world_matrix = projection_mtx * view_mtx;
-
-            let push_constants = GPUDrawPushConstants {
-                world_matrix,
-                device_address: buffer.vertex_buffer_address(),
-            };
             self.handle.cmd_push_constants(
                 command_buffer,
                 layout,
-                vk::ShaderStageFlags::VERTEX,
+                vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
                 0,
-                push_constants.as_bytes(),
+                push_constants,
             );
             self.handle.cmd_bind_index_buffer(
                 command_buffer,
@@ -983,6 +2257,205 @@ impl Device {
                 0,
             );
         }
+        self.draw_calls.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // vertices are generated in the vertex shader, e.g. fullscreen triangles
+    pub fn draw_generated(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        layout: vk::PipelineLayout,
+        vertex_count: u32,
+        push_constants: &[u8],
+    ) {
+        unsafe {
+            // pipelines without push constants pass an empty slice
+            if !push_constants.is_empty() {
+                self.handle.cmd_push_constants(
+                    command_buffer,
+                    layout,
+                    vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+                    0,
+                    push_constants,
+                );
+            }
+            self.handle.cmd_draw(command_buffer, vertex_count, 1, 0, 0);
+        }
+        self.draw_calls.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // draw_count draws from an indirect buffer with one index buffer, the draw data of the draws
+    // is at draw_buffer => only the draw buffer address is pushed
+    // count buffer + offset => the draw count is read from there, draw_count is the maximum
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw_indexed_indirect(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        layout: vk::PipelineLayout,
+        index_buffer: vk::Buffer,
+        indirect_buffer: vk::Buffer,
+        offset: vk::DeviceSize,
+        draw_count: u32,
+        count_buffer: Option<(vk::Buffer, vk::DeviceSize)>,
+        draw_buffer: vk::DeviceAddress,
+    ) {
+        unsafe {
+            self.handle.cmd_push_constants(
+                command_buffer,
+                layout,
+                vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+                std::mem::offset_of!(GPUDrawPushConstants, draw_buffer) as u32,
+                bytemuck::bytes_of(&draw_buffer),
+            );
+            self.handle.cmd_bind_index_buffer(
+                command_buffer,
+                index_buffer,
+                0,
+                vk::IndexType::UINT32,
+            );
+        }
+        let stride = std::mem::size_of::<vk::DrawIndexedIndirectCommand>() as u32;
+        match count_buffer {
+            Some((count_buffer, count_offset)) => self.cmd_draw_indexed_indirect_count(
+                command_buffer,
+                indirect_buffer,
+                offset,
+                count_buffer,
+                count_offset,
+                draw_count,
+                stride,
+            ),
+            None => self.cmd_draw_indexed_indirect(
+                command_buffer,
+                indirect_buffer,
+                offset,
+                draw_count,
+                stride,
+            ),
+        }
+    }
+
+    // like draw_indexed_indirect, but the draws have no index buffer
+    pub fn draw_indirect(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        layout: vk::PipelineLayout,
+        indirect_buffer: vk::Buffer,
+        offset: vk::DeviceSize,
+        draw_count: u32,
+        draw_buffer: vk::DeviceAddress,
+    ) {
+        unsafe {
+            self.handle.cmd_push_constants(
+                command_buffer,
+                layout,
+                vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+                std::mem::offset_of!(GPUDrawPushConstants, draw_buffer) as u32,
+                bytemuck::bytes_of(&draw_buffer),
+            );
+            self.handle.cmd_draw_indirect(
+                command_buffer,
+                indirect_buffer,
+                offset,
+                draw_count,
+                std::mem::size_of::<vk::DrawIndirectCommand>() as u32,
+            );
+        }
+        self.draw_calls.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn cmd_draw_indexed_indirect(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        buffer: vk::Buffer,
+        offset: vk::DeviceSize,
+        draw_count: u32,
+        stride: u32,
+    ) {
+        unsafe {
+            self.handle.cmd_draw_indexed_indirect(
+                command_buffer,
+                buffer,
+                offset,
+                draw_count,
+                stride,
+            );
+        }
+        self.draw_calls.fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn cmd_draw_indexed_indirect_count(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        buffer: vk::Buffer,
+        offset: vk::DeviceSize,
+        count_buffer: vk::Buffer,
+        count_buffer_offset: vk::DeviceSize,
+        max_draw_count: u32,
+        stride: u32,
+    ) {
+        unsafe {
+            self.handle.cmd_draw_indexed_indirect_count(
+                command_buffer,
+                buffer,
+                offset,
+                count_buffer,
+                count_buffer_offset,
+                max_draw_count,
+                stride,
+            );
+        }
+        self.draw_calls.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // index buffer without a mesh, e.g. geometry that is written every frame (ui)
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw_indexed(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        layout: vk::PipelineLayout,
+        index_buffer: vk::Buffer,
+        first_index: u32,
+        index_count: u32,
+        vertex_offset: i32,
+        push_constants: &[u8],
+    ) {
+        unsafe {
+            self.handle.cmd_push_constants(
+                command_buffer,
+                layout,
+                vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+                0,
+                push_constants,
+            );
+            self.handle.cmd_bind_index_buffer(
+                command_buffer,
+                index_buffer,
+                0,
+                vk::IndexType::UINT32,
+            );
+            self.handle.cmd_draw_indexed(
+                command_buffer,
+                index_count,
+                1,
+                first_index,
+                vertex_offset,
+                0,
+            );
+        }
+        self.draw_calls.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // resets the counter => call once per frame after recording
+    pub fn take_draw_call_count(&self) -> u32 {
+        self.draw_calls.swap(0, Ordering::Relaxed)
+    }
+
+    pub fn cmd_set_scissor(&self, command_buffer: vk::CommandBuffer, scissor: vk::Rect2D) {
+        unsafe {
+            self.handle.cmd_set_scissor(command_buffer, 0, &[scissor]);
+        }
     }
 
     pub fn cmd_copy_buffer(
@@ -998,6 +2471,20 @@ impl Device {
         }
     }
 
+    pub fn cmd_fill_buffer(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        buffer: vk::Buffer,
+        offset: vk::DeviceSize,
+        size: vk::DeviceSize,
+        data: u32,
+    ) {
+        unsafe {
+            self.handle
+                .cmd_fill_buffer(command_buffer, buffer, offset, size, data)
+        }
+    }
+
     pub fn cmd_copy_buffer_to_image(
         &self,
         command_buffer: vk::CommandBuffer,
@@ -1017,14 +2504,32 @@ impl Device {
         }
     }
 
-    pub fn create_sampler(&self, create_info: &vk::SamplerCreateInfo) -> vk::Sampler {
+    pub fn cmd_copy_image_to_buffer(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        src_image: vk::Image,
+        src_image_layout: vk::ImageLayout,
+        dst_buffer: vk::Buffer,
+        copy_regions: &[vk::BufferImageCopy],
+    ) {
         unsafe {
-            self.handle
-                .create_sampler(create_info, None)
-                .expect("I pray that I never run out of memory")
+            self.handle.cmd_copy_image_to_buffer(
+                command_buffer,
+                src_image,
+                src_image_layout,
+                dst_buffer,
+                copy_regions,
+            );
         }
     }
 
+    pub fn create_sampler(
+        &self,
+        create_info: &vk::SamplerCreateInfo,
+    ) -> Result<vk::Sampler, VulkanError> {
+        Ok(unsafe { self.handle.create_sampler(create_info, None)? })
+    }
+
     pub fn destroy_sampler(&self, sampler: vk::Sampler) {
         unsafe {
             self.handle.destroy_sampler(sampler, None);
@@ -0,0 +1,307 @@
+use super::allocation::AllocatedImage;
+use super::allocation::Allocator;
+use super::descriptor::DescriptorAllocator;
+use super::descriptor::DescriptorLayoutBuilder;
+use super::descriptor::DescriptorSetLayout;
+use super::descriptor::DescriptorSetSlot;
+use super::descriptor::DescriptorWriter;
+use super::descriptor::SetLayouts;
+use super::device::Device;
+use super::error::VulkanError;
+use super::mesh::MeshAsset;
+use super::pipelines::ComputePipeline;
+use super::pipelines::GraphicsPipeline;
+use super::pipelines::GraphicsPipelineBuilder;
+use super::pipelines::PushConstants;
+use super::shader::ShaderModule;
+use ash::vk;
+use nalgebra_glm as glm;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Instant;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DistortionMode {
+    // animated noise, e.g. above fires or hot asphalt
+    HeatHaze,
+    // offset along the view space normal, e.g. glass
+    Refraction,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DistortionSettings {
+    pub mode: DistortionMode,
+    // maximum offset as fraction of the screen
+    pub strength: f32,
+    // size of the noise pattern in world units, unused for refraction
+    pub scale: f32,
+    // how fast the noise scrolls upwards, unused for refraction
+    pub speed: f32,
+}
+
+impl DistortionSettings {
+    pub fn heat_haze() -> Self {
+        DistortionSettings {
+            mode: DistortionMode::HeatHaze,
+            strength: 0.005,
+            scale: 0.5,
+            speed: 1.5,
+        }
+    }
+
+    pub fn refraction() -> Self {
+        DistortionSettings {
+            mode: DistortionMode::Refraction,
+            strength: 0.03,
+            scale: 1.0,
+            speed: 0.0,
+        }
+    }
+}
+
+// same layout as the push constants in distortion.frag, starts like GPUDrawPushConstants
+// => mesh.vert can be reused
+#[repr(C)]
+#[derive(Debug, bytemuck::NoUninit, Copy, Clone)]
+struct GPUDistortionPushConstants {
+    world_matrix: glm::Mat4,
+    device_address: vk::DeviceAddress,
+    // vec4 is 16 byte aligned on the gpu
+    _padding: u64,
+    // x = strength, y = scale, z = time * speed, w = mode
+    params: glm::Vec4,
+    // always 0 => indexed draws
+    index_address: vk::DeviceAddress,
+    _padding_2: u64,
+    // always 0 => mesh.vert reads the push constants instead of a draw buffer
+    draw_buffer: vk::DeviceAddress,
+}
+
+// screen space distortion in two steps:
+//   marked meshes are drawn into a distortion image (uv offsets, depth tested, no depth write)
+//   a compute pass samples a copy of the draw image at the offset positions
+pub struct Distortion {
+    device: Arc<Device>,
+    distortion_image: AllocatedImage,
+    scene_copy_image: AllocatedImage,
+    // binding 0: scene copy, 1: distortion, 2: draw image
+    _descriptor_layout: DescriptorSetLayout,
+    apply_descriptor: vk::DescriptorSet,
+    apply_pipeline: ComputePipeline,
+    draw_pipeline: GraphicsPipeline,
+    // mesh name => settings
+    marked_meshes: HashMap<String, DistortionSettings>,
+    start_time: Instant,
+}
+
+impl Distortion {
+    pub fn new(
+        device: Arc<Device>,
+        allocator: Arc<Mutex<Allocator>>,
+        descriptor_allocator: &DescriptorAllocator,
+        draw_image: &AllocatedImage,
+        depth_format: vk::Format,
+        scene_data_layout: &DescriptorSetLayout,
+    ) -> Result<Self, VulkanError> {
+        // rgba16f instead of rg16f => no extended storage image formats needed
+        let distortion_image = AllocatedImage::new(
+            device.clone(),
+            allocator.clone(),
+            vk::Format::R16G16B16A16_SFLOAT,
+            vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::STORAGE,
+            draw_image.extent(),
+            vk::ImageAspectFlags::COLOR,
+            1,
+        )?;
+        let scene_copy_image =
+            AllocatedImage::new_draw_color_image(device.clone(), allocator, draw_image.extent())?;
+
+        let mut builder = DescriptorLayoutBuilder::new();
+        for binding in 0..3 {
+            builder.add_binding(
+                binding,
+                vk::DescriptorType::STORAGE_IMAGE,
+                vk::ShaderStageFlags::COMPUTE,
+            );
+        }
+        let descriptor_layout =
+            builder.build(device.clone(), vk::DescriptorSetLayoutCreateFlags::empty())?;
+        let apply_descriptor = descriptor_allocator.allocate(descriptor_layout.layout())?;
+        let mut writer = DescriptorWriter::new();
+        writer.add_storage_image(0, scene_copy_image.image_view());
+        writer.add_storage_image(1, distortion_image.image_view());
+        writer.add_storage_image(2, draw_image.image_view());
+        writer.update_descriptor_set(&device, apply_descriptor);
+
+        let apply_shader = ShaderModule::new(device.clone(), "shaders/distortion_comp.spv")?;
+        let apply_pipeline =
+            ComputePipeline::new(device.clone(), &[descriptor_layout.layout()], apply_shader)?;
+
+        let draw_pipeline = Self::build_draw_pipeline(
+            device.clone(),
+            scene_data_layout.layout(),
+            distortion_image.format(),
+            depth_format,
+        )?;
+
+        Ok(Distortion {
+            device,
+            distortion_image,
+            scene_copy_image,
+            _descriptor_layout: descriptor_layout,
+            apply_descriptor,
+            apply_pipeline,
+            draw_pipeline,
+            marked_meshes: HashMap::new(),
+            start_time: Instant::now(),
+        })
+    }
+
+    fn build_draw_pipeline(
+        device: Arc<Device>,
+        scene_data_layout: vk::DescriptorSetLayout,
+        color_format: vk::Format,
+        depth_format: vk::Format,
+    ) -> Result<GraphicsPipeline, VulkanError> {
+        let push_constants = vk::PushConstantRange {
+            stage_flags: vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+            offset: 0,
+            size: std::mem::size_of::<GPUDistortionPushConstants>() as u32,
+        };
+        // mesh.vert is shared with the mesh pipelines => same slot for the scene data
+        let set_layouts = SetLayouts::new().with(DescriptorSetSlot::Scene, scene_data_layout);
+        let layout_info = vk::PipelineLayoutCreateInfo {
+            s_type: vk::StructureType::PIPELINE_LAYOUT_CREATE_INFO,
+            p_next: std::ptr::null(),
+            flags: vk::PipelineLayoutCreateFlags::empty(),
+            set_layout_count: set_layouts.layouts().len() as u32,
+            p_set_layouts: set_layouts.layouts().as_ptr(),
+            push_constant_range_count: 1,
+            p_push_constant_ranges: &push_constants,
+            ..Default::default()
+        };
+        let pipeline_layout = device.create_pipeline_layout(&layout_info)?;
+        let frag_shader = ShaderModule::new(device.clone(), "shaders/distortion_frag.spv")?;
+        let vert_shader = ShaderModule::new(device.clone(), "shaders/mesh_vert.spv")?;
+        // overlapping volumes add up their offsets
+        GraphicsPipelineBuilder::new()
+            .set_layout(pipeline_layout)
+            .set_shaders(&frag_shader, &vert_shader)
+            .set_input_topology(vk::PrimitiveTopology::TRIANGLE_LIST)
+            .set_polygon_mode(vk::PolygonMode::FILL)
+            .set_cull_mode(vk::CullModeFlags::NONE, vk::FrontFace::CLOCKWISE)
+            .disable_multisampling()
+            .set_color_attachment_format(color_format)
+            .set_depth_format(depth_format)
+            .enable_blending_additive()
+            .enable_depth_test(vk::FALSE, vk::CompareOp::GREATER_OR_EQUAL)
+            .build_pipeline(device)
+    }
+
+    // None => the mesh is not distorting anymore
+    pub fn set(&mut self, mesh_name: &str, settings: Option<DistortionSettings>) {
+        match settings {
+            Some(settings) => {
+                self.marked_meshes.insert(mesh_name.to_string(), settings);
+            }
+            None => {
+                self.marked_meshes.remove(mesh_name);
+            }
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        !self.marked_meshes.is_empty()
+    }
+
+    pub fn distortion_image(&self) -> vk::Image {
+        self.distortion_image.image()
+    }
+
+    pub fn distortion_format(&self) -> vk::Format {
+        self.distortion_image.format()
+    }
+
+    pub fn scene_copy_image(&self) -> vk::Image {
+        self.scene_copy_image.image()
+    }
+
+    // expects the depth image of the geometry pass => distortion behind walls is hidden
+    pub fn record_draw<'a>(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        depth_image: vk::ImageView,
+        extent: vk::Extent2D,
+        scene_descriptor_set: vk::DescriptorSet,
+        mesh_instances: impl Iterator<Item = (&'a MeshAsset, &'a glm::Mat4)>,
+    ) {
+        self.draw_pipeline.begin_drawing_load_depth(
+            command_buffer,
+            self.distortion_image.image_view(),
+            depth_image,
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL,
+            extent,
+            Some(vk::ClearColorValue {
+                float32: [0.0, 0.0, 0.0, 0.0],
+            }),
+        );
+        self.device.cmd_bind_descriptor_set(
+            command_buffer,
+            self.draw_pipeline.layout(),
+            vk::PipelineBindPoint::GRAPHICS,
+            DescriptorSetSlot::Scene,
+            scene_descriptor_set,
+        );
+        let time = self.start_time.elapsed().as_secs_f32();
+        for (mesh, world_matrix) in mesh_instances {
+            let Some(settings) = self.marked_meshes.get(mesh.name()) else {
+                continue;
+            };
+            let mode = match settings.mode {
+                DistortionMode::HeatHaze => 0.0,
+                DistortionMode::Refraction => 1.0,
+            };
+            let push_constants = GPUDistortionPushConstants {
+                world_matrix: *world_matrix,
+                device_address: mesh.buffers().vertex_buffer_address(),
+                _padding: 0,
+                params: glm::vec4(
+                    settings.strength,
+                    settings.scale,
+                    time * settings.speed,
+                    mode,
+                ),
+                index_address: 0,
+                _padding_2: 0,
+                draw_buffer: 0,
+            };
+            for surface in mesh.surfaces() {
+                self.draw_pipeline.draw_with_constants(
+                    command_buffer,
+                    mesh,
+                    surface,
+                    bytemuck::bytes_of(&push_constants),
+                );
+            }
+        }
+        self.draw_pipeline.end_drawing(command_buffer);
+    }
+
+    // scene copy + distortion => draw image
+    pub fn record_apply(&self, command_buffer: vk::CommandBuffer, extent: vk::Extent2D) {
+        let push_constants = PushConstants::new(
+            glm::vec4(extent.width as f32, extent.height as f32, 0.0, 0.0),
+            glm::Vec4::zeros(),
+            glm::Vec4::zeros(),
+            glm::Vec4::zeros(),
+        );
+        self.apply_pipeline.execute_compute_with_constants(
+            command_buffer,
+            &[self.apply_descriptor],
+            extent,
+            &push_constants,
+        );
+    }
+}
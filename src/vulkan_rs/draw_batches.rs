@@ -0,0 +1,680 @@
+use super::allocation::AllocatedBuffer;
+use super::allocation::Allocator;
+use super::device::Device;
+use super::error::VulkanError;
+use super::gpu_sort::GpuSort;
+use super::gpu_sort::GROUP_SIZE;
+use super::material::Material;
+use super::material::MaterialPass;
+use super::mesh::GPUDrawData;
+use super::mesh::GeometricSurface;
+use super::mesh::MeshAsset;
+use super::pipelines::ComputePipeline;
+use super::pipelines::GraphicsPipeline;
+use super::render_graph::BufferHandle;
+use super::render_graph::BufferUsage;
+use super::render_graph::GraphPass;
+use super::render_graph::RenderGraph;
+use super::shader::ShaderModule;
+use ash::vk;
+use nalgebra_glm as glm;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+// matches the MODE_ constants in transparent_sort.comp
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TransparentSortMode {
+    Keys = 0,
+    Reorder = 1,
+}
+
+// matches the push constants of transparent_sort.comp
+#[repr(C)]
+#[derive(bytemuck::NoUninit, Copy, Clone, Debug)]
+struct GPUTransparentSortPushConstants {
+    camera_position: glm::Vec4,
+    draw_buffer: vk::DeviceAddress,
+    key_values: vk::DeviceAddress,
+    commands: vk::DeviceAddress,
+    sorted_commands: vk::DeviceAddress,
+    first_command: u32,
+    count: u32,
+    mode: u32,
+    _padding: u32,
+}
+
+// matches the push constants of cull.comp
+#[repr(C)]
+#[derive(bytemuck::NoUninit, Copy, Clone, Debug)]
+struct GPUCullPushConstants {
+    view_proj: glm::Mat4,
+    draw_buffer: vk::DeviceAddress,
+    bounds: vk::DeviceAddress,
+    commands: vk::DeviceAddress,
+    culled_commands: vk::DeviceAddress,
+    command_batches: vk::DeviceAddress,
+    draw_counts: vk::DeviceAddress,
+    count: u32,
+    _padding: u32,
+}
+
+// draw data + indirect commands of one frame slot, grown when a frame has more draws
+struct BatchBuffers {
+    draws: AllocatedBuffer,
+    // object space bounding sphere per draw
+    bounds: AllocatedBuffer,
+    commands: AllocatedBuffer,
+    // (batch, first command of the batch) per opaque command
+    command_batches: AllocatedBuffer,
+    // visible opaque commands at the start of their batch, only written on the gpu
+    culled_commands: AllocatedBuffer,
+    // visible commands per opaque batch
+    draw_counts: AllocatedBuffer,
+    // (not in view << 31 | inverted distance, command) of the transparent draws, sorted on the gpu
+    key_values: AllocatedBuffer,
+    // transparent commands back to front as non indexed draws, only written on the gpu
+    sorted_commands: AllocatedBuffer,
+    capacity: usize,
+}
+
+impl BatchBuffers {
+    fn new(
+        device: Arc<Device>,
+        allocator: Arc<Mutex<Allocator>>,
+        capacity: usize,
+    ) -> Result<Self, VulkanError> {
+        let draws = AllocatedBuffer::new(
+            device.clone(),
+            allocator.clone(),
+            "Draw Data Buffer",
+            vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+            (capacity * std::mem::size_of::<GPUDrawData>()) as u64,
+            gpu_allocator::MemoryLocation::CpuToGpu,
+        )?;
+        let bounds = AllocatedBuffer::new(
+            device.clone(),
+            allocator.clone(),
+            "Draw Bounds Buffer",
+            vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+            (capacity * std::mem::size_of::<glm::Vec4>()) as u64,
+            gpu_allocator::MemoryLocation::CpuToGpu,
+        )?;
+        let command_size = std::mem::size_of::<vk::DrawIndexedIndirectCommand>();
+        let commands = AllocatedBuffer::new(
+            device.clone(),
+            allocator.clone(),
+            "Indirect Draw Buffer",
+            vk::BufferUsageFlags::INDIRECT_BUFFER
+                | vk::BufferUsageFlags::STORAGE_BUFFER
+                | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+            (capacity * command_size) as u64,
+            gpu_allocator::MemoryLocation::CpuToGpu,
+        )?;
+        let command_batches = AllocatedBuffer::new(
+            device.clone(),
+            allocator.clone(),
+            "Command Batch Buffer",
+            vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+            (capacity * std::mem::size_of::<[u32; 2]>()) as u64,
+            gpu_allocator::MemoryLocation::CpuToGpu,
+        )?;
+        let culled_commands = AllocatedBuffer::new(
+            device.clone(),
+            allocator.clone(),
+            "Culled Indirect Draw Buffer",
+            vk::BufferUsageFlags::INDIRECT_BUFFER
+                | vk::BufferUsageFlags::STORAGE_BUFFER
+                | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+            (capacity * command_size) as u64,
+            gpu_allocator::MemoryLocation::GpuOnly,
+        )?;
+        // there are never more batches than commands
+        let draw_counts = AllocatedBuffer::new(
+            device.clone(),
+            allocator.clone(),
+            "Draw Count Buffer",
+            vk::BufferUsageFlags::INDIRECT_BUFFER
+                | vk::BufferUsageFlags::STORAGE_BUFFER
+                | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS
+                | vk::BufferUsageFlags::TRANSFER_DST,
+            (capacity * std::mem::size_of::<u32>()) as u64,
+            gpu_allocator::MemoryLocation::GpuOnly,
+        )?;
+        // capacity is a power of two => the padded transparent draws always fit
+        let key_values = AllocatedBuffer::new(
+            device.clone(),
+            allocator.clone(),
+            "Transparent Sort Buffer",
+            vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+            (capacity * std::mem::size_of::<[u32; 2]>()) as u64,
+            gpu_allocator::MemoryLocation::CpuToGpu,
+        )?;
+        let sorted_commands = AllocatedBuffer::new(
+            device,
+            allocator,
+            "Sorted Indirect Draw Buffer",
+            vk::BufferUsageFlags::INDIRECT_BUFFER
+                | vk::BufferUsageFlags::STORAGE_BUFFER
+                | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+            (capacity * command_size) as u64,
+            gpu_allocator::MemoryLocation::GpuOnly,
+        )?;
+        Ok(BatchBuffers {
+            draws,
+            bounds,
+            commands,
+            command_batches,
+            culled_commands,
+            draw_counts,
+            key_values,
+            sorted_commands,
+            capacity,
+        })
+    }
+}
+
+struct PendingDraw {
+    pass: MaterialPass,
+    index_buffer: vk::Buffer,
+    first_index: u32,
+    index_count: u32,
+    data: GPUDrawData,
+    bounds: glm::Vec4,
+    in_view: bool,
+    // world space center of the surface
+    center: glm::Vec3,
+    sort_key: u64,
+}
+
+// most significant first:
+//   1 bit  transparent => the pipeline, opaque draws first
+//   1 bit  not in view => the batches that are in view are next to each other
+// opaque:
+//   16 bit index buffer => the batches
+//   16 bit material => draws with the same material are next to each other
+//   24 bit camera distance, front to back (early z)
+// transparent:
+//   24 bit camera distance, back to front like the gpu sort of add_passes
+//   16 bit index buffer
+fn sort_key(draw: &PendingDraw, index_buffer_id: u64, material_id: u64, distance: f32) -> u64 {
+    let transparent = draw.pass == MaterialPass::Transparent;
+    // positive floats sort like their bits => the upper 24 bits are a coarse distance
+    let distance = (distance.max(0.0).to_bits() >> 8) as u64;
+    let index_buffer_id = index_buffer_id.min(0xFFFF);
+    let order = if transparent {
+        (0xFF_FFFF - distance) << 32 | index_buffer_id << 16
+    } else {
+        index_buffer_id << 40 | material_id.min(0xFFFF) << 24 | distance
+    };
+    (transparent as u64) << 57 | (!draw.in_view as u64) << 56 | order
+}
+
+// consecutive opaque draws that share an index buffer => one indirect draw
+struct DrawBatch {
+    // outside the camera frustum => only drawn by record (shadows, minimap), not by record_culled
+    in_view: bool,
+    index_buffer: vk::Buffer,
+    first_command: u32,
+    command_count: u32,
+}
+
+// collects the surfaces of a frame and records them as a few indirect draws instead of one draw
+// per surface. Every indirect command uses its index as first instance => mesh.vert finds its
+// GPUDrawData through gl_InstanceIndex
+// the opaque draws are culled against the camera frustum. The transparent draws are sorted back
+// to front on the gpu and drawn by one non indexed indirect draw, mesh.vert reads their indices
+// through GPUDrawData => the order holds across index buffers
+pub struct DrawBatches {
+    device: Arc<Device>,
+    allocator: Arc<Mutex<Allocator>>,
+    frame_buffers: Vec<BatchBuffers>,
+    frame_slot: usize,
+    pending: Vec<PendingDraw>,
+    // opaque only
+    batches: Vec<DrawBatch>,
+    // the transparent commands come after the opaque ones
+    first_transparent: u32,
+    transparent_count: u32,
+    // the transparent commands in view are the first ones after the sort
+    transparent_in_view: u32,
+    opaque_count: u32,
+    opaque_batch_count: u32,
+    gpu_sort: GpuSort,
+    sort_pipeline: ComputePipeline,
+    cull_pipeline: ComputePipeline,
+}
+
+impl DrawBatches {
+    pub fn new(
+        device: Arc<Device>,
+        allocator: Arc<Mutex<Allocator>>,
+        frame_count: usize,
+    ) -> Result<Self, VulkanError> {
+        let frame_buffers = (0..frame_count)
+            .map(|_| BatchBuffers::new(device.clone(), allocator.clone(), 256))
+            .collect::<Result<Vec<_>, _>>()?;
+        let gpu_sort = GpuSort::new(device.clone())?;
+        let shader = ShaderModule::new(device.clone(), "shaders/transparent_sort_comp.spv")?;
+        let sort_pipeline = ComputePipeline::new(device.clone(), &[], shader)?;
+        let shader = ShaderModule::new(device.clone(), "shaders/cull_comp.spv")?;
+        let cull_pipeline = ComputePipeline::new(device.clone(), &[], shader)?;
+        Ok(DrawBatches {
+            device,
+            allocator,
+            frame_buffers,
+            frame_slot: 0,
+            pending: Vec::new(),
+            batches: Vec::new(),
+            first_transparent: 0,
+            transparent_count: 0,
+            transparent_in_view: 0,
+            opaque_count: 0,
+            opaque_batch_count: 0,
+            gpu_sort,
+            sort_pipeline,
+            cull_pipeline,
+        })
+    }
+
+    // the material decides the pass, not the surface => overrides can move a surface
+    // in_view: the surface can be seen by the camera, others still cast shadows
+    pub fn add(
+        &mut self,
+        mesh: &MeshAsset,
+        surface: &GeometricSurface,
+        material: &Material,
+        world_matrix: &glm::Mat4,
+        in_view: bool,
+    ) {
+        let transparent = material.pass() == MaterialPass::Transparent;
+        self.pending.push(PendingDraw {
+            pass: material.pass(),
+            in_view,
+            index_buffer: mesh.buffers().index_buffer(),
+            first_index: surface.start_idx() as u32,
+            index_count: surface.count(),
+            data: GPUDrawData {
+                world_matrix: *world_matrix,
+                device_address: mesh.buffers().vertex_buffer_address(),
+                material_address: material.constants_address(),
+                texture_indices: material.texture_indices(),
+                index_address: if transparent {
+                    mesh.buffers().index_buffer_address()
+                } else {
+                    0
+                },
+                _padding: 0,
+            },
+            bounds: glm::vec4(
+                mesh.bounds().center.x,
+                mesh.bounds().center.y,
+                mesh.bounds().center.z,
+                mesh.bounds().radius,
+            ),
+            center: world_matrix
+                .transform_point(&surface.bounds().center().into())
+                .coords,
+            sort_key: 0,
+        });
+    }
+
+    // sorts and batches the added draws and writes them into the buffers of the slot, the slot
+    // has to be finished on the gpu. The added draws are cleared for the next frame
+    pub fn prepare(
+        &mut self,
+        frame_slot: usize,
+        camera_position: glm::Vec3,
+    ) -> Result<(), VulkanError> {
+        self.frame_slot = frame_slot;
+        self.batches.clear();
+        self.opaque_batch_count = 0;
+        // ids in the order of first use => they fit into the sort key
+        let mut index_buffer_ids = HashMap::new();
+        let mut material_ids = HashMap::new();
+        for draw in self.pending.iter_mut() {
+            let id_count = index_buffer_ids.len() as u64;
+            let index_buffer_id = *index_buffer_ids
+                .entry(draw.index_buffer)
+                .or_insert(id_count);
+            let id_count = material_ids.len() as u64;
+            let material_id = *material_ids
+                .entry(draw.data.material_address)
+                .or_insert(id_count);
+            let distance = glm::distance(&camera_position, &draw.center);
+            draw.sort_key = sort_key(draw, index_buffer_id, material_id, distance);
+        }
+        self.pending.sort_by_key(|draw| draw.sort_key);
+
+        let buffers = &mut self.frame_buffers[frame_slot];
+        // the slot is finished => the old buffers can be dropped right away
+        if self.pending.len() > buffers.capacity {
+            *buffers = BatchBuffers::new(
+                self.device.clone(),
+                self.allocator.clone(),
+                self.pending.len().next_power_of_two(),
+            )?;
+        }
+
+        let mut draws = Vec::with_capacity(self.pending.len());
+        let mut bounds = Vec::with_capacity(self.pending.len());
+        let mut commands = Vec::with_capacity(self.pending.len());
+        // the gpu adds the distance to the key
+        let mut key_values = Vec::new();
+        self.first_transparent = 0;
+        for (idx, draw) in self.pending.drain(..).enumerate() {
+            let idx = idx as u32;
+            if draw.pass == MaterialPass::Transparent {
+                if key_values.is_empty() {
+                    self.first_transparent = idx;
+                }
+                key_values.push([(!draw.in_view as u32) << 31, idx - self.first_transparent]);
+            } else {
+                match self.batches.last_mut() {
+                    Some(batch)
+                        if batch.in_view == draw.in_view
+                            && batch.index_buffer == draw.index_buffer =>
+                    {
+                        batch.command_count += 1;
+                    }
+                    _ => self.batches.push(DrawBatch {
+                        in_view: draw.in_view,
+                        index_buffer: draw.index_buffer,
+                        first_command: idx,
+                        command_count: 1,
+                    }),
+                }
+            }
+            commands.push(vk::DrawIndexedIndirectCommand {
+                index_count: draw.index_count,
+                instance_count: 1,
+                first_index: draw.first_index,
+                vertex_offset: 0,
+                first_instance: idx,
+            });
+            draws.push(draw.data);
+            bounds.push(draw.bounds);
+        }
+        buffers.draws.copy_from_slice(&draws, 0);
+        buffers.bounds.copy_from_slice(&bounds, 0);
+        buffers.commands.copy_from_slice(&commands, 0);
+
+        // opaque batches in view come first => their commands are the first ones
+        // the others are never drawn by record_culled => no need to cull them on the gpu
+        let mut command_batches = Vec::new();
+        let opaque_batches = self.batches.iter().take_while(|batch| batch.in_view);
+        for (batch_idx, batch) in opaque_batches.enumerate() {
+            command_batches
+                .extend((0..batch.command_count).map(|_| [batch_idx as u32, batch.first_command]));
+            self.opaque_batch_count = batch_idx as u32 + 1;
+        }
+        self.opaque_count = command_batches.len() as u32;
+        buffers.command_batches.copy_from_slice(&command_batches, 0);
+
+        self.transparent_count = key_values.len() as u32;
+        self.transparent_in_view = key_values.iter().filter(|[key, _]| *key == 0).count() as u32;
+        key_values.resize(key_values.len().next_power_of_two(), [u32::MAX, u32::MAX]);
+        buffers.key_values.copy_from_slice(&key_values, 0);
+        Ok(())
+    }
+
+    // culls the opaque draws of the prepared frame against the frustum of view_proj and sorts the
+    // transparent draws back to front. The returned buffers have to be declared as Indirect by the
+    // passes that record the draws
+    pub fn add_passes<'a>(
+        &'a self,
+        graph: &mut RenderGraph<'a>,
+        camera_position: glm::Vec3,
+        view_proj: glm::Mat4,
+    ) -> Vec<BufferHandle> {
+        let mut indirect_buffers = Vec::new();
+        if self.opaque_count > 0 {
+            indirect_buffers.extend(self.add_cull_passes(graph, view_proj));
+        }
+        if self.transparent_count > 0 {
+            indirect_buffers.push(self.add_sort_passes(graph, camera_position));
+        }
+        indirect_buffers
+    }
+
+    fn add_cull_passes<'a>(
+        &'a self,
+        graph: &mut RenderGraph<'a>,
+        view_proj: glm::Mat4,
+    ) -> [BufferHandle; 2] {
+        let buffers = &self.frame_buffers[self.frame_slot];
+        let culled_commands =
+            graph.import_buffer("culled draw commands", buffers.culled_commands.buffer());
+        let draw_counts = graph.import_buffer("draw counts", buffers.draw_counts.buffer());
+        let device = &self.device;
+        let draw_counts_buffer = buffers.draw_counts.buffer();
+        let draw_counts_size =
+            (self.opaque_batch_count as usize * std::mem::size_of::<u32>()) as u64;
+        graph.add_pass(
+            GraphPass::new("cull clear")
+                .buffer(draw_counts, BufferUsage::TransferDst)
+                .record(move |command_buffer| {
+                    device.cmd_fill_buffer(
+                        command_buffer,
+                        draw_counts_buffer,
+                        0,
+                        draw_counts_size,
+                        0,
+                    );
+                }),
+        );
+        let push_constants = GPUCullPushConstants {
+            view_proj,
+            draw_buffer: buffers.draws.get_device_address(),
+            bounds: buffers.bounds.get_device_address(),
+            commands: buffers.commands.get_device_address(),
+            culled_commands: buffers.culled_commands.get_device_address(),
+            command_batches: buffers.command_batches.get_device_address(),
+            draw_counts: buffers.draw_counts.get_device_address(),
+            count: self.opaque_count,
+            _padding: 0,
+        };
+        let pipeline = &self.cull_pipeline;
+        let group_counts = [self.opaque_count.div_ceil(GROUP_SIZE), 1, 1];
+        graph.add_pass(
+            GraphPass::new("cull")
+                .buffer(draw_counts, BufferUsage::StorageWrite)
+                .buffer(culled_commands, BufferUsage::StorageWrite)
+                .record(move |command_buffer| {
+                    pipeline.dispatch(command_buffer, &[], group_counts, &push_constants);
+                }),
+        );
+        [culled_commands, draw_counts]
+    }
+
+    fn add_sort_passes<'a>(
+        &'a self,
+        graph: &mut RenderGraph<'a>,
+        camera_position: glm::Vec3,
+    ) -> BufferHandle {
+        let buffers = &self.frame_buffers[self.frame_slot];
+        let key_values = graph.import_buffer("transparent sort", buffers.key_values.buffer());
+        let sorted_commands =
+            graph.import_buffer("sorted draw commands", buffers.sorted_commands.buffer());
+        let key_values_address = buffers.key_values.get_device_address();
+        let push_constants = |mode: TransparentSortMode| GPUTransparentSortPushConstants {
+            camera_position: glm::vec4(
+                camera_position.x,
+                camera_position.y,
+                camera_position.z,
+                1.0,
+            ),
+            draw_buffer: buffers.draws.get_device_address(),
+            key_values: key_values_address,
+            commands: buffers.commands.get_device_address(),
+            sorted_commands: buffers.sorted_commands.get_device_address(),
+            first_command: self.first_transparent,
+            count: self.transparent_count,
+            mode: mode as u32,
+            _padding: 0,
+        };
+        let pipeline = &self.sort_pipeline;
+        let group_counts = [self.transparent_count.div_ceil(GROUP_SIZE), 1, 1];
+
+        let constants = push_constants(TransparentSortMode::Keys);
+        graph.add_pass(
+            GraphPass::new("transparent sort keys")
+                .buffer(key_values, BufferUsage::StorageWrite)
+                .record(move |command_buffer| {
+                    pipeline.dispatch(command_buffer, &[], group_counts, &constants);
+                }),
+        );
+        self.gpu_sort.add_passes(
+            graph,
+            key_values,
+            key_values_address,
+            self.transparent_count.next_power_of_two(),
+        );
+        let constants = push_constants(TransparentSortMode::Reorder);
+        graph.add_pass(
+            GraphPass::new("transparent reorder")
+                .buffer(key_values, BufferUsage::StorageRead)
+                .buffer(sorted_commands, BufferUsage::StorageWrite)
+                .record(move |command_buffer| {
+                    pipeline.dispatch(command_buffer, &[], group_counts, &constants);
+                }),
+        );
+        sorted_commands
+    }
+
+    // the pipeline and its descriptor sets have to be bound, the transparent draws have to be
+    // sorted by add_passes in the same graph
+    pub fn record(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        pipeline: &GraphicsPipeline,
+        pass: MaterialPass,
+    ) {
+        self.record_batches(command_buffer, pipeline, pass, false);
+    }
+
+    // like record, but only the draws in view and the opaque ones only if they passed the culling
+    // of add_passes => only for the camera of add_passes
+    pub fn record_culled(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        pipeline: &GraphicsPipeline,
+        pass: MaterialPass,
+    ) {
+        self.record_batches(command_buffer, pipeline, pass, true);
+    }
+
+    fn record_batches(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        pipeline: &GraphicsPipeline,
+        pass: MaterialPass,
+        culled: bool,
+    ) {
+        let buffers = &self.frame_buffers[self.frame_slot];
+        let draw_buffer = buffers.draws.get_device_address();
+        if pass == MaterialPass::Transparent {
+            let count = if culled {
+                self.transparent_in_view
+            } else {
+                self.transparent_count
+            };
+            if count > 0 {
+                pipeline.draw_indirect(
+                    command_buffer,
+                    buffers.sorted_commands.buffer(),
+                    0,
+                    count,
+                    draw_buffer,
+                );
+            }
+            return;
+        }
+        let batches = self
+            .batches
+            .iter()
+            .enumerate()
+            .filter(|(_, batch)| batch.in_view || !culled);
+        for (batch_idx, batch) in batches {
+            let (commands, count_buffer) = if culled {
+                (
+                    &buffers.culled_commands,
+                    Some((
+                        buffers.draw_counts.buffer(),
+                        (batch_idx * std::mem::size_of::<u32>()) as u64,
+                    )),
+                )
+            } else {
+                (&buffers.commands, None)
+            };
+            pipeline.draw_indexed_indirect(
+                command_buffer,
+                batch.index_buffer,
+                commands.buffer(),
+                (batch.first_command as usize
+                    * std::mem::size_of::<vk::DrawIndexedIndirectCommand>()) as u64,
+                batch.command_count,
+                count_buffer,
+                draw_buffer,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn draw(pass: MaterialPass, in_view: bool) -> PendingDraw {
+        PendingDraw {
+            pass,
+            index_buffer: vk::Buffer::null(),
+            first_index: 0,
+            index_count: 0,
+            data: GPUDrawData {
+                world_matrix: glm::Mat4::identity(),
+                device_address: 0,
+                material_address: 0,
+                texture_indices: [0; 4],
+                index_address: 0,
+                _padding: 0,
+            },
+            bounds: glm::Vec4::zeros(),
+            in_view,
+            center: glm::Vec3::zeros(),
+            sort_key: 0,
+        }
+    }
+
+    #[test]
+    fn opaque_front_to_back_within_material() {
+        let opaque = draw(MaterialPass::Opaque, true);
+        let near = sort_key(&opaque, 0, 3, 1.0);
+        let far = sort_key(&opaque, 0, 3, 10.0);
+        assert!(near < far);
+        // the material still groups the draws
+        assert!(far < sort_key(&opaque, 0, 4, 0.5));
+    }
+
+    #[test]
+    fn transparent_back_to_front_across_index_buffers() {
+        let transparent = draw(MaterialPass::Transparent, true);
+        let far = sort_key(&transparent, 1, 0, 10.0);
+        let near = sort_key(&transparent, 0, 0, 1.0);
+        assert!(far < near);
+        let far = sort_key(&transparent, 0, 0, 10.0);
+        let near = sort_key(&transparent, 1, 0, 1.0);
+        assert!(far < near);
+        // opaque draws come first
+        assert!(sort_key(&draw(MaterialPass::Opaque, false), 0, 0, 1000.0) < far);
+    }
+
+    #[test]
+    fn in_view_before_out_of_view() {
+        for pass in [MaterialPass::Opaque, MaterialPass::Transparent] {
+            let in_view = sort_key(&draw(pass, true), 0xFFFF, 0xFFFF, 1000.0);
+            let out_of_view = sort_key(&draw(pass, false), 0, 0, 0.0);
+            assert!(in_view < out_of_view);
+        }
+    }
+}
@@ -0,0 +1,459 @@
+use super::allocation::AllocatedBuffer;
+use super::allocation::AllocatedImage;
+use super::allocation::Allocator;
+use super::async_upload::AsyncUploader;
+use super::deletion_queue::DeletionQueue;
+use super::descriptor::TextureHandle;
+use super::device::Device;
+use super::error::VulkanError;
+use super::material::MaterialCache;
+use super::material::MaterialTexture;
+use super::mesh::SamplerSettings;
+use super::pipelines::GraphicsPipeline;
+use super::pipelines::GraphicsPipelineBuilder;
+use super::shader::ShaderModule;
+use super::texture::ColorSpace;
+use ash::vk;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+// the buffers only grow => start big enough for a few windows
+const INITIAL_VERTEX_COUNT: usize = 1 << 14;
+const INITIAL_INDEX_COUNT: usize = 1 << 15;
+
+// same layout as the push constants in egui.vert/egui.frag
+#[repr(C)]
+#[derive(Debug, bytemuck::NoUninit, Copy, Clone)]
+struct GPUEguiPushConstants {
+    screen_size: [f32; 2],
+    texture_index: u32,
+    // 1 => srgb target, the hardware encodes the output
+    linear_output: u32,
+    vertex_buffer: vk::DeviceAddress,
+}
+
+struct EguiTexture {
+    _texture: MaterialTexture,
+    handle: TextureHandle,
+    // egui patches parts of its font atlas => the patched copy is uploaded as a new texture
+    pixels: Vec<egui::Color32>,
+    size: [usize; 2],
+}
+
+// host visible, rewritten every frame the slot is used
+struct FrameBuffers {
+    vertices: AllocatedBuffer,
+    vertex_capacity: usize,
+    indices: AllocatedBuffer,
+    index_capacity: usize,
+}
+
+impl FrameBuffers {
+    fn new(
+        device: Arc<Device>,
+        allocator: Arc<Mutex<Allocator>>,
+        vertex_capacity: usize,
+        index_capacity: usize,
+    ) -> Result<Self, VulkanError> {
+        let vertices = AllocatedBuffer::new(
+            device.clone(),
+            allocator.clone(),
+            "egui vertices",
+            vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+            (vertex_capacity * std::mem::size_of::<egui::epaint::Vertex>()) as u64,
+            gpu_allocator::MemoryLocation::CpuToGpu,
+        )?;
+        let indices = AllocatedBuffer::new(
+            device,
+            allocator,
+            "egui indices",
+            vk::BufferUsageFlags::INDEX_BUFFER,
+            (index_capacity * std::mem::size_of::<u32>()) as u64,
+            gpu_allocator::MemoryLocation::CpuToGpu,
+        )?;
+        Ok(FrameBuffers {
+            vertices,
+            vertex_capacity,
+            indices,
+            index_capacity,
+        })
+    }
+}
+
+// one egui mesh => one draw with its own texture and clip rect
+struct UiDraw {
+    // in pixels
+    scissor: vk::Rect2D,
+    texture_index: u32,
+    first_index: u32,
+    index_count: u32,
+    vertex_offset: i32,
+}
+
+// draws the tessellated egui output on top of the finished frame
+// textures live in the bindless texture table of the MaterialCache, the geometry is pulled from a
+// per frame slot buffer like the meshes
+pub struct EguiRenderer {
+    device: Arc<Device>,
+    allocator: Arc<Mutex<Allocator>>,
+    pipeline: GraphicsPipeline,
+    target_format: vk::Format,
+    textures: HashMap<egui::TextureId, EguiTexture>,
+    frame_buffers: Vec<FrameBuffers>,
+    // accumulated until the next frame that is actually drawn (e.g. not while minimized)
+    pending_textures: Vec<(egui::TextureId, egui::epaint::ImageDelta)>,
+    pending_frees: Vec<egui::TextureId>,
+    primitives: Vec<egui::ClippedPrimitive>,
+    pixels_per_point: f32,
+    // built by prepare for the frame that is recorded next
+    draws: Vec<UiDraw>,
+    frame_slot: usize,
+}
+
+impl EguiRenderer {
+    pub fn new(
+        device: Arc<Device>,
+        allocator: Arc<Mutex<Allocator>>,
+        material_cache: &MaterialCache,
+        target_format: vk::Format,
+        frame_count: usize,
+    ) -> Result<Self, VulkanError> {
+        let pipeline = Self::build_pipeline(device.clone(), material_cache, target_format)?;
+        let frame_buffers = (0..frame_count)
+            .map(|_| {
+                FrameBuffers::new(
+                    device.clone(),
+                    allocator.clone(),
+                    INITIAL_VERTEX_COUNT,
+                    INITIAL_INDEX_COUNT,
+                )
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(EguiRenderer {
+            device,
+            allocator,
+            pipeline,
+            target_format,
+            textures: HashMap::new(),
+            frame_buffers,
+            pending_textures: Vec::new(),
+            pending_frees: Vec::new(),
+            primitives: Vec::new(),
+            pixels_per_point: 1.0,
+            draws: Vec::new(),
+            frame_slot: 0,
+        })
+    }
+
+    fn build_pipeline(
+        device: Arc<Device>,
+        material_cache: &MaterialCache,
+        target_format: vk::Format,
+    ) -> Result<GraphicsPipeline, VulkanError> {
+        let push_constants = vk::PushConstantRange {
+            stage_flags: vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+            offset: 0,
+            size: std::mem::size_of::<GPUEguiPushConstants>() as u32,
+        };
+        let set_layout = material_cache.texture_table_layout();
+        let layout_info = vk::PipelineLayoutCreateInfo {
+            s_type: vk::StructureType::PIPELINE_LAYOUT_CREATE_INFO,
+            p_next: std::ptr::null(),
+            flags: vk::PipelineLayoutCreateFlags::empty(),
+            set_layout_count: 1,
+            p_set_layouts: &set_layout,
+            push_constant_range_count: 1,
+            p_push_constant_ranges: &push_constants,
+            ..Default::default()
+        };
+        let pipeline_layout = device.create_pipeline_layout(&layout_info)?;
+        let frag_shader = ShaderModule::new(device.clone(), "shaders/egui_frag.spv")?;
+        let vert_shader = ShaderModule::new(device.clone(), "shaders/egui_vert.spv")?;
+        GraphicsPipelineBuilder::new()
+            .set_layout(pipeline_layout)
+            .set_shaders(&frag_shader, &vert_shader)
+            .set_input_topology(vk::PrimitiveTopology::TRIANGLE_LIST)
+            .set_polygon_mode(vk::PolygonMode::FILL)
+            .set_cull_mode(vk::CullModeFlags::NONE, vk::FrontFace::CLOCKWISE)
+            .disable_multisampling()
+            .set_color_attachment_format(target_format)
+            .disable_depth_test()
+            .enable_blending_premultiplied()
+            .build_pipeline(device)
+    }
+
+    // output of one egui frame, replaces the primitives of a frame that was not drawn
+    pub fn set_frame(
+        &mut self,
+        primitives: Vec<egui::ClippedPrimitive>,
+        textures_delta: egui::TexturesDelta,
+        pixels_per_point: f32,
+    ) {
+        self.primitives = primitives;
+        self.pixels_per_point = pixels_per_point;
+        self.pending_textures.extend(textures_delta.set);
+        self.pending_frees.extend(textures_delta.free);
+    }
+
+    // the frame slot has to be finished on the gpu
+    // replaced/freed textures go to the deletion queue of the slot => alive until the frame is done
+    pub fn prepare(
+        &mut self,
+        frame_slot: usize,
+        target_format: vk::Format,
+        material_cache: &mut MaterialCache,
+        uploader: &AsyncUploader,
+        deletion_queue: &mut DeletionQueue,
+    ) -> Result<(), VulkanError> {
+        if target_format != self.target_format {
+            let pipeline =
+                Self::build_pipeline(self.device.clone(), material_cache, target_format)?;
+            deletion_queue.push_resource(std::mem::replace(&mut self.pipeline, pipeline));
+            self.target_format = target_format;
+        }
+        for (id, delta) in std::mem::take(&mut self.pending_textures) {
+            if let Some(old_texture) = self.update_texture(id, delta, material_cache, uploader)? {
+                deletion_queue.push_resource(old_texture);
+            }
+        }
+        self.frame_slot = frame_slot;
+        self.upload_geometry()?;
+        // egui frees textures after the frame that still uses them
+        for id in std::mem::take(&mut self.pending_frees) {
+            if let Some(texture) = self.textures.remove(&id) {
+                deletion_queue.push_resource(texture);
+            }
+        }
+        Ok(())
+    }
+
+    // returns the texture that was replaced
+    fn update_texture(
+        &mut self,
+        id: egui::TextureId,
+        delta: egui::epaint::ImageDelta,
+        material_cache: &mut MaterialCache,
+        uploader: &AsyncUploader,
+    ) -> Result<Option<EguiTexture>, VulkanError> {
+        let delta_size = delta.image.size();
+        let delta_pixels: Vec<egui::Color32> = match &delta.image {
+            egui::ImageData::Color(image) => image.pixels.clone(),
+            egui::ImageData::Font(image) => image.srgba_pixels(None).collect(),
+        };
+        let (pixels, size) = match delta.pos {
+            None => (delta_pixels, delta_size),
+            Some([x, y]) => {
+                let Some(texture) = self.textures.get(&id) else {
+                    log::warn!("egui updated texture {:?} before creating it", id);
+                    return Ok(None);
+                };
+                let mut pixels = texture.pixels.clone();
+                for (row, delta_row) in delta_pixels.chunks_exact(delta_size[0]).enumerate() {
+                    let start = (y + row) * texture.size[0] + x;
+                    pixels[start..start + delta_size[0]].copy_from_slice(delta_row);
+                }
+                (pixels, texture.size)
+            }
+        };
+
+        let image = AllocatedImage::new_texture(
+            &pixels,
+            self.device.clone(),
+            self.allocator.clone(),
+            vk::Format::R8G8B8A8_SRGB,
+            vk::ImageUsageFlags::SAMPLED,
+            vk::Extent3D {
+                width: size[0] as u32,
+                height: size[1] as u32,
+                depth: 1,
+            },
+            false,
+            uploader,
+        )?;
+        image.set_name(&format!("egui texture {:?}", id));
+        let sampler = material_cache.sampler(Self::sampler_settings(delta.options))?;
+        let texture = MaterialTexture {
+            image: Arc::new(image),
+            sampler,
+        };
+        let handle = material_cache.register_texture(&texture)?;
+        Ok(self.textures.insert(
+            id,
+            EguiTexture {
+                _texture: texture,
+                handle,
+                pixels,
+                size,
+            },
+        ))
+    }
+
+    fn sampler_settings(options: egui::TextureOptions) -> SamplerSettings {
+        let filter = |filter: egui::TextureFilter| match filter {
+            egui::TextureFilter::Nearest => vk::Filter::NEAREST,
+            egui::TextureFilter::Linear => vk::Filter::LINEAR,
+        };
+        let address_mode = match options.wrap_mode {
+            egui::TextureWrapMode::ClampToEdge => vk::SamplerAddressMode::CLAMP_TO_EDGE,
+            egui::TextureWrapMode::Repeat => vk::SamplerAddressMode::REPEAT,
+            egui::TextureWrapMode::MirroredRepeat => vk::SamplerAddressMode::MIRRORED_REPEAT,
+        };
+        let mut settings =
+            SamplerSettings::new(filter(options.minification), filter(options.magnification))
+                .mipmap_mode(None);
+        settings.address_mode_u = address_mode;
+        settings.address_mode_v = address_mode;
+        settings
+    }
+
+    fn upload_geometry(&mut self) -> Result<(), VulkanError> {
+        self.draws.clear();
+        let meshes: Vec<(egui::Rect, &egui::Mesh)> = self
+            .primitives
+            .iter()
+            .filter_map(|primitive| match &primitive.primitive {
+                egui::epaint::Primitive::Mesh(mesh) if !mesh.indices.is_empty() => {
+                    Some((primitive.clip_rect, mesh))
+                }
+                // paint callbacks are not supported
+                _ => None,
+            })
+            .collect();
+        let vertex_count: usize = meshes.iter().map(|(_, mesh)| mesh.vertices.len()).sum();
+        let index_count: usize = meshes.iter().map(|(_, mesh)| mesh.indices.len()).sum();
+
+        let buffers = &mut self.frame_buffers[self.frame_slot];
+        // the slot is finished => the old buffers can be dropped right away
+        if vertex_count > buffers.vertex_capacity || index_count > buffers.index_capacity {
+            *buffers = FrameBuffers::new(
+                self.device.clone(),
+                self.allocator.clone(),
+                vertex_count
+                    .next_power_of_two()
+                    .max(buffers.vertex_capacity),
+                index_count.next_power_of_two().max(buffers.index_capacity),
+            )?;
+        }
+
+        let mut draws = Vec::with_capacity(meshes.len());
+        let (mut first_vertex, mut first_index) = (0, 0);
+        for (clip_rect, mesh) in meshes {
+            buffers.vertices.copy_from_slice(
+                &mesh.vertices,
+                first_vertex * std::mem::size_of::<egui::epaint::Vertex>(),
+            );
+            buffers
+                .indices
+                .copy_from_slice(&mesh.indices, first_index * std::mem::size_of::<u32>());
+            match texture_index(&self.textures, mesh.texture_id) {
+                Some(texture_index) => draws.push(UiDraw {
+                    scissor: scissor(clip_rect, self.pixels_per_point),
+                    texture_index,
+                    first_index: first_index as u32,
+                    index_count: mesh.indices.len() as u32,
+                    vertex_offset: first_vertex as i32,
+                }),
+                None => log::warn!("egui texture {:?} does not exist", mesh.texture_id),
+            }
+            first_vertex += mesh.vertices.len();
+            first_index += mesh.indices.len();
+        }
+        self.draws = draws;
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.draws.is_empty()
+    }
+
+    // the target has to be in COLOR_ATTACHMENT_OPTIMAL and have the format passed to prepare
+    pub fn record(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        target: vk::ImageView,
+        extent: vk::Extent2D,
+        texture_descriptor_set: vk::DescriptorSet,
+    ) {
+        if self.draws.is_empty() {
+            return;
+        }
+        let buffers = &self.frame_buffers[self.frame_slot];
+        self.pipeline.begin_color_only(
+            command_buffer,
+            target,
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            extent,
+        );
+        self.device.cmd_bind_descriptor_sets(
+            command_buffer,
+            self.pipeline.layout(),
+            vk::PipelineBindPoint::GRAPHICS,
+            &[texture_descriptor_set],
+        );
+        let linear_output = ColorSpace::of_format(self.target_format) == ColorSpace::Srgb;
+        let vertex_buffer = buffers.vertices.get_device_address();
+        for draw in self.draws.iter() {
+            let x = (draw.scissor.offset.x as u32).min(extent.width);
+            let y = (draw.scissor.offset.y as u32).min(extent.height);
+            let scissor = vk::Rect2D {
+                offset: draw.scissor.offset,
+                extent: vk::Extent2D {
+                    width: draw.scissor.extent.width.min(extent.width - x),
+                    height: draw.scissor.extent.height.min(extent.height - y),
+                },
+            };
+            if scissor.extent.width == 0 || scissor.extent.height == 0 {
+                continue;
+            }
+            self.pipeline.set_scissor(command_buffer, scissor);
+            let push_constants = GPUEguiPushConstants {
+                screen_size: [
+                    extent.width as f32 / self.pixels_per_point,
+                    extent.height as f32 / self.pixels_per_point,
+                ],
+                texture_index: draw.texture_index,
+                linear_output: linear_output as u32,
+                vertex_buffer,
+            };
+            self.pipeline.draw_indexed(
+                command_buffer,
+                buffers.indices.buffer(),
+                draw.first_index,
+                draw.index_count,
+                draw.vertex_offset,
+                bytemuck::bytes_of(&push_constants),
+            );
+        }
+        self.pipeline.end_drawing(command_buffer);
+    }
+}
+
+// TextureId::User is an index into the texture table, e.g. from MaterialCache::register_texture
+fn texture_index(
+    textures: &HashMap<egui::TextureId, EguiTexture>,
+    id: egui::TextureId,
+) -> Option<u32> {
+    match id {
+        egui::TextureId::Managed(_) => textures.get(&id).map(|texture| texture.handle.index()),
+        egui::TextureId::User(index) => Some(index as u32),
+    }
+}
+
+// clip rect in points => scissor in pixels, not clamped to the target yet
+fn scissor(clip_rect: egui::Rect, pixels_per_point: f32) -> vk::Rect2D {
+    let min = (clip_rect.min.to_vec2() * pixels_per_point).round();
+    let max = (clip_rect.max.to_vec2() * pixels_per_point).round();
+    let min_x = min.x.max(0.0);
+    let min_y = min.y.max(0.0);
+    vk::Rect2D {
+        offset: vk::Offset2D {
+            x: min_x as i32,
+            y: min_y as i32,
+        },
+        extent: vk::Extent2D {
+            width: (max.x - min_x).max(0.0) as u32,
+            height: (max.y - min_y).max(0.0) as u32,
+        },
+    }
+}
@@ -0,0 +1,449 @@
+use super::allocation::AllocatedImage;
+use super::allocation::Allocator;
+use super::async_upload::AsyncUploader;
+use super::descriptor::DescriptorAllocator;
+use super::descriptor::DescriptorLayoutBuilder;
+use super::descriptor::DescriptorSetLayout;
+use super::descriptor::DescriptorWriter;
+use super::descriptor::PoolSizeRatio;
+use super::device::Device;
+use super::device::ImageLayers;
+use super::error::AssetError;
+use super::error::VulkanError;
+use super::immediate_submit::ImmediateCommandData;
+use super::material::MaterialCache;
+use super::material::MaterialTexture;
+use super::mesh::Sampler;
+use super::mesh::SamplerSettings;
+use super::pipelines::ComputePipeline;
+use super::pipelines::PushConstants;
+use super::shader::ShaderModule;
+use ash::vk;
+use nalgebra_glm as glm;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+const IRRADIANCE_SIZE: u32 = 32;
+const SPECULAR_SIZE: u32 = 128;
+// roughness 0 at level 0 up to roughness 1 at the last level
+const SPECULAR_MIP_LEVELS: u32 = 5;
+const BRDF_LUT_SIZE: u32 = 256;
+// every compute target is written through a storage image => one format that supports it everywhere
+const TARGET_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+
+// image based lighting, prefiltered once when the environment is loaded:
+//   irradiance map: diffuse lighting for every normal direction
+//   specular map: reflections, blurrier with every mip level (split sum approximation)
+//   brdf lut: scale/bias of the fresnel term for (n dot v, roughness)
+pub struct Environment {
+    // unfiltered, shared with the skybox
+    environment_map: Arc<AllocatedImage>,
+    irradiance_map: AllocatedImage,
+    specular_map: AllocatedImage,
+    brdf_lut: AllocatedImage,
+    cube_sampler: Arc<Sampler>,
+    lut_sampler: Arc<Sampler>,
+}
+
+impl Environment {
+    // white in every direction => the ambient color alone decides the ambient lighting
+    pub fn new_uniform(
+        device: Arc<Device>,
+        allocator: Arc<Mutex<Allocator>>,
+        immediate_command: &ImmediateCommandData,
+        uploader: &AsyncUploader,
+        material_cache: &mut MaterialCache,
+    ) -> Result<Self, VulkanError> {
+        Self::from_equirectangular(
+            &[1.0, 1.0, 1.0, 1.0],
+            vk::Extent2D {
+                width: 1,
+                height: 1,
+            },
+            device,
+            allocator,
+            immediate_command,
+            uploader,
+            material_cache,
+        )
+    }
+
+    // equirectangular (latitude/longitude) .hdr file
+    pub fn load_hdr(
+        path: &Path,
+        device: Arc<Device>,
+        allocator: Arc<Mutex<Allocator>>,
+        immediate_command: &ImmediateCommandData,
+        uploader: &AsyncUploader,
+        material_cache: &mut MaterialCache,
+    ) -> Result<Self, AssetError> {
+        let image = image::open(path)?.into_rgba32f();
+        let max_size = device.properties().limits.max_image_dimension2_d;
+        if image.width() > max_size || image.height() > max_size {
+            return Err(AssetError::UnsupportedTexture(format!(
+                "{}x{} is larger than the maximum texture size {}",
+                image.width(),
+                image.height(),
+                max_size
+            )));
+        }
+        log::info!(
+            "Prefiltering environment {:?} ({}x{})",
+            path,
+            image.width(),
+            image.height()
+        );
+        let environment = Self::from_equirectangular(
+            image.as_raw(),
+            vk::Extent2D {
+                width: image.width(),
+                height: image.height(),
+            },
+            device,
+            allocator,
+            immediate_command,
+            uploader,
+            material_cache,
+        )?;
+        Ok(environment)
+    }
+
+    fn from_equirectangular(
+        pixels: &[f32],
+        extent: vk::Extent2D,
+        device: Arc<Device>,
+        allocator: Arc<Mutex<Allocator>>,
+        immediate_command: &ImmediateCommandData,
+        uploader: &AsyncUploader,
+        material_cache: &mut MaterialCache,
+    ) -> Result<Self, VulkanError> {
+        // 32 bit floats => no conversion needed, but linear filtering is not guaranteed
+        let equirectangular = AllocatedImage::new_texture_with_levels(
+            &[bytemuck::cast_slice(pixels)],
+            device.clone(),
+            allocator.clone(),
+            vk::Format::R32G32B32A32_SFLOAT,
+            vk::ImageUsageFlags::SAMPLED,
+            vk::Extent3D {
+                width: extent.width,
+                height: extent.height,
+                depth: 1,
+            },
+            ImageLayers::Single,
+            uploader,
+        )?;
+        // a cube face covers a quarter of the equirectangular width
+        let environment_size = (extent.width / 4).next_power_of_two().clamp(16, 512);
+        let environment_mip_levels = environment_size.ilog2() + 1;
+        let environment = AllocatedImage::new_cubemap(
+            device.clone(),
+            allocator.clone(),
+            TARGET_FORMAT,
+            vk::ImageUsageFlags::STORAGE
+                | vk::ImageUsageFlags::SAMPLED
+                | vk::ImageUsageFlags::TRANSFER_SRC
+                | vk::ImageUsageFlags::TRANSFER_DST,
+            environment_size,
+            environment_mip_levels,
+        )?;
+        let target_usage = vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::SAMPLED;
+        let irradiance_map = AllocatedImage::new_cubemap(
+            device.clone(),
+            allocator.clone(),
+            TARGET_FORMAT,
+            target_usage,
+            IRRADIANCE_SIZE,
+            1,
+        )?;
+        let specular_map = AllocatedImage::new_cubemap(
+            device.clone(),
+            allocator.clone(),
+            TARGET_FORMAT,
+            target_usage,
+            SPECULAR_SIZE,
+            SPECULAR_MIP_LEVELS,
+        )?;
+        let brdf_lut = AllocatedImage::new(
+            device.clone(),
+            allocator,
+            TARGET_FORMAT,
+            target_usage,
+            vk::Extent3D {
+                width: BRDF_LUT_SIZE,
+                height: BRDF_LUT_SIZE,
+                depth: 1,
+            },
+            vk::ImageAspectFlags::COLOR,
+            1,
+        )?;
+
+        let nearest_sampler = material_cache.sampler(
+            SamplerSettings::new(vk::Filter::NEAREST, vk::Filter::NEAREST)
+                .mipmap_mode(None)
+                .address_mode(vk::SamplerAddressMode::CLAMP_TO_EDGE),
+        )?;
+        let cube_sampler = material_cache.sampler(
+            SamplerSettings::new(vk::Filter::LINEAR, vk::Filter::LINEAR)
+                .address_mode(vk::SamplerAddressMode::CLAMP_TO_EDGE),
+        )?;
+        let lut_sampler = material_cache.sampler(
+            SamplerSettings::new(vk::Filter::LINEAR, vk::Filter::LINEAR)
+                .mipmap_mode(None)
+                .address_mode(vk::SamplerAddressMode::CLAMP_TO_EDGE),
+        )?;
+
+        // storage views of single mip levels, all 6 faces as array layers
+        let mut storage_views = Vec::new();
+        let result = (|| {
+            let mut mip_view = |image: &AllocatedImage, level: u32| {
+                let view = image.create_mip_view(level)?;
+                storage_views.push(view);
+                Ok::<_, VulkanError>(view)
+            };
+            let environment_view = mip_view(&environment, 0)?;
+            let irradiance_view = mip_view(&irradiance_map, 0)?;
+            let specular_views = (0..SPECULAR_MIP_LEVELS)
+                .map(|level| mip_view(&specular_map, level))
+                .collect::<Result<Vec<_>, _>>()?;
+
+            let prefilter = Prefilter::new(device.clone(), 3 + SPECULAR_MIP_LEVELS)?;
+            let to_cube_set = prefilter.allocate_set(
+                (equirectangular.image_view(), nearest_sampler.sampler()),
+                environment_view,
+            )?;
+            let environment_input = (environment.image_view(), cube_sampler.sampler());
+            let irradiance_set = prefilter.allocate_set(environment_input, irradiance_view)?;
+            let specular_sets = specular_views
+                .iter()
+                .map(|view| prefilter.allocate_set(environment_input, *view))
+                .collect::<Result<Vec<_>, _>>()?;
+            let brdf_set = prefilter.allocate_set(environment_input, brdf_lut.image_view())?;
+
+            // the equirectangular upload has to be done before the compute passes sample it
+            uploader.wait_idle()?;
+            immediate_command.immediate_submit(|device, cmd| {
+                device.transition_image_layout(
+                    cmd,
+                    environment.image(),
+                    vk::ImageLayout::UNDEFINED,
+                    vk::ImageLayout::GENERAL,
+                );
+                prefilter.dispatch_faces(
+                    cmd,
+                    &prefilter.to_cube_pipeline,
+                    to_cube_set,
+                    environment_size,
+                    0.0,
+                );
+                // mip chain of the environment => the specular filter can sample lower levels
+                // instead of taking thousands of samples
+                device.transition_image_layout(
+                    cmd,
+                    environment.image(),
+                    vk::ImageLayout::GENERAL,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                );
+                device.generate_mipmaps(
+                    cmd,
+                    environment.image(),
+                    vk::Extent2D {
+                        width: environment_size,
+                        height: environment_size,
+                    },
+                    environment_mip_levels,
+                    6,
+                );
+
+                for image in [&irradiance_map, &specular_map, &brdf_lut] {
+                    device.transition_image_layout(
+                        cmd,
+                        image.image(),
+                        vk::ImageLayout::UNDEFINED,
+                        vk::ImageLayout::GENERAL,
+                    );
+                }
+                prefilter.dispatch_faces(
+                    cmd,
+                    &prefilter.irradiance_pipeline,
+                    irradiance_set,
+                    IRRADIANCE_SIZE,
+                    0.0,
+                );
+                for (level, set) in specular_sets.iter().enumerate() {
+                    let roughness = level as f32 / (SPECULAR_MIP_LEVELS - 1) as f32;
+                    prefilter.dispatch_faces(
+                        cmd,
+                        &prefilter.specular_pipeline,
+                        *set,
+                        (SPECULAR_SIZE >> level).max(1),
+                        roughness,
+                    );
+                }
+                prefilter.brdf_pipeline.execute_compute_with_constants(
+                    cmd,
+                    &[brdf_set],
+                    vk::Extent2D {
+                        width: BRDF_LUT_SIZE,
+                        height: BRDF_LUT_SIZE,
+                    },
+                    &PushConstants::new(
+                        glm::vec4(BRDF_LUT_SIZE as f32, 0.0, 0.0, 0.0),
+                        glm::Vec4::zeros(),
+                        glm::Vec4::zeros(),
+                        glm::Vec4::zeros(),
+                    ),
+                );
+                for image in [&irradiance_map, &specular_map, &brdf_lut] {
+                    device.transition_image_layout(
+                        cmd,
+                        image.image(),
+                        vk::ImageLayout::GENERAL,
+                        vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                    );
+                }
+            })
+        })();
+        // immediate_submit waits for the gpu => nothing uses the views anymore
+        for view in storage_views {
+            device.destroy_image_view(view);
+        }
+        result?;
+
+        Ok(Environment {
+            environment_map: Arc::new(environment),
+            irradiance_map,
+            specular_map,
+            brdf_lut,
+            cube_sampler,
+            lut_sampler,
+        })
+    }
+
+    pub fn environment_map(&self) -> MaterialTexture {
+        MaterialTexture {
+            image: self.environment_map.clone(),
+            sampler: self.cube_sampler.clone(),
+        }
+    }
+
+    pub fn irradiance_map(&self) -> (vk::ImageView, vk::Sampler) {
+        (
+            self.irradiance_map.image_view(),
+            self.cube_sampler.sampler(),
+        )
+    }
+
+    pub fn specular_map(&self) -> (vk::ImageView, vk::Sampler) {
+        (self.specular_map.image_view(), self.cube_sampler.sampler())
+    }
+
+    pub fn brdf_lut(&self) -> (vk::ImageView, vk::Sampler) {
+        (self.brdf_lut.image_view(), self.lut_sampler.sampler())
+    }
+}
+
+// compute pipelines that are only needed while prefiltering
+// the shaders read binding 0 (sampler) and write binding 1 (storage image)
+// => one layout with the bindings of all of them
+struct Prefilter {
+    device: Arc<Device>,
+    layout: DescriptorSetLayout,
+    descriptor_allocator: DescriptorAllocator,
+    to_cube_pipeline: ComputePipeline,
+    irradiance_pipeline: ComputePipeline,
+    specular_pipeline: ComputePipeline,
+    brdf_pipeline: ComputePipeline,
+}
+
+impl Prefilter {
+    fn new(device: Arc<Device>, max_sets: u32) -> Result<Self, VulkanError> {
+        let shaders = [
+            ShaderModule::new(device.clone(), "shaders/equirect_to_cube_comp.spv")?,
+            ShaderModule::new(device.clone(), "shaders/irradiance_comp.spv")?,
+            ShaderModule::new(device.clone(), "shaders/prefilter_specular_comp.spv")?,
+            ShaderModule::new(device.clone(), "shaders/brdf_lut_comp.spv")?,
+        ];
+        let mut builder = DescriptorLayoutBuilder::new();
+        for shader in &shaders {
+            builder.add_shader_bindings(shader, 0, vk::ShaderStageFlags::COMPUTE);
+        }
+        let layout = builder.build(device.clone(), vk::DescriptorSetLayoutCreateFlags::empty())?;
+        let mut descriptor_allocator = DescriptorAllocator::new(device.clone());
+        descriptor_allocator.init_pool(
+            max_sets,
+            &[
+                PoolSizeRatio {
+                    descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                    ratio: 1.0,
+                },
+                PoolSizeRatio {
+                    descriptor_type: vk::DescriptorType::STORAGE_IMAGE,
+                    ratio: 1.0,
+                },
+            ],
+        )?;
+        let [to_cube_shader, irradiance_shader, specular_shader, brdf_shader] = shaders;
+        let pipeline = |shader| ComputePipeline::new(device.clone(), &[layout.layout()], shader);
+        let to_cube_pipeline = pipeline(to_cube_shader)?;
+        let irradiance_pipeline = pipeline(irradiance_shader)?;
+        let specular_pipeline = pipeline(specular_shader)?;
+        let brdf_pipeline = pipeline(brdf_shader)?;
+        Ok(Prefilter {
+            device,
+            layout,
+            descriptor_allocator,
+            to_cube_pipeline,
+            irradiance_pipeline,
+            specular_pipeline,
+            brdf_pipeline,
+        })
+    }
+
+    fn allocate_set(
+        &self,
+        input: (vk::ImageView, vk::Sampler),
+        output: vk::ImageView,
+    ) -> Result<vk::DescriptorSet, VulkanError> {
+        let set = self.descriptor_allocator.allocate(self.layout.layout())?;
+        let mut writer = DescriptorWriter::new();
+        writer.add_image(
+            0,
+            input.0,
+            input.1,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+        );
+        writer.add_storage_image(1, output);
+        writer.update_descriptor_set(&self.device, set);
+        Ok(set)
+    }
+
+    // one dispatch per cube face
+    // push constants: x = face size, y = face index, z = roughness
+    fn dispatch_faces(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        pipeline: &ComputePipeline,
+        set: vk::DescriptorSet,
+        size: u32,
+        roughness: f32,
+    ) {
+        for face in 0..6 {
+            pipeline.execute_compute_with_constants(
+                command_buffer,
+                &[set],
+                vk::Extent2D {
+                    width: size,
+                    height: size,
+                },
+                &PushConstants::new(
+                    glm::vec4(size as f32, face as f32, roughness, 0.0),
+                    glm::Vec4::zeros(),
+                    glm::Vec4::zeros(),
+                    glm::Vec4::zeros(),
+                ),
+            );
+        }
+    }
+}
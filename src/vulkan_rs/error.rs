@@ -0,0 +1,158 @@
+use ash::vk;
+use std::ffi::CString;
+
+#[derive(Debug)]
+pub enum VulkanError {
+    // vulkan library/driver could not be loaded
+    Loading(ash::LoadingError),
+    Vk(vk::Result),
+    Allocation(gpu_allocator::AllocationError),
+    MissingLayer(CString),
+    NoSuitableDevice,
+    UnsupportedDisplay,
+    WindowHandle(raw_window_handle::HandleError),
+    InvalidName(std::ffi::NulError),
+    ShaderFile { path: String, error: std::io::Error },
+    // glslc is missing or the glsl source is invalid
+    ShaderCompilation { path: String, message: String },
+    // spir-v that could be loaded but not understood by the reflection
+    ShaderReflection { path: String, message: String },
+    // every slot of the bindless texture table is in use
+    TextureTableFull,
+    // frame is the first one that didnt finish within the timeout, passes are "frame: pass"
+    // the device is still usable => the frame can be skipped
+    GpuHang { frame: u64, passes: Vec<String> },
+}
+
+impl std::fmt::Display for VulkanError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VulkanError::Loading(e) => write!(f, "Could not load Vulkan: {}", e),
+            VulkanError::Vk(e) => write!(f, "Vulkan call failed: {}", e),
+            VulkanError::Allocation(e) => write!(f, "GPU allocation failed: {}", e),
+            VulkanError::MissingLayer(layer) => {
+                write!(f, "Required layer not available: {:?}", layer)
+            }
+            VulkanError::NoSuitableDevice => write!(f, "No suitable GPU found"),
+            VulkanError::UnsupportedDisplay => write!(f, "Unsupported display handle"),
+            VulkanError::WindowHandle(e) => write!(f, "Window handle not available: {}", e),
+            VulkanError::InvalidName(e) => write!(f, "Invalid name: {}", e),
+            VulkanError::ShaderFile { path, error } => {
+                write!(f, "Could not read shader {}: {}", path, error)
+            }
+            VulkanError::ShaderCompilation { path, message } => {
+                write!(f, "Could not compile shader {}: {}", path, message)
+            }
+            VulkanError::ShaderReflection { path, message } => {
+                write!(f, "Could not reflect shader {}: {}", path, message)
+            }
+            VulkanError::TextureTableFull => write!(f, "Bindless texture table is full"),
+            VulkanError::GpuHang { frame, passes } => write!(
+                f,
+                "GPU hangs in frame {} (in flight: {})",
+                frame,
+                passes.join(", ")
+            ),
+        }
+    }
+}
+
+impl VulkanError {
+    // the renderer is still in a consistent state => drawing can be retried on the next frame
+    pub fn is_recoverable(&self) -> bool {
+        matches!(self, VulkanError::GpuHang { .. })
+    }
+}
+
+impl std::error::Error for VulkanError {}
+
+impl From<vk::Result> for VulkanError {
+    fn from(e: vk::Result) -> Self {
+        VulkanError::Vk(e)
+    }
+}
+
+impl From<ash::LoadingError> for VulkanError {
+    fn from(e: ash::LoadingError) -> Self {
+        VulkanError::Loading(e)
+    }
+}
+
+impl From<gpu_allocator::AllocationError> for VulkanError {
+    fn from(e: gpu_allocator::AllocationError) -> Self {
+        VulkanError::Allocation(e)
+    }
+}
+
+impl From<raw_window_handle::HandleError> for VulkanError {
+    fn from(e: raw_window_handle::HandleError) -> Self {
+        VulkanError::WindowHandle(e)
+    }
+}
+
+impl From<std::ffi::NulError> for VulkanError {
+    fn from(e: std::ffi::NulError) -> Self {
+        VulkanError::InvalidName(e)
+    }
+}
+
+// loading assets can fail because of the file itself or because of the upload to the gpu
+#[derive(Debug)]
+pub enum AssetError {
+    Gltf(gltf::Error),
+    Image(image::ImageError),
+    Ktx2(ktx2::ParseError),
+    Io(std::io::Error),
+    // valid file, but the engine or the gpu cant use it
+    UnsupportedTexture(String),
+    UnsupportedMesh(String),
+    Vulkan(VulkanError),
+}
+
+impl std::fmt::Display for AssetError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AssetError::Gltf(e) => write!(f, "Could not load glTF: {}", e),
+            AssetError::Image(e) => write!(f, "Could not load image: {}", e),
+            AssetError::Ktx2(e) => write!(f, "Could not parse KTX2 file: {}", e),
+            AssetError::Io(e) => write!(f, "Could not read asset: {}", e),
+            AssetError::UnsupportedTexture(reason) => {
+                write!(f, "Unsupported texture: {}", reason)
+            }
+            AssetError::UnsupportedMesh(reason) => write!(f, "Unsupported mesh: {}", reason),
+            AssetError::Vulkan(e) => write!(f, "Could not upload asset: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for AssetError {}
+
+impl From<gltf::Error> for AssetError {
+    fn from(e: gltf::Error) -> Self {
+        AssetError::Gltf(e)
+    }
+}
+
+impl From<image::ImageError> for AssetError {
+    fn from(e: image::ImageError) -> Self {
+        AssetError::Image(e)
+    }
+}
+
+impl From<ktx2::ParseError> for AssetError {
+    fn from(e: ktx2::ParseError) -> Self {
+        AssetError::Ktx2(e)
+    }
+}
+
+impl From<std::io::Error> for AssetError {
+    fn from(e: std::io::Error) -> Self {
+        AssetError::Io(e)
+    }
+}
+
+impl From<VulkanError> for AssetError {
+    fn from(e: VulkanError) -> Self {
+        AssetError::Vulkan(e)
+    }
+}
@@ -0,0 +1,179 @@
+use super::device::Device;
+use super::error::VulkanError;
+use ash::vk;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+
+// two timestamps per scope
+const MAX_SCOPES_PER_FRAME: u32 = 32;
+
+#[derive(Debug, Clone, Copy)]
+pub struct PassTiming {
+    pub name: &'static str,
+    pub duration: Duration,
+}
+
+struct Scope {
+    name: &'static str,
+    // the end timestamp is the query after it
+    first_query: u32,
+}
+
+// measures gpu time with timestamp queries, one range of queries per frame slot
+// the timestamps of a slot are read when the slot comes around again => results lag behind by
+// the number of frames in flight but reading them never stalls
+pub struct GpuProfiler {
+    device: Arc<Device>,
+    // None => the graphics queue does not support timestamps, scopes do nothing
+    query_pool: Option<vk::QueryPool>,
+    // nanoseconds per tick
+    timestamp_period: f64,
+    timestamp_mask: u64,
+    // per frame slot, a mutex so that scopes can be opened from the render graph closures
+    scopes: Vec<Mutex<Vec<Scope>>>,
+    current_slot: usize,
+    frame_timings: Vec<PassTiming>,
+}
+
+impl GpuProfiler {
+    pub fn new(device: Arc<Device>, frame_count: usize) -> Result<Self, VulkanError> {
+        let valid_bits = device.graphics_timestamp_valid_bits();
+        let query_pool = if valid_bits == 0 {
+            log::warn!("Graphics queue does not support timestamps, gpu profiling is disabled");
+            None
+        } else {
+            let query_pool = device.create_query_pool(
+                vk::QueryType::TIMESTAMP,
+                frame_count as u32 * MAX_SCOPES_PER_FRAME * 2,
+            )?;
+            device.set_object_name(query_pool, "gpu profiler queries");
+            Some(query_pool)
+        };
+        let timestamp_mask = if valid_bits >= 64 {
+            u64::MAX
+        } else {
+            (1 << valid_bits) - 1
+        };
+        Ok(GpuProfiler {
+            timestamp_period: device.properties().limits.timestamp_period as f64,
+            device,
+            query_pool,
+            timestamp_mask,
+            scopes: (0..frame_count).map(|_| Mutex::new(Vec::new())).collect(),
+            current_slot: 0,
+            frame_timings: Vec::new(),
+        })
+    }
+
+    // the slot has to be finished on the gpu, has to be recorded before any scope of the frame
+    pub fn begin_frame(&mut self, command_buffer: vk::CommandBuffer, frame_slot: usize) {
+        let Some(query_pool) = self.query_pool else {
+            return;
+        };
+        self.current_slot = frame_slot;
+        let first_query = frame_slot as u32 * MAX_SCOPES_PER_FRAME * 2;
+        let scopes = std::mem::take(&mut *self.scopes[frame_slot].lock().unwrap());
+        if !scopes.is_empty() {
+            let mut results = vec![[0u64; 2]; scopes.len() * 2];
+            match self.device.get_query_results_with_availability(
+                query_pool,
+                first_query,
+                &mut results,
+            ) {
+                Ok(()) => self.resolve(&scopes, &results, first_query),
+                Err(e) => log::warn!("Could not read gpu timestamps: {}", e),
+            }
+        }
+        self.device.cmd_reset_query_pool(
+            command_buffer,
+            query_pool,
+            first_query,
+            MAX_SCOPES_PER_FRAME * 2,
+        );
+    }
+
+    fn resolve(&mut self, scopes: &[Scope], results: &[[u64; 2]], first_query: u32) {
+        self.frame_timings.clear();
+        for scope in scopes {
+            let idx = (scope.first_query - first_query) as usize;
+            let ([start, start_available], [end, end_available]) = (results[idx], results[idx + 1]);
+            // the frame was never submitted (error while recording)
+            if start_available == 0 || end_available == 0 {
+                continue;
+            }
+            let ticks = end.wrapping_sub(start) & self.timestamp_mask;
+            self.frame_timings.push(PassTiming {
+                name: scope.name,
+                duration: Duration::from_nanos((ticks as f64 * self.timestamp_period) as u64),
+            });
+        }
+    }
+
+    // the scope ends when the guard is dropped, has to be in the same command buffer
+    pub fn scope(&self, command_buffer: vk::CommandBuffer, name: &'static str) -> GpuScope<'_> {
+        let Some(query_pool) = self.query_pool else {
+            return GpuScope {
+                profiler: self,
+                command_buffer,
+                end_query: None,
+            };
+        };
+        let mut scopes = self.scopes[self.current_slot].lock().unwrap();
+        if scopes.len() as u32 >= MAX_SCOPES_PER_FRAME {
+            log::warn!("Too many gpu profiler scopes, {} is not measured", name);
+            return GpuScope {
+                profiler: self,
+                command_buffer,
+                end_query: None,
+            };
+        }
+        let first_query =
+            (self.current_slot as u32 * MAX_SCOPES_PER_FRAME + scopes.len() as u32) * 2;
+        scopes.push(Scope { name, first_query });
+        self.device.cmd_write_timestamp(
+            command_buffer,
+            vk::PipelineStageFlags2::TOP_OF_PIPE,
+            query_pool,
+            first_query,
+        );
+        GpuScope {
+            profiler: self,
+            command_buffer,
+            end_query: Some(first_query + 1),
+        }
+    }
+
+    // gpu time of the scopes of a previous frame, in the order they were opened
+    pub fn frame_timings(&self) -> &[PassTiming] {
+        &self.frame_timings
+    }
+}
+
+impl Drop for GpuProfiler {
+    fn drop(&mut self) {
+        if let Some(query_pool) = self.query_pool {
+            self.device.destroy_query_pool(query_pool);
+        }
+    }
+}
+
+pub struct GpuScope<'a> {
+    profiler: &'a GpuProfiler,
+    command_buffer: vk::CommandBuffer,
+    // None => not measured
+    end_query: Option<u32>,
+}
+
+impl Drop for GpuScope<'_> {
+    fn drop(&mut self) {
+        if let (Some(query_pool), Some(end_query)) = (self.profiler.query_pool, self.end_query) {
+            self.profiler.device.cmd_write_timestamp(
+                self.command_buffer,
+                vk::PipelineStageFlags2::BOTTOM_OF_PIPE,
+                query_pool,
+                end_query,
+            );
+        }
+    }
+}
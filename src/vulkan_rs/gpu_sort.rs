@@ -0,0 +1,76 @@
+use super::device::Device;
+use super::error::VulkanError;
+use super::pipelines::ComputePipeline;
+use super::render_graph::BufferHandle;
+use super::render_graph::BufferUsage;
+use super::render_graph::GraphPass;
+use super::render_graph::RenderGraph;
+use super::shader::ShaderModule;
+use ash::vk;
+use std::sync::Arc;
+
+// has to match local_size_x in sort.comp
+pub const GROUP_SIZE: u32 = 256;
+
+// matches the push constants of sort.comp
+#[repr(C)]
+#[derive(bytemuck::NoUninit, Copy, Clone, Debug)]
+struct GPUSortPushConstants {
+    key_values: vk::DeviceAddress,
+    count: u32,
+    block_size: u32,
+    distance: u32,
+    _padding: u32,
+}
+
+// bitonic sort of (key, value) u32 pairs on the gpu, ascending by key
+// the pairs are read through their device address => works on any buffer with
+// SHADER_DEVICE_ADDRESS, nothing has to be read back to sort e.g. draws by distance
+// only the transparent draws of DrawBatches are sorted with it for now
+pub struct GpuSort {
+    pipeline: ComputePipeline,
+}
+
+impl GpuSort {
+    pub fn new(device: Arc<Device>) -> Result<Self, VulkanError> {
+        let shader = ShaderModule::new(device.clone(), "shaders/sort_comp.spv")?;
+        let pipeline = ComputePipeline::new(device, &[], shader)?;
+        Ok(GpuSort { pipeline })
+    }
+
+    // count has to be a power of two => pad with u32::MAX keys, they end up at the end
+    // one pass per step => the graph puts the barriers between the steps
+    pub fn add_passes<'a>(
+        &'a self,
+        graph: &mut RenderGraph<'a>,
+        key_values: BufferHandle,
+        key_values_address: vk::DeviceAddress,
+        count: u32,
+    ) {
+        debug_assert!(count.is_power_of_two());
+        let pipeline = &self.pipeline;
+        let group_counts = [count.div_ceil(GROUP_SIZE), 1, 1];
+        let mut block_size = 2;
+        while block_size <= count {
+            let mut distance = block_size / 2;
+            while distance > 0 {
+                let push_constants = GPUSortPushConstants {
+                    key_values: key_values_address,
+                    count,
+                    block_size,
+                    distance,
+                    _padding: 0,
+                };
+                graph.add_pass(
+                    GraphPass::new("sort")
+                        .buffer(key_values, BufferUsage::StorageWrite)
+                        .record(move |command_buffer| {
+                            pipeline.dispatch(command_buffer, &[], group_counts, &push_constants);
+                        }),
+                );
+                distance /= 2;
+            }
+            block_size *= 2;
+        }
+    }
+}
@@ -0,0 +1,243 @@
+use super::allocation::AllocatedBuffer;
+use super::allocation::AllocatedImage;
+use super::allocation::Allocator;
+use super::descriptor::DescriptorAllocator;
+use super::descriptor::DescriptorLayoutBuilder;
+use super::descriptor::DescriptorSetLayout;
+use super::descriptor::DescriptorWriter;
+use super::descriptor::PoolSizeRatio;
+use super::device::Device;
+use super::error::VulkanError;
+use super::pipelines::ComputePipeline;
+use super::pipelines::PushConstants;
+use super::render_graph::BufferUsage;
+use super::render_graph::GraphPass;
+use super::render_graph::ImageHandle;
+use super::render_graph::ImageUsage;
+use super::render_graph::RenderGraph;
+use super::shader::ShaderModule;
+use ash::vk;
+use nalgebra_glm as glm;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+// has to match BIN_COUNT in histogram.comp
+pub const HISTOGRAM_BINS: usize = 64;
+// luminance, red, green, blue
+const BINS_SIZE: vk::DeviceSize = (HISTOGRAM_BINS * 4 * std::mem::size_of::<u32>()) as u64;
+
+// pixel counts of the sRGB encoded final image => what ends up on the screen
+// the last bin also counts everything that is clipped
+#[derive(Debug, Clone)]
+pub struct ColorHistogram {
+    pub luminance: [u32; HISTOGRAM_BINS],
+    pub red: [u32; HISTOGRAM_BINS],
+    pub green: [u32; HISTOGRAM_BINS],
+    pub blue: [u32; HISTOGRAM_BINS],
+    pub pixel_count: u32,
+}
+
+// histogram of the draw image computed in a compute pass, only the bins are read back
+// a slot is read when it comes around again => the result lags behind by the frames in flight
+// but reading it never stalls
+pub struct Histogram {
+    device: Arc<Device>,
+    enabled: bool,
+    bins_buffer: AllocatedBuffer,
+    // one readback buffer per frame in flight => we only read a buffer after its frame finished
+    readback_buffers: Vec<AllocatedBuffer>,
+    // pixel count of the histogram that was copied into the readback buffer
+    pending_readbacks: Vec<Option<u32>>,
+    _descriptor_allocator: DescriptorAllocator,
+    _descriptor_layout: DescriptorSetLayout,
+    descriptor: vk::DescriptorSet,
+    pipeline: ComputePipeline,
+    latest: Option<ColorHistogram>,
+}
+
+impl Histogram {
+    pub fn new(
+        device: Arc<Device>,
+        allocator: Arc<Mutex<Allocator>>,
+        draw_image: &AllocatedImage,
+        frame_count: usize,
+    ) -> Result<Self, VulkanError> {
+        let bins_buffer = AllocatedBuffer::new(
+            device.clone(),
+            allocator.clone(),
+            "Histogram Bins",
+            vk::BufferUsageFlags::STORAGE_BUFFER
+                | vk::BufferUsageFlags::TRANSFER_SRC
+                | vk::BufferUsageFlags::TRANSFER_DST,
+            BINS_SIZE,
+            gpu_allocator::MemoryLocation::GpuOnly,
+        )?;
+        let readback_buffers = (0..frame_count)
+            .map(|_| {
+                AllocatedBuffer::new(
+                    device.clone(),
+                    allocator.clone(),
+                    "Histogram Readback Buffer",
+                    vk::BufferUsageFlags::TRANSFER_DST,
+                    BINS_SIZE,
+                    gpu_allocator::MemoryLocation::GpuToCpu,
+                )
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut builder = DescriptorLayoutBuilder::new();
+        builder.add_binding(
+            0,
+            vk::DescriptorType::STORAGE_IMAGE,
+            vk::ShaderStageFlags::COMPUTE,
+        );
+        builder.add_binding(
+            1,
+            vk::DescriptorType::STORAGE_BUFFER,
+            vk::ShaderStageFlags::COMPUTE,
+        );
+        let descriptor_layout =
+            builder.build(device.clone(), vk::DescriptorSetLayoutCreateFlags::empty())?;
+
+        let mut descriptor_allocator = DescriptorAllocator::new(device.clone());
+        descriptor_allocator.init_pool(
+            1,
+            &[
+                PoolSizeRatio {
+                    descriptor_type: vk::DescriptorType::STORAGE_IMAGE,
+                    ratio: 1.0,
+                },
+                PoolSizeRatio {
+                    descriptor_type: vk::DescriptorType::STORAGE_BUFFER,
+                    ratio: 1.0,
+                },
+            ],
+        )?;
+        let descriptor = descriptor_allocator.allocate(descriptor_layout.layout())?;
+        let mut writer = DescriptorWriter::new();
+        writer.add_storage_image(0, draw_image.image_view());
+        writer.add_buffer(
+            1,
+            bins_buffer.buffer(),
+            BINS_SIZE,
+            0,
+            vk::DescriptorType::STORAGE_BUFFER,
+        );
+        writer.update_descriptor_set(&device, descriptor);
+
+        let shader = ShaderModule::new(device.clone(), "shaders/histogram_comp.spv")?;
+        let pipeline = ComputePipeline::new(device.clone(), &[descriptor_layout.layout()], shader)?;
+
+        Ok(Histogram {
+            device,
+            enabled: false,
+            bins_buffer,
+            readback_buffers,
+            pending_readbacks: vec![None; frame_count],
+            _descriptor_allocator: descriptor_allocator,
+            _descriptor_layout: descriptor_layout,
+            descriptor,
+            pipeline,
+            latest: None,
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    // disabled => no passes are added and the old result is dropped
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.latest = None;
+        }
+    }
+
+    // None while disabled or until the first frame was read back
+    pub fn latest(&self) -> Option<&ColorHistogram> {
+        self.latest.as_ref()
+    }
+
+    // has to be called after the frame slot was waited on
+    pub fn collect(&mut self, frame_slot: usize) {
+        let Some(pixel_count) = self.pending_readbacks[frame_slot].take() else {
+            return;
+        };
+        if !self.enabled {
+            return;
+        }
+        let bins: &[u32] = bytemuck::cast_slice(self.readback_buffers[frame_slot].mapped_bytes());
+        let channel = |idx: usize| {
+            let mut channel = [0; HISTOGRAM_BINS];
+            channel.copy_from_slice(&bins[idx * HISTOGRAM_BINS..(idx + 1) * HISTOGRAM_BINS]);
+            channel
+        };
+        self.latest = Some(ColorHistogram {
+            luminance: channel(0),
+            red: channel(1),
+            green: channel(2),
+            blue: channel(3),
+            pixel_count,
+        });
+    }
+
+    // counts the pixels of image inside extent, image has to be the draw image
+    pub fn add_passes<'a>(
+        &'a mut self,
+        graph: &mut RenderGraph<'a>,
+        image: ImageHandle,
+        frame_slot: usize,
+        extent: vk::Extent2D,
+    ) {
+        self.pending_readbacks[frame_slot] = Some(extent.width * extent.height);
+        let histogram = &*self;
+        let device = &histogram.device;
+        let bins_buffer = histogram.bins_buffer.buffer();
+        let bins = graph.import_buffer("histogram bins", bins_buffer);
+        graph.add_pass(
+            GraphPass::new("histogram clear")
+                .buffer(bins, BufferUsage::TransferDst)
+                .record(move |command_buffer| {
+                    device.cmd_fill_buffer(command_buffer, bins_buffer, 0, BINS_SIZE, 0);
+                }),
+        );
+        let push_constants = PushConstants::new(
+            glm::vec4(extent.width as f32, extent.height as f32, 0.0, 0.0),
+            glm::Vec4::zeros(),
+            glm::Vec4::zeros(),
+            glm::Vec4::zeros(),
+        );
+        graph.add_pass(
+            GraphPass::new("histogram")
+                .image(image, ImageUsage::StorageRead)
+                .buffer(bins, BufferUsage::StorageWrite)
+                .record(move |command_buffer| {
+                    histogram.pipeline.execute_compute_with_constants(
+                        command_buffer,
+                        &[histogram.descriptor],
+                        extent,
+                        &push_constants,
+                    );
+                }),
+        );
+        let readback_buffer = histogram.readback_buffers[frame_slot].buffer();
+        graph.add_pass(
+            GraphPass::new("histogram readback")
+                .buffer(bins, BufferUsage::TransferSrc)
+                .side_effects()
+                .record(move |command_buffer| {
+                    device.cmd_copy_buffer(
+                        command_buffer,
+                        bins_buffer,
+                        readback_buffer,
+                        &[vk::BufferCopy {
+                            src_offset: 0,
+                            dst_offset: 0,
+                            size: BINS_SIZE,
+                        }],
+                    );
+                }),
+        );
+    }
+}
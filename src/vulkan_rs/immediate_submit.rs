@@ -1,4 +1,5 @@
 use super::device::Device;
+use super::error::VulkanError;
 use ash::vk;
 use std::sync::Arc;
 
@@ -10,30 +11,42 @@ pub struct ImmediateCommandData {
 }
 
 impl ImmediateCommandData {
-    pub fn new(device: Arc<Device>) -> Self {
-        let command_pool = device.create_command_pool();
-        let command_buffer = device.create_command_buffer(command_pool);
-        let fence = device.create_fence(vk::FenceCreateFlags::SIGNALED);
-        Self {
+    pub fn new(device: Arc<Device>) -> Result<Self, VulkanError> {
+        let command_pool = device.create_command_pool()?;
+        let command_buffer = match device.create_command_buffer(command_pool) {
+            Ok(command_buffer) => command_buffer,
+            Err(e) => {
+                device.destroy_command_pool(command_pool);
+                return Err(e);
+            }
+        };
+        let fence = match device.create_fence(vk::FenceCreateFlags::SIGNALED) {
+            Ok(fence) => fence,
+            Err(e) => {
+                device.destroy_command_pool(command_pool);
+                return Err(e);
+            }
+        };
+        Ok(Self {
             device,
             command_pool,
             command_buffer,
             fence,
-        }
+        })
     }
 
-    pub fn immediate_submit<F>(&self, commands: F)
+    pub fn immediate_submit<F>(&self, commands: F) -> Result<(), VulkanError>
     where
         F: FnOnce(&Device, vk::CommandBuffer),
     {
-        self.device.reset_fence(&self.fence);
-        self.device.reset_command_buffer(self.command_buffer);
+        self.device.reset_fence(&self.fence)?;
+        self.device.reset_command_buffer(self.command_buffer)?;
         self.device.begin_command_buffer(
             self.command_buffer,
             vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT,
-        );
+        )?;
         commands(&self.device, self.command_buffer);
-        self.device.end_command_buffer(self.command_buffer);
+        self.device.end_command_buffer(self.command_buffer)?;
         let submit_info = vk::SubmitInfo2 {
             s_type: vk::StructureType::SUBMIT_INFO_2,
             p_next: std::ptr::null(),
@@ -47,8 +60,8 @@ impl ImmediateCommandData {
             ..Default::default()
         };
         self.device
-            .submit_to_graphics_queue(submit_info, self.fence);
-        self.device.wait_for_fence(&self.fence, u64::MAX);
+            .submit_to_graphics_queue(submit_info, self.fence)?;
+        self.device.wait_for_fence(&self.fence, u64::MAX)
     }
 }
 
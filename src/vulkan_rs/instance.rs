@@ -1,4 +1,6 @@
 use super::device::DeviceFeatures;
+use super::device::RayTracingFeatures;
+use super::error::VulkanError;
 use super::window::Surface;
 use ash::ext::debug_utils;
 use ash::khr::{android_surface, wayland_surface, win32_surface, xcb_surface, xlib_surface};
@@ -9,12 +11,15 @@ use gpu_allocator::AllocatorDebugSettings;
 use raw_window_handle::RawDisplayHandle;
 use raw_window_handle::RawWindowHandle;
 use std::ffi::c_char;
+use std::ffi::CStr;
 use std::ffi::CString;
 use std::sync::Arc;
 
 pub struct Instance {
     entry: ash::Entry,
     handle: ash::Instance,
+    // required + the optional ones that are available
+    enabled_extensions: Vec<CString>,
 }
 
 #[derive(Copy, Clone)]
@@ -30,12 +35,8 @@ impl Version {
     }
 }
 
-fn get_available_instance_layers(entry: &ash::Entry) -> Vec<CString> {
-    let layer_properties = unsafe {
-        entry
-            .enumerate_instance_layer_properties()
-            .expect("Device should not run out of memory this early already")
-    };
+fn get_available_instance_layers(entry: &ash::Entry) -> Result<Vec<CString>, VulkanError> {
+    let layer_properties = unsafe { entry.enumerate_instance_layer_properties()? };
     let instance_layers: Vec<CString> = layer_properties
         .iter()
         .map(|prop| {
@@ -53,18 +54,29 @@ fn get_available_instance_layers(entry: &ash::Entry) -> Vec<CString> {
     }
     log::debug!("==================");
 
-    instance_layers
+    Ok(instance_layers)
 }
 
-fn check_instance_layer_support(entry: &ash::Entry, required_layers: &[CString]) -> bool {
-    let available_layers = get_available_instance_layers(entry);
+fn get_available_instance_extensions(entry: &ash::Entry) -> Result<Vec<CString>, VulkanError> {
+    let extension_properties = unsafe { entry.enumerate_instance_extension_properties(None)? };
+    Ok(extension_properties
+        .iter()
+        .filter_map(|prop| prop.extension_name_as_c_str().ok())
+        .map(CString::from)
+        .collect())
+}
+
+fn check_instance_layer_support(
+    entry: &ash::Entry,
+    required_layers: &[CString],
+) -> Result<(), VulkanError> {
+    let available_layers = get_available_instance_layers(entry)?;
     for required_layer in required_layers.iter() {
         if !available_layers.contains(required_layer) {
-            log::error!("Required layer not available: {:?}", required_layer);
-            return false;
+            return Err(VulkanError::MissingLayer(required_layer.clone()));
         }
     }
-    true
+    Ok(())
 }
 
 pub struct AppInfo {
@@ -84,16 +96,26 @@ impl Instance {
         engine_info: EngineInfo,
         required_layers: &[CString],
         required_extensions: &[CString],
+        // only enabled if available, check with is_extension_enabled
+        optional_extensions: &[CString],
         debug_messenger_create_info: Option<vk::DebugUtilsMessengerCreateInfoEXT>,
-    ) -> Arc<Instance> {
-        let entry = unsafe { ash::Entry::load().expect("Vulkan Drivers should be installed.") };
-
-        if !check_instance_layer_support(&entry, required_layers) {
-            panic!("Required layers are not available!");
+    ) -> Result<Arc<Instance>, VulkanError> {
+        let entry = unsafe { ash::Entry::load()? };
+
+        check_instance_layer_support(&entry, required_layers)?;
+        let mut enabled_extensions = required_extensions.to_vec();
+        if !optional_extensions.is_empty() {
+            let available_extensions = get_available_instance_extensions(&entry)?;
+            for extension in optional_extensions {
+                if available_extensions.contains(extension) {
+                    enabled_extensions.push(extension.clone());
+                } else {
+                    log::info!("Optional instance extension {:?} not available", extension);
+                }
+            }
         }
-        let app_name = CString::new(app_info.name).expect("String should not contain null byte");
-        let engine_name =
-            CString::new(engine_info.name).expect("String should not contain null byte");
+        let app_name = CString::new(app_info.name)?;
+        let engine_name = CString::new(engine_info.name)?;
         let app_version = vk::make_api_version(
             0,
             app_info.version.major,
@@ -123,8 +145,8 @@ impl Instance {
             ..Default::default()
         };
 
-        let required_extensions_raw: Vec<*const c_char> =
-            required_extensions.iter().map(|ext| ext.as_ptr()).collect();
+        let enabled_extensions_raw: Vec<*const c_char> =
+            enabled_extensions.iter().map(|ext| ext.as_ptr()).collect();
         let required_layers_raw: Vec<*const c_char> =
             required_layers.iter().map(|layer| layer.as_ptr()).collect();
         let p_next = match debug_messenger_create_info {
@@ -138,31 +160,30 @@ impl Instance {
         let instance_info = vk::InstanceCreateInfo {
             s_type: vk::StructureType::INSTANCE_CREATE_INFO,
             p_application_info: &app_info,
-            enabled_extension_count: required_extensions_raw.len() as u32,
-            pp_enabled_extension_names: required_extensions_raw.as_ptr(),
+            enabled_extension_count: enabled_extensions_raw.len() as u32,
+            pp_enabled_extension_names: enabled_extensions_raw.as_ptr(),
             p_next,
             enabled_layer_count: required_layers_raw.len() as u32,
             pp_enabled_layer_names: required_layers_raw.as_ptr(),
             ..Default::default()
         };
         log::debug!("Creating instance!");
-        let instance = unsafe {
-            entry
-                .create_instance(&instance_info, None)
-                .expect("Extensions should be supported. Layer might not be installed, but this is only relevant for devs.")
-        };
-        Arc::new(Instance {
+        let instance = unsafe { entry.create_instance(&instance_info, None)? };
+        Ok(Arc::new(Instance {
             entry,
             handle: instance,
-        })
+            enabled_extensions,
+        }))
     }
 
-    pub fn enumerate_physical_devices(&self) -> Vec<vk::PhysicalDevice> {
-        unsafe {
-            self.handle
-                .enumerate_physical_devices()
-                .expect("We hopefully have enough memory to handle this call")
-        }
+    pub fn is_extension_enabled(&self, name: &CStr) -> bool {
+        self.enabled_extensions
+            .iter()
+            .any(|extension| extension.as_c_str() == name)
+    }
+
+    pub fn enumerate_physical_devices(&self) -> Result<Vec<vk::PhysicalDevice>, VulkanError> {
+        Ok(unsafe { self.handle.enumerate_physical_devices()? })
     }
 
     pub fn get_physical_device_properties(
@@ -172,6 +193,27 @@ impl Instance {
         unsafe { self.handle.get_physical_device_properties(physical_device) }
     }
 
+    pub fn get_physical_device_memory_properties(
+        &self,
+        physical_device: vk::PhysicalDevice,
+    ) -> vk::PhysicalDeviceMemoryProperties {
+        unsafe {
+            self.handle
+                .get_physical_device_memory_properties(physical_device)
+        }
+    }
+
+    pub fn get_physical_device_format_properties(
+        &self,
+        physical_device: vk::PhysicalDevice,
+        format: vk::Format,
+    ) -> vk::FormatProperties {
+        unsafe {
+            self.handle
+                .get_physical_device_format_properties(physical_device, format)
+        }
+    }
+
     pub fn get_physical_device_queue_family_properties(
         &self,
         physical_device: &vk::PhysicalDevice,
@@ -185,12 +227,11 @@ impl Instance {
     pub fn enumerate_device_extension_properties(
         &self,
         physical_device: vk::PhysicalDevice,
-    ) -> Vec<vk::ExtensionProperties> {
-        unsafe {
+    ) -> Result<Vec<vk::ExtensionProperties>, VulkanError> {
+        Ok(unsafe {
             self.handle
-                .enumerate_device_extension_properties(physical_device)
-                .expect("We hopefully have enough memory to handle this call")
-        }
+                .enumerate_device_extension_properties(physical_device)?
+        })
     }
 
     pub fn get_supported_features<'a>(&self, device: &vk::PhysicalDevice) -> DeviceFeatures<'a> {
@@ -226,58 +267,202 @@ impl Instance {
             vulkan11_features: vulkan11_feats,
             vulkan12_features: vulkan12_feats,
             vulkan13_features: vulkan13_feats,
-            base_features: device_features,
+            // filled by the query, device_features is only the copy we passed in
+            base_features: feature2.features,
+        }
+    }
+
+    // the structs of unsupported extensions are not allowed in the chain
+    // => only the ones whose extension the device has are queried, the others stay false
+    pub fn get_ray_tracing_features(
+        &self,
+        device: vk::PhysicalDevice,
+    ) -> Result<RayTracingFeatures, VulkanError> {
+        let supported_extensions = self.enumerate_device_extension_properties(device)?;
+        let has_extension = |name: &CStr| {
+            supported_extensions
+                .iter()
+                .any(|prop| prop.extension_name_as_c_str() == Ok(name))
+        };
+        let mut ray_tracing_pipeline_feats = vk::PhysicalDeviceRayTracingPipelineFeaturesKHR {
+            s_type: vk::StructureType::PHYSICAL_DEVICE_RAY_TRACING_PIPELINE_FEATURES_KHR,
+            ..Default::default()
+        };
+        let mut ray_query_feats = vk::PhysicalDeviceRayQueryFeaturesKHR {
+            s_type: vk::StructureType::PHYSICAL_DEVICE_RAY_QUERY_FEATURES_KHR,
+            ..Default::default()
+        };
+        let mut acceleration_structure_feats = vk::PhysicalDeviceAccelerationStructureFeaturesKHR {
+            s_type: vk::StructureType::PHYSICAL_DEVICE_ACCELERATION_STRUCTURE_FEATURES_KHR,
+            ..Default::default()
+        };
+        let mut features_chain: *mut std::ffi::c_void = std::ptr::null_mut();
+        if has_extension(ash::khr::ray_tracing_pipeline::NAME) {
+            ray_tracing_pipeline_feats.p_next = features_chain;
+            features_chain = &mut ray_tracing_pipeline_feats as *mut _ as *mut std::ffi::c_void;
         }
+        if has_extension(ash::khr::ray_query::NAME) {
+            ray_query_feats.p_next = features_chain;
+            features_chain = &mut ray_query_feats as *mut _ as *mut std::ffi::c_void;
+        }
+        if has_extension(ash::khr::acceleration_structure::NAME) {
+            acceleration_structure_feats.p_next = features_chain;
+            features_chain = &mut acceleration_structure_feats as *mut _ as *mut std::ffi::c_void;
+        }
+        let mut feature2 = vk::PhysicalDeviceFeatures2 {
+            s_type: vk::StructureType::PHYSICAL_DEVICE_FEATURES_2,
+            p_next: features_chain,
+            ..Default::default()
+        };
+        unsafe {
+            self.handle
+                .get_physical_device_features2(device, &mut feature2)
+        };
+        // both ways of tracing rays need the acceleration structures
+        let acceleration_structure = has_extension(ash::khr::acceleration_structure::NAME)
+            && has_extension(ash::khr::deferred_host_operations::NAME)
+            && acceleration_structure_feats.acceleration_structure == vk::TRUE;
+        Ok(RayTracingFeatures {
+            acceleration_structure,
+            ray_tracing_pipeline: acceleration_structure
+                && has_extension(ash::khr::ray_tracing_pipeline::NAME)
+                && ray_tracing_pipeline_feats.ray_tracing_pipeline == vk::TRUE,
+            ray_query: acceleration_structure
+                && has_extension(ash::khr::ray_query::NAME)
+                && ray_query_feats.ray_query == vk::TRUE,
+        })
+    }
+
+    // handle sizes and alignments of the shader binding table
+    pub fn get_ray_tracing_pipeline_properties(
+        &self,
+        device: vk::PhysicalDevice,
+    ) -> vk::PhysicalDeviceRayTracingPipelinePropertiesKHR<'static> {
+        let mut ray_tracing_props = vk::PhysicalDeviceRayTracingPipelinePropertiesKHR {
+            s_type: vk::StructureType::PHYSICAL_DEVICE_RAY_TRACING_PIPELINE_PROPERTIES_KHR,
+            ..Default::default()
+        };
+        let mut properties2 = vk::PhysicalDeviceProperties2 {
+            s_type: vk::StructureType::PHYSICAL_DEVICE_PROPERTIES_2,
+            p_next: &mut ray_tracing_props as *mut _ as *mut std::ffi::c_void,
+            ..Default::default()
+        };
+        unsafe {
+            self.handle
+                .get_physical_device_properties2(device, &mut properties2)
+        };
+        ray_tracing_props
     }
 
     pub fn create_logical_device(
         &self,
         device: &vk::PhysicalDevice,
         device_create_info: &vk::DeviceCreateInfo,
-    ) -> ash::Device {
-        unsafe {
+    ) -> Result<ash::Device, VulkanError> {
+        // features and extensions are checked during the device suitability test
+        Ok(unsafe {
             self.handle
-                .create_device(*device, device_create_info, None)
-                .expect("Device should hopefully not be out of memory already. Features and Extensions should be supported (checked during device suitability test)!")
-        }
+                .create_device(*device, device_create_info, None)?
+        })
     }
 
+    // graphics and present from the same family if possible => the swapchain images can use
+    // EXCLUSIVE sharing mode
+    // compute and transfer prefer families without graphics (async compute, dma engines) and
+    // fall back to the graphics family
     pub fn find_queue_families(
         &self,
         device: &vk::PhysicalDevice,
         surface: &Surface,
-    ) -> QueueFamilyIndices {
+    ) -> Result<QueueFamilyIndices, VulkanError> {
         let queue_family_properties = self.get_physical_device_queue_family_properties(device);
+        let mut present_support = Vec::with_capacity(queue_family_properties.len());
+        for idx in 0..queue_family_properties.len() {
+            present_support.push(surface.get_physical_device_surface_support(device, idx as u32)?);
+        }
+        let find_family = |required: vk::QueueFlags, excluded: vk::QueueFlags| {
+            queue_family_properties
+                .iter()
+                .position(|properties| {
+                    properties.queue_count > 0
+                        && properties.queue_flags.contains(required)
+                        && !properties.queue_flags.intersects(excluded)
+                })
+                .map(|idx| idx as u32)
+        };
+
         let mut queue_family_indices = QueueFamilyIndices::new();
-        for (idx, queue_family_property) in queue_family_properties.iter().enumerate() {
-            if queue_family_property
-                .queue_flags
-                .contains(vk::QueueFlags::GRAPHICS)
-            {
-                queue_family_indices.graphics_family = Some(idx as u32);
-            }
-            if surface.get_physical_device_surface_support(device, idx as u32) {
-                queue_family_indices.presentation_family = Some(idx as u32);
-            }
+        let combined_family = queue_family_properties
+            .iter()
+            .zip(&present_support)
+            .position(|(properties, present)| {
+                *present && properties.queue_flags.contains(vk::QueueFlags::GRAPHICS)
+            })
+            .map(|idx| idx as u32);
+        if let Some(idx) = combined_family {
+            queue_family_indices.graphics_family = Some(idx);
+            queue_family_indices.presentation_family = Some(idx);
+        } else {
+            queue_family_indices.graphics_family =
+                find_family(vk::QueueFlags::GRAPHICS, vk::QueueFlags::empty());
+            queue_family_indices.presentation_family = present_support
+                .iter()
+                .position(|present| *present)
+                .map(|idx| idx as u32);
         }
-        queue_family_indices
+        queue_family_indices.compute_family =
+            find_family(vk::QueueFlags::COMPUTE, vk::QueueFlags::GRAPHICS)
+                .or(queue_family_indices.graphics_family);
+        // graphics and compute families support transfers too, even if they dont report it
+        queue_family_indices.transfer_family = find_family(
+            vk::QueueFlags::TRANSFER,
+            vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE,
+        )
+        .or_else(|| find_family(vk::QueueFlags::TRANSFER, vk::QueueFlags::GRAPHICS))
+        .or(queue_family_indices.graphics_family);
+        Ok(queue_family_indices)
     }
 
     pub fn create_swapchain_loader(&self, device: &ash::Device) -> ash::khr::swapchain::Device {
         ash::khr::swapchain::Device::new(&self.handle, device)
     }
 
+    pub fn create_full_screen_exclusive_loader(
+        &self,
+        device: &ash::Device,
+    ) -> ash::ext::full_screen_exclusive::Device {
+        ash::ext::full_screen_exclusive::Device::new(&self.handle, device)
+    }
+
+    pub fn create_acceleration_structure_loader(
+        &self,
+        device: &ash::Device,
+    ) -> ash::khr::acceleration_structure::Device {
+        ash::khr::acceleration_structure::Device::new(&self.handle, device)
+    }
+
+    pub fn create_ray_tracing_pipeline_loader(
+        &self,
+        device: &ash::Device,
+    ) -> ash::khr::ray_tracing_pipeline::Device {
+        ash::khr::ray_tracing_pipeline::Device::new(&self.handle, device)
+    }
+
     pub fn create_debug_utils_instance(&self) -> debug_utils::Instance {
         debug_utils::Instance::new(&self.entry, &self.handle)
     }
 
+    pub fn create_debug_utils_device(&self, device: &ash::Device) -> debug_utils::Device {
+        debug_utils::Device::new(&self.handle, device)
+    }
+
     pub fn create_surface(
         &self,
         display_handle: RawDisplayHandle,
         window_handle: RawWindowHandle,
         allocation_callbacks: Option<&vk::AllocationCallbacks<'_>>,
-    ) -> SurfaceKHR {
-        let surface_opt = match (display_handle, window_handle) {
+    ) -> Result<SurfaceKHR, VulkanError> {
+        let surface_result = match (display_handle, window_handle) {
             (RawDisplayHandle::Windows(_), RawWindowHandle::Win32(window)) => {
                 let surface_desc = vk::Win32SurfaceCreateInfoKHR::default()
                     .hwnd(window.hwnd.get())
@@ -357,9 +542,9 @@ impl Instance {
             //     let surface_fn = metal_surface::Instance::new(entry, instance);
             //     surface_fn.create_metal_surface(&surface_desc, allocation_callbacks)
             // }
-            _ => panic!("Unsupported display handle"),
+            _ => return Err(VulkanError::UnsupportedDisplay),
         };
-        surface_opt.expect("Device should have enough memory!")
+        Ok(surface_result?)
     }
 
     pub fn create_surface_loader(&self) -> ash::khr::surface::Instance {
@@ -370,8 +555,8 @@ impl Instance {
         &self,
         physical_device: vk::PhysicalDevice,
         device: ash::Device,
-    ) -> Allocator {
-        Allocator::new(&AllocatorCreateDesc {
+    ) -> Result<Allocator, VulkanError> {
+        Ok(Allocator::new(&AllocatorCreateDesc {
             instance: self.handle.clone(),
             device,
             physical_device,
@@ -385,8 +570,7 @@ impl Instance {
             },
             buffer_device_address: true,
             allocation_sizes: Default::default(),
-        })
-        .expect("I dont even know what most of these errors mean. So :shrug:")
+        })?)
     }
 }
 
@@ -403,6 +587,9 @@ impl Drop for Instance {
 pub struct QueueFamilyIndices {
     pub graphics_family: Option<u32>,
     pub presentation_family: Option<u32>,
+    // same as the graphics family if there is no dedicated one
+    pub compute_family: Option<u32>,
+    pub transfer_family: Option<u32>,
 }
 
 impl QueueFamilyIndices {
@@ -410,6 +597,8 @@ impl QueueFamilyIndices {
         QueueFamilyIndices {
             graphics_family: None,
             presentation_family: None,
+            compute_family: None,
+            transfer_family: None,
         }
     }
     pub fn is_complete(&self) -> bool {
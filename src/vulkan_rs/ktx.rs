@@ -0,0 +1,382 @@
+use super::allocation::AllocatedImage;
+use super::allocation::Allocator;
+use super::async_upload::AsyncUploader;
+use super::block_decode;
+use super::device::Device;
+use super::device::ImageLayers;
+use super::error::AssetError;
+use ash::vk;
+use std::io::Read;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+// loads a 2d or cube KTX2 texture with all mip levels stored in the file
+// the data is uploaded as is if the gpu can sample the format, otherwise bc1-5 and etc2 are
+// decoded to rgba8 on the loading thread
+// basis universal (etc1s/uastc) files are not transcoded at runtime, there is no transcoder we
+// can build here => convert them to bc/astc KTX2 files offline (e.g. toktx/basisu)
+pub fn load_ktx2(
+    path: &Path,
+    device: Arc<Device>,
+    allocator: Arc<Mutex<Allocator>>,
+    uploader: &AsyncUploader,
+) -> Result<AllocatedImage, AssetError> {
+    let bytes = std::fs::read(path)?;
+    let reader = ktx2::Reader::new(bytes.as_slice())?;
+    let header = reader.header();
+    log::debug!("Loading KTX2 texture {:?}: {:?}", path, header);
+
+    if header.pixel_depth > 1 || header.layer_count > 1 {
+        return Err(AssetError::UnsupportedTexture(
+            "only 2d and cube textures without layers are supported".to_string(),
+        ));
+    }
+    let layers = match header.face_count {
+        0 | 1 => ImageLayers::Single,
+        6 if header.pixel_width == header.pixel_height => ImageLayers::Cube,
+        _ => {
+            return Err(AssetError::UnsupportedTexture(format!(
+                "{} faces of {}x{} are not a cube",
+                header.face_count, header.pixel_width, header.pixel_height
+            )))
+        }
+    };
+    // basis universal (etc1s/uastc) data has no vk format
+    let Some(format) = header.format else {
+        return Err(AssetError::UnsupportedTexture(
+            "basis universal textures are not supported, transcode them to bc/astc offline"
+                .to_string(),
+        ));
+    };
+    let format = vk::Format::from_raw(format.value() as i32);
+    let Some(block) = block_size(format) else {
+        return Err(AssetError::UnsupportedTexture(format!(
+            "{:?} is not a supported texture format",
+            format
+        )));
+    };
+    let required_features =
+        vk::FormatFeatureFlags::SAMPLED_IMAGE | vk::FormatFeatureFlags::TRANSFER_DST;
+    let upload_format = select_format(format, |format| {
+        device.supports_format(format, required_features)
+    })?;
+    let max_size = device.properties().limits.max_image_dimension2_d;
+    if header.pixel_width > max_size || header.pixel_height > max_size {
+        return Err(AssetError::UnsupportedTexture(format!(
+            "{}x{} is larger than the maximum texture size {}",
+            header.pixel_width, header.pixel_height, max_size
+        )));
+    }
+
+    let levels = reader
+        .levels()
+        .map(|level| match header.supercompression_scheme {
+            None => Ok(level.data.to_vec()),
+            Some(ktx2::SupercompressionScheme::Zstandard) => {
+                let mut data = Vec::with_capacity(level.uncompressed_byte_length as usize);
+                ruzstd::StreamingDecoder::new(level.data)
+                    .map_err(std::io::Error::other)?
+                    .read_to_end(&mut data)?;
+                Ok(data)
+            }
+            Some(scheme) => Err(AssetError::UnsupportedTexture(format!(
+                "supercompression {:?} is not supported",
+                scheme
+            ))),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let width = header.pixel_width.max(1);
+    let height = header.pixel_height.max(1);
+    let level_sizes: Vec<usize> = levels.iter().map(|level| level.len()).collect();
+    validate_levels(format, block, width, height, layers.count(), &level_sizes)?;
+
+    let levels = if upload_format == format {
+        levels
+    } else {
+        log::warn!(
+            "{:?} can not be sampled on this gpu => {:?} is decoded to {:?}",
+            format,
+            path,
+            upload_format
+        );
+        levels
+            .iter()
+            .enumerate()
+            .map(|(idx, level)| {
+                let face_size = level.len() / layers.count() as usize;
+                level
+                    .chunks(face_size.max(1))
+                    .flat_map(|face| {
+                        block_decode::decode(format, face, width >> idx, height >> idx)
+                    })
+                    .collect()
+            })
+            .collect()
+    };
+    let levels: Vec<&[u8]> = levels.iter().map(|level| level.as_slice()).collect();
+
+    let image = AllocatedImage::new_texture_with_levels(
+        &levels,
+        device,
+        allocator,
+        upload_format,
+        vk::ImageUsageFlags::SAMPLED,
+        vk::Extent3D {
+            width,
+            height,
+            depth: 1,
+        },
+        layers,
+        uploader,
+    )?;
+    Ok(image)
+}
+
+// the format itself if it can be sampled, otherwise the rgba8 format it can be decoded to
+fn select_format(
+    format: vk::Format,
+    can_sample: impl Fn(vk::Format) -> bool,
+) -> Result<vk::Format, AssetError> {
+    if can_sample(format) {
+        return Ok(format);
+    }
+    block_decode::decoded_format(format)
+        .filter(|decoded| can_sample(*decoded))
+        .ok_or_else(|| {
+            AssetError::UnsupportedTexture(format!(
+                "{:?} can not be sampled on this gpu and there is no cpu decoder for it",
+                format
+            ))
+        })
+}
+
+// the copies read exactly one mip level per level => a truncated file would read past the
+// staging data. A level holds one image per face
+fn validate_levels(
+    format: vk::Format,
+    block: BlockSize,
+    width: u32,
+    height: u32,
+    faces: u32,
+    level_sizes: &[usize],
+) -> Result<(), AssetError> {
+    let max_levels = u32::BITS - width.max(height).max(1).leading_zeros();
+    if level_sizes.len() as u32 > max_levels {
+        return Err(AssetError::UnsupportedTexture(format!(
+            "{} mip levels for a {}x{} texture",
+            level_sizes.len(),
+            width,
+            height
+        )));
+    }
+    for (idx, size) in level_sizes.iter().enumerate() {
+        let expected = block.level_size(width >> idx, height >> idx) * faces as u64;
+        if *size as u64 != expected {
+            return Err(AssetError::UnsupportedTexture(format!(
+                "mip level {} has {} bytes, {:?} needs {}",
+                idx, size, format, expected
+            )));
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy)]
+struct BlockSize {
+    width: u32,
+    height: u32,
+    bytes: u64,
+}
+
+impl BlockSize {
+    const fn new(width: u32, height: u32, bytes: u64) -> Self {
+        BlockSize {
+            width,
+            height,
+            bytes,
+        }
+    }
+
+    fn level_size(&self, width: u32, height: u32) -> u64 {
+        let blocks_x = width.max(1).div_ceil(self.width) as u64;
+        let blocks_y = height.max(1).div_ceil(self.height) as u64;
+        blocks_x * blocks_y * self.bytes
+    }
+}
+
+// formats that are common in KTX2 files, uncompressed formats are 1x1 blocks
+fn block_size(format: vk::Format) -> Option<BlockSize> {
+    let block = match format {
+        vk::Format::R8_UNORM | vk::Format::R8_SNORM | vk::Format::R8_SRGB => {
+            BlockSize::new(1, 1, 1)
+        }
+        vk::Format::R8G8_UNORM
+        | vk::Format::R8G8_SNORM
+        | vk::Format::R8G8_SRGB
+        | vk::Format::R16_UNORM
+        | vk::Format::R16_SFLOAT
+        | vk::Format::R5G6B5_UNORM_PACK16
+        | vk::Format::R4G4B4A4_UNORM_PACK16 => BlockSize::new(1, 1, 2),
+        vk::Format::R8G8B8_UNORM | vk::Format::R8G8B8_SRGB => BlockSize::new(1, 1, 3),
+        vk::Format::R8G8B8A8_UNORM
+        | vk::Format::R8G8B8A8_SNORM
+        | vk::Format::R8G8B8A8_SRGB
+        | vk::Format::B8G8R8A8_UNORM
+        | vk::Format::B8G8R8A8_SRGB
+        | vk::Format::R16G16_UNORM
+        | vk::Format::R16G16_SFLOAT
+        | vk::Format::R32_SFLOAT
+        | vk::Format::A2B10G10R10_UNORM_PACK32
+        | vk::Format::B10G11R11_UFLOAT_PACK32
+        | vk::Format::E5B9G9R9_UFLOAT_PACK32 => BlockSize::new(1, 1, 4),
+        vk::Format::R16G16B16A16_UNORM
+        | vk::Format::R16G16B16A16_SFLOAT
+        | vk::Format::R32G32_SFLOAT => BlockSize::new(1, 1, 8),
+        vk::Format::R32G32B32A32_SFLOAT => BlockSize::new(1, 1, 16),
+        vk::Format::BC1_RGB_UNORM_BLOCK
+        | vk::Format::BC1_RGB_SRGB_BLOCK
+        | vk::Format::BC1_RGBA_UNORM_BLOCK
+        | vk::Format::BC1_RGBA_SRGB_BLOCK
+        | vk::Format::BC4_UNORM_BLOCK
+        | vk::Format::BC4_SNORM_BLOCK
+        | vk::Format::ETC2_R8G8B8_UNORM_BLOCK
+        | vk::Format::ETC2_R8G8B8_SRGB_BLOCK
+        | vk::Format::ETC2_R8G8B8A1_UNORM_BLOCK
+        | vk::Format::ETC2_R8G8B8A1_SRGB_BLOCK
+        | vk::Format::EAC_R11_UNORM_BLOCK
+        | vk::Format::EAC_R11_SNORM_BLOCK => BlockSize::new(4, 4, 8),
+        vk::Format::BC2_UNORM_BLOCK
+        | vk::Format::BC2_SRGB_BLOCK
+        | vk::Format::BC3_UNORM_BLOCK
+        | vk::Format::BC3_SRGB_BLOCK
+        | vk::Format::BC5_UNORM_BLOCK
+        | vk::Format::BC5_SNORM_BLOCK
+        | vk::Format::BC6H_UFLOAT_BLOCK
+        | vk::Format::BC6H_SFLOAT_BLOCK
+        | vk::Format::BC7_UNORM_BLOCK
+        | vk::Format::BC7_SRGB_BLOCK
+        | vk::Format::ETC2_R8G8B8A8_UNORM_BLOCK
+        | vk::Format::ETC2_R8G8B8A8_SRGB_BLOCK
+        | vk::Format::EAC_R11G11_UNORM_BLOCK
+        | vk::Format::EAC_R11G11_SNORM_BLOCK
+        | vk::Format::ASTC_4X4_UNORM_BLOCK
+        | vk::Format::ASTC_4X4_SRGB_BLOCK => BlockSize::new(4, 4, 16),
+        // every astc block is 16 bytes, only the footprint differs
+        vk::Format::ASTC_5X4_UNORM_BLOCK | vk::Format::ASTC_5X4_SRGB_BLOCK => {
+            BlockSize::new(5, 4, 16)
+        }
+        vk::Format::ASTC_5X5_UNORM_BLOCK | vk::Format::ASTC_5X5_SRGB_BLOCK => {
+            BlockSize::new(5, 5, 16)
+        }
+        vk::Format::ASTC_6X5_UNORM_BLOCK | vk::Format::ASTC_6X5_SRGB_BLOCK => {
+            BlockSize::new(6, 5, 16)
+        }
+        vk::Format::ASTC_6X6_UNORM_BLOCK | vk::Format::ASTC_6X6_SRGB_BLOCK => {
+            BlockSize::new(6, 6, 16)
+        }
+        vk::Format::ASTC_8X5_UNORM_BLOCK | vk::Format::ASTC_8X5_SRGB_BLOCK => {
+            BlockSize::new(8, 5, 16)
+        }
+        vk::Format::ASTC_8X6_UNORM_BLOCK | vk::Format::ASTC_8X6_SRGB_BLOCK => {
+            BlockSize::new(8, 6, 16)
+        }
+        vk::Format::ASTC_8X8_UNORM_BLOCK | vk::Format::ASTC_8X8_SRGB_BLOCK => {
+            BlockSize::new(8, 8, 16)
+        }
+        vk::Format::ASTC_10X5_UNORM_BLOCK | vk::Format::ASTC_10X5_SRGB_BLOCK => {
+            BlockSize::new(10, 5, 16)
+        }
+        vk::Format::ASTC_10X6_UNORM_BLOCK | vk::Format::ASTC_10X6_SRGB_BLOCK => {
+            BlockSize::new(10, 6, 16)
+        }
+        vk::Format::ASTC_10X8_UNORM_BLOCK | vk::Format::ASTC_10X8_SRGB_BLOCK => {
+            BlockSize::new(10, 8, 16)
+        }
+        vk::Format::ASTC_10X10_UNORM_BLOCK | vk::Format::ASTC_10X10_SRGB_BLOCK => {
+            BlockSize::new(10, 10, 16)
+        }
+        vk::Format::ASTC_12X10_UNORM_BLOCK | vk::Format::ASTC_12X10_SRGB_BLOCK => {
+            BlockSize::new(12, 10, 16)
+        }
+        vk::Format::ASTC_12X12_UNORM_BLOCK | vk::Format::ASTC_12X12_SRGB_BLOCK => {
+            BlockSize::new(12, 12, 16)
+        }
+        _ => return None,
+    };
+    Some(block)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn level_size_rounds_up_to_whole_blocks() {
+        let bc1 = block_size(vk::Format::BC1_RGB_UNORM_BLOCK).unwrap();
+        assert_eq!(bc1.level_size(4, 4), 8);
+        assert_eq!(bc1.level_size(5, 3), 2 * 8);
+        // the smallest mip levels still need a whole block
+        assert_eq!(bc1.level_size(1, 1), 8);
+        assert_eq!(bc1.level_size(0, 0), 8);
+        let astc = block_size(vk::Format::ASTC_10X8_UNORM_BLOCK).unwrap();
+        assert_eq!(astc.level_size(64, 64), 7 * 8 * 16);
+        let rgba = block_size(vk::Format::R8G8B8A8_SRGB).unwrap();
+        assert_eq!(rgba.level_size(3, 5), 3 * 5 * 4);
+    }
+
+    #[test]
+    fn full_mip_chain_is_valid() {
+        let format = vk::Format::BC3_UNORM_BLOCK;
+        let block = block_size(format).unwrap();
+        // 16x8, 8x4, 4x2, 2x1, 1x1
+        let sizes = [128, 32, 16, 16, 16];
+        assert!(validate_levels(format, block, 16, 8, 1, &sizes).is_ok());
+        assert!(validate_levels(format, block, 16, 8, 1, &sizes[..2]).is_ok());
+        let cube_sizes = sizes.map(|size| size * 6);
+        assert!(validate_levels(format, block, 16, 8, 6, &cube_sizes).is_ok());
+    }
+
+    #[test]
+    fn too_many_mip_levels() {
+        let format = vk::Format::R8_UNORM;
+        let block = block_size(format).unwrap();
+        let sizes = [16, 4, 1, 1];
+        assert!(matches!(
+            validate_levels(format, block, 4, 4, 1, &sizes),
+            Err(AssetError::UnsupportedTexture(_))
+        ));
+    }
+
+    #[test]
+    fn truncated_mip_level() {
+        let format = vk::Format::BC1_RGBA_SRGB_BLOCK;
+        let block = block_size(format).unwrap();
+        assert!(matches!(
+            validate_levels(format, block, 8, 8, 1, &[32, 7]),
+            Err(AssetError::UnsupportedTexture(_))
+        ));
+        // a cube needs all 6 faces per level
+        assert!(matches!(
+            validate_levels(format, block, 8, 8, 6, &[32]),
+            Err(AssetError::UnsupportedTexture(_))
+        ));
+    }
+
+    #[test]
+    fn unsampleable_formats_fall_back_to_rgba8() {
+        let all = |_| true;
+        let only_rgba8 =
+            |format| format == vk::Format::R8G8B8A8_SRGB || format == vk::Format::R8G8B8A8_UNORM;
+        let nothing = |_| false;
+        let format = vk::Format::BC1_RGB_SRGB_BLOCK;
+        assert_eq!(select_format(format, all).unwrap(), format);
+        assert_eq!(
+            select_format(format, only_rgba8).unwrap(),
+            vk::Format::R8G8B8A8_SRGB
+        );
+        assert!(select_format(format, nothing).is_err());
+        // no decoder for astc
+        assert!(select_format(vk::Format::ASTC_4X4_UNORM_BLOCK, only_rgba8).is_err());
+    }
+}
@@ -0,0 +1,72 @@
+use std::backtrace::Backtrace;
+use std::backtrace::BacktraceStatus;
+use std::collections::BTreeMap;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
+
+struct LiveObject {
+    kind: &'static str,
+    name: String,
+    // only captured with RUST_BACKTRACE=1 => cheap otherwise
+    backtrace: Backtrace,
+}
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+// BTreeMap => the report lists objects in creation order
+static LIVE_OBJECTS: Mutex<BTreeMap<u64, LiveObject>> = Mutex::new(BTreeMap::new());
+
+// registers the owning gpu object while it is alive, stored as a field of the object
+// none of them should outlive the renderer => anything left afterwards was leaked
+// (Arc cycles, mem::forget, resources stashed in statics, ...)
+pub struct TrackedObject {
+    id: u64,
+}
+
+impl TrackedObject {
+    pub fn new(kind: &'static str, name: impl Into<String>) -> Self {
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        LIVE_OBJECTS.lock().unwrap().insert(
+            id,
+            LiveObject {
+                kind,
+                name: name.into(),
+                backtrace: Backtrace::capture(),
+            },
+        );
+        TrackedObject { id }
+    }
+}
+
+impl Drop for TrackedObject {
+    fn drop(&mut self) {
+        LIVE_OBJECTS.lock().unwrap().remove(&self.id);
+    }
+}
+
+// call after the renderer was dropped
+pub fn report_live_objects() {
+    let live_objects = LIVE_OBJECTS.lock().unwrap();
+    if live_objects.is_empty() {
+        log::info!("No leaked gpu objects");
+        return;
+    }
+    let mut report = format!(
+        "{} gpu objects are still alive after shutdown:\n{:<24} {}\n",
+        live_objects.len(),
+        "TYPE",
+        "NAME"
+    );
+    let mut has_backtraces = false;
+    for object in live_objects.values() {
+        report.push_str(&format!("{:<24} {}\n", object.kind, object.name));
+        if object.backtrace.status() == BacktraceStatus::Captured {
+            has_backtraces = true;
+            report.push_str(&format!("created at:\n{}\n", object.backtrace));
+        }
+    }
+    if !has_backtraces {
+        report.push_str("run with RUST_BACKTRACE=1 to see where they were created");
+    }
+    log::warn!("{}", report);
+}
@@ -0,0 +1,100 @@
+use nalgebra_glm as glm;
+
+// lights without shadows that are submitted every frame, the sun is set separately
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Light {
+    Directional {
+        direction: glm::Vec3,
+        color: glm::Vec3,
+        intensity: f32,
+    },
+    // range => distance at which the light has faded out completely
+    Point {
+        position: glm::Vec3,
+        color: glm::Vec3,
+        intensity: f32,
+        range: f32,
+    },
+    // angles in radians between the direction and the edge of the cone
+    // full intensity inside inner_angle, fades out until outer_angle
+    Spot {
+        position: glm::Vec3,
+        direction: glm::Vec3,
+        color: glm::Vec3,
+        intensity: f32,
+        range: f32,
+        inner_angle: f32,
+        outer_angle: f32,
+    },
+}
+
+const LIGHT_TYPE_DIRECTIONAL: f32 = 0.0;
+const LIGHT_TYPE_POINT: f32 = 1.0;
+const LIGHT_TYPE_SPOT: f32 = 2.0;
+
+// same layout as the Light struct in mesh.frag
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct GPULight {
+    // w = type
+    position: glm::Vec4,
+    // w = range
+    direction: glm::Vec4,
+    // w = intensity
+    color: glm::Vec4,
+    // x = cos of the inner angle, y = cos of the outer angle
+    cone: glm::Vec4,
+}
+
+impl From<&Light> for GPULight {
+    fn from(light: &Light) -> Self {
+        match *light {
+            Light::Directional {
+                direction,
+                color,
+                intensity,
+            } => GPULight {
+                position: glm::vec4(0.0, 0.0, 0.0, LIGHT_TYPE_DIRECTIONAL),
+                direction: normalized_or_down(direction).push(0.0),
+                color: color.push(intensity),
+                cone: glm::Vec4::zeros(),
+            },
+            Light::Point {
+                position,
+                color,
+                intensity,
+                range,
+            } => GPULight {
+                position: position.push(LIGHT_TYPE_POINT),
+                direction: glm::vec4(0.0, 0.0, 0.0, range.max(0.0)),
+                color: color.push(intensity),
+                cone: glm::Vec4::zeros(),
+            },
+            Light::Spot {
+                position,
+                direction,
+                color,
+                intensity,
+                range,
+                inner_angle,
+                outer_angle,
+            } => {
+                let inner_angle = inner_angle.min(outer_angle);
+                GPULight {
+                    position: position.push(LIGHT_TYPE_SPOT),
+                    direction: normalized_or_down(direction).push(range.max(0.0)),
+                    color: color.push(intensity),
+                    cone: glm::vec4(inner_angle.cos(), outer_angle.cos(), 0.0, 0.0),
+                }
+            }
+        }
+    }
+}
+
+fn normalized_or_down(direction: glm::Vec3) -> glm::Vec3 {
+    if direction.norm_squared() > 0.0 {
+        glm::normalize(&direction)
+    } else {
+        -glm::Vec3::y()
+    }
+}
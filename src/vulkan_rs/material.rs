@@ -0,0 +1,489 @@
+use super::allocation::AllocatedBuffer;
+use super::allocation::AllocatedImage;
+use super::allocation::Allocator;
+use super::async_upload::AsyncUploader;
+use super::descriptor::DescriptorSetLayout;
+use super::descriptor::DescriptorSetSlot;
+use super::descriptor::SetLayouts;
+use super::descriptor::TextureHandle;
+use super::descriptor::TextureTable;
+use super::device::Device;
+use super::error::VulkanError;
+use super::mesh::GPUDrawPushConstants;
+use super::mesh::Sampler;
+use super::mesh::SamplerSettings;
+use super::pipelines::GraphicsPipeline;
+use super::pipelines::GraphicsPipelineBuilder;
+use super::shader::ShaderModule;
+use super::texture::check_color_space;
+use super::texture::ColorSpace;
+use ash::vk;
+use nalgebra_glm as glm;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MaterialPass {
+    Opaque,
+    // alpha blended, drawn after all opaque surfaces without writing depth
+    Transparent,
+}
+
+// matches MaterialData in mesh.frag, read through its device address
+#[repr(C)]
+#[derive(Debug, bytemuck::NoUninit, Copy, Clone)]
+pub struct MaterialConstants {
+    pub color_factors: glm::Vec4,
+    // x: metallic, y: roughness, zw: unused
+    pub metal_rough_factors: glm::Vec4,
+}
+
+impl Default for MaterialConstants {
+    fn default() -> Self {
+        MaterialConstants {
+            color_factors: glm::vec4(1.0, 1.0, 1.0, 1.0),
+            metal_rough_factors: glm::vec4(1.0, 1.0, 0.0, 0.0),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct MaterialTexture {
+    pub image: Arc<AllocatedImage>,
+    pub sampler: Arc<Sampler>,
+}
+
+// textures that are None fall back to the defaults of the MaterialCache
+#[derive(Clone)]
+pub struct MaterialDescription {
+    pub pass: MaterialPass,
+    pub constants: MaterialConstants,
+    pub base_color: Option<MaterialTexture>,
+    pub metal_rough: Option<MaterialTexture>,
+    pub normal: Option<MaterialTexture>,
+}
+
+impl Default for MaterialDescription {
+    fn default() -> Self {
+        MaterialDescription {
+            pass: MaterialPass::Opaque,
+            constants: MaterialConstants::default(),
+            base_color: None,
+            metal_rough: None,
+            normal: None,
+        }
+    }
+}
+
+pub struct Material {
+    pass: MaterialPass,
+    constants_address: vk::DeviceAddress,
+    texture_handles: [TextureHandle; 3],
+    // referenced by the push constants and the texture table => have to live as long as the material
+    _constants: AllocatedBuffer,
+    _textures: [MaterialTexture; 3],
+}
+
+impl Material {
+    pub fn pass(&self) -> MaterialPass {
+        self.pass
+    }
+
+    pub fn constants_address(&self) -> vk::DeviceAddress {
+        self.constants_address
+    }
+
+    // base color, metal rough, normal, unused
+    pub fn texture_indices(&self) -> [u32; 4] {
+        let [base_color, metal_rough, normal] = &self.texture_handles;
+        [base_color.index(), metal_rough.index(), normal.index(), 0]
+    }
+}
+
+// owns everything materials need: the bindless texture table, one pipeline per MaterialPass,
+// deduplicated samplers and the default textures
+// materials are cached by name => loading the same file twice doesnt upload the constants again
+pub struct MaterialCache {
+    device: Arc<Device>,
+    allocator: Arc<Mutex<Allocator>>,
+    texture_table: Arc<TextureTable>,
+    opaque_pipeline: GraphicsPipeline,
+    transparent_pipeline: GraphicsPipeline,
+    shadow_pipeline: GraphicsPipeline,
+    samplers: HashMap<SamplerSettings, Arc<Sampler>>,
+    materials: HashMap<String, Arc<Material>>,
+    white_texture: Arc<AllocatedImage>,
+    flat_normal_texture: Arc<AllocatedImage>,
+    error_texture: Arc<AllocatedImage>,
+    default_material: Option<Arc<Material>>,
+    // check the color space of the textures of every new material
+    color_validation: bool,
+}
+
+impl MaterialCache {
+    pub fn new(
+        device: Arc<Device>,
+        allocator: Arc<Mutex<Allocator>>,
+        uploader: &AsyncUploader,
+        scene_data_layout: &DescriptorSetLayout,
+        error_texture: Arc<AllocatedImage>,
+        color_format: vk::Format,
+        depth_format: vk::Format,
+    ) -> Result<Self, VulkanError> {
+        let texture_table = TextureTable::new(device.clone())?;
+        let (opaque_pipeline, transparent_pipeline, shadow_pipeline) = Self::build_pipelines(
+            device.clone(),
+            scene_data_layout,
+            &texture_table,
+            color_format,
+            depth_format,
+        )?;
+
+        let white = Self::new_pixel_texture(
+            device.clone(),
+            allocator.clone(),
+            uploader,
+            [255, 255, 255, 255],
+        )?;
+        // normal maps store (0, 0, 1) as (0.5, 0.5, 1)
+        let flat_normal = Self::new_pixel_texture(
+            device.clone(),
+            allocator.clone(),
+            uploader,
+            [128, 128, 255, 255],
+        )?;
+
+        let mut cache = MaterialCache {
+            device,
+            allocator,
+            texture_table,
+            opaque_pipeline,
+            transparent_pipeline,
+            shadow_pipeline,
+            samplers: HashMap::new(),
+            materials: HashMap::new(),
+            white_texture: Arc::new(white),
+            flat_normal_texture: Arc::new(flat_normal),
+            error_texture,
+            default_material: None,
+            color_validation: false,
+        };
+        let default_material = cache.create_material(MaterialDescription::default())?;
+        cache.default_material = Some(default_material);
+        Ok(cache)
+    }
+
+    // opaque, transparent and shadow pipeline
+    fn build_pipelines(
+        device: Arc<Device>,
+        scene_data_layout: &DescriptorSetLayout,
+        texture_table: &TextureTable,
+        color_format: vk::Format,
+        depth_format: vk::Format,
+    ) -> Result<(GraphicsPipeline, GraphicsPipeline, GraphicsPipeline), VulkanError> {
+        let frag_shader = ShaderModule::new(device.clone(), "shaders/mesh_frag.spv")?;
+        let vert_shader = ShaderModule::new(device.clone(), "shaders/mesh_vert.spv")?;
+        let set_layouts = SetLayouts::new()
+            .with(DescriptorSetSlot::Scene, scene_data_layout.layout())
+            .with(DescriptorSetSlot::Material, texture_table.layout());
+        let opaque_pipeline = Self::build_pipeline(
+            device.clone(),
+            &set_layouts,
+            &frag_shader,
+            &vert_shader,
+            MaterialPass::Opaque,
+            color_format,
+            depth_format,
+        )?;
+        let transparent_pipeline = Self::build_pipeline(
+            device.clone(),
+            &set_layouts,
+            &frag_shader,
+            &vert_shader,
+            MaterialPass::Transparent,
+            color_format,
+            depth_format,
+        )?;
+        let shadow_shader = ShaderModule::new(device.clone(), "shaders/shadow_vert.spv")?;
+        let shadow_pipeline =
+            Self::build_shadow_pipeline(device, scene_data_layout.layout(), &shadow_shader)?;
+        Ok((opaque_pipeline, transparent_pipeline, shadow_pipeline))
+    }
+
+    // after the mesh/shadow shaders were recompiled, materials look up the pipeline per draw
+    // => they use the new ones right away
+    // returns the old pipelines since frames in flight might still use them
+    pub fn rebuild_pipelines(
+        &mut self,
+        scene_data_layout: &DescriptorSetLayout,
+        color_format: vk::Format,
+        depth_format: vk::Format,
+    ) -> Result<Vec<GraphicsPipeline>, VulkanError> {
+        let (opaque_pipeline, transparent_pipeline, shadow_pipeline) = Self::build_pipelines(
+            self.device.clone(),
+            scene_data_layout,
+            &self.texture_table,
+            color_format,
+            depth_format,
+        )?;
+        Ok(vec![
+            std::mem::replace(&mut self.opaque_pipeline, opaque_pipeline),
+            std::mem::replace(&mut self.transparent_pipeline, transparent_pipeline),
+            std::mem::replace(&mut self.shadow_pipeline, shadow_pipeline),
+        ])
+    }
+
+    fn new_pixel_texture(
+        device: Arc<Device>,
+        allocator: Arc<Mutex<Allocator>>,
+        uploader: &AsyncUploader,
+        pixel: [u8; 4],
+    ) -> Result<AllocatedImage, VulkanError> {
+        AllocatedImage::new_texture(
+            &pixel,
+            device,
+            allocator,
+            vk::Format::R8G8B8A8_UNORM,
+            vk::ImageUsageFlags::SAMPLED,
+            vk::Extent3D {
+                width: 1,
+                height: 1,
+                depth: 1,
+            },
+            false,
+            uploader,
+        )
+    }
+
+    fn build_pipeline(
+        device: Arc<Device>,
+        set_layouts: &SetLayouts,
+        frag_shader: &ShaderModule,
+        vert_shader: &ShaderModule,
+        pass: MaterialPass,
+        color_format: vk::Format,
+        depth_format: vk::Format,
+    ) -> Result<GraphicsPipeline, VulkanError> {
+        let push_constants = vk::PushConstantRange {
+            stage_flags: vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+            offset: 0,
+            size: std::mem::size_of::<GPUDrawPushConstants>() as u32,
+        };
+        let layout_info = vk::PipelineLayoutCreateInfo {
+            s_type: vk::StructureType::PIPELINE_LAYOUT_CREATE_INFO,
+            p_next: std::ptr::null(),
+            flags: vk::PipelineLayoutCreateFlags::empty(),
+            set_layout_count: set_layouts.layouts().len() as u32,
+            p_set_layouts: set_layouts.layouts().as_ptr(),
+            push_constant_range_count: 1,
+            p_push_constant_ranges: &push_constants,
+            ..Default::default()
+        };
+        // every pipeline owns its layout => create one per pass even though they are identical
+        let pipeline_layout = device.create_pipeline_layout(&layout_info)?;
+        let builder = GraphicsPipelineBuilder::new()
+            .set_layout(pipeline_layout)
+            .set_shaders(frag_shader, vert_shader)
+            .set_input_topology(vk::PrimitiveTopology::TRIANGLE_LIST)
+            .set_polygon_mode(vk::PolygonMode::FILL)
+            .set_cull_mode(vk::CullModeFlags::NONE, vk::FrontFace::CLOCKWISE)
+            .disable_multisampling()
+            .set_color_attachment_format(color_format)
+            .set_depth_format(depth_format);
+        let builder = match pass {
+            MaterialPass::Opaque => builder
+                .disable_blending()
+                .enable_depth_test(vk::TRUE, vk::CompareOp::GREATER_OR_EQUAL),
+            MaterialPass::Transparent => builder
+                .enable_blending_alphablend()
+                .enable_depth_test(vk::FALSE, vk::CompareOp::GREATER_OR_EQUAL),
+        };
+        builder.build_pipeline(device)
+    }
+
+    // depth only, renders the scene from the light into a D32_SFLOAT shadow map
+    fn build_shadow_pipeline(
+        device: Arc<Device>,
+        scene_data_layout: vk::DescriptorSetLayout,
+        vert_shader: &ShaderModule,
+    ) -> Result<GraphicsPipeline, VulkanError> {
+        // same range as the material pipelines => draw_mesh can push the same constants
+        let push_constants = vk::PushConstantRange {
+            stage_flags: vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+            offset: 0,
+            size: std::mem::size_of::<GPUDrawPushConstants>() as u32,
+        };
+        let set_layouts = SetLayouts::new().with(DescriptorSetSlot::Scene, scene_data_layout);
+        let layout_info = vk::PipelineLayoutCreateInfo {
+            s_type: vk::StructureType::PIPELINE_LAYOUT_CREATE_INFO,
+            p_next: std::ptr::null(),
+            flags: vk::PipelineLayoutCreateFlags::empty(),
+            set_layout_count: set_layouts.layouts().len() as u32,
+            p_set_layouts: set_layouts.layouts().as_ptr(),
+            push_constant_range_count: 1,
+            p_push_constant_ranges: &push_constants,
+            ..Default::default()
+        };
+        let pipeline_layout = device.create_pipeline_layout(&layout_info)?;
+        GraphicsPipelineBuilder::new()
+            .set_layout(pipeline_layout)
+            .set_vertex_shader(vert_shader)
+            .set_input_topology(vk::PrimitiveTopology::TRIANGLE_LIST)
+            .set_polygon_mode(vk::PolygonMode::FILL)
+            .set_cull_mode(vk::CullModeFlags::NONE, vk::FrontFace::CLOCKWISE)
+            // reversed depth => negative bias moves the surface away from the light (acne)
+            .enable_depth_bias(-1.0, -1.5)
+            .disable_multisampling()
+            .set_depth_format(vk::Format::D32_SFLOAT)
+            .enable_depth_test(vk::TRUE, vk::CompareOp::GREATER_OR_EQUAL)
+            .build_pipeline(device)
+    }
+
+    pub fn shadow_pipeline(&self) -> &GraphicsPipeline {
+        &self.shadow_pipeline
+    }
+
+    pub fn pipeline(&self, pass: MaterialPass) -> &GraphicsPipeline {
+        match pass {
+            MaterialPass::Opaque => &self.opaque_pipeline,
+            MaterialPass::Transparent => &self.transparent_pipeline,
+        }
+    }
+
+    // bound as set 1 of the material pipelines, the texture indices come from the push constants
+    pub fn texture_descriptor_set(&self) -> vk::DescriptorSet {
+        self.texture_table.descriptor_set()
+    }
+
+    // for pipelines outside of the cache that index into the texture table
+    pub fn texture_table_layout(&self) -> vk::DescriptorSetLayout {
+        self.texture_table.layout()
+    }
+
+    // for textures that are not part of a material, e.g. render targets that the ui samples
+    pub fn register_texture(
+        &self,
+        texture: &MaterialTexture,
+    ) -> Result<TextureHandle, VulkanError> {
+        self.texture_table
+            .register(texture.image.image_view(), texture.sampler.sampler())
+    }
+
+    pub fn default_material(&self) -> Arc<Material> {
+        self.default_material
+            .clone()
+            .expect("Default material is created in new")
+    }
+
+    // used when a texture could not be loaded
+    pub fn error_texture(&mut self) -> Result<MaterialTexture, VulkanError> {
+        Ok(MaterialTexture {
+            image: self.error_texture.clone(),
+            sampler: self.sampler(SamplerSettings::new(
+                vk::Filter::NEAREST,
+                vk::Filter::NEAREST,
+            ))?,
+        })
+    }
+
+    pub fn set_color_validation(&mut self, enabled: bool) {
+        self.color_validation = enabled;
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<Material>> {
+        self.materials.get(name).cloned()
+    }
+
+    // gltf materials are cached as "path#idx" => a reloaded file gets new ones
+    // meshes that use the old ones keep them alive
+    pub fn remove_file_materials(&mut self, file_path: &Path) {
+        let prefix = format!("{}#", file_path.display());
+        self.materials.retain(|name, _| !name.starts_with(&prefix));
+    }
+
+    pub fn sampler(&mut self, settings: SamplerSettings) -> Result<Arc<Sampler>, VulkanError> {
+        if let Some(sampler) = self.samplers.get(&settings) {
+            return Ok(sampler.clone());
+        }
+        let sampler = Arc::new(Sampler::with_settings(self.device.clone(), &settings)?);
+        self.samplers.insert(settings, sampler.clone());
+        Ok(sampler)
+    }
+
+    pub fn insert(
+        &mut self,
+        name: &str,
+        description: MaterialDescription,
+    ) -> Result<Arc<Material>, VulkanError> {
+        let material = self.create_material(description)?;
+        self.materials.insert(name.to_string(), material.clone());
+        Ok(material)
+    }
+
+    // uncached material, e.g. for textures dropped onto the window
+    pub fn create_material(
+        &mut self,
+        description: MaterialDescription,
+    ) -> Result<Arc<Material>, VulkanError> {
+        if self.color_validation {
+            Self::validate_color_spaces(&description);
+        }
+        let default_sampler =
+            self.sampler(SamplerSettings::new(vk::Filter::LINEAR, vk::Filter::LINEAR))?;
+        let or_default = |texture: Option<MaterialTexture>, image: &Arc<AllocatedImage>| {
+            texture.unwrap_or_else(|| MaterialTexture {
+                image: image.clone(),
+                sampler: default_sampler.clone(),
+            })
+        };
+        let textures = [
+            or_default(description.base_color, &self.white_texture),
+            or_default(description.metal_rough, &self.white_texture),
+            or_default(description.normal, &self.flat_normal_texture),
+        ];
+
+        let mut constants = AllocatedBuffer::new(
+            self.device.clone(),
+            self.allocator.clone(),
+            "Material Constants Buffer",
+            vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+            std::mem::size_of::<MaterialConstants>() as u64,
+            gpu_allocator::MemoryLocation::CpuToGpu,
+        )?;
+        constants.copy_from_slice(&[description.constants], 0);
+
+        let register = |texture: &MaterialTexture| {
+            self.texture_table
+                .register(texture.image.image_view(), texture.sampler.sampler())
+        };
+        let texture_handles = [
+            register(&textures[0])?,
+            register(&textures[1])?,
+            register(&textures[2])?,
+        ];
+
+        Ok(Arc::new(Material {
+            pass: description.pass,
+            constants_address: constants.get_device_address(),
+            texture_handles,
+            _constants: constants,
+            _textures: textures,
+        }))
+    }
+
+    // only the textures of the description, the defaults are created in the right color space
+    fn validate_color_spaces(description: &MaterialDescription) {
+        let slots = [
+            ("base color", &description.base_color, ColorSpace::Srgb),
+            ("metal rough", &description.metal_rough, ColorSpace::Linear),
+            ("normal", &description.normal, ColorSpace::Linear),
+        ];
+        for (usage, texture, expected) in slots {
+            if let Some(texture) = texture {
+                check_color_space(usage, texture.image.format(), expected);
+            }
+        }
+    }
+}
@@ -1,10 +1,23 @@
 use super::allocation::AllocatedBuffer;
+use super::allocation::AllocatedImage;
 use super::allocation::Allocator;
+use super::async_upload::AsyncUploader;
 use super::device::Device;
-use super::immediate_submit::ImmediateCommandData;
+use super::error::AssetError;
+use super::error::VulkanError;
+use super::material::Material;
+use super::material::MaterialCache;
+use super::material::MaterialConstants;
+use super::material::MaterialDescription;
+use super::material::MaterialPass;
+use super::material::MaterialTexture;
+use super::texture::ColorSpace;
 use ash::vk;
 use nalgebra_glm as glm;
+use std::collections::HashMap;
 use std::path::Path;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use std::sync::Mutex;
 
@@ -36,11 +49,53 @@ impl Vertex {
     }
 }
 
+// JOINTS_0/WEIGHTS_0 of a vertex, same layout as SkinWeights in skinning.comp
+#[repr(C)]
+#[derive(Debug, bytemuck::NoUninit, Copy, Clone)]
+pub struct GPUSkinWeights {
+    // indices into the joints of the skin
+    joints: [u32; 4],
+    weights: glm::Vec4,
+}
+
+impl GPUSkinWeights {
+    // for primitives without joints in a skinned mesh
+    fn first_joint() -> Self {
+        GPUSkinWeights {
+            joints: [0; 4],
+            weights: glm::vec4(1.0, 0.0, 0.0, 0.0),
+        }
+    }
+}
+
+// displacement of one vertex by one morph target, same layout as MorphDelta in skinning.comp
+#[repr(C)]
+#[derive(Debug, bytemuck::NoUninit, Copy, Clone)]
+pub struct GPUMorphDelta {
+    position: glm::Vec4,
+    normal: glm::Vec4,
+}
+
+impl GPUMorphDelta {
+    fn zero() -> Self {
+        GPUMorphDelta {
+            position: glm::Vec4::zeros(),
+            normal: glm::Vec4::zeros(),
+        }
+    }
+}
+
+static NEXT_MESH_BUFFERS_ID: AtomicU64 = AtomicU64::new(0);
+
 #[repr(C)]
 pub struct GPUMeshBuffers {
-    index_buffer: AllocatedBuffer,
+    // unique per vertex buffer => addresses can be reused once a buffer is freed, ids cant
+    id: u64,
+    // shared with the copies that only get their own vertices
+    index_buffer: Arc<AllocatedBuffer>,
     vertex_buffer: AllocatedBuffer,
     vertex_buffer_address: vk::DeviceAddress,
+    vertex_count: u32,
 }
 
 impl GPUMeshBuffers {
@@ -49,8 +104,14 @@ impl GPUMeshBuffers {
         allocator: Arc<Mutex<Allocator>>,
         indices: &[u32],
         vertices: &[Vertex],
-        immediate_command: &ImmediateCommandData,
-    ) -> Self {
+        uploader: &AsyncUploader,
+    ) -> Result<Self, VulkanError> {
+        // the acceleration structures of the ray tracing passes are built from both buffers
+        let build_input = if device.supports_acceleration_structures() {
+            vk::BufferUsageFlags::ACCELERATION_STRUCTURE_BUILD_INPUT_READ_ONLY_KHR
+        } else {
+            vk::BufferUsageFlags::empty()
+        };
         let vertex_buffer_size = std::mem::size_of_val(vertices);
         let vertex_buffer = AllocatedBuffer::new(
             device.clone(),
@@ -58,10 +119,11 @@ impl GPUMeshBuffers {
             "Vertex Buffer",
             vk::BufferUsageFlags::STORAGE_BUFFER
                 | vk::BufferUsageFlags::TRANSFER_DST
-                | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+                | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS
+                | build_input,
             vertex_buffer_size as vk::DeviceSize,
             gpu_allocator::MemoryLocation::GpuOnly,
-        );
+        )?;
         let buffer_device_address = vertex_buffer.get_device_address();
 
         let index_buffer_size = std::mem::size_of_val(indices);
@@ -69,10 +131,13 @@ impl GPUMeshBuffers {
             device.clone(),
             allocator.clone(),
             "Index Buffer",
-            vk::BufferUsageFlags::INDEX_BUFFER | vk::BufferUsageFlags::TRANSFER_DST,
+            vk::BufferUsageFlags::INDEX_BUFFER
+                | vk::BufferUsageFlags::TRANSFER_DST
+                | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS
+                | build_input,
             index_buffer_size as vk::DeviceSize,
             gpu_allocator::MemoryLocation::GpuOnly,
-        );
+        )?;
 
         let mut staging_buffer = AllocatedBuffer::new(
             device,
@@ -81,57 +146,111 @@ impl GPUMeshBuffers {
             vk::BufferUsageFlags::TRANSFER_SRC,
             (vertex_buffer_size + index_buffer_size) as vk::DeviceSize,
             gpu_allocator::MemoryLocation::CpuToGpu,
-        );
+        )?;
 
         staging_buffer.copy_from_slice(vertices, 0);
         staging_buffer.copy_from_slice(indices, vertex_buffer_size);
 
-        immediate_command.immediate_submit(|device, command_buffer| {
-            let vertex_copy = vk::BufferCopy {
-                src_offset: 0,
-                dst_offset: 0,
-                size: vertex_buffer_size as vk::DeviceSize,
-            };
-            device.cmd_copy_buffer(
-                command_buffer,
-                staging_buffer.buffer(),
-                vertex_buffer.buffer(),
-                &[vertex_copy],
-            );
-            let index_copy = vk::BufferCopy {
-                src_offset: vertex_buffer_size as vk::DeviceSize,
-                dst_offset: 0,
-                size: index_buffer_size as vk::DeviceSize,
-            };
-            device.cmd_copy_buffer(
-                command_buffer,
-                staging_buffer.buffer(),
-                index_buffer.buffer(),
-                &[index_copy],
-            );
-        });
+        let vertex_copy = vk::BufferCopy {
+            src_offset: 0,
+            dst_offset: 0,
+            size: vertex_buffer_size as vk::DeviceSize,
+        };
+        let index_copy = vk::BufferCopy {
+            src_offset: vertex_buffer_size as vk::DeviceSize,
+            dst_offset: 0,
+            size: index_buffer_size as vk::DeviceSize,
+        };
+        uploader.upload_buffers(
+            staging_buffer,
+            &[
+                (vertex_buffer.buffer(), vertex_copy),
+                (index_buffer.buffer(), index_copy),
+            ],
+        )?;
 
-        Self {
-            index_buffer,
+        Ok(Self {
+            id: NEXT_MESH_BUFFERS_ID.fetch_add(1, Ordering::Relaxed),
+            index_buffer: Arc::new(index_buffer),
             vertex_buffer,
             vertex_buffer_address: buffer_device_address,
-        }
+            vertex_count: vertices.len() as u32,
+        })
+    }
+
+    // same indices, the vertices are uninitialized => they have to be written by a compute shader
+    // e.g. one copy per skinned instance of a mesh
+    pub fn with_new_vertices(
+        &self,
+        device: Arc<Device>,
+        allocator: Arc<Mutex<Allocator>>,
+    ) -> Result<Self, VulkanError> {
+        let vertex_buffer = AllocatedBuffer::new(
+            device,
+            allocator,
+            "Vertex Buffer",
+            vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+            (self.vertex_count as usize * std::mem::size_of::<Vertex>()) as vk::DeviceSize,
+            gpu_allocator::MemoryLocation::GpuOnly,
+        )?;
+        Ok(Self {
+            id: NEXT_MESH_BUFFERS_ID.fetch_add(1, Ordering::Relaxed),
+            index_buffer: self.index_buffer.clone(),
+            vertex_buffer_address: vertex_buffer.get_device_address(),
+            vertex_buffer,
+            vertex_count: self.vertex_count,
+        })
+    }
+
+    // e.g. as the key of caches that outlive the mesh
+    pub fn id(&self) -> u64 {
+        self.id
     }
 
     pub fn vertex_buffer_address(&self) -> vk::DeviceAddress {
         self.vertex_buffer_address
     }
 
+    pub fn vertex_count(&self) -> u32 {
+        self.vertex_count
+    }
+
+    // vertices can also be written by compute shaders, e.g. for cloth
+    pub fn vertex_buffer(&self) -> vk::Buffer {
+        self.vertex_buffer.buffer()
+    }
+
     pub fn index_buffer(&self) -> vk::Buffer {
         self.index_buffer.buffer()
     }
+
+    pub fn index_buffer_address(&self) -> vk::DeviceAddress {
+        self.index_buffer.get_device_address()
+    }
 }
 
+// everything mesh.vert needs for one surface, pushed for single draws and stored in a draw
+// buffer for indirect draws
 #[repr(C)]
 #[derive(Debug, bytemuck::NoUninit, Copy, Clone)]
-pub struct GPUDrawPushConstants {
+pub struct GPUDrawData {
     pub world_matrix: glm::Mat4,
     pub device_address: vk::DeviceAddress,
+    // MaterialConstants of the surface
+    pub material_address: vk::DeviceAddress,
+    // slots in the bindless texture table: base color, metal rough, normal, unused
+    pub texture_indices: [u32; 4],
+    // != 0 => drawn without an index buffer, mesh.vert reads the vertex index from here
+    pub index_address: vk::DeviceAddress,
+    pub _padding: u64,
+}
+
+#[repr(C)]
+#[derive(Debug, bytemuck::NoUninit, Copy, Clone)]
+pub struct GPUDrawPushConstants {
+    pub draw: GPUDrawData,
+    // array of GPUDrawData indexed by the first instance of an indirect draw, 0 => draw is used
+    pub draw_buffer: vk::DeviceAddress,
 }
 
 impl GPUDrawPushConstants {
@@ -140,48 +259,450 @@ impl GPUDrawPushConstants {
     }
 }
 
-#[derive(Debug, Copy, Clone)]
+// axis aligned box, object space unless it was transformed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: glm::Vec3,
+    pub max: glm::Vec3,
+}
+
+impl Aabb {
+    // no points => empty box at the origin
+    pub fn from_points(points: impl Iterator<Item = glm::Vec3>) -> Self {
+        points
+            .fold(None, |bounds: Option<Aabb>, point| match bounds {
+                None => Some(Aabb {
+                    min: point,
+                    max: point,
+                }),
+                Some(bounds) => Some(Aabb {
+                    min: glm::min2(&bounds.min, &point),
+                    max: glm::max2(&bounds.max, &point),
+                }),
+            })
+            .unwrap_or(Aabb {
+                min: glm::Vec3::zeros(),
+                max: glm::Vec3::zeros(),
+            })
+    }
+
+    // never culled, e.g. for meshes that are deformed on the gpu
+    // f32::MAX instead of infinity => the center stays at the origin instead of NaN
+    pub fn infinite() -> Self {
+        Aabb {
+            min: glm::Vec3::repeat(-f32::MAX),
+            max: glm::Vec3::repeat(f32::MAX),
+        }
+    }
+
+    pub fn center(&self) -> glm::Vec3 {
+        self.min * 0.5 + self.max * 0.5
+    }
+
+    // half the size
+    pub fn extent(&self) -> glm::Vec3 {
+        self.max * 0.5 - self.min * 0.5
+    }
+
+    // box around the transformed box => grows with rotations
+    pub fn transformed(&self, matrix: &glm::Mat4) -> Self {
+        let center = matrix.transform_point(&self.center().into()).coords;
+        let rotation_scale = glm::mat4_to_mat3(matrix).abs();
+        let extent = rotation_scale * self.extent();
+        Aabb {
+            min: center - extent,
+            max: center + extent,
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct GeometricSurface {
     //idx of Surface in the buffer => we use one big buffer for whole mesh
     start_idx: usize,
     count: u32,
+    material: Arc<Material>,
+    bounds: Aabb,
 }
 
 impl GeometricSurface {
+    pub fn new(start_idx: usize, count: u32, material: Arc<Material>, bounds: Aabb) -> Self {
+        GeometricSurface {
+            start_idx,
+            count,
+            material,
+            bounds,
+        }
+    }
+
     pub fn start_idx(&self) -> usize {
         self.start_idx
     }
     pub fn count(&self) -> u32 {
         self.count
     }
+    pub fn material(&self) -> &Arc<Material> {
+        &self.material
+    }
+    pub fn bounds(&self) -> &Aabb {
+        &self.bounds
+    }
+}
+
+// gltf textures can be used as srgb (base color) and unorm (normal/metal rough) data
+// None => the image format is not supported, the error texture is used instead
+type TextureCache = HashMap<(usize, ColorSpace), Option<Arc<AllocatedImage>>>;
+
+struct GltfContext<'a> {
+    device: Arc<Device>,
+    allocator: Arc<Mutex<Allocator>>,
+    uploader: &'a AsyncUploader,
+    images: &'a [gltf::image::Data],
+    textures: TextureCache,
+}
+
+impl GltfContext<'_> {
+    fn upload_texture(
+        &mut self,
+        texture: gltf::Texture,
+        color_space: ColorSpace,
+    ) -> Result<(), VulkanError> {
+        let image_idx = texture.source().index();
+        if !self.textures.contains_key(&(image_idx, color_space)) {
+            let image = self.upload_image(image_idx, color_space)?.map(Arc::new);
+            self.textures.insert((image_idx, color_space), image);
+        }
+        Ok(())
+    }
+
+    fn upload_material_textures(&mut self, material: gltf::Material) -> Result<(), VulkanError> {
+        let pbr = material.pbr_metallic_roughness();
+        if let Some(info) = pbr.base_color_texture() {
+            self.upload_texture(info.texture(), ColorSpace::Srgb)?;
+        }
+        if let Some(info) = pbr.metallic_roughness_texture() {
+            self.upload_texture(info.texture(), ColorSpace::Linear)?;
+        }
+        if let Some(normal) = material.normal_texture() {
+            self.upload_texture(normal.texture(), ColorSpace::Linear)?;
+        }
+        Ok(())
+    }
+
+    fn upload_image(
+        &self,
+        image_idx: usize,
+        color_space: ColorSpace,
+    ) -> Result<Option<AllocatedImage>, VulkanError> {
+        let data = &self.images[image_idx];
+        let pixels: Vec<u8> = match data.format {
+            gltf::image::Format::R8G8B8A8 => data.pixels.clone(),
+            gltf::image::Format::R8G8B8 => data
+                .pixels
+                .chunks_exact(3)
+                .flat_map(|rgb| [rgb[0], rgb[1], rgb[2], 255])
+                .collect(),
+            gltf::image::Format::R8G8 => data
+                .pixels
+                .chunks_exact(2)
+                .flat_map(|rg| [rg[0], rg[1], 0, 255])
+                .collect(),
+            gltf::image::Format::R8 => data.pixels.iter().flat_map(|r| [*r, *r, *r, 255]).collect(),
+            format => {
+                log::warn!("Unsupported texture format {:?}", format);
+                return Ok(None);
+            }
+        };
+        let image = AllocatedImage::new_texture(
+            &pixels,
+            self.device.clone(),
+            self.allocator.clone(),
+            color_space.rgba8_format(),
+            vk::ImageUsageFlags::SAMPLED,
+            vk::Extent3D {
+                width: data.width,
+                height: data.height,
+                depth: 1,
+            },
+            true,
+            self.uploader,
+        )?;
+        Ok(Some(image))
+    }
+}
+
+// a mesh whose buffers are uploaded, the surfaces reference their gltf material by index
+struct PendingMesh {
+    mesh: MeshAsset,
+    surfaces: Vec<(usize, u32, Option<usize>, Aabb)>,
+}
+
+// the meshes of a gltf file with their buffers and textures uploaded. Uploading doesnt need the
+// material cache => MeshAsset::upload_gltf_meshes can run on any thread, create_materials has to
+// run on the render thread but only creates materials
+pub struct GltfMeshes {
+    meshes: Vec<PendingMesh>,
+    textures: TextureCache,
+}
+
+impl GltfMeshes {
+    // meshes and materials are returned in the order of the gltf file => node mesh and primitive
+    // material indices can be used directly. Materials no primitive uses are created too
+    pub fn create_materials(
+        self,
+        gltf: &gltf::Document,
+        file_path: &Path,
+        material_cache: &mut MaterialCache,
+    ) -> Result<(Vec<MeshAsset>, Vec<Arc<Material>>), VulkanError> {
+        let materials = gltf
+            .materials()
+            .map(|material| self.load_material(material_cache, material, file_path))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut meshes = Vec::with_capacity(self.meshes.len());
+        for pending in self.meshes.iter() {
+            let mut surfaces = Vec::with_capacity(pending.surfaces.len());
+            for (start_idx, count, material_idx, bounds) in pending.surfaces.iter() {
+                let material = match material_idx {
+                    Some(idx) => materials[*idx].clone(),
+                    // primitives without a material use the gltf default material
+                    None => material_cache.default_material(),
+                };
+                surfaces.push(GeometricSurface::new(*start_idx, *count, material, *bounds));
+            }
+            meshes.push(surfaces);
+        }
+        let meshes = self
+            .meshes
+            .into_iter()
+            .zip(meshes)
+            .map(|(pending, surfaces)| MeshAsset {
+                surfaces,
+                ..pending.mesh
+            })
+            .collect();
+        Ok((meshes, materials))
+    }
+
+    fn texture(
+        &self,
+        material_cache: &mut MaterialCache,
+        texture: gltf::Texture,
+        color_space: ColorSpace,
+    ) -> Result<MaterialTexture, VulkanError> {
+        let Some(Some(image)) = self.textures.get(&(texture.source().index(), color_space)) else {
+            return material_cache.error_texture();
+        };
+        let sampler = material_cache.sampler(Self::sampler_settings(&texture.sampler()))?;
+        Ok(MaterialTexture {
+            image: image.clone(),
+            sampler,
+        })
+    }
+
+    fn sampler_settings(sampler: &gltf::texture::Sampler) -> SamplerSettings {
+        use gltf::texture::MagFilter;
+        use gltf::texture::MinFilter;
+        use gltf::texture::WrappingMode;
+        let address_mode = |mode| match mode {
+            WrappingMode::ClampToEdge => vk::SamplerAddressMode::CLAMP_TO_EDGE,
+            WrappingMode::MirroredRepeat => vk::SamplerAddressMode::MIRRORED_REPEAT,
+            WrappingMode::Repeat => vk::SamplerAddressMode::REPEAT,
+        };
+        // no min filter => trilinear
+        let (min_filter, mipmap_mode) = match sampler.min_filter() {
+            Some(MinFilter::Nearest) => (vk::Filter::NEAREST, None),
+            Some(MinFilter::Linear) => (vk::Filter::LINEAR, None),
+            Some(MinFilter::NearestMipmapNearest) => {
+                (vk::Filter::NEAREST, Some(vk::SamplerMipmapMode::NEAREST))
+            }
+            Some(MinFilter::LinearMipmapNearest) => {
+                (vk::Filter::LINEAR, Some(vk::SamplerMipmapMode::NEAREST))
+            }
+            Some(MinFilter::NearestMipmapLinear) => {
+                (vk::Filter::NEAREST, Some(vk::SamplerMipmapMode::LINEAR))
+            }
+            Some(MinFilter::LinearMipmapLinear) | None => {
+                (vk::Filter::LINEAR, Some(vk::SamplerMipmapMode::LINEAR))
+            }
+        };
+        let mag_filter = match sampler.mag_filter() {
+            Some(MagFilter::Nearest) => vk::Filter::NEAREST,
+            _ => vk::Filter::LINEAR,
+        };
+        SamplerSettings {
+            min_filter,
+            mag_filter,
+            address_mode_u: address_mode(sampler.wrap_s()),
+            address_mode_v: address_mode(sampler.wrap_t()),
+            mipmap_mode,
+            lod_bias: 0.0,
+        }
+    }
+
+    fn load_material(
+        &self,
+        material_cache: &mut MaterialCache,
+        material: gltf::Material,
+        file_path: &Path,
+    ) -> Result<Arc<Material>, VulkanError> {
+        // primitives without a material use the gltf default material
+        let Some(material_idx) = material.index() else {
+            return Ok(material_cache.default_material());
+        };
+        let name = format!("{}#{}", file_path.display(), material_idx);
+        if let Some(material) = material_cache.get(&name) {
+            return Ok(material);
+        }
+        log::debug!(
+            "Loading material {}: {}",
+            name,
+            material.name().unwrap_or("Unnamed Material")
+        );
+
+        let pbr = material.pbr_metallic_roughness();
+        let base_color = match pbr.base_color_texture() {
+            Some(info) => Some(self.texture(material_cache, info.texture(), ColorSpace::Srgb)?),
+            None => None,
+        };
+        let metal_rough = match pbr.metallic_roughness_texture() {
+            Some(info) => Some(self.texture(material_cache, info.texture(), ColorSpace::Linear)?),
+            None => None,
+        };
+        let normal = match material.normal_texture() {
+            Some(normal) => {
+                Some(self.texture(material_cache, normal.texture(), ColorSpace::Linear)?)
+            }
+            None => None,
+        };
+        let color_factors = pbr.base_color_factor();
+        let description = MaterialDescription {
+            pass: match material.alpha_mode() {
+                gltf::material::AlphaMode::Blend => MaterialPass::Transparent,
+                // TODO: alpha cutoff for masked materials
+                _ => MaterialPass::Opaque,
+            },
+            constants: MaterialConstants {
+                color_factors: glm::vec4(
+                    color_factors[0],
+                    color_factors[1],
+                    color_factors[2],
+                    color_factors[3],
+                ),
+                metal_rough_factors: glm::vec4(
+                    pbr.metallic_factor(),
+                    pbr.roughness_factor(),
+                    0.0,
+                    0.0,
+                ),
+            },
+            base_color,
+            metal_rough,
+            normal,
+        };
+        material_cache.insert(&name, description)
+    }
+}
+
+// object space, encloses every vertex of a mesh
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingSphere {
+    pub center: glm::Vec3,
+    pub radius: f32,
+}
+
+impl BoundingSphere {
+    // centered on the bounding box => not the smallest sphere, but good enough for culling
+    pub fn from_points(points: impl Iterator<Item = glm::Vec3> + Clone) -> Self {
+        let Some((min, max)) = points.clone().fold(None, |bounds, point| match bounds {
+            None => Some((point, point)),
+            Some((min, max)) => Some((glm::min2(&min, &point), glm::max2(&max, &point))),
+        }) else {
+            return BoundingSphere {
+                center: glm::Vec3::zeros(),
+                radius: 0.0,
+            };
+        };
+        let center = (min + max) * 0.5;
+        let radius = points
+            .map(|point| glm::distance(&center, &point))
+            .fold(0.0, f32::max);
+        BoundingSphere { center, radius }
+    }
+
+    // never culled, e.g. for meshes that are deformed on the gpu
+    pub fn infinite() -> Self {
+        BoundingSphere {
+            center: glm::Vec3::zeros(),
+            radius: f32::INFINITY,
+        }
+    }
 }
 
 pub struct MeshAsset {
-    #[allow(dead_code)]
     name: String,
     surfaces: Vec<GeometricSurface>,
     buffers: GPUMeshBuffers,
+    bounds: BoundingSphere,
+    // one GPUSkinWeights per vertex, None for meshes without joints
+    skin_weights: Option<AllocatedBuffer>,
+    // vertex count GPUMorphDeltas per target, target after target
+    morph_targets: Option<AllocatedBuffer>,
+    morph_target_count: usize,
+    // used by nodes that dont have their own weights
+    default_morph_weights: Vec<f32>,
 }
 
 impl MeshAsset {
-    pub fn load_gltf(
+    pub fn new(
+        name: &str,
+        surfaces: Vec<GeometricSurface>,
+        buffers: GPUMeshBuffers,
+        bounds: BoundingSphere,
+    ) -> Self {
+        MeshAsset {
+            name: name.to_string(),
+            surfaces,
+            buffers,
+            bounds,
+            skin_weights: None,
+            morph_targets: None,
+            morph_target_count: 0,
+            default_morph_weights: Vec::new(),
+        }
+    }
+
+    // uploads the buffers and textures, the materials are created by GltfMeshes::create_materials
+    #[allow(clippy::too_many_arguments)]
+    pub fn upload_gltf_meshes(
         device: Arc<Device>,
         allocator: Arc<Mutex<Allocator>>,
-        immediate_command_data: &ImmediateCommandData,
+        uploader: &AsyncUploader,
+        gltf: &gltf::Document,
+        buffers: &[gltf::buffer::Data],
+        images: &[gltf::image::Data],
         file_path: &Path,
         overwrite_color_with_normals: bool,
-    ) -> Result<Vec<Self>, gltf::Error> {
-        log::info!("Loading GLTF from file: {:?}", file_path);
-
-        let (gltf, buffers, _) = gltf::import(file_path)?;
+    ) -> Result<GltfMeshes, AssetError> {
+        let mut context = GltfContext {
+            device: device.clone(),
+            allocator: allocator.clone(),
+            uploader,
+            images,
+            textures: HashMap::new(),
+        };
 
         let mut meshes = Vec::new();
         let mut indices = Vec::new();
         let mut vertices = Vec::new();
+        let mut skin_weights = Vec::new();
+        // one list of deltas per target
+        let mut morph_targets: Vec<Vec<GPUMorphDelta>> = Vec::new();
         for mesh in gltf.meshes() {
             // we store per mesh indices/vertices => clear them for each mesh
             indices.clear();
             vertices.clear();
+            skin_weights.clear();
+            morph_targets.clear();
             let mut surfaces = Vec::new();
 
             let mesh_name = mesh.name().unwrap_or("Unnamed Mesh");
@@ -201,8 +722,6 @@ impl MeshAsset {
                         indices.push(index + initial_vtx as u32);
                     }
                 }
-                surfaces.push(GeometricSurface { start_idx, count });
-
                 match reader.read_positions() {
                     Some(iter) => {
                         vertices.reserve(iter.len() + vertices.len());
@@ -220,8 +739,24 @@ impl MeshAsset {
                             ));
                         }
                     }
-                    None => panic!("No positions found in mesh"),
+                    // runs on the asset loading threads => an error instead of a panic
+                    None => {
+                        return Err(AssetError::UnsupportedMesh(format!(
+                            "no positions found in mesh {}",
+                            mesh_name
+                        )))
+                    }
                 }
+                let material = primitive.material();
+                if material.index().is_some() {
+                    context.upload_material_textures(material.clone())?;
+                }
+                surfaces.push((
+                    start_idx,
+                    count,
+                    material.index(),
+                    Aabb::from_points(vertices[initial_vtx..].iter().map(|vertex| vertex.position)),
+                ));
 
                 match reader.read_normals() {
                     Some(iter) => {
@@ -262,7 +797,72 @@ impl MeshAsset {
                         file_path
                     ),
                 }
+
+                // primitives without joints follow the first joint of the skin
+                if let (Some(joints), Some(weights)) =
+                    (reader.read_joints(0), reader.read_weights(0))
+                {
+                    skin_weights.resize(initial_vtx, GPUSkinWeights::first_joint());
+                    skin_weights.extend(joints.into_u16().zip(weights.into_f32()).map(
+                        |(joints, weights)| GPUSkinWeights {
+                            joints: joints.map(u32::from),
+                            weights: glm::Vec4::from(weights),
+                        },
+                    ));
+                }
+
+                // tangent deltas are ignored, the vertices dont have tangents
+                let primitive_vertex_count = vertices.len() - initial_vtx;
+                for (target_idx, (positions, normals, _)) in reader.read_morph_targets().enumerate()
+                {
+                    if morph_targets.len() <= target_idx {
+                        morph_targets.push(Vec::new());
+                    }
+                    let target = &mut morph_targets[target_idx];
+                    target.resize(initial_vtx + primitive_vertex_count, GPUMorphDelta::zero());
+                    let delta = |value: [f32; 3]| glm::vec4(value[0], value[1], value[2], 0.0);
+                    if let Some(positions) = positions {
+                        for (vertex, position) in target[initial_vtx..].iter_mut().zip(positions) {
+                            vertex.position = delta(position);
+                        }
+                    }
+                    if let Some(normals) = normals {
+                        for (vertex, normal) in target[initial_vtx..].iter_mut().zip(normals) {
+                            vertex.normal = delta(normal);
+                        }
+                    }
+                }
             }
+            let skin_weights = if skin_weights.is_empty() {
+                None
+            } else {
+                skin_weights.resize(vertices.len(), GPUSkinWeights::first_joint());
+                Some(upload_storage_buffer(
+                    device.clone(),
+                    allocator.clone(),
+                    "Skin Weights",
+                    &skin_weights,
+                    uploader,
+                )?)
+            };
+            let morph_target_buffer = if morph_targets.is_empty() {
+                None
+            } else {
+                let mut deltas = Vec::with_capacity(morph_targets.len() * vertices.len());
+                for target in morph_targets.iter_mut() {
+                    target.resize(vertices.len(), GPUMorphDelta::zero());
+                    deltas.extend_from_slice(target);
+                }
+                Some(upload_storage_buffer(
+                    device.clone(),
+                    allocator.clone(),
+                    "Morph Targets",
+                    &deltas,
+                    uploader,
+                )?)
+            };
+            let mut default_morph_weights = mesh.weights().unwrap_or_default().to_vec();
+            default_morph_weights.resize(morph_targets.len(), 0.0);
             if overwrite_color_with_normals {
                 for vertex in &mut vertices {
                     vertex.color =
@@ -271,18 +871,29 @@ impl MeshAsset {
             }
             let new_mesh = MeshAsset {
                 name: mesh_name.to_string(),
-                surfaces,
+                surfaces: Vec::new(),
+                bounds: BoundingSphere::from_points(vertices.iter().map(|vertex| vertex.position)),
                 buffers: GPUMeshBuffers::upload_mesh(
                     device.clone(),
                     allocator.clone(),
                     &indices,
                     &vertices,
-                    immediate_command_data,
-                ),
+                    uploader,
+                )?,
+                skin_weights,
+                morph_targets: morph_target_buffer,
+                morph_target_count: morph_targets.len(),
+                default_morph_weights,
             };
-            meshes.push(new_mesh);
+            meshes.push(PendingMesh {
+                mesh: new_mesh,
+                surfaces,
+            });
         }
-        Ok(meshes)
+        Ok(GltfMeshes {
+            meshes,
+            textures: context.textures,
+        })
     }
 
     pub fn buffers(&self) -> &GPUMeshBuffers {
@@ -293,10 +904,138 @@ impl MeshAsset {
         &self.surfaces
     }
 
-    #[allow(dead_code)]
     pub fn name(&self) -> &str {
         &self.name
     }
+
+    pub fn bounds(&self) -> &BoundingSphere {
+        &self.bounds
+    }
+
+    pub fn skin_weights(&self) -> Option<&AllocatedBuffer> {
+        self.skin_weights.as_ref()
+    }
+
+    pub fn morph_targets(&self) -> Option<&AllocatedBuffer> {
+        self.morph_targets.as_ref()
+    }
+
+    pub fn morph_target_count(&self) -> usize {
+        self.morph_target_count
+    }
+
+    // one weight per morph target
+    pub fn default_morph_weights(&self) -> &[f32] {
+        &self.default_morph_weights
+    }
+}
+
+// gpu only storage buffer that is read through its device address
+fn upload_storage_buffer<T: bytemuck::NoUninit>(
+    device: Arc<Device>,
+    allocator: Arc<Mutex<Allocator>>,
+    name: &str,
+    data: &[T],
+    uploader: &AsyncUploader,
+) -> Result<AllocatedBuffer, VulkanError> {
+    let size = std::mem::size_of_val(data) as vk::DeviceSize;
+    let buffer = AllocatedBuffer::new(
+        device.clone(),
+        allocator.clone(),
+        name,
+        vk::BufferUsageFlags::STORAGE_BUFFER
+            | vk::BufferUsageFlags::TRANSFER_DST
+            | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+        size,
+        gpu_allocator::MemoryLocation::GpuOnly,
+    )?;
+    let mut staging_buffer = AllocatedBuffer::new(
+        device,
+        allocator,
+        "Staging Buffer",
+        vk::BufferUsageFlags::TRANSFER_SRC,
+        size,
+        gpu_allocator::MemoryLocation::CpuToGpu,
+    )?;
+    staging_buffer.copy_from_slice(data, 0);
+    uploader.upload_buffers(
+        staging_buffer,
+        &[(
+            buffer.buffer(),
+            vk::BufferCopy {
+                src_offset: 0,
+                dst_offset: 0,
+                size,
+            },
+        )],
+    )?;
+    Ok(buffer)
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SamplerSettings {
+    pub min_filter: vk::Filter,
+    pub mag_filter: vk::Filter,
+    pub address_mode_u: vk::SamplerAddressMode,
+    pub address_mode_v: vk::SamplerAddressMode,
+    // None => only the base level is sampled
+    pub mipmap_mode: Option<vk::SamplerMipmapMode>,
+    // added to the computed level of detail, > 0 => blurrier, < 0 => sharper
+    pub lod_bias: f32,
+}
+
+impl SamplerSettings {
+    pub fn new(min_filter: vk::Filter, mag_filter: vk::Filter) -> Self {
+        SamplerSettings {
+            min_filter,
+            mag_filter,
+            address_mode_u: vk::SamplerAddressMode::REPEAT,
+            address_mode_v: vk::SamplerAddressMode::REPEAT,
+            mipmap_mode: Some(vk::SamplerMipmapMode::LINEAR),
+            lod_bias: 0.0,
+        }
+    }
+
+    pub fn mipmap_mode(mut self, mipmap_mode: Option<vk::SamplerMipmapMode>) -> Self {
+        self.mipmap_mode = mipmap_mode;
+        self
+    }
+
+    pub fn lod_bias(mut self, lod_bias: f32) -> Self {
+        self.lod_bias = lod_bias;
+        self
+    }
+
+    pub fn address_mode(mut self, address_mode: vk::SamplerAddressMode) -> Self {
+        self.address_mode_u = address_mode;
+        self.address_mode_v = address_mode;
+        self
+    }
+}
+
+// settings are used as key of the sampler cache => compare the bias bitwise, same as the hash
+impl PartialEq for SamplerSettings {
+    fn eq(&self, other: &Self) -> bool {
+        self.min_filter == other.min_filter
+            && self.mag_filter == other.mag_filter
+            && self.address_mode_u == other.address_mode_u
+            && self.address_mode_v == other.address_mode_v
+            && self.mipmap_mode == other.mipmap_mode
+            && self.lod_bias.to_bits() == other.lod_bias.to_bits()
+    }
+}
+
+impl Eq for SamplerSettings {}
+
+impl std::hash::Hash for SamplerSettings {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.min_filter.hash(state);
+        self.mag_filter.hash(state);
+        self.address_mode_u.hash(state);
+        self.address_mode_v.hash(state);
+        self.mipmap_mode.hash(state);
+        self.lod_bias.to_bits().hash(state);
+    }
 }
 
 pub struct Sampler {
@@ -305,17 +1044,40 @@ pub struct Sampler {
 }
 
 impl Sampler {
-    pub fn new(device: Arc<Device>, min_filter: vk::Filter, mag_filter: vk::Filter) -> Self {
+    pub fn new(
+        device: Arc<Device>,
+        min_filter: vk::Filter,
+        mag_filter: vk::Filter,
+    ) -> Result<Self, VulkanError> {
+        Self::with_settings(device, &SamplerSettings::new(min_filter, mag_filter))
+    }
+
+    pub fn with_settings(
+        device: Arc<Device>,
+        settings: &SamplerSettings,
+    ) -> Result<Self, VulkanError> {
         let create_info = vk::SamplerCreateInfo {
             s_type: vk::StructureType::SAMPLER_CREATE_INFO,
             p_next: std::ptr::null(),
             flags: vk::SamplerCreateFlags::empty(),
-            mag_filter,
-            min_filter,
+            mag_filter: settings.mag_filter,
+            min_filter: settings.min_filter,
+            address_mode_u: settings.address_mode_u,
+            address_mode_v: settings.address_mode_v,
+            mipmap_mode: settings
+                .mipmap_mode
+                .unwrap_or(vk::SamplerMipmapMode::NEAREST),
+            mip_lod_bias: settings.lod_bias,
+            min_lod: 0.0,
+            max_lod: if settings.mipmap_mode.is_some() {
+                vk::LOD_CLAMP_NONE
+            } else {
+                0.0
+            },
             ..Default::default()
         };
-        let sampler = device.create_sampler(&create_info);
-        Self { device, sampler }
+        let sampler = device.create_sampler(&create_info)?;
+        Ok(Self { device, sampler })
     }
 
     pub fn sampler(&self) -> vk::Sampler {
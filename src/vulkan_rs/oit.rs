@@ -0,0 +1,280 @@
+use super::allocation::AllocatedImage;
+use super::allocation::Allocator;
+use super::descriptor::DescriptorAllocator;
+use super::descriptor::DescriptorLayoutBuilder;
+use super::descriptor::DescriptorSetLayout;
+use super::descriptor::DescriptorSetSlot;
+use super::descriptor::DescriptorWriter;
+use super::descriptor::SetLayouts;
+use super::device::Device;
+use super::draw_batches::DrawBatches;
+use super::error::VulkanError;
+use super::material::MaterialPass;
+use super::mesh::GPUDrawPushConstants;
+use super::mesh::Sampler;
+use super::pipelines::GraphicsPipeline;
+use super::pipelines::GraphicsPipelineBuilder;
+use super::render_graph::ImageHandle;
+use super::render_graph::RenderGraph;
+use super::shader::ShaderModule;
+use ash::vk;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+// same order as the outputs of mesh_oit.frag
+const OIT_FORMATS: [vk::Format; 2] = [
+    // rgb = weighted premultiplied color, a = weighted alpha
+    vk::Format::R16G16B16A16_SFLOAT,
+    // sum of -log(1 - alpha)
+    vk::Format::R16_SFLOAT,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transparency {
+    // alpha blended back to front, exact but only sorted within a mesh
+    Sorted,
+    // weighted blended order independent transparency => no sorting, but only an approximation
+    // where many layers overlap, e.g. particles and foliage
+    WeightedBlended,
+}
+
+// weighted blended order independent transparency in two passes:
+//   accumulate: transparent surfaces with mesh_oit.frag into the accumulation and revealage
+//               images, additive blending, depth tested against the opaque surfaces
+//   composite: full screen triangle, blends the weighted average color over the draw image
+pub struct Oit {
+    device: Arc<Device>,
+    // accumulation, revealage
+    images: [AllocatedImage; 2],
+    // only referenced by the descriptor
+    _sampler: Sampler,
+    _descriptor_layout: DescriptorSetLayout,
+    descriptor: vk::DescriptorSet,
+    accumulate_pipeline: GraphicsPipeline,
+    composite_pipeline: GraphicsPipeline,
+}
+
+impl Oit {
+    pub fn new(
+        device: Arc<Device>,
+        allocator: Arc<Mutex<Allocator>>,
+        descriptor_allocator: &DescriptorAllocator,
+        draw_image: &AllocatedImage,
+        depth_format: vk::Format,
+        scene_data_layout: &DescriptorSetLayout,
+        texture_table_layout: vk::DescriptorSetLayout,
+    ) -> Result<Self, VulkanError> {
+        let new_image = |format: vk::Format, name: &str| {
+            let image = AllocatedImage::new(
+                device.clone(),
+                allocator.clone(),
+                format,
+                vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+                draw_image.extent(),
+                vk::ImageAspectFlags::COLOR,
+                1,
+            )?;
+            image.set_name(name);
+            Ok::<_, VulkanError>(image)
+        };
+        let images = [
+            new_image(OIT_FORMATS[0], "oit accumulation")?,
+            new_image(OIT_FORMATS[1], "oit revealage")?,
+        ];
+        // texel fetches only, the filter doesnt matter
+        let sampler = Sampler::new(device.clone(), vk::Filter::NEAREST, vk::Filter::NEAREST)?;
+
+        let mut builder = DescriptorLayoutBuilder::new();
+        for binding in 0..2 {
+            builder.add_binding(
+                binding,
+                vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                vk::ShaderStageFlags::FRAGMENT,
+            );
+        }
+        let descriptor_layout =
+            builder.build(device.clone(), vk::DescriptorSetLayoutCreateFlags::empty())?;
+        let descriptor = descriptor_allocator.allocate(descriptor_layout.layout())?;
+        let mut writer = DescriptorWriter::new();
+        for (binding, image) in images.iter().enumerate() {
+            writer.add_image(
+                binding as i32,
+                image.image_view(),
+                sampler.sampler(),
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            );
+        }
+        writer.update_descriptor_set(&device, descriptor);
+
+        let accumulate_pipeline = Self::build_accumulate_pipeline(
+            device.clone(),
+            scene_data_layout.layout(),
+            texture_table_layout,
+            depth_format,
+        )?;
+        let composite_pipeline = Self::build_composite_pipeline(
+            device.clone(),
+            descriptor_layout.layout(),
+            draw_image.format(),
+        )?;
+
+        Ok(Oit {
+            device,
+            images,
+            _sampler: sampler,
+            _descriptor_layout: descriptor_layout,
+            descriptor,
+            accumulate_pipeline,
+            composite_pipeline,
+        })
+    }
+
+    // same layout as the transparent material pipeline => draw_batches can record into it
+    fn build_accumulate_pipeline(
+        device: Arc<Device>,
+        scene_data_layout: vk::DescriptorSetLayout,
+        texture_table_layout: vk::DescriptorSetLayout,
+        depth_format: vk::Format,
+    ) -> Result<GraphicsPipeline, VulkanError> {
+        let push_constants = vk::PushConstantRange {
+            stage_flags: vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+            offset: 0,
+            size: std::mem::size_of::<GPUDrawPushConstants>() as u32,
+        };
+        let set_layouts = SetLayouts::new()
+            .with(DescriptorSetSlot::Scene, scene_data_layout)
+            .with(DescriptorSetSlot::Material, texture_table_layout);
+        let layout_info = vk::PipelineLayoutCreateInfo {
+            s_type: vk::StructureType::PIPELINE_LAYOUT_CREATE_INFO,
+            p_next: std::ptr::null(),
+            flags: vk::PipelineLayoutCreateFlags::empty(),
+            set_layout_count: set_layouts.layouts().len() as u32,
+            p_set_layouts: set_layouts.layouts().as_ptr(),
+            push_constant_range_count: 1,
+            p_push_constant_ranges: &push_constants,
+            ..Default::default()
+        };
+        let pipeline_layout = device.create_pipeline_layout(&layout_info)?;
+        let frag_shader = ShaderModule::new(device.clone(), "shaders/mesh_oit_frag.spv")?;
+        let vert_shader = ShaderModule::new(device.clone(), "shaders/mesh_vert.spv")?;
+        // the order doesnt matter => the sums can be blended in any order
+        GraphicsPipelineBuilder::new()
+            .set_layout(pipeline_layout)
+            .set_shaders(&frag_shader, &vert_shader)
+            .set_input_topology(vk::PrimitiveTopology::TRIANGLE_LIST)
+            .set_polygon_mode(vk::PolygonMode::FILL)
+            .set_cull_mode(vk::CullModeFlags::NONE, vk::FrontFace::CLOCKWISE)
+            .disable_multisampling()
+            .set_color_attachment_formats(&OIT_FORMATS)
+            .set_depth_format(depth_format)
+            .enable_blending_accumulate()
+            .enable_depth_test(vk::FALSE, vk::CompareOp::GREATER_OR_EQUAL)
+            .build_pipeline(device)
+    }
+
+    fn build_composite_pipeline(
+        device: Arc<Device>,
+        descriptor_layout: vk::DescriptorSetLayout,
+        color_format: vk::Format,
+    ) -> Result<GraphicsPipeline, VulkanError> {
+        let layout_info = vk::PipelineLayoutCreateInfo {
+            s_type: vk::StructureType::PIPELINE_LAYOUT_CREATE_INFO,
+            p_next: std::ptr::null(),
+            flags: vk::PipelineLayoutCreateFlags::empty(),
+            set_layout_count: 1,
+            p_set_layouts: &descriptor_layout,
+            ..Default::default()
+        };
+        let pipeline_layout = device.create_pipeline_layout(&layout_info)?;
+        let frag_shader = ShaderModule::new(device.clone(), "shaders/oit_composite_frag.spv")?;
+        // the full screen triangle of the skybox
+        let vert_shader = ShaderModule::new(device.clone(), "shaders/skybox_vert.spv")?;
+        GraphicsPipelineBuilder::new()
+            .set_layout(pipeline_layout)
+            .set_shaders(&frag_shader, &vert_shader)
+            .set_input_topology(vk::PrimitiveTopology::TRIANGLE_LIST)
+            .set_polygon_mode(vk::PolygonMode::FILL)
+            .set_cull_mode(vk::CullModeFlags::NONE, vk::FrontFace::CLOCKWISE)
+            .disable_multisampling()
+            .set_color_attachment_format(color_format)
+            .enable_blending_alphablend()
+            .disable_depth_test()
+            .build_pipeline(device)
+    }
+
+    // accumulation, revealage, cleared by the accumulate pass every frame
+    pub fn import_images(&self, graph: &mut RenderGraph, extent: vk::Extent2D) -> [ImageHandle; 2] {
+        self.images.each_ref().map(|image| {
+            let handle = graph.import_image(
+                "oit image",
+                image.image(),
+                vk::ImageAspectFlags::COLOR,
+                vk::ImageLayout::UNDEFINED,
+            );
+            graph.set_image_size(handle, extent, image.format());
+            handle
+        })
+    }
+
+    // expects the depth image with the opaque surfaces, it is not written
+    pub fn record_accumulate(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        depth_image: vk::ImageView,
+        extent: vk::Extent2D,
+        scene_descriptor_set: vk::DescriptorSet,
+        texture_descriptor_set: vk::DescriptorSet,
+        draw_batches: &DrawBatches,
+    ) {
+        let pipeline = &self.accumulate_pipeline;
+        let color_images = self.images.each_ref().map(|image| image.image_view());
+        pipeline.begin_drawing_multiple_load_depth(
+            command_buffer,
+            &color_images,
+            depth_image,
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL,
+            extent,
+        );
+        self.device.cmd_bind_descriptor_set(
+            command_buffer,
+            pipeline.layout(),
+            vk::PipelineBindPoint::GRAPHICS,
+            DescriptorSetSlot::Scene,
+            scene_descriptor_set,
+        );
+        self.device.cmd_bind_descriptor_set(
+            command_buffer,
+            pipeline.layout(),
+            vk::PipelineBindPoint::GRAPHICS,
+            DescriptorSetSlot::Material,
+            texture_descriptor_set,
+        );
+        draw_batches.record_culled(command_buffer, pipeline, MaterialPass::Transparent);
+        pipeline.end_drawing(command_buffer);
+    }
+
+    pub fn record_composite(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        draw_image: vk::ImageView,
+        extent: vk::Extent2D,
+    ) {
+        let pipeline = &self.composite_pipeline;
+        pipeline.begin_color_only(
+            command_buffer,
+            draw_image,
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            extent,
+        );
+        self.device.cmd_bind_descriptor_sets(
+            command_buffer,
+            pipeline.layout(),
+            vk::PipelineBindPoint::GRAPHICS,
+            &[self.descriptor],
+        );
+        pipeline.draw_generated(command_buffer, 3, &[]);
+        pipeline.end_drawing(command_buffer);
+    }
+}
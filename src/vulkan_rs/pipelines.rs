@@ -1,7 +1,11 @@
 use super::device::Device;
+use super::error::VulkanError;
+use super::material::Material;
+use super::mesh::GeometricSurface;
 use super::shader::ShaderModule;
 use super::MeshAsset;
 use ash::vk;
+use nalgebra_glm as glm;
 use nalgebra_glm::Vec4;
 use std::sync::Arc;
 
@@ -15,8 +19,13 @@ pub struct PushConstants {
 }
 
 impl PushConstants {
-    pub fn as_bytes(&self) -> &[u8] {
-        bytemuck::bytes_of(self)
+    pub fn new(data1: Vec4, data2: Vec4, data3: Vec4, data4: Vec4) -> Self {
+        PushConstants {
+            data1,
+            data2,
+            data3,
+            data4,
+        }
     }
 }
 
@@ -31,11 +40,14 @@ impl ComputePipeline {
         device: Arc<Device>,
         set_layouts: &[vk::DescriptorSetLayout],
         shader: ShaderModule,
-    ) -> Self {
+    ) -> Result<Self, VulkanError> {
+        // execute_compute always pushes the whole struct => the range can not be smaller
         let push_constants = vk::PushConstantRange {
             stage_flags: vk::ShaderStageFlags::COMPUTE,
             offset: 0,
-            size: std::mem::size_of::<PushConstants>() as u32,
+            size: shader
+                .push_constant_size()
+                .max(std::mem::size_of::<PushConstants>() as u32),
         };
         let layout_create_info = vk::PipelineLayoutCreateInfo {
             s_type: vk::StructureType::PIPELINE_LAYOUT_CREATE_INFO,
@@ -46,7 +58,7 @@ impl ComputePipeline {
             p_push_constant_ranges: &push_constants,
             ..Default::default()
         };
-        let pipeline_layout = device.create_pipeline_layout(&layout_create_info);
+        let pipeline_layout = device.create_pipeline_layout(&layout_create_info)?;
         let stage_info = shader.create_shader_stage_info(vk::ShaderStageFlags::COMPUTE);
 
         let pipeline_create_info = vk::ComputePipelineCreateInfo {
@@ -58,12 +70,20 @@ impl ComputePipeline {
         };
 
         // we pass only one create info => should get exactly one pipeline
-        let pipeline = device.create_compute_pipelines(&[pipeline_create_info])[0];
-        Self {
+        let pipeline = match device.create_compute_pipelines(&[pipeline_create_info]) {
+            Ok(pipelines) => pipelines[0],
+            Err(e) => {
+                device.destroy_pipeline_layout(pipeline_layout);
+                return Err(e);
+            }
+        };
+        device.set_object_name(pipeline, shader.name());
+        device.set_object_name(pipeline_layout, &format!("{} layout", shader.name()));
+        Ok(Self {
             device,
             pipeline,
             pipeline_layout,
-        }
+        })
     }
 
     pub fn execute_compute(
@@ -72,25 +92,55 @@ impl ComputePipeline {
         descriptor_sets: &[vk::DescriptorSet],
         extent: vk::Extent2D,
     ) {
-        let group_counts = [
-            (extent.width as f32 / 16.0).ceil() as u32,
-            (extent.height as f32 / 16.0).ceil() as u32,
-            1,
-        ];
         let push_constants = PushConstants {
             data1: Vec4::new(1.0, 0.0, 0.0, 1.0),
             data2: Vec4::new(0.0, 0.0, 1.0, 1.0),
             data3: Vec4::new(0.0, 0.0, 0.0, 0.0),
             data4: Vec4::new(0.0, 0.0, 0.0, 0.0),
         };
+        self.execute_compute_with_constants(
+            command_buffer,
+            descriptor_sets,
+            extent,
+            &push_constants,
+        );
+    }
+
+    pub fn execute_compute_with_constants(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        descriptor_sets: &[vk::DescriptorSet],
+        extent: vk::Extent2D,
+        push_constants: &PushConstants,
+    ) {
+        let group_counts = [
+            (extent.width as f32 / 16.0).ceil() as u32,
+            (extent.height as f32 / 16.0).ceil() as u32,
+            1,
+        ];
+        self.dispatch(
+            command_buffer,
+            descriptor_sets,
+            group_counts,
+            push_constants,
+        )
+    }
 
+    // for shaders with their own push constant block instead of PushConstants
+    pub fn dispatch<T: bytemuck::NoUninit>(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        descriptor_sets: &[vk::DescriptorSet],
+        group_counts: [u32; 3],
+        push_constants: &T,
+    ) {
         self.device.execute_compute_pipeline(
             command_buffer,
             self.pipeline,
             self.pipeline_layout,
             descriptor_sets,
             group_counts,
-            &push_constants,
+            bytemuck::bytes_of(push_constants),
         )
     }
 }
@@ -120,6 +170,175 @@ impl GraphicsPipeline {
         depth_image_layout: vk::ImageLayout,
         render_extent: vk::Extent2D,
         clear_color: Option<vk::ClearColorValue>,
+    ) {
+        self.begin_rendering(
+            command_buffer,
+            color_image,
+            depth_image,
+            color_image_layout,
+            depth_image_layout,
+            render_extent,
+            clear_color,
+            true,
+        );
+    }
+
+    // depth test against what earlier passes rendered, e.g. for overlays that dont write depth
+    #[allow(clippy::too_many_arguments)]
+    pub fn begin_drawing_load_depth(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        color_image: vk::ImageView,
+        depth_image: vk::ImageView,
+        color_image_layout: vk::ImageLayout,
+        depth_image_layout: vk::ImageLayout,
+        render_extent: vk::Extent2D,
+        clear_color: Option<vk::ClearColorValue>,
+    ) {
+        self.begin_rendering(
+            command_buffer,
+            color_image,
+            depth_image,
+            color_image_layout,
+            depth_image_layout,
+            render_extent,
+            clear_color,
+            false,
+        );
+    }
+
+    // multiple render targets, all colors are cleared to zero and the depth is cleared too
+    pub fn begin_drawing_multiple(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        color_images: &[vk::ImageView],
+        depth_image: vk::ImageView,
+        color_image_layout: vk::ImageLayout,
+        depth_image_layout: vk::ImageLayout,
+        render_extent: vk::Extent2D,
+    ) {
+        self.begin_rendering_multiple(
+            command_buffer,
+            color_images,
+            depth_image,
+            color_image_layout,
+            depth_image_layout,
+            render_extent,
+            true,
+        );
+    }
+
+    // like begin_drawing_multiple, but depth tests against what earlier passes rendered
+    pub fn begin_drawing_multiple_load_depth(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        color_images: &[vk::ImageView],
+        depth_image: vk::ImageView,
+        color_image_layout: vk::ImageLayout,
+        depth_image_layout: vk::ImageLayout,
+        render_extent: vk::Extent2D,
+    ) {
+        self.begin_rendering_multiple(
+            command_buffer,
+            color_images,
+            depth_image,
+            color_image_layout,
+            depth_image_layout,
+            render_extent,
+            false,
+        );
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn begin_rendering_multiple(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        color_images: &[vk::ImageView],
+        depth_image: vk::ImageView,
+        color_image_layout: vk::ImageLayout,
+        depth_image_layout: vk::ImageLayout,
+        render_extent: vk::Extent2D,
+        clear_depth: bool,
+    ) {
+        let color_attachment_infos: Vec<_> = color_images
+            .iter()
+            .map(|color_image| vk::RenderingAttachmentInfo {
+                s_type: vk::StructureType::RENDERING_ATTACHMENT_INFO,
+                p_next: std::ptr::null(),
+                image_view: *color_image,
+                image_layout: color_image_layout,
+                load_op: vk::AttachmentLoadOp::CLEAR,
+                store_op: vk::AttachmentStoreOp::STORE,
+                clear_value: vk::ClearValue::default(),
+                ..Default::default()
+            })
+            .collect();
+        let depth_attachment_info = vk::RenderingAttachmentInfo {
+            s_type: vk::StructureType::RENDERING_ATTACHMENT_INFO,
+            p_next: std::ptr::null(),
+            image_view: depth_image,
+            image_layout: depth_image_layout,
+            load_op: if clear_depth {
+                vk::AttachmentLoadOp::CLEAR
+            } else {
+                vk::AttachmentLoadOp::LOAD
+            },
+            store_op: vk::AttachmentStoreOp::STORE,
+            // reversed depth => 0 is the far plane
+            clear_value: vk::ClearValue {
+                depth_stencil: vk::ClearDepthStencilValue {
+                    depth: 0.0,
+                    stencil: 0,
+                },
+            },
+            ..Default::default()
+        };
+        let rendering_info = vk::RenderingInfo {
+            s_type: vk::StructureType::RENDERING_INFO,
+            p_next: std::ptr::null(),
+            render_area: vk::Rect2D {
+                offset: vk::Offset2D { x: 0, y: 0 },
+                extent: render_extent,
+            },
+            layer_count: 1,
+            color_attachment_count: color_attachment_infos.len() as u32,
+            p_color_attachments: color_attachment_infos.as_ptr(),
+            p_depth_attachment: &depth_attachment_info,
+            p_stencil_attachment: std::ptr::null(),
+            ..Default::default()
+        };
+        let view_port = vk::Viewport {
+            x: 0.0,
+            y: 0.0,
+            width: render_extent.width as f32,
+            height: render_extent.height as f32,
+            min_depth: 0.0,
+            max_depth: 1.0,
+        };
+        let scissor = vk::Rect2D {
+            offset: vk::Offset2D { x: 0, y: 0 },
+            extent: render_extent,
+        };
+        self.device.begin_rendering(
+            command_buffer,
+            &rendering_info,
+            self.pipeline,
+            view_port,
+            scissor,
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn begin_rendering(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        color_image: vk::ImageView,
+        depth_image: vk::ImageView,
+        color_image_layout: vk::ImageLayout,
+        depth_image_layout: vk::ImageLayout,
+        render_extent: vk::Extent2D,
+        clear_color: Option<vk::ClearColorValue>,
+        clear_depth: bool,
     ) {
         let color_attachment_info = vk::RenderingAttachmentInfo {
             s_type: vk::StructureType::RENDERING_ATTACHMENT_INFO,
@@ -145,7 +364,11 @@ impl GraphicsPipeline {
             p_next: std::ptr::null(),
             image_view: depth_image,
             image_layout: depth_image_layout,
-            load_op: vk::AttachmentLoadOp::CLEAR,
+            load_op: if clear_depth {
+                vk::AttachmentLoadOp::CLEAR
+            } else {
+                vk::AttachmentLoadOp::LOAD
+            },
             store_op: vk::AttachmentStoreOp::STORE,
             clear_value: vk::ClearValue {
                 depth_stencil: vk::ClearDepthStencilValue {
@@ -194,18 +417,243 @@ impl GraphicsPipeline {
         )
     }
 
+    // for pipelines without color attachments, e.g. shadow maps
+    pub fn begin_depth_only(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        depth_image: vk::ImageView,
+        depth_image_layout: vk::ImageLayout,
+        render_extent: vk::Extent2D,
+    ) {
+        let depth_attachment_info = vk::RenderingAttachmentInfo {
+            s_type: vk::StructureType::RENDERING_ATTACHMENT_INFO,
+            p_next: std::ptr::null(),
+            image_view: depth_image,
+            image_layout: depth_image_layout,
+            load_op: vk::AttachmentLoadOp::CLEAR,
+            store_op: vk::AttachmentStoreOp::STORE,
+            // reversed depth => 0 is the far plane
+            clear_value: vk::ClearValue {
+                depth_stencil: vk::ClearDepthStencilValue {
+                    depth: 0.0,
+                    stencil: 0,
+                },
+            },
+            ..Default::default()
+        };
+        let rendering_info = vk::RenderingInfo {
+            s_type: vk::StructureType::RENDERING_INFO,
+            p_next: std::ptr::null(),
+            render_area: vk::Rect2D {
+                offset: vk::Offset2D { x: 0, y: 0 },
+                extent: render_extent,
+            },
+            layer_count: 1,
+            color_attachment_count: 0,
+            p_color_attachments: std::ptr::null(),
+            p_depth_attachment: &depth_attachment_info,
+            p_stencil_attachment: std::ptr::null(),
+            ..Default::default()
+        };
+        let view_port = vk::Viewport {
+            x: 0.0,
+            y: 0.0,
+            width: render_extent.width as f32,
+            height: render_extent.height as f32,
+            min_depth: 0.0,
+            max_depth: 1.0,
+        };
+        let scissor = vk::Rect2D {
+            offset: vk::Offset2D { x: 0, y: 0 },
+            extent: render_extent,
+        };
+        self.device.begin_rendering(
+            command_buffer,
+            &rendering_info,
+            self.pipeline,
+            view_port,
+            scissor,
+        )
+    }
+
+    // without depth, draws on top of what is already in the color image, e.g. the ui
+    pub fn begin_color_only(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        color_image: vk::ImageView,
+        color_image_layout: vk::ImageLayout,
+        render_extent: vk::Extent2D,
+    ) {
+        let color_attachment_info = vk::RenderingAttachmentInfo {
+            s_type: vk::StructureType::RENDERING_ATTACHMENT_INFO,
+            p_next: std::ptr::null(),
+            image_view: color_image,
+            image_layout: color_image_layout,
+            load_op: vk::AttachmentLoadOp::LOAD,
+            store_op: vk::AttachmentStoreOp::STORE,
+            ..Default::default()
+        };
+        let rendering_info = vk::RenderingInfo {
+            s_type: vk::StructureType::RENDERING_INFO,
+            p_next: std::ptr::null(),
+            render_area: vk::Rect2D {
+                offset: vk::Offset2D { x: 0, y: 0 },
+                extent: render_extent,
+            },
+            layer_count: 1,
+            color_attachment_count: 1,
+            p_color_attachments: &color_attachment_info,
+            p_depth_attachment: std::ptr::null(),
+            p_stencil_attachment: std::ptr::null(),
+            ..Default::default()
+        };
+        let view_port = vk::Viewport {
+            x: 0.0,
+            y: 0.0,
+            width: render_extent.width as f32,
+            height: render_extent.height as f32,
+            min_depth: 0.0,
+            max_depth: 1.0,
+        };
+        let scissor = vk::Rect2D {
+            offset: vk::Offset2D { x: 0, y: 0 },
+            extent: render_extent,
+        };
+        self.device.begin_rendering(
+            command_buffer,
+            &rendering_info,
+            self.pipeline,
+            view_port,
+            scissor,
+        )
+    }
+
     pub fn end_drawing(&self, command_buffer: vk::CommandBuffer) {
         self.device.end_rendering(command_buffer);
     }
 
+    // begin_drawing already binds the pipeline => only needed when switching pipelines
+    pub fn bind(&self, command_buffer: vk::CommandBuffer) {
+        self.device
+            .cmd_bind_graphics_pipeline(command_buffer, self.pipeline);
+    }
+
     pub fn draw(
         &self,
         command_buffer: vk::CommandBuffer,
-        render_extent: vk::Extent2D,
         mesh: &MeshAsset,
+        surface: &GeometricSurface,
+        material: &Material,
+        world_matrix: &glm::Mat4,
     ) {
-        self.device
-            .draw_mesh(command_buffer, self.pipeline_layout, render_extent, mesh);
+        self.device.draw_mesh(
+            command_buffer,
+            self.pipeline_layout,
+            mesh,
+            surface,
+            material,
+            world_matrix,
+        );
+    }
+
+    // for pipelines with their own push constants instead of GPUDrawPushConstants
+    pub fn draw_with_constants(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        mesh: &MeshAsset,
+        surface: &GeometricSurface,
+        push_constants: &[u8],
+    ) {
+        self.device.draw_surface(
+            command_buffer,
+            self.pipeline_layout,
+            mesh,
+            surface,
+            push_constants,
+        );
+    }
+
+    // without vertex or index buffer, the vertex shader generates the vertices
+    pub fn draw_generated(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        vertex_count: u32,
+        push_constants: &[u8],
+    ) {
+        self.device.draw_generated(
+            command_buffer,
+            self.pipeline_layout,
+            vertex_count,
+            push_constants,
+        );
+    }
+
+    // vertex pulling => the push constants carry the vertex buffer address
+    pub fn draw_indexed(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        index_buffer: vk::Buffer,
+        first_index: u32,
+        index_count: u32,
+        vertex_offset: i32,
+        push_constants: &[u8],
+    ) {
+        self.device.draw_indexed(
+            command_buffer,
+            self.pipeline_layout,
+            index_buffer,
+            first_index,
+            index_count,
+            vertex_offset,
+            push_constants,
+        );
+    }
+
+    // draw_count draws that share one index buffer, their GPUDrawData is at draw_buffer
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw_indexed_indirect(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        index_buffer: vk::Buffer,
+        indirect_buffer: vk::Buffer,
+        offset: vk::DeviceSize,
+        draw_count: u32,
+        count_buffer: Option<(vk::Buffer, vk::DeviceSize)>,
+        draw_buffer: vk::DeviceAddress,
+    ) {
+        self.device.draw_indexed_indirect(
+            command_buffer,
+            self.pipeline_layout,
+            index_buffer,
+            indirect_buffer,
+            offset,
+            draw_count,
+            count_buffer,
+            draw_buffer,
+        );
+    }
+
+    // draw_count draws without an index buffer, their GPUDrawData is at draw_buffer
+    pub fn draw_indirect(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        indirect_buffer: vk::Buffer,
+        offset: vk::DeviceSize,
+        draw_count: u32,
+        draw_buffer: vk::DeviceAddress,
+    ) {
+        self.device.draw_indirect(
+            command_buffer,
+            self.pipeline_layout,
+            indirect_buffer,
+            offset,
+            draw_count,
+            draw_buffer,
+        );
+    }
+
+    pub fn set_scissor(&self, command_buffer: vk::CommandBuffer, scissor: vk::Rect2D) {
+        self.device.cmd_set_scissor(command_buffer, scissor);
     }
 
     pub fn layout(&self) -> vk::PipelineLayout {
@@ -229,8 +677,11 @@ pub struct GraphicsPipelineBuilder<'a> {
     multisampling_info: vk::PipelineMultisampleStateCreateInfo<'a>,
     depth_stencil_info: vk::PipelineDepthStencilStateCreateInfo<'a>,
     rendering_info: vk::PipelineRenderingCreateInfo<'a>,
-    color_attachment_format: vk::Format,
+    // the builder is moved around => the rendering info only points to them in build_pipeline
+    color_attachment_formats: Vec<vk::Format>,
     pipeline_layout: Option<vk::PipelineLayout>,
+    // debug name, made up from the shader names
+    name: String,
 }
 
 #[allow(dead_code)]
@@ -261,12 +712,13 @@ impl<'a> GraphicsPipelineBuilder<'a> {
                 s_type: vk::StructureType::PIPELINE_RENDERING_CREATE_INFO,
                 ..Default::default()
             },
-            color_attachment_format: vk::Format::UNDEFINED,
+            color_attachment_formats: Vec::new(),
             pipeline_layout: None,
+            name: String::new(),
         }
     }
 
-    pub fn build_pipeline(mut self, device: Arc<Device>) -> GraphicsPipeline {
+    pub fn build_pipeline(mut self, device: Arc<Device>) -> Result<GraphicsPipeline, VulkanError> {
         //TODO: support multiviewport stuff at some point
         // dont need to set more stuff since we do dynamic viewport
         let viewport_info = vk::PipelineViewportStateCreateInfo {
@@ -276,14 +728,20 @@ impl<'a> GraphicsPipelineBuilder<'a> {
             scissor_count: 1,
             ..Default::default()
         };
+        self.rendering_info.color_attachment_count = self.color_attachment_formats.len() as u32;
+        self.rendering_info.p_color_attachment_formats = self.color_attachment_formats.as_ptr();
+        // every attachment is blended the same way
+        let blend_attachments =
+            vec![self.color_blend_attachment; self.color_attachment_formats.len()];
         //TODO: play around with blending
         let blending_info = vk::PipelineColorBlendStateCreateInfo {
             s_type: vk::StructureType::PIPELINE_COLOR_BLEND_STATE_CREATE_INFO,
             p_next: std::ptr::null(),
             logic_op: vk::LogicOp::COPY,
             logic_op_enable: vk::FALSE,
-            attachment_count: 1,
-            p_attachments: &self.color_blend_attachment,
+            // depth only pipelines dont have a color attachment
+            attachment_count: blend_attachments.len() as u32,
+            p_attachments: blend_attachments.as_ptr(),
             ..Default::default()
         };
         // dont need vertex input info since we do vertex pulling
@@ -322,12 +780,20 @@ impl<'a> GraphicsPipelineBuilder<'a> {
                     ..Default::default()
                 };
                 // should return exactly one pipeline since we only pass one create info
-                let pipeline = device.create_graphics_pipeline(&[pipeline_info])[0];
-                GraphicsPipeline {
+                let pipeline = match device.create_graphics_pipeline(&[pipeline_info]) {
+                    Ok(pipelines) => pipelines[0],
+                    Err(e) => {
+                        device.destroy_pipeline_layout(pipeline_layout);
+                        return Err(e);
+                    }
+                };
+                device.set_object_name(pipeline, &self.name);
+                device.set_object_name(pipeline_layout, &format!("{} layout", self.name));
+                Ok(GraphicsPipeline {
                     device,
                     pipeline,
                     pipeline_layout,
-                }
+                })
             }
             None => panic!("Pipeline layout not set"),
         }
@@ -347,6 +813,15 @@ impl<'a> GraphicsPipelineBuilder<'a> {
             .push(fragment_shader.create_shader_stage_info(vk::ShaderStageFlags::FRAGMENT));
         self.shader_stages
             .push(vertex_shader.create_shader_stage_info(vk::ShaderStageFlags::VERTEX));
+        self.name = format!("{} + {}", vertex_shader.name(), fragment_shader.name());
+        self
+    }
+
+    // no fragment shader => only depth is written
+    pub fn set_vertex_shader(mut self, vertex_shader: &'a ShaderModule) -> Self {
+        self.shader_stages
+            .push(vertex_shader.create_shader_stage_info(vk::ShaderStageFlags::VERTEX));
+        self.name = vertex_shader.name().to_string();
         self
     }
 
@@ -369,6 +844,14 @@ impl<'a> GraphicsPipelineBuilder<'a> {
         self
     }
 
+    pub fn enable_depth_bias(mut self, constant_factor: f32, slope_factor: f32) -> Self {
+        self.rasterizer_info.depth_bias_enable = vk::TRUE;
+        self.rasterizer_info.depth_bias_constant_factor = constant_factor;
+        self.rasterizer_info.depth_bias_slope_factor = slope_factor;
+        self.rasterizer_info.depth_bias_clamp = 0.0;
+        self
+    }
+
     pub fn disable_multisampling(mut self) -> Self {
         self.multisampling_info.sample_shading_enable = vk::FALSE;
         // 1 sample per pixel => :sparkles: disabled :sparkles:
@@ -389,10 +872,13 @@ impl<'a> GraphicsPipelineBuilder<'a> {
         self
     }
 
-    pub fn set_color_attachment_format(mut self, format: vk::Format) -> Self {
-        self.color_attachment_format = format;
-        self.rendering_info.p_color_attachment_formats = &self.color_attachment_format;
-        self.rendering_info.color_attachment_count = 1;
+    pub fn set_color_attachment_format(self, format: vk::Format) -> Self {
+        self.set_color_attachment_formats(&[format])
+    }
+
+    // multiple render targets, e.g. a g-buffer => the fragment shader writes location 0..n
+    pub fn set_color_attachment_formats(mut self, formats: &[vk::Format]) -> Self {
+        self.color_attachment_formats = formats.to_vec();
         self
     }
 
@@ -446,6 +932,38 @@ impl<'a> GraphicsPipelineBuilder<'a> {
         self
     }
 
+    // plain sum of all fragments, color and alpha, e.g. weighted blended transparency
+    pub fn enable_blending_accumulate(mut self) -> Self {
+        self.color_blend_attachment.color_write_mask = vk::ColorComponentFlags::R
+            | vk::ColorComponentFlags::G
+            | vk::ColorComponentFlags::B
+            | vk::ColorComponentFlags::A;
+        self.color_blend_attachment.blend_enable = vk::TRUE;
+        self.color_blend_attachment.src_color_blend_factor = vk::BlendFactor::ONE;
+        self.color_blend_attachment.dst_color_blend_factor = vk::BlendFactor::ONE;
+        self.color_blend_attachment.color_blend_op = vk::BlendOp::ADD;
+        self.color_blend_attachment.src_alpha_blend_factor = vk::BlendFactor::ONE;
+        self.color_blend_attachment.dst_alpha_blend_factor = vk::BlendFactor::ONE;
+        self.color_blend_attachment.alpha_blend_op = vk::BlendOp::ADD;
+        self
+    }
+
+    // colors are already multiplied with alpha, e.g. egui
+    pub fn enable_blending_premultiplied(mut self) -> Self {
+        self.color_blend_attachment.color_write_mask = vk::ColorComponentFlags::R
+            | vk::ColorComponentFlags::G
+            | vk::ColorComponentFlags::B
+            | vk::ColorComponentFlags::A;
+        self.color_blend_attachment.blend_enable = vk::TRUE;
+        self.color_blend_attachment.src_color_blend_factor = vk::BlendFactor::ONE;
+        self.color_blend_attachment.dst_color_blend_factor = vk::BlendFactor::ONE_MINUS_SRC_ALPHA;
+        self.color_blend_attachment.color_blend_op = vk::BlendOp::ADD;
+        self.color_blend_attachment.src_alpha_blend_factor = vk::BlendFactor::ONE_MINUS_DST_ALPHA;
+        self.color_blend_attachment.dst_alpha_blend_factor = vk::BlendFactor::ONE;
+        self.color_blend_attachment.alpha_blend_op = vk::BlendOp::ADD;
+        self
+    }
+
     pub fn enable_blending_alphablend(mut self) -> Self {
         self.color_blend_attachment.color_write_mask = vk::ColorComponentFlags::R
             | vk::ColorComponentFlags::G
@@ -0,0 +1,322 @@
+use super::allocation::AllocatedImage;
+use super::allocation::Allocator;
+use super::descriptor::DescriptorAllocator;
+use super::descriptor::DescriptorLayoutBuilder;
+use super::descriptor::DescriptorSetLayout;
+use super::descriptor::DescriptorWriter;
+use super::device::Device;
+use super::error::VulkanError;
+use super::pipelines::ComputePipeline;
+use super::pipelines::PushConstants;
+use super::render_graph::GraphPass;
+use super::render_graph::ImageHandle;
+use super::render_graph::ImageUsage;
+use super::render_graph::RenderGraph;
+use super::shader::ShaderModule;
+use super::window::OutputColorSpace;
+use ash::vk;
+use nalgebra_glm as glm;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tonemapper {
+    // clamps to [0, 1] => the look from before tonemapping
+    None,
+    // Narkowicz fit of the ACES curve, saturated highlights
+    Aces,
+    // Hable's Uncharted 2 curve, softer and less saturated than ACES
+    Filmic,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PostProcessSettings {
+    // linear scale of the hdr color before tonemapping
+    pub exposure: f32,
+    pub tonemapper: Tonemapper,
+    // luminance above which pixels start to glow
+    pub bloom_threshold: f32,
+    // 0 => no bloom passes
+    pub bloom_intensity: f32,
+    // how much the corners are darkened, 0 => no vignette
+    pub vignette_intensity: f32,
+    // hdr output only: nits of a linear 1.0 after exposure and of the brightest highlight
+    pub paper_white: f32,
+    pub peak_brightness: f32,
+}
+
+impl Default for PostProcessSettings {
+    fn default() -> Self {
+        PostProcessSettings {
+            exposure: 1.0,
+            tonemapper: Tonemapper::Aces,
+            bloom_threshold: 1.0,
+            bloom_intensity: 0.05,
+            vignette_intensity: 0.25,
+            paper_white: 200.0,
+            peak_brightness: 1000.0,
+        }
+    }
+}
+
+impl PostProcessSettings {
+    // every effect turned off => the passes can be skipped
+    fn is_neutral(&self) -> bool {
+        self.exposure == 1.0
+            && self.tonemapper == Tonemapper::None
+            && self.bloom_intensity <= 0.0
+            && self.vignette_intensity <= 0.0
+    }
+}
+
+// compute passes between the scene and the upscaler, all in place on the draw image:
+//   bloom threshold: draw image -> bloom image 0 at half resolution, only the bright parts
+//   bloom blur: separable gaussian, bloom image 0 -> 1 -> 0
+//   tonemap: adds the bloom, applies exposure, tonemapping and vignette to the draw image
+//            and encodes it for hdr swapchains
+pub struct PostProcess {
+    settings: PostProcessSettings,
+    bloom_images: [AllocatedImage; 2],
+    // every pass uses one storage image as input (binding 0) and one as output (binding 1)
+    _descriptor_layout: DescriptorSetLayout,
+    threshold_descriptor: vk::DescriptorSet,
+    blur_descriptors: [vk::DescriptorSet; 2],
+    tonemap_descriptor: vk::DescriptorSet,
+    threshold_pipeline: ComputePipeline,
+    blur_pipeline: ComputePipeline,
+    tonemap_pipeline: ComputePipeline,
+}
+
+impl PostProcess {
+    pub fn new(
+        device: Arc<Device>,
+        allocator: Arc<Mutex<Allocator>>,
+        descriptor_allocator: &DescriptorAllocator,
+        draw_image: &AllocatedImage,
+    ) -> Result<Self, VulkanError> {
+        let draw_extent = draw_image.extent();
+        let bloom_extent = vk::Extent3D {
+            width: draw_extent.width.div_ceil(2),
+            height: draw_extent.height.div_ceil(2),
+            depth: 1,
+        };
+        let new_bloom_image = |name: &str| -> Result<AllocatedImage, VulkanError> {
+            let image = AllocatedImage::new(
+                device.clone(),
+                allocator.clone(),
+                vk::Format::R16G16B16A16_SFLOAT,
+                vk::ImageUsageFlags::STORAGE,
+                bloom_extent,
+                vk::ImageAspectFlags::COLOR,
+                1,
+            )?;
+            image.set_name(name);
+            Ok(image)
+        };
+        let bloom_images = [new_bloom_image("bloom 0")?, new_bloom_image("bloom 1")?];
+
+        let mut builder = DescriptorLayoutBuilder::new();
+        builder.add_binding(
+            0,
+            vk::DescriptorType::STORAGE_IMAGE,
+            vk::ShaderStageFlags::COMPUTE,
+        );
+        builder.add_binding(
+            1,
+            vk::DescriptorType::STORAGE_IMAGE,
+            vk::ShaderStageFlags::COMPUTE,
+        );
+        let descriptor_layout =
+            builder.build(device.clone(), vk::DescriptorSetLayoutCreateFlags::empty())?;
+        let allocate = |input: vk::ImageView, output: vk::ImageView| {
+            let descriptor = descriptor_allocator.allocate(descriptor_layout.layout())?;
+            let mut writer = DescriptorWriter::new();
+            writer.add_storage_image(0, input);
+            writer.add_storage_image(1, output);
+            writer.update_descriptor_set(&device, descriptor);
+            Ok::<_, VulkanError>(descriptor)
+        };
+        let threshold_descriptor = allocate(draw_image.image_view(), bloom_images[0].image_view())?;
+        let blur_descriptors = [
+            allocate(bloom_images[0].image_view(), bloom_images[1].image_view())?,
+            allocate(bloom_images[1].image_view(), bloom_images[0].image_view())?,
+        ];
+        // the draw image is read and written in place => binding 1
+        let tonemap_descriptor = allocate(bloom_images[0].image_view(), draw_image.image_view())?;
+
+        let pipeline = |path: &str| {
+            let shader = ShaderModule::new(device.clone(), path)?;
+            ComputePipeline::new(device.clone(), &[descriptor_layout.layout()], shader)
+        };
+        let threshold_pipeline = pipeline("shaders/bloom_threshold_comp.spv")?;
+        let blur_pipeline = pipeline("shaders/bloom_blur_comp.spv")?;
+        let tonemap_pipeline = pipeline("shaders/tonemap_comp.spv")?;
+
+        Ok(PostProcess {
+            settings: PostProcessSettings::default(),
+            bloom_images,
+            _descriptor_layout: descriptor_layout,
+            threshold_descriptor,
+            blur_descriptors,
+            tonemap_descriptor,
+            threshold_pipeline,
+            blur_pipeline,
+            tonemap_pipeline,
+        })
+    }
+
+    pub fn settings(&self) -> &PostProcessSettings {
+        &self.settings
+    }
+
+    pub fn set_settings(&mut self, settings: PostProcessSettings) {
+        self.settings = settings;
+    }
+
+    // draw has to be the draw image, extent is the part of it the scene was rendered to
+    // output is the color space of the swapchain the draw image is copied to
+    pub fn add_passes<'a>(
+        &'a self,
+        graph: &mut RenderGraph<'a>,
+        draw: ImageHandle,
+        extent: vk::Extent2D,
+        output: OutputColorSpace,
+    ) {
+        let settings = self.settings;
+        // hdr swapchains always need the encoding
+        if settings.is_neutral() && !output.is_hdr() {
+            return;
+        }
+        let bloom_extent = vk::Extent2D {
+            width: extent.width.div_ceil(2),
+            height: extent.height.div_ceil(2),
+        };
+        let bloom_image = (settings.bloom_intensity > 0.0)
+            .then(|| self.add_bloom_passes(graph, draw, bloom_extent, settings.bloom_threshold));
+        let tonemapper = match settings.tonemapper {
+            Tonemapper::None => 0.0,
+            Tonemapper::Aces => 1.0,
+            Tonemapper::Filmic => 2.0,
+        };
+        let output_mode = match output {
+            OutputColorSpace::Srgb => 0.0,
+            OutputColorSpace::ScRgb => 1.0,
+            OutputColorSpace::Hdr10 => 2.0,
+        };
+        let paper_white = settings.paper_white.max(1.0);
+        let push_constants = PushConstants::new(
+            glm::vec4(
+                extent.width as f32,
+                extent.height as f32,
+                settings.exposure,
+                tonemapper,
+            ),
+            glm::vec4(
+                bloom_extent.width as f32,
+                bloom_extent.height as f32,
+                bloom_image.map_or(0.0, |_| settings.bloom_intensity),
+                settings.vignette_intensity.max(0.0),
+            ),
+            glm::vec4(
+                output_mode,
+                paper_white,
+                settings.peak_brightness.max(paper_white),
+                0.0,
+            ),
+            glm::Vec4::zeros(),
+        );
+        // compute only => graphics passes that dont use the draw image can overlap it
+        let mut tonemap_pass = GraphPass::new("tonemap")
+            .image(draw, ImageUsage::StorageWrite)
+            .async_compute();
+        if let Some(bloom_image) = bloom_image {
+            tonemap_pass = tonemap_pass.image(bloom_image, ImageUsage::StorageRead);
+        }
+        graph.add_pass(tonemap_pass.record(move |command_buffer| {
+            self.tonemap_pipeline.execute_compute_with_constants(
+                command_buffer,
+                &[self.tonemap_descriptor],
+                extent,
+                &push_constants,
+            );
+        }));
+    }
+
+    // returns the blurred bloom image
+    fn add_bloom_passes<'a>(
+        &'a self,
+        graph: &mut RenderGraph<'a>,
+        draw: ImageHandle,
+        bloom_extent: vk::Extent2D,
+        threshold: f32,
+    ) -> ImageHandle {
+        let bloom_images = self.bloom_images.each_ref().map(|image| {
+            let handle = graph.import_image(
+                "bloom image",
+                image.image(),
+                vk::ImageAspectFlags::COLOR,
+                vk::ImageLayout::UNDEFINED,
+            );
+            graph.set_image_size(handle, bloom_extent, image.format());
+            handle
+        });
+        let push_constants = PushConstants::new(
+            glm::vec4(
+                bloom_extent.width as f32,
+                bloom_extent.height as f32,
+                threshold,
+                0.0,
+            ),
+            glm::Vec4::zeros(),
+            glm::Vec4::zeros(),
+            glm::Vec4::zeros(),
+        );
+        graph.add_pass(
+            GraphPass::new("bloom threshold")
+                .image(draw, ImageUsage::StorageRead)
+                .image(bloom_images[0], ImageUsage::StorageWrite)
+                .async_compute()
+                .record(move |command_buffer| {
+                    self.threshold_pipeline.execute_compute_with_constants(
+                        command_buffer,
+                        &[self.threshold_descriptor],
+                        bloom_extent,
+                        &push_constants,
+                    );
+                }),
+        );
+        // horizontal 0 -> 1, vertical 1 -> 0
+        for (idx, direction) in [glm::vec2(1.0, 0.0), glm::vec2(0.0, 1.0)]
+            .into_iter()
+            .enumerate()
+        {
+            let push_constants = PushConstants::new(
+                glm::vec4(
+                    bloom_extent.width as f32,
+                    bloom_extent.height as f32,
+                    direction.x,
+                    direction.y,
+                ),
+                glm::Vec4::zeros(),
+                glm::Vec4::zeros(),
+                glm::Vec4::zeros(),
+            );
+            let descriptor = self.blur_descriptors[idx];
+            graph.add_pass(
+                GraphPass::new("bloom blur")
+                    .image(bloom_images[idx], ImageUsage::StorageRead)
+                    .image(bloom_images[1 - idx], ImageUsage::StorageWrite)
+                    .async_compute()
+                    .record(move |command_buffer| {
+                        self.blur_pipeline.execute_compute_with_constants(
+                            command_buffer,
+                            &[descriptor],
+                            bloom_extent,
+                            &push_constants,
+                        );
+                    }),
+            );
+        }
+        bloom_images[0]
+    }
+}
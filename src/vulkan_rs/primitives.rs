@@ -0,0 +1,118 @@
+use super::allocation::Allocator;
+use super::async_upload::AsyncUploader;
+use super::device::Device;
+use super::error::VulkanError;
+use super::material::Material;
+use super::mesh::Aabb;
+use super::mesh::BoundingSphere;
+use super::mesh::GPUMeshBuffers;
+use super::mesh::GeometricSurface;
+use super::mesh::MeshAsset;
+use super::mesh::Vertex;
+use nalgebra_glm as glm;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+// procedural meshes around the origin with a radius/half extent of 1
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Primitive {
+    // 4 vertices per face => flat normals
+    Cube,
+    // uv sphere, rings from pole to pole
+    Sphere { segments: u32, rings: u32 },
+}
+
+impl Primitive {
+    pub fn name(self) -> &'static str {
+        match self {
+            Primitive::Cube => "Cube",
+            Primitive::Sphere { .. } => "Sphere",
+        }
+    }
+
+    fn geometry(self) -> (Vec<Vertex>, Vec<u32>) {
+        match self {
+            Primitive::Cube => cube(),
+            Primitive::Sphere { segments, rings } => sphere(segments.max(3), rings.max(2)),
+        }
+    }
+
+    // one surface with the given material
+    pub fn create_mesh(
+        self,
+        device: Arc<Device>,
+        allocator: Arc<Mutex<Allocator>>,
+        uploader: &AsyncUploader,
+        material: Arc<Material>,
+    ) -> Result<MeshAsset, VulkanError> {
+        let (vertices, indices) = self.geometry();
+        let buffers =
+            GPUMeshBuffers::upload_mesh(device, allocator, &indices, &vertices, uploader)?;
+        // both shapes fit into the unit cube
+        let corners = [glm::vec3(-1.0, -1.0, -1.0), glm::vec3(1.0, 1.0, 1.0)];
+        let surface = GeometricSurface::new(
+            0,
+            indices.len() as u32,
+            material,
+            Aabb::from_points(corners.into_iter()),
+        );
+        Ok(MeshAsset::new(
+            self.name(),
+            vec![surface],
+            buffers,
+            BoundingSphere::from_points(corners.into_iter()),
+        ))
+    }
+}
+
+fn cube() -> (Vec<Vertex>, Vec<u32>) {
+    let white = glm::vec4(1.0, 1.0, 1.0, 1.0);
+    let mut vertices = Vec::with_capacity(24);
+    let mut indices = Vec::with_capacity(36);
+    for axis in 0..3 {
+        for sign in [1.0, -1.0] {
+            let mut normal = glm::Vec3::zeros();
+            normal[axis] = sign;
+            // two axes spanning the face, u x v = normal => counter clockwise seen from outside
+            let mut u = glm::Vec3::zeros();
+            u[(axis + 1) % 3] = sign;
+            let v = normal.cross(&u);
+            let first = vertices.len() as u32;
+            for (x, y) in [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)] {
+                let position = normal + u * (x * 2.0 - 1.0) + v * (y * 2.0 - 1.0);
+                vertices.push(Vertex::new(position, x, normal, y, white));
+            }
+            indices.extend_from_slice(&[first, first + 1, first + 2, first, first + 2, first + 3]);
+        }
+    }
+    (vertices, indices)
+}
+
+fn sphere(segments: u32, rings: u32) -> (Vec<Vertex>, Vec<u32>) {
+    let white = glm::vec4(1.0, 1.0, 1.0, 1.0);
+    // the seam and the poles get their own vertices => continuous uvs
+    let mut vertices = Vec::with_capacity(((segments + 1) * (rings + 1)) as usize);
+    for ring in 0..=rings {
+        let v = ring as f32 / rings as f32;
+        let theta = v * std::f32::consts::PI;
+        for segment in 0..=segments {
+            let u = segment as f32 / segments as f32;
+            let phi = u * std::f32::consts::TAU;
+            let normal = glm::vec3(
+                theta.sin() * phi.cos(),
+                theta.cos(),
+                theta.sin() * phi.sin(),
+            );
+            vertices.push(Vertex::new(normal, u, normal, v, white));
+        }
+    }
+    let mut indices = Vec::with_capacity((segments * rings * 6) as usize);
+    for ring in 0..rings {
+        for segment in 0..segments {
+            let idx = ring * (segments + 1) + segment;
+            let below = idx + segments + 1;
+            indices.extend_from_slice(&[idx, idx + 1, below, idx + 1, below + 1, below]);
+        }
+    }
+    (vertices, indices)
+}
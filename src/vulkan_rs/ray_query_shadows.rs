@@ -0,0 +1,183 @@
+use super::allocation::AllocatedImage;
+use super::descriptor::DescriptorAllocator;
+use super::descriptor::DescriptorLayoutBuilder;
+use super::descriptor::DescriptorSetLayout;
+use super::descriptor::DescriptorWriter;
+use super::descriptor::PoolSizeRatio;
+use super::device::Device;
+use super::error::VulkanError;
+use super::mesh::Sampler;
+use super::pipelines::ComputePipeline;
+use super::ray_tracing::AccelerationStructure;
+use super::render_graph::BufferHandle;
+use super::render_graph::BufferUsage;
+use super::render_graph::GraphPass;
+use super::render_graph::ImageHandle;
+use super::render_graph::ImageUsage;
+use super::render_graph::RenderGraph;
+use super::shader::ShaderModule;
+use ash::vk;
+use nalgebra_glm as glm;
+use std::sync::Arc;
+
+// long enough for every caster of the scene, the tlas has no far plane like the shadow map
+const SHADOW_RAY_LENGTH: f32 = 10_000.0;
+
+// same layout as the push constants in ray_query_shadows.comp
+#[repr(C)]
+#[derive(bytemuck::NoUninit, Copy, Clone, Debug)]
+struct GPUShadowPushConstants {
+    inverse_view_proj: glm::Mat4,
+    sun_direction: glm::Vec4,
+    data: glm::Vec4,
+}
+
+// sun shadows traced inline with VK_KHR_ray_query instead of the shadow map
+// a compute pass between the g-buffer and the lighting pass writes the shadow mask of the
+// deferred path => hard shadows without the resolution and range limits of the shadow map
+// forward and transparent surfaces still use the shadow map
+pub struct RayQueryShadows {
+    device: Arc<Device>,
+    enabled: bool,
+    // own pool => the acceleration structure descriptors dont need the ray query
+    // extensions in the shared pools
+    _descriptor_allocator: DescriptorAllocator,
+    _descriptor_layout: DescriptorSetLayout,
+    // one per frame slot, the tlas of the slot can be recreated
+    descriptors: Vec<vk::DescriptorSet>,
+    // binding 1 + 2 = depth and g-buffer normals
+    sampler: Sampler,
+    pipeline: ComputePipeline,
+}
+
+impl RayQueryShadows {
+    pub fn new(device: Arc<Device>, frame_count: usize) -> Result<Self, VulkanError> {
+        let mut descriptor_allocator = DescriptorAllocator::new(device.clone());
+        let ratio_sizes = [
+            PoolSizeRatio {
+                descriptor_type: vk::DescriptorType::ACCELERATION_STRUCTURE_KHR,
+                ratio: 1.0,
+            },
+            PoolSizeRatio {
+                descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                ratio: 2.0,
+            },
+            PoolSizeRatio {
+                descriptor_type: vk::DescriptorType::STORAGE_IMAGE,
+                ratio: 1.0,
+            },
+        ];
+        descriptor_allocator.init_pool(frame_count as u32, &ratio_sizes)?;
+
+        let mut builder = DescriptorLayoutBuilder::new();
+        builder.add_binding(
+            0,
+            vk::DescriptorType::ACCELERATION_STRUCTURE_KHR,
+            vk::ShaderStageFlags::COMPUTE,
+        );
+        for binding in 1..3 {
+            builder.add_binding(
+                binding,
+                vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                vk::ShaderStageFlags::COMPUTE,
+            );
+        }
+        builder.add_binding(
+            3,
+            vk::DescriptorType::STORAGE_IMAGE,
+            vk::ShaderStageFlags::COMPUTE,
+        );
+        let descriptor_layout =
+            builder.build(device.clone(), vk::DescriptorSetLayoutCreateFlags::empty())?;
+        let descriptors = (0..frame_count)
+            .map(|_| descriptor_allocator.allocate(descriptor_layout.layout()))
+            .collect::<Result<Vec<_>, _>>()?;
+        // texel fetches only, the filter doesnt matter
+        let sampler = Sampler::new(device.clone(), vk::Filter::NEAREST, vk::Filter::NEAREST)?;
+
+        let shader = ShaderModule::new(device.clone(), "shaders/ray_query_shadows_comp.spv")?;
+        let pipeline = ComputePipeline::new(device.clone(), &[descriptor_layout.layout()], shader)?;
+
+        Ok(RayQueryShadows {
+            device,
+            enabled: true,
+            _descriptor_allocator: descriptor_allocator,
+            _descriptor_layout: descriptor_layout,
+            descriptors,
+            sampler,
+            pipeline,
+        })
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    // after the g-buffer pass, tlas is the one RayTracingScene built for frame_slot
+    // sun_direction points from the sun to the scene like GPUSceneData::sunlight_dir
+    // returns false if nothing was added => the lighting pass has to use the shadow map
+    #[allow(clippy::too_many_arguments)]
+    pub fn add_pass<'a>(
+        &'a self,
+        graph: &mut RenderGraph<'a>,
+        tlas: (BufferHandle, &AccelerationStructure),
+        frame_slot: usize,
+        depth: (ImageHandle, &AllocatedImage),
+        normal: (ImageHandle, &AllocatedImage),
+        shadow_mask: (ImageHandle, &AllocatedImage),
+        extent: vk::Extent2D,
+        view_proj: &glm::Mat4,
+        sun_direction: glm::Vec3,
+    ) -> bool {
+        if !self.enabled {
+            return false;
+        }
+        // the slot finished => its descriptor can be rewritten
+        let descriptor = self.descriptors[frame_slot];
+        let mut writer = DescriptorWriter::new();
+        writer.add_acceleration_structure(0, tlas.1.handle());
+        for (binding, image) in [(1, depth.1), (2, normal.1)] {
+            writer.add_image(
+                binding,
+                image.image_view(),
+                self.sampler.sampler(),
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            );
+        }
+        writer.add_storage_image(3, shadow_mask.1.image_view());
+        writer.update_descriptor_set(&self.device, descriptor);
+
+        let towards_sun = -sun_direction;
+        let push_constants = GPUShadowPushConstants {
+            inverse_view_proj: glm::inverse(view_proj),
+            sun_direction: glm::vec4(
+                towards_sun.x,
+                towards_sun.y,
+                towards_sun.z,
+                SHADOW_RAY_LENGTH,
+            ),
+            data: glm::vec4(extent.width as f32, extent.height as f32, 0.0, 0.0),
+        };
+        graph.add_pass(
+            GraphPass::new("ray query shadows")
+                .buffer(tlas.0, BufferUsage::AccelerationStructureQuery)
+                .image(depth.0, ImageUsage::Sampled)
+                .image(normal.0, ImageUsage::Sampled)
+                .image(shadow_mask.0, ImageUsage::StorageWrite)
+                .record(move |command_buffer| {
+                    self.pipeline.dispatch(
+                        command_buffer,
+                        &[descriptor],
+                        [extent.width.div_ceil(16), extent.height.div_ceil(16), 1],
+                        &push_constants,
+                    );
+                }),
+        );
+        true
+    }
+}
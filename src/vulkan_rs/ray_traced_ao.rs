@@ -0,0 +1,218 @@
+use super::allocation::AllocatedImage;
+use super::allocation::Allocator;
+use super::descriptor::DescriptorAllocator;
+use super::descriptor::DescriptorLayoutBuilder;
+use super::descriptor::DescriptorSetLayout;
+use super::descriptor::DescriptorWriter;
+use super::descriptor::PoolSizeRatio;
+use super::device::Device;
+use super::error::VulkanError;
+use super::mesh::Sampler;
+use super::ray_tracing::AccelerationStructure;
+use super::ray_tracing::RayTracingPipeline;
+use super::render_graph::BufferHandle;
+use super::render_graph::BufferUsage;
+use super::render_graph::GraphPass;
+use super::render_graph::ImageHandle;
+use super::render_graph::ImageUsage;
+use super::render_graph::RenderGraph;
+use super::shader::ShaderModule;
+use ash::vk;
+use nalgebra_glm as glm;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RayTracedAoSettings {
+    // world space length of the rays, occluders further away dont darken
+    pub radius: f32,
+    // 0 => no darkening, 1 => fully occluded pixels are black
+    pub intensity: f32,
+    // per pixel and frame, more => less noise
+    pub ray_count: u32,
+}
+
+impl Default for RayTracedAoSettings {
+    fn default() -> Self {
+        RayTracedAoSettings {
+            radius: 1.0,
+            intensity: 0.8,
+            ray_count: 4,
+        }
+    }
+}
+
+// same layout as the push constants in ray_traced_ao.rgen
+#[repr(C)]
+#[derive(bytemuck::NoUninit, Copy, Clone, Debug)]
+struct GPUAoPushConstants {
+    inverse_view_proj: glm::Mat4,
+    camera_position: glm::Vec4,
+    // x = radius, y = intensity, z = ray count, w = frame index for the noise
+    params: glm::Vec4,
+}
+
+// ambient occlusion with hardware ray tracing, the first user of the acceleration structures
+// one raygen invocation per pixel: reconstructs the surface from the depth image, traces short
+// rays around its normal and darkens the draw image in place by the fraction that hit something
+// => no screen space artifacts, but it also darkens direct light
+pub struct RayTracedAo {
+    device: Arc<Device>,
+    settings: RayTracedAoSettings,
+    // own pool => the acceleration structure descriptors dont need the ray tracing
+    // extensions in the shared pools
+    _descriptor_allocator: DescriptorAllocator,
+    _descriptor_layout: DescriptorSetLayout,
+    // one per frame slot, the tlas of the slot can be recreated
+    descriptors: Vec<vk::DescriptorSet>,
+    // binding 1 = depth
+    sampler: Sampler,
+    pipeline: RayTracingPipeline,
+    frame_index: u32,
+}
+
+impl RayTracedAo {
+    pub fn new(
+        device: Arc<Device>,
+        allocator: Arc<Mutex<Allocator>>,
+        frame_count: usize,
+    ) -> Result<Self, VulkanError> {
+        let mut descriptor_allocator = DescriptorAllocator::new(device.clone());
+        let ratio_sizes = [
+            PoolSizeRatio {
+                descriptor_type: vk::DescriptorType::ACCELERATION_STRUCTURE_KHR,
+                ratio: 1.0,
+            },
+            PoolSizeRatio {
+                descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                ratio: 1.0,
+            },
+            PoolSizeRatio {
+                descriptor_type: vk::DescriptorType::STORAGE_IMAGE,
+                ratio: 1.0,
+            },
+        ];
+        descriptor_allocator.init_pool(frame_count as u32, &ratio_sizes)?;
+
+        let mut builder = DescriptorLayoutBuilder::new();
+        builder.add_binding(
+            0,
+            vk::DescriptorType::ACCELERATION_STRUCTURE_KHR,
+            vk::ShaderStageFlags::RAYGEN_KHR,
+        );
+        builder.add_binding(
+            1,
+            vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            vk::ShaderStageFlags::RAYGEN_KHR,
+        );
+        builder.add_binding(
+            2,
+            vk::DescriptorType::STORAGE_IMAGE,
+            vk::ShaderStageFlags::RAYGEN_KHR,
+        );
+        let descriptor_layout =
+            builder.build(device.clone(), vk::DescriptorSetLayoutCreateFlags::empty())?;
+        let descriptors = (0..frame_count)
+            .map(|_| descriptor_allocator.allocate(descriptor_layout.layout()))
+            .collect::<Result<Vec<_>, _>>()?;
+        // texel fetches only, the filter doesnt matter
+        let sampler = Sampler::new(device.clone(), vk::Filter::NEAREST, vk::Filter::NEAREST)?;
+
+        let raygen = ShaderModule::new(device.clone(), "shaders/ray_traced_ao_rgen.spv")?;
+        let miss = ShaderModule::new(device.clone(), "shaders/ray_traced_ao_rmiss.spv")?;
+        let pipeline = RayTracingPipeline::new(
+            device.clone(),
+            allocator,
+            &[descriptor_layout.layout()],
+            &raygen,
+            &[&miss],
+            &[],
+        )?;
+
+        Ok(RayTracedAo {
+            device,
+            settings: RayTracedAoSettings::default(),
+            _descriptor_allocator: descriptor_allocator,
+            _descriptor_layout: descriptor_layout,
+            descriptors,
+            sampler,
+            pipeline,
+            frame_index: 0,
+        })
+    }
+
+    pub fn settings(&self) -> &RayTracedAoSettings {
+        &self.settings
+    }
+
+    pub fn set_settings(&mut self, settings: RayTracedAoSettings) {
+        self.settings = settings;
+    }
+
+    // has to be called once per frame before add_passes => the noise changes every frame
+    pub fn advance_frame(&mut self) {
+        self.frame_index = self.frame_index.wrapping_add(1);
+    }
+
+    // after the opaque surfaces, tlas is the one RayTracingScene built for frame_slot
+    // view_proj has to be the one the depth image was rendered with
+    #[allow(clippy::too_many_arguments)]
+    pub fn add_passes<'a>(
+        &'a self,
+        graph: &mut RenderGraph<'a>,
+        tlas: (BufferHandle, &AccelerationStructure),
+        frame_slot: usize,
+        draw: (ImageHandle, &AllocatedImage),
+        depth: (ImageHandle, &AllocatedImage),
+        extent: vk::Extent2D,
+        view_proj: &glm::Mat4,
+        camera_position: glm::Vec3,
+    ) {
+        if self.settings.intensity <= 0.0 || self.settings.ray_count == 0 {
+            return;
+        }
+        // the slot finished => its descriptor can be rewritten
+        let descriptor = self.descriptors[frame_slot];
+        let mut writer = DescriptorWriter::new();
+        writer.add_acceleration_structure(0, tlas.1.handle());
+        writer.add_image(
+            1,
+            depth.1.image_view(),
+            self.sampler.sampler(),
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+        );
+        writer.add_storage_image(2, draw.1.image_view());
+        writer.update_descriptor_set(&self.device, descriptor);
+
+        let push_constants = GPUAoPushConstants {
+            inverse_view_proj: glm::inverse(view_proj),
+            camera_position: glm::vec4(
+                camera_position.x,
+                camera_position.y,
+                camera_position.z,
+                1.0,
+            ),
+            params: glm::vec4(
+                self.settings.radius.max(0.0),
+                self.settings.intensity.min(1.0),
+                self.settings.ray_count as f32,
+                self.frame_index as f32,
+            ),
+        };
+        graph.add_pass(
+            GraphPass::new("ray traced ao")
+                .buffer(tlas.0, BufferUsage::AccelerationStructureRead)
+                .image(depth.0, ImageUsage::RayTracingSampled)
+                .image(draw.0, ImageUsage::RayTracingStorageWrite)
+                .record(move |command_buffer| {
+                    self.pipeline.trace_rays(
+                        command_buffer,
+                        &[descriptor],
+                        extent,
+                        bytemuck::bytes_of(&push_constants),
+                    );
+                }),
+        );
+    }
+}
@@ -0,0 +1,721 @@
+use super::allocation::AllocatedBuffer;
+use super::allocation::Allocator;
+use super::deletion_queue::DeletionQueue;
+use super::device::Device;
+use super::error::VulkanError;
+use super::mesh::GPUMeshBuffers;
+use super::mesh::GeometricSurface;
+use super::mesh::MeshAsset;
+use super::mesh::Vertex;
+use super::render_graph::BufferHandle;
+use super::render_graph::BufferUsage;
+use super::render_graph::GraphPass;
+use super::render_graph::RenderGraph;
+use super::shader::ShaderModule;
+use ash::vk;
+use nalgebra_glm as glm;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+// minAccelerationStructureScratchOffsetAlignment is at most 256 on every device
+const SCRATCH_ALIGNMENT: vk::DeviceSize = 256;
+
+// same layout as vk::AccelerationStructureInstanceKHR, which is not NoUninit
+#[repr(C)]
+#[derive(bytemuck::NoUninit, Copy, Clone, Debug)]
+struct GPUInstance {
+    // rows of a 3x4 matrix
+    transform: [f32; 12],
+    // 24 bit custom index, 8 bit mask
+    custom_index_and_mask: u32,
+    // 24 bit hit group offset, 8 bit flags
+    sbt_offset_and_flags: u32,
+    blas_address: vk::DeviceAddress,
+}
+
+impl GPUInstance {
+    fn new(world_matrix: &glm::Mat4, blas_address: vk::DeviceAddress) -> Self {
+        let mut transform = [0.0; 12];
+        for row in 0..3 {
+            for column in 0..4 {
+                transform[row * 4 + column] = world_matrix[(row, column)];
+            }
+        }
+        // surfaces are rasterized without culling => rays hit both sides too
+        let flags = vk::GeometryInstanceFlagsKHR::TRIANGLE_FACING_CULL_DISABLE.as_raw();
+        GPUInstance {
+            transform,
+            custom_index_and_mask: 0xff << 24,
+            sbt_offset_and_flags: flags << 24,
+            blas_address,
+        }
+    }
+}
+
+pub struct AccelerationStructure {
+    device: Arc<Device>,
+    handle: vk::AccelerationStructureKHR,
+    address: vk::DeviceAddress,
+    buffer: AllocatedBuffer,
+}
+
+impl AccelerationStructure {
+    fn new(
+        device: Arc<Device>,
+        allocator: Arc<Mutex<Allocator>>,
+        ty: vk::AccelerationStructureTypeKHR,
+        size: vk::DeviceSize,
+        name: &str,
+    ) -> Result<Self, VulkanError> {
+        let buffer = AllocatedBuffer::new(
+            device.clone(),
+            allocator,
+            name,
+            vk::BufferUsageFlags::ACCELERATION_STRUCTURE_STORAGE_KHR
+                | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+            size,
+            gpu_allocator::MemoryLocation::GpuOnly,
+        )?;
+        let create_info = vk::AccelerationStructureCreateInfoKHR {
+            s_type: vk::StructureType::ACCELERATION_STRUCTURE_CREATE_INFO_KHR,
+            buffer: buffer.buffer(),
+            size,
+            ty,
+            ..Default::default()
+        };
+        let handle = device.create_acceleration_structure(&create_info)?;
+        device.set_object_name(handle, name);
+        let address = device.get_acceleration_structure_device_address(handle);
+        Ok(AccelerationStructure {
+            device,
+            handle,
+            address,
+            buffer,
+        })
+    }
+
+    pub fn handle(&self) -> vk::AccelerationStructureKHR {
+        self.handle
+    }
+
+    pub fn address(&self) -> vk::DeviceAddress {
+        self.address
+    }
+
+    // the memory behind the acceleration structure, e.g. for render graph barriers
+    pub fn buffer(&self) -> vk::Buffer {
+        self.buffer.buffer()
+    }
+}
+
+impl Drop for AccelerationStructure {
+    fn drop(&mut self) {
+        log::debug!("Dropping AccelerationStructure");
+        self.device.destroy_acceleration_structure(self.handle);
+    }
+}
+
+// only needed while the build runs
+struct ScratchBuffer {
+    _buffer: AllocatedBuffer,
+    // aligned to SCRATCH_ALIGNMENT
+    address: vk::DeviceAddress,
+}
+
+impl ScratchBuffer {
+    fn new(
+        device: Arc<Device>,
+        allocator: Arc<Mutex<Allocator>>,
+        size: vk::DeviceSize,
+    ) -> Result<Self, VulkanError> {
+        let buffer = AllocatedBuffer::new(
+            device,
+            allocator,
+            "Acceleration Structure Scratch Buffer",
+            vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+            size + SCRATCH_ALIGNMENT,
+            gpu_allocator::MemoryLocation::GpuOnly,
+        )?;
+        let address = buffer
+            .get_device_address()
+            .next_multiple_of(SCRATCH_ALIGNMENT);
+        Ok(ScratchBuffer {
+            _buffer: buffer,
+            address,
+        })
+    }
+}
+
+// bottom level acceleration structure of a mesh, one triangle geometry per surface
+// indices and vertices are read straight from the mesh buffers
+pub struct Blas {
+    structure: AccelerationStructure,
+    geometries: Vec<vk::AccelerationStructureGeometryKHR<'static>>,
+    ranges: Vec<vk::AccelerationStructureBuildRangeInfoKHR>,
+}
+
+impl Blas {
+    // the structure is empty until record_build, returns the scratch buffer the build needs
+    fn new(
+        device: Arc<Device>,
+        allocator: Arc<Mutex<Allocator>>,
+        buffers: &GPUMeshBuffers,
+        surfaces: &[GeometricSurface],
+        name: &str,
+    ) -> Result<(Self, ScratchBuffer), VulkanError> {
+        let triangles = vk::AccelerationStructureGeometryTrianglesDataKHR {
+            s_type: vk::StructureType::ACCELERATION_STRUCTURE_GEOMETRY_TRIANGLES_DATA_KHR,
+            // the position is the first member of Vertex
+            vertex_format: vk::Format::R32G32B32_SFLOAT,
+            vertex_data: vk::DeviceOrHostAddressConstKHR {
+                device_address: buffers.vertex_buffer_address(),
+            },
+            vertex_stride: std::mem::size_of::<Vertex>() as vk::DeviceSize,
+            max_vertex: buffers.vertex_count().saturating_sub(1),
+            index_type: vk::IndexType::UINT32,
+            index_data: vk::DeviceOrHostAddressConstKHR {
+                device_address: buffers.index_buffer_address(),
+            },
+            ..Default::default()
+        };
+        // transparent surfaces block rays as well => no any hit shaders needed
+        let geometry = vk::AccelerationStructureGeometryKHR {
+            s_type: vk::StructureType::ACCELERATION_STRUCTURE_GEOMETRY_KHR,
+            geometry_type: vk::GeometryTypeKHR::TRIANGLES,
+            geometry: vk::AccelerationStructureGeometryDataKHR { triangles },
+            flags: vk::GeometryFlagsKHR::OPAQUE,
+            ..Default::default()
+        };
+        let geometries = vec![geometry; surfaces.len()];
+        let ranges: Vec<_> = surfaces
+            .iter()
+            .map(|surface| vk::AccelerationStructureBuildRangeInfoKHR {
+                primitive_count: surface.count() / 3,
+                // in bytes into the index buffer
+                primitive_offset: (surface.start_idx() * std::mem::size_of::<u32>()) as u32,
+                first_vertex: 0,
+                transform_offset: 0,
+            })
+            .collect();
+        let primitive_counts: Vec<u32> = ranges.iter().map(|range| range.primitive_count).collect();
+        let sizes = device.get_acceleration_structure_build_sizes(
+            &Self::build_info(&geometries, vk::AccelerationStructureKHR::null(), 0),
+            &primitive_counts,
+        );
+        let structure = AccelerationStructure::new(
+            device.clone(),
+            allocator.clone(),
+            vk::AccelerationStructureTypeKHR::BOTTOM_LEVEL,
+            sizes.acceleration_structure_size,
+            name,
+        )?;
+        let scratch = ScratchBuffer::new(device, allocator, sizes.build_scratch_size)?;
+        Ok((
+            Blas {
+                structure,
+                geometries,
+                ranges,
+            },
+            scratch,
+        ))
+    }
+
+    fn build_info(
+        geometries: &[vk::AccelerationStructureGeometryKHR<'static>],
+        dst: vk::AccelerationStructureKHR,
+        scratch_address: vk::DeviceAddress,
+    ) -> vk::AccelerationStructureBuildGeometryInfoKHR<'static> {
+        vk::AccelerationStructureBuildGeometryInfoKHR {
+            s_type: vk::StructureType::ACCELERATION_STRUCTURE_BUILD_GEOMETRY_INFO_KHR,
+            ty: vk::AccelerationStructureTypeKHR::BOTTOM_LEVEL,
+            // built once, traced every frame
+            flags: vk::BuildAccelerationStructureFlagsKHR::PREFER_FAST_TRACE,
+            mode: vk::BuildAccelerationStructureModeKHR::BUILD,
+            dst_acceleration_structure: dst,
+            geometry_count: geometries.len() as u32,
+            p_geometries: geometries.as_ptr(),
+            scratch_data: vk::DeviceOrHostAddressKHR {
+                device_address: scratch_address,
+            },
+            ..Default::default()
+        }
+    }
+
+    fn record_build(
+        &self,
+        device: &Device,
+        command_buffer: vk::CommandBuffer,
+        scratch_address: vk::DeviceAddress,
+    ) {
+        let build_info =
+            Self::build_info(&self.geometries, self.structure.handle(), scratch_address);
+        device.cmd_build_acceleration_structures(command_buffer, &[build_info], &[&self.ranges]);
+    }
+
+    pub fn address(&self) -> vk::DeviceAddress {
+        self.structure.address()
+    }
+}
+
+// top level acceleration structure of one frame slot, rebuilt every frame
+struct TlasSlot {
+    // host visible => written by the cpu before the build
+    instance_buffer: AllocatedBuffer,
+    structure: AccelerationStructure,
+    scratch: ScratchBuffer,
+    // instances that fit into the buffers
+    capacity: usize,
+    instance_count: u32,
+}
+
+impl TlasSlot {
+    fn new(
+        device: Arc<Device>,
+        allocator: Arc<Mutex<Allocator>>,
+        capacity: usize,
+    ) -> Result<Self, VulkanError> {
+        let instance_buffer = AllocatedBuffer::new(
+            device.clone(),
+            allocator.clone(),
+            "Tlas Instance Buffer",
+            vk::BufferUsageFlags::ACCELERATION_STRUCTURE_BUILD_INPUT_READ_ONLY_KHR
+                | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+            (capacity * std::mem::size_of::<GPUInstance>()) as vk::DeviceSize,
+            gpu_allocator::MemoryLocation::CpuToGpu,
+        )?;
+        let geometry = Self::geometry(instance_buffer.get_device_address());
+        let sizes = device.get_acceleration_structure_build_sizes(
+            &Self::build_info(&geometry, vk::AccelerationStructureKHR::null(), 0),
+            &[capacity as u32],
+        );
+        let structure = AccelerationStructure::new(
+            device.clone(),
+            allocator.clone(),
+            vk::AccelerationStructureTypeKHR::TOP_LEVEL,
+            sizes.acceleration_structure_size,
+            "Tlas",
+        )?;
+        let scratch = ScratchBuffer::new(device, allocator, sizes.build_scratch_size)?;
+        Ok(TlasSlot {
+            instance_buffer,
+            structure,
+            scratch,
+            capacity,
+            instance_count: 0,
+        })
+    }
+
+    fn geometry(
+        instance_address: vk::DeviceAddress,
+    ) -> vk::AccelerationStructureGeometryKHR<'static> {
+        let instances = vk::AccelerationStructureGeometryInstancesDataKHR {
+            s_type: vk::StructureType::ACCELERATION_STRUCTURE_GEOMETRY_INSTANCES_DATA_KHR,
+            array_of_pointers: vk::FALSE,
+            data: vk::DeviceOrHostAddressConstKHR {
+                device_address: instance_address,
+            },
+            ..Default::default()
+        };
+        vk::AccelerationStructureGeometryKHR {
+            s_type: vk::StructureType::ACCELERATION_STRUCTURE_GEOMETRY_KHR,
+            geometry_type: vk::GeometryTypeKHR::INSTANCES,
+            geometry: vk::AccelerationStructureGeometryDataKHR { instances },
+            ..Default::default()
+        }
+    }
+
+    fn build_info(
+        geometry: &vk::AccelerationStructureGeometryKHR<'static>,
+        dst: vk::AccelerationStructureKHR,
+        scratch_address: vk::DeviceAddress,
+    ) -> vk::AccelerationStructureBuildGeometryInfoKHR<'static> {
+        vk::AccelerationStructureBuildGeometryInfoKHR {
+            s_type: vk::StructureType::ACCELERATION_STRUCTURE_BUILD_GEOMETRY_INFO_KHR,
+            ty: vk::AccelerationStructureTypeKHR::TOP_LEVEL,
+            // rebuilt every frame => the build time matters as much as the trace time
+            flags: vk::BuildAccelerationStructureFlagsKHR::PREFER_FAST_BUILD,
+            mode: vk::BuildAccelerationStructureModeKHR::BUILD,
+            dst_acceleration_structure: dst,
+            geometry_count: 1,
+            p_geometries: geometry,
+            scratch_data: vk::DeviceOrHostAddressKHR {
+                device_address: scratch_address,
+            },
+            ..Default::default()
+        }
+    }
+
+    fn record_build(&self, device: &Device, command_buffer: vk::CommandBuffer) {
+        let geometry = Self::geometry(self.instance_buffer.get_device_address());
+        let build_info = Self::build_info(&geometry, self.structure.handle(), self.scratch.address);
+        let range = vk::AccelerationStructureBuildRangeInfoKHR {
+            primitive_count: self.instance_count,
+            ..Default::default()
+        };
+        device.cmd_build_acceleration_structures(command_buffer, &[build_info], &[&[range]]);
+    }
+}
+
+// acceleration structures of everything that is rendered, for the ray tracing passes
+//   blas: one per mesh, built in the frame the mesh shows up first
+//   tlas: one per frame slot with the instances of the frame, rebuilt every frame
+// only static meshes => deformed meshes (skinning, cloth) are not hit by rays
+pub struct RayTracingScene {
+    device: Arc<Device>,
+    allocator: Arc<Mutex<Allocator>>,
+    // by the id of the mesh buffers => shared by every instance of a mesh
+    blases: HashMap<u64, Blas>,
+    // blas + its scratch address, built before the tlas of the frame
+    pending_builds: Vec<(u64, vk::DeviceAddress)>,
+    tlas_slots: Vec<Option<TlasSlot>>,
+}
+
+impl RayTracingScene {
+    pub fn new(device: Arc<Device>, allocator: Arc<Mutex<Allocator>>, frame_count: usize) -> Self {
+        RayTracingScene {
+            device,
+            allocator,
+            blases: HashMap::new(),
+            pending_builds: Vec::new(),
+            tlas_slots: (0..frame_count).map(|_| None).collect(),
+        }
+    }
+
+    // writes the instances of the frame, new meshes get a blas
+    // scratch buffers and blases of meshes that are not rendered anymore are kept alive by the
+    // deletion queue of the frame
+    pub fn prepare<'m>(
+        &mut self,
+        frame_slot: usize,
+        instances: impl Iterator<Item = (&'m MeshAsset, &'m glm::Mat4)>,
+        deletion_queue: &mut DeletionQueue,
+    ) -> Result<(), VulkanError> {
+        self.pending_builds.clear();
+        let mut gpu_instances = Vec::new();
+        let mut used = HashSet::new();
+        for (mesh, world_matrix) in instances {
+            if mesh.surfaces().is_empty() {
+                continue;
+            }
+            let key = mesh.buffers().id();
+            used.insert(key);
+            if !self.blases.contains_key(&key) {
+                let (blas, scratch) = Blas::new(
+                    self.device.clone(),
+                    self.allocator.clone(),
+                    mesh.buffers(),
+                    mesh.surfaces(),
+                    mesh.name(),
+                )?;
+                self.pending_builds.push((key, scratch.address));
+                deletion_queue.push_resource(scratch);
+                self.blases.insert(key, blas);
+            }
+            gpu_instances.push(GPUInstance::new(world_matrix, self.blases[&key].address()));
+        }
+        // the mesh might be gone => rebuilt if it shows up again
+        let unused: Vec<u64> = self
+            .blases
+            .keys()
+            .filter(|key| !used.contains(*key))
+            .copied()
+            .collect();
+        for key in unused {
+            if let Some(blas) = self.blases.remove(&key) {
+                deletion_queue.push_resource(blas);
+            }
+        }
+
+        // the frame that used this slot last finished => the old buffers can be replaced
+        let slot = &mut self.tlas_slots[frame_slot];
+        let capacity = slot.as_ref().map_or(0, |slot| slot.capacity);
+        if gpu_instances.len() > capacity {
+            *slot = Some(TlasSlot::new(
+                self.device.clone(),
+                self.allocator.clone(),
+                gpu_instances.len().next_power_of_two(),
+            )?);
+        }
+        if let Some(slot) = slot.as_mut() {
+            slot.instance_buffer.copy_from_slice(&gpu_instances, 0);
+            slot.instance_count = gpu_instances.len() as u32;
+        }
+        Ok(())
+    }
+
+    // None before the first prepare with instances
+    pub fn tlas(&self, frame_slot: usize) -> Option<&AccelerationStructure> {
+        self.tlas_slots[frame_slot]
+            .as_ref()
+            .map(|slot| &slot.structure)
+    }
+
+    // builds the new blases and the tlas of the frame, returns the buffer of the tlas
+    // => passes that trace rays read it with BufferUsage::AccelerationStructureRead
+    // once per frame after prepare, all passes that trace rays share the tlas
+    pub fn add_build_pass<'a>(
+        &'a self,
+        graph: &mut RenderGraph<'a>,
+        frame_slot: usize,
+    ) -> Option<BufferHandle> {
+        let slot = self.tlas_slots[frame_slot].as_ref()?;
+        let tlas = graph.import_buffer("tlas", slot.structure.buffer());
+        let device = &self.device;
+        let mut pass = GraphPass::new("acceleration structures")
+            .buffer(tlas, BufferUsage::AccelerationStructureBuild);
+        // the new blases are only built once => the pass cant be culled in a frame without rays
+        if !self.pending_builds.is_empty() {
+            pass = pass.side_effects();
+        }
+        graph.add_pass(pass.record(move |command_buffer| {
+            let mut barriers = Vec::with_capacity(self.pending_builds.len());
+            for (key, scratch_address) in self.pending_builds.iter() {
+                let blas = &self.blases[key];
+                blas.record_build(device, command_buffer, *scratch_address);
+                barriers.push(vk::BufferMemoryBarrier2 {
+                    s_type: vk::StructureType::BUFFER_MEMORY_BARRIER_2,
+                    src_stage_mask: vk::PipelineStageFlags2::ACCELERATION_STRUCTURE_BUILD_KHR,
+                    src_access_mask: vk::AccessFlags2::ACCELERATION_STRUCTURE_WRITE_KHR,
+                    dst_stage_mask: vk::PipelineStageFlags2::ACCELERATION_STRUCTURE_BUILD_KHR,
+                    dst_access_mask: vk::AccessFlags2::ACCELERATION_STRUCTURE_READ_KHR,
+                    src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+                    dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+                    buffer: blas.structure.buffer(),
+                    offset: 0,
+                    size: vk::WHOLE_SIZE,
+                    ..Default::default()
+                });
+            }
+            // the tlas build reads the new blases
+            if !barriers.is_empty() {
+                device.cmd_pipeline_barrier(command_buffer, &[], &barriers);
+            }
+            slot.record_build(device, command_buffer);
+        }));
+        Some(tlas)
+    }
+}
+
+// one record per shader group, the regions are in the order raygen, miss, hit, callable
+pub struct ShaderBindingTable {
+    _buffer: AllocatedBuffer,
+    regions: [vk::StridedDeviceAddressRegionKHR; 4],
+}
+
+impl ShaderBindingTable {
+    // the groups of the pipeline have to be raygen, miss_count miss groups, hit_count hit groups
+    pub fn new(
+        device: Arc<Device>,
+        allocator: Arc<Mutex<Allocator>>,
+        pipeline: vk::Pipeline,
+        miss_count: u32,
+        hit_count: u32,
+    ) -> Result<Self, VulkanError> {
+        let properties = *device.ray_tracing_properties();
+        let handle_size = properties.shader_group_handle_size as vk::DeviceSize;
+        let base_alignment = properties.shader_group_base_alignment as vk::DeviceSize;
+        let stride = handle_size.next_multiple_of(properties.shader_group_handle_alignment as u64);
+        // the raygen region has to be exactly one record
+        let raygen_size = stride.next_multiple_of(base_alignment);
+        let region_size =
+            |count: u32| (count as vk::DeviceSize * stride).next_multiple_of(base_alignment);
+        let sizes = [
+            raygen_size,
+            region_size(miss_count),
+            region_size(hit_count),
+            0,
+        ];
+        let counts = [1, miss_count, hit_count, 0];
+
+        // + base_alignment => the start can be aligned
+        let buffer_size = sizes.iter().sum::<vk::DeviceSize>() + base_alignment;
+        let mut buffer = AllocatedBuffer::new(
+            device.clone(),
+            allocator,
+            "Shader Binding Table",
+            vk::BufferUsageFlags::SHADER_BINDING_TABLE_KHR
+                | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+            buffer_size,
+            gpu_allocator::MemoryLocation::CpuToGpu,
+        )?;
+        let buffer_address = buffer.get_device_address();
+        let start = buffer_address.next_multiple_of(base_alignment);
+
+        let handles =
+            device.get_ray_tracing_shader_group_handles(pipeline, 1 + miss_count + hit_count)?;
+        let mut table = vec![0u8; sizes.iter().sum::<vk::DeviceSize>() as usize];
+        let mut regions = [vk::StridedDeviceAddressRegionKHR::default(); 4];
+        let mut region_offset = 0;
+        let mut group = 0;
+        for (idx, (size, count)) in sizes.into_iter().zip(counts).enumerate() {
+            if count == 0 {
+                continue;
+            }
+            for record in 0..count as u64 {
+                let src = group * handle_size as usize;
+                let dst = (region_offset + record * stride) as usize;
+                table[dst..dst + handle_size as usize]
+                    .copy_from_slice(&handles[src..src + handle_size as usize]);
+                group += 1;
+            }
+            regions[idx] = vk::StridedDeviceAddressRegionKHR {
+                device_address: start + region_offset,
+                stride: if idx == 0 { size } else { stride },
+                size,
+            };
+            region_offset += size;
+        }
+        buffer.copy_from_slice(&table, (start - buffer_address) as usize);
+
+        Ok(ShaderBindingTable {
+            _buffer: buffer,
+            regions,
+        })
+    }
+
+    pub fn regions(&self) -> &[vk::StridedDeviceAddressRegionKHR; 4] {
+        &self.regions
+    }
+}
+
+pub struct RayTracingPipeline {
+    device: Arc<Device>,
+    pipeline: vk::Pipeline,
+    pipeline_layout: vk::PipelineLayout,
+    shader_binding_table: ShaderBindingTable,
+}
+
+impl RayTracingPipeline {
+    // one general group per raygen/miss shader, one triangle hit group per closest hit shader
+    // the push constants are only visible to the raygen shader
+    pub fn new(
+        device: Arc<Device>,
+        allocator: Arc<Mutex<Allocator>>,
+        set_layouts: &[vk::DescriptorSetLayout],
+        raygen: &ShaderModule,
+        miss: &[&ShaderModule],
+        closest_hit: &[&ShaderModule],
+    ) -> Result<Self, VulkanError> {
+        let push_constants = vk::PushConstantRange {
+            stage_flags: vk::ShaderStageFlags::RAYGEN_KHR,
+            offset: 0,
+            size: raygen.push_constant_size(),
+        };
+        let layout_create_info = vk::PipelineLayoutCreateInfo {
+            s_type: vk::StructureType::PIPELINE_LAYOUT_CREATE_INFO,
+            p_next: std::ptr::null(),
+            set_layout_count: set_layouts.len() as u32,
+            p_set_layouts: set_layouts.as_ptr(),
+            push_constant_range_count: (push_constants.size > 0) as u32,
+            p_push_constant_ranges: &push_constants,
+            ..Default::default()
+        };
+        let pipeline_layout = device.create_pipeline_layout(&layout_create_info)?;
+
+        let mut stages = vec![raygen.create_shader_stage_info(vk::ShaderStageFlags::RAYGEN_KHR)];
+        stages.extend(
+            miss.iter()
+                .map(|shader| shader.create_shader_stage_info(vk::ShaderStageFlags::MISS_KHR)),
+        );
+        stages.extend(
+            closest_hit.iter().map(|shader| {
+                shader.create_shader_stage_info(vk::ShaderStageFlags::CLOSEST_HIT_KHR)
+            }),
+        );
+        // stage idx == group idx
+        let groups: Vec<_> = (0..stages.len() as u32)
+            .map(|stage| {
+                let general = stage <= miss.len() as u32;
+                vk::RayTracingShaderGroupCreateInfoKHR {
+                    s_type: vk::StructureType::RAY_TRACING_SHADER_GROUP_CREATE_INFO_KHR,
+                    ty: if general {
+                        vk::RayTracingShaderGroupTypeKHR::GENERAL
+                    } else {
+                        vk::RayTracingShaderGroupTypeKHR::TRIANGLES_HIT_GROUP
+                    },
+                    general_shader: if general {
+                        stage
+                    } else {
+                        vk::SHADER_UNUSED_KHR
+                    },
+                    closest_hit_shader: if general {
+                        vk::SHADER_UNUSED_KHR
+                    } else {
+                        stage
+                    },
+                    any_hit_shader: vk::SHADER_UNUSED_KHR,
+                    intersection_shader: vk::SHADER_UNUSED_KHR,
+                    ..Default::default()
+                }
+            })
+            .collect();
+        let pipeline_create_info = vk::RayTracingPipelineCreateInfoKHR {
+            s_type: vk::StructureType::RAY_TRACING_PIPELINE_CREATE_INFO_KHR,
+            stage_count: stages.len() as u32,
+            p_stages: stages.as_ptr(),
+            group_count: groups.len() as u32,
+            p_groups: groups.as_ptr(),
+            // rays are only traced from the raygen shader
+            max_pipeline_ray_recursion_depth: 1,
+            layout: pipeline_layout,
+            ..Default::default()
+        };
+        let pipeline = match device.create_ray_tracing_pipelines(&[pipeline_create_info]) {
+            Ok(pipelines) => pipelines[0],
+            Err(e) => {
+                device.destroy_pipeline_layout(pipeline_layout);
+                return Err(e);
+            }
+        };
+        device.set_object_name(pipeline, raygen.name());
+        let shader_binding_table = match ShaderBindingTable::new(
+            device.clone(),
+            allocator,
+            pipeline,
+            miss.len() as u32,
+            closest_hit.len() as u32,
+        ) {
+            Ok(table) => table,
+            Err(e) => {
+                device.destroy_pipeline(pipeline);
+                device.destroy_pipeline_layout(pipeline_layout);
+                return Err(e);
+            }
+        };
+        Ok(RayTracingPipeline {
+            device,
+            pipeline,
+            pipeline_layout,
+            shader_binding_table,
+        })
+    }
+
+    // one raygen invocation per pixel of extent
+    pub fn trace_rays(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        descriptor_sets: &[vk::DescriptorSet],
+        extent: vk::Extent2D,
+        push_constants: &[u8],
+    ) {
+        self.device.trace_rays(
+            command_buffer,
+            self.pipeline,
+            self.pipeline_layout,
+            descriptor_sets,
+            self.shader_binding_table.regions(),
+            extent,
+            push_constants,
+        );
+    }
+}
+
+impl Drop for RayTracingPipeline {
+    fn drop(&mut self) {
+        log::debug!("Dropping RayTracingPipeline");
+        self.device.destroy_pipeline(self.pipeline);
+        self.device.destroy_pipeline_layout(self.pipeline_layout);
+    }
+}
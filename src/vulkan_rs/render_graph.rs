@@ -0,0 +1,992 @@
+use super::device::Device;
+use super::error::VulkanError;
+use super::utils::format_size;
+use ash::vk;
+
+const PASS_LABEL_COLOR: [f32; 4] = [0.2, 0.6, 1.0, 1.0];
+
+// usages dont say which shader stage accesses a resource => sync against all of them
+fn shader_stages() -> vk::PipelineStageFlags2 {
+    vk::PipelineStageFlags2::COMPUTE_SHADER
+        | vk::PipelineStageFlags2::VERTEX_SHADER
+        | vk::PipelineStageFlags2::FRAGMENT_SHADER
+}
+
+// stages a compute queue can sync on, barriers of async compute passes are limited to them
+fn compute_queue_stages() -> vk::PipelineStageFlags2 {
+    vk::PipelineStageFlags2::COMPUTE_SHADER
+        | vk::PipelineStageFlags2::ALL_TRANSFER
+        | vk::PipelineStageFlags2::DRAW_INDIRECT
+        | vk::PipelineStageFlags2::ACCELERATION_STRUCTURE_BUILD_KHR
+        | vk::PipelineStageFlags2::ALL_COMMANDS
+}
+
+fn compute_queue_accesses() -> vk::AccessFlags2 {
+    vk::AccessFlags2::SHADER_READ
+        | vk::AccessFlags2::SHADER_WRITE
+        | vk::AccessFlags2::SHADER_SAMPLED_READ
+        | vk::AccessFlags2::SHADER_STORAGE_READ
+        | vk::AccessFlags2::SHADER_STORAGE_WRITE
+        | vk::AccessFlags2::UNIFORM_READ
+        | vk::AccessFlags2::INDIRECT_COMMAND_READ
+        | vk::AccessFlags2::TRANSFER_READ
+        | vk::AccessFlags2::TRANSFER_WRITE
+        | vk::AccessFlags2::ACCELERATION_STRUCTURE_READ_KHR
+        | vk::AccessFlags2::ACCELERATION_STRUCTURE_WRITE_KHR
+        | vk::AccessFlags2::MEMORY_READ
+        | vk::AccessFlags2::MEMORY_WRITE
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PassQueue {
+    Graphics,
+    // the async compute queue if the device has one, the graphics queue otherwise
+    Compute,
+}
+
+impl PassQueue {
+    pub fn index(self) -> usize {
+        match self {
+            PassQueue::Graphics => 0,
+            PassQueue::Compute => 1,
+        }
+    }
+
+    // graphics stages/accesses of the other queue are dropped, the semaphore between the
+    // submissions already waits for them
+    fn barrier_scope(
+        self,
+        (stage, access): (vk::PipelineStageFlags2, vk::AccessFlags2),
+    ) -> (vk::PipelineStageFlags2, vk::AccessFlags2) {
+        match self {
+            PassQueue::Graphics => (stage, access),
+            PassQueue::Compute => {
+                let compute_stage = stage & compute_queue_stages();
+                if compute_stage.is_empty() {
+                    (
+                        vk::PipelineStageFlags2::ALL_COMMANDS,
+                        vk::AccessFlags2::NONE,
+                    )
+                } else {
+                    (compute_stage, access & compute_queue_accesses())
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[allow(dead_code)]
+pub enum ImageUsage {
+    ColorAttachment,
+    DepthAttachment,
+    StorageRead,
+    StorageWrite,
+    Sampled,
+    TransferSrc,
+    TransferDst,
+    // ray tracing shaders, not part of shader_stages() => the stage needs the ray tracing feature
+    RayTracingSampled,
+    RayTracingStorageWrite,
+}
+
+impl ImageUsage {
+    fn layout(&self) -> vk::ImageLayout {
+        match self {
+            ImageUsage::ColorAttachment => vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            ImageUsage::DepthAttachment => vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL,
+            ImageUsage::StorageRead
+            | ImageUsage::StorageWrite
+            | ImageUsage::RayTracingStorageWrite => vk::ImageLayout::GENERAL,
+            ImageUsage::Sampled | ImageUsage::RayTracingSampled => {
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
+            }
+            ImageUsage::TransferSrc => vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            ImageUsage::TransferDst => vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+        }
+    }
+
+    // how often the image goes through memory, attachments are loaded and stored
+    fn traffic(&self) -> u64 {
+        match self {
+            ImageUsage::ColorAttachment
+            | ImageUsage::DepthAttachment
+            | ImageUsage::StorageWrite
+            | ImageUsage::RayTracingStorageWrite => 2,
+            _ => 1,
+        }
+    }
+
+    fn access(&self) -> Access {
+        match self {
+            ImageUsage::ColorAttachment => Access {
+                stage: vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+                access: vk::AccessFlags2::COLOR_ATTACHMENT_READ
+                    | vk::AccessFlags2::COLOR_ATTACHMENT_WRITE,
+                write: true,
+            },
+            ImageUsage::DepthAttachment => Access {
+                stage: vk::PipelineStageFlags2::EARLY_FRAGMENT_TESTS
+                    | vk::PipelineStageFlags2::LATE_FRAGMENT_TESTS,
+                access: vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_READ
+                    | vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                write: true,
+            },
+            ImageUsage::StorageRead => Access {
+                stage: shader_stages(),
+                access: vk::AccessFlags2::SHADER_STORAGE_READ,
+                write: false,
+            },
+            ImageUsage::StorageWrite => Access {
+                stage: shader_stages(),
+                access: vk::AccessFlags2::SHADER_STORAGE_READ
+                    | vk::AccessFlags2::SHADER_STORAGE_WRITE,
+                write: true,
+            },
+            ImageUsage::Sampled => Access {
+                stage: shader_stages(),
+                access: vk::AccessFlags2::SHADER_SAMPLED_READ,
+                write: false,
+            },
+            ImageUsage::TransferSrc => Access {
+                stage: vk::PipelineStageFlags2::TRANSFER,
+                access: vk::AccessFlags2::TRANSFER_READ,
+                write: false,
+            },
+            ImageUsage::TransferDst => Access {
+                stage: vk::PipelineStageFlags2::TRANSFER,
+                access: vk::AccessFlags2::TRANSFER_WRITE,
+                write: true,
+            },
+            ImageUsage::RayTracingSampled => Access {
+                stage: vk::PipelineStageFlags2::RAY_TRACING_SHADER_KHR,
+                access: vk::AccessFlags2::SHADER_SAMPLED_READ,
+                write: false,
+            },
+            ImageUsage::RayTracingStorageWrite => Access {
+                stage: vk::PipelineStageFlags2::RAY_TRACING_SHADER_KHR,
+                access: vk::AccessFlags2::SHADER_STORAGE_READ
+                    | vk::AccessFlags2::SHADER_STORAGE_WRITE,
+                write: true,
+            },
+        }
+    }
+}
+
+// buffers that are only written by the host before the graph runs dont have to be imported
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[allow(dead_code)]
+pub enum BufferUsage {
+    Uniform,
+    StorageRead,
+    StorageWrite,
+    Index,
+    Indirect,
+    TransferSrc,
+    TransferDst,
+    // the buffer of an acceleration structure, written by the build, read by ray tracing shaders
+    AccelerationStructureBuild,
+    AccelerationStructureRead,
+    // ray queries in compute/graphics shaders, the stage of AccelerationStructureRead needs
+    // the ray tracing pipeline extension
+    AccelerationStructureQuery,
+}
+
+impl BufferUsage {
+    fn access(&self) -> Access {
+        match self {
+            BufferUsage::Uniform => Access {
+                stage: shader_stages(),
+                access: vk::AccessFlags2::UNIFORM_READ,
+                write: false,
+            },
+            BufferUsage::StorageRead => Access {
+                stage: shader_stages(),
+                access: vk::AccessFlags2::SHADER_STORAGE_READ,
+                write: false,
+            },
+            BufferUsage::StorageWrite => Access {
+                stage: shader_stages(),
+                access: vk::AccessFlags2::SHADER_STORAGE_READ
+                    | vk::AccessFlags2::SHADER_STORAGE_WRITE,
+                write: true,
+            },
+            BufferUsage::Index => Access {
+                stage: vk::PipelineStageFlags2::INDEX_INPUT,
+                access: vk::AccessFlags2::INDEX_READ,
+                write: false,
+            },
+            BufferUsage::Indirect => Access {
+                stage: vk::PipelineStageFlags2::DRAW_INDIRECT,
+                access: vk::AccessFlags2::INDIRECT_COMMAND_READ,
+                write: false,
+            },
+            BufferUsage::TransferSrc => Access {
+                stage: vk::PipelineStageFlags2::TRANSFER,
+                access: vk::AccessFlags2::TRANSFER_READ,
+                write: false,
+            },
+            BufferUsage::TransferDst => Access {
+                stage: vk::PipelineStageFlags2::TRANSFER,
+                access: vk::AccessFlags2::TRANSFER_WRITE,
+                write: true,
+            },
+            BufferUsage::AccelerationStructureBuild => Access {
+                stage: vk::PipelineStageFlags2::ACCELERATION_STRUCTURE_BUILD_KHR,
+                access: vk::AccessFlags2::ACCELERATION_STRUCTURE_READ_KHR
+                    | vk::AccessFlags2::ACCELERATION_STRUCTURE_WRITE_KHR,
+                write: true,
+            },
+            BufferUsage::AccelerationStructureRead => Access {
+                stage: vk::PipelineStageFlags2::RAY_TRACING_SHADER_KHR,
+                access: vk::AccessFlags2::ACCELERATION_STRUCTURE_READ_KHR,
+                write: false,
+            },
+            BufferUsage::AccelerationStructureQuery => Access {
+                stage: shader_stages(),
+                access: vk::AccessFlags2::ACCELERATION_STRUCTURE_READ_KHR,
+                write: false,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Access {
+    stage: vk::PipelineStageFlags2,
+    access: vk::AccessFlags2,
+    write: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImageHandle(usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BufferHandle(usize);
+
+// what happened to a resource since its last write. Used to find out which barriers are needed
+#[derive(Debug, Clone, Copy)]
+struct ResourceState {
+    layout: vk::ImageLayout,
+    write_stage: vk::PipelineStageFlags2,
+    write_access: vk::AccessFlags2,
+    // reads that already wait for the last write
+    read_stage: vk::PipelineStageFlags2,
+    read_access: vk::AccessFlags2,
+}
+
+impl ResourceState {
+    // we dont know what happened before the graph => wait for everything
+    fn imported(layout: vk::ImageLayout) -> Self {
+        ResourceState {
+            layout,
+            write_stage: vk::PipelineStageFlags2::ALL_COMMANDS,
+            write_access: vk::AccessFlags2::MEMORY_WRITE,
+            read_stage: vk::PipelineStageFlags2::NONE,
+            read_access: vk::AccessFlags2::NONE,
+        }
+    }
+
+    // returns the (src stage, src access) of the barrier that is needed before the access
+    fn transition(
+        &mut self,
+        access: Access,
+        layout: vk::ImageLayout,
+    ) -> Option<(vk::PipelineStageFlags2, vk::AccessFlags2)> {
+        let needs_transition = self.layout != layout;
+        if access.write || needs_transition {
+            // write after read only needs an execution dependency on the reads
+            let src = (self.write_stage | self.read_stage, self.write_access);
+            self.layout = layout;
+            if access.write {
+                self.write_stage = access.stage;
+                self.write_access = access.access;
+                self.read_stage = vk::PipelineStageFlags2::NONE;
+                self.read_access = vk::AccessFlags2::NONE;
+            } else {
+                // the layout transition is the last write => later reads have to wait for it
+                self.write_stage = access.stage;
+                self.write_access = vk::AccessFlags2::NONE;
+                self.read_stage = access.stage;
+                self.read_access = access.access;
+            }
+            return Some(src);
+        }
+        if self.read_stage.contains(access.stage) && self.read_access.contains(access.access) {
+            return None;
+        }
+        self.read_stage |= access.stage;
+        self.read_access |= access.access;
+        Some((self.write_stage, self.write_access))
+    }
+}
+
+// which submissions touched a resource since its last write => what a pass has to wait for
+#[derive(Debug, Clone, Default)]
+struct ResourceSubmissions {
+    writer: Option<usize>,
+    readers: Vec<usize>,
+}
+
+impl ResourceSubmissions {
+    fn dependencies(&self, write: bool) -> impl Iterator<Item = usize> + '_ {
+        // write after read => the reads have to be finished too
+        let readers = if write { &self.readers[..] } else { &[] };
+        self.writer.iter().chain(readers).copied()
+    }
+
+    fn record(&mut self, submission: usize, write: bool) {
+        if write {
+            self.writer = Some(submission);
+            self.readers.clear();
+        } else {
+            self.readers.push(submission);
+        }
+    }
+}
+
+struct GraphImage {
+    name: String,
+    image: vk::Image,
+    aspect_mask: vk::ImageAspectFlags,
+    state: ResourceState,
+    final_layout: Option<vk::ImageLayout>,
+    // bytes of the part of the image the passes use, None => not counted in the estimates
+    size: Option<u64>,
+    // waited for by the submission of the first pass that uses the image
+    wait_semaphore: Option<vk::SemaphoreSubmitInfo<'static>>,
+}
+
+// rough per pass numbers: every access reads/writes the whole used area of an image once
+// caches, compression and buffers are ignored => only good for comparing passes
+#[derive(Debug, Clone)]
+pub struct PassEstimate {
+    pub name: String,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct GraphEstimate {
+    // passes that were not culled, in execution order
+    pub passes: Vec<PassEstimate>,
+    // images with a size that are used by any pass
+    pub image_memory: u64,
+}
+
+struct GraphBuffer {
+    name: String,
+    buffer: vk::Buffer,
+    state: ResourceState,
+    exported: bool,
+}
+
+// one command buffer of the graph, has to be submitted in the order of the submissions
+// every submission signals the timeline of its queue once => the n-th submission of a queue
+// in this frame signals the n-th value after the ones of the previous frame
+pub struct GraphSubmission {
+    pub queue: PassQueue,
+    pub command_buffer: vk::CommandBuffer,
+    // index of the submission of the other queue that has to be finished first
+    pub wait: Option<usize>,
+    // e.g. the acquire semaphore of the swapchain image, see wait_before_use
+    pub wait_semaphores: Vec<vk::SemaphoreSubmitInfo<'static>>,
+}
+
+// where the kept passes go, decided before anything is recorded
+#[derive(Debug)]
+struct GraphPlan {
+    // (pass, submission) in execution order
+    passes: Vec<(usize, usize)>,
+    // queue of every submission and the submission of the other queue it waits for
+    submissions: Vec<(PassQueue, Option<usize>)>,
+    // graphics submission that ends the frame with the final layout transitions
+    final_submission: usize,
+}
+
+type RecordFn<'a> = Box<dyn FnOnce(vk::CommandBuffer) + 'a>;
+
+pub struct GraphPass<'a> {
+    name: String,
+    images: Vec<(ImageHandle, ImageUsage)>,
+    buffers: Vec<(BufferHandle, BufferUsage)>,
+    side_effects: bool,
+    queue: PassQueue,
+    record: Option<RecordFn<'a>>,
+}
+
+impl<'a> GraphPass<'a> {
+    pub fn new(name: &str) -> Self {
+        GraphPass {
+            name: name.to_string(),
+            images: Vec::new(),
+            buffers: Vec::new(),
+            side_effects: false,
+            queue: PassQueue::Graphics,
+            record: None,
+        }
+    }
+
+    pub fn image(mut self, image: ImageHandle, usage: ImageUsage) -> Self {
+        self.images.push((image, usage));
+        self
+    }
+
+    pub fn buffer(mut self, buffer: BufferHandle, usage: BufferUsage) -> Self {
+        self.buffers.push((buffer, usage));
+        self
+    }
+
+    // pass writes to something the graph doesnt know about (e.g. readback buffers)
+    // => it is never culled
+    pub fn side_effects(mut self) -> Self {
+        self.side_effects = true;
+        self
+    }
+
+    // only compute dispatches and transfers => the pass can overlap the graphics passes on the
+    // async compute queue. Images it shares with graphics passes need the async compute
+    // families (CONCURRENT), the graph doesnt transfer ownership
+    pub fn async_compute(mut self) -> Self {
+        self.queue = PassQueue::Compute;
+        self
+    }
+
+    pub fn record(mut self, record: impl FnOnce(vk::CommandBuffer) + 'a) -> Self {
+        self.record = Some(Box::new(record));
+        self
+    }
+}
+
+// passes declare which resources they use and how, the graph inserts the barriers/layout
+// transitions between them. The execution order is derived from the usages: a pass runs after
+// the passes added before it that wrote what it uses or used what it writes. Independent
+// passes are free to move => async compute passes run as early as possible, everything else
+// keeps the order it was added in. Passes that dont contribute to an exported resource are culled
+// async compute passes are recorded into their own submissions, timeline semaphores between
+// the submissions replace the barriers between the queues
+// resources only live for one frame => rebuild the graph every frame
+#[derive(Default)]
+pub struct RenderGraph<'a> {
+    images: Vec<GraphImage>,
+    buffers: Vec<GraphBuffer>,
+    passes: Vec<GraphPass<'a>>,
+}
+
+impl<'a> RenderGraph<'a> {
+    pub fn new() -> Self {
+        RenderGraph {
+            images: Vec::new(),
+            buffers: Vec::new(),
+            passes: Vec::new(),
+        }
+    }
+
+    pub fn import_image(
+        &mut self,
+        name: &str,
+        image: vk::Image,
+        aspect_mask: vk::ImageAspectFlags,
+        initial_layout: vk::ImageLayout,
+    ) -> ImageHandle {
+        self.images.push(GraphImage {
+            name: name.to_string(),
+            image,
+            aspect_mask,
+            state: ResourceState::imported(initial_layout),
+            final_layout: None,
+            size: None,
+            wait_semaphore: None,
+        });
+        ImageHandle(self.images.len() - 1)
+    }
+
+    // e.g. the acquire semaphore of a swapchain image => passes that dont use it dont wait
+    pub fn wait_before_use(
+        &mut self,
+        image: ImageHandle,
+        semaphore: vk::SemaphoreSubmitInfo<'static>,
+    ) {
+        self.images[image.0].wait_semaphore = Some(semaphore);
+    }
+
+    // the part of the image the passes use => used for the bandwidth estimates
+    pub fn set_image_size(&mut self, image: ImageHandle, extent: vk::Extent2D, format: vk::Format) {
+        self.images[image.0].size =
+            Some(extent.width as u64 * extent.height as u64 * format_size(format));
+    }
+
+    pub fn import_buffer(&mut self, name: &str, buffer: vk::Buffer) -> BufferHandle {
+        self.buffers.push(GraphBuffer {
+            name: name.to_string(),
+            buffer,
+            state: ResourceState::imported(vk::ImageLayout::UNDEFINED),
+            exported: false,
+        });
+        BufferHandle(self.buffers.len() - 1)
+    }
+
+    // image is used after the graph (e.g. presented) => transitioned to final_layout at the end
+    pub fn export_image(&mut self, image: ImageHandle, final_layout: vk::ImageLayout) {
+        self.images[image.0].final_layout = Some(final_layout);
+    }
+
+    #[allow(dead_code)]
+    pub fn export_buffer(&mut self, buffer: BufferHandle) {
+        self.buffers[buffer.0].exported = true;
+    }
+
+    pub fn add_pass(&mut self, pass: GraphPass<'a>) {
+        self.passes.push(pass);
+    }
+
+    // walks the passes backwards and only keeps the ones that write something that is needed
+    // later. Every resource a kept pass uses is needed => conservative, but never wrong
+    fn cull_passes(&self) -> Vec<bool> {
+        let mut needed_images: Vec<bool> = self
+            .images
+            .iter()
+            .map(|image| image.final_layout.is_some())
+            .collect();
+        let mut needed_buffers: Vec<bool> =
+            self.buffers.iter().map(|buffer| buffer.exported).collect();
+        let mut keep = vec![false; self.passes.len()];
+        for (idx, pass) in self.passes.iter().enumerate().rev() {
+            let writes_needed_image = pass
+                .images
+                .iter()
+                .any(|(image, usage)| usage.access().write && needed_images[image.0]);
+            let writes_needed_buffer = pass
+                .buffers
+                .iter()
+                .any(|(buffer, usage)| usage.access().write && needed_buffers[buffer.0]);
+            if !(pass.side_effects || writes_needed_image || writes_needed_buffer) {
+                log::trace!("Culling render graph pass {}", pass.name);
+                continue;
+            }
+            keep[idx] = true;
+            for (image, _) in pass.images.iter() {
+                needed_images[image.0] = true;
+            }
+            for (buffer, _) in pass.buffers.iter() {
+                needed_buffers[buffer.0] = true;
+            }
+        }
+        keep
+    }
+
+    // topological order of the kept passes. Hazards (read after write, write after read/write,
+    // layout changes) between passes that use the same resource keep the order the passes were
+    // added in, those are the only edges
+    fn schedule_passes(&self, keep: &[bool], async_compute: bool) -> Vec<usize> {
+        let mut image_submissions = vec![ResourceSubmissions::default(); self.images.len()];
+        let mut image_layouts: Vec<vk::ImageLayout> =
+            self.images.iter().map(|image| image.state.layout).collect();
+        let mut buffer_submissions = vec![ResourceSubmissions::default(); self.buffers.len()];
+        let mut dependents: Vec<Vec<usize>> = vec![Vec::new(); self.passes.len()];
+        let mut dependency_count = vec![0; self.passes.len()];
+        for (idx, pass) in self.passes.iter().enumerate() {
+            if !keep[idx] {
+                continue;
+            }
+            let mut dependencies = Vec::new();
+            for (handle, usage) in pass.images.iter() {
+                // a layout transition writes the image
+                let write = usage.access().write || image_layouts[handle.0] != usage.layout();
+                image_layouts[handle.0] = usage.layout();
+                dependencies.extend(image_submissions[handle.0].dependencies(write));
+                image_submissions[handle.0].record(idx, write);
+            }
+            for (handle, usage) in pass.buffers.iter() {
+                let write = usage.access().write;
+                dependencies.extend(buffer_submissions[handle.0].dependencies(write));
+                buffer_submissions[handle.0].record(idx, write);
+            }
+            dependencies.sort_unstable();
+            dependencies.dedup();
+            for dependency in dependencies
+                .into_iter()
+                .filter(|dependency| *dependency != idx)
+            {
+                dependents[dependency].push(idx);
+                dependency_count[idx] += 1;
+            }
+        }
+
+        let mut ready: Vec<usize> = (0..self.passes.len())
+            .filter(|idx| keep[*idx] && dependency_count[*idx] == 0)
+            .collect();
+        let mut order = Vec::with_capacity(ready.len());
+        while let Some(position) = ready
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, idx)| {
+                let compute = async_compute && self.passes[**idx].queue == PassQueue::Compute;
+                (!compute, **idx)
+            })
+            .map(|(position, _)| position)
+        {
+            let idx = ready.swap_remove(position);
+            order.push(idx);
+            for dependent in dependents[idx].iter() {
+                dependency_count[*dependent] -= 1;
+                if dependency_count[*dependent] == 0 {
+                    ready.push(*dependent);
+                }
+            }
+        }
+        order
+    }
+
+    fn image_barrier(
+        image: &GraphImage,
+        old_layout: vk::ImageLayout,
+        src: (vk::PipelineStageFlags2, vk::AccessFlags2),
+        dst: (vk::PipelineStageFlags2, vk::AccessFlags2),
+    ) -> vk::ImageMemoryBarrier2<'static> {
+        vk::ImageMemoryBarrier2 {
+            s_type: vk::StructureType::IMAGE_MEMORY_BARRIER_2,
+            p_next: std::ptr::null(),
+            src_stage_mask: src.0,
+            src_access_mask: src.1,
+            dst_stage_mask: dst.0,
+            dst_access_mask: dst.1,
+            old_layout,
+            new_layout: image.state.layout,
+            image: image.image,
+            subresource_range: vk::ImageSubresourceRange {
+                aspect_mask: image.aspect_mask,
+                base_mip_level: 0,
+                level_count: vk::REMAINING_MIP_LEVELS,
+                base_array_layer: 0,
+                layer_count: vk::REMAINING_ARRAY_LAYERS,
+            },
+            ..Default::default()
+        }
+    }
+
+    // a pass goes into the latest submission of its queue unless it has to wait for a newer
+    // submission of the other queue => independent passes overlap the other queue
+    // the last submission is always on the graphics queue and waits for every compute one
+    fn plan(&self, async_compute: bool) -> GraphPlan {
+        let keep = self.cull_passes();
+        let order = self.schedule_passes(&keep, async_compute);
+        let mut image_submissions = vec![ResourceSubmissions::default(); self.images.len()];
+        let mut buffer_submissions = vec![ResourceSubmissions::default(); self.buffers.len()];
+        let mut submissions: Vec<(PassQueue, Option<usize>)> = vec![(PassQueue::Graphics, None)];
+        // latest submission of each queue
+        let mut open: [Option<usize>; 2] = [Some(0), None];
+        let mut passes = Vec::with_capacity(order.len());
+        for idx in order {
+            let pass = &self.passes[idx];
+            let queue = if async_compute {
+                pass.queue
+            } else {
+                PassQueue::Graphics
+            };
+            // submissions are created in order => the newest one of the other queue is enough
+            let image_dependencies = pass.images.iter().flat_map(|(handle, usage)| {
+                image_submissions[handle.0].dependencies(usage.access().write)
+            });
+            let buffer_dependencies = pass.buffers.iter().flat_map(|(handle, usage)| {
+                buffer_submissions[handle.0].dependencies(usage.access().write)
+            });
+            let wait = image_dependencies
+                .chain(buffer_dependencies)
+                .filter(|idx| submissions[*idx].0 != queue)
+                .max();
+            let submission = match open[queue.index()] {
+                Some(submission) if wait <= submissions[submission].1 => submission,
+                _ => {
+                    submissions.push((queue, wait));
+                    open[queue.index()] = Some(submissions.len() - 1);
+                    submissions.len() - 1
+                }
+            };
+            for (handle, usage) in pass.images.iter() {
+                image_submissions[handle.0].record(submission, usage.access().write);
+            }
+            for (handle, usage) in pass.buffers.iter() {
+                buffer_submissions[handle.0].record(submission, usage.access().write);
+            }
+            passes.push((idx, submission));
+        }
+
+        // the final transitions and the end of the frame come after everything
+        let last_compute = submissions
+            .iter()
+            .rposition(|(queue, _)| *queue == PassQueue::Compute);
+        let final_submission = match open[PassQueue::Graphics.index()] {
+            Some(submission) if last_compute <= submissions[submission].1 => submission,
+            _ => {
+                submissions.push((PassQueue::Graphics, last_compute));
+                submissions.len() - 1
+            }
+        };
+        GraphPlan {
+            passes,
+            submissions,
+            final_submission,
+        }
+    }
+
+    // command_buffer is the first graphics submission, next_command_buffer has to return a
+    // command buffer of the queue in the recording state for every other submission of the plan
+    pub fn execute(
+        mut self,
+        device: &Device,
+        command_buffer: vk::CommandBuffer,
+        mut next_command_buffer: impl FnMut(PassQueue) -> Result<vk::CommandBuffer, VulkanError>,
+    ) -> Result<(GraphEstimate, Vec<GraphSubmission>), VulkanError> {
+        let plan = self.plan(device.has_async_compute());
+        let mut submissions = Vec::with_capacity(plan.submissions.len());
+        for (idx, (queue, wait)) in plan.submissions.iter().enumerate() {
+            submissions.push(GraphSubmission {
+                queue: *queue,
+                command_buffer: if idx == 0 {
+                    command_buffer
+                } else {
+                    next_command_buffer(*queue)?
+                },
+                wait: *wait,
+                wait_semaphores: Vec::new(),
+            });
+        }
+        let mut passes: Vec<Option<GraphPass>> = std::mem::take(&mut self.passes)
+            .into_iter()
+            .map(Some)
+            .collect();
+        let mut estimate = GraphEstimate::default();
+        let mut used_images = vec![false; self.images.len()];
+        let planned_passes = plan
+            .passes
+            .iter()
+            .filter_map(|(idx, submission_idx)| Some((passes[*idx].take()?, *submission_idx)));
+        for (pass, submission_idx) in planned_passes {
+            let queue = submissions[submission_idx].queue;
+            let command_buffer = submissions[submission_idx].command_buffer;
+
+            let mut bytes = 0;
+            for (handle, usage) in pass.images.iter() {
+                bytes += self.images[handle.0].size.unwrap_or(0) * usage.traffic();
+                used_images[handle.0] = true;
+            }
+            estimate.passes.push(PassEstimate {
+                name: pass.name.clone(),
+                bytes,
+            });
+
+            // barriers are part of the label => captures show them under the pass that needs them
+            device.cmd_begin_label(command_buffer, &pass.name, PASS_LABEL_COLOR);
+            let mut image_barriers = Vec::new();
+            for (handle, usage) in pass.images.iter() {
+                let image = &mut self.images[handle.0];
+                let old_layout = image.state.layout;
+                let access = usage.access();
+                if let Some(semaphore) = image.wait_semaphore.take() {
+                    submissions[submission_idx].wait_semaphores.push(semaphore);
+                }
+                if let Some(src) = image.state.transition(access, usage.layout()) {
+                    log::trace!(
+                        "{}: barrier for {} ({:?} -> {:?})",
+                        pass.name,
+                        image.name,
+                        old_layout,
+                        image.state.layout
+                    );
+                    image_barriers.push(Self::image_barrier(
+                        image,
+                        old_layout,
+                        queue.barrier_scope(src),
+                        queue.barrier_scope((access.stage, access.access)),
+                    ));
+                }
+            }
+            let mut buffer_barriers = Vec::new();
+            for (handle, usage) in pass.buffers.iter() {
+                let buffer = &mut self.buffers[handle.0];
+                let access = usage.access();
+                if let Some(src) = buffer.state.transition(access, vk::ImageLayout::UNDEFINED) {
+                    log::trace!("{}: barrier for {}", pass.name, buffer.name);
+                    let src = queue.barrier_scope(src);
+                    let dst = queue.barrier_scope((access.stage, access.access));
+                    buffer_barriers.push(vk::BufferMemoryBarrier2 {
+                        s_type: vk::StructureType::BUFFER_MEMORY_BARRIER_2,
+                        p_next: std::ptr::null(),
+                        src_stage_mask: src.0,
+                        src_access_mask: src.1,
+                        dst_stage_mask: dst.0,
+                        dst_access_mask: dst.1,
+                        buffer: buffer.buffer,
+                        offset: 0,
+                        size: vk::WHOLE_SIZE,
+                        ..Default::default()
+                    });
+                }
+            }
+            if !image_barriers.is_empty() || !buffer_barriers.is_empty() {
+                device.cmd_pipeline_barrier(command_buffer, &image_barriers, &buffer_barriers);
+            }
+            if let Some(record) = pass.record {
+                record(command_buffer);
+            }
+            device.cmd_end_label(command_buffer);
+        }
+
+        let command_buffer = submissions[plan.final_submission].command_buffer;
+        let mut final_barriers = Vec::new();
+        for image in self.images.iter_mut() {
+            let Some(final_layout) = image.final_layout else {
+                continue;
+            };
+            if image.state.layout == final_layout {
+                continue;
+            }
+            let old_layout = image.state.layout;
+            let src = (
+                image.state.write_stage | image.state.read_stage,
+                image.state.write_access,
+            );
+            image.state.layout = final_layout;
+            // whatever comes after the graph (present, next submit) is synchronized with
+            // semaphores/fences => only the layout transition matters here
+            final_barriers.push(Self::image_barrier(
+                image,
+                old_layout,
+                src,
+                (
+                    vk::PipelineStageFlags2::ALL_COMMANDS,
+                    vk::AccessFlags2::NONE,
+                ),
+            ));
+        }
+        if !final_barriers.is_empty() {
+            device.cmd_pipeline_barrier(command_buffer, &final_barriers, &[]);
+        }
+        estimate.image_memory = self
+            .images
+            .iter()
+            .zip(used_images)
+            .filter(|(_, used)| *used)
+            .filter_map(|(image, _)| image.size)
+            .sum();
+        Ok((estimate, submissions))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // only the declared usages matter for the order => null handles are enough
+    fn image(graph: &mut RenderGraph, name: &str) -> ImageHandle {
+        graph.import_image(
+            name,
+            vk::Image::null(),
+            vk::ImageAspectFlags::COLOR,
+            vk::ImageLayout::UNDEFINED,
+        )
+    }
+
+    fn pass_names(graph: &RenderGraph, order: &[usize]) -> Vec<String> {
+        order
+            .iter()
+            .map(|idx| graph.passes[*idx].name.clone())
+            .collect()
+    }
+
+    #[test]
+    fn write_read_chain_declared_out_of_order() {
+        let mut graph = RenderGraph::new();
+        // imported in the opposite order of their use
+        let target = image(&mut graph, "target");
+        let shadow = image(&mut graph, "shadow");
+        let draws = graph.import_buffer("draws", vk::Buffer::null());
+        graph.export_image(target, vk::ImageLayout::PRESENT_SRC_KHR);
+        graph.add_pass(GraphPass::new("shadow").image(shadow, ImageUsage::DepthAttachment));
+        // added after the shadow pass, but independent of it
+        graph.add_pass(
+            GraphPass::new("cull")
+                .buffer(draws, BufferUsage::StorageWrite)
+                .async_compute(),
+        );
+        graph.add_pass(
+            GraphPass::new("draw")
+                .buffer(draws, BufferUsage::Indirect)
+                .image(shadow, ImageUsage::Sampled)
+                .image(target, ImageUsage::ColorAttachment),
+        );
+        let keep = graph.cull_passes();
+        assert_eq!(keep, vec![true; 3]);
+        // async compute passes move to the front, the draw still waits for both writers
+        let order = graph.schedule_passes(&keep, true);
+        assert_eq!(pass_names(&graph, &order), ["cull", "shadow", "draw"]);
+        let order = graph.schedule_passes(&keep, false);
+        assert_eq!(pass_names(&graph, &order), ["shadow", "cull", "draw"]);
+    }
+
+    #[test]
+    fn unused_pass_is_culled() {
+        let mut graph = RenderGraph::new();
+        let target = image(&mut graph, "target");
+        let debug = image(&mut graph, "debug view");
+        graph.export_image(target, vk::ImageLayout::PRESENT_SRC_KHR);
+        graph.add_pass(GraphPass::new("debug").image(debug, ImageUsage::StorageWrite));
+        graph.add_pass(GraphPass::new("draw").image(target, ImageUsage::ColorAttachment));
+        assert_eq!(graph.cull_passes(), [false, true]);
+        let plan = graph.plan(false);
+        assert_eq!(plan.passes, [(1, 0)]);
+    }
+
+    #[test]
+    fn side_effect_pass_is_kept() {
+        let mut graph = RenderGraph::new();
+        let target = image(&mut graph, "target");
+        let readback = graph.import_buffer("readback", vk::Buffer::null());
+        graph.add_pass(GraphPass::new("draw").image(target, ImageUsage::ColorAttachment));
+        // nothing is exported, the copy into the readback buffer is the only output
+        graph.add_pass(
+            GraphPass::new("capture")
+                .image(target, ImageUsage::TransferSrc)
+                .buffer(readback, BufferUsage::TransferDst)
+                .side_effects(),
+        );
+        assert_eq!(graph.cull_passes(), [true, true]);
+    }
+
+    #[test]
+    fn cross_queue_write_read_waits() {
+        let mut graph = RenderGraph::new();
+        let target = image(&mut graph, "target");
+        let histogram = graph.import_buffer("histogram", vk::Buffer::null());
+        graph.export_image(target, vk::ImageLayout::PRESENT_SRC_KHR);
+        graph.add_pass(
+            GraphPass::new("histogram")
+                .buffer(histogram, BufferUsage::StorageWrite)
+                .async_compute(),
+        );
+        graph.add_pass(
+            GraphPass::new("tonemap")
+                .buffer(histogram, BufferUsage::StorageRead)
+                .image(target, ImageUsage::StorageWrite),
+        );
+        let plan = graph.plan(true);
+        // the empty first graphics submission, the compute one and the graphics one waiting
+        // for it, which also ends the frame
+        assert_eq!(plan.passes, [(0, 1), (1, 2)]);
+        assert_eq!(
+            plan.submissions,
+            [
+                (PassQueue::Graphics, None),
+                (PassQueue::Compute, None),
+                (PassQueue::Graphics, Some(1)),
+            ]
+        );
+        assert_eq!(plan.final_submission, 2);
+
+        // without an async compute queue everything is one submission
+        let plan = graph.plan(false);
+        assert_eq!(plan.passes, [(0, 0), (1, 0)]);
+        assert_eq!(plan.submissions, [(PassQueue::Graphics, None)]);
+        assert_eq!(plan.final_submission, 0);
+    }
+}
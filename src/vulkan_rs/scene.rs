@@ -0,0 +1,411 @@
+use super::allocation::Allocator;
+use super::animation::Animation;
+use super::animation::AnimationPlayer;
+use super::animation::ChannelTarget;
+use super::animation::Skin;
+use super::async_upload::AsyncUploader;
+use super::device::Device;
+use super::error::AssetError;
+use super::error::VulkanError;
+use super::material::Material;
+use super::material::MaterialCache;
+use super::mesh::GltfMeshes;
+use super::mesh::MeshAsset;
+use super::skinning::DeformedMesh;
+use nalgebra_glm as glm;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+pub struct Node {
+    name: String,
+    // relative to the parent node
+    local_transform: glm::Mat4,
+    // decomposed local transform, animations replace single parts of it
+    translation: glm::Vec3,
+    rotation: glm::Quat,
+    scale: glm::Vec3,
+    // cached => has to be updated with Scene::update_transforms after changing local transforms
+    world_transform: glm::Mat4,
+    // index into Scene::meshes
+    mesh: Option<usize>,
+    // index into Scene::deformed_meshes
+    deformed_mesh: Option<usize>,
+    // one weight per morph target of the mesh, animated by weight channels
+    morph_weights: Vec<f32>,
+    children: Vec<usize>,
+}
+
+impl Node {
+    #[allow(dead_code)]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    #[allow(dead_code)]
+    pub fn local_transform(&self) -> &glm::Mat4 {
+        &self.local_transform
+    }
+
+    pub fn world_transform(&self) -> &glm::Mat4 {
+        &self.world_transform
+    }
+
+    pub fn morph_weights(&self) -> &[f32] {
+        &self.morph_weights
+    }
+
+    #[allow(dead_code)]
+    pub fn mesh(&self) -> Option<usize> {
+        self.mesh
+    }
+
+    #[allow(dead_code)]
+    pub fn children(&self) -> &[usize] {
+        &self.children
+    }
+}
+
+// what gltf::import returns, read and decoded by the loading threads of the asset manager
+pub type GltfImport = (
+    gltf::Document,
+    Vec<gltf::buffer::Data>,
+    Vec<gltf::image::Data>,
+);
+
+// the uploaded meshes of a gltf file + what is needed to build scenes from it
+// cached by the asset manager, every Scene built from it gets its own nodes and animation state
+pub struct Model {
+    path: PathBuf,
+    document: gltf::Document,
+    buffers: Vec<gltf::buffer::Data>,
+    meshes: Vec<Arc<MeshAsset>>,
+    // every material of the file in gltf order
+    materials: Vec<Arc<Material>>,
+}
+
+// a gltf file with its buffers and textures uploaded, only the materials are missing
+pub struct UploadedModel {
+    path: PathBuf,
+    document: gltf::Document,
+    buffers: Vec<gltf::buffer::Data>,
+    meshes: GltfMeshes,
+}
+
+impl UploadedModel {
+    // doesnt need the material cache => runs on the loading threads of the asset manager
+    pub fn new(
+        device: Arc<Device>,
+        allocator: Arc<Mutex<Allocator>>,
+        uploader: &AsyncUploader,
+        file_path: &Path,
+        (document, buffers, images): GltfImport,
+        overwrite_color_with_normals: bool,
+    ) -> Result<Self, AssetError> {
+        let meshes = MeshAsset::upload_gltf_meshes(
+            device,
+            allocator,
+            uploader,
+            &document,
+            &buffers,
+            &images,
+            file_path,
+            overwrite_color_with_normals,
+        )?;
+        Ok(UploadedModel {
+            path: file_path.to_path_buf(),
+            document,
+            buffers,
+            meshes,
+        })
+    }
+}
+
+impl Model {
+    // the materials need the cache => has to run on the render thread
+    pub fn new(
+        uploaded: UploadedModel,
+        material_cache: &mut MaterialCache,
+    ) -> Result<Self, VulkanError> {
+        let (meshes, materials) =
+            uploaded
+                .meshes
+                .create_materials(&uploaded.document, &uploaded.path, material_cache)?;
+        Ok(Model {
+            path: uploaded.path,
+            document: uploaded.document,
+            buffers: uploaded.buffers,
+            meshes: meshes.into_iter().map(Arc::new).collect(),
+            materials,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn meshes(&self) -> &[Arc<MeshAsset>] {
+        &self.meshes
+    }
+
+    pub fn materials(&self) -> &[Arc<Material>] {
+        &self.materials
+    }
+}
+
+// node hierarchy of a gltf file. Nodes reference their children by index => no Rc/RefCell needed
+#[derive(Default)]
+pub struct Scene {
+    // shared => meshes submitted by the game outlive a scene switch
+    meshes: Vec<Arc<MeshAsset>>,
+    nodes: Vec<Node>,
+    root_nodes: Vec<usize>,
+    skins: Vec<Skin>,
+    deformed_meshes: Vec<DeformedMesh>,
+    animations: Vec<Animation>,
+    animation_player: Option<AnimationPlayer>,
+}
+
+impl Scene {
+    // the meshes are shared with the model, skinned/morphed ones get their own copies
+    pub fn from_model(
+        device: Arc<Device>,
+        allocator: Arc<Mutex<Allocator>>,
+        model: &Model,
+    ) -> Result<Self, VulkanError> {
+        let gltf = &model.document;
+        let buffers = &model.buffers;
+        let file_path = model.path();
+        let meshes = model.meshes.clone();
+
+        let skins: Vec<Skin> = gltf
+            .skins()
+            .map(|skin| Skin::load_gltf(skin, buffers))
+            .collect();
+        let animations: Vec<Animation> = gltf
+            .animations()
+            .map(|animation| Animation::load_gltf(animation, buffers))
+            .collect();
+
+        // nodes with a skinned or morphed mesh get their own deformed copy of the mesh
+        let mut deformed_meshes = Vec::new();
+        for node in gltf.nodes() {
+            let Some(mesh) = node.mesh() else {
+                continue;
+            };
+            let mesh = &meshes[mesh.index()];
+            let skin = node.skin().filter(|_| {
+                if mesh.skin_weights().is_none() {
+                    log::warn!("Mesh {} has a skin but no joints", mesh.name());
+                }
+                mesh.skin_weights().is_some()
+            });
+            if skin.is_none() && mesh.morph_target_count() == 0 {
+                continue;
+            }
+            deformed_meshes.push(DeformedMesh::new(
+                device.clone(),
+                allocator.clone(),
+                node.index(),
+                skin.map(|skin| skin.index()),
+                mesh.clone(),
+            )?);
+        }
+
+        // gltf node indices are kept => children can be copied as is
+        let nodes = gltf
+            .nodes()
+            .map(|node| {
+                let (translation, rotation, scale) = node.transform().decomposed();
+                // node weights override the default weights of the mesh
+                let mut morph_weights = Vec::new();
+                if let Some(mesh) = node.mesh() {
+                    let mesh = &meshes[mesh.index()];
+                    morph_weights = node
+                        .weights()
+                        .unwrap_or(mesh.default_morph_weights())
+                        .to_vec();
+                    morph_weights.resize(mesh.morph_target_count(), 0.0);
+                }
+                Node {
+                    name: node.name().unwrap_or("Unnamed Node").to_string(),
+                    local_transform: glm::Mat4::from(node.transform().matrix()),
+                    translation: glm::Vec3::from(translation),
+                    rotation: glm::Quat::from(glm::Vec4::from(rotation)),
+                    scale: glm::Vec3::from(scale),
+                    world_transform: glm::Mat4::identity(),
+                    mesh: node.mesh().map(|mesh| mesh.index()),
+                    deformed_mesh: deformed_meshes
+                        .iter()
+                        .position(|deformed_mesh| deformed_mesh.node() == node.index()),
+                    morph_weights,
+                    children: node.children().map(|child| child.index()).collect(),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let root_nodes = match gltf.default_scene().or_else(|| gltf.scenes().next()) {
+            Some(scene) => scene.nodes().map(|node| node.index()).collect(),
+            None => {
+                // files without scenes are still valid gltf => treat nodes without a parent as roots
+                log::warn!(
+                    "{:?} does not contain a scene, using all root nodes",
+                    file_path
+                );
+                let mut has_parent = vec![false; nodes.len()];
+                for node in &nodes {
+                    for child in &node.children {
+                        has_parent[*child] = true;
+                    }
+                }
+                (0..nodes.len()).filter(|idx| !has_parent[*idx]).collect()
+            }
+        };
+
+        if !animations.is_empty() || !deformed_meshes.is_empty() {
+            log::info!(
+                "{} animations, {} skins and {} deformed meshes",
+                animations.len(),
+                skins.len(),
+                deformed_meshes.len()
+            );
+        }
+        // the first animation plays right away => imported characters dont stand in bind pose
+        let animation_player = (!animations.is_empty()).then(|| AnimationPlayer::new(0, true));
+        let mut scene = Scene {
+            meshes,
+            nodes,
+            root_nodes,
+            skins,
+            deformed_meshes,
+            animations,
+            animation_player,
+        };
+        scene.update_transforms();
+        Ok(scene)
+    }
+
+    pub fn meshes(&self) -> &[Arc<MeshAsset>] {
+        &self.meshes
+    }
+
+    pub fn nodes(&self) -> &[Node] {
+        &self.nodes
+    }
+
+    pub fn skins(&self) -> &[Skin] {
+        &self.skins
+    }
+
+    pub fn deformed_meshes(&self) -> &[DeformedMesh] {
+        &self.deformed_meshes
+    }
+
+    pub fn animations(&self) -> &[Animation] {
+        &self.animations
+    }
+
+    pub fn set_animation_speed(&mut self, speed: f32) {
+        if let Some(player) = self.animation_player.as_mut() {
+            player.set_speed(speed);
+        }
+    }
+
+    // None => the nodes keep their current pose
+    pub fn play_animation(&mut self, animation: Option<usize>, looping: bool) {
+        self.animation_player = animation
+            .filter(|animation| *animation < self.animations.len())
+            .map(|animation| AnimationPlayer::new(animation, looping));
+    }
+
+    // samples the playing animation into the node transforms and morph weights
+    // and updates the world transforms. Animated nodes ignore set_local_transform
+    pub fn advance_animation(&mut self, delta_time: f32) {
+        let Some(player) = self.animation_player.as_mut() else {
+            return;
+        };
+        let animation = &self.animations[player.animation()];
+        player.advance(delta_time, animation.duration());
+        let mut value = Vec::new();
+        for channel in animation.channels() {
+            channel.sample(player.time(), &mut value);
+            let node = &mut self.nodes[channel.node()];
+            match channel.target() {
+                ChannelTarget::Translation => node.translation = glm::make_vec3(&value),
+                ChannelTarget::Rotation => node.rotation = glm::make_quat(&value),
+                ChannelTarget::Scale => node.scale = glm::make_vec3(&value),
+                ChannelTarget::Weights => {
+                    // extra weights of broken files are dropped => the weight buffer layout stays fixed
+                    let count = value.len().min(node.morph_weights.len());
+                    node.morph_weights[..count].copy_from_slice(&value[..count]);
+                    continue;
+                }
+            }
+            node.local_transform = glm::translation(&node.translation)
+                * glm::quat_to_mat4(&node.rotation)
+                * glm::scaling(&node.scale);
+        }
+        self.update_transforms();
+    }
+
+    #[allow(dead_code)]
+    pub fn root_nodes(&self) -> &[usize] {
+        &self.root_nodes
+    }
+
+    #[allow(dead_code)]
+    pub fn set_local_transform(&mut self, node: usize, transform: glm::Mat4) {
+        self.nodes[node].local_transform = transform;
+    }
+
+    pub fn update_transforms(&mut self) {
+        let mut stack = self
+            .root_nodes
+            .iter()
+            .map(|root| (*root, glm::Mat4::identity()))
+            .collect::<Vec<_>>();
+        while let Some((idx, parent_transform)) = stack.pop() {
+            let node = &mut self.nodes[idx];
+            node.world_transform = parent_transform * node.local_transform;
+            let world_transform = node.world_transform;
+            stack.extend(node.children.iter().map(|child| (*child, world_transform)));
+        }
+    }
+
+    // every node with a mesh in the active scene together with its world transform
+    // deformed nodes return their deformed vertices, skinned ones are already in world space
+    pub fn mesh_instances(&self) -> impl Iterator<Item = (&MeshAsset, &glm::Mat4)> {
+        let mut stack = self.root_nodes.clone();
+        let mut instances = Vec::new();
+        while let Some(idx) = stack.pop() {
+            let node = &self.nodes[idx];
+            if let Some(deformed_mesh) = node.deformed_mesh {
+                let deformed_mesh = &self.deformed_meshes[deformed_mesh];
+                instances.push((
+                    deformed_mesh.mesh(),
+                    deformed_mesh.world_transform(node.world_transform()),
+                ));
+            } else if let Some(mesh) = node.mesh {
+                instances.push((self.meshes[mesh].as_ref(), node.world_transform()));
+            }
+            stack.extend_from_slice(&node.children);
+        }
+        instances.into_iter()
+    }
+
+    // like mesh_instances, but without the deformed meshes => their vertices never change,
+    // e.g. for acceleration structures that are only built once per mesh
+    pub fn static_mesh_instances(&self) -> impl Iterator<Item = (&MeshAsset, &glm::Mat4)> {
+        let mut stack = self.root_nodes.clone();
+        let mut instances = Vec::new();
+        while let Some(idx) = stack.pop() {
+            let node = &self.nodes[idx];
+            if let (None, Some(mesh)) = (node.deformed_mesh, node.mesh) {
+                instances.push((self.meshes[mesh].as_ref(), node.world_transform()));
+            }
+            stack.extend_from_slice(&node.children);
+        }
+        instances.into_iter()
+    }
+}
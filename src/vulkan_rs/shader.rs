@@ -1,23 +1,87 @@
 use super::device::Device;
+use super::error::VulkanError;
+use crate::paths;
 use ash::vk;
-use std::io::Read;
+use std::collections::HashMap;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
 use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+use std::time::SystemTime;
+
+// checking the modification times every frame would be a lot of syscalls for nothing
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+// (file name, spir-v) compiled in by build.rs with the embed-shaders feature, empty otherwise
+static EMBEDDED_SHADERS: &[(&str, &[u8])] =
+    include!(concat!(env!("OUT_DIR"), "/embedded_shaders.rs"));
+// files in this directory are used instead of the embedded ones, e.g. for hot reloading
+static SHADER_OVERRIDE_DIR: Mutex<Option<PathBuf>> = Mutex::new(None);
 
 pub struct ShaderModule {
     device: Arc<Device>,
     module: vk::ShaderModule,
+    reflection: ShaderReflection,
+    // file name without directory, used to name the module and its pipelines
+    name: String,
+}
+
+// a resource the shader declares with layout(set = .., binding = ..)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReflectedBinding {
+    pub set: u32,
+    pub binding: u32,
+    pub descriptor_type: vk::DescriptorType,
+    // 0 for runtime sized arrays (descriptor indexing)
+    pub descriptor_count: u32,
+}
+
+#[derive(Debug, Clone, Default)]
+struct ShaderReflection {
+    bindings: Vec<ReflectedBinding>,
+    // size of the push constant block in bytes, 0 if there is none
+    push_constant_size: u32,
+}
+
+pub fn set_shader_override_dir(dir: Option<PathBuf>) {
+    *SHADER_OVERRIDE_DIR.lock().unwrap() = dir;
 }
 
-fn read_shader_file(path: &str) -> Vec<u8> {
-    std::fs::File::open(path)
-        .expect("I hope that the file exists")
-        .bytes()
-        .map(|byte| byte.expect("Bytecode should be valid cuz it was created by a fancy compiler"))
-        .collect()
+// shaders are looked up by file name in the override dir, then in the embedded shaders
+// and last at the given path relative to the asset root
+fn read_shader_file(path: &str) -> Result<Vec<u8>, VulkanError> {
+    let file_name = Path::new(path)
+        .file_name()
+        .and_then(|file_name| file_name.to_str())
+        .unwrap_or(path);
+    let override_path = SHADER_OVERRIDE_DIR
+        .lock()
+        .unwrap()
+        .as_ref()
+        .map(|dir| dir.join(file_name))
+        .filter(|override_path| override_path.is_file());
+    if let Some(override_path) = override_path {
+        log::debug!("Loading shader {:?}", override_path);
+        return std::fs::read(&override_path).map_err(|error| VulkanError::ShaderFile {
+            path: override_path.to_string_lossy().to_string(),
+            error,
+        });
+    }
+    if let Some((_, code)) = EMBEDDED_SHADERS.iter().find(|(name, _)| *name == file_name) {
+        return Ok(code.to_vec());
+    }
+    let path = paths::asset_path(path);
+    std::fs::read(&path).map_err(|error| VulkanError::ShaderFile {
+        path: path.to_string_lossy().to_string(),
+        error,
+    })
 }
 impl ShaderModule {
-    pub fn new(device: Arc<Device>, path: &str) -> Self {
-        let shader_file_bytes = read_shader_file(path);
+    pub fn new(device: Arc<Device>, path: &str) -> Result<Self, VulkanError> {
+        let shader_file_bytes = read_shader_file(path)?;
         let create_info = vk::ShaderModuleCreateInfo {
             s_type: vk::StructureType::SHADER_MODULE_CREATE_INFO,
             p_next: std::ptr::null(),
@@ -26,8 +90,36 @@ impl ShaderModule {
             ..Default::default()
         };
 
-        let module = device.create_shader_module(&create_info);
-        Self { device, module }
+        let reflection =
+            reflect(&shader_file_bytes).map_err(|message| VulkanError::ShaderReflection {
+                path: path.to_string(),
+                message,
+            })?;
+        let module = device.create_shader_module(&create_info)?;
+        let name = Path::new(path).file_name().map_or_else(
+            || path.to_string(),
+            |name| name.to_string_lossy().to_string(),
+        );
+        device.set_object_name(module, &name);
+        Ok(Self {
+            device,
+            module,
+            reflection,
+            name,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    // sorted by set and binding
+    pub fn bindings(&self) -> &[ReflectedBinding] {
+        &self.reflection.bindings
+    }
+
+    pub fn push_constant_size(&self) -> u32 {
+        self.reflection.push_constant_size
     }
 
     pub fn create_shader_stage_info(
@@ -51,3 +143,375 @@ impl Drop for ShaderModule {
         self.device.destroy_shader_module(self.module);
     }
 }
+
+// compiles glsl at runtime with glslc, the same compiler build.rs uses
+// the spir-v is written next to the source like the build script does => ShaderModule::new
+// picks up the new version, the owner of the pipeline has to rebuild it
+pub struct ShaderCompiler {
+    source_dir: PathBuf,
+    // last seen modification time per source file
+    modified: HashMap<PathBuf, SystemTime>,
+    last_poll: Instant,
+}
+
+impl ShaderCompiler {
+    pub fn new(source_dir: impl Into<PathBuf>) -> Self {
+        let mut compiler = ShaderCompiler {
+            source_dir: source_dir.into(),
+            modified: HashMap::new(),
+            last_poll: Instant::now(),
+        };
+        // build.rs already compiled the current versions
+        compiler.modified = compiler.source_files();
+        compiler
+    }
+
+    fn source_files(&self) -> HashMap<PathBuf, SystemTime> {
+        let Ok(entries) = std::fs::read_dir(&self.source_dir) else {
+            return HashMap::new();
+        };
+        entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| spirv_file_name(path).is_some())
+            .filter_map(|path| {
+                let modified = std::fs::metadata(&path).and_then(|m| m.modified()).ok()?;
+                Some((path, modified))
+            })
+            .collect()
+    }
+
+    // returns the path of the written spir-v file
+    pub fn compile(&self, source: &Path) -> Result<PathBuf, VulkanError> {
+        let compilation_error = |message: String| VulkanError::ShaderCompilation {
+            path: source.display().to_string(),
+            message,
+        };
+        let file_name = spirv_file_name(source).ok_or_else(|| {
+            compilation_error("expected a .vert, .frag, .comp or ray tracing file".to_string())
+        })?;
+        let output_path = source.with_file_name(file_name);
+        let mut command = Command::new("glslc");
+        command.arg(source).arg("-o").arg(&output_path);
+        // same target as build.rs
+        if needs_spirv_1_4(source) {
+            command.arg("--target-env=vulkan1.3");
+        }
+        let output = command
+            .output()
+            .map_err(|e| compilation_error(format!("could not run glslc: {}", e)))?;
+        if !output.status.success() {
+            return Err(compilation_error(
+                String::from_utf8_lossy(&output.stderr).trim().to_string(),
+            ));
+        }
+        Ok(output_path)
+    }
+
+    // recompiles every source that changed since the last call
+    // returns the file names of the new spir-v files, e.g. "dither_comp.spv"
+    pub fn poll_changes(&mut self) -> Vec<String> {
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return Vec::new();
+        }
+        self.last_poll = Instant::now();
+        let mut recompiled = Vec::new();
+        for (path, modified) in self.source_files() {
+            if self.modified.get(&path) == Some(&modified) {
+                continue;
+            }
+            self.modified.insert(path.clone(), modified);
+            // a broken shader keeps the old pipeline => fix it and save again
+            match self.compile(&path) {
+                Ok(output_path) => {
+                    log::info!("Recompiled {:?}", path);
+                    if let Some(file_name) = output_path.file_name() {
+                        recompiled.push(file_name.to_string_lossy().to_string());
+                    }
+                }
+                Err(e) => log::error!("{}", e),
+            }
+        }
+        recompiled
+    }
+}
+
+fn is_ray_tracing_stage(source: &Path) -> bool {
+    matches!(
+        source.extension().and_then(|extension| extension.to_str()),
+        Some("rgen" | "rmiss" | "rchit")
+    )
+}
+
+// ray tracing stages and ray queries in any stage
+fn needs_spirv_1_4(source: &Path) -> bool {
+    is_ray_tracing_stage(source)
+        || std::fs::read_to_string(source).is_ok_and(|text| text.contains("GL_EXT_ray_query"))
+}
+
+// same naming as build.rs: dither.comp => dither_comp.spv
+fn spirv_file_name(source: &Path) -> Option<String> {
+    let extension = source.extension()?.to_str()?;
+    if !matches!(extension, "vert" | "frag" | "comp") && !is_ray_tracing_stage(source) {
+        return None;
+    }
+    let stem = source.file_stem()?.to_str()?;
+    Some(format!("{}_{}.spv", stem, extension))
+}
+
+#[derive(Default)]
+struct TypeInfo {
+    op: Option<spirv::Op>,
+    operands: Vec<u32>,
+}
+
+// only what is needed for descriptor set layouts and push constant ranges
+fn reflect(bytes: &[u8]) -> Result<ShaderReflection, String> {
+    if !bytes.len().is_multiple_of(4) || bytes.len() < 20 {
+        return Err("not a spir-v file".to_string());
+    }
+    let words: Vec<u32> = bytes
+        .chunks_exact(4)
+        .map(|chunk| u32::from_ne_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+        .collect();
+    if words[0] != spirv::MAGIC_NUMBER {
+        return Err("wrong magic number".to_string());
+    }
+
+    // result id => defining instruction
+    let mut types: HashMap<u32, TypeInfo> = HashMap::new();
+    let mut decorations: HashMap<(u32, spirv::Decoration), u32> = HashMap::new();
+    let mut block_decorations: HashMap<u32, spirv::Decoration> = HashMap::new();
+    // (struct, member) => offset/matrix stride
+    let mut member_offsets: HashMap<(u32, u32), u32> = HashMap::new();
+    let mut member_matrix_strides: HashMap<(u32, u32), u32> = HashMap::new();
+    // (pointer type, variable, storage class)
+    let mut variables = Vec::new();
+
+    let mut idx = 5;
+    while idx < words.len() {
+        let word_count = (words[idx] >> 16) as usize;
+        if word_count == 0 || idx + word_count > words.len() {
+            return Err(format!("invalid instruction at word {}", idx));
+        }
+        let op = spirv::Op::from_u32(words[idx] & 0xffff);
+        let operands = &words[idx + 1..idx + word_count];
+        idx += word_count;
+        let Some(op) = op else {
+            continue;
+        };
+        match op {
+            spirv::Op::Decorate if operands.len() >= 2 => {
+                let Some(decoration) = spirv::Decoration::from_u32(operands[1]) else {
+                    continue;
+                };
+                match decoration {
+                    spirv::Decoration::Block | spirv::Decoration::BufferBlock => {
+                        block_decorations.insert(operands[0], decoration);
+                    }
+                    _ => {
+                        if let Some(value) = operands.get(2) {
+                            decorations.insert((operands[0], decoration), *value);
+                        }
+                    }
+                }
+            }
+            spirv::Op::MemberDecorate if operands.len() >= 4 => {
+                let key = (operands[0], operands[1]);
+                match spirv::Decoration::from_u32(operands[2]) {
+                    Some(spirv::Decoration::Offset) => {
+                        member_offsets.insert(key, operands[3]);
+                    }
+                    Some(spirv::Decoration::MatrixStride) => {
+                        member_matrix_strides.insert(key, operands[3]);
+                    }
+                    _ => (),
+                }
+            }
+            spirv::Op::Variable if operands.len() >= 3 => {
+                variables.push((operands[0], operands[1], operands[2]));
+            }
+            // result type comes first => the id is the second operand
+            spirv::Op::Constant if operands.len() >= 3 => {
+                types.insert(
+                    operands[1],
+                    TypeInfo {
+                        op: Some(op),
+                        operands: operands[2..].to_vec(),
+                    },
+                );
+            }
+            spirv::Op::TypeInt
+            | spirv::Op::TypeFloat
+            | spirv::Op::TypeVector
+            | spirv::Op::TypeMatrix
+            | spirv::Op::TypeImage
+            | spirv::Op::TypeSampler
+            | spirv::Op::TypeSampledImage
+            | spirv::Op::TypeArray
+            | spirv::Op::TypeRuntimeArray
+            | spirv::Op::TypeStruct
+            | spirv::Op::TypePointer
+            | spirv::Op::TypeAccelerationStructureKHR
+                if !operands.is_empty() =>
+            {
+                types.insert(
+                    operands[0],
+                    TypeInfo {
+                        op: Some(op),
+                        operands: operands[1..].to_vec(),
+                    },
+                );
+            }
+            _ => (),
+        }
+    }
+
+    let get_type = |id: u32| {
+        types
+            .get(&id)
+            .ok_or_else(|| format!("unknown type id {}", id))
+    };
+    // size in bytes as laid out in a push constant block
+    fn type_size(
+        id: u32,
+        types: &HashMap<u32, TypeInfo>,
+        decorations: &HashMap<(u32, spirv::Decoration), u32>,
+        member_offsets: &HashMap<(u32, u32), u32>,
+        matrix_stride: Option<u32>,
+    ) -> u32 {
+        let Some(info) = types.get(&id) else {
+            return 0;
+        };
+        let size = |id| type_size(id, types, decorations, member_offsets, None);
+        match info.op {
+            Some(spirv::Op::TypeInt) | Some(spirv::Op::TypeFloat) => info.operands[0] / 8,
+            Some(spirv::Op::TypeVector) => size(info.operands[0]) * info.operands[1],
+            Some(spirv::Op::TypeMatrix) => {
+                let column_size = matrix_stride.unwrap_or_else(|| size(info.operands[0]));
+                column_size * info.operands[1]
+            }
+            Some(spirv::Op::TypeArray) => {
+                let length = types
+                    .get(&info.operands[1])
+                    .and_then(|constant| constant.operands.first().copied())
+                    .unwrap_or(0);
+                let stride = decorations
+                    .get(&(id, spirv::Decoration::ArrayStride))
+                    .copied()
+                    .unwrap_or_else(|| size(info.operands[0]));
+                stride * length
+            }
+            Some(spirv::Op::TypeStruct) => (0..info.operands.len() as u32)
+                .map(|member| {
+                    let offset = member_offsets.get(&(id, member)).copied().unwrap_or(0);
+                    offset + size(info.operands[member as usize])
+                })
+                .max()
+                .unwrap_or(0),
+            // buffer references are 64 bit addresses
+            Some(spirv::Op::TypePointer) => 8,
+            _ => 0,
+        }
+    }
+
+    let mut reflection = ShaderReflection::default();
+    for (pointer_type, variable, storage_class) in variables {
+        let pointer = get_type(pointer_type)?;
+        let Some(&pointee) = pointer.operands.get(1) else {
+            continue;
+        };
+        let storage_class = spirv::StorageClass::from_u32(storage_class);
+        if storage_class == Some(spirv::StorageClass::PushConstant) {
+            let Some(info) = types.get(&pointee) else {
+                continue;
+            };
+            let size = (0..info.operands.len() as u32)
+                .map(|member| {
+                    let offset = member_offsets.get(&(pointee, member)).copied().unwrap_or(0);
+                    let stride = member_matrix_strides.get(&(pointee, member)).copied();
+                    offset
+                        + type_size(
+                            info.operands[member as usize],
+                            &types,
+                            &decorations,
+                            &member_offsets,
+                            stride,
+                        )
+                })
+                .max()
+                .unwrap_or(0);
+            reflection.push_constant_size = reflection.push_constant_size.max(size);
+            continue;
+        }
+        let (Some(&set), Some(&binding)) = (
+            decorations.get(&(variable, spirv::Decoration::DescriptorSet)),
+            decorations.get(&(variable, spirv::Decoration::Binding)),
+        ) else {
+            continue;
+        };
+        // arrays of descriptors => the element type decides the descriptor type
+        let mut descriptor_count = 1;
+        let mut element = pointee;
+        loop {
+            let info = get_type(element)?;
+            match info.op {
+                Some(spirv::Op::TypeArray) => {
+                    descriptor_count *= get_type(info.operands[1])?
+                        .operands
+                        .first()
+                        .copied()
+                        .unwrap_or(1);
+                    element = info.operands[0];
+                }
+                Some(spirv::Op::TypeRuntimeArray) => {
+                    descriptor_count = 0;
+                    element = info.operands[0];
+                }
+                _ => break,
+            }
+        }
+        let info = get_type(element)?;
+        let descriptor_type = match (storage_class, info.op) {
+            (Some(spirv::StorageClass::StorageBuffer), _) => vk::DescriptorType::STORAGE_BUFFER,
+            (Some(spirv::StorageClass::Uniform), _) => match block_decorations.get(&element) {
+                Some(spirv::Decoration::BufferBlock) => vk::DescriptorType::STORAGE_BUFFER,
+                _ => vk::DescriptorType::UNIFORM_BUFFER,
+            },
+            (_, Some(spirv::Op::TypeSampledImage)) => vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            (_, Some(spirv::Op::TypeSampler)) => vk::DescriptorType::SAMPLER,
+            (_, Some(spirv::Op::TypeAccelerationStructureKHR)) => {
+                vk::DescriptorType::ACCELERATION_STRUCTURE_KHR
+            }
+            (_, Some(spirv::Op::TypeImage)) => {
+                // operands: sampled type, dim, depth, arrayed, ms, sampled (1 = sampled, 2 = storage)
+                let storage = info.operands.get(5) == Some(&2);
+                match spirv::Dim::from_u32(info.operands[1]) {
+                    Some(spirv::Dim::DimBuffer) if storage => {
+                        vk::DescriptorType::STORAGE_TEXEL_BUFFER
+                    }
+                    Some(spirv::Dim::DimBuffer) => vk::DescriptorType::UNIFORM_TEXEL_BUFFER,
+                    Some(spirv::Dim::DimSubpassData) => vk::DescriptorType::INPUT_ATTACHMENT,
+                    _ if storage => vk::DescriptorType::STORAGE_IMAGE,
+                    _ => vk::DescriptorType::SAMPLED_IMAGE,
+                }
+            }
+            _ => {
+                return Err(format!(
+                    "unsupported resource at set {} binding {}",
+                    set, binding
+                ))
+            }
+        };
+        reflection.bindings.push(ReflectedBinding {
+            set,
+            binding,
+            descriptor_type,
+            descriptor_count,
+        });
+    }
+    reflection
+        .bindings
+        .sort_by_key(|binding| (binding.set, binding.binding));
+    Ok(reflection)
+}
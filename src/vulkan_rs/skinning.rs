@@ -0,0 +1,264 @@
+use super::allocation::AllocatedBuffer;
+use super::allocation::Allocator;
+use super::device::Device;
+use super::error::VulkanError;
+use super::mesh::Aabb;
+use super::mesh::BoundingSphere;
+use super::mesh::GeometricSurface;
+use super::mesh::MeshAsset;
+use super::pipelines::ComputePipeline;
+use super::render_graph::BufferHandle;
+use super::render_graph::BufferUsage;
+use super::render_graph::GraphPass;
+use super::render_graph::RenderGraph;
+use super::scene::Scene;
+use super::shader::ShaderModule;
+use ash::vk;
+use nalgebra_glm as glm;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+// has to match local_size_x in skinning.comp
+const GROUP_SIZE: u32 = 64;
+
+// same layout as the push constants in skinning.comp
+#[repr(C)]
+#[derive(bytemuck::NoUninit, Copy, Clone, Debug)]
+struct GPUSkinningPushConstants {
+    source_vertices: vk::DeviceAddress,
+    skin_weights: vk::DeviceAddress,
+    joint_matrices: vk::DeviceAddress,
+    morph_targets: vk::DeviceAddress,
+    morph_weights: vk::DeviceAddress,
+    output_vertices: vk::DeviceAddress,
+    vertex_count: u32,
+    morph_target_count: u32,
+    // bool
+    skinned: u32,
+    _padding: u32,
+}
+
+// a node whose mesh is skinned and/or has morph targets. The vertices are deformed every frame
+// => the instance has its own vertex buffer
+pub struct DeformedMesh {
+    node: usize,
+    // index into the skins of the scene, None => only morph targets
+    skin: Option<usize>,
+    source: Arc<MeshAsset>,
+    mesh: MeshAsset,
+    identity: glm::Mat4,
+}
+
+impl DeformedMesh {
+    pub fn new(
+        device: Arc<Device>,
+        allocator: Arc<Mutex<Allocator>>,
+        node: usize,
+        skin: Option<usize>,
+        source: Arc<MeshAsset>,
+    ) -> Result<Self, VulkanError> {
+        let buffers = source.buffers().with_new_vertices(device, allocator)?;
+        // the pose changes every frame => the instance is never culled
+        let surfaces = source
+            .surfaces()
+            .iter()
+            .map(|surface| {
+                GeometricSurface::new(
+                    surface.start_idx(),
+                    surface.count(),
+                    surface.material().clone(),
+                    Aabb::infinite(),
+                )
+            })
+            .collect();
+        let mesh = MeshAsset::new(source.name(), surfaces, buffers, BoundingSphere::infinite());
+        Ok(DeformedMesh {
+            node,
+            skin,
+            source,
+            mesh,
+            identity: glm::Mat4::identity(),
+        })
+    }
+
+    pub fn node(&self) -> usize {
+        self.node
+    }
+
+    pub fn skin(&self) -> Option<usize> {
+        self.skin
+    }
+
+    // the deformed vertices, only valid after the skinning pass of the frame
+    pub fn mesh(&self) -> &MeshAsset {
+        &self.mesh
+    }
+
+    // skinned vertices are already in world space, the joint matrices contain the node transform
+    pub fn world_transform<'a>(&'a self, node_world_transform: &'a glm::Mat4) -> &'a glm::Mat4 {
+        match self.skin {
+            Some(_) => &self.identity,
+            None => node_world_transform,
+        }
+    }
+}
+
+// (buffer, capacity in elements), created on first use
+type SlotBuffer = Option<(AllocatedBuffer, usize)>;
+
+// blends the morph targets and skins the deformed meshes of a scene in a compute pass per instance
+// joint matrices and morph weights are written by the host => one buffer per frame in flight
+pub struct Skinning {
+    device: Arc<Device>,
+    allocator: Arc<Mutex<Allocator>>,
+    pipeline: ComputePipeline,
+    joint_buffers: Vec<SlotBuffer>,
+    weight_buffers: Vec<SlotBuffer>,
+    // (first joint matrix, first morph weight) of every deformed mesh in the current slot
+    addresses: Vec<(vk::DeviceAddress, vk::DeviceAddress)>,
+}
+
+impl Skinning {
+    pub fn new(
+        device: Arc<Device>,
+        allocator: Arc<Mutex<Allocator>>,
+        frame_count: usize,
+    ) -> Result<Self, VulkanError> {
+        let shader = ShaderModule::new(device.clone(), "shaders/skinning_comp.spv")?;
+        let pipeline = ComputePipeline::new(device.clone(), &[], shader)?;
+        Ok(Skinning {
+            device,
+            allocator,
+            pipeline,
+            joint_buffers: (0..frame_count).map(|_| None).collect(),
+            weight_buffers: (0..frame_count).map(|_| None).collect(),
+            addresses: Vec::new(),
+        })
+    }
+
+    // writes the joint matrices of the current pose and the morph weights of the nodes
+    // the slot has to be finished on the gpu
+    // has to be called after the node transforms of the frame were updated
+    pub fn prepare(&mut self, frame_slot: usize, scene: &Scene) -> Result<(), VulkanError> {
+        self.addresses.clear();
+        let mut matrices = Vec::new();
+        let mut weights = Vec::new();
+        let mut offsets = Vec::with_capacity(scene.deformed_meshes().len());
+        for deformed_mesh in scene.deformed_meshes() {
+            offsets.push((matrices.len(), weights.len()));
+            let node = &scene.nodes()[deformed_mesh.node()];
+            if let Some(skin) = deformed_mesh.skin() {
+                matrices.extend(
+                    scene.skins()[skin]
+                        .joint_matrices(|node| scene.nodes()[node].world_transform()),
+                );
+            }
+            weights.extend_from_slice(node.morph_weights());
+        }
+        let joint_address = write_slot(
+            &self.device,
+            &self.allocator,
+            &mut self.joint_buffers[frame_slot],
+            "Joint Matrices",
+            &matrices,
+        )?;
+        let weight_address = write_slot(
+            &self.device,
+            &self.allocator,
+            &mut self.weight_buffers[frame_slot],
+            "Morph Weights",
+            &weights,
+        )?;
+        self.addresses
+            .extend(offsets.iter().map(|(joint_offset, weight_offset)| {
+                (
+                    joint_address
+                        + (joint_offset * std::mem::size_of::<glm::Mat4>()) as vk::DeviceAddress,
+                    weight_address
+                        + (weight_offset * std::mem::size_of::<f32>()) as vk::DeviceAddress,
+                )
+            }));
+        Ok(())
+    }
+
+    // one pass per deformed mesh of the scene that prepare was called with
+    // the returned vertex buffers have to be declared as StorageRead by the passes that draw them
+    pub fn add_passes<'a>(
+        &'a self,
+        graph: &mut RenderGraph<'a>,
+        scene: &'a Scene,
+    ) -> Vec<BufferHandle> {
+        let pipeline = &self.pipeline;
+        scene
+            .deformed_meshes()
+            .iter()
+            .zip(&self.addresses)
+            .map(|(deformed_mesh, (joint_matrices, morph_weights))| {
+                let source = &deformed_mesh.source;
+                let buffers = deformed_mesh.mesh.buffers();
+                let vertices = graph.import_buffer("deformed vertices", buffers.vertex_buffer());
+                let address = |buffer: Option<&AllocatedBuffer>| {
+                    buffer.map_or(0, |buffer| buffer.get_device_address())
+                };
+                let push_constants = GPUSkinningPushConstants {
+                    source_vertices: source.buffers().vertex_buffer_address(),
+                    skin_weights: address(source.skin_weights()),
+                    joint_matrices: *joint_matrices,
+                    morph_targets: address(source.morph_targets()),
+                    morph_weights: *morph_weights,
+                    output_vertices: buffers.vertex_buffer_address(),
+                    vertex_count: buffers.vertex_count(),
+                    morph_target_count: source.morph_target_count() as u32,
+                    skinned: deformed_mesh.skin.is_some() as u32,
+                    _padding: 0,
+                };
+                graph.add_pass(
+                    GraphPass::new("skinning")
+                        .buffer(vertices, BufferUsage::StorageWrite)
+                        .record(move |command_buffer| {
+                            pipeline.dispatch(
+                                command_buffer,
+                                &[],
+                                [push_constants.vertex_count.div_ceil(GROUP_SIZE), 1, 1],
+                                &push_constants,
+                            );
+                        }),
+                );
+                vertices
+            })
+            .collect()
+    }
+}
+
+// copies data into the buffer of a slot, grows it if needed
+// returns the address of the first element, 0 for empty data
+fn write_slot<T: bytemuck::NoUninit>(
+    device: &Arc<Device>,
+    allocator: &Arc<Mutex<Allocator>>,
+    slot: &mut SlotBuffer,
+    name: &str,
+    data: &[T],
+) -> Result<vk::DeviceAddress, VulkanError> {
+    if data.is_empty() {
+        return Ok(0);
+    }
+    if slot
+        .as_ref()
+        .is_none_or(|(_, capacity)| *capacity < data.len())
+    {
+        let capacity = data.len().next_power_of_two();
+        let buffer = AllocatedBuffer::new(
+            device.clone(),
+            allocator.clone(),
+            name,
+            vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+            (capacity * std::mem::size_of::<T>()) as u64,
+            gpu_allocator::MemoryLocation::CpuToGpu,
+        )?;
+        // the slot is finished => the old buffer can be dropped right away
+        *slot = Some((buffer, capacity));
+    }
+    let (buffer, _) = slot.as_mut().expect("Created above");
+    buffer.copy_from_slice(data, 0);
+    Ok(buffer.get_device_address())
+}
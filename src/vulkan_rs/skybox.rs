@@ -0,0 +1,148 @@
+use super::descriptor::DescriptorAllocator;
+use super::descriptor::DescriptorLayoutBuilder;
+use super::descriptor::DescriptorSetLayout;
+use super::descriptor::DescriptorWriter;
+use super::descriptor::PoolSizeRatio;
+use super::device::Device;
+use super::device::ImageLayers;
+use super::error::VulkanError;
+use super::material::MaterialTexture;
+use super::pipelines::GraphicsPipeline;
+use super::pipelines::GraphicsPipelineBuilder;
+use super::shader::ShaderModule;
+use ash::vk;
+use nalgebra_glm as glm;
+use std::sync::Arc;
+
+// same layout as the push constants in skybox.frag
+#[repr(C)]
+#[derive(Debug, bytemuck::NoUninit, Copy, Clone)]
+struct GPUSkyboxPushConstants {
+    inverse_view_proj: glm::Mat4,
+}
+
+// cubemap drawn behind the scene as one fullscreen triangle at the far plane
+// depth test EQUAL => only pixels that no opaque surface was drawn to are covered
+pub struct Skybox {
+    device: Arc<Device>,
+    _descriptor_layout: DescriptorSetLayout,
+    _descriptor_allocator: DescriptorAllocator,
+    descriptor_set: vk::DescriptorSet,
+    pipeline: GraphicsPipeline,
+    // None => the background of the gradient pass stays visible
+    cubemap: Option<MaterialTexture>,
+}
+
+impl Skybox {
+    pub fn new(
+        device: Arc<Device>,
+        color_format: vk::Format,
+        depth_format: vk::Format,
+    ) -> Result<Self, VulkanError> {
+        let mut builder = DescriptorLayoutBuilder::new();
+        builder.add_binding(
+            0,
+            vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            vk::ShaderStageFlags::FRAGMENT,
+        );
+        let descriptor_layout =
+            builder.build(device.clone(), vk::DescriptorSetLayoutCreateFlags::empty())?;
+        let mut descriptor_allocator = DescriptorAllocator::new(device.clone());
+        descriptor_allocator.init_pool(
+            1,
+            &[PoolSizeRatio {
+                descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                ratio: 1.0,
+            }],
+        )?;
+        let descriptor_set = descriptor_allocator.allocate(descriptor_layout.layout())?;
+
+        let push_constants = vk::PushConstantRange {
+            stage_flags: vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+            offset: 0,
+            size: std::mem::size_of::<GPUSkyboxPushConstants>() as u32,
+        };
+        let set_layout = descriptor_layout.layout();
+        let layout_info = vk::PipelineLayoutCreateInfo {
+            s_type: vk::StructureType::PIPELINE_LAYOUT_CREATE_INFO,
+            p_next: std::ptr::null(),
+            flags: vk::PipelineLayoutCreateFlags::empty(),
+            set_layout_count: 1,
+            p_set_layouts: &set_layout,
+            push_constant_range_count: 1,
+            p_push_constant_ranges: &push_constants,
+            ..Default::default()
+        };
+        let pipeline_layout = device.create_pipeline_layout(&layout_info)?;
+        let frag_shader = ShaderModule::new(device.clone(), "shaders/skybox_frag.spv")?;
+        let vert_shader = ShaderModule::new(device.clone(), "shaders/skybox_vert.spv")?;
+        // reversed depth => the depth image is cleared to 0 = far plane
+        let pipeline = GraphicsPipelineBuilder::new()
+            .set_layout(pipeline_layout)
+            .set_shaders(&frag_shader, &vert_shader)
+            .set_input_topology(vk::PrimitiveTopology::TRIANGLE_LIST)
+            .set_polygon_mode(vk::PolygonMode::FILL)
+            .set_cull_mode(vk::CullModeFlags::NONE, vk::FrontFace::CLOCKWISE)
+            .disable_multisampling()
+            .set_color_attachment_format(color_format)
+            .set_depth_format(depth_format)
+            .disable_blending()
+            .enable_depth_test(vk::FALSE, vk::CompareOp::EQUAL)
+            .build_pipeline(device.clone())?;
+
+        Ok(Skybox {
+            device,
+            _descriptor_layout: descriptor_layout,
+            _descriptor_allocator: descriptor_allocator,
+            descriptor_set,
+            pipeline,
+            cubemap: None,
+        })
+    }
+
+    // the descriptor set is rewritten => the gpu must not use it anymore
+    // images without a cube view would be a validation error => shown as gradient instead
+    pub fn set_cubemap(&mut self, cubemap: Option<MaterialTexture>) {
+        let cubemap = cubemap.filter(|cubemap| {
+            let is_cube = cubemap.image.layers() == ImageLayers::Cube;
+            if !is_cube {
+                log::error!(
+                    "Skybox needs a cubemap, got an image with {:?}",
+                    cubemap.image.layers()
+                );
+            }
+            is_cube
+        });
+        if let Some(cubemap) = &cubemap {
+            let mut writer = DescriptorWriter::new();
+            writer.add_image(
+                0,
+                cubemap.image.image_view(),
+                cubemap.sampler.sampler(),
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            );
+            writer.update_descriptor_set(&self.device, self.descriptor_set);
+        }
+        self.cubemap = cubemap;
+    }
+
+    // expects to be recorded inside the geometry pass, after the opaque surfaces
+    pub fn record(&self, command_buffer: vk::CommandBuffer, view_proj: &glm::Mat4) {
+        if self.cubemap.is_none() {
+            return;
+        }
+        self.pipeline.bind(command_buffer);
+        self.device.cmd_bind_descriptor_sets(
+            command_buffer,
+            self.pipeline.layout(),
+            vk::PipelineBindPoint::GRAPHICS,
+            &[self.descriptor_set],
+        );
+        let push_constants = GPUSkyboxPushConstants {
+            inverse_view_proj: glm::inverse(view_proj),
+        };
+        self.pipeline
+            .draw_generated(command_buffer, 3, bytemuck::bytes_of(&push_constants));
+    }
+}
@@ -0,0 +1,334 @@
+use super::allocation::AllocatedImage;
+use super::allocation::Allocator;
+use super::descriptor::DescriptorAllocator;
+use super::descriptor::DescriptorLayoutBuilder;
+use super::descriptor::DescriptorSetLayout;
+use super::descriptor::DescriptorWriter;
+use super::device::Device;
+use super::error::VulkanError;
+use super::mesh::Sampler;
+use super::pipelines::ComputePipeline;
+use super::pipelines::PushConstants;
+use super::render_graph::GraphPass;
+use super::render_graph::ImageHandle;
+use super::render_graph::ImageUsage;
+use super::render_graph::RenderGraph;
+use super::shader::ShaderModule;
+use ash::vk;
+use nalgebra_glm as glm;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+// jitter positions before the sequence repeats
+const JITTER_SAMPLES: u64 = 8;
+// weight of the current frame, lower => smoother but more ghosting
+const CURRENT_WEIGHT: f32 = 0.1;
+
+// same layout as the push constants in taa_velocity.comp
+#[repr(C)]
+#[derive(bytemuck::NoUninit, Copy, Clone, Debug)]
+struct GPUVelocityPushConstants {
+    // jittered clip space of this frame -> clip space of the last frame
+    reprojection: glm::Mat4,
+    // xy = extent, zw = jitter in uv units
+    data: glm::Vec4,
+}
+
+// what prepare computed for the passes of the current frame
+struct TaaFrame {
+    extent: vk::Extent2D,
+    // index into history_images that is written this frame, the other one is read
+    history: usize,
+    // false => no usable history, e.g. first frame or after a resize
+    history_valid: bool,
+    velocity_push_constants: GPUVelocityPushConstants,
+}
+
+// temporal anti-aliasing: the projection is jittered by a sub pixel offset every frame and the
+// frames are accumulated in a history image
+//   velocity: depth -> screen space motion since the last frame, only camera motion
+//   resolve: draw image + history of the last frame (reprojected, clamped to the neighbourhood
+//            of the current pixel) -> history of this frame
+//   copy: history of this frame -> draw image
+// moving objects have no velocity => the neighbourhood clamp keeps their ghosting short
+pub struct Taa {
+    device: Arc<Device>,
+    velocity_image: AllocatedImage,
+    // ping pong => the history of the last frame is read while the new one is written
+    history_images: [AllocatedImage; 2],
+    // only referenced by the velocity descriptor
+    _depth_sampler: Sampler,
+    _velocity_layout: DescriptorSetLayout,
+    _resolve_layout: DescriptorSetLayout,
+    velocity_descriptor: vk::DescriptorSet,
+    // index = history image that is written
+    resolve_descriptors: [vk::DescriptorSet; 2],
+    velocity_pipeline: ComputePipeline,
+    resolve_pipeline: ComputePipeline,
+    frame: u64,
+    // unjittered view projection and extent of the last frame
+    previous: Option<(glm::Mat4, vk::Extent2D)>,
+    current: Option<TaaFrame>,
+}
+
+impl Taa {
+    pub fn new(
+        device: Arc<Device>,
+        allocator: Arc<Mutex<Allocator>>,
+        descriptor_allocator: &DescriptorAllocator,
+        draw_image: &AllocatedImage,
+        depth_image: &AllocatedImage,
+    ) -> Result<Self, VulkanError> {
+        let extent = draw_image.extent();
+        let velocity_image = AllocatedImage::new(
+            device.clone(),
+            allocator.clone(),
+            vk::Format::R16G16_SFLOAT,
+            vk::ImageUsageFlags::STORAGE,
+            extent,
+            vk::ImageAspectFlags::COLOR,
+            1,
+        )?;
+        velocity_image.set_name("velocity image");
+        let new_history_image = |name: &str| -> Result<AllocatedImage, VulkanError> {
+            let image =
+                AllocatedImage::new_draw_color_image(device.clone(), allocator.clone(), extent)?;
+            image.set_name(name);
+            Ok(image)
+        };
+        let history_images = [
+            new_history_image("taa history 0")?,
+            new_history_image("taa history 1")?,
+        ];
+        // texel fetches only, the filter doesnt matter
+        let depth_sampler = Sampler::new(device.clone(), vk::Filter::NEAREST, vk::Filter::NEAREST)?;
+
+        let mut builder = DescriptorLayoutBuilder::new();
+        builder.add_binding(
+            0,
+            vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            vk::ShaderStageFlags::COMPUTE,
+        );
+        builder.add_binding(
+            1,
+            vk::DescriptorType::STORAGE_IMAGE,
+            vk::ShaderStageFlags::COMPUTE,
+        );
+        let velocity_layout =
+            builder.build(device.clone(), vk::DescriptorSetLayoutCreateFlags::empty())?;
+        let velocity_descriptor = descriptor_allocator.allocate(velocity_layout.layout())?;
+        let mut writer = DescriptorWriter::new();
+        writer.add_image(
+            0,
+            depth_image.image_view(),
+            depth_sampler.sampler(),
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+        );
+        writer.add_storage_image(1, velocity_image.image_view());
+        writer.update_descriptor_set(&device, velocity_descriptor);
+
+        // 0 = draw image, 1 = history of the last frame, 2 = velocity, 3 = new history
+        let mut builder = DescriptorLayoutBuilder::new();
+        for binding in 0..4 {
+            builder.add_binding(
+                binding,
+                vk::DescriptorType::STORAGE_IMAGE,
+                vk::ShaderStageFlags::COMPUTE,
+            );
+        }
+        let resolve_layout =
+            builder.build(device.clone(), vk::DescriptorSetLayoutCreateFlags::empty())?;
+        let allocate_resolve = |history: usize| {
+            let descriptor = descriptor_allocator.allocate(resolve_layout.layout())?;
+            let mut writer = DescriptorWriter::new();
+            writer.add_storage_image(0, draw_image.image_view());
+            writer.add_storage_image(1, history_images[1 - history].image_view());
+            writer.add_storage_image(2, velocity_image.image_view());
+            writer.add_storage_image(3, history_images[history].image_view());
+            writer.update_descriptor_set(&device, descriptor);
+            Ok::<_, VulkanError>(descriptor)
+        };
+        let resolve_descriptors = [allocate_resolve(0)?, allocate_resolve(1)?];
+
+        let shader = ShaderModule::new(device.clone(), "shaders/taa_velocity_comp.spv")?;
+        let velocity_pipeline =
+            ComputePipeline::new(device.clone(), &[velocity_layout.layout()], shader)?;
+        let shader = ShaderModule::new(device.clone(), "shaders/taa_resolve_comp.spv")?;
+        let resolve_pipeline =
+            ComputePipeline::new(device.clone(), &[resolve_layout.layout()], shader)?;
+
+        Ok(Taa {
+            device,
+            velocity_image,
+            history_images,
+            _depth_sampler: depth_sampler,
+            _velocity_layout: velocity_layout,
+            _resolve_layout: resolve_layout,
+            velocity_descriptor,
+            resolve_descriptors,
+            velocity_pipeline,
+            resolve_pipeline,
+            frame: 0,
+            previous: None,
+            current: None,
+        })
+    }
+
+    // offset of the projection for the next frame in clip space units (2 / extent = 1 pixel)
+    pub fn jitter(&self, extent: vk::Extent2D) -> glm::Vec2 {
+        // halton(2, 3) => evenly spread over the pixel
+        let index = self.frame % JITTER_SAMPLES + 1;
+        let offset = glm::vec2(halton(index, 2) - 0.5, halton(index, 3) - 0.5);
+        glm::vec2(
+            offset.x * 2.0 / extent.width as f32,
+            offset.y * 2.0 / extent.height as f32,
+        )
+    }
+
+    // the history is not used by the next frame, e.g. while taa is turned off
+    pub fn reset(&mut self) {
+        self.previous = None;
+        self.current = None;
+    }
+
+    // view_proj is the unjittered view projection of the frame, jitter has to come from jitter()
+    pub fn prepare(&mut self, view_proj: &glm::Mat4, jitter: glm::Vec2, extent: vk::Extent2D) {
+        let jittered_view_proj = glm::translation(&glm::vec3(jitter.x, jitter.y, 0.0)) * view_proj;
+        let previous = self
+            .previous
+            .filter(|(_, previous_extent)| *previous_extent == extent);
+        let reprojection = previous.map_or(glm::Mat4::identity(), |(previous_view_proj, _)| {
+            previous_view_proj * glm::inverse(&jittered_view_proj)
+        });
+        self.current = Some(TaaFrame {
+            extent,
+            history: (self.frame % 2) as usize,
+            history_valid: previous.is_some(),
+            velocity_push_constants: GPUVelocityPushConstants {
+                reprojection,
+                data: glm::vec4(
+                    extent.width as f32,
+                    extent.height as f32,
+                    jitter.x * 0.5,
+                    jitter.y * 0.5,
+                ),
+            },
+        });
+        self.previous = Some((*view_proj, extent));
+        self.frame += 1;
+    }
+
+    // has to be called after prepare, draw and depth are the images of the scene
+    pub fn add_passes<'a>(
+        &'a self,
+        graph: &mut RenderGraph<'a>,
+        draw: ImageHandle,
+        draw_image: vk::Image,
+        depth: ImageHandle,
+    ) {
+        let Some(frame) = self.current.as_ref() else {
+            return;
+        };
+        let extent = frame.extent;
+        let velocity = graph.import_image(
+            "velocity image",
+            self.velocity_image.image(),
+            vk::ImageAspectFlags::COLOR,
+            vk::ImageLayout::UNDEFINED,
+        );
+        graph.set_image_size(velocity, extent, self.velocity_image.format());
+        // the old history is left in GENERAL by the last frame
+        let previous_layout = if frame.history_valid {
+            vk::ImageLayout::GENERAL
+        } else {
+            vk::ImageLayout::UNDEFINED
+        };
+        let previous_history = graph.import_image(
+            "taa history",
+            self.history_images[1 - frame.history].image(),
+            vk::ImageAspectFlags::COLOR,
+            previous_layout,
+        );
+        let history = graph.import_image(
+            "taa history",
+            self.history_images[frame.history].image(),
+            vk::ImageAspectFlags::COLOR,
+            vk::ImageLayout::UNDEFINED,
+        );
+        for image in [previous_history, history] {
+            graph.set_image_size(image, extent, self.history_images[0].format());
+        }
+        graph.export_image(history, vk::ImageLayout::GENERAL);
+
+        let velocity_push_constants = frame.velocity_push_constants;
+        graph.add_pass(
+            GraphPass::new("taa velocity")
+                .image(depth, ImageUsage::Sampled)
+                .image(velocity, ImageUsage::StorageWrite)
+                .record(move |command_buffer| {
+                    self.velocity_pipeline.dispatch(
+                        command_buffer,
+                        &[self.velocity_descriptor],
+                        [extent.width.div_ceil(16), extent.height.div_ceil(16), 1],
+                        &velocity_push_constants,
+                    );
+                }),
+        );
+        let push_constants = PushConstants::new(
+            glm::vec4(
+                extent.width as f32,
+                extent.height as f32,
+                frame.history_valid as u32 as f32,
+                CURRENT_WEIGHT,
+            ),
+            glm::Vec4::zeros(),
+            glm::Vec4::zeros(),
+            glm::Vec4::zeros(),
+        );
+        let descriptor = self.resolve_descriptors[frame.history];
+        graph.add_pass(
+            GraphPass::new("taa resolve")
+                .image(draw, ImageUsage::StorageRead)
+                .image(previous_history, ImageUsage::StorageRead)
+                .image(velocity, ImageUsage::StorageRead)
+                .image(history, ImageUsage::StorageWrite)
+                .record(move |command_buffer| {
+                    self.resolve_pipeline.execute_compute_with_constants(
+                        command_buffer,
+                        &[descriptor],
+                        extent,
+                        &push_constants,
+                    );
+                }),
+        );
+        let device = &self.device;
+        let history_image = self.history_images[frame.history].image();
+        graph.add_pass(
+            GraphPass::new("taa copy")
+                .image(history, ImageUsage::TransferSrc)
+                .image(draw, ImageUsage::TransferDst)
+                .record(move |command_buffer| {
+                    device.copy_image_to_image(
+                        command_buffer,
+                        history_image,
+                        draw_image,
+                        extent,
+                        extent,
+                    );
+                }),
+        );
+    }
+}
+
+// radical inverse of index in the given base, [0, 1)
+fn halton(mut index: u64, base: u64) -> f32 {
+    let mut result = 0.0;
+    let mut fraction = 1.0;
+    while index > 0 {
+        fraction /= base as f32;
+        result += fraction * (index % base) as f32;
+        index /= base;
+    }
+    result
+}
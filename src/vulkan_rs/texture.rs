@@ -0,0 +1,121 @@
+use super::allocation::AllocatedImage;
+use super::allocation::Allocator;
+use super::async_upload::AsyncUploader;
+use super::device::Device;
+use super::error::AssetError;
+use ash::vk;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+// how the 8 bit channels of a texture have to be interpreted
+// color data (albedo, emissive) is srgb, data textures (normals, roughness, ...) are linear
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ColorSpace {
+    Srgb,
+    Linear,
+}
+
+impl ColorSpace {
+    pub fn rgba8_format(self) -> vk::Format {
+        match self {
+            ColorSpace::Srgb => vk::Format::R8G8B8A8_SRGB,
+            ColorSpace::Linear => vk::Format::R8G8B8A8_UNORM,
+        }
+    }
+
+    // srgb formats are decoded when sampled and encoded when written, everything else is used as is
+    pub fn of_format(format: vk::Format) -> Self {
+        match format {
+            vk::Format::R8_SRGB
+            | vk::Format::R8G8_SRGB
+            | vk::Format::R8G8B8_SRGB
+            | vk::Format::B8G8R8_SRGB
+            | vk::Format::R8G8B8A8_SRGB
+            | vk::Format::B8G8R8A8_SRGB
+            | vk::Format::A8B8G8R8_SRGB_PACK32
+            | vk::Format::BC1_RGB_SRGB_BLOCK
+            | vk::Format::BC1_RGBA_SRGB_BLOCK
+            | vk::Format::BC2_SRGB_BLOCK
+            | vk::Format::BC3_SRGB_BLOCK
+            | vk::Format::BC7_SRGB_BLOCK
+            | vk::Format::ETC2_R8G8B8_SRGB_BLOCK
+            | vk::Format::ETC2_R8G8B8A1_SRGB_BLOCK
+            | vk::Format::ETC2_R8G8B8A8_SRGB_BLOCK => ColorSpace::Srgb,
+            _ => ColorSpace::Linear,
+        }
+    }
+}
+
+// color validation => logs images that are used where the other color space is expected
+// an srgb normal map is decoded into wrong vectors, a linear albedo looks washed out
+// and a linear swapchain shows the linear draw image too dark
+pub fn check_color_space(usage: &str, format: vk::Format, expected: ColorSpace) {
+    let actual = ColorSpace::of_format(format);
+    if actual != expected {
+        log::warn!(
+            "Color space mismatch: {} expects {:?} data but the image is {:?} ({:?})",
+            usage,
+            expected,
+            actual,
+            format
+        );
+    }
+}
+
+// decodes png/jpeg/tga/hdr and uploads it through the uploader
+// everything is expanded to rgba since 3 channel formats are barely supported
+// float images (hdr) are always linear and stay 32 bit floats
+pub fn load_texture(
+    path: &Path,
+    color_space: ColorSpace,
+    device: Arc<Device>,
+    allocator: Arc<Mutex<Allocator>>,
+    uploader: &AsyncUploader,
+) -> Result<AllocatedImage, AssetError> {
+    let image = image::open(path)?;
+    let extent = vk::Extent3D {
+        width: image.width(),
+        height: image.height(),
+        depth: 1,
+    };
+    let is_float = matches!(
+        image.color(),
+        image::ColorType::Rgb32F | image::ColorType::Rgba32F
+    );
+    let texture = if is_float {
+        let pixels = image.into_rgba32f();
+        // linear filtering of 32 bit floats is optional => no blitted mipmaps
+        AllocatedImage::new_texture(
+            pixels.as_raw(),
+            device,
+            allocator,
+            vk::Format::R32G32B32A32_SFLOAT,
+            vk::ImageUsageFlags::SAMPLED,
+            extent,
+            false,
+            uploader,
+        )?
+    } else {
+        let pixels = image.into_rgba8();
+        AllocatedImage::new_texture(
+            pixels.as_raw(),
+            device,
+            allocator,
+            color_space.rgba8_format(),
+            vk::ImageUsageFlags::SAMPLED,
+            extent,
+            true,
+            uploader,
+        )?
+    };
+    texture.set_name(&path.display().to_string());
+    log::info!(
+        "Loaded texture {:?} ({}x{}, {:?})",
+        path,
+        extent.width,
+        extent.height,
+        texture.format()
+    );
+    Ok(texture)
+}
@@ -0,0 +1,139 @@
+use super::allocation::AllocatedImage;
+use super::allocation::Allocator;
+use super::descriptor::DescriptorAllocator;
+use super::descriptor::DescriptorLayoutBuilder;
+use super::descriptor::DescriptorSetLayout;
+use super::descriptor::DescriptorWriter;
+use super::device::Device;
+use super::error::VulkanError;
+use super::pipelines::ComputePipeline;
+use super::pipelines::PushConstants;
+use super::shader::ShaderModule;
+use ash::vk;
+use nalgebra_glm as glm;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+// FSR 1 style spatial upscaler in two compute passes:
+//   easu: low resolution part of the draw image -> intermediate image at output resolution
+//   rcas: intermediate image -> draw image at output resolution (sharpened)
+// => afterwards the draw image can be copied to the swapchain without scaling
+pub struct Upscaler {
+    intermediate_image: AllocatedImage,
+    // both passes use one storage image as input (binding 0) and one as output (binding 1)
+    _descriptor_layout: DescriptorSetLayout,
+    easu_descriptor: vk::DescriptorSet,
+    rcas_descriptor: vk::DescriptorSet,
+    easu_pipeline: ComputePipeline,
+    rcas_pipeline: ComputePipeline,
+}
+
+impl Upscaler {
+    pub fn new(
+        device: Arc<Device>,
+        allocator: Arc<Mutex<Allocator>>,
+        descriptor_allocator: &DescriptorAllocator,
+        draw_image: &AllocatedImage,
+    ) -> Result<Self, VulkanError> {
+        // draw image is as large as the largest possible output => same extent is enough
+        let intermediate_image =
+            AllocatedImage::new_draw_color_image(device.clone(), allocator, draw_image.extent())?;
+
+        let mut builder = DescriptorLayoutBuilder::new();
+        builder.add_binding(
+            0,
+            vk::DescriptorType::STORAGE_IMAGE,
+            vk::ShaderStageFlags::COMPUTE,
+        );
+        builder.add_binding(
+            1,
+            vk::DescriptorType::STORAGE_IMAGE,
+            vk::ShaderStageFlags::COMPUTE,
+        );
+        let descriptor_layout =
+            builder.build(device.clone(), vk::DescriptorSetLayoutCreateFlags::empty())?;
+
+        let easu_descriptor = descriptor_allocator.allocate(descriptor_layout.layout())?;
+        let mut writer = DescriptorWriter::new();
+        writer.add_storage_image(0, draw_image.image_view());
+        writer.add_storage_image(1, intermediate_image.image_view());
+        writer.update_descriptor_set(&device, easu_descriptor);
+
+        let rcas_descriptor = descriptor_allocator.allocate(descriptor_layout.layout())?;
+        let mut writer = DescriptorWriter::new();
+        writer.add_storage_image(0, intermediate_image.image_view());
+        writer.add_storage_image(1, draw_image.image_view());
+        writer.update_descriptor_set(&device, rcas_descriptor);
+
+        let easu_shader = ShaderModule::new(device.clone(), "shaders/easu_comp.spv")?;
+        let easu_pipeline =
+            ComputePipeline::new(device.clone(), &[descriptor_layout.layout()], easu_shader)?;
+        let rcas_shader = ShaderModule::new(device.clone(), "shaders/rcas_comp.spv")?;
+        let rcas_pipeline =
+            ComputePipeline::new(device, &[descriptor_layout.layout()], rcas_shader)?;
+
+        Ok(Upscaler {
+            intermediate_image,
+            _descriptor_layout: descriptor_layout,
+            easu_descriptor,
+            rcas_descriptor,
+            easu_pipeline,
+            rcas_pipeline,
+        })
+    }
+
+    pub fn intermediate_image(&self) -> vk::Image {
+        self.intermediate_image.image()
+    }
+
+    pub fn record_easu(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        input_extent: vk::Extent2D,
+        output_extent: vk::Extent2D,
+    ) {
+        let push_constants = PushConstants::new(
+            glm::vec4(
+                input_extent.width as f32,
+                input_extent.height as f32,
+                output_extent.width as f32,
+                output_extent.height as f32,
+            ),
+            glm::Vec4::zeros(),
+            glm::Vec4::zeros(),
+            glm::Vec4::zeros(),
+        );
+        self.easu_pipeline.execute_compute_with_constants(
+            command_buffer,
+            &[self.easu_descriptor],
+            output_extent,
+            &push_constants,
+        );
+    }
+
+    // sharpness: 0 is the strongest, every +1 halves the sharpening
+    pub fn record_rcas(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        output_extent: vk::Extent2D,
+        sharpness: f32,
+    ) {
+        let push_constants = PushConstants::new(
+            glm::vec4(
+                output_extent.width as f32,
+                output_extent.height as f32,
+                sharpness,
+                0.0,
+            ),
+            glm::Vec4::zeros(),
+            glm::Vec4::zeros(),
+            glm::Vec4::zeros(),
+        );
+        self.rcas_pipeline.execute_compute_with_constants(
+            command_buffer,
+            &[self.rcas_descriptor],
+            output_extent,
+            &push_constants,
+        );
+    }
+}
@@ -1,3 +1,5 @@
+use ash::vk;
+
 pub fn clamp<T: PartialOrd>(value: T, min: T, max: T) -> T {
     if value < min {
         min
@@ -7,3 +9,31 @@ pub fn clamp<T: PartialOrd>(value: T, min: T, max: T) -> T {
         value
     }
 }
+
+// bytes per pixel of the formats we render to, everything else is counted as 4 bytes
+pub fn format_size(format: vk::Format) -> u64 {
+    match format {
+        vk::Format::R32G32B32A32_SFLOAT => 16,
+        vk::Format::R16G16B16A16_SFLOAT => 8,
+        vk::Format::D32_SFLOAT_S8_UINT => 5,
+        vk::Format::R8G8B8_SRGB | vk::Format::B8G8R8_SRGB => 3,
+        _ => 4,
+    }
+}
+
+// binary semaphores ignore the value => 0 is fine for them
+pub fn semaphore_submit_info(
+    semaphore: vk::Semaphore,
+    value: u64,
+    stage_mask: vk::PipelineStageFlags2,
+) -> vk::SemaphoreSubmitInfo<'static> {
+    vk::SemaphoreSubmitInfo {
+        s_type: vk::StructureType::SEMAPHORE_SUBMIT_INFO,
+        p_next: std::ptr::null(),
+        semaphore,
+        value,
+        stage_mask,
+        device_index: 0,
+        ..Default::default()
+    }
+}
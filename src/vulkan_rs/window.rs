@@ -1,4 +1,6 @@
 use super::device::Device;
+use super::device::Queue;
+use super::error::VulkanError;
 use super::instance::Instance;
 use super::utils;
 use ash::{
@@ -12,8 +14,10 @@ use std::sync::Arc;
 use winit::dpi::LogicalSize;
 use winit::window::Window;
 
-pub fn get_required_instance_extensions(display_handle: RawDisplayHandle) -> Vec<CString> {
-    match display_handle {
+pub fn get_required_instance_extensions(
+    display_handle: RawDisplayHandle,
+) -> Result<Vec<CString>, VulkanError> {
+    let extensions = match display_handle {
         RawDisplayHandle::Windows(_) => {
             vec![win32_surface::NAME.to_owned(), surface::NAME.to_owned()]
         }
@@ -38,96 +42,237 @@ pub fn get_required_instance_extensions(display_handle: RawDisplayHandle) -> Vec
             vec![metal_surface::NAME.to_owned(), surface::NAME.to_owned()]
         }
 
-        _ => panic!("Unsupported display handle"),
+        _ => return Err(VulkanError::UnsupportedDisplay),
+    };
+    Ok(extensions)
+}
+
+// enabled if available
+// swapchain_colorspace => hdr surface formats, get_surface_capabilities2 => exclusive fullscreen
+pub fn get_optional_instance_extensions(display_handle: RawDisplayHandle) -> Vec<CString> {
+    let mut extensions = vec![ash::ext::swapchain_colorspace::NAME.to_owned()];
+    if let RawDisplayHandle::Windows(_) = display_handle {
+        extensions.push(ash::khr::get_surface_capabilities2::NAME.to_owned());
+    }
+    extensions
+}
+
+// monitor of a fullscreen window => the swapchain can take exclusive control of it
+#[cfg(target_os = "windows")]
+fn exclusive_fullscreen_monitor(window: &Window) -> Option<vk::HMONITOR> {
+    use winit::platform::windows::MonitorHandleExtWindows;
+    window.fullscreen()?;
+    Some(window.current_monitor()?.hmonitor() as vk::HMONITOR)
+}
+
+#[cfg(not(target_os = "windows"))]
+fn exclusive_fullscreen_monitor(_window: &Window) -> Option<vk::HMONITOR> {
+    None
+}
+
+// how the display interprets the values in the swapchain images
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputColorSpace {
+    // srgb format, the present blit encodes the linear draw image
+    Srgb,
+    // linear rec709 in a float format, 1.0 = 80 nits, values above 1 are brighter
+    ScRgb,
+    // rec2020 primaries encoded with the pq curve, 10 bits per channel
+    Hdr10,
+}
+
+impl OutputColorSpace {
+    pub fn is_hdr(self) -> bool {
+        self != OutputColorSpace::Srgb
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PresentModePreference {
+    // lowest latency, may tear
+    Immediate,
+    // vsync, always supported
+    Fifo,
+    // low latency without tearing, renders as fast as possible
+    Mailbox,
+    // vsync, but late frames are presented immediately (may tear)
+    FifoRelaxed,
+}
+
+impl PresentModePreference {
+    // modes to try in order. FIFO is the last fallback since it is guaranteed to be available
+    fn candidates(&self) -> &'static [vk::PresentModeKHR] {
+        match self {
+            PresentModePreference::Immediate => {
+                &[vk::PresentModeKHR::IMMEDIATE, vk::PresentModeKHR::MAILBOX]
+            }
+            PresentModePreference::Fifo => &[],
+            PresentModePreference::Mailbox => {
+                &[vk::PresentModeKHR::MAILBOX, vk::PresentModeKHR::IMMEDIATE]
+            }
+            PresentModePreference::FifoRelaxed => &[vk::PresentModeKHR::FIFO_RELAXED],
+        }
+    }
+
+    pub fn is_vsync(&self) -> bool {
+        matches!(
+            self,
+            PresentModePreference::Fifo | PresentModePreference::FifoRelaxed
+        )
     }
 }
 
+impl std::str::FromStr for PresentModePreference {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "immediate" => Ok(PresentModePreference::Immediate),
+            "fifo" => Ok(PresentModePreference::Fifo),
+            "mailbox" => Ok(PresentModePreference::Mailbox),
+            "fifo-relaxed" | "fifo_relaxed" => Ok(PresentModePreference::FifoRelaxed),
+            _ => Err(format!("Unknown present mode: {}", s)),
+        }
+    }
+}
+
+type SwapchainParts = (
+    vk::SwapchainKHR,
+    ash::khr::swapchain::Device,
+    Vec<vk::Image>,
+    Vec<vk::ImageView>,
+    vk::Extent2D,
+    vk::Format,
+    OutputColorSpace,
+    // exclusive fullscreen was acquired for the swapchain
+    bool,
+);
+
 pub struct Surface {
     handle: vk::SurfaceKHR,
     loader: ash::khr::surface::Instance,
     _instance: Arc<Instance>,
-    _window: Arc<Window>,
+    window: Arc<Window>,
 }
 
 impl Surface {
-    pub fn new(instance: Arc<Instance>, window: Arc<Window>) -> Arc<Surface> {
-        let raw_window_handle = window
-            .window_handle()
-            .expect("I hope the window handle exists")
-            .as_raw();
-        let raw_display_handle = window
-            .display_handle()
-            .expect("I hope window has a display handle")
-            .as_raw();
-        let surface = instance.create_surface(raw_display_handle, raw_window_handle, None);
+    pub fn new(instance: Arc<Instance>, window: Arc<Window>) -> Result<Arc<Surface>, VulkanError> {
+        let raw_window_handle = window.window_handle()?.as_raw();
+        let raw_display_handle = window.display_handle()?.as_raw();
+        let surface = instance.create_surface(raw_display_handle, raw_window_handle, None)?;
         let loader = instance.create_surface_loader();
 
-        Arc::new(Surface {
+        Ok(Arc::new(Surface {
             handle: surface,
             loader,
             _instance: instance,
-            _window: window,
-        })
+            window,
+        }))
     }
 
     pub fn get_physical_device_surface_support(
         &self,
         device: &vk::PhysicalDevice,
         idx: u32,
-    ) -> bool {
-        unsafe {
+    ) -> Result<bool, VulkanError> {
+        Ok(unsafe {
             self.loader
-                .get_physical_device_surface_support(*device, idx, self.handle)
-                .expect("Host does not have enough resources or smth")
-        }
+                .get_physical_device_surface_support(*device, idx, self.handle)?
+        })
     }
 
-    pub fn query_support_details(&self, device: &vk::PhysicalDevice) -> SwapChainSupportDetails {
+    pub fn query_support_details(
+        &self,
+        device: &vk::PhysicalDevice,
+    ) -> Result<SwapChainSupportDetails, VulkanError> {
         let surface_instance = &self.loader;
         let surface = self.handle;
-        let capabilities = unsafe {
-            surface_instance
-                .get_physical_device_surface_capabilities(*device, surface)
-                .expect("Could not get surface capabilities")
-        };
-        let surface_formats = unsafe {
-            surface_instance
-                .get_physical_device_surface_formats(*device, surface)
-                .expect("Could not get surface formats")
-        };
+        let capabilities =
+            unsafe { surface_instance.get_physical_device_surface_capabilities(*device, surface)? };
+        let surface_formats =
+            unsafe { surface_instance.get_physical_device_surface_formats(*device, surface)? };
         let present_modes = unsafe {
-            surface_instance
-                .get_physical_device_surface_present_modes(*device, surface)
-                .expect("Could not get present modes")
+            surface_instance.get_physical_device_surface_present_modes(*device, surface)?
         };
-        SwapChainSupportDetails {
+        Ok(SwapChainSupportDetails {
             capabilities,
             surface_formats,
             present_modes,
-        }
+        })
     }
 
     fn choose_swap_surface_format(
         available_formats: &[vk::SurfaceFormatKHR],
-    ) -> vk::SurfaceFormatKHR {
-        let desired_format = available_formats.iter().find(|format| {
-            format.format == vk::Format::B8G8R8_SRGB
-                && format.color_space == vk::ColorSpaceKHR::SRGB_NONLINEAR
-        });
+        hdr_output: bool,
+    ) -> (vk::SurfaceFormatKHR, OutputColorSpace) {
+        // scrgb first => no precision lost in the blit, the tonemapper encodes hdr10 itself
+        // hdr formats are only offered with VK_EXT_swapchain_colorspace and an hdr display
+        let hdr_formats = [
+            (
+                vk::Format::R16G16B16A16_SFLOAT,
+                vk::ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT,
+                OutputColorSpace::ScRgb,
+            ),
+            (
+                vk::Format::A2B10G10R10_UNORM_PACK32,
+                vk::ColorSpaceKHR::HDR10_ST2084_EXT,
+                OutputColorSpace::Hdr10,
+            ),
+            (
+                vk::Format::A2R10G10B10_UNORM_PACK32,
+                vk::ColorSpaceKHR::HDR10_ST2084_EXT,
+                OutputColorSpace::Hdr10,
+            ),
+        ];
+        // the draw image is linear => an srgb format lets the present blit do the encoding
+        // 3 channel formats are almost never offered for surfaces
+        let sdr_formats = [
+            (
+                vk::Format::B8G8R8A8_SRGB,
+                vk::ColorSpaceKHR::SRGB_NONLINEAR,
+                OutputColorSpace::Srgb,
+            ),
+            (
+                vk::Format::R8G8B8A8_SRGB,
+                vk::ColorSpaceKHR::SRGB_NONLINEAR,
+                OutputColorSpace::Srgb,
+            ),
+        ];
+        let candidates = if hdr_output { &hdr_formats[..] } else { &[] };
+        let desired_format =
+            candidates
+                .iter()
+                .chain(&sdr_formats)
+                .find_map(|(format, color_space, output)| {
+                    available_formats
+                        .iter()
+                        .find(|available| {
+                            available.format == *format && available.color_space == *color_space
+                        })
+                        .map(|available| (*available, *output))
+                });
+        if hdr_output && desired_format.is_none_or(|(_, output)| !output.is_hdr()) {
+            log::warn!("HDR output requested but the surface offers no HDR format, using SDR");
+        }
         match desired_format {
-            Some(format) => *format,
-            None => *available_formats.first().expect(
-                "Should not be empty, since we checked for the existence of atleast one format",
+            Some(format) => format,
+            None => (
+                *available_formats.first().expect(
+                    "Should not be empty, since we checked for the existence of atleast one format",
+                ),
+                OutputColorSpace::Srgb,
             ),
         }
     }
 
     fn choose_swap_present_mode(
         available_present_modes: &[vk::PresentModeKHR],
+        preference: PresentModePreference,
     ) -> vk::PresentModeKHR {
-        let desired_mode = available_present_modes
+        let desired_mode = preference
+            .candidates()
             .iter()
-            .find(|mode| **mode == vk::PresentModeKHR::MAILBOX);
+            .find(|mode| available_present_modes.contains(mode));
         match desired_mode {
             Some(mode) => *mode,
             // FIFO is guaranteed to be available
@@ -135,10 +280,12 @@ impl Surface {
         }
     }
 
+    // can be 0x0 while the window is minimized
     fn choose_swap_extent(
         capabilities: &vk::SurfaceCapabilitiesKHR,
         window_size: LogicalSize<u32>,
     ) -> vk::Extent2D {
+        // u32::MAX => the surface size is decided by the swapchain (e.g. wayland)
         if capabilities.current_extent.width != u32::MAX {
             capabilities.current_extent
         } else {
@@ -162,18 +309,27 @@ impl Surface {
         physical_device: &vk::PhysicalDevice,
         device: &Device,
         window_size: LogicalSize<u32>,
-    ) -> (
-        vk::SwapchainKHR,
-        ash::khr::swapchain::Device,
-        Vec<vk::Image>,
-        Vec<vk::ImageView>,
-        vk::Extent2D,
-        vk::Format,
-    ) {
-        let support_details = self.query_support_details(physical_device);
-
-        let surface_format = Self::choose_swap_surface_format(&support_details.surface_formats);
-        let present_mode = Self::choose_swap_present_mode(&support_details.present_modes);
+        present_mode_preference: PresentModePreference,
+        exclusive_fullscreen: bool,
+        hdr_output: bool,
+    ) -> Result<SwapchainParts, VulkanError> {
+        let support_details = self.query_support_details(physical_device)?;
+
+        let (surface_format, output_color_space) =
+            Self::choose_swap_surface_format(&support_details.surface_formats, hdr_output);
+        log::debug!(
+            "Using surface format {:?} in {:?} ({:?})",
+            surface_format.format,
+            surface_format.color_space,
+            output_color_space
+        );
+        let present_mode =
+            Self::choose_swap_present_mode(&support_details.present_modes, present_mode_preference);
+        log::debug!(
+            "Using present mode {:?} (preferred: {:?})",
+            present_mode,
+            present_mode_preference
+        );
         let extent = Self::choose_swap_extent(&support_details.capabilities, window_size);
 
         let mut image_count = support_details.capabilities.min_image_count + 1;
@@ -192,6 +348,31 @@ impl Surface {
                 (vk::SharingMode::EXCLUSIVE, 0, std::ptr::null())
             };
 
+        // application controlled => the swapchain behaves like a borderless window until
+        // exclusive mode is acquired
+        let exclusive_monitor = if exclusive_fullscreen && device.full_screen_exclusive().is_some()
+        {
+            exclusive_fullscreen_monitor(&self.window)
+        } else {
+            None
+        };
+        let mut exclusive_win32_info = vk::SurfaceFullScreenExclusiveWin32InfoEXT {
+            s_type: vk::StructureType::SURFACE_FULL_SCREEN_EXCLUSIVE_WIN32_INFO_EXT,
+            p_next: std::ptr::null(),
+            hmonitor: exclusive_monitor.unwrap_or_default(),
+            ..Default::default()
+        };
+        let exclusive_info = vk::SurfaceFullScreenExclusiveInfoEXT {
+            s_type: vk::StructureType::SURFACE_FULL_SCREEN_EXCLUSIVE_INFO_EXT,
+            p_next: &mut exclusive_win32_info as *mut _ as *mut std::ffi::c_void,
+            full_screen_exclusive: vk::FullScreenExclusiveEXT::APPLICATION_CONTROLLED,
+            ..Default::default()
+        };
+        let p_next = match exclusive_monitor {
+            Some(_) => &exclusive_info as *const _ as *const std::ffi::c_void,
+            None => std::ptr::null(),
+        };
+
         let create_info = vk::SwapchainCreateInfoKHR {
             s_type: vk::StructureType::SWAPCHAIN_CREATE_INFO_KHR,
             surface: self.handle,
@@ -209,32 +390,58 @@ impl Surface {
             present_mode,
             clipped: vk::TRUE,
             old_swapchain: vk::SwapchainKHR::null(),
-            p_next: std::ptr::null(),
+            p_next,
             flags: vk::SwapchainCreateFlagsKHR::empty(),
             ..Default::default()
         };
 
         let swapchain_loader = device.create_swapchain_loader();
-        let swapchain = unsafe {
-            swapchain_loader
-                .create_swapchain(&create_info, None)
-                .expect("Could not create swapchain")
+        let swapchain = unsafe { swapchain_loader.create_swapchain(&create_info, None)? };
+        let swapchain_images = match unsafe { swapchain_loader.get_swapchain_images(swapchain) } {
+            Ok(images) => images,
+            Err(e) => {
+                unsafe { swapchain_loader.destroy_swapchain(swapchain, None) };
+                return Err(e.into());
+            }
         };
-        let swapchain_images = unsafe {
-            swapchain_loader
-                .get_swapchain_images(swapchain)
-                .expect("Device should not be out of memory")
+        let image_views = match device.create_image_views(surface_format.format, &swapchain_images)
+        {
+            Ok(image_views) => image_views,
+            Err(e) => {
+                unsafe { swapchain_loader.destroy_swapchain(swapchain, None) };
+                return Err(e);
+            }
+        };
+        // fails e.g. if the window is not on top => stay borderless
+        let exclusive_acquired = match (exclusive_monitor, device.full_screen_exclusive()) {
+            (Some(_), Some(loader)) => {
+                match unsafe { loader.acquire_full_screen_exclusive_mode(swapchain) } {
+                    Ok(()) => {
+                        log::info!("Acquired exclusive fullscreen");
+                        true
+                    }
+                    Err(e) => {
+                        log::warn!(
+                            "Exclusive fullscreen not available ({}), using borderless",
+                            e
+                        );
+                        false
+                    }
+                }
+            }
+            _ => false,
         };
-        let image_views = device.create_image_views(surface_format.format, &swapchain_images);
 
-        (
+        Ok((
             swapchain,
             swapchain_loader,
             swapchain_images,
             image_views,
             extent,
             surface_format.format,
-        )
+            output_color_space,
+            exclusive_acquired,
+        ))
     }
 
     pub fn create_swapchain(
@@ -242,12 +449,32 @@ impl Surface {
         physical_device: &vk::PhysicalDevice,
         device: Arc<Device>,
         window_size: LogicalSize<u32>,
-    ) -> Swapchain {
-        let (swapchain, swapchain_loader, swapchain_images, image_views, extent, surface_format) =
-            self.create_swapchain_internal(physical_device, &device, window_size);
+        present_mode_preference: PresentModePreference,
+        // only used while the window is fullscreen and the device supports it
+        exclusive_fullscreen: bool,
+        // falls back to srgb if the surface offers no hdr format
+        hdr_output: bool,
+    ) -> Result<Swapchain, VulkanError> {
+        let (
+            swapchain,
+            swapchain_loader,
+            swapchain_images,
+            image_views,
+            extent,
+            surface_format,
+            color_space,
+            exclusive_acquired,
+        ) = self.create_swapchain_internal(
+            physical_device,
+            &device,
+            window_size,
+            present_mode_preference,
+            exclusive_fullscreen,
+            hdr_output,
+        )?;
         let presentation_queue = device.get_presentation_queue();
 
-        Swapchain {
+        Ok(Swapchain {
             device,
             surface: self.clone(),
             swapchain,
@@ -257,7 +484,12 @@ impl Surface {
             extent,
             presentation_queue,
             format: surface_format,
-        }
+            color_space,
+            present_mode_preference,
+            exclusive_fullscreen,
+            hdr_output,
+            exclusive_acquired,
+        })
     }
 }
 
@@ -278,6 +510,13 @@ pub struct SwapChainSupportDetails {
     pub present_modes: Vec<vk::PresentModeKHR>,
 }
 
+pub struct AcquiredImage {
+    pub index: u32,
+    pub image: vk::Image,
+    // image can still be presented, but the swapchain should be recreated
+    pub suboptimal: bool,
+}
+
 pub struct Swapchain {
     device: Arc<Device>,
     surface: Arc<Surface>,
@@ -287,11 +526,20 @@ pub struct Swapchain {
     image_views: Vec<vk::ImageView>,
     extent: vk::Extent2D,
     format: vk::Format,
-    presentation_queue: vk::Queue,
+    color_space: OutputColorSpace,
+    presentation_queue: Arc<Queue>,
+    present_mode_preference: PresentModePreference,
+    exclusive_fullscreen: bool,
+    hdr_output: bool,
+    exclusive_acquired: bool,
 }
 
 impl Swapchain {
-    pub fn acquire_next_image(&self, semaphore: vk::Semaphore, timeout: u64) -> (u32, vk::Image) {
+    pub fn acquire_next_image(
+        &self,
+        semaphore: vk::Semaphore,
+        timeout: u64,
+    ) -> Result<Option<AcquiredImage>, VulkanError> {
         let result = unsafe {
             self.swapchain_loader.acquire_next_image(
                 self.swapchain,
@@ -301,14 +549,30 @@ impl Swapchain {
             )
         };
         match result {
-            Ok((image_index, _is_surface_suboptimal)) => {
-                (image_index, self.images[image_index as usize])
+            Ok((index, suboptimal)) => Ok(Some(AcquiredImage {
+                index,
+                image: self.images[index as usize],
+                suboptimal,
+            })),
+            // semaphore is not signaled in this case => nothing to clean up
+            // lost exclusive fullscreen (e.g. alt tab) => recreating tries to acquire it again
+            Err(vk::Result::ERROR_OUT_OF_DATE_KHR)
+            | Err(vk::Result::ERROR_FULL_SCREEN_EXCLUSIVE_MODE_LOST_EXT) => Ok(None),
+            // no image became available in time, e.g. a hidden window on wayland
+            // => treat it like an out of date swapchain and try again next frame
+            Err(vk::Result::TIMEOUT) | Err(vk::Result::NOT_READY) => {
+                log::debug!("No swapchain image available, retrying next frame");
+                Ok(None)
             }
-            Err(e) => panic!("Failed to acquire next image: {:?}", e),
+            Err(e) => Err(e.into()),
         }
     }
 
-    pub fn present_image(&self, wait_semaphore: vk::Semaphore, image_index: u32) {
+    pub fn present_image(
+        &self,
+        wait_semaphore: vk::Semaphore,
+        image_index: u32,
+    ) -> Result<bool, VulkanError> {
         let present_info = vk::PresentInfoKHR {
             s_type: vk::StructureType::PRESENT_INFO_KHR,
             p_next: std::ptr::null(),
@@ -320,19 +584,47 @@ impl Swapchain {
             ..Default::default()
         };
 
-        unsafe {
+        // returns true if the swapchain no longer matches the surface and has to be recreated
+        let result = unsafe {
             self.swapchain_loader
-                .queue_present(self.presentation_queue, &present_info)
-                .expect("Failed to present image");
+                .queue_present(*self.presentation_queue.lock(), &present_info)
+        };
+        match result {
+            Ok(suboptimal) => Ok(suboptimal),
+            Err(vk::Result::ERROR_OUT_OF_DATE_KHR)
+            | Err(vk::Result::ERROR_FULL_SCREEN_EXCLUSIVE_MODE_LOST_EXT) => Ok(true),
+            Err(e) => Err(e.into()),
         }
     }
 
+    // returns false if the surface has no area right now => the old swapchain is kept and
+    // recreate has to be called again later
     pub fn recreate(
         &mut self,
         physical_device: &vk::PhysicalDevice,
         logical_size: LogicalSize<u32>,
-    ) {
-        log::debug!("Recreating swapchain to size: {:?}", logical_size);
+    ) -> Result<bool, VulkanError> {
+        // the capabilities change with the window => query them again before every recreation
+        let capabilities = self
+            .surface
+            .query_support_details(physical_device)?
+            .capabilities;
+        let extent = Surface::choose_swap_extent(&capabilities, logical_size);
+        if extent.width == 0 || extent.height == 0 {
+            log::debug!(
+                "Surface extent is {}x{}, postponing swapchain recreation",
+                extent.width,
+                extent.height
+            );
+            return Ok(false);
+        }
+        log::debug!(
+            "Recreating swapchain to size: {:?} (surface extent {}x{})",
+            logical_size,
+            extent.width,
+            extent.height
+        );
+        self.release_exclusive_fullscreen();
         unsafe {
             for image_view in self.image_views.iter() {
                 self.device.destroy_image_view(*image_view);
@@ -340,25 +632,84 @@ impl Swapchain {
             self.swapchain_loader
                 .destroy_swapchain(self.swapchain, None)
         }
-        let (swapchain, swapchain_loader, swapchain_images, image_views, extent, format) = self
-            .surface
-            .create_swapchain_internal(physical_device, &self.device, logical_size);
+        // the old handles are gone => dont destroy them again in drop if recreating fails
+        self.swapchain = vk::SwapchainKHR::null();
+        self.image_views.clear();
+        let (
+            swapchain,
+            swapchain_loader,
+            swapchain_images,
+            image_views,
+            extent,
+            format,
+            color_space,
+            exclusive_acquired,
+        ) = self.surface.create_swapchain_internal(
+            physical_device,
+            &self.device,
+            logical_size,
+            self.present_mode_preference,
+            self.exclusive_fullscreen,
+            self.hdr_output,
+        )?;
+        self.exclusive_acquired = exclusive_acquired;
         self.swapchain = swapchain;
         self.swapchain_loader = swapchain_loader;
         self.images = swapchain_images;
         self.image_views = image_views;
         self.extent = extent;
         self.format = format;
+        // can change when the window moves to another monitor
+        self.color_space = color_space;
+        Ok(true)
+    }
+
+    fn release_exclusive_fullscreen(&mut self) {
+        if !std::mem::take(&mut self.exclusive_acquired) {
+            return;
+        }
+        if let Some(loader) = self.device.full_screen_exclusive() {
+            if let Err(e) = unsafe { loader.release_full_screen_exclusive_mode(self.swapchain) } {
+                log::warn!("Could not release exclusive fullscreen: {}", e);
+            }
+        }
     }
 
     pub fn extent(&self) -> vk::Extent2D {
         self.extent
     }
+
+    pub fn format(&self) -> vk::Format {
+        self.format
+    }
+
+    pub fn color_space(&self) -> OutputColorSpace {
+        self.color_space
+    }
+
+    pub fn image_view(&self, index: u32) -> vk::ImageView {
+        self.image_views[index as usize]
+    }
+
+    // can differ from the requested min_image_count
+    pub fn image_count(&self) -> usize {
+        self.images.len()
+    }
+
+    pub fn present_mode_preference(&self) -> PresentModePreference {
+        self.present_mode_preference
+    }
+
+    // only takes effect on the next call to recreate
+    pub fn set_present_mode_preference(&mut self, preference: PresentModePreference) {
+        self.present_mode_preference = preference;
+    }
 }
 
 impl Drop for Swapchain {
     fn drop(&mut self) {
         log::debug!("Dropping swapchain");
+        self.release_exclusive_fullscreen();
         unsafe {
             for image_view in self.image_views.iter() {
                 self.device.destroy_image_view(*image_view);
@@ -0,0 +1,113 @@
+use std::path::Path;
+use winit::event_loop::ActiveEventLoop;
+use winit::window::BadIcon;
+use winit::window::BadImage;
+use winit::window::Cursor;
+use winit::window::CursorIcon;
+use winit::window::CustomCursor;
+use winit::window::Icon;
+use winit::window::Window;
+
+#[derive(Debug)]
+pub enum IconError {
+    Image(image::ImageError),
+    Icon(BadIcon),
+    Cursor(BadImage),
+    TooLarge { width: u32, height: u32 },
+}
+
+impl std::fmt::Display for IconError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IconError::Image(e) => write!(f, "Could not load image: {}", e),
+            IconError::Icon(e) => write!(f, "Invalid window icon: {}", e),
+            IconError::Cursor(e) => write!(f, "Invalid cursor image: {}", e),
+            IconError::TooLarge { width, height } => {
+                write!(f, "Cursor image is too large: {}x{}", width, height)
+            }
+        }
+    }
+}
+
+impl std::error::Error for IconError {}
+
+impl From<image::ImageError> for IconError {
+    fn from(e: image::ImageError) -> Self {
+        IconError::Image(e)
+    }
+}
+
+pub fn load_window_icon(path: &Path) -> Result<Icon, IconError> {
+    let image = image::open(path)?.to_rgba8();
+    let (width, height) = image.dimensions();
+    Icon::from_rgba(image.into_raw(), width, height).map_err(IconError::Icon)
+}
+
+pub fn set_window_icon(window: &Window, path: &Path) -> Result<(), IconError> {
+    let icon = load_window_icon(path)?;
+    window.set_window_icon(Some(icon));
+    Ok(())
+}
+
+// hotspot is the pixel of the image that is the actual "click position"
+pub fn load_custom_cursor(
+    event_loop: &ActiveEventLoop,
+    path: &Path,
+    hotspot_x: u16,
+    hotspot_y: u16,
+) -> Result<CustomCursor, IconError> {
+    let image = image::open(path)?.to_rgba8();
+    let (width, height) = image.dimensions();
+    let (Ok(cursor_width), Ok(cursor_height)) = (u16::try_from(width), u16::try_from(height))
+    else {
+        return Err(IconError::TooLarge { width, height });
+    };
+    let source = CustomCursor::from_rgba(
+        image.into_raw(),
+        cursor_width,
+        cursor_height,
+        hotspot_x,
+        hotspot_y,
+    )
+    .map_err(IconError::Cursor)?;
+    Ok(event_loop.create_custom_cursor(source))
+}
+
+// cycles through a fixed list of cursors, e.g. to switch between ui and gameplay cursors
+pub struct CursorSet {
+    cursors: Vec<Cursor>,
+    current: usize,
+}
+
+impl CursorSet {
+    pub fn new() -> Self {
+        CursorSet {
+            cursors: vec![Cursor::Icon(CursorIcon::Default)],
+            current: 0,
+        }
+    }
+
+    pub fn add(&mut self, cursor: impl Into<Cursor>) -> usize {
+        self.cursors.push(cursor.into());
+        self.cursors.len() - 1
+    }
+
+    pub fn select(&mut self, window: &Window, idx: usize) {
+        if let Some(cursor) = self.cursors.get(idx) {
+            self.current = idx;
+            window.set_cursor(cursor.clone());
+        } else {
+            log::warn!("Cursor {} does not exist", idx);
+        }
+    }
+
+    pub fn select_next(&mut self, window: &Window) {
+        self.select(window, (self.current + 1) % self.cursors.len());
+    }
+}
+
+impl Default for CursorSet {
+    fn default() -> Self {
+        Self::new()
+    }
+}